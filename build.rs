@@ -0,0 +1,31 @@
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// 编译时记下 git 提交、构建时间和开启的特性，供 /version 使用 (见 src/build_info.rs)。
+// 没有 git (源码包构建) 时提交为 unknown；设置了 AIZASY_GIT_COMMIT / SOURCE_DATE_EPOCH 时以它们为准，便于可复现构建。
+fn main() {
+    println!("cargo:rerun-if-env-changed=AIZASY_GIT_COMMIT");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+
+    let commit = std::env::var("AIZASY_GIT_COMMIT").ok().filter(|c| !c.is_empty()).or_else(|| {
+        let output = Command::new("git").args(["rev-parse", "--short=12", "HEAD"]).output().ok()?;
+        output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    });
+    println!("cargo:rustc-env=AIZASY_GIT_COMMIT={}", commit.unwrap_or_else(|| "unknown".to_string()));
+
+    let built_at = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0));
+    println!("cargo:rustc-env=AIZASY_BUILD_TIME={}", built_at);
+
+    // cargo 给构建脚本的 CARGO_FEATURE_<NAME>，名字是大写、- 换成 _ 之后的
+    let mut features: Vec<String> = std::env::vars()
+        .filter_map(|(key, _)| key.strip_prefix("CARGO_FEATURE_").map(|name| name.to_lowercase().replace('_', "-")))
+        .filter(|name| name != "default")
+        .collect();
+    features.sort();
+    println!("cargo:rustc-env=AIZASY_FEATURES={}", features.join(","));
+}
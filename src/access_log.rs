@@ -0,0 +1,209 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{FromRequestParts, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use http_body_util::BodyExt;
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+use crate::cached_contents::format_timestamp;
+use crate::client_ip::ClientIp;
+use crate::events::RequestId;
+use crate::finish_body::{self, BodyWatcher};
+use crate::sanitize::redact_path;
+use crate::slow_log::UpstreamTiming;
+use crate::AppState;
+
+// --- 结构化访问日志 ---
+// 每个请求在响应体发完 (或客户端断开) 后写一行 JSON，和 tracing 日志分开输出，
+// 便于直接交给 Loki / ELK 采集。字段:
+//   {"ts":"2026-01-01T00:00:00.000Z","request_id":1,"method":"POST","path":"/v1beta/models/...",
+//    "client_ip":"1.2.3.4","status":200,"upstream_status":200,"duration_ms":812,"ttfb_ms":240,
+//    "bytes_in":512,"bytes_out":8192,"upstream":"https://...","key":"key-2","key_index":1}
+// 网关自己生成的响应 (鉴权失败、限流、缓存命中等) 没有 upstream_* / key 字段。
+
+/// 访问日志输出位置：`stdout`、`stderr` 或文件路径 (追加写入)
+pub struct AccessLog {
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl AccessLog {
+    pub fn open(target: &str) -> Result<Self, String> {
+        let writer: Box<dyn Write + Send> = match target {
+            "stdout" | "-" => Box::new(std::io::stdout()),
+            "stderr" => Box::new(std::io::stderr()),
+            path => Box::new(
+                std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| format!("{}: {}", path, e))?,
+            ),
+        };
+        Ok(Self { writer: Mutex::new(writer) })
+    }
+
+    /// 只检查能否写入，不创建文件；配置检查用
+    pub fn check(target: &str) -> Result<(), String> {
+        match target {
+            "stdout" | "-" | "stderr" => Ok(()),
+            path => check_writable(Path::new(path)),
+        }
+    }
+
+    fn write(&self, entry: &AccessLogEntry) {
+        let Ok(mut line) = serde_json::to_vec(entry) else {
+            return;
+        };
+        line.push(b'\n');
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = writer.write_all(&line).and_then(|_| writer.flush()) {
+            warn!("📝 Failed to write access log: {}", e);
+        }
+    }
+}
+
+/// 已有的文件以追加方式打开一次 (不会改动内容)，不存在时要求所在目录存在；
+/// 配置检查用，审计日志和 SQLite 存储也走这里
+pub fn check_writable(path: &Path) -> Result<(), String> {
+    if path.exists() {
+        return std::fs::OpenOptions::new()
+            .append(true)
+            .open(path)
+            .map(|_| ())
+            .map_err(|e| format!("{}: {}", path.display(), e));
+    }
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    if dir.is_dir() {
+        Ok(())
+    } else {
+        Err(format!("{}: directory {} does not exist", path.display(), dir.display()))
+    }
+}
+
+/// proxy_handler 挂在响应 extensions 上的上游信息
+#[derive(Debug, Clone)]
+pub(crate) struct UpstreamInfo {
+    /// 连接失败时为 None
+    pub(crate) status: Option<u16>,
+    pub(crate) target: String,
+    pub(crate) key: Option<String>,
+    pub(crate) key_index: Option<usize>,
+    /// 最后一次上游尝试的耗时 (见 slow_log)
+    pub(crate) timing: Option<UpstreamTiming>,
+    /// 收到上游响应头的时刻，连接失败时为 None
+    pub(crate) responded_at: Option<Instant>,
+}
+
+#[derive(Serialize)]
+struct AccessLogEntry {
+    ts: String,
+    request_id: Option<u64>,
+    method: String,
+    path: String,
+    client_ip: String,
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    upstream_status: Option<u16>,
+    duration_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ttfb_ms: Option<u64>,
+    bytes_in: u64,
+    bytes_out: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    upstream: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key_index: Option<usize>,
+    /// 响应体没发完 (上游中断或客户端断开)
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    incomplete: bool,
+}
+
+pub(crate) async fn record(State(state): State<Arc<AppState>>, req: Request, next: Next) -> Response {
+    let Some(log) = state.access_log.clone() else {
+        return next.run(req).await;
+    };
+    let started_at = Instant::now();
+    let (mut parts, body) = req.into_parts();
+    let Ok(ClientIp(client_ip)) = ClientIp::from_request_parts(&mut parts, &state).await;
+    let entry = AccessLogEntry {
+        ts: String::new(),
+        request_id: parts.extensions.get::<RequestId>().map(|id| id.0),
+        method: parts.method.to_string(),
+        path: redact_path(parts.uri.path_and_query().map(|p| p.as_str()).unwrap_or("/")),
+        client_ip: client_ip.to_string(),
+        status: 0,
+        upstream_status: None,
+        duration_ms: 0,
+        ttfb_ms: None,
+        bytes_in: 0,
+        bytes_out: 0,
+        upstream: None,
+        key: None,
+        key_index: None,
+        incomplete: false,
+    };
+
+    // 请求体边读边计数
+    let bytes_in = Arc::new(AtomicU64::new(0));
+    let counter = bytes_in.clone();
+    let body = Body::new(body.map_frame(move |frame| {
+        if let Some(data) = frame.data_ref() {
+            counter.fetch_add(data.len() as u64, Ordering::Relaxed);
+        }
+        frame
+    }));
+    let mut response = next.run(Request::from_parts(parts, body)).await;
+    // 首字节时间算到收到上游响应头为止，不受网关缓冲响应体、客户端读得慢的影响；网关自己生成的响应算到响应头就绪
+    let mut headers_at = Instant::now();
+
+    let mut entry = entry;
+    entry.status = response.status().as_u16();
+    if let Some(upstream) = response.extensions_mut().remove::<UpstreamInfo>() {
+        entry.upstream_status = upstream.status;
+        entry.upstream = Some(upstream.target);
+        entry.key = upstream.key;
+        entry.key_index = upstream.key_index;
+        headers_at = upstream.responded_at.unwrap_or(headers_at);
+    }
+    entry.ttfb_ms = Some(headers_at.saturating_duration_since(started_at).as_millis() as u64);
+    let logged = Logged {
+        log,
+        entry,
+        started_at,
+        bytes_in,
+    };
+    finish_body::watch(response, logged)
+}
+
+// 响应体发完或被丢弃时写一条日志
+struct Logged {
+    log: Arc<AccessLog>,
+    entry: AccessLogEntry,
+    started_at: Instant,
+    bytes_in: Arc<AtomicU64>,
+}
+
+impl BodyWatcher for Logged {
+    fn on_data(&mut self, data: &Bytes) {
+        self.entry.bytes_out += data.len() as u64;
+    }
+
+    fn on_finish(mut self, incomplete: bool) {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        self.entry.ts = format_timestamp(now);
+        self.entry.duration_ms = self.started_at.elapsed().as_millis() as u64;
+        self.entry.bytes_in = self.bytes_in.load(Ordering::Relaxed);
+        self.entry.incomplete = incomplete;
+        self.log.write(&self.entry);
+    }
+}
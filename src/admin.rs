@@ -0,0 +1,840 @@
+use axum::{
+    extract::{Path, Query, Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    middleware::{self, Next},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Html, IntoResponse, Response,
+    },
+    routing::{get, post, put},
+    Json, Router,
+};
+use serde::Deserialize;
+use futures_util::stream;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+use crate::build_info;
+use crate::dashboard;
+use crate::key_pool;
+use crate::ledger;
+use crate::report;
+use crate::proxy;
+use crate::routes::RouteRule;
+use crate::sanitize::{self, sanitize_headers};
+use crate::AppState;
+
+// --- 管理 API ---
+// 挂在 /admin 下，所有接口都要求 `Authorization: Bearer <admin token>`。
+// 配置了单独的管理监听地址时 (open)，没有设置 token 就不鉴权，由监听地址本身限制访问。
+
+pub(crate) fn router(state: Arc<AppState>, open: bool) -> Router<Arc<AppState>> {
+    let router = Router::new()
+        .route("/maintenance", get(get_maintenance).put(put_maintenance))
+        .route("/routes", get(list_routes))
+        .route("/routes/:id", put(put_route).delete(delete_route))
+        .route("/ledger", get(get_ledger))
+        .route("/reports/monthly", get(monthly_report))
+        .route("/usage", get(usage_breakdown))
+        .route("/requests", get(recent_requests))
+        .route("/requests/tail", get(tail_requests))
+        .route("/failures", get(list_failures))
+        .route("/failures/:id/replay", post(replay_failure))
+        .route("/credits/:client", get(get_credit).post(top_up_credit))
+        .route("/caches", get(list_caches))
+        .route("/keys", get(list_keys))
+        .route("/keys/reload", post(reload_keys))
+        .route("/keys/:name", put(put_key))
+        .route("/upstreams", get(list_upstreams))
+        .route("/canary", get(get_canary).put(put_canary))
+        .route("/config", get(get_config))
+        .route("/version", get(get_version))
+        .route("/stats", get(get_stats))
+        .route("/dashboard/data", get(dashboard_data))
+        .route("/log-level", get(get_log_level).put(put_log_level))
+        .route("/drain", get(get_drain).post(start_drain));
+    let router = match open && state.admin_token.is_none() {
+        true => router,
+        false => router.route_layer(middleware::from_fn_with_state(state, require_token)),
+    };
+    // 面板页面本身不含数据，不要求 token；页面里的脚本带着 token 拉取 /dashboard/data
+    router.route("/dashboard", get(dashboard_page))
+}
+
+// 逐字节比较，避免按前缀泄露 token
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+pub(crate) async fn require_token(State(state): State<Arc<AppState>>, req: Request, next: Next) -> Response {
+    let Some(expected) = &state.admin_token else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let provided = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    match provided {
+        Some(token) if constant_time_eq(token.as_bytes(), expected.as_bytes()) => next.run(req).await,
+        _ => {
+            warn!("🔑 Rejected admin request to {}", req.uri().path());
+            (StatusCode::UNAUTHORIZED, "Invalid admin token").into_response()
+        }
+    }
+}
+
+fn error(status: StatusCode, message: impl Into<String>) -> Response {
+    (status, Json(json!({ "error": message.into() }))).into_response()
+}
+
+async fn get_maintenance(State(state): State<Arc<AppState>>) -> Response {
+    Json(state.maintenance.get()).into_response()
+}
+
+#[derive(Deserialize)]
+struct MaintenanceBody {
+    enabled: bool,
+    message: Option<String>,
+    retry_after_secs: Option<u64>,
+}
+
+// 没有给出的字段保持原值
+async fn put_maintenance(State(state): State<Arc<AppState>>, Json(body): Json<MaintenanceBody>) -> Response {
+    let mut current = state.maintenance.get();
+    current.enabled = body.enabled;
+    if let Some(message) = body.message {
+        current.message = message;
+    }
+    if let Some(retry_after) = body.retry_after_secs {
+        current.retry_after_secs = retry_after;
+    }
+    state.maintenance.set(current.clone());
+    Json(current).into_response()
+}
+
+async fn list_routes(State(state): State<Arc<AppState>>) -> Response {
+    let rules: Vec<RouteRule> = state.routes.snapshot().iter().map(|r| (**r).clone()).collect();
+    Json(json!({ "routes": rules })).into_response()
+}
+
+#[derive(Deserialize)]
+struct RouteBody {
+    prefix: String,
+    target: String,
+    #[serde(default)]
+    strip_prefix: bool,
+    key_group: Option<String>,
+    host: Option<String>,
+    #[serde(default)]
+    passthrough: bool,
+}
+
+async fn put_route(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(body): Json<RouteBody>,
+) -> Response {
+    let rule = RouteRule {
+        id,
+        prefix: body.prefix,
+        target: body.target,
+        strip_prefix: body.strip_prefix,
+        key_group: body.key_group,
+        host: body.host,
+        passthrough: body.passthrough,
+        dynamic: false,
+    };
+    // 运行时设置的上游必须通过 SSRF 策略
+    match state.set_route(rule.clone()).await {
+        Ok(replaced) => {
+            info!("🧭 Route {} set: {} -> {}", rule.id, rule.prefix, rule.target);
+            let status = if replaced { StatusCode::OK } else { StatusCode::CREATED };
+            let stored = state.routes.snapshot().iter().find(|r| r.id == rule.id).map(|r| (**r).clone());
+            (status, Json(stored.unwrap_or(rule))).into_response()
+        }
+        Err(e) => error(StatusCode::BAD_REQUEST, e),
+    }
+}
+
+async fn delete_route(State(state): State<Arc<AppState>>, Path(id): Path<String>) -> Response {
+    if state.routes.remove(&id) {
+        info!("🧭 Route {} deleted", id);
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        error(StatusCode::NOT_FOUND, format!("route '{}' not found", id))
+    }
+}
+
+#[derive(Deserialize)]
+struct LedgerQuery {
+    /// unix 秒，默认 24 小时前
+    from: Option<u64>,
+    /// unix 秒，默认现在
+    to: Option<u64>,
+    client: Option<String>,
+}
+
+async fn get_ledger(State(state): State<Arc<AppState>>, Query(query): Query<LedgerQuery>) -> Response {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let to = query.to.unwrap_or(now + 1);
+    let from = query.from.unwrap_or(to.saturating_sub(86_400));
+
+    match ledger::query(state.storage.as_ref(), from.saturating_mul(1000), to.saturating_mul(1000), query.client.as_deref()).await {
+        Ok(entries) => {
+            let total_cost: f64 = entries.iter().map(|e| e.cost).sum();
+            let prompt_tokens: u64 = entries.iter().map(|e| e.prompt_tokens).sum();
+            let output_tokens: u64 = entries.iter().map(|e| e.output_tokens).sum();
+            Json(json!({
+                "from": from,
+                "to": to,
+                "requests": entries.len(),
+                "prompt_tokens": prompt_tokens,
+                "output_tokens": output_tokens,
+                "total_cost": total_cost,
+                "entries": entries,
+            }))
+            .into_response()
+        }
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+#[derive(Deserialize)]
+struct UsageQuery {
+    /// unix 秒，默认 24 小时前
+    from: Option<u64>,
+    /// unix 秒，默认现在
+    to: Option<u64>,
+    /// key (默认) 或 client
+    by: Option<String>,
+    /// json (默认) 或 csv
+    format: Option<String>,
+}
+
+async fn usage_breakdown(State(state): State<Arc<AppState>>, Query(query): Query<UsageQuery>) -> Response {
+    let by = match query.by.as_deref().unwrap_or("key").parse::<report::GroupBy>() {
+        Ok(by) => by,
+        Err(e) => return error(StatusCode::BAD_REQUEST, e),
+    };
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let to = query.to.unwrap_or(now + 1);
+    let from = query.from.unwrap_or(to.saturating_sub(86_400));
+
+    let csv = match query.format.as_deref().unwrap_or("json") {
+        "json" => false,
+        "csv" => true,
+        other => return error(StatusCode::BAD_REQUEST, format!("unknown format '{}', expected json or csv", other)),
+    };
+
+    match ledger::query(state.storage.as_ref(), from.saturating_mul(1000), to.saturating_mul(1000), None).await {
+        Ok(entries) => {
+            let groups = report::usage_by(&entries, by);
+            if csv {
+                let disposition = format!("attachment; filename=\"usage-by-{}-{}-{}.csv\"", by.as_str(), from, to);
+                return (
+                    [(header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()), (header::CONTENT_DISPOSITION, disposition)],
+                    report::usage_csv(&groups, by),
+                )
+                    .into_response();
+            }
+            Json(json!({
+                "from": from,
+                "to": to,
+                "by": by,
+                "groups": groups,
+            }))
+            .into_response()
+        }
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+#[derive(Deserialize)]
+struct ReportQuery {
+    /// YYYY-MM，默认当月
+    month: Option<String>,
+    client: Option<String>,
+}
+
+async fn monthly_report(State(state): State<Arc<AppState>>, Query(query): Query<ReportQuery>) -> Response {
+    let month = query.month.unwrap_or_else(|| {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        report::utc_month(now)
+    });
+    if let Err(e) = report::month_bounds(&month) {
+        return error(StatusCode::BAD_REQUEST, e);
+    }
+    match report::monthly(state.storage.as_ref(), &month, query.client.as_deref()).await {
+        Ok(report) => Json(report).into_response(),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+async fn get_credit(State(state): State<Arc<AppState>>, Path(client): Path<String>) -> Response {
+    let Some(credits) = &state.credits else {
+        return error(StatusCode::NOT_FOUND, "credit accounts are not enabled");
+    };
+    match credits.balance(&client).await {
+        Ok(balance) => Json(balance).into_response(),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+#[derive(Deserialize)]
+struct TopUp {
+    amount: f64,
+}
+
+async fn top_up_credit(
+    State(state): State<Arc<AppState>>,
+    Path(client): Path<String>,
+    Json(body): Json<TopUp>,
+) -> Response {
+    let Some(credits) = &state.credits else {
+        return error(StatusCode::NOT_FOUND, "credit accounts are not enabled");
+    };
+    if let Err(e) = credits.delta(body.amount) {
+        return error(StatusCode::BAD_REQUEST, e);
+    }
+    match credits.top_up(&client, body.amount).await {
+        Ok(balance) => {
+            info!("🪙 Credit for {} adjusted by {} -> {}", client, body.amount, balance.balance);
+            Json(balance).into_response()
+        }
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+async fn list_keys(State(state): State<Arc<AppState>>) -> Response {
+    let Some(pool) = &state.key_pool else {
+        return error(StatusCode::NOT_FOUND, "key pool is not enabled");
+    };
+    Json(json!({ "keys": pool.status() })).into_response()
+}
+
+// 立即重新读取 key 文件 / 地址，不用等下一次定时读取
+async fn reload_keys(State(state): State<Arc<AppState>>) -> Response {
+    let Some(pool) = &state.key_pool else {
+        return error(StatusCode::NOT_FOUND, "key pool is not enabled");
+    };
+    match key_pool::reload(&state).await {
+        Ok((added, removed)) => Json(json!({ "added": added, "removed": removed, "keys": pool.status() })).into_response(),
+        Err(e) if state.key_source.is_none() => error(StatusCode::NOT_FOUND, e),
+        Err(e) => error(StatusCode::BAD_GATEWAY, e),
+    }
+}
+
+#[derive(Deserialize)]
+struct KeyBody {
+    #[serde(default)]
+    disabled: Option<bool>,
+    /// 只能设为 false：解除自动隔离
+    #[serde(default)]
+    quarantined: Option<bool>,
+}
+
+// 停用 / 重新启用一个 key、解除隔离，立即对新请求生效；SIGHUP 重载配置或重启后恢复为启用
+async fn put_key(State(state): State<Arc<AppState>>, Path(name): Path<String>, Json(body): Json<KeyBody>) -> Response {
+    let Some(pool) = &state.key_pool else {
+        return error(StatusCode::NOT_FOUND, "key pool is not enabled");
+    };
+    if body.quarantined == Some(true) {
+        return error(StatusCode::BAD_REQUEST, "keys are quarantined automatically; use disabled to take a key out of rotation");
+    }
+    if body.disabled.is_none() && body.quarantined.is_none() {
+        return error(StatusCode::BAD_REQUEST, "expected disabled or quarantined");
+    }
+    if !pool.names().contains(&name) {
+        return error(StatusCode::NOT_FOUND, format!("key '{}' not found", name));
+    }
+    if body.quarantined == Some(false) {
+        pool.release_quarantine(&name);
+        info!("🔑 Key {} released from quarantine via admin API", name);
+    }
+    match body.disabled {
+        Some(true) => {
+            pool.set_disabled(&name, true);
+            warn!("🔑 Key {} disabled via admin API", name);
+        }
+        Some(false) => {
+            pool.set_disabled(&name, false);
+            info!("🔑 Key {} enabled via admin API", name);
+        }
+        None => {}
+    }
+    Json(json!({ "keys": pool.status() })).into_response()
+}
+
+async fn list_upstreams(State(state): State<Arc<AppState>>) -> Response {
+    let proxies = state.egress.as_ref().map(|pool| pool.status()).unwrap_or_default();
+    Json(json!({ "upstreams": state.upstreams.status(), "proxies": proxies })).into_response()
+}
+
+fn canary_status(canary: &crate::canary::Canary) -> serde_json::Value {
+    let (stable, canary_stats) = canary.stats();
+    json!({
+        "target": canary.target,
+        "percent": canary.percent(),
+        "sticky": canary.sticky(),
+        "stable": stable,
+        "canary": canary_stats,
+    })
+}
+
+async fn get_canary(State(state): State<Arc<AppState>>) -> Response {
+    let Some(canary) = &state.canary else {
+        return error(StatusCode::NOT_FOUND, "canary routing is not enabled");
+    };
+    Json(canary_status(canary)).into_response()
+}
+
+#[derive(Deserialize)]
+struct CanaryBody {
+    percent: f64,
+}
+
+// 调整发往金丝雀的比例，立即对新请求生效；热重载或重启后恢复为配置的比例
+async fn put_canary(State(state): State<Arc<AppState>>, Json(body): Json<CanaryBody>) -> Response {
+    let Some(canary) = &state.canary else {
+        return error(StatusCode::NOT_FOUND, "canary routing is not enabled");
+    };
+    if !(0.0..=100.0).contains(&body.percent) {
+        return error(StatusCode::BAD_REQUEST, "percent must be between 0 and 100");
+    }
+    canary.set_percent(body.percent);
+    info!("🐤 Canary {} now gets {}% of requests (admin API)", canary.target, body.percent);
+    Json(canary_status(canary)).into_response()
+}
+
+async fn get_config(State(state): State<Arc<AppState>>) -> Response {
+    Json(config_summary(&state)).into_response()
+}
+
+// 构建信息加上生效中的配置
+async fn get_version(State(state): State<Arc<AppState>>) -> Response {
+    let mut info = build_info::json();
+    info["config"] = config_summary(&state);
+    Json(info).into_response()
+}
+
+// 生效中的配置摘要，不含任何 key / token
+fn config_summary(state: &AppState) -> serde_json::Value {
+    let secs = |d: Option<std::time::Duration>| d.map(|d| d.as_secs_f64());
+    let plugins: Vec<&str> = state.plugins.iter().map(|p| p.name()).collect();
+    #[cfg(feature = "compression")]
+    let compression = state.compression.as_ref().map(|c| json!({
+        "algorithms": c.names(),
+        "min_size": c.min_size,
+    }));
+    #[cfg(not(feature = "compression"))]
+    let compression: Option<serde_json::Value> = None;
+    json!({
+        "upstreams": state.upstreams.status(),
+        "upstream_balancing": state.upstreams.balancing().name(),
+        "key_pool": state.key_pool.as_ref().map(|pool| json!({
+            "keys": pool.len(),
+            "enabled": pool.enabled(),
+            "max_attempts": pool.max_attempts(None),
+            "groups": pool.groups(),
+            "selection": match pool.selection() {
+                crate::key_pool::KeySelection::RoundRobin => "round-robin",
+                crate::key_pool::KeySelection::Sticky => "sticky",
+            },
+            "max_concurrency": pool.max_concurrency(),
+            "quarantine_after": pool.quarantine_after(),
+            "quarantine_webhook": state.quarantine_webhook.is_some(),
+            "source": state.key_source.as_ref().map(|source| json!({
+                "from": source.describe(),
+                "reload_secs": source.interval.as_secs(),
+            })),
+        })),
+        "providers": state.providers.iter().map(|p| json!({
+            "name": p.name,
+            "kind": p.kind,
+            "url": p.url,
+            "models": p.models,
+            "deployments": p.deployments.iter().map(|(model, deployment)| (model.clone(), json!(deployment))).collect::<serde_json::Map<_, _>>(),
+            "key": p.key.is_some(),
+        })).collect::<Vec<_>>(),
+        "limits": {
+            "max_request_size": state.max_request_size,
+            "max_response_size": state.max_response_size,
+            "max_inflight": state.inflight.as_ref().map(|l| l.config().max_inflight),
+            "max_inflight_queue": state.inflight.as_ref().map(|l| l.config().queue),
+        },
+        "request_queue": state.request_queue.as_ref().map(|q| json!({
+            "max_queue": q.config().max_queue,
+            "max_wait_secs": q.config().max_wait.as_secs_f64(),
+            "priority_header": q.config().priority_header.as_ref().map(|h| h.as_str()),
+            "max_header_priority": q.config().max_header_priority,
+            "priorities": q.config().priorities.iter().cloned().collect::<std::collections::BTreeMap<_, _>>(),
+            "waiting": q.len(),
+        })),
+        "timeouts_secs": {
+            "connect": state.connect_timeout.as_secs_f64(),
+            "first_byte": state.first_byte_timeout.as_secs_f64(),
+            "total": secs(state.total_timeout),
+            "stream_idle": secs(state.stream_idle_timeout),
+            "sse_keepalive": secs(state.sse_keepalive),
+        },
+        "retry": state.retry.as_ref().map(|r| json!({
+            "max_retries": r.max_retries,
+            "backoff_ms": r.backoff.as_millis() as u64,
+            "max_backoff_ms": r.max_backoff.as_millis() as u64,
+            "jitter": r.jitter,
+            "max_body": r.max_body,
+        })),
+        "mirror": state.mirror.as_ref().map(|m| json!({
+            "target": m.target,
+            "percent": m.percent,
+            "key": m.key.is_some(),
+        })),
+        "canary": state.canary.as_ref().map(|c| json!({
+            "target": c.target,
+            "percent": c.percent(),
+            "sticky": c.sticky(),
+        })),
+        "model_fallbacks": state.model_fallbacks.as_ref().map_or(0, |f| f.len()),
+        "rewrites": state.rewrites.len(),
+        "header_rules": state.header_rules.len(),
+        "routes": state.routes.snapshot().len(),
+        "trusted_proxies": state.trusted_proxies.iter().map(|c| c.to_string()).collect::<Vec<_>>(),
+        "ip_filter": state.ip_filter.is_some(),
+        "burst_guard": state.burst.as_ref().map(|b| b.config()).map(|c| json!({
+            "window_secs": c.window.as_secs(),
+            "requests": c.requests,
+            "auth_failures": c.auth_failures,
+            "delay_ms": c.delay.as_millis() as u64,
+            "max_delay_ms": c.max_delay.as_millis() as u64,
+            "ban_after": c.ban_after,
+            "ban_secs": c.ban.as_secs(),
+            "exempt": c.exempt.iter().map(|c| c.to_string()).collect::<Vec<_>>(),
+        })),
+        "path_allowlist": state.path_allowlist.as_ref().map(|a| a.patterns()),
+        "bandwidth_limits": state.bandwidth.as_ref().map(|b| b.limits().iter().map(|l| json!({
+            "subject": l.subject,
+            "rate": l.rate,
+            "burst": l.burst,
+        })).collect::<Vec<_>>()),
+        "coalesce_get": state.coalescer.is_some(),
+        "cors": state.cors.is_some(),
+        "compression": compression,
+        "accept_encoding": state.accept_encoding.name(),
+        "grpc": state.grpc.as_ref().map(|g| json!({ "target": g.target })),
+        "dns": {
+            "servers": state.dns.servers.iter().map(|s| s.to_string()).collect::<Vec<_>>(),
+            "overrides": state.dns.overrides.iter().map(|o| (o.host.clone(), json!(o.addrs))).collect::<serde_json::Map<_, _>>(),
+            "ip_strategy": state.dns.strategy.name(),
+            "happy_eyeballs_ms": state.dns.happy_eyeballs.map(|d| d.as_millis() as u64),
+        },
+        "uploads": state.uploads.as_ref().map(|u| json!({
+            "ttl_secs": u.config().ttl.as_secs(),
+            "public_url": u.config().public_url,
+            "sessions": u.len(),
+        })),
+        "prewarm": state.prewarm.as_ref().map(|p| json!({
+            "connections": p.config().connections,
+            "idle_secs": p.config().idle.as_secs(),
+            "warm": p.warm(),
+        })),
+        "slow_request_ms": state.slow_request.map(|d| d.as_millis() as u64),
+        "client_auth": state.client_tokens.is_some(),
+        "maintenance": state.maintenance.get().enabled,
+        "plugins": plugins,
+        "storage": state.storage.name(),
+        "log_level": state.log_level.as_ref().map(|l| l.get()),
+    })
+}
+
+async fn get_stats(State(state): State<Arc<AppState>>) -> Response {
+    let keys = state.key_pool.as_ref().map(|pool| {
+        let status = pool.status();
+        json!({
+            "total": status.len(),
+            "disabled": status.iter().filter(|k| k.disabled).count(),
+            "cooling_down": status.iter().filter(|k| !k.disabled && k.cooldown_secs > 0).count(),
+            "quarantined": status.iter().filter(|k| k.quarantined.is_some()).count(),
+        })
+    });
+    let upstreams = state.upstreams.status();
+    Json(json!({
+        "uptime_secs": state.started.elapsed().as_secs(),
+        "requests_total": state.request_ids.load(Ordering::Relaxed),
+        "in_flight": state.in_flight.load(Ordering::Relaxed),
+        "keys": keys,
+        "upstreams": {
+            "total": upstreams.len(),
+            "down": upstreams.iter().filter(|u| u.down_secs > 0).count(),
+        },
+        "proxies": state.egress.as_ref().map(|pool| {
+            let status = pool.status();
+            json!({
+                "total": status.len(),
+                "down": status.iter().filter(|p| p.down_secs > 0).count(),
+            })
+        }),
+        "maintenance": state.maintenance.get().enabled,
+    }))
+    .into_response()
+}
+
+async fn dashboard_page(State(state): State<Arc<AppState>>) -> Response {
+    if state.dashboard.is_none() {
+        return error(StatusCode::NOT_FOUND, "dashboard is not enabled");
+    }
+    Html(dashboard::PAGE).into_response()
+}
+
+async fn dashboard_data(State(state): State<Arc<AppState>>) -> Response {
+    let Some(dashboard) = &state.dashboard else {
+        return error(StatusCode::NOT_FOUND, "dashboard is not enabled");
+    };
+    let usage = dashboard.keys();
+    let keys = state.key_pool.as_ref().map(|pool| {
+        pool.status()
+            .into_iter()
+            .map(|key| {
+                let used = usage.get(&key.name).cloned().unwrap_or_default();
+                json!({
+                    "name": key.name,
+                    "cooldown_secs": key.cooldown_secs,
+                    "disabled": key.disabled,
+                    "quarantined": key.quarantined,
+                    "active": key.active,
+                    "requests": used.requests,
+                    "errors": used.errors,
+                })
+            })
+            .collect::<Vec<_>>()
+    });
+    Json(json!({
+        "uptime_secs": state.started.elapsed().as_secs(),
+        "requests_total": state.request_ids.load(Ordering::Relaxed),
+        "in_flight": state.in_flight.load(Ordering::Relaxed),
+        "series": dashboard.series(),
+        "latency": dashboard.latency(),
+        "keys": keys,
+        "upstreams": state.upstreams.status(),
+        "errors": dashboard.errors(),
+    }))
+    .into_response()
+}
+
+async fn get_log_level(State(state): State<Arc<AppState>>) -> Response {
+    let Some(control) = &state.log_level else {
+        return error(StatusCode::NOT_FOUND, "runtime log level control is not enabled");
+    };
+    Json(json!({ "level": control.get() })).into_response()
+}
+
+#[derive(Deserialize)]
+struct LogLevelBody {
+    /// tracing 过滤表达式，如 debug 或 info,aizasy_gateway=trace
+    level: String,
+}
+
+async fn put_log_level(State(state): State<Arc<AppState>>, Json(body): Json<LogLevelBody>) -> Response {
+    let Some(control) = &state.log_level else {
+        return error(StatusCode::NOT_FOUND, "runtime log level control is not enabled");
+    };
+    if let Err(e) = control.set(&body.level) {
+        return error(StatusCode::BAD_REQUEST, e);
+    }
+    info!("📝 Log level set to {} via admin API", body.level);
+    Json(json!({ "level": control.get() })).into_response()
+}
+
+fn drain_status(state: &AppState) -> Response {
+    Json(json!({
+        "draining": state.drain.is_draining(),
+        "in_flight": state.in_flight.load(Ordering::Relaxed),
+    }))
+    .into_response()
+}
+
+async fn get_drain(State(state): State<Arc<AppState>>) -> Response {
+    drain_status(&state)
+}
+
+// 开始摘流，重复调用无副作用；摘流不能撤销，只能等进程退出
+async fn start_drain(State(state): State<Arc<AppState>>) -> Response {
+    if state.drain.start() {
+        warn!("🚰 Drain requested via admin API");
+    }
+    drain_status(&state)
+}
+
+#[derive(Deserialize)]
+struct CacheQuery {
+    client: Option<String>,
+}
+
+async fn list_caches(State(state): State<Arc<AppState>>, Query(query): Query<CacheQuery>) -> Response {
+    let Some(caches) = &state.cached_contents else {
+        return error(StatusCode::NOT_FOUND, "cachedContents tracking is not enabled");
+    };
+    match caches.list(query.client.as_deref()).await {
+        Ok(records) => Json(json!({ "caches": records })).into_response(),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+#[derive(Deserialize)]
+struct RecentQuery {
+    limit: Option<usize>,
+}
+
+async fn recent_requests(State(state): State<Arc<AppState>>, Query(query): Query<RecentQuery>) -> Response {
+    let Some(inspector) = &state.inspector else {
+        return error(StatusCode::NOT_FOUND, "request inspector is not enabled");
+    };
+    let limit = query.limit.unwrap_or(100);
+    Json(json!({ "requests": inspector.recent(limit) })).into_response()
+}
+
+// SSE：每完成一个请求推送一条 `request` 事件；跟不上时推送 `lagged` 事件说明丢了多少条
+async fn tail_requests(State(state): State<Arc<AppState>>) -> Response {
+    let Some(inspector) = &state.inspector else {
+        return error(StatusCode::NOT_FOUND, "request inspector is not enabled");
+    };
+    let events = stream::unfold(inspector.subscribe(), |mut rx| async move {
+        let event = match rx.recv().await {
+            Ok(summary) => Event::default().event("request").json_data(&summary).ok()?,
+            Err(RecvError::Lagged(skipped)) => Event::default().event("lagged").data(skipped.to_string()),
+            Err(RecvError::Closed) => return None,
+        };
+        Some((Ok::<_, std::convert::Infallible>(event), rx))
+    });
+    Sse::new(events).keep_alive(KeepAlive::default()).into_response()
+}
+
+async fn list_failures(State(state): State<Arc<AppState>>) -> Response {
+    let Some(failures) = &state.failures else {
+        return error(StatusCode::NOT_FOUND, "failure log is not enabled");
+    };
+    Json(json!({ "failures": failures.list() })).into_response()
+}
+
+#[derive(Deserialize, Default)]
+struct ReplayBody {
+    /// 补充或覆盖的请求头，例如留存时被去掉的 x-goog-api-key
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+}
+
+// 重放结果里最多返回这么多字节的响应体
+const REPLAY_BODY_LIMIT: usize = 64 * 1024;
+
+async fn replay_failure(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<u64>,
+    body: Option<Json<ReplayBody>>,
+) -> Response {
+    let Some(failures) = &state.failures else {
+        return error(StatusCode::NOT_FOUND, "failure log is not enabled");
+    };
+    let Some(failed) = failures.get(id) else {
+        return error(StatusCode::NOT_FOUND, format!("failed request {} not found", id));
+    };
+    if failed.truncated {
+        return error(StatusCode::CONFLICT, "request body was too large to keep, cannot replay");
+    }
+    let Ok(method) = failed.method.parse::<Method>() else {
+        return error(StatusCode::BAD_REQUEST, "invalid method");
+    };
+    let target = failed.target.as_deref().unwrap_or(&state.target_url);
+    let overrides = body.map(|Json(b)| b).unwrap_or_default();
+
+    let mut headers = HeaderMap::new();
+    for (name, value) in &failed.headers {
+        if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(value)) {
+            headers.append(name, value);
+        }
+    }
+    // 留存时去掉了凭证，当时用了 key 池的请求重放时也由网关注入，按原来的 key 分组选
+    let plan = &failed.plan;
+    let mut key = None;
+    let mut _slot = None;
+    let path = match state.key_pool.as_ref().filter(|_| plan.key_pool) {
+        Some(pool) => {
+            let Some((index, entry, slot)) = key_pool::reserve(&state, pool, &[], None, plan.key_group.as_deref()) else {
+                return error(StatusCode::SERVICE_UNAVAILABLE, "no upstream key available");
+            };
+            key = Some(index);
+            _slot = slot;
+            if plan.grpc {
+                pool.inject_header(&entry, &mut headers);
+                failed.path.clone()
+            } else {
+                pool.inject(&entry, &mut headers, &failed.path)
+            }
+        }
+        None => failed.path.clone(),
+    };
+    for (name, value) in &overrides.headers {
+        match (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(value)) {
+            (Ok(name), Ok(value)) => {
+                headers.insert(name, value);
+            }
+            _ => return error(StatusCode::BAD_REQUEST, format!("invalid header '{}'", name)),
+        }
+    }
+    if let Err(e) = proxy::check_proxied_route(&state, &failed.plan, target).await {
+        return error(StatusCode::FORBIDDEN, format!("target rejected by the dynamic target policy: {}", e));
+    }
+    // 和正常请求一样选客户端：上游自己的代理、运行时路由的 SSRF 过滤、代理池
+    let client = proxy::upstream_client(&state, plan, proxy::own_client(&state, target), &mut None, key, &[]);
+    let request = client.request(method, format!("{}{}", target, path)).headers(headers);
+    info!("🔁 Replaying failed request {} ({} {})", id, failed.method, failed.path);
+    let started = Instant::now();
+    // reqwest 的错误信息带着完整 URL，key 放在 query 里时会原样出现
+    let failure = |status: Option<u16>, e: reqwest::Error| {
+        Json(json!({
+            "id": id,
+            "status": status,
+            "error": sanitize::redact_text(&e.to_string()),
+            "elapsed_ms": started.elapsed().as_millis() as u64,
+        }))
+        .into_response()
+    };
+    let mut response = match request.body(failed.body.clone()).send().await {
+        Ok(response) => response,
+        Err(e) => return failure(None, e),
+    };
+    let status = response.status().as_u16();
+    let headers = sanitize_headers(response.headers());
+    // 只读 REPLAY_BODY_LIMIT 字节，剩下的不读
+    let mut body = Vec::new();
+    let mut truncated = false;
+    loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) => {
+                let room = REPLAY_BODY_LIMIT - body.len();
+                if chunk.len() > room {
+                    body.extend_from_slice(&chunk[..room]);
+                    truncated = true;
+                    break;
+                }
+                body.extend_from_slice(&chunk);
+            }
+            Ok(None) => break,
+            Err(e) => return failure(Some(status), e),
+        }
+    }
+    let text = String::from_utf8_lossy(&body).to_string();
+    Json(json!({
+        "id": id,
+        "status": status,
+        "headers": headers,
+        "body": text,
+        "body_truncated": truncated,
+        "elapsed_ms": started.elapsed().as_millis() as u64,
+    }))
+    .into_response()
+}
@@ -0,0 +1,443 @@
+use async_trait::async_trait;
+use axum::{
+    body::Bytes,
+    http::{HeaderMap, Method, StatusCode},
+    response::Response,
+};
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Semaphore;
+use tracing::warn;
+
+use crate::cached_contents::format_timestamp;
+use crate::plugin::{ChunkAction, GatewayPlugin, Outcome, RequestContext};
+use crate::sanitize::sanitize_path;
+use crate::usage::{self, UpstreamKeyId};
+
+// --- 提示词审计日志 ---
+// 合规模式：每个经过网关的模型调用 (GET / HEAD 以外的请求) 结束时追加一行 JSON，记下客户端身份、时间、
+// 请求体和响应体，供必须留存 LLM 交互记录的组织审计:
+//   {"ts":"2026-01-01T00:00:00.000Z","request_id":7,"client":"team-a","client_ip":"1.2.3.4","method":"POST",
+//    "path":"/v1beta/models/gemini-2.5-flash:generateContent","model":"gemini-2.5-flash","key":"key-1",
+//    "status":200,"duration_ms":812,"request":"{...}","response":"{...}",
+//    "request_hash":"…","response_hash":"…","request_bytes":512,"response_bytes":8192}
+// mode=hash 时不写原文，只写请求体和响应体的加盐哈希 (HMAC-SHA256，盐即密钥)，能核对某段内容是否出现过，
+// 但拿不到内容本身；mode=full 时同时写原文 (每边最多 MAX_CAPTURED 字节，超出时 truncated 为 true)，
+// 哈希总是覆盖完整内容，没有配置盐时是普通 SHA-256。流式响应记录的是原始 SSE 文本，不是 UTF-8 的内容 (压缩过的响应等) 只留哈希。
+// 写到的位置:
+//   文件路径        追加写入，超过 max_size 时改名为 .1 (原来的 .1 变成 .2 ……)，最多保留 max_files 个旧文件
+//   http(s)://URL   每条记录单独 POST 过去；连接失败、5xx、429 时按 1s、2s、4s 重试，
+//                   在途 (含等待重试) 的记录最多 MAX_PENDING 条，超出的丢弃并记警告和累计丢弃数
+// 插件按注册顺序执行，审计记录的是它之前的插件 (脱敏、改写) 处理之后、真正发往上游的请求体；
+// 被前面的插件直接答复的请求 (响应缓存命中、校验拒绝) 没有到达上游，不记录。
+
+// full 模式下每边最多保留的原文字节数
+const MAX_CAPTURED: usize = 1024 * 1024;
+
+// HTTP sink 最多同时发送 / 等待重试的记录数
+const MAX_PENDING: usize = 1024;
+// 每次重试前等待的时间
+const RETRY_BACKOFF: [Duration; 3] = [Duration::from_secs(1), Duration::from_secs(2), Duration::from_secs(4)];
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AuditMode {
+    /// 原文和哈希
+    #[default]
+    Full,
+    /// 只有加盐哈希
+    Hash,
+}
+
+impl std::str::FromStr for AuditMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "full" => Ok(Self::Full),
+            "hash" => Ok(Self::Hash),
+            _ => Err(format!("unknown audit mode '{}' (expected full or hash)", s)),
+        }
+    }
+}
+
+impl AuditMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Full => "full",
+            Self::Hash => "hash",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditConfig {
+    /// 文件路径或 http(s):// 地址
+    pub target: String,
+    pub mode: AuditMode,
+    /// 哈希的盐；hash 模式必须设置
+    pub salt: Option<String>,
+    /// 文件超过这么多字节时轮转，0 表示不轮转
+    pub max_size: u64,
+    /// 保留的旧文件个数
+    pub max_files: usize,
+}
+
+impl AuditConfig {
+    pub fn new(target: impl Into<String>) -> Self {
+        Self {
+            target: target.into(),
+            mode: AuditMode::Full,
+            salt: None,
+            max_size: 100 * 1024 * 1024,
+            max_files: 10,
+        }
+    }
+}
+
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    max_files: usize,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_size: u64, max_files: usize) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+        Ok(Self { path, file, size, max_size, max_files })
+    }
+
+    fn rotated(&self, index: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        name.into()
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        if self.max_files == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            let _ = std::fs::remove_file(self.rotated(self.max_files));
+            for index in (1..self.max_files).rev() {
+                let from = self.rotated(index);
+                if from.exists() {
+                    std::fs::rename(&from, self.rotated(index + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, self.rotated(1))?;
+        }
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    fn write(&mut self, line: &[u8]) -> std::io::Result<()> {
+        if self.max_size > 0 && self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(line)?;
+        self.file.flush()?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+}
+
+enum Sink {
+    File(Arc<Mutex<RotatingFile>>),
+    Http(Arc<HttpSink>),
+}
+
+struct HttpSink {
+    url: String,
+    client: Client,
+    // 在途记录的名额
+    slots: Arc<Semaphore>,
+    // 累计丢弃的记录数
+    dropped: AtomicU64,
+}
+
+impl HttpSink {
+    // 发送一次：连接失败、5xx、429 返回 Err 交给调用方重试，其他非 2xx 记警告后放弃
+    async fn post(&self, line: &[u8]) -> Result<(), String> {
+        let request = self.client.post(&self.url).header("content-type", "application/json").body(line.to_vec());
+        match request.send().await {
+            Ok(response) if response.status().is_server_error() || response.status() == StatusCode::TOO_MANY_REQUESTS => {
+                Err(format!("returned {}", response.status()))
+            }
+            Ok(response) if !response.status().is_success() => {
+                warn!("🧾 Audit sink {} returned {}, dropping the record", self.url, response.status());
+                Ok(())
+            }
+            Ok(_) => Ok(()),
+            Err(e) => Err(e.without_url().to_string()),
+        }
+    }
+
+    async fn send(&self, line: Vec<u8>) {
+        let mut result = self.post(&line).await;
+        for delay in RETRY_BACKOFF {
+            let Err(e) = &result else {
+                return;
+            };
+            warn!("🧾 Failed to send audit record to {} ({}), retrying in {}s", self.url, e, delay.as_secs());
+            tokio::time::sleep(delay).await;
+            result = self.post(&line).await;
+        }
+        if let Err(e) = result {
+            self.drop_record(&format!("{} after {} retries", e, RETRY_BACKOFF.len()));
+        }
+    }
+
+    fn drop_record(&self, reason: &str) {
+        let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+        warn!("🧾 Dropped audit record for {}: {} ({} dropped so far)", self.url, reason, dropped);
+    }
+}
+
+#[derive(Clone)]
+enum Hasher {
+    Plain(Sha256),
+    Keyed(HmacSha256),
+}
+
+impl Hasher {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Plain(hasher) => hasher.update(data),
+            Hasher::Keyed(mac) => mac.update(data),
+        }
+    }
+
+    fn finish(self) -> String {
+        match self {
+            Hasher::Plain(hasher) => hex::encode(hasher.finalize()),
+            Hasher::Keyed(mac) => hex::encode(mac.finalize().into_bytes()),
+        }
+    }
+}
+
+// 一个请求已经记下的内容，挂在 RequestContext.extensions 上
+struct Capture {
+    request: Option<String>,
+    request_hash: String,
+    request_bytes: usize,
+    response: Vec<u8>,
+    response_hasher: Hasher,
+    response_bytes: usize,
+    // 非 identity 的响应编码，原文不可读
+    encoded: bool,
+    truncated: bool,
+}
+
+#[derive(Clone)]
+struct InFlight(Arc<Mutex<Capture>>);
+
+#[derive(Serialize)]
+struct AuditEntry {
+    ts: String,
+    request_id: u64,
+    client: String,
+    client_ip: String,
+    method: String,
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+    status: Option<u16>,
+    duration_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response: Option<String>,
+    request_hash: String,
+    response_hash: String,
+    request_bytes: usize,
+    response_bytes: usize,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    truncated: bool,
+}
+
+pub struct AuditLog {
+    mode: AuditMode,
+    salt: Option<Vec<u8>>,
+    sink: Sink,
+}
+
+impl AuditLog {
+    pub fn open(config: &AuditConfig) -> Result<Self, String> {
+        if config.mode == AuditMode::Hash && config.salt.as_deref().is_none_or(str::is_empty) {
+            return Err("audit mode hash needs a salt".to_string());
+        }
+        let sink = if config.target.starts_with("http://") || config.target.starts_with("https://") {
+            let client = Client::builder().build().map_err(|e| e.to_string())?;
+            Sink::Http(Arc::new(HttpSink {
+                url: config.target.clone(),
+                client,
+                slots: Arc::new(Semaphore::new(MAX_PENDING)),
+                dropped: AtomicU64::new(0),
+            }))
+        } else {
+            let file = RotatingFile::open(PathBuf::from(&config.target), config.max_size, config.max_files)?;
+            Sink::File(Arc::new(Mutex::new(file)))
+        };
+        Ok(Self {
+            mode: config.mode,
+            salt: config.salt.as_ref().filter(|s| !s.is_empty()).map(|s| s.as_bytes().to_vec()),
+            sink,
+        })
+    }
+
+    /// 和 open 做同样的检查，但不创建日志文件；配置检查用
+    pub fn check(config: &AuditConfig) -> Result<(), String> {
+        if config.mode == AuditMode::Hash && config.salt.as_deref().is_none_or(str::is_empty) {
+            return Err("audit mode hash needs a salt".to_string());
+        }
+        if config.target.starts_with("http://") || config.target.starts_with("https://") {
+            return reqwest::Url::parse(&config.target).map(|_| ()).map_err(|e| format!("{}: {}", config.target, e));
+        }
+        crate::access_log::check_writable(Path::new(&config.target))
+    }
+
+    fn hasher(&self) -> Hasher {
+        match &self.salt {
+            Some(salt) => Hasher::Keyed(HmacSha256::new_from_slice(salt).expect("HMAC accepts any key length")),
+            None => Hasher::Plain(Sha256::new()),
+        }
+    }
+
+    fn write(&self, entry: AuditEntry) {
+        let Ok(mut line) = serde_json::to_vec(&entry) else {
+            return;
+        };
+        match &self.sink {
+            Sink::File(file) => {
+                let file = file.clone();
+                line.push(b'\n');
+                tokio::task::spawn_blocking(move || {
+                    let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+                    if let Err(e) = file.write(&line) {
+                        warn!("🧾 Failed to write audit log {}: {}", file.path.display(), e);
+                    }
+                });
+            }
+            Sink::Http(sink) => {
+                let Ok(permit) = sink.slots.clone().try_acquire_owned() else {
+                    sink.drop_record(&format!("{} records already pending", MAX_PENDING));
+                    return;
+                };
+                let sink = sink.clone();
+                tokio::spawn(async move {
+                    let _permit = permit;
+                    sink.send(line).await;
+                });
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl GatewayPlugin for AuditLog {
+    fn name(&self) -> &str {
+        "audit"
+    }
+
+    async fn on_request(&self, ctx: &mut RequestContext) -> Result<(), Response> {
+        if ctx.method == Method::GET || ctx.method == Method::HEAD {
+            return Ok(());
+        }
+        let mut hasher = self.hasher();
+        hasher.update(&ctx.body);
+        let request = (self.mode == AuditMode::Full).then(|| {
+            let captured = &ctx.body[..ctx.body.len().min(MAX_CAPTURED)];
+            String::from_utf8_lossy(captured).into_owned()
+        });
+        let capture = Capture {
+            request,
+            request_hash: hasher.finish(),
+            request_bytes: ctx.body.len(),
+            response: Vec::new(),
+            response_hasher: self.hasher(),
+            response_bytes: 0,
+            encoded: false,
+            truncated: ctx.body.len() > MAX_CAPTURED && self.mode == AuditMode::Full,
+        };
+        ctx.extensions.insert(InFlight(Arc::new(Mutex::new(capture))));
+        Ok(())
+    }
+
+    async fn on_upstream_response(&self, ctx: &RequestContext, _status: StatusCode, headers: &mut HeaderMap) {
+        if let Some(InFlight(capture)) = ctx.extensions.get::<InFlight>() {
+            let encoding = headers.get("content-encoding").and_then(|v| v.to_str().ok());
+            capture.lock().unwrap_or_else(|e| e.into_inner()).encoded = encoding.is_some_and(|e| e != "identity");
+        }
+    }
+
+    fn on_chunk(&self, ctx: &RequestContext, chunk: &Bytes) -> ChunkAction {
+        if let Some(InFlight(capture)) = ctx.extensions.get::<InFlight>() {
+            let mut capture = capture.lock().unwrap_or_else(|e| e.into_inner());
+            capture.response_hasher.update(chunk);
+            capture.response_bytes += chunk.len();
+            if self.mode == AuditMode::Full && !capture.encoded {
+                let room = MAX_CAPTURED.saturating_sub(capture.response.len());
+                capture.response.extend_from_slice(&chunk[..chunk.len().min(room)]);
+                capture.truncated |= chunk.len() > room;
+            }
+        }
+        ChunkAction::Continue
+    }
+
+    fn on_complete(&self, ctx: &RequestContext, outcome: &Outcome) {
+        let Some(InFlight(capture)) = ctx.extensions.get::<InFlight>() else {
+            return;
+        };
+        let mut capture = capture.lock().unwrap_or_else(|e| e.into_inner());
+        let response = std::mem::take(&mut capture.response);
+        let response = match self.mode {
+            AuditMode::Full if !capture.encoded => Some(String::from_utf8_lossy(&response).into_owned()),
+            _ => None,
+        };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        let path = sanitize_path(ctx.uri.path_and_query().map(|p| p.as_str()).unwrap_or("/"));
+        let entry = AuditEntry {
+            ts: format_timestamp(now),
+            request_id: ctx.id,
+            client: usage::client_label(ctx),
+            client_ip: ctx.client_ip.to_string(),
+            method: ctx.method.to_string(),
+            model: usage::model_from_path(ctx.uri.path()).map(str::to_string),
+            path,
+            key: ctx.extensions.get::<UpstreamKeyId>().map(|k| k.0.clone()),
+            status: outcome.status.map(|s| s.as_u16()),
+            duration_ms: outcome.duration.as_millis() as u64,
+            error: outcome.error.clone(),
+            request: capture.request.take(),
+            response,
+            request_hash: capture.request_hash.clone(),
+            response_hash: capture.response_hasher.clone().finish(),
+            request_bytes: capture.request_bytes,
+            response_bytes: capture.response_bytes,
+            truncated: capture.truncated,
+        };
+        drop(capture);
+        self.write(entry);
+    }
+}
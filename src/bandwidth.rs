@@ -0,0 +1,264 @@
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::time::Sleep;
+
+use crate::client_ip::ClientIp;
+use crate::usage::{self, ClientIdentity};
+use crate::AppState;
+
+// --- 下行带宽限制 ---
+// 按字节的令牌桶限制发给客户端的响应体速率，防止单个客户端拉大体积的多模态响应占满网关的上行带宽。
+// 客户端的划分和 --rate-limit 一样：有客户端令牌时按令牌名，否则按来源 IP。
+//   SUBJECT 为令牌名或 ip:<地址>    只限制这个客户端
+//   SUBJECT 为 *                    没有单独配置的客户端各自一个桶
+//   SUBJECT 为 global               所有响应共用一个桶，和按客户端的限制同时生效
+// 桶容量 burst 默认等于每秒速率；桶空了就暂停读取上游响应体，背压一路传回上游连接。
+// 计的是压缩 (--compress) 之前的字节；桶只在单个实例内有效，多实例部署时每个实例各自限制。
+
+// 每次最多放出的字节数，大的非流式响应体也能被均匀地切开
+const MAX_FRAME: usize = 16 * 1024;
+
+// 按客户端的桶超过这个数后清理已经回满且没有在途响应的
+const MAX_CLIENT_BUCKETS: usize = 10_000;
+
+/// 解析 `1048576`、`512KB`、`64MB`、`1GB` 这样的大小
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let upper = value.to_ascii_uppercase();
+    let (number, unit) = [("GB", 1 << 30), ("MB", 1 << 20), ("KB", 1 << 10), ("B", 1)]
+        .into_iter()
+        .find_map(|(suffix, unit)| upper.strip_suffix(suffix).map(|n| (n.trim().to_string(), unit)))
+        .unwrap_or((upper.clone(), 1));
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(unit))
+        .ok_or_else(|| format!("invalid size '{}'", value))
+}
+
+#[derive(Debug, Clone)]
+pub struct BandwidthLimit {
+    /// 客户端标识、`*` 或 `global`
+    pub subject: String,
+    /// 每秒字节数
+    pub rate: u64,
+    /// 桶容量 (字节)
+    pub burst: u64,
+}
+
+impl BandwidthLimit {
+    /// 解析 `SUBJECT;rate=SIZE;burst=SIZE`，大小可以带单位 (KB/MB/GB)，burst 默认等于 rate
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut parts = spec.split(';');
+        let subject = parts.next().unwrap_or("").trim();
+        if subject.is_empty() {
+            return Err(format!("bandwidth limit '{}' has no subject", spec));
+        }
+        let (mut rate, mut burst) = (None, None);
+        for part in parts.map(str::trim).filter(|p| !p.is_empty()) {
+            let size = |value: &str| parse_size(value).ok().filter(|v| *v > 0);
+            match part.split_once('=') {
+                Some(("rate", value)) => rate = Some(size(value).ok_or_else(|| format!("invalid rate in '{}'", spec))?),
+                Some(("burst", value)) => burst = Some(size(value).ok_or_else(|| format!("invalid burst in '{}'", spec))?),
+                _ => return Err(format!("unknown bandwidth limit option '{}'", part)),
+            }
+        }
+        let rate = rate.ok_or_else(|| format!("bandwidth limit '{}' needs rate", spec))?;
+        Ok(BandwidthLimit {
+            subject: subject.to_string(),
+            rate,
+            burst: burst.unwrap_or(rate),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    rate: f64,
+    capacity: f64,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl Bucket {
+    fn new(limit: &BandwidthLimit) -> Self {
+        Self {
+            rate: limit.rate as f64,
+            capacity: limit.burst as f64,
+            state: Mutex::new((limit.burst as f64, Instant::now())),
+        }
+    }
+
+    // 补充到现在，返回当前余量
+    fn refill(&self, state: &mut (f64, Instant)) -> f64 {
+        let now = Instant::now();
+        state.0 = (state.0 + now.duration_since(state.1).as_secs_f64() * self.rate).min(self.capacity);
+        state.1 = now;
+        state.0
+    }
+
+    fn is_full(&self) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        self.refill(&mut state) >= self.capacity
+    }
+}
+
+pub struct Bandwidth {
+    limits: Vec<BandwidthLimit>,
+    global: Option<Arc<Bucket>>,
+    clients: Mutex<HashMap<String, Arc<Bucket>>>,
+}
+
+impl Bandwidth {
+    pub fn new(limits: Vec<BandwidthLimit>) -> Self {
+        let global = limits.iter().find(|l| l.subject == "global").map(|l| Arc::new(Bucket::new(l)));
+        Self {
+            limits,
+            global,
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn limits(&self) -> &[BandwidthLimit] {
+        &self.limits
+    }
+
+    // 单独配置的客户端优先于 `*`
+    fn client_bucket(&self, client: &str) -> Option<Arc<Bucket>> {
+        let limit = self
+            .limits
+            .iter()
+            .find(|l| l.subject == client)
+            .or_else(|| self.limits.iter().find(|l| l.subject == "*"))?;
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(bucket) = clients.get(client) {
+            return Some(bucket.clone());
+        }
+        if clients.len() >= MAX_CLIENT_BUCKETS {
+            clients.retain(|_, bucket| Arc::strong_count(bucket) > 1 || !bucket.is_full());
+        }
+        let bucket = Arc::new(Bucket::new(limit));
+        clients.insert(client.to_string(), bucket.clone());
+        Some(bucket)
+    }
+
+    fn buckets(&self, client: &str) -> Vec<Arc<Bucket>> {
+        self.client_bucket(client).into_iter().chain(self.global.clone()).collect()
+    }
+}
+
+// 从所有桶里取最多 want 个字节；桶都有余量时返回取到的字节数，否则返回要等多久
+fn take(buckets: &[Arc<Bucket>], want: usize) -> Result<usize, Duration> {
+    // 固定按 客户端 -> global 的顺序加锁
+    let mut states: Vec<_> = buckets.iter().map(|b| b.state.lock().unwrap_or_else(|e| e.into_inner())).collect();
+    let mut grant = want as f64;
+    let mut wait = Duration::ZERO;
+    for (bucket, state) in buckets.iter().zip(states.iter_mut()) {
+        let level = bucket.refill(state);
+        grant = grant.min(level.floor());
+        // 至少攒够一帧 (或整个桶) 再放行，避免一个字节一个字节地发
+        let needed = (want as f64).min(bucket.capacity);
+        if level < needed {
+            wait = wait.max(Duration::from_secs_f64((needed - level) / bucket.rate));
+        }
+    }
+    if wait > Duration::ZERO {
+        return Err(wait);
+    }
+    let grant = grant.max(1.0);
+    for state in states.iter_mut() {
+        state.0 -= grant;
+    }
+    Ok(grant as usize)
+}
+
+pub(crate) async fn throttle(State(state): State<Arc<AppState>>, ClientIp(ip): ClientIp, req: Request, next: Next) -> Response {
+    let Some(bandwidth) = &state.bandwidth else {
+        return next.run(req).await;
+    };
+    let client = match req.extensions().get::<ClientIdentity>() {
+        Some(identity) => identity.0.clone(),
+        None => usage::ip_label(ip),
+    };
+    let buckets = bandwidth.buckets(&client);
+    let response = next.run(req).await;
+    if buckets.is_empty() {
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let body = ThrottledBody {
+        inner: body,
+        buckets,
+        pending: Bytes::new(),
+        sleep: None,
+        state: state.clone(),
+    };
+    Response::from_parts(parts, Body::new(body))
+}
+
+struct ThrottledBody {
+    inner: Body,
+    buckets: Vec<Arc<Bucket>>,
+    // 从上游读到、还没放出去的数据
+    pending: Bytes,
+    sleep: Option<Pin<Box<Sleep>>>,
+    state: Arc<AppState>,
+}
+
+impl HttpBody for ThrottledBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<http_body::Frame<Self::Data>, Self::Error>>> {
+        let this = &mut *self;
+        loop {
+            if let Some(sleep) = this.sleep.as_mut() {
+                ready!(sleep.as_mut().poll(cx));
+                this.sleep = None;
+            }
+            if !this.pending.is_empty() {
+                match take(&this.buckets, this.pending.len().min(MAX_FRAME)) {
+                    Ok(n) => return Poll::Ready(Some(Ok(http_body::Frame::data(this.pending.split_to(n))))),
+                    Err(wait) => {
+                        this.state.metrics.inc("aizasy_bandwidth_throttled_total", &[]);
+                        this.sleep = Some(Box::pin(tokio::time::sleep(wait)));
+                        continue;
+                    }
+                }
+            }
+            match ready!(Pin::new(&mut this.inner).poll_frame(cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => this.pending = data,
+                    Err(frame) => return Poll::Ready(Some(Ok(frame))),
+                },
+                other => return Poll::Ready(other),
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.pending.is_empty() && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        let mut hint = self.inner.size_hint();
+        let pending = self.pending.len() as u64;
+        if let Some(upper) = hint.upper() {
+            hint.set_upper(upper + pending);
+        }
+        hint.set_lower(hint.lower() + pending);
+        hint
+    }
+}
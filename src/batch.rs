@@ -0,0 +1,245 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::{header, HeaderMap, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Extension, Json, Router,
+};
+use futures_util::{stream, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tower::Service;
+use tracing::debug;
+
+use crate::client_ip::ClientIp;
+use crate::events::RequestId;
+use crate::AppState;
+
+// --- 批量扇出 ---
+// POST /batch 接收一组 Gemini 请求，网关并发转发后汇总结果，客户端不用自己管理并发：
+//   {"requests": [{"model": "gemini-1.5-flash", "body": {...}},
+//                 {"path": "/v1beta/models/text-embedding-004:embedContent", "body": {...}}]}
+// 每个子请求都完整走一遍代理流程：PreProxy 层、突发限制、插件 (模型映射 / 别名、配额、速率限制、
+// 系统提示词等)、路径白名单、注入 key、计费，并继承批量请求的请求头、查询参数和鉴权结果。
+// 默认等全部完成后按顺序返回；`?alt=ndjson` 或 Accept: application/x-ndjson 时每完成一个输出一行。
+// 全网关共用一个并发上限，多个批量请求同时到达也不会把上游 key 打满。
+
+const MAX_BATCH_BODY: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct BatchConfig {
+    /// 单个批量请求最多包含的子请求数
+    pub max_requests: usize,
+    /// 全网关同时在途的子请求上限
+    pub concurrency: usize,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            max_requests: 100,
+            concurrency: 8,
+        }
+    }
+}
+
+pub(crate) struct BatchFanout {
+    config: BatchConfig,
+    permits: Arc<Semaphore>,
+}
+
+impl BatchFanout {
+    pub(crate) fn new(config: BatchConfig) -> Self {
+        let permits = Arc::new(Semaphore::new(config.concurrency.max(1)));
+        Self { config, permits }
+    }
+}
+
+/// 子请求走的 Router (代理处理函数加上逐个请求生效的中间件)，由 Gateway::router 挂在 /batch 上
+#[derive(Clone)]
+pub(crate) struct Subrequests(pub(crate) Router);
+
+#[derive(Debug, Deserialize)]
+struct BatchItem {
+    /// 完整 API 路径，优先于 model + method
+    #[serde(default)]
+    path: Option<String>,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default = "default_method")]
+    method: String,
+    #[serde(default)]
+    body: Value,
+}
+
+fn default_method() -> String {
+    "generateContent".to_string()
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchRequest {
+    requests: Vec<BatchItem>,
+}
+
+impl BatchItem {
+    fn resolve_path(&self) -> Result<String, String> {
+        let path = match (&self.path, &self.model) {
+            (Some(path), _) => path.clone(),
+            (None, Some(model)) => {
+                let model = model.strip_prefix("models/").unwrap_or(model);
+                format!("/v1beta/models/{}:{}", model, self.method)
+            }
+            (None, None) => return Err("each request needs either path or model".to_string()),
+        };
+        if !path.starts_with("/v1") || path.contains("..") || path.contains('?') {
+            return Err(format!("invalid request path '{}'", path));
+        }
+        // 流式接口没法汇总到一个响应里
+        if path.contains(":stream") {
+            return Err(format!("streaming method not allowed in batch: '{}'", path));
+        }
+        Ok(path)
+    }
+}
+
+fn invalid(message: String) -> Response {
+    let body = json!({
+        "error": {
+            "code": 400,
+            "message": message,
+            "status": "INVALID_ARGUMENT",
+        }
+    });
+    (StatusCode::BAD_REQUEST, Json(body)).into_response()
+}
+
+// 批量请求自身的查询参数 (例如 key=...) 原样带给每个子请求，去掉只对批量本身有意义的 alt
+fn forwarded_query(uri: &Uri) -> String {
+    uri.query()
+        .map(|q| q.split('&').filter(|p| !p.is_empty() && !p.starts_with("alt=")).collect::<Vec<_>>().join("&"))
+        .unwrap_or_default()
+}
+
+pub(crate) async fn handler(
+    State(state): State<Arc<AppState>>,
+    ClientIp(client_ip): ClientIp,
+    Extension(pipeline): Extension<Subrequests>,
+    req: Request,
+) -> Response {
+    let Some(fanout) = state.batch.as_ref() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if req.method() != Method::POST {
+        return StatusCode::METHOD_NOT_ALLOWED.into_response();
+    }
+    let ndjson = req.uri().query().is_some_and(|q| q.split('&').any(|p| p == "alt=ndjson"))
+        || req
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.contains("application/x-ndjson"));
+
+    let (parts, body) = req.into_parts();
+    let body = match axum::body::to_bytes(body, MAX_BATCH_BODY).await {
+        Ok(body) => body,
+        Err(e) => return invalid(format!("failed to read batch body: {}", e)),
+    };
+    let batch: BatchRequest = match serde_json::from_slice(&body) {
+        Ok(batch) => batch,
+        Err(e) => return invalid(format!("invalid batch body: {}", e)),
+    };
+    if batch.requests.is_empty() {
+        return invalid("batch has no requests".to_string());
+    }
+    if batch.requests.len() > fanout.config.max_requests {
+        return invalid(format!(
+            "batch has {} requests, at most {} allowed",
+            batch.requests.len(),
+            fanout.config.max_requests
+        ));
+    }
+    let mut subrequests = Vec::with_capacity(batch.requests.len());
+    for (index, item) in batch.requests.iter().enumerate() {
+        match item.resolve_path() {
+            Ok(path) => subrequests.push((index, path, Bytes::from(item.body.to_string()))),
+            Err(e) => return invalid(format!("requests[{}]: {}", index, e)),
+        }
+    }
+    debug!("📦 Batch of {} requests from {}", subrequests.len(), client_ip);
+    state.metrics.add("aizasy_batch_subrequests_total", &[], subrequests.len() as u64);
+
+    let query = forwarded_query(&parts.uri);
+    let mut headers = parts.headers;
+    headers.insert(header::CONTENT_TYPE, header::HeaderValue::from_static("application/json"));
+    headers.remove(header::ACCEPT);
+    let shared = Arc::new((headers, parts.extensions));
+    let total = subrequests.len();
+    let results = stream::iter(subrequests)
+        .map(move |(index, path, body)| {
+            let state = state.clone();
+            let shared = shared.clone();
+            let query = query.clone();
+            let pipeline = pipeline.clone();
+            async move {
+                let permits = state.batch.as_ref().map(|f| f.permits.clone());
+                let _permit = match &permits {
+                    Some(permits) => permits.acquire().await.ok(),
+                    None => None,
+                };
+                let uri = if query.is_empty() { path } else { format!("{}?{}", path, query) };
+                let result = run_one(&state, pipeline, &shared.0, &shared.1, &uri, body).await;
+                item_json(index, result)
+            }
+        })
+        .buffer_unordered(total);
+
+    if ndjson {
+        let lines = results.map(|item| Ok::<_, std::io::Error>(Bytes::from(format!("{}\n", item))));
+        return ([(header::CONTENT_TYPE, "application/x-ndjson")], Body::from_stream(lines)).into_response();
+    }
+    let mut items: Vec<(usize, Value)> = results.map(|item| (item["index"].as_u64().unwrap_or(0) as usize, item)).collect().await;
+    items.sort_by_key(|(index, _)| *index);
+    Json(json!({ "responses": items.into_iter().map(|(_, item)| item).collect::<Vec<_>>() })).into_response()
+}
+
+async fn run_one(
+    state: &AppState,
+    Subrequests(mut router): Subrequests,
+    headers: &HeaderMap,
+    extensions: &axum::http::Extensions,
+    uri: &str,
+    body: Bytes,
+) -> Result<(StatusCode, Bytes), String> {
+    let mut request = Request::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .body(Body::from(body))
+        .map_err(|e| e.to_string())?;
+    *request.headers_mut() = headers.clone();
+    *request.extensions_mut() = extensions.clone();
+    // 子请求有自己的编号，日志和账本里可以单独追踪
+    let id = state.request_ids.fetch_add(1, Ordering::Relaxed) + 1;
+    request.extensions_mut().insert(RequestId(id));
+    let response = match router.call(request).await {
+        Ok(response) => response,
+        Err(never) => match never {},
+    };
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), MAX_BATCH_BODY).await.map_err(|e| e.to_string())?;
+    Ok((status, body))
+}
+
+fn item_json(index: usize, result: Result<(StatusCode, Bytes), String>) -> Value {
+    match result {
+        Ok((status, body)) => {
+            // 上游错误也是 JSON；网关自身的纯文本错误原样作为字符串返回
+            let body = serde_json::from_slice::<Value>(&body)
+                .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&body).into_owned()));
+            json!({ "index": index, "status": status.as_u16(), "body": body })
+        }
+        Err(e) => json!({ "index": index, "status": 502, "error": e }),
+    }
+}
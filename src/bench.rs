@@ -0,0 +1,117 @@
+use futures_util::{stream, StreamExt};
+use reqwest::header::HeaderMap;
+use reqwest::Method;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+// --- 压测 ---
+// 对网关 (或任意 Gemini 兼容地址) 并发发一批相同的请求，统计延迟分位数、吞吐和状态码分布，
+// 方便调整限流、连接池等参数后快速对比。响应体会完整读完，流式接口统计的是整段耗时。
+
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    pub url: String,
+    pub method: Method,
+    pub headers: HeaderMap,
+    pub body: Option<String>,
+    pub requests: usize,
+    pub concurrency: usize,
+    pub timeout: Duration,
+}
+
+#[derive(Debug, Default)]
+pub struct BenchReport {
+    pub elapsed: Duration,
+    pub statuses: BTreeMap<u16, usize>,
+    /// 连接失败、超时等没有拿到状态码的请求
+    pub errors: usize,
+    // 所有拿到响应的请求耗时，升序
+    latencies: Vec<Duration>,
+}
+
+impl BenchReport {
+    pub fn total(&self) -> usize {
+        self.latencies.len() + self.errors
+    }
+
+    pub fn percentile(&self, p: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = ((p / 100.0) * self.latencies.len() as f64).ceil() as usize;
+        self.latencies[rank.clamp(1, self.latencies.len()) - 1]
+    }
+
+    pub fn throughput(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.total() as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+fn ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Requests:   {} in {:.2}s ({:.1} req/s)", self.total(), self.elapsed.as_secs_f64(), self.throughput())?;
+        writeln!(
+            f,
+            "Latency:    p50 {:.1}ms  p90 {:.1}ms  p99 {:.1}ms  max {:.1}ms",
+            ms(self.percentile(50.0)),
+            ms(self.percentile(90.0)),
+            ms(self.percentile(99.0)),
+            ms(self.latencies.last().copied().unwrap_or_default()),
+        )?;
+        for (status, count) in &self.statuses {
+            writeln!(f, "Status {}: {}", status, count)?;
+        }
+        if self.errors > 0 {
+            writeln!(f, "Errors:     {}", self.errors)?;
+        }
+        Ok(())
+    }
+}
+
+pub async fn run(client: &reqwest::Client, config: &BenchConfig) -> BenchReport {
+    let started = Instant::now();
+    let results: Vec<Result<(u16, Duration), String>> = stream::iter(0..config.requests)
+        .map(|_| async move {
+            let sent = Instant::now();
+            let mut request = client
+                .request(config.method.clone(), &config.url)
+                .headers(config.headers.clone())
+                .timeout(config.timeout);
+            if let Some(body) = &config.body {
+                request = request.header("content-type", "application/json").body(body.clone());
+            }
+            let response = request.send().await.map_err(|e| e.to_string())?;
+            let status = response.status().as_u16();
+            response.bytes().await.map_err(|e| e.to_string())?;
+            Ok((status, sent.elapsed()))
+        })
+        .buffer_unordered(config.concurrency.max(1))
+        .collect()
+        .await;
+
+    let mut report = BenchReport {
+        elapsed: started.elapsed(),
+        ..Default::default()
+    };
+    for result in results {
+        match result {
+            Ok((status, latency)) => {
+                *report.statuses.entry(status).or_default() += 1;
+                report.latencies.push(latency);
+            }
+            Err(_) => report.errors += 1,
+        }
+    }
+    report.latencies.sort();
+    report
+}
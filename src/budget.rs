@@ -0,0 +1,212 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Serialize;
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::export::{civil_from_days, utc_day};
+use crate::ledger::{LedgerEntry, LedgerHook};
+use crate::report::days_from_civil;
+use crate::storage::Storage;
+
+// --- 预算阈值告警 ---
+// 每条账本记录累加到 (对象, 周期) 的计数器上；跨过 50% / 80% / 100% 时记日志并发 webhook。
+// 每个阈值在一个周期内只告警一次 (已告警的最高阈值存在存储里)，计数器波动不会反复触发。
+
+const THRESHOLDS: &[u64] = &[50, 80, 100];
+// 费用以百万分之一为单位存成整数计数器
+const COST_SCALE: f64 = 1_000_000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetUnit {
+    Cost,
+    Tokens,
+}
+
+impl std::str::FromStr for BudgetUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cost" => Ok(Self::Cost),
+            "tokens" => Ok(Self::Tokens),
+            other => Err(format!("unknown unit '{}'", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetPeriod {
+    Day,
+    Month,
+}
+
+impl BudgetPeriod {
+    pub(crate) fn key(&self, ts_ms: u64) -> String {
+        let day = utc_day(ts_ms);
+        match self {
+            Self::Day => day,
+            Self::Month => day[..7].to_string(),
+        }
+    }
+
+    /// ts_ms 所在周期的起止 (unix 毫秒，左闭右开)
+    pub(crate) fn bounds(&self, ts_ms: u64) -> (u64, u64) {
+        const DAY_MS: u64 = 86_400_000;
+        let day = ts_ms / DAY_MS;
+        match self {
+            Self::Day => (day * DAY_MS, (day + 1) * DAY_MS),
+            Self::Month => {
+                let (year, month, _) = civil_from_days(day as i64);
+                let (next_year, next_month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+                let start = days_from_civil(year, month as i64, 1) as u64 * DAY_MS;
+                let end = days_from_civil(next_year, next_month as i64, 1) as u64 * DAY_MS;
+                (start, end)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Budget {
+    /// 客户端标识、`key:<key 名>`，或 `*` (每个客户端各自计算)
+    pub subject: String,
+    pub limit: f64,
+    pub unit: BudgetUnit,
+    pub period: BudgetPeriod,
+}
+
+impl Budget {
+    /// 解析 `SUBJECT=LIMIT;unit=cost|tokens;period=day|month`，默认按月计费用
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut parts = spec.split(';');
+        let head = parts.next().unwrap_or("");
+        let (subject, limit) = head
+            .rsplit_once('=')
+            .ok_or_else(|| format!("budget '{}' must be SUBJECT=LIMIT", spec))?;
+        let limit: f64 = limit.trim().parse().map_err(|_| format!("invalid budget limit in '{}'", spec))?;
+        if limit <= 0.0 {
+            return Err(format!("budget limit must be positive in '{}'", spec));
+        }
+        let mut budget = Budget {
+            subject: subject.trim().to_string(),
+            limit,
+            unit: BudgetUnit::Cost,
+            period: BudgetPeriod::Month,
+        };
+        for part in parts.map(str::trim).filter(|p| !p.is_empty()) {
+            match part.split_once('=') {
+                Some(("unit", unit)) => budget.unit = unit.parse()?,
+                Some(("period", "day")) => budget.period = BudgetPeriod::Day,
+                Some(("period", "month")) => budget.period = BudgetPeriod::Month,
+                _ => return Err(format!("unknown budget option '{}'", part)),
+            }
+        }
+        Ok(budget)
+    }
+
+    // 返回这条记录应计入的对象名
+    fn subject_for(&self, entry: &LedgerEntry) -> Option<String> {
+        if self.subject == "*" {
+            return Some(entry.client.clone());
+        }
+        let key_match = entry.key.as_deref().is_some_and(|k| self.subject.strip_prefix("key:") == Some(k));
+        (self.subject == entry.client || key_match).then(|| self.subject.clone())
+    }
+
+    // 计数器里的单位
+    fn amount(&self, entry: &LedgerEntry) -> i64 {
+        match self.unit {
+            BudgetUnit::Cost => (entry.cost * COST_SCALE).round() as i64,
+            BudgetUnit::Tokens => (entry.prompt_tokens + entry.output_tokens) as i64,
+        }
+    }
+
+    fn scaled_limit(&self) -> f64 {
+        match self.unit {
+            BudgetUnit::Cost => self.limit * COST_SCALE,
+            BudgetUnit::Tokens => self.limit,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BudgetAlert {
+    pub subject: String,
+    pub threshold_percent: u64,
+    pub used: f64,
+    pub limit: f64,
+    pub unit: BudgetUnit,
+    pub period: BudgetPeriod,
+    pub period_key: String,
+}
+
+pub struct BudgetMonitor {
+    budgets: Vec<Budget>,
+    storage: Arc<dyn Storage>,
+    webhook: Option<String>,
+    client: Client,
+}
+
+impl BudgetMonitor {
+    pub fn new(budgets: Vec<Budget>, storage: Arc<dyn Storage>, webhook: Option<String>) -> Self {
+        Self {
+            budgets,
+            storage,
+            webhook,
+            client: Client::new(),
+        }
+    }
+
+    async fn check(&self, budget: &Budget, subject: String, entry: &LedgerEntry) -> Result<(), String> {
+        let period_key = budget.period.key(entry.ts);
+        let base = format!("budget:{}:{}", subject, period_key);
+        let used = self.storage.incr(&base, budget.amount(entry), None).await?;
+
+        let percent = (used as f64 / budget.scaled_limit() * 100.0) as u64;
+        let Some(&crossed) = THRESHOLDS.iter().rev().find(|t| percent >= **t) else {
+            return Ok(());
+        };
+        // 每个阈值一个标记，用 incr 抢占：并发 (或多实例共用存储) 时只有拿到 1 的那次发告警
+        let alerted_key = format!("{}:alerted:{}", base, crossed);
+        if self.storage.incr(&alerted_key, 1, None).await? != 1 {
+            return Ok(());
+        }
+
+        let scale = budget.scaled_limit() / budget.limit;
+        let alert = BudgetAlert {
+            subject,
+            threshold_percent: crossed,
+            used: used as f64 / scale,
+            limit: budget.limit,
+            unit: budget.unit,
+            period: budget.period,
+            period_key,
+        };
+        warn!(
+            "💸 Budget {}% reached for {} ({:.4}/{} {:?} in {})",
+            alert.threshold_percent, alert.subject, alert.used, alert.limit, alert.unit, alert.period_key
+        );
+        if let Some(url) = &self.webhook {
+            if let Err(e) = self.client.post(url).json(&alert).send().await.and_then(|r| r.error_for_status()) {
+                warn!("💸 Budget webhook {} failed: {}", url, e);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LedgerHook for BudgetMonitor {
+    async fn on_entry(&self, entry: &LedgerEntry) {
+        for budget in &self.budgets {
+            if let Some(subject) = budget.subject_for(entry) {
+                if let Err(e) = self.check(budget, subject, entry).await {
+                    warn!("💸 Budget tracking failed: {}", e);
+                }
+            }
+        }
+    }
+}
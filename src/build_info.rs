@@ -0,0 +1,30 @@
+use serde_json::{json, Value};
+
+use crate::cached_contents::format_timestamp;
+
+// --- 构建信息 ---
+// 版本号、git 提交、构建时间和编译时开启的特性 (由 build.rs 写入)，
+// 公开的 /version 只返回这些；/admin/version 另外带上生效中的配置摘要，方便核对整个集群部署的是什么。
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const GIT_COMMIT: &str = env!("AIZASY_GIT_COMMIT");
+pub const FEATURES: &str = env!("AIZASY_FEATURES");
+
+/// 构建时间 (unix 秒)
+pub fn built_at() -> u64 {
+    env!("AIZASY_BUILD_TIME").parse().unwrap_or(0)
+}
+
+pub fn features() -> Vec<&'static str> {
+    FEATURES.split(',').filter(|f| !f.is_empty()).collect()
+}
+
+pub(crate) fn json() -> Value {
+    json!({
+        "name": env!("CARGO_PKG_NAME"),
+        "version": VERSION,
+        "git_commit": GIT_COMMIT,
+        "built_at": format_timestamp(built_at() * 1000),
+        "features": features(),
+    })
+}
@@ -0,0 +1,237 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+use tracing::{info, warn};
+
+use crate::cidr::Cidr;
+use crate::client_ip::ClientIp;
+use crate::AppState;
+
+// --- 按 IP 的突发检测 + 拖延 / 临时封禁 ---
+// 和按客户端的限流 (--rate-limit) 互相独立：只看来源 IP，用来对付异常的请求突发和撞令牌。
+// 一个 IP 在一个窗口内的请求数超过阈值，或者鉴权失败次数达到阈值后，之后的每个请求都先被拖住再处理，
+// (鉴权失败不在这里计数，读的是 lockout 的按 IP 失败表：没开 --auth-lockout 时网关建一个只计数不锁定的)
+// 拖延从 delay 开始每次翻倍，封顶 max_delay；累计 ban_after 次后临时封禁，封禁期内直接返回 429。
+// 一个完整的窗口里没有再超限时清零重新计算。
+// 被拖住的连接同时最多 MAX_TARPITTED 个，再多的超限请求直接拒绝，拖延本身不会拖垮网关。
+// 过了窗口、没被封禁的条目由后台每个窗口清理一次，请求路径上不做全表扫描。
+
+const MAX_TARPITTED: usize = 1024;
+
+// 最多跟踪的 IP 数；表满时新出现的 IP 不计数直接放行，等后台清理腾出位置
+const MAX_ENTRIES: usize = 100_000;
+
+#[derive(Debug, Clone)]
+pub struct BurstConfig {
+    pub window: Duration,
+    /// 窗口内的请求数上限
+    pub requests: u32,
+    /// 连续鉴权失败次数上限 (和 lockout 共用计数，鉴权成功或恢复期内没有新的失败就清零)，0 表示不按鉴权失败判断
+    pub auth_failures: u32,
+    /// 第一次拖延的时长，之后每次翻倍
+    pub delay: Duration,
+    pub max_delay: Duration,
+    /// 累计超限多少次后封禁，0 表示只拖延不封禁
+    pub ban_after: u32,
+    pub ban: Duration,
+    /// 不检测的来源 (内网负载均衡、健康检查等)
+    pub exempt: Vec<Cidr>,
+}
+
+impl Default for BurstConfig {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(10),
+            requests: 100,
+            auth_failures: 10,
+            delay: Duration::from_millis(250),
+            max_delay: Duration::from_secs(10),
+            ban_after: 20,
+            ban: Duration::from_secs(600),
+            exempt: Vec::new(),
+        }
+    }
+}
+
+struct Entry {
+    window_start: Instant,
+    requests: u32,
+    // 这个窗口里是否超限过
+    abusive: bool,
+    // 累计超限次数，决定拖延时长和是否封禁
+    strikes: u32,
+    banned_until: Option<Instant>,
+}
+
+enum Verdict {
+    Pass,
+    Delay(Duration),
+    // 这次刚被封禁
+    Ban(Duration),
+    Banned(Duration),
+}
+
+pub struct BurstGuard {
+    config: BurstConfig,
+    entries: Mutex<HashMap<IpAddr, Entry>>,
+    tarpit: Semaphore,
+}
+
+impl BurstGuard {
+    pub fn new(config: BurstConfig) -> Self {
+        Self {
+            config,
+            entries: Mutex::new(HashMap::new()),
+            tarpit: Semaphore::new(MAX_TARPITTED),
+        }
+    }
+
+    #[cfg(feature = "admin")]
+    pub(crate) fn config(&self) -> &BurstConfig {
+        &self.config
+    }
+
+    /// 按鉴权失败判断时需要 lockout 的失败计数，返回计数多久没有新失败算恢复
+    pub(crate) fn auth_failure_window(&self) -> Option<Duration> {
+        (self.config.auth_failures > 0).then_some(self.config.window)
+    }
+
+    // 过了窗口就重新计数；上一个窗口没有超限时清零
+    fn roll(&self, entry: &mut Entry, now: Instant) {
+        if now.duration_since(entry.window_start) < self.config.window {
+            return;
+        }
+        if !entry.abusive {
+            entry.strikes = 0;
+        }
+        entry.window_start = now;
+        entry.requests = 0;
+        entry.abusive = false;
+    }
+
+    /// auth_failures 是这个 IP 还没恢复的鉴权失败次数
+    fn check(&self, ip: IpAddr, auth_failures: u32) -> Verdict {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.len() >= MAX_ENTRIES && !entries.contains_key(&ip) {
+            return Verdict::Pass;
+        }
+        let entry = entries.entry(ip).or_insert(Entry {
+            window_start: now,
+            requests: 0,
+            abusive: false,
+            strikes: 0,
+            banned_until: None,
+        });
+        match entry.banned_until {
+            Some(until) if until > now => return Verdict::Banned(until - now),
+            Some(_) => {
+                entry.banned_until = None;
+                info!("🔓 Burst ban expired for {}", ip);
+            }
+            None => {}
+        }
+        self.roll(entry, now);
+        entry.requests += 1;
+        let over = entry.requests > self.config.requests
+            || (self.config.auth_failures > 0 && auth_failures >= self.config.auth_failures);
+        if !over {
+            return Verdict::Pass;
+        }
+        entry.abusive = true;
+        entry.strikes += 1;
+        if self.config.ban_after > 0 && entry.strikes >= self.config.ban_after {
+            entry.strikes = 0;
+            entry.banned_until = Some(now + self.config.ban);
+            warn!("🚫 {} banned for {}s after repeated bursts", ip, self.config.ban.as_secs());
+            return Verdict::Ban(self.config.ban);
+        }
+        let exp = (entry.strikes - 1).min(16);
+        Verdict::Delay(self.config.delay.saturating_mul(1 << exp).min(self.config.max_delay))
+    }
+
+    // 去掉已经过了窗口、没被封禁的条目
+    fn sweep(&self) {
+        let now = Instant::now();
+        let window = self.config.window;
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.retain(|_, e| now.duration_since(e.window_start) < window || e.banned_until.is_some_and(|u| u > now));
+    }
+}
+
+/// 后台每个窗口清理一次过期条目；网关被释放 (热重载换掉) 后自动退出
+pub(crate) fn spawn_sweeper(state: Weak<AppState>) {
+    let Some(window) = state.upgrade().and_then(|state| state.burst.as_ref().map(|burst| burst.config.window)) else {
+        return;
+    };
+    tokio::spawn(async move {
+        let mut timer = tokio::time::interval(window);
+        timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        timer.tick().await;
+        loop {
+            timer.tick().await;
+            let Some(state) = state.upgrade() else {
+                return;
+            };
+            if let Some(burst) = &state.burst {
+                burst.sweep();
+            }
+        }
+    });
+}
+
+fn too_many(retry_after: Duration, message: &str) -> Response {
+    let retry_after = retry_after.as_secs().max(1);
+    let body = json!({
+        "error": {
+            "code": 429,
+            "message": message,
+            "status": "RESOURCE_EXHAUSTED",
+        }
+    });
+    (StatusCode::TOO_MANY_REQUESTS, [(header::RETRY_AFTER, retry_after.to_string())], Json(body)).into_response()
+}
+
+pub(crate) async fn guard(
+    State(state): State<Arc<AppState>>,
+    ClientIp(ip): ClientIp,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Some(burst) = state.burst.as_ref().filter(|b| !b.config.exempt.iter().any(|c| c.contains(ip))) else {
+        return next.run(req).await;
+    };
+
+    let auth_failures = state.lockout.as_ref().map_or(0, |lockout| lockout.failures(ip));
+    match burst.check(ip, auth_failures) {
+        Verdict::Pass => {}
+        Verdict::Ban(ban) => {
+            state.metrics.inc("aizasy_burst_bans_total", &[]);
+            return too_many(ban, "Too many requests from this address, temporarily banned");
+        }
+        Verdict::Banned(remaining) => {
+            state.metrics.inc("aizasy_burst_banned_requests_total", &[]);
+            return too_many(remaining, "Too many requests from this address, temporarily banned");
+        }
+        Verdict::Delay(delay) => {
+            let Ok(_permit) = burst.tarpit.try_acquire() else {
+                state.metrics.inc("aizasy_burst_rejected_total", &[]);
+                return too_many(delay, "Too many requests from this address");
+            };
+            state.metrics.inc("aizasy_burst_tarpitted_total", &[]);
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    next.run(req).await
+}
@@ -0,0 +1,427 @@
+use async_trait::async_trait;
+use axum::{
+    body::Bytes,
+    http::{Method, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, warn};
+
+use crate::plugin::{ChunkAction, GatewayPlugin, Outcome, RequestContext};
+use crate::report::days_from_civil;
+use crate::storage::Storage;
+use crate::usage::{self, ClientIdentity};
+
+// --- 上下文缓存 (cachedContents) ---
+// 跟踪每个客户端通过网关创建的 cachedContents，和上游的过期时间保持一致：
+// - 创建 / 更新成功后记录 name、model、expireTime，过期的记录定期清理
+// - 打开隔离后，客户端只能看到、修改、删除自己创建的缓存 (共用上游 key 时不会互相串)；
+//   没有客户端令牌的请求不能创建缓存，查不到归属 (存储出错) 时拒绝访问
+// - 可以给指定模型自动挂上运维预先创建好的缓存，客户端不用自己管理 cache handle
+// - 可以限制缓存的最长 TTL，避免忘记删除的大缓存一直计费
+
+// 创建 / 更新的响应体最多缓冲这么多字节用于解析
+const MAX_CAPTURE_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheRecord {
+    /// `cachedContents/xxx`
+    pub name: String,
+    pub client: String,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    /// unix 毫秒
+    pub expire_at: u64,
+    #[serde(default)]
+    pub total_tokens: Option<u64>,
+}
+
+/// 某个模型 (前缀匹配) 的请求自动使用的缓存
+#[derive(Debug, Clone)]
+pub struct CacheAttach {
+    pub model: String,
+    pub cache: String,
+}
+
+impl CacheAttach {
+    /// 解析 `MODEL=cachedContents/NAME`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (model, cache) = spec
+            .split_once('=')
+            .ok_or_else(|| format!("cache attach '{}' must be MODEL=cachedContents/NAME", spec))?;
+        let (model, cache) = (model.trim(), cache.trim());
+        if model.is_empty() || !cache.starts_with("cachedContents/") {
+            return Err(format!("cache attach '{}' must be MODEL=cachedContents/NAME", spec));
+        }
+        Ok(Self {
+            model: model.strip_prefix("models/").unwrap_or(model).to_string(),
+            cache: cache.to_string(),
+        })
+    }
+}
+
+pub struct CachedContents {
+    storage: Arc<dyn Storage>,
+    attach: Vec<CacheAttach>,
+    isolate: bool,
+    max_ttl: Option<Duration>,
+}
+
+// 需要解析响应体的请求 (创建 / 更新)
+#[derive(Clone)]
+struct Capture(Arc<Mutex<Vec<u8>>>);
+
+// 删除成功后要清掉的记录
+#[derive(Clone)]
+struct PendingDelete(String);
+
+enum CacheRoute {
+    List,
+    Create,
+    Item(String),
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+// `/v1beta/cachedContents[/ID]`
+fn cache_route(method: &Method, path: &str) -> Option<CacheRoute> {
+    let rest = &path[path.find("/cachedContents")? + "/cachedContents".len()..];
+    match (rest, method) {
+        ("", &Method::GET) => Some(CacheRoute::List),
+        ("", &Method::POST) => Some(CacheRoute::Create),
+        (id, _) => {
+            let id = id.strip_prefix('/')?;
+            (!id.is_empty() && !id.contains('/')).then(|| CacheRoute::Item(format!("cachedContents/{}", id)))
+        }
+    }
+}
+
+/// RFC 3339 UTC 时间 (`2024-06-30T09:00:00.123456Z`) -> unix 毫秒
+pub fn parse_timestamp(ts: &str) -> Option<u64> {
+    let ts = ts.strip_suffix('Z')?;
+    let (date, time) = ts.split_once('T')?;
+    let mut date = date.split('-').map(|p| p.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    let (hms, frac) = time.split_once('.').unwrap_or((time, ""));
+    let mut hms = hms.split(':').map(|p| p.parse::<u64>().ok());
+    let (h, m, s) = (hms.next()??, hms.next()??, hms.next()??);
+    let millis = format!("{:0<3}", frac.get(..3.min(frac.len()))?).parse::<u64>().ok()?;
+    let days = days_from_civil(year, month, day);
+    (days >= 0).then(|| (days as u64 * 86_400 + h * 3600 + m * 60 + s) * 1000 + millis)
+}
+
+// ttl 字段形如 "3600s" / "1.5s"
+fn parse_ttl(ttl: &str) -> Option<Duration> {
+    ttl.strip_suffix('s')?.parse::<f64>().ok().filter(|s| *s >= 0.0).map(Duration::from_secs_f64)
+}
+
+fn not_found(name: &str) -> Response {
+    let body = json!({
+        "error": {
+            "code": 404,
+            "message": format!("{} not found", name),
+            "status": "NOT_FOUND",
+        }
+    });
+    (StatusCode::NOT_FOUND, Json(body)).into_response()
+}
+
+fn rejected(status: StatusCode, state: &str, message: &str) -> Response {
+    let body = json!({
+        "error": {
+            "code": status.as_u16(),
+            "message": message,
+            "status": state,
+        }
+    });
+    (status, Json(body)).into_response()
+}
+
+fn record_key(client: &str, name: &str) -> String {
+    format!("cache:{}:{}", client, name)
+}
+
+fn owner_key(name: &str) -> String {
+    format!("cacheowner:{}", name)
+}
+
+async fn owner(storage: &dyn Storage, name: &str) -> Result<Option<String>, String> {
+    Ok(storage
+        .get(&owner_key(name))
+        .await?
+        .map(|v| String::from_utf8_lossy(&v).into_owned()))
+}
+
+async fn store(storage: &dyn Storage, record: CacheRecord) -> Result<(), String> {
+    let now = unix_ms();
+    if record.expire_at <= now {
+        return remove(storage, &record.name).await;
+    }
+    // 存储层的 TTL 比上游多留一分钟，既能自动过期，又不会在上游删除前先丢了归属
+    let ttl = Some(Duration::from_millis(record.expire_at - now) + Duration::from_secs(60));
+    let value = serde_json::to_vec(&record).map_err(|e| e.to_string())?;
+    storage.set(&owner_key(&record.name), record.client.clone().into_bytes(), ttl).await?;
+    storage.set(&record_key(&record.client, &record.name), value, ttl).await
+}
+
+async fn remove(storage: &dyn Storage, name: &str) -> Result<(), String> {
+    if let Some(owner) = owner(storage, name).await? {
+        storage.delete(&record_key(&owner, name)).await?;
+    }
+    storage.delete(&owner_key(name)).await
+}
+
+impl CachedContents {
+    pub fn new(storage: Arc<dyn Storage>, attach: Vec<CacheAttach>, isolate: bool, max_ttl: Option<Duration>) -> Self {
+        Self {
+            storage,
+            attach,
+            isolate,
+            max_ttl,
+        }
+    }
+
+    /// 客户端名下未过期的缓存，None 表示所有客户端
+    pub async fn list(&self, client: Option<&str>) -> Result<Vec<CacheRecord>, String> {
+        let (start, end) = match client {
+            Some(client) => (format!("cache:{}:", client), format!("cache:{};", client)),
+            None => ("cache:".to_string(), "cache;".to_string()),
+        };
+        let now = unix_ms();
+        let mut records: Vec<CacheRecord> = self
+            .storage
+            .scan(&start, &end)
+            .await?
+            .into_iter()
+            .filter_map(|(_, value)| serde_json::from_slice::<CacheRecord>(&value).ok())
+            .filter(|r| r.expire_at > now)
+            .collect();
+        records.sort_by_key(|r| r.expire_at);
+        Ok(records)
+    }
+
+    /// 清理已过期的记录，返回清掉的条数
+    pub async fn sweep(&self) -> Result<usize, String> {
+        let now = unix_ms();
+        let mut removed = 0;
+        for (key, value) in self.storage.scan("cache:", "cache;").await? {
+            let expired = serde_json::from_slice::<CacheRecord>(&value).map_or(true, |r| r.expire_at <= now);
+            if expired {
+                self.storage.delete(&key).await?;
+                if let Some(name) = key.rsplit_once(':').map(|(_, name)| name) {
+                    self.storage.delete(&owner_key(name)).await?;
+                }
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// 后台定期清理过期记录
+    pub fn spawn_sweeper(self: &Arc<Self>, interval: Duration) {
+        let this = self.clone();
+        tokio::spawn(async move {
+            let mut timer = tokio::time::interval(interval);
+            timer.tick().await;
+            loop {
+                timer.tick().await;
+                match this.sweep().await {
+                    Ok(0) => {}
+                    Ok(n) => debug!("🧊 Swept {} expired cachedContents record(s)", n),
+                    Err(e) => warn!("🧊 cachedContents sweep failed: {}", e),
+                }
+            }
+        });
+    }
+
+    // 创建请求超过最长 TTL 时改写成最长 TTL
+    fn clamp_ttl(&self, body: &mut Value) -> bool {
+        let (Some(max), Some(obj)) = (self.max_ttl, body.as_object_mut()) else {
+            return false;
+        };
+        let requested = match (obj.get("ttl").and_then(Value::as_str), obj.get("expireTime").and_then(Value::as_str)) {
+            (Some(ttl), _) => parse_ttl(ttl),
+            (None, Some(expire)) => parse_timestamp(expire).map(|at| Duration::from_millis(at.saturating_sub(unix_ms()))),
+            // 上游默认 1 小时
+            (None, None) => Some(Duration::from_secs(3600)),
+        };
+        if requested.is_some_and(|ttl| ttl <= max) {
+            return false;
+        }
+        obj.remove("expireTime");
+        obj.insert("ttl".to_string(), Value::String(format!("{}s", max.as_secs())));
+        true
+    }
+
+    fn attach_for(&self, model: &str) -> Option<&CacheAttach> {
+        self.attach.iter().find(|a| model.starts_with(&a.model))
+    }
+
+    async fn list_response(&self, client: &str) -> Response {
+        match self.list(Some(client)).await {
+            Ok(records) => {
+                let items: Vec<Value> = records
+                    .iter()
+                    .map(|r| {
+                        json!({
+                            "name": r.name,
+                            "model": r.model,
+                            "displayName": r.display_name,
+                            "expireTime": format_timestamp(r.expire_at),
+                        })
+                    })
+                    .collect();
+                Json(json!({ "cachedContents": items })).into_response()
+            }
+            Err(e) => {
+                warn!("🧊 cachedContents list failed: {}", e);
+                StatusCode::SERVICE_UNAVAILABLE.into_response()
+            }
+        }
+    }
+}
+
+/// unix 毫秒格式化为 RFC 3339 (UTC)
+pub fn format_timestamp(ms: u64) -> String {
+    let secs = ms / 1000;
+    let (year, month, day) = crate::export::civil_from_days((secs / 86_400) as i64);
+    let rem = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60,
+        ms % 1000
+    )
+}
+
+fn record_from_response(client: &str, body: &[u8]) -> Option<CacheRecord> {
+    let value: Value = serde_json::from_slice(body).ok()?;
+    Some(CacheRecord {
+        name: value.get("name")?.as_str()?.to_string(),
+        client: client.to_string(),
+        model: value.get("model").and_then(Value::as_str).map(str::to_string),
+        display_name: value.get("displayName").and_then(Value::as_str).map(str::to_string),
+        expire_at: parse_timestamp(value.get("expireTime")?.as_str()?)?,
+        total_tokens: value.pointer("/usageMetadata/totalTokenCount").and_then(Value::as_u64),
+    })
+}
+
+#[async_trait]
+impl GatewayPlugin for CachedContents {
+    fn name(&self) -> &str {
+        "cached-contents"
+    }
+
+    async fn on_request(&self, ctx: &mut RequestContext) -> Result<(), Response> {
+        let client = usage::client_label(ctx);
+        match cache_route(&ctx.method, ctx.uri.path()) {
+            Some(CacheRoute::List) if self.isolate => return Err(self.list_response(&client).await),
+            Some(CacheRoute::List) => {}
+            Some(CacheRoute::Create) => {
+                // 按来源 IP 区分不可靠 (NAT 后的多个客户端会共用)，隔离时不替没有令牌的请求建缓存
+                if self.isolate && ctx.extensions.get::<ClientIdentity>().is_none() {
+                    return Err(rejected(
+                        StatusCode::FORBIDDEN,
+                        "PERMISSION_DENIED",
+                        "creating cachedContents through this gateway requires a client token",
+                    ));
+                }
+                if let Ok(mut body) = serde_json::from_slice::<Value>(&ctx.body) {
+                    if self.clamp_ttl(&mut body) {
+                        debug!("🧊 Clamped cachedContents ttl for {}", client);
+                        ctx.body = Bytes::from(body.to_string());
+                    }
+                }
+                ctx.extensions.insert(Capture(Arc::new(Mutex::new(Vec::new()))));
+            }
+            Some(CacheRoute::Item(name)) => {
+                if self.isolate {
+                    match owner(self.storage.as_ref(), &name).await {
+                        Ok(Some(owner)) if owner == client => {}
+                        Ok(_) => return Err(not_found(&name)),
+                        Err(e) => {
+                            warn!("🧊 cachedContents owner lookup failed: {}", e);
+                            return Err(rejected(StatusCode::SERVICE_UNAVAILABLE, "UNAVAILABLE", "cachedContents ownership is unavailable"));
+                        }
+                    }
+                }
+                if ctx.method == Method::PATCH {
+                    if let Ok(mut body) = serde_json::from_slice::<Value>(&ctx.body) {
+                        if self.clamp_ttl(&mut body) {
+                            ctx.body = Bytes::from(body.to_string());
+                        }
+                    }
+                    ctx.extensions.insert(Capture(Arc::new(Mutex::new(Vec::new()))));
+                } else if ctx.method == Method::DELETE {
+                    ctx.extensions.insert(PendingDelete(name));
+                }
+            }
+            None => {
+                let path = ctx.uri.path();
+                if !(path.ends_with(":generateContent") || path.ends_with(":streamGenerateContent")) {
+                    return Ok(());
+                }
+                let Some(attach) = usage::model_from_path(path).and_then(|m| self.attach_for(m)) else {
+                    return Ok(());
+                };
+                let Ok(mut body) = serde_json::from_slice::<Value>(&ctx.body) else {
+                    return Ok(());
+                };
+                if let Some(obj) = body.as_object_mut() {
+                    if !obj.contains_key("cachedContent") {
+                        obj.insert("cachedContent".to_string(), Value::String(attach.cache.clone()));
+                        ctx.body = Bytes::from(body.to_string());
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn on_chunk(&self, ctx: &RequestContext, chunk: &Bytes) -> ChunkAction {
+        if let Some(Capture(buf)) = ctx.extensions.get::<Capture>() {
+            let mut buf = buf.lock().unwrap();
+            if buf.len() + chunk.len() <= MAX_CAPTURE_BYTES {
+                buf.extend_from_slice(chunk);
+            }
+        }
+        ChunkAction::Continue
+    }
+
+    fn on_complete(&self, ctx: &RequestContext, outcome: &Outcome) {
+        if !outcome.status.is_some_and(|s| s.is_success()) || outcome.error.is_some() {
+            return;
+        }
+        let storage = self.storage.clone();
+        if let Some(Capture(buf)) = ctx.extensions.get::<Capture>() {
+            let client = usage::client_label(ctx);
+            let Some(record) = record_from_response(&client, &buf.lock().unwrap()) else {
+                return;
+            };
+            info!("🧊 {} cached {} until {}", client, record.name, format_timestamp(record.expire_at));
+            tokio::spawn(async move {
+                if let Err(e) = store(storage.as_ref(), record).await {
+                    warn!("🧊 Failed to record cachedContents: {}", e);
+                }
+            });
+        } else if let Some(PendingDelete(name)) = ctx.extensions.get::<PendingDelete>().cloned() {
+            tokio::spawn(async move {
+                if let Err(e) = remove(storage.as_ref(), &name).await {
+                    warn!("🧊 Failed to forget cachedContents {}: {}", name, e);
+                }
+            });
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use reqwest::Client;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// --- 金丝雀路由 ---
+// 把一定比例原本发往默认上游的请求改发给金丝雀上游 (新区域、换了代理的出口、预发布镜像)，
+// 和流量镜像不同，金丝雀的响应就是客户端拿到的响应。比例可以通过管理 API 随时调整，逐步放量。
+// 金丝雀连接失败或返回 5xx 时当前请求退回默认上游重放，客户端不受影响。
+// 路由规则、租户、其他厂商等指定了上游的请求不参与。
+// 默认按比例均匀抽样；sticky 时按客户端身份哈希，同一个客户端固定落在同一边 (上游有会话状态、隐式缓存时)。
+// 两边各自的请求数、成功数 (非 5xx)、失败数 (5xx 或请求出错) 记在 aizasy_canary_attempts_total{side,result}
+// 和管理 API 的 /admin/canary 里，用来对比新旧基础设施。
+
+#[derive(Debug, Clone)]
+pub struct CanaryConfig {
+    /// 金丝雀上游，例如 https://europe-west4-generativelanguage.googleapis.com
+    pub target: String,
+    /// 发往金丝雀的比例 (0..=100)
+    pub percent: f64,
+    /// 访问金丝雀走的代理，不设置时和默认上游用同一个客户端
+    pub proxy: Option<String>,
+    /// 按客户端身份固定分边
+    pub sticky: bool,
+}
+
+impl CanaryConfig {
+    pub fn new(target: impl Into<String>, percent: f64) -> Self {
+        Self {
+            target: target.into(),
+            percent,
+            proxy: None,
+            sticky: false,
+        }
+    }
+}
+
+/// 请求落在哪一边
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Side {
+    Stable,
+    Canary,
+}
+
+impl Side {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Side::Stable => "stable",
+            Side::Canary => "canary",
+        }
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    requests: AtomicU64,
+    successes: AtomicU64,
+    failures: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SideStats {
+    pub requests: u64,
+    pub successes: u64,
+    pub failures: u64,
+}
+
+impl Counters {
+    #[cfg(feature = "admin")]
+    fn snapshot(&self) -> SideStats {
+        SideStats {
+            requests: self.requests.load(Ordering::Relaxed),
+            successes: self.successes.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub(crate) struct Canary {
+    pub(crate) target: String,
+    sticky: bool,
+    // 比例 (0..=1) 的 f64 位模式，管理 API 可以改
+    ratio: AtomicU64,
+    client: Option<Client>,
+    seen: AtomicU64,
+    stable: Counters,
+    canary: Counters,
+}
+
+// FNV-1a，多个实例对同一个客户端分边一致
+fn hash(value: &str) -> u64 {
+    value.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+impl Canary {
+    pub(crate) fn new(config: CanaryConfig, client: Option<Client>) -> Self {
+        let canary = Self {
+            target: config.target.trim_end_matches('/').to_string(),
+            sticky: config.sticky,
+            ratio: AtomicU64::new(0),
+            client,
+            seen: AtomicU64::new(0),
+            stable: Counters::default(),
+            canary: Counters::default(),
+        };
+        canary.set_percent(config.percent);
+        canary
+    }
+
+    #[cfg(feature = "admin")]
+    pub(crate) fn percent(&self) -> f64 {
+        f64::from_bits(self.ratio.load(Ordering::Relaxed)) * 100.0
+    }
+
+    pub(crate) fn set_percent(&self, percent: f64) {
+        self.ratio.store((percent.clamp(0.0, 100.0) / 100.0).to_bits(), Ordering::Relaxed);
+    }
+
+    #[cfg(feature = "admin")]
+    pub(crate) fn sticky(&self) -> bool {
+        self.sticky
+    }
+
+    pub(crate) fn client(&self) -> Option<&Client> {
+        self.client.as_ref()
+    }
+
+    /// 这个请求走哪一边；client 只在 sticky 时用到
+    pub(crate) fn choose(&self, client: impl FnOnce() -> String) -> Side {
+        let ratio = f64::from_bits(self.ratio.load(Ordering::Relaxed));
+        let canary = if self.sticky {
+            // 客户端落在 [0, 1) 上的固定位置，比例调大时原来在金丝雀的客户端不会被换回去
+            (hash(&client()) % 10_000) as f64 / 10_000.0 < ratio
+        } else {
+            // 和镜像一样均匀抽样：累计应发给金丝雀的个数跨过整数时发一个
+            let n = self.seen.fetch_add(1, Ordering::Relaxed) as f64;
+            ((n + 1.0) * ratio).floor() > (n * ratio).floor()
+        };
+        if canary {
+            Side::Canary
+        } else {
+            Side::Stable
+        }
+    }
+
+    /// 记一次上游尝试的结果：ok 为 false 表示 5xx 或请求出错
+    pub(crate) fn record(&self, side: Side, ok: bool) {
+        let counters = match side {
+            Side::Stable => &self.stable,
+            Side::Canary => &self.canary,
+        };
+        counters.requests.fetch_add(1, Ordering::Relaxed);
+        match ok {
+            true => counters.successes.fetch_add(1, Ordering::Relaxed),
+            false => counters.failures.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+
+    #[cfg(feature = "admin")]
+    pub(crate) fn stats(&self) -> (SideStats, SideStats) {
+        (self.stable.snapshot(), self.canary.snapshot())
+    }
+}
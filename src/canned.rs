@@ -0,0 +1,198 @@
+use async_trait::async_trait;
+use axum::{
+    body::{Body, Bytes},
+    http::{header, HeaderName, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Response},
+};
+use futures_util::stream;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tracing::debug;
+
+use crate::mock;
+use crate::plugin::{GatewayPlugin, RequestContext};
+use crate::security_headers::UpstreamResponse;
+use crate::usage;
+
+// --- 固定响应 (canned response) ---
+// 按路径给部分接口配置固定响应，其余请求照常转发：前端开发时不消耗上游额度。
+// 流式接口可以用生成器按设定的 tokens/sec 吐出一段文本，模拟真实的打字机节奏。
+//
+// 配置文件是 JSON 数组:
+// [
+//   {"path": "/v1beta/models/gemini-1.5-pro:generateContent", "body": {"candidates": [...]}},
+//   {"path": "/v1beta/models/", "method": "POST", "stream": {"text": "Hello there", "tokens_per_sec": 20}}
+// ]
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StreamSpec {
+    pub text: String,
+    /// 每秒输出的 token 数 (按空白切分近似)
+    #[serde(default = "default_tokens_per_sec")]
+    pub tokens_per_sec: f64,
+}
+
+fn default_tokens_per_sec() -> f64 {
+    20.0
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CannedResponse {
+    /// 路径前缀，最长匹配优先
+    pub path: String,
+    /// 不填时匹配任意方法
+    #[serde(default, with = "method_opt")]
+    pub method: Option<Method>,
+    #[serde(default = "default_status")]
+    pub status: u16,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// 固定响应体 (JSON)
+    #[serde(default)]
+    pub body: Option<Value>,
+    /// 流式生成器；和 body 同时配置时优先使用
+    #[serde(default)]
+    pub stream: Option<StreamSpec>,
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+mod method_opt {
+    use axum::http::Method;
+    use serde::{Deserialize, Deserializer};
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Method>, D::Error> {
+        let value: Option<String> = Option::deserialize(d)?;
+        value
+            .map(|m| m.to_ascii_uppercase().parse().map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+pub struct CannedResponses {
+    // 按路径长度降序
+    responses: Vec<CannedResponse>,
+}
+
+impl CannedResponses {
+    pub fn new(mut responses: Vec<CannedResponse>) -> Self {
+        responses.sort_by_key(|r| std::cmp::Reverse(r.path.len()));
+        Self { responses }
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let data = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        let responses: Vec<CannedResponse> = serde_json::from_slice(&data).map_err(|e| format!("{}: {}", path, e))?;
+        for response in &responses {
+            StatusCode::from_u16(response.status).map_err(|_| format!("{}: invalid status {}", path, response.status))?;
+        }
+        Ok(Self::new(responses))
+    }
+
+    fn find(&self, method: &Method, path: &str) -> Option<&CannedResponse> {
+        self.responses
+            .iter()
+            .find(|r| path.starts_with(&r.path) && r.method.as_ref().is_none_or(|m| m == method))
+    }
+}
+
+// streaming 为 None 表示非流式接口，生成器配置直接返回完整文本
+fn render(canned: &CannedResponse, model: &str, streaming: Option<bool>) -> Response {
+    let status = StatusCode::from_u16(canned.status).unwrap_or(StatusCode::OK);
+    let mut response = match (&canned.stream, streaming) {
+        (Some(spec), Some(sse)) => generate_stream(spec, model.to_string(), sse),
+        (Some(spec), None) => {
+            let tokens = spec.text.split_whitespace().count();
+            let body = mock::candidate(model, &spec.text, Some(mock::usage(0, tokens))).to_string();
+            ([(header::CONTENT_TYPE, "application/json")], body).into_response()
+        }
+        (None, _) => {
+            let body = canned.body.as_ref().map(|b| b.to_string()).unwrap_or_default();
+            ([(header::CONTENT_TYPE, "application/json")], body).into_response()
+        }
+    };
+    *response.status_mut() = status;
+    for (name, value) in &canned.headers {
+        if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(value)) {
+            response.headers_mut().insert(name, value);
+        }
+    }
+    // 和上游响应一样对待，不套错误模板、不注入安全头
+    response.extensions_mut().insert(UpstreamResponse);
+    response
+}
+
+fn generate_stream(spec: &StreamSpec, model: String, sse: bool) -> Response {
+    // 每个 token 保留后面的空白，拼起来就是原文
+    let tokens: Vec<String> = spec.text.split_inclusive(char::is_whitespace).map(str::to_string).collect();
+    let total = tokens.len();
+    let interval = if spec.tokens_per_sec > 0.0 {
+        Duration::from_secs_f64(1.0 / spec.tokens_per_sec)
+    } else {
+        Duration::ZERO
+    };
+
+    let chunks = stream::unfold(0usize, move |i| {
+        let tokens = tokens.clone();
+        let model = model.clone();
+        async move {
+            if i > total {
+                return None;
+            }
+            if i == total {
+                // 空文本也要输出一块带 usage 的结尾；JSON 数组模式需要闭合
+                return match (total, sse) {
+                    (0, _) => {
+                        let payload = mock::candidate(&model, "", Some(mock::usage(0, 0))).to_string();
+                        let frame = if sse { format!("data: {}\r\n\r\n", payload) } else { format!("[{}]", payload) };
+                        Some((Ok::<_, std::io::Error>(Bytes::from(frame)), i + 1))
+                    }
+                    (_, false) => Some((Ok(Bytes::from_static(b"]")), i + 1)),
+                    (_, true) => None,
+                };
+            }
+            if i > 0 && !interval.is_zero() {
+                tokio::time::sleep(interval).await;
+            }
+            let last = i + 1 == total;
+            let usage = last.then(|| mock::usage(0, total));
+            let payload = mock::candidate(&model, &tokens[i], usage).to_string();
+            let frame = if sse {
+                format!("data: {}\r\n\r\n", payload)
+            } else if i == 0 {
+                format!("[{}", payload)
+            } else {
+                format!("\r\n,{}", payload)
+            };
+            Some((Ok(Bytes::from(frame)), i + 1))
+        }
+    });
+
+    let content_type = if sse { "text/event-stream" } else { "application/json" };
+    ([(header::CONTENT_TYPE, content_type)], Body::from_stream(chunks)).into_response()
+}
+
+#[async_trait]
+impl GatewayPlugin for CannedResponses {
+    fn name(&self) -> &str {
+        "canned"
+    }
+
+    async fn on_request(&self, ctx: &mut RequestContext) -> Result<(), Response> {
+        let Some(canned) = self.find(&ctx.method, ctx.uri.path()) else {
+            return Ok(());
+        };
+        debug!("🥫 Canned response for {} {}", ctx.method, ctx.uri.path());
+        let model = usage::model_from_path(ctx.uri.path()).unwrap_or("canned");
+        let streaming = ctx
+            .uri
+            .path()
+            .ends_with(":streamGenerateContent")
+            .then(|| ctx.uri.query().is_some_and(|q| q.split('&').any(|p| p == "alt=sse")));
+        Err(render(canned, model, streaming))
+    }
+}
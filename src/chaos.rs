@@ -0,0 +1,219 @@
+use async_trait::async_trait;
+use axum::{
+    body::Bytes,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use rand::Rng;
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::debug;
+
+use crate::plugin::{ChunkAction, GatewayPlugin, RequestContext};
+
+// --- 故障注入 ---
+// 按路径子串匹配规则，第一条命中的规则生效。用于测试客户端的重试 / 超时逻辑。
+// latency 在转发上游之前等待，ttfb 在上游响应之后、响应头发给客户端之前等待，
+// 两者都可以是固定值、区间或分布，用来模拟慢模型下的转圈和超时:
+//   models/gemini-1.5-pro;latency=normal:800:200;ttfb=exp:1500
+
+/// 注入延迟的取值方式 (毫秒)
+#[derive(Clone, Debug, PartialEq)]
+pub enum Delay {
+    /// `300` 或 `100-500`，区间内均匀分布
+    Uniform(u64, u64),
+    /// `normal:MEAN:STDDEV`，小于 0 的取样按 0 处理
+    Normal { mean: f64, stddev: f64 },
+    /// `exp:MEAN`，指数分布，偶尔出现很长的尾部延迟
+    Exponential { mean: f64 },
+}
+
+impl Delay {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let num = |v: &str| v.trim().parse::<f64>().map_err(|e| format!("invalid delay '{}': {}", spec, e));
+        let delay = match spec.split(':').collect::<Vec<_>>()[..] {
+            ["normal", mean, stddev] => Delay::Normal {
+                mean: num(mean)?,
+                stddev: num(stddev)?,
+            },
+            ["exp", mean] => Delay::Exponential { mean: num(mean)? },
+            [range] => {
+                let (min, max) = range.split_once('-').unwrap_or((range, range));
+                let min: u64 = min.trim().parse().map_err(|e| format!("invalid delay '{}': {}", spec, e))?;
+                let max: u64 = max.trim().parse().map_err(|e| format!("invalid delay '{}': {}", spec, e))?;
+                Delay::Uniform(min.min(max), min.max(max))
+            }
+            _ => return Err(format!("invalid delay '{}', expected MS, MIN-MAX, normal:MEAN:STDDEV or exp:MEAN", spec)),
+        };
+        match delay {
+            Delay::Normal { mean, stddev } if mean < 0.0 || stddev < 0.0 => Err(format!("invalid delay '{}'", spec)),
+            Delay::Exponential { mean } if mean <= 0.0 => Err(format!("invalid delay '{}'", spec)),
+            delay => Ok(delay),
+        }
+    }
+
+    fn sample(&self, rng: &mut impl Rng) -> Duration {
+        let ms = match *self {
+            Delay::Uniform(min, max) => rng.random_range(min..=max) as f64,
+            Delay::Normal { mean, stddev } => {
+                // Box-Muller
+                let u1: f64 = 1.0 - rng.random::<f64>();
+                let u2: f64 = rng.random();
+                mean + stddev * (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+            }
+            Delay::Exponential { mean } => -mean * (1.0 - rng.random::<f64>()).ln(),
+        };
+        Duration::from_millis(ms.max(0.0).round() as u64)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ChaosRule {
+    /// 路径子串，"*" 匹配全部
+    pub path: String,
+    /// 转发上游前的额外延迟
+    pub latency: Option<Delay>,
+    /// 上游响应后、首字节发给客户端前的额外延迟
+    pub ttfb: Option<Delay>,
+    /// 直接返回合成错误的比例
+    pub error_rate: f64,
+    pub error_status: StatusCode,
+    /// 响应流中途断开的比例
+    pub drop_rate: f64,
+    /// 断流前放行的分块数
+    pub drop_after: usize,
+}
+
+impl ChaosRule {
+    /// 解析 `PATH;latency=100-500;ttfb=exp:800;error_rate=0.1;status=503;drop_rate=0.2;drop_after=3`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut parts = spec.split(';');
+        let path = parts.next().unwrap_or("*").trim().to_string();
+        let mut rule = ChaosRule {
+            path,
+            latency: None,
+            ttfb: None,
+            error_rate: 0.0,
+            error_status: StatusCode::INTERNAL_SERVER_ERROR,
+            drop_rate: 0.0,
+            drop_after: 1,
+        };
+
+        for part in parts.map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("chaos option '{}' must be key=value", part))?;
+            let bad = |e: &dyn std::fmt::Display| format!("chaos option '{}': {}", part, e);
+            match key {
+                "latency" => rule.latency = Some(Delay::parse(value)?),
+                "ttfb" => rule.ttfb = Some(Delay::parse(value)?),
+                "error_rate" => rule.error_rate = value.parse().map_err(|e| bad(&e))?,
+                "status" => {
+                    let code: u16 = value.parse().map_err(|e| bad(&e))?;
+                    rule.error_status = StatusCode::from_u16(code).map_err(|e| bad(&e))?;
+                }
+                "drop_rate" => rule.drop_rate = value.parse().map_err(|e| bad(&e))?,
+                "drop_after" => rule.drop_after = value.parse().map_err(|e| bad(&e))?,
+                _ => return Err(format!("unknown chaos option '{}'", key)),
+            }
+        }
+        Ok(rule)
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        self.path == "*" || path.contains(self.path.as_str())
+    }
+}
+
+// 命中 ttfb 规则的请求在 extensions 上带着本次抽到的延迟
+#[derive(Clone, Copy)]
+struct PendingTtfb(Duration);
+
+// 被选中断流的请求在 extensions 上带着计数器
+#[derive(Clone)]
+struct PendingDrop {
+    after: usize,
+    seen: Arc<AtomicUsize>,
+}
+
+pub struct ChaosPlugin {
+    rules: Vec<ChaosRule>,
+}
+
+impl ChaosPlugin {
+    pub fn new(rules: Vec<ChaosRule>) -> Self {
+        Self { rules }
+    }
+}
+
+#[async_trait]
+impl GatewayPlugin for ChaosPlugin {
+    fn name(&self) -> &str {
+        "chaos"
+    }
+
+    async fn on_request(&self, ctx: &mut RequestContext) -> Result<(), Response> {
+        let Some(rule) = self.rules.iter().find(|r| r.matches(ctx.uri.path())) else {
+            return Ok(());
+        };
+
+        // ThreadRng 不能跨 await，先把随机结果都算出来
+        let (delay, ttfb, inject_error, drop) = {
+            let mut rng = rand::rng();
+            let delay = rule.latency.as_ref().map(|d| d.sample(&mut rng));
+            let ttfb = rule.ttfb.as_ref().map(|d| d.sample(&mut rng));
+            (
+                delay,
+                ttfb,
+                rng.random_bool(rule.error_rate.clamp(0.0, 1.0)),
+                rng.random_bool(rule.drop_rate.clamp(0.0, 1.0)),
+            )
+        };
+
+        if let Some(delay) = delay {
+            debug!("🐒 Chaos: +{}ms on {}", delay.as_millis(), ctx.uri.path());
+            tokio::time::sleep(delay).await;
+        }
+        if let Some(ttfb) = ttfb {
+            ctx.extensions.insert(PendingTtfb(ttfb));
+        }
+        if inject_error {
+            debug!("🐒 Chaos: synthetic {} on {}", rule.error_status, ctx.uri.path());
+            let body = json!({
+                "error": {
+                    "code": rule.error_status.as_u16(),
+                    "message": "Injected by gateway chaos mode",
+                    "status": "CHAOS",
+                }
+            });
+            return Err((rule.error_status, Json(body)).into_response());
+        }
+        if drop {
+            ctx.extensions.insert(PendingDrop {
+                after: rule.drop_after,
+                seen: Arc::new(AtomicUsize::new(0)),
+            });
+        }
+        Ok(())
+    }
+
+    async fn on_upstream_response(&self, ctx: &RequestContext, _status: StatusCode, _headers: &mut HeaderMap) {
+        if let Some(PendingTtfb(delay)) = ctx.extensions.get::<PendingTtfb>().copied() {
+            debug!("🐒 Chaos: TTFB +{}ms on {}", delay.as_millis(), ctx.uri.path());
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    fn on_chunk(&self, ctx: &RequestContext, _chunk: &Bytes) -> ChunkAction {
+        match ctx.extensions.get::<PendingDrop>() {
+            Some(pending) if pending.seen.fetch_add(1, Ordering::Relaxed) >= pending.after => {
+                debug!("🐒 Chaos: dropping stream on {}", ctx.uri.path());
+                ChunkAction::Abort("chaos stream drop".into())
+            }
+            _ => ChunkAction::Continue,
+        }
+    }
+}
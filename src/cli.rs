@@ -0,0 +1,2477 @@
+use crate::access_log::AccessLog;
+use crate::audit::{AuditConfig, AuditLog, AuditMode};
+use crate::bandwidth::{parse_size, Bandwidth, BandwidthLimit};
+use crate::batch::BatchConfig;
+use crate::budget::{Budget, BudgetMonitor, BudgetUnit};
+use crate::burst::{BurstConfig, BurstGuard};
+use crate::cached_contents::{CacheAttach, CachedContents};
+use crate::cidr::Cidr;
+use crate::client_auth::{ClientToken, ClientTokens};
+use crate::cors::{Cors, CorsConfig};
+use crate::credits::CreditAccounts;
+use crate::dns::{DnsConfig, HostOverride, IpStrategy};
+#[cfg(feature = "dns")]
+use crate::dns::DnsServer;
+use crate::drain::Drain;
+use crate::egress::{self, EgressPoolConfig, EgressRotation, UpstreamHttpConfig, UpstreamProtocol};
+use crate::encoding::EncodingMode;
+#[cfg(feature = "devtools")]
+use crate::canned::CannedResponses;
+#[cfg(feature = "compression")]
+use crate::compression::{Algorithm, CompressionConfig};
+#[cfg(feature = "devtools")]
+use crate::chaos::{ChaosPlugin, ChaosRule};
+use crate::error_templates::ErrorTemplates;
+#[cfg(feature = "geoip")]
+use crate::geoip::GeoIp;
+use crate::failures::FailureLog;
+use crate::grpc::{GrpcConfig, DEFAULT_GRPC_TARGET};
+use crate::uploads::UploadConfig;
+use crate::export::{ExportFormat, ExportSink, UsageExporter};
+use crate::header_rules::HeaderRule;
+use crate::inspector::RequestInspector;
+use crate::ip_filter::IpFilter;
+use crate::key_pool::{self, FailoverConfig, KeyInjection, KeyPool, KeySelection, KeySource};
+use crate::ledger::{Ledger, PendingWrites, PriceTable};
+use crate::load_shed::InflightConfig;
+use crate::maintenance::MaintenanceState;
+use crate::metering::{MeteringConfig, MeteringPush};
+#[cfg(feature = "otel")]
+use crate::otel;
+use crate::model_fallback::ModelFallbacks;
+use crate::model_map::ModelMap;
+use crate::generation_policy::GenerationPolicy;
+use crate::redact::BodyRedactor;
+use crate::validate::RequestValidator;
+use crate::model_router::{CostRouter, ModelAlias};
+use crate::prewarm::PrewarmConfig;
+use crate::project::Projects;
+use crate::providers::Provider;
+use crate::request_queue::QueueConfig;
+use crate::response_filter::ResponseFilter;
+use crate::stream_transform::StreamTransform;
+use crate::system_prompt::{SystemPrompt, SystemPromptMode};
+use crate::quota::{Quota, QuotaLimiter};
+use crate::rate_limit::{RateLimit, RateLimiter};
+use crate::response_cache::{ResponseCache, ResponseCacheConfig};
+use crate::mirror::MirrorConfig;
+use crate::canary::CanaryConfig;
+use crate::retry::RetryConfig;
+use crate::rewrite::RewriteRule;
+use crate::routes::RouteRule;
+use crate::schedule::{Schedule, ScheduleGuard};
+use crate::token_count::LocalTokenCounter;
+use crate::lockout::AuthLockout;
+#[cfg(feature = "devtools")]
+use crate::mock::{self, MockConfig};
+#[cfg(feature = "devtools")]
+use crate::record::{self, Recorder};
+use crate::path_allowlist::PathAllowlist;
+use crate::scanner::ScannerGuard;
+#[cfg(feature = "secrets")]
+use crate::secrets::SecretDecryptor;
+#[cfg(feature = "vertex")]
+use crate::vertex::{self, VertexAuth, VertexConfig};
+use crate::security_headers::SecurityHeaders;
+use crate::signed_url::UrlSigner;
+use crate::storage;
+use crate::target_policy::TargetPolicy;
+use crate::tenant::{Tenant, Tenants};
+use crate::upstreams::{Balancing, HealthCheckConfig, WeightedTarget};
+use crate::{Gateway, DEFAULT_TARGET};
+#[cfg(feature = "admin")]
+use crate::LogLevelControl;
+#[cfg(feature = "devtools")]
+use axum::http::StatusCode;
+use crate::storage::Storage;
+#[cfg(feature = "config")]
+use crate::config_file;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tracing::{info, warn};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
+
+// --- 命令行参数到网关的组装 ---
+// serve / check 共用的参数定义，以及把它们校验、组装成 Gateway 的全部逻辑；
+// 二进制只负责解析命令行、处理信号和退出码，配置错误都以 Err 返回，热重载失败不会拖垮正在服务的网关。
+
+// --- 配置参数 ---
+#[derive(clap::Args, Debug, Clone)]
+pub struct Args {
+    /// 配置文件 (TOML / YAML)，键名同命令行参数长名；命令行和环境变量优先。
+    /// 收到 SIGHUP 时重新加载，进行中的请求不受影响
+    #[cfg(feature = "config")]
+    #[arg(long, env = "AIZASY_CONFIG", value_name = "FILE")]
+    pub config: Option<String>,
+
+    /// 只检查配置后退出，同 `check` 子命令 (适合只能改启动参数的部署流水线)
+    #[arg(long, env = "AIZASY_CHECK_CONFIG", default_value = "false")]
+    pub check_config: bool,
+
+    /// 监听地址，可重复或逗号分隔，如 0.0.0.0:3000,[::]:3000；单独的 [::] 同时接受 IPv4；
+    /// 配置了 --tls-cert 时所有地址都走 HTTPS，写成 http://127.0.0.1:3000 的地址仍然是明文；
+    /// unix:/run/aizasy.sock 监听 Unix domain socket (明文 HTTP，对端 IP 记为 127.0.0.1)
+    #[arg(short, long, env = "AIZASY_LISTEN", default_value = "0.0.0.0:3000", value_delimiter = ',')]
+    pub listen: Vec<String>,
+
+    /// Unix socket 文件权限 (八进制)
+    #[arg(long, env = "AIZASY_UNIX_SOCKET_MODE", default_value = "660", value_parser = parse_mode)]
+    pub unix_socket_mode: u32,
+
+    /// HTTPS 证书 (PEM，可含中间证书链)，需要同时给 --tls-key；文件更新后自动重新加载
+    #[cfg(feature = "tls")]
+    #[arg(long, env = "AIZASY_TLS_CERT", value_name = "FILE", requires = "tls_key")]
+    pub tls_cert: Option<String>,
+
+    /// HTTPS 私钥 (PEM)
+    #[cfg(feature = "tls")]
+    #[arg(long, env = "AIZASY_TLS_KEY", value_name = "FILE", requires = "tls_cert")]
+    pub tls_key: Option<String>,
+
+    /// 在 HTTPS 端口上同时监听 HTTP/3 (UDP)，响应里用 Alt-Svc 告诉客户端；需要 --tls-cert
+    #[cfg(feature = "http3")]
+    #[arg(long, env = "AIZASY_HTTP3", default_value = "false", requires = "tls_cert")]
+    pub http3: bool,
+
+    /// 出站代理: http(s)://、socks5:// (本地解析域名) 或 socks5h:// (代理解析域名)，账号密码可写成 user:pass@host；
+    /// 可重复或逗号分隔，多个时组成代理池轮换使用，连接失败的暂停使用并定期重新探测
+    #[arg(short, long, env = "AIZASY_PROXY", value_delimiter = ',')]
+    pub proxy: Vec<String>,
+
+    /// 代理账号密码 USER:PASS，覆盖 --proxy 里写的 (不需要百分号编码)
+    #[arg(long, env = "AIZASY_PROXY_AUTH", value_name = "USER:PASS", requires = "proxy")]
+    pub proxy_auth: Option<String>,
+
+    /// 代理池的轮换方式: request (每个请求轮询) 或 key (同一个 key 固定走同一个代理)
+    #[arg(long, env = "AIZASY_PROXY_ROTATION", default_value = "request")]
+    pub proxy_rotation: EgressRotation,
+
+    /// 代理池里的代理连接失败后暂停使用的秒数
+    #[arg(long, env = "AIZASY_PROXY_COOLDOWN_SECS", default_value = "30")]
+    pub proxy_cooldown_secs: u64,
+
+    /// 重新探测不可用代理的间隔秒数
+    #[arg(long, env = "AIZASY_PROXY_PROBE_INTERVAL_SECS", default_value = "15")]
+    pub proxy_probe_interval_secs: u64,
+
+    /// 上游地址，可重复或逗号分隔: URL[;weight=N][;proxy=PROXY_URL]；多个时按权重轮询，连接失败或 5xx 时换下一个；
+    /// proxy 让这个上游单独走指定的代理
+    #[arg(short, long, env = "AIZASY_TARGET", default_value = DEFAULT_TARGET, value_delimiter = ',')]
+    pub target: Vec<String>,
+
+    /// 按路径前缀发往不同的上游，可重复指定: PREFIX=TARGET[;strip][;host=HOST][;passthrough][;id=ID]，
+    /// 如 /upload=https://upload.example.com、/openai/*=https://api.openai.com;strip;passthrough；按最长前缀匹配，
+    /// 没有命中的请求发往 --target。strip 转发前去掉前缀，host 指定发给上游的 Host 头，passthrough 不注入 key 池的 key
+    #[arg(long = "route", env = "AIZASY_ROUTES", value_name = "PREFIX=TARGET")]
+    pub routes: Vec<String>,
+
+    /// Vertex AI 服务账号 JSON 文件；设置后 Gemini API 路径转换成 Vertex AI 格式，用 OAuth2 访问令牌鉴权
+    /// (没有指定 --target 时上游默认为区域对应的 Vertex AI 端点，不能和 --keys 同时使用)
+    #[cfg(feature = "vertex")]
+    #[arg(long, env = "AIZASY_VERTEX_CREDENTIALS", value_name = "FILE")]
+    pub vertex_credentials: Option<String>,
+
+    /// Vertex AI 项目 ID，默认取服务账号文件里的 project_id
+    #[cfg(feature = "vertex")]
+    #[arg(long, env = "AIZASY_VERTEX_PROJECT")]
+    pub vertex_project: Option<String>,
+
+    /// Vertex AI 区域，如 us-central1；global 使用全球端点
+    #[cfg(feature = "vertex")]
+    #[arg(long, env = "AIZASY_VERTEX_LOCATION", default_value = "us-central1")]
+    pub vertex_location: String,
+
+    /// 上游连接失败或返回 5xx 后暂停使用的秒数 (多个上游时生效)
+    #[arg(long, env = "AIZASY_TARGET_COOLDOWN_SECS", default_value = "30")]
+    pub target_cooldown_secs: u64,
+
+    /// 多个上游时的选择策略：weighted (按权重轮询) / least-latency (首字节延迟 EWMA 最低的) /
+    /// p2c (随机两个里延迟低的)
+    #[arg(long, env = "AIZASY_TARGET_BALANCING", default_value = "weighted", value_name = "STRATEGY")]
+    pub target_balancing: Balancing,
+
+    /// 主动健康检查间隔秒数；不设置则只靠真实请求的失败被动判断
+    #[arg(long, env = "AIZASY_HEALTH_CHECK_INTERVAL_SECS")]
+    pub health_check_interval_secs: Option<u64>,
+
+    /// 健康检查请求的路径 (GET，配置了 key 池时带上池里的 key)
+    #[arg(long, env = "AIZASY_HEALTH_CHECK_PATH", default_value = "/v1beta/models")]
+    pub health_check_path: String,
+
+    /// 单次健康检查的超时秒数
+    #[arg(long, env = "AIZASY_HEALTH_CHECK_TIMEOUT_SECS", default_value = "5")]
+    pub health_check_timeout_secs: u64,
+
+    /// 连续失败几次后把上游移出轮询
+    #[arg(long, env = "AIZASY_HEALTH_CHECK_THRESHOLD", default_value = "2")]
+    pub health_check_threshold: u32,
+
+    /// 上游 key 池，逗号分隔的 KEY 或 NAME=KEY；配置后客户端无需持有真实 key
+    #[arg(long = "keys", env = "AIZASY_KEYS", value_delimiter = ',', hide_env_values = true)]
+    pub keys: Vec<String>,
+
+    /// 从文件加载 key 池 (每行 KEY 或 NAME=KEY)，可以和 --keys 同时使用
+    #[arg(long, env = "AIZASY_KEYS_FILE")]
+    pub keys_file: Option<String>,
+
+    /// 从这个地址 (HTTPS / secret manager 的读取接口) 加载 key 池，内容格式和 --keys-file 一样
+    #[arg(long, env = "AIZASY_KEYS_URL")]
+    pub keys_url: Option<String>,
+
+    /// 访问 --keys-url 时带的 Authorization 请求头，例如 "Bearer TOKEN"
+    #[arg(long, env = "AIZASY_KEYS_URL_AUTH", hide_env_values = true)]
+    pub keys_url_auth: Option<String>,
+
+    /// 每隔多少秒重新读取 --keys-file / --keys-url，有变化时原地更新 key 池；0 表示不自动重新读取
+    #[arg(long, env = "AIZASY_KEYS_RELOAD_SECS", default_value = "30")]
+    pub keys_reload_secs: u64,
+
+    /// key 注入方式: header (x-goog-api-key) / query (?key=)
+    #[arg(long, env = "AIZASY_KEY_INJECTION", default_value = "header")]
+    pub key_injection: KeyInjection,
+
+    /// key 选择方式: round-robin (轮询) / sticky (按客户端身份固定 key，冷却时换下一个)
+    #[arg(long, env = "AIZASY_KEY_SELECTION", default_value = "round-robin")]
+    pub key_selection: KeySelection,
+
+    /// sticky 模式下优先按这个请求头 (如 x-session-id) 的值固定 key，没有这个头时按客户端身份
+    #[arg(long, env = "AIZASY_KEY_AFFINITY_HEADER", value_name = "HEADER")]
+    pub key_affinity_header: Option<String>,
+
+    /// 上游返回 429 / 403 时单个请求最多换几个 key (含第一次)
+    #[arg(long, env = "AIZASY_KEY_MAX_ATTEMPTS", default_value = "3")]
+    pub key_max_attempts: usize,
+
+    /// 429 且上游没有 Retry-After 时 key 的冷却秒数
+    #[arg(long, env = "AIZASY_KEY_COOLDOWN_SECS", default_value = "60")]
+    pub key_cooldown_secs: u64,
+
+    /// 403 时 key 的冷却秒数
+    #[arg(long, env = "AIZASY_KEY_FORBIDDEN_COOLDOWN_SECS", default_value = "600")]
+    pub key_forbidden_cooldown_secs: u64,
+
+    /// 每个 key 同时在处理的请求上限 (流式响应发完才归还)，满了的 key 跳过；0 表示不限制
+    #[arg(long, env = "AIZASY_KEY_MAX_CONCURRENCY", value_name = "N", default_value = "0")]
+    pub key_max_concurrency: usize,
+
+    /// key 分组 NAME=KEY1,KEY2 (key 名)，可重复；--route 的 keys=NAME 只用组里的 key
+    #[arg(long = "key-group", env = "AIZASY_KEY_GROUPS", value_name = "NAME=KEYS")]
+    pub key_groups: Vec<String>,
+
+    /// 上游连续多少次判定 key 失效 (API_KEY_INVALID、API_KEY_EXPIRED 等) 后隔离这个 key，
+    /// 隔离的 key 不再使用，直到通过管理 API 解除；0 表示不隔离 (默认)
+    #[arg(long, env = "AIZASY_KEY_QUARANTINE_AFTER", value_name = "N", default_value = "0")]
+    pub key_quarantine_after: u32,
+
+    /// key 被隔离时通知的 webhook (POST JSON)
+    #[arg(long, env = "AIZASY_KEY_QUARANTINE_WEBHOOK", value_name = "URL")]
+    pub key_quarantine_webhook: Option<String>,
+
+    /// key 都在冷却 (上游 429) 或并发名额都已占满时最多排队等待的请求数，有 key 恢复后按优先级放行；0 表示不排队直接返回 429
+    #[arg(long, env = "AIZASY_RATE_LIMIT_QUEUE", value_name = "N", default_value = "0")]
+    pub rate_limit_queue: usize,
+
+    /// 排队请求最多等待的秒数，超时返回 429
+    #[arg(long, env = "AIZASY_RATE_LIMIT_QUEUE_SECS", value_name = "SECS", default_value = "30")]
+    pub rate_limit_queue_secs: u64,
+
+    /// 允许客户端用这个请求头声明排队优先级 (整数，越大越先放行)，如 x-aizasy-priority；默认不接受客户端声明。
+    /// 这个请求头不会转发给上游
+    #[arg(long, env = "AIZASY_QUEUE_PRIORITY_HEADER", value_name = "HEADER")]
+    pub queue_priority_header: Option<String>,
+
+    /// 客户端在请求头里声明的优先级最高到这个值；默认 0，客户端只能把自己往后排
+    #[arg(long, env = "AIZASY_QUEUE_PRIORITY_MAX", value_name = "N", default_value = "0", allow_hyphen_values = true)]
+    pub queue_priority_max: i64,
+
+    /// 客户端的默认排队优先级，可重复指定: CLIENT=N (CLIENT 为客户端标识或 *)
+    #[arg(long = "queue-priority", env = "AIZASY_QUEUE_PRIORITIES", value_delimiter = ',', value_name = "CLIENT=N")]
+    pub queue_priorities: Vec<String>,
+
+    /// 网关签发的客户端令牌，可重复指定: NAME=TOKEN[;expires=2026-12-31]；
+    /// 配置后代理路由必须带令牌 (Authorization: Bearer / x-goog-api-key / ?key=)，否则返回 401
+    #[arg(long = "client-token", env = "AIZASY_CLIENT_TOKENS", value_delimiter = ',', value_name = "SPEC", hide_env_values = true)]
+    pub client_tokens: Vec<String>,
+
+    /// 从文件加载客户端令牌 (每行一个 NAME=TOKEN[;expires=...])，可以和 --client-token 同时使用
+    #[arg(long, env = "AIZASY_CLIENT_TOKENS_FILE")]
+    pub client_tokens_file: Option<String>,
+
+    #[arg(long, env = "AIZASY_INSECURE", default_value = "false")]
+    pub insecure: bool,
+
+    /// 额外信任的 CA 证书 (PEM，可以是含多张证书的 bundle)，可重复；用于公司的 TLS 中间人代理，代替 --insecure
+    #[arg(long, env = "AIZASY_CA_CERT", value_name = "FILE", value_delimiter = ',')]
+    pub ca_cert: Vec<String>,
+
+    /// 连接上游时出示的客户端证书 (PEM，可含中间证书链)，用于要求 mTLS 的上游或公司网关；需要同时给 --upstream-client-key
+    #[arg(long, env = "AIZASY_UPSTREAM_CLIENT_CERT", value_name = "FILE", requires = "upstream_client_key")]
+    pub upstream_client_cert: Option<String>,
+
+    /// 上游客户端证书的私钥 (PEM)
+    #[arg(long, env = "AIZASY_UPSTREAM_CLIENT_KEY", value_name = "FILE", requires = "upstream_client_cert")]
+    pub upstream_client_key: Option<String>,
+
+    #[arg(long, env = "AIZASY_LOG", default_value = "info")]
+    pub log_level: String,
+
+    /// OTLP/HTTP collector 地址 (如 http://localhost:4318)，每个代理请求导出一个 span，上游调用是子 span
+    #[cfg(feature = "otel")]
+    #[arg(long, env = "AIZASY_OTLP_ENDPOINT", value_name = "URL")]
+    pub otlp_endpoint: Option<String>,
+
+    /// 导出 span 的 service.name
+    #[cfg(feature = "otel")]
+    #[arg(long, env = "AIZASY_OTLP_SERVICE_NAME", default_value = "aizasy-gateway")]
+    pub otlp_service_name: String,
+
+    /// 结构化 JSON 访问日志，每个请求一行: stdout、stderr 或文件路径 (追加写入)；和 --log-level 无关
+    #[arg(long, env = "AIZASY_ACCESS_LOG", value_name = "stdout|stderr|FILE")]
+    pub access_log: Option<String>,
+
+    /// 合规审计日志：每个模型调用 (GET / HEAD 以外) 的客户端身份、时间、请求体和响应体追加写到这个文件，
+    /// 或者逐条 POST 到 http(s) 地址
+    #[arg(long, env = "AIZASY_AUDIT_LOG", value_name = "FILE|URL")]
+    pub audit_log: Option<String>,
+
+    /// 审计日志的内容：full (原文和哈希) / hash (只写加盐哈希，需要 --audit-salt)
+    #[arg(long, env = "AIZASY_AUDIT_MODE", default_value = "full", value_name = "MODE", requires = "audit_log")]
+    pub audit_mode: AuditMode,
+
+    /// 审计日志里哈希的盐 (HMAC-SHA256 密钥)；不设置时 full 模式用普通 SHA-256
+    #[arg(long, env = "AIZASY_AUDIT_SALT", requires = "audit_log")]
+    pub audit_salt: Option<String>,
+
+    /// 审计日志文件超过这个大小时轮转，0 表示不轮转；可以带单位 (KB/MB/GB)
+    #[arg(long, env = "AIZASY_AUDIT_MAX_SIZE", default_value = "100MB", value_parser = parse_size)]
+    pub audit_max_size: u64,
+
+    /// 轮转后保留的旧审计日志文件个数
+    #[arg(long, env = "AIZASY_AUDIT_MAX_FILES", default_value = "10")]
+    pub audit_max_files: usize,
+
+    /// 代理请求体上限，超过时返回 413；可以带单位 (KB/MB/GB，1024 进制)
+    #[arg(long, env = "AIZASY_MAX_REQUEST_SIZE", default_value = "64MB", value_parser = parse_size)]
+    pub max_request_size: u64,
+
+    /// 上游响应体上限，Content-Length 超过时返回 502，流式响应超过时中断；默认不限制
+    #[arg(long, env = "AIZASY_MAX_RESPONSE_SIZE", value_parser = parse_size)]
+    pub max_response_size: Option<u64>,
+
+    /// 上游连接失败或返回 500 / 502 / 503 / 504 时的重试次数，0 表示不重试；
+    /// 客户端可以用 x-aizasy-no-retry 请求头关闭
+    #[arg(long, env = "AIZASY_RETRIES", default_value = "0")]
+    pub retries: u32,
+
+    /// 第一次重试前等待的毫秒数，之后每次翻倍
+    #[arg(long, env = "AIZASY_RETRY_BACKOFF_MS", default_value = "200")]
+    pub retry_backoff_ms: u64,
+
+    /// 重试等待时间上限 (毫秒)
+    #[arg(long, env = "AIZASY_RETRY_MAX_BACKOFF_MS", default_value = "5000")]
+    pub retry_max_backoff_ms: u64,
+
+    /// 重试等待的随机抖动比例 (0 到 1)
+    #[arg(long, env = "AIZASY_RETRY_JITTER", default_value = "0.5")]
+    pub retry_jitter: f64,
+
+    /// 请求体超过这个大小时不重试
+    #[arg(long, env = "AIZASY_RETRY_MAX_BODY", default_value = "1MB", value_parser = parse_size)]
+    pub retry_max_body: u64,
+
+    /// 流量镜像：把一部分请求复制一份发给这个影子上游 (后台发送，响应丢弃，不影响客户端)
+    #[arg(long, env = "AIZASY_MIRROR", value_name = "URL")]
+    pub mirror: Option<String>,
+
+    /// 镜像的请求比例 (0 到 100)
+    #[arg(long, env = "AIZASY_MIRROR_PERCENT", default_value = "100", value_parser = parse_percent, requires = "mirror")]
+    pub mirror_percent: f64,
+
+    /// 访问影子上游走的代理，不设置时和主上游相同
+    #[arg(long, env = "AIZASY_MIRROR_PROXY", value_name = "URL", requires = "mirror")]
+    pub mirror_proxy: Option<String>,
+
+    /// 单个镜像请求的超时秒数 (含读完响应体)
+    #[arg(long, env = "AIZASY_MIRROR_TIMEOUT_SECS", value_name = "SECS", default_value = "60")]
+    pub mirror_timeout_secs: u64,
+
+    /// 同时在途的镜像请求上限，影子上游变慢时超出的直接丢弃
+    #[arg(long, env = "AIZASY_MIRROR_MAX_INFLIGHT", default_value = "64")]
+    pub mirror_max_inflight: usize,
+
+    /// 发给影子上游的 API key；生产上游的 key 和鉴权头不会被镜像，不设置时镜像请求不带凭据
+    #[arg(long, env = "AIZASY_MIRROR_KEY", value_name = "KEY", requires = "mirror")]
+    pub mirror_key: Option<String>,
+
+    /// 金丝雀路由：把一部分发往默认上游的请求改发给这个上游，连接失败或 5xx 时退回默认上游
+    #[arg(long, env = "AIZASY_CANARY", value_name = "URL")]
+    pub canary: Option<String>,
+
+    /// 发往金丝雀的请求比例 (0 到 100)，可以通过管理 API 调整
+    #[arg(long, env = "AIZASY_CANARY_PERCENT", default_value = "5", value_parser = parse_percent, requires = "canary")]
+    pub canary_percent: f64,
+
+    /// 访问金丝雀上游走的代理，不设置时和默认上游相同
+    #[arg(long, env = "AIZASY_CANARY_PROXY", value_name = "URL", requires = "canary")]
+    pub canary_proxy: Option<String>,
+
+    /// 按客户端身份固定分边 (同一个客户端总是落在金丝雀或默认上游的同一边)，默认按比例均匀抽样
+    #[arg(long, env = "AIZASY_CANARY_STICKY", default_value = "false", requires = "canary")]
+    pub canary_sticky: bool,
+
+    /// 上游响应流连续这么多秒没有数据时中断 (SSE 卡住不动时释放连接)；默认不限制
+    #[arg(long, env = "AIZASY_STREAM_IDLE_TIMEOUT_SECS", value_name = "SECS")]
+    pub stream_idle_timeout_secs: Option<u64>,
+
+    /// SSE 响应连续这么多秒没有数据时给客户端发 `: keepalive` 注释行，防止负载均衡 / 代理掐断长时间静默的流；默认不发
+    #[arg(long, env = "AIZASY_SSE_KEEPALIVE_SECS", value_name = "SECS")]
+    pub sse_keepalive_secs: Option<u64>,
+
+    /// 客户端 Accept-Encoding 的处理方式：identity (默认，转发时去掉，上游返回未压缩的响应，插件都能处理) /
+    /// passthrough (原样转发，上游压缩过的响应逐字节透传，用量统计和响应改写对这类响应不生效)
+    #[arg(long, env = "AIZASY_ACCEPT_ENCODING", default_value = "identity", value_name = "MODE")]
+    pub accept_encoding: EncodingMode,
+
+    /// 转发 gRPC (`application/grpc`) 请求：走 HTTP/2 到 --grpc-target，响应逐帧透传并带回 trailers
+    #[arg(long, env = "AIZASY_GRPC", default_value = "false")]
+    pub grpc: bool,
+
+    /// gRPC 上游地址
+    #[arg(long, env = "AIZASY_GRPC_TARGET", default_value = DEFAULT_GRPC_TARGET, value_name = "URL")]
+    pub grpc_target: String,
+
+    /// File API 断点续传会话的保留秒数：上游返回的上传地址换成指向网关的地址，后续分块经网关转发；0 不改写
+    #[arg(long, env = "AIZASY_UPLOAD_SESSION_TTL_SECS", default_value = "86400")]
+    pub upload_session_ttl_secs: u64,
+
+    /// 网关对外的地址 (如 https://gw.example.com)，用于改写上传地址；默认按请求的 Host / X-Forwarded-Proto
+    #[arg(long, env = "AIZASY_PUBLIC_URL", value_name = "URL")]
+    pub public_url: Option<String>,
+
+    /// 固定上游域名解析，可重复指定: HOST=IP[,IP...] (如 generativelanguage.googleapis.com=142.250.0.95)；不查 DNS
+    #[arg(long = "resolve", env = "AIZASY_RESOLVE", value_name = "HOST=IP")]
+    pub resolve: Vec<HostOverride>,
+
+    /// 用指定的 DNS 服务器解析上游域名，可重复指定: 1.1.1.1 (UDP/TCP) / tcp://IP / tls://IP#NAME (DoT) /
+    /// https://IP[/PATH]#NAME (DoH)；NAME 是服务器证书上的域名
+    #[cfg(feature = "dns")]
+    #[arg(long = "dns-server", env = "AIZASY_DNS_SERVERS", value_delimiter = ',', value_name = "SERVER")]
+    pub dns_servers: Vec<DnsServer>,
+
+    /// 上游地址族：auto (按解析顺序) / ipv4 / ipv6 (只用一种) / prefer-ipv4 / prefer-ipv6 (先试一种)
+    #[arg(long, env = "AIZASY_IP_STRATEGY", default_value = "auto", value_name = "STRATEGY")]
+    pub ip_strategy: IpStrategy,
+
+    /// Happy Eyeballs：上游同时有 IPv4 和 IPv6 地址时，首选地址族这么多毫秒没连上就同时试另一个，
+    /// 连得上的地址族记住 5 分钟；默认不探测 (IPv6 路由坏掉的主机上建议设为 250 左右)
+    #[arg(long, env = "AIZASY_HAPPY_EYEBALLS_MS", value_name = "MS")]
+    pub happy_eyeballs_ms: Option<u64>,
+
+    /// 上游首字节时间或整个请求超过这么多毫秒时打 WARN 日志，带上 DNS / 连接 / 首字节 / 流式传输的耗时拆分
+    #[arg(long, env = "AIZASY_SLOW_REQUEST_MS", value_name = "MS")]
+    pub slow_request_ms: Option<u64>,
+
+    /// 启动时对每个默认上游预先建立这么多连接 (含 TLS 握手)，网关空闲 --prewarm-idle-secs 后重新预热；0 不预热
+    #[arg(long, env = "AIZASY_PREWARM_CONNECTIONS", default_value = "0", value_name = "N")]
+    pub prewarm_connections: usize,
+
+    /// 空闲多少秒后重新预热连接 (连接池的空闲连接 90 秒后关闭)
+    #[arg(long, env = "AIZASY_PREWARM_IDLE_SECS", default_value = "60", value_name = "SECS")]
+    pub prewarm_idle_secs: u64,
+
+    /// 连接上游 (含 TLS 握手) 的超时秒数
+    #[arg(long, env = "AIZASY_CONNECT_TIMEOUT", value_name = "SECS", default_value = "10")]
+    pub connect_timeout: u64,
+
+    /// 到上游的 HTTP 版本: auto (ALPN 协商)、http1 (固定 HTTP/1.1) 或 http2 (prior knowledge，不协商直接用 h2)
+    #[arg(long, env = "AIZASY_UPSTREAM_PROTOCOL", default_value = "auto")]
+    pub upstream_protocol: UpstreamProtocol,
+
+    /// 上游 HTTP/2 单条流的初始窗口，如 4MB；高延迟链路上调大能提升流式吞吐
+    #[arg(long, env = "AIZASY_UPSTREAM_H2_STREAM_WINDOW", value_name = "SIZE", value_parser = parse_window)]
+    pub upstream_h2_stream_window: Option<u32>,
+
+    /// 上游 HTTP/2 整条连接的初始窗口
+    #[arg(long, env = "AIZASY_UPSTREAM_H2_CONNECTION_WINDOW", value_name = "SIZE", value_parser = parse_window)]
+    pub upstream_h2_connection_window: Option<u32>,
+
+    /// 按带宽时延积自动调整上游 HTTP/2 窗口 (忽略上面两个窗口设置)
+    #[arg(long, env = "AIZASY_UPSTREAM_H2_ADAPTIVE_WINDOW", default_value = "false")]
+    pub upstream_h2_adaptive_window: bool,
+
+    /// 上游 HTTP/2 最大帧大小 (16384 到 16777215 字节)
+    #[arg(long, env = "AIZASY_UPSTREAM_H2_MAX_FRAME_SIZE", value_name = "SIZE", value_parser = parse_frame_size)]
+    pub upstream_h2_max_frame_size: Option<u32>,
+
+    /// 上游 HTTP/2 连接的 PING 保活间隔秒数，连接空闲时也发送
+    #[arg(long, env = "AIZASY_UPSTREAM_H2_KEEPALIVE_SECS", value_name = "SECS")]
+    pub upstream_h2_keepalive_secs: Option<u64>,
+
+    /// 发出请求到收到上游响应头的超时秒数，超时返回 504
+    #[arg(long, env = "AIZASY_FIRST_BYTE_TIMEOUT", value_name = "SECS", default_value = "120")]
+    pub first_byte_timeout: u64,
+
+    /// 整个请求 (含响应体) 的超时秒数；默认不限制，设置后过长的流式生成也会被截断
+    #[arg(long, env = "AIZASY_TOTAL_TIMEOUT", value_name = "SECS")]
+    pub total_timeout: Option<u64>,
+
+    /// 同时处理的代理请求上限 (流式响应发完才算结束)，超出时返回 503；默认不限制
+    #[arg(long, env = "AIZASY_MAX_INFLIGHT", value_name = "N")]
+    pub max_inflight: Option<usize>,
+
+    /// 达到 --max-inflight 后允许排队等待的请求数，0 表示直接拒绝
+    #[arg(long, env = "AIZASY_MAX_INFLIGHT_QUEUE", default_value = "0")]
+    pub max_inflight_queue: usize,
+
+    /// 排队请求最多等待的秒数
+    #[arg(long, env = "AIZASY_MAX_INFLIGHT_QUEUE_SECS", default_value = "10")]
+    pub max_inflight_queue_secs: u64,
+
+    /// 受信任的反向代理 (逗号分隔的 CIDR，如 10.0.0.0/8,127.0.0.1)：来自这些地址的请求取
+    /// X-Forwarded-For 里的真实客户端 IP，并把 X-Forwarded-For 转发给上游；其他请求的 X-Forwarded-For 会被丢弃
+    #[arg(long, env = "AIZASY_TRUSTED_PROXIES", value_delimiter = ',')]
+    pub trusted_proxies: Vec<Cidr>,
+
+    /// 只允许这些来源访问 (逗号分隔的 CIDR)，覆盖所有路由；SIGHUP 重新加载配置文件时生效
+    #[arg(long, env = "AIZASY_IP_ALLOW", value_delimiter = ',')]
+    pub ip_allow: Vec<Cidr>,
+
+    /// 拒绝这些来源访问 (逗号分隔的 CIDR)，优先于 --ip-allow
+    #[arg(long, env = "AIZASY_IP_DENY", value_delimiter = ',')]
+    pub ip_deny: Vec<Cidr>,
+
+    /// MaxMind GeoLite2/GeoIP2 Country 数据库路径 (.mmdb)
+    #[cfg(feature = "geoip")]
+    #[arg(long, env = "AIZASY_GEOIP_DB")]
+    pub geoip_db: Option<String>,
+
+    /// 只允许这些国家访问 (逗号分隔的 ISO 国家码，如 CN,HK)
+    #[cfg(feature = "geoip")]
+    #[arg(long, env = "AIZASY_GEOIP_ALLOW", value_delimiter = ',')]
+    pub geoip_allow: Vec<String>,
+
+    /// 拒绝这些国家访问 (逗号分隔的 ISO 国家码)
+    #[cfg(feature = "geoip")]
+    #[arg(long, env = "AIZASY_GEOIP_DENY", value_delimiter = ',')]
+    pub geoip_deny: Vec<String>,
+
+    /// 检测扫描器探测路径 (/wp-login.php, /.env 等) 并自动封禁来源 IP
+    #[arg(long, env = "AIZASY_SCANNER_BAN", default_value = "false")]
+    pub scanner_ban: bool,
+
+    /// 封禁时长 (秒)
+    #[arg(long, env = "AIZASY_SCANNER_BAN_SECS", default_value = "3600")]
+    pub scanner_ban_secs: u64,
+
+    /// 命中后拖延响应的时间 (毫秒)
+    #[arg(long, env = "AIZASY_SCANNER_TARPIT_MS", default_value = "5000")]
+    pub scanner_tarpit_ms: u64,
+
+    /// 额外的扫描器路径片段 (逗号分隔，不区分大小写的子串匹配)
+    #[arg(long, env = "AIZASY_SCANNER_PATHS", value_delimiter = ',')]
+    pub scanner_paths: Vec<String>,
+
+    /// 只转发这些上游路径，其余返回 403 (逗号分隔，`*` 匹配任意字符)，
+    /// 例如 */models/*:generateContent,*/models/*:streamGenerateContent
+    #[arg(long = "allow-path", env = "AIZASY_ALLOW_PATHS", value_delimiter = ',')]
+    pub allow_paths: Vec<String>,
+
+    /// 签名 URL 的 HMAC 密钥，至少 16 字节
+    #[arg(long, env = "AIZASY_SIGNING_SECRET", value_parser = parse_signing_secret)]
+    pub signing_secret: Option<String>,
+
+    /// 代理路由必须携带有效签名
+    #[arg(long, env = "AIZASY_SIGNED_URL_REQUIRED", default_value = "false", requires = "signing_secret")]
+    pub signed_url_required: bool,
+
+    /// 为给定 path (可带 query) 生成签名 URL 后退出
+    #[arg(long, value_name = "PATH", requires = "signing_secret")]
+    pub sign_url: Option<String>,
+
+    /// 签名 URL 的有效期 (秒)
+    #[arg(long, default_value = "3600")]
+    pub sign_ttl: u64,
+
+    /// 按来源 IP 和出示的客户端令牌限制鉴权失败次数，超出后指数递增锁定
+    #[arg(long, env = "AIZASY_AUTH_LOCKOUT", default_value = "false")]
+    pub auth_lockout: bool,
+
+    /// 连续失败多少次后开始锁定
+    #[arg(long, env = "AIZASY_AUTH_LOCKOUT_THRESHOLD", default_value = "5")]
+    pub auth_lockout_threshold: u32,
+
+    /// 首次锁定时长 (秒)，之后每次失败翻倍
+    #[arg(long, env = "AIZASY_AUTH_LOCKOUT_BASE_SECS", default_value = "30")]
+    pub auth_lockout_base_secs: u64,
+
+    /// 锁定时长上限 (秒)
+    #[arg(long, env = "AIZASY_AUTH_LOCKOUT_MAX_SECS", default_value = "3600")]
+    pub auth_lockout_max_secs: u64,
+
+    /// 按来源 IP 检测请求突发和反复的鉴权失败，超限后逐步拖延响应，屡次超限时临时封禁
+    #[arg(long, env = "AIZASY_BURST_GUARD", default_value = "false")]
+    pub burst_guard: bool,
+
+    /// 突发检测的窗口 (秒)
+    #[arg(long, env = "AIZASY_BURST_WINDOW_SECS", default_value = "10", requires = "burst_guard")]
+    pub burst_window_secs: u64,
+
+    /// 单个 IP 一个窗口内的请求数上限
+    #[arg(long, env = "AIZASY_BURST_REQUESTS", default_value = "100", requires = "burst_guard")]
+    pub burst_requests: u32,
+
+    /// 单个 IP 连续鉴权失败次数上限 (和 --auth-lockout 共用计数)，0 表示不按鉴权失败判断
+    #[arg(long, env = "AIZASY_BURST_AUTH_FAILURES", default_value = "10", requires = "burst_guard")]
+    pub burst_auth_failures: u32,
+
+    /// 超限后第一次拖延的时长 (毫秒)，之后每次翻倍
+    #[arg(long, env = "AIZASY_BURST_DELAY_MS", default_value = "250", requires = "burst_guard")]
+    pub burst_delay_ms: u64,
+
+    /// 拖延时长上限 (毫秒)
+    #[arg(long, env = "AIZASY_BURST_MAX_DELAY_MS", default_value = "10000", requires = "burst_guard")]
+    pub burst_max_delay_ms: u64,
+
+    /// 累计超限多少次后临时封禁，0 表示只拖延不封禁
+    #[arg(long, env = "AIZASY_BURST_BAN_AFTER", default_value = "20", requires = "burst_guard")]
+    pub burst_ban_after: u32,
+
+    /// 封禁时长 (秒)
+    #[arg(long, env = "AIZASY_BURST_BAN_SECS", default_value = "600", requires = "burst_guard")]
+    pub burst_ban_secs: u64,
+
+    /// 不做突发检测的来源 (逗号分隔的 CIDR，如内网负载均衡)
+    #[arg(long, env = "AIZASY_BURST_EXEMPT", value_delimiter = ',', requires = "burst_guard")]
+    pub burst_exempt: Vec<Cidr>,
+
+    /// 给网关自身生成的响应 (health、metrics、错误页) 加上标准安全头
+    #[arg(long, env = "AIZASY_SECURITY_HEADERS", default_value = "false")]
+    pub security_headers: bool,
+
+    /// 不注入安全头的路径前缀 (逗号分隔)
+    #[arg(long, env = "AIZASY_SECURITY_HEADERS_SKIP", value_delimiter = ',')]
+    pub security_headers_skip: Vec<String>,
+
+    /// HSTS max-age (秒)，仅在启用 TLS 时下发
+    #[arg(long, env = "AIZASY_HSTS_MAX_AGE", default_value = "31536000")]
+    pub hsts_max_age: u64,
+
+    /// 路径改写规则，可重复指定: FROM=TO，在路由匹配之前按顺序尝试，第一条命中的生效。
+    /// /gemini/*=/* 去掉前缀，/api/*=/v1beta/* 换前缀，~REGEX=/v1beta/$1 用正则
+    #[arg(long = "rewrite", env = "AIZASY_REWRITES", value_name = "FROM=TO")]
+    pub rewrites: Vec<String>,
+
+    /// 请求头 / 响应头改写规则，可重复指定: DIRECTION:ACTION:NAME[=VALUE]，
+    /// 如 request:set:x-goog-user-project=my-project、response:remove:server；ACTION 为 add / set / remove
+    #[arg(long = "header-rule", env = "AIZASY_HEADER_RULES", value_name = "RULE")]
+    pub header_rules: Vec<String>,
+
+    /// 启用 CORS，允许这些来源 (逗号分隔；* 表示任意来源，也可以写 https://*.example.com)
+    #[arg(long, env = "AIZASY_CORS_ORIGINS", value_delimiter = ',', value_name = "ORIGIN")]
+    pub cors_origin: Vec<String>,
+
+    /// 预检允许的方法
+    #[arg(long, env = "AIZASY_CORS_METHODS", value_delimiter = ',', default_value = "GET,POST,PUT,PATCH,DELETE,OPTIONS")]
+    pub cors_methods: Vec<String>,
+
+    /// 预检允许的请求头 (逗号分隔)；不指定时允许预检里请求的所有头
+    #[arg(long, env = "AIZASY_CORS_HEADERS", value_delimiter = ',')]
+    pub cors_headers: Vec<String>,
+
+    /// 允许浏览器脚本读取的响应头 (逗号分隔)
+    #[arg(long, env = "AIZASY_CORS_EXPOSE_HEADERS", value_delimiter = ',')]
+    pub cors_expose_headers: Vec<String>,
+
+    /// 预检结果缓存秒数
+    #[arg(long, env = "AIZASY_CORS_MAX_AGE_SECS", default_value = "600")]
+    pub cors_max_age_secs: u64,
+
+    /// 允许携带 Cookie / Authorization 等凭证 (回显具体来源而不是 *)
+    #[arg(long, env = "AIZASY_CORS_ALLOW_CREDENTIALS", default_value = "false")]
+    pub cors_allow_credentials: bool,
+
+    /// 网关错误响应模板 STATUS=FILE (可重复指定)，变量:
+    /// {{status}} {{reason}} {{message}} {{request_id}} {{retry_after}}
+    #[arg(long = "error-template", env = "AIZASY_ERROR_TEMPLATES", value_delimiter = ',', value_name = "STATUS=FILE")]
+    pub error_templates: Vec<String>,
+
+    /// 租户定义，可重复指定: NAME;host=a.example.com;prefix=/team-a;target=https://...;keys=GROUP;clients=a,b
+    /// (keys 是 --key-group 的分组，clients 是允许访问的 --client-token 名字)
+    #[arg(long = "tenant", env = "AIZASY_TENANTS", value_name = "SPEC")]
+    pub tenants: Vec<String>,
+
+    /// 不属于任何租户的请求返回 404
+    #[arg(long, env = "AIZASY_TENANT_REQUIRED", default_value = "false", requires = "tenants")]
+    pub tenant_required: bool,
+
+    /// 以维护模式启动：代理路由返回 503，health 和管理 API 照常可用
+    #[arg(long, env = "AIZASY_MAINTENANCE", default_value = "false")]
+    pub maintenance: bool,
+
+    /// 维护模式下返回给客户端的说明
+    #[arg(long, env = "AIZASY_MAINTENANCE_MESSAGE")]
+    pub maintenance_message: Option<String>,
+
+    /// 维护模式下的 Retry-After (秒)
+    #[arg(long, env = "AIZASY_MAINTENANCE_RETRY_AFTER_SECS", default_value = "300")]
+    pub maintenance_retry_after_secs: u64,
+
+    /// 摘流 (SIGUSR1 或 POST /admin/drain) 后继续接受新连接的秒数：/health 已经返回 503，
+    /// 留给负载均衡的健康检查发现
+    #[arg(long, env = "AIZASY_DRAIN_DELAY_SECS", default_value = "0")]
+    pub drain_delay_secs: u64,
+
+    /// 停止接受新连接后等待在途请求完成的最长秒数，超过后直接退出
+    #[arg(long, env = "AIZASY_DRAIN_TIMEOUT_SECS", default_value = "30")]
+    pub drain_timeout_secs: u64,
+
+    /// 启用 POST /batch：一次提交多条 Gemini 请求，由网关并发转发后汇总
+    #[arg(long, env = "AIZASY_BATCH", default_value = "false")]
+    pub batch: bool,
+
+    /// 单个批量请求最多包含的子请求数
+    #[arg(long, env = "AIZASY_BATCH_MAX_REQUESTS", default_value = "100")]
+    pub batch_max_requests: usize,
+
+    /// 所有批量请求合计同时在途的子请求上限
+    #[arg(long, env = "AIZASY_BATCH_CONCURRENCY", default_value = "8")]
+    pub batch_concurrency: usize,
+
+    /// 合并同一时刻的相同 GET 请求 (例如频繁刷新的 /v1beta/models)：只转发第一个，其余共享它的响应
+    #[arg(long, env = "AIZASY_COALESCE_GET", default_value = "false")]
+    pub coalesce_get: bool,
+
+    /// 启用 OpenAI 兼容端点：/v1/chat/completions、/v1/embeddings、/v1/models 转成 Gemini 调用
+    #[arg(long, env = "AIZASY_OPENAI_COMPAT", default_value = "false")]
+    pub openai_compat: bool,
+
+    /// 其他厂商的上游，可重复指定: openai|anthropic|azure[;models=gpt-*,o3*][;key=KEY][;url=URL][;name=NAME][;version=V]
+    /// 请求体里的 model 匹配时原样转发给这个厂商 (换成它的鉴权头，不用 Gemini key 池)；
+    /// azure 需要 url=https://<资源>.openai.azure.com，可用 deployments=gpt-4o=DEPLOYMENT,... 映射部署名，
+    /// version 为 api-version (默认 2024-10-21)
+    #[arg(long = "provider", env = "AIZASY_PROVIDERS", value_name = "SPEC", hide_env_values = true)]
+    pub providers: Vec<String>,
+
+    /// 跟踪各客户端创建的 cachedContents，过期记录自动清理，可通过管理 API 查看
+    #[arg(long, env = "AIZASY_CACHED_CONTENTS", default_value = "false")]
+    pub cached_contents: bool,
+
+    /// 客户端只能列出 / 读取 / 修改 / 删除自己创建的缓存；没有客户端令牌的请求不能创建缓存
+    #[arg(long, env = "AIZASY_CACHE_ISOLATION", default_value = "false", requires = "cached_contents")]
+    pub cache_isolation: bool,
+
+    /// 给模型自动挂上缓存 (请求里没有 cachedContent 时): MODEL=cachedContents/NAME，可重复指定
+    #[arg(long = "cache-attach", env = "AIZASY_CACHE_ATTACH", value_delimiter = ',', requires = "cached_contents")]
+    pub cache_attach: Vec<String>,
+
+    /// 创建 / 更新缓存时允许的最长 TTL (秒)，超出的请求会被改写
+    #[arg(long, env = "AIZASY_CACHE_MAX_TTL_SECS", requires = "cached_contents")]
+    pub cache_max_ttl_secs: Option<u64>,
+
+    /// 在内存里保留最近这么多个请求的摘要，可通过管理 API 查看或 SSE 实时订阅；0 为关闭
+    #[arg(long, env = "AIZASY_INSPECTOR", default_value = "0")]
+    pub inspector: usize,
+
+    /// 在内存里留存最近这么多条失败请求 (已脱敏)，可通过管理 API 查看和重放；0 为关闭
+    #[arg(long, env = "AIZASY_FAILURE_LOG", default_value = "0")]
+    pub failure_log: usize,
+
+    /// 允许的项目名 (逗号分隔)，客户端用 X-Aizasy-Project 头把用量记到项目上
+    #[arg(long = "project", env = "AIZASY_PROJECTS", value_name = "NAME", value_delimiter = ',')]
+    pub projects: Vec<String>,
+
+    /// 没有 X-Aizasy-Project 头的请求返回 400
+    #[arg(long, env = "AIZASY_PROJECT_REQUIRED", default_value = "false", requires = "projects")]
+    pub project_required: bool,
+
+    /// 按客户端和上游 key 记录每个请求的 token 数与费用 (写入 --storage，sqlite:PATH 可持久化)，
+    /// 管理 API /admin/usage 按 key 或客户端汇总
+    #[arg(long, env = "AIZASY_LEDGER", default_value = "false")]
+    pub ledger: bool,
+
+    /// 价格表 JSON: {"gemini-1.5-pro": {"input": 1.25, "output": 5.0}}，单位为每百万 token
+    #[arg(long, env = "AIZASY_PRICE_TABLE", value_name = "FILE")]
+    pub price_table: Option<String>,
+
+    /// 转发前按 Gemini API 结构检查请求体，不合法的直接返回 400，不消耗上游配额
+    #[arg(long, env = "AIZASY_VALIDATE_REQUESTS", default_value = "false")]
+    pub validate_requests: bool,
+
+    /// 用自定义 JSON Schema 校验请求体，可重复指定: SELECTOR=FILE，
+    /// SELECTOR 是路径前缀 (以 / 开头) 或方法名 (如 generateContent)
+    #[cfg(feature = "schema")]
+    #[arg(long = "request-schema", env = "AIZASY_REQUEST_SCHEMAS", value_delimiter = ',', value_name = "SELECTOR=FILE")]
+    pub request_schemas: Vec<String>,
+
+    /// 生成参数策略，可重复指定: FIELD<=N / FIELD>=N / FIELD=VALUE / FIELD?=VALUE (FIELD 相对 generationConfig，
+    /// 如 maxOutputTokens<=2048、temperature<=1、candidateCount=1)；转发前改写请求体
+    #[arg(long = "generation-policy", env = "AIZASY_GENERATION_POLICY", value_name = "RULE")]
+    pub generation_policy: Vec<String>,
+
+    /// 强制安全设置，可重复指定: CATEGORY=THRESHOLD (如 HARM_CATEGORY_HARASSMENT=BLOCK_LOW_AND_ABOVE)，
+    /// 覆盖客户端对同一类别的设置
+    #[arg(long = "force-safety", env = "AIZASY_FORCE_SAFETY", value_delimiter = ',', value_name = "CATEGORY=THRESHOLD")]
+    pub force_safety: Vec<String>,
+
+    /// 强制系统提示词，可重复指定: SCOPE=TEXT，SCOPE 为 * / 路径前缀 (以 / 开头) / client:NAME / 方法名，
+    /// TEXT 以 @ 开头时从文件读取；转发前注入请求体的 systemInstruction
+    #[arg(long = "system-prompt", env = "AIZASY_SYSTEM_PROMPTS", value_name = "SCOPE=TEXT")]
+    pub system_prompts: Vec<String>,
+
+    /// 强制系统提示词和客户端自己的合并方式: prepend / append / replace / default (客户端没设置时才补上)
+    #[arg(long, env = "AIZASY_SYSTEM_PROMPT_MODE", default_value = "prepend")]
+    pub system_prompt_mode: SystemPromptMode,
+
+    /// 请求体脱敏规则，可重复指定: PATTERN[;path=JSONPATH][;with=TEXT]，
+    /// PATTERN 为 email / phone / card 或 regex:表达式；转发前把命中的内容替换成 TEXT (默认 [REDACTED])
+    #[arg(long = "redact", env = "AIZASY_REDACT", value_name = "SPEC")]
+    pub redact: Vec<String>,
+
+    /// 流式响应改写规则，可重复指定: drop:FIELD (如 drop:safetyRatings) / set:FIELD=VALUE (如 set:modelVersion=my-model)；
+    /// 边转发边改写 SSE / JSON 流里的每个事件
+    #[arg(long = "stream-transform", env = "AIZASY_STREAM_TRANSFORM", value_name = "RULE")]
+    pub stream_transforms: Vec<String>,
+
+    /// 从非流式 JSON 响应里去掉的字段 (逗号分隔)：NAME 匹配任意层级，A.B.C 为从顶层开始的路径，
+    /// 前面加 METHOD: 只对这个方法生效，如 promptFeedback,generateContent:candidates.content.parts.inlineData；
+    /// 给带宽受限的客户端省流量
+    #[arg(long = "strip-response-field", env = "AIZASY_STRIP_RESPONSE_FIELDS", value_delimiter = ',', value_name = "FIELD")]
+    pub strip_response_fields: Vec<String>,
+
+    /// 按客户端的 Accept-Encoding 压缩非流式响应 (逗号分隔: gzip,br)；SSE 流不压缩
+    #[cfg(feature = "compression")]
+    #[arg(long = "compress", env = "AIZASY_COMPRESS", value_delimiter = ',', value_name = "ALGORITHM")]
+    pub compress: Vec<Algorithm>,
+
+    /// 小于这个字节数的响应不压缩
+    #[cfg(feature = "compression")]
+    #[arg(long, env = "AIZASY_COMPRESS_MIN_SIZE", default_value = "1024")]
+    pub compress_min_size: u64,
+
+    /// 模型名映射，可重复指定: FROM=TO (如 gemini-pro-latest=gemini-2.5-pro)，
+    /// 转发前改写路径和请求体里的模型名
+    #[arg(long = "model-map", env = "AIZASY_MODEL_MAP", value_delimiter = ',', value_name = "FROM=TO")]
+    pub model_map: Vec<String>,
+
+    /// 模型降级链，可重复指定: MODEL=FALLBACK[,FALLBACK...]；上游对 MODEL 返回 404 / 429 / 503 时
+    /// 依次换成后面的模型重试，实际使用的模型写在 x-aizasy-routed-model 响应头里
+    #[arg(long = "model-fallback", env = "AIZASY_MODEL_FALLBACKS", value_name = "SPEC")]
+    pub model_fallbacks: Vec<String>,
+
+    /// 模型别名，可重复指定: ALIAS=MODEL[@TIER],MODEL[@TIER];tier=N
+    /// 请求别名时按价格表选满足质量等级的最便宜模型 (请求头 x-aizasy-quality-tier 可覆盖等级)
+    #[arg(long = "model-alias", env = "AIZASY_MODEL_ALIASES", value_name = "SPEC")]
+    pub model_aliases: Vec<String>,
+
+    /// 模型返回 429 / 403 后的冷却时间 (秒)，期间别名优先选其他模型
+    #[arg(long, env = "AIZASY_MODEL_COOLDOWN_SECS", default_value = "60")]
+    pub model_cooldown_secs: u64,
+
+    /// 按客户端的请求配额，可重复指定:
+    /// SUBJECT=LIMIT;window=SECS|day|month;unit=requests|tokens;policy=fixed|sliding|leaky;carry_over=RATIO
+    /// (SUBJECT 为客户端标识或 *；day / month 按 UTC 自然日 / 月重置，计数存在 --storage 里)
+    #[arg(long = "quota", env = "AIZASY_QUOTAS", value_name = "SPEC")]
+    pub quotas: Vec<String>,
+
+    /// 每分钟速率限制 (滑动窗口，burst 倍的窗口长度和额度)，可重复指定: SUBJECT;rpm=N;tpm=N;burst=RATIO
+    /// (SUBJECT 为客户端令牌名、ip:<地址>、tenant:<租户名> 或 *)，超出时返回 429 和 Retry-After
+    #[arg(long = "rate-limit", env = "AIZASY_RATE_LIMITS", value_name = "SPEC")]
+    pub rate_limits: Vec<String>,
+
+    /// 响应体下行带宽限制 (令牌桶)，可重复指定: SUBJECT;rate=SIZE;burst=SIZE (每秒字节数，可以带 KB/MB 单位)
+    /// (SUBJECT 为客户端令牌名、ip:<地址>、* 或 global，global 是所有客户端共用的总带宽)
+    #[arg(long = "bandwidth-limit", env = "AIZASY_BANDWIDTH_LIMITS", value_name = "SPEC")]
+    pub bandwidth_limits: Vec<String>,
+
+    /// 定时窗口，可重复指定: NAME;cron=分 时 日 月 星期;path=PREFIX;client=ID;action=block|throttle;limit=N;utc_offset=+8
+    /// 窗口内 (cron 匹配的每一分钟) 对匹配的请求拒绝或按每分钟 limit 限速
+    #[arg(long = "schedule", env = "AIZASY_SCHEDULES", value_name = "SPEC")]
+    pub schedules: Vec<String>,
+
+    /// countTokens 请求在本地近似估算，不访问上游 (结果带 x-aizasy-token-estimate 响应头)
+    #[arg(long, env = "AIZASY_LOCAL_COUNT_TOKENS", default_value = "false")]
+    pub local_count_tokens: bool,
+
+    /// 在内存里缓存 embedContent / batchEmbedContents / countTokens 的 200 响应，
+    /// 相同请求 (method + path + 请求体 + 凭证) 直接返回 (响应头 x-aizasy-cache: HIT)
+    #[arg(long, env = "AIZASY_RESPONSE_CACHE", default_value = "false")]
+    pub response_cache: bool,
+
+    /// temperature 为 0 的非流式 generateContent 也进响应缓存
+    #[arg(long, env = "AIZASY_RESPONSE_CACHE_DETERMINISTIC", default_value = "false")]
+    pub response_cache_deterministic: bool,
+
+    /// 响应缓存最多条目数，超过时淘汰最久没用到的
+    #[arg(long, env = "AIZASY_RESPONSE_CACHE_ENTRIES", default_value = "1000")]
+    pub response_cache_entries: usize,
+
+    /// 响应缓存总大小上限 (MB)
+    #[arg(long, env = "AIZASY_RESPONSE_CACHE_MAX_MB", default_value = "64")]
+    pub response_cache_max_mb: usize,
+
+    /// 响应缓存条目有效期 (秒)
+    #[arg(long, env = "AIZASY_RESPONSE_CACHE_TTL_SECS", default_value = "3600")]
+    pub response_cache_ttl_secs: u64,
+
+    /// 预算，可重复指定: SUBJECT=LIMIT;unit=cost|tokens;period=day|month
+    /// (SUBJECT 为客户端标识、key:<名称> 或 *)，跨过 50/80/100% 时告警
+    #[arg(long = "budget", env = "AIZASY_BUDGETS", value_name = "SPEC", requires = "ledger")]
+    pub budgets: Vec<String>,
+
+    /// 预算告警 webhook (POST JSON)
+    #[arg(long, env = "AIZASY_BUDGET_WEBHOOK", value_name = "URL")]
+    pub budget_webhook: Option<String>,
+
+    /// 预付费额度：余额耗尽的客户端被拒绝 (402)，通过管理 API 充值
+    #[arg(long, env = "AIZASY_CREDITS", default_value = "false", requires = "ledger")]
+    pub credits: bool,
+
+    /// 额度单位: cost / tokens
+    #[arg(long, env = "AIZASY_CREDIT_UNIT", default_value = "cost")]
+    pub credit_unit: BudgetUnit,
+
+    /// 把每条账本记录推送到外部计费系统 (POST JSON 数组)
+    #[arg(long, env = "AIZASY_METERING_URL", value_name = "URL", requires = "ledger")]
+    pub metering_url: Option<String>,
+
+    /// 推送时附带的鉴权头，例如 "Authorization: Bearer xxx"
+    #[arg(long, env = "AIZASY_METERING_AUTH", value_name = "HEADER", hide_env_values = true)]
+    pub metering_auth: Option<String>,
+
+    /// 每批最多推送的事件数
+    #[arg(long, env = "AIZASY_METERING_BATCH_SIZE", default_value = "100")]
+    pub metering_batch_size: usize,
+
+    /// 不满一批时的刷新间隔 (秒)
+    #[arg(long, env = "AIZASY_METERING_FLUSH_SECS", default_value = "10")]
+    pub metering_flush_secs: u64,
+
+    /// 推送失败后的重试次数
+    #[arg(long, env = "AIZASY_METERING_RETRIES", default_value = "3")]
+    pub metering_retries: u32,
+
+    /// 定期导出用量汇总到文件或 http(s):// 地址 (需要 --ledger)
+    #[arg(long, env = "AIZASY_USAGE_EXPORT", value_name = "FILE|URL", requires = "ledger")]
+    pub usage_export: Option<String>,
+
+    /// 导出格式: csv / json
+    #[arg(long, env = "AIZASY_USAGE_EXPORT_FORMAT", default_value = "csv")]
+    pub usage_export_format: ExportFormat,
+
+    /// 导出间隔 (秒)
+    #[arg(long, env = "AIZASY_USAGE_EXPORT_INTERVAL_SECS", default_value = "3600")]
+    pub usage_export_interval_secs: u64,
+
+    /// 共享状态存储: memory / sqlite:PATH / redis://HOST:PORT
+    /// (多副本共用同一个后端时配额、速率限制和 key 冷却状态在副本之间一致)
+    #[arg(long, env = "AIZASY_STORAGE", default_value = "memory")]
+    pub storage: String,
+
+    /// 管理 API 的 Bearer token；不设置则不开放 /admin
+    #[cfg(feature = "admin")]
+    #[arg(long, env = "AIZASY_ADMIN_TOKEN", hide_env_values = true)]
+    pub admin_token: Option<String>,
+
+    /// 管理 API 单独监听的地址 (如 127.0.0.1:9100)，设置后主监听地址不再提供 /admin；
+    /// 没有设置 --admin-token 时这个地址上的管理 API 不鉴权；来源 IP 白名单和访问日志同样生效
+    #[cfg(feature = "admin")]
+    #[arg(long, env = "AIZASY_ADMIN_LISTEN")]
+    pub admin_listen: Option<SocketAddr>,
+
+    /// 在管理 API 的 /admin/dashboard 提供内置监控面板 (实时 RPS、延迟分位数、key 用量和冷却状态、最近的错误)
+    #[cfg(feature = "admin")]
+    #[arg(long, env = "AIZASY_DASHBOARD", default_value = "false")]
+    pub dashboard: bool,
+
+    /// 在主监听地址上不鉴权地提供 /metrics；默认要带 --admin-token，或者放在 --admin-listen 上
+    /// (指标带着 key、客户端、代理和国家等标签)
+    #[cfg(feature = "metrics")]
+    #[arg(long, env = "AIZASY_METRICS_PUBLIC", default_value = "false")]
+    pub metrics_public: bool,
+
+    /// 运行时设置的上游允许使用 http://
+    #[arg(long, env = "AIZASY_DYNAMIC_TARGET_ALLOW_HTTP", default_value = "false")]
+    pub dynamic_target_allow_http: bool,
+
+    /// 运行时设置的上游允许指向内网 / 回环 / 链路本地地址
+    #[arg(long, env = "AIZASY_DYNAMIC_TARGET_ALLOW_PRIVATE", default_value = "false")]
+    pub dynamic_target_allow_private: bool,
+
+    /// 运行时设置的上游主机名白名单 (逗号分隔，".example.com" 表示后缀匹配)
+    #[arg(long, env = "AIZASY_DYNAMIC_TARGET_HOSTS", value_delimiter = ',')]
+    pub dynamic_target_hosts: Vec<String>,
+
+    /// 固定响应配置 (JSON 数组)：匹配的路径直接返回配置的响应或生成的流，不访问上游
+    #[cfg(feature = "devtools")]
+    #[arg(long, env = "AIZASY_CANNED", value_name = "FILE")]
+    pub canned: Option<String>,
+
+    /// 启动内置的 mock Gemini 上游并把 target 指向它 (不需要真实 key)
+    #[cfg(feature = "devtools")]
+    #[arg(long, env = "AIZASY_MOCK_UPSTREAM", default_value = "false")]
+    pub mock_upstream: bool,
+
+    /// mock 上游的响应延迟 (毫秒)
+    #[cfg(feature = "devtools")]
+    #[arg(long, default_value = "0")]
+    pub mock_latency_ms: u64,
+
+    /// mock 上游流式响应的分块数
+    #[cfg(feature = "devtools")]
+    #[arg(long, default_value = "5")]
+    pub mock_chunks: usize,
+
+    /// mock 上游流式分块间隔 (毫秒)
+    #[cfg(feature = "devtools")]
+    #[arg(long, default_value = "100")]
+    pub mock_chunk_interval_ms: u64,
+
+    /// mock 上游注入错误的比例 (0.0 ~ 1.0)
+    #[cfg(feature = "devtools")]
+    #[arg(long, default_value = "0")]
+    pub mock_error_rate: f64,
+
+    /// mock 上游注入错误的状态码
+    #[cfg(feature = "devtools")]
+    #[arg(long, default_value = "429")]
+    pub mock_error_status: u16,
+
+    /// mock 上游流式响应输出这么多块后断流
+    #[cfg(feature = "devtools")]
+    #[arg(long)]
+    pub mock_drop_after: Option<usize>,
+
+    /// 把每个请求/响应 (已脱敏，含流式分块时间) 录制到该目录
+    #[cfg(feature = "devtools")]
+    #[arg(long, env = "AIZASY_RECORD", value_name = "DIR")]
+    pub record: Option<String>,
+
+    /// 用录制目录代替真实上游进行回放
+    #[cfg(feature = "devtools")]
+    #[arg(long, env = "AIZASY_REPLAY", value_name = "DIR", conflicts_with = "mock_upstream")]
+    pub replay: Option<String>,
+
+    /// 回放时不保留原始分块节奏，立即返回
+    #[cfg(feature = "devtools")]
+    #[arg(long, default_value = "false")]
+    pub replay_fast: bool,
+
+    /// 故障注入规则，可重复指定:
+    /// PATH;latency=100-500;ttfb=exp:800;error_rate=0.1;status=503;drop_rate=0.2;drop_after=3
+    #[cfg(feature = "devtools")]
+    #[arg(long = "chaos", env = "AIZASY_CHAOS", value_name = "RULE")]
+    pub chaos: Vec<String>,
+
+    /// age 身份文件，用于解密 ENC[age:...] 形式的参数值
+    #[cfg(feature = "secrets")]
+    #[arg(long, env = "AIZASY_AGE_IDENTITY")]
+    pub age_identity: Option<String>,
+
+    /// age 口令，用于解密 `age -p` 加密的参数值
+    #[cfg(feature = "secrets")]
+    #[arg(long, env = "AIZASY_AGE_PASSPHRASE", hide_env_values = true)]
+    pub age_passphrase: Option<String>,
+
+    /// 加载 WASM 插件 (可重复指定，按顺序执行)；`PATH@/v1beta/models/gemini-2.5-pro|/other` 只对
+    /// 这些路径前缀生效
+    #[cfg(feature = "wasm")]
+    #[arg(long = "wasm-plugin", env = "AIZASY_WASM_PLUGINS", value_delimiter = ',')]
+    pub wasm_plugins: Vec<String>,
+
+    /// 加载 Rhai 脚本钩子 (可重复指定，文件修改后自动重载)
+    #[cfg(feature = "scripting")]
+    #[arg(long = "script", env = "AIZASY_SCRIPTS", value_delimiter = ',')]
+    pub scripts: Vec<String>,
+}
+
+// HTTP/2 窗口和帧大小，协议上限 2^31-1
+fn parse_window(value: &str) -> Result<u32, String> {
+    parse_size(value)?
+        .try_into()
+        .ok()
+        .filter(|size: &u32| *size <= i32::MAX as u32)
+        .ok_or_else(|| format!("'{}' is too large for an HTTP/2 window", value))
+}
+
+// HTTP/2 规定的帧大小范围 (RFC 9113 §4.2)，超出范围时 h2 直接 panic
+fn parse_frame_size(value: &str) -> Result<u32, String> {
+    parse_size(value)?
+        .try_into()
+        .ok()
+        .filter(|size: &u32| (16_384..=16_777_215).contains(size))
+        .ok_or_else(|| format!("'{}' must be between 16KB and 16MB - 1 for an HTTP/2 frame", value))
+}
+
+// 0 到 100 的百分比
+fn parse_percent(value: &str) -> Result<f64, String> {
+    value
+        .trim_end_matches('%')
+        .parse::<f64>()
+        .ok()
+        .filter(|percent| (0.0..=100.0).contains(percent))
+        .ok_or_else(|| format!("'{}' is not a percentage between 0 and 100", value))
+}
+
+// 签名密钥：ENC[...] 加密的值解密后再检查长度
+fn parse_signing_secret(value: &str) -> Result<String, String> {
+    if !value.starts_with("ENC[") {
+        UrlSigner::check_secret(value)?;
+    }
+    Ok(value.to_string())
+}
+
+// 八进制文件权限，如 660
+fn parse_mode(value: &str) -> Result<u32, String> {
+    u32::from_str_radix(value.trim_start_matches("0o"), 8)
+        .ok()
+        .filter(|mode| *mode <= 0o777)
+        .ok_or_else(|| format!("invalid file mode '{}'", value))
+}
+
+/// 管理 API 的日志级别开关；日志只在启动时初始化一次，热重载构建的网关共用同一个
+#[cfg(feature = "admin")]
+static LOG_LEVEL: OnceLock<LogLevelControl> = OnceLock::new();
+
+/// 账本的在途写入；导出任务只在启动时创建，热重载构建的账本要和它共用同一个
+static LEDGER_WRITES: OnceLock<PendingWrites> = OnceLock::new();
+
+/// 热重载时沿用的资源
+pub struct Reuse {
+    pub storage: Arc<dyn Storage>,
+    /// 启动时实际使用的上游 (mock / 回放时是本地地址)
+    pub target: String,
+    /// 新旧网关共享摘流状态
+    pub drain: Drain,
+}
+
+/// 只用来探测上游的网关：上游、路由、证书、第一个代理和 key 池，
+/// 不打开存储、不写文件、不起后台任务 (健康检查、代理探测、key 重新加载等)
+pub async fn probe_gateway(args: &Args) -> Result<Gateway, String> {
+    let targets = args.target.iter().map(|spec| WeightedTarget::parse(spec)).collect::<Result<Vec<_>, _>>()?;
+    let routes = args
+        .routes
+        .iter()
+        .enumerate()
+        .map(|(i, spec)| RouteRule::parse(spec, i))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut builder = Gateway::builder()
+        .targets(targets)
+        .routes(routes)
+        .insecure(args.insecure)
+        .connect_timeout(Duration::from_secs(args.connect_timeout))
+        .upstream_http(upstream_http(args))
+        .dns(dns_config(args));
+    for path in &args.ca_cert {
+        builder = builder.ca_cert(path);
+    }
+    if let (Some(cert), Some(key)) = (&args.upstream_client_cert, &args.upstream_client_key) {
+        builder = builder.upstream_client_cert(cert, key);
+    }
+    if let Some(proxy) = args.proxy.first() {
+        builder = builder.proxies(vec![proxy.clone()]);
+    }
+    if let Some(auth) = &args.proxy_auth {
+        builder = builder.proxy_auth(auth.clone());
+    }
+    let keys = key_source(args)?.load().await.map_err(|e| format!("Invalid key pool: {}", e))?;
+    if !keys.is_empty() {
+        builder = builder.key_pool(KeyPool::new(keys, args.key_injection)?);
+    }
+    builder.build()
+}
+
+/// 检查时收集到的错误；值写在配置文件里时带上文件名和行号
+struct Problems {
+    errors: Vec<String>,
+    #[cfg(feature = "config")]
+    file: Option<(String, String, Vec<config_file::ConfigEntry>)>,
+}
+
+impl Problems {
+    fn new(#[cfg_attr(not(feature = "config"), allow(unused_variables))] args: &Args) -> Self {
+        Self {
+            errors: Vec::new(),
+            // 文件在解析命令行时已经读过一次，这里读不到就不带行号
+            #[cfg(feature = "config")]
+            file: args.config.as_ref().and_then(|path| {
+                let (content, entries) = config_file::load(path).ok()?;
+                Some((path.clone(), content, entries))
+            }),
+        }
+    }
+
+    fn push(&mut self, e: String) {
+        self.errors.push(e);
+    }
+
+    /// 单个值的解析结果
+    fn note<T>(&mut self, flag: &str, value: &str, result: Result<T, String>) {
+        if let Err(e) = result {
+            self.report(flag, &[value], e);
+        }
+    }
+
+    /// 同一参数的所有值一起解析 (规则之间有关联) 的结果
+    fn note_all<T>(&mut self, flag: &str, values: &[String], result: Result<T, String>) {
+        if let Err(e) = result {
+            let values: Vec<&str> = values.iter().map(String::as_str).collect();
+            self.report(flag, &values, e);
+        }
+    }
+
+    #[cfg_attr(not(feature = "config"), allow(unused_variables))]
+    fn report(&mut self, flag: &str, values: &[&str], e: String) {
+        #[cfg(feature = "config")]
+        if let Some((path, content, entries)) = &self.file {
+            let entry = entries.iter().find(|entry| {
+                entry.key == flag && !values.is_empty() && values.iter().all(|v| entry.values.iter().any(|value| value == v))
+            });
+            if let Some(entry) = entry {
+                self.errors.push(config_file::error_at(path, content, entry, &format!("{}: {}", flag, e)));
+                return;
+            }
+        }
+        self.errors.push(format!("--{}: {}", flag, e));
+    }
+}
+
+/// 不在第一个错误处退出，返回发现的全部问题；build 里会失败的解析都要在这里检查一遍，
+/// 但不能有副作用：不打开存储、不创建文件和目录、不启动后台任务。解密后的值写回 `args`
+pub async fn check_config(args: &mut Args) -> Vec<String> {
+    let mut problems = Problems::new(args);
+    #[cfg_attr(not(feature = "secrets"), allow(unused_mut))]
+    let mut tokens = client_tokens(args).unwrap_or_else(|e| {
+        problems.push(e);
+        Vec::new()
+    });
+    #[cfg(feature = "secrets")]
+    if let Err(e) = decrypt_args(args, &mut tokens) {
+        problems.push(e);
+    }
+    let args = &*args;
+    if !tokens.is_empty() {
+        if let Err(e) = ClientTokens::new(tokens) {
+            problems.push(format!("client tokens: {}", e));
+        }
+    }
+
+    #[cfg(feature = "tls")]
+    let tls = args.tls_cert.is_some();
+    #[cfg(not(feature = "tls"))]
+    let tls = false;
+    for addr in args.listen.iter().map(|addr| addr.trim()).filter(|addr| !addr.starts_with("unix:")) {
+        if addr.starts_with("https://") && !tls {
+            problems.push(format!("--listen {}: https:// listen addresses need --tls-cert and --tls-key", addr));
+        }
+        let bare = addr.trim_start_matches("https://").trim_start_matches("http://");
+        problems.note("listen", addr, bare.parse::<SocketAddr>().map_err(|e| format!("{}: {}", addr, e)));
+    }
+    #[cfg(feature = "tls")]
+    if let (Some(cert), Some(key)) = (&args.tls_cert, &args.tls_key) {
+        problems.note("tls-cert", cert, crate::check_certificate(cert.as_ref(), key.as_ref()));
+    }
+    for path in args.ca_cert.iter().chain(&args.upstream_client_cert).chain(&args.upstream_client_key) {
+        if let Err(e) = std::fs::read(path) {
+            problems.push(format!("{}: {}", path, e));
+        }
+    }
+    for proxy in &args.proxy {
+        // 解密失败的已经报告过了
+        #[cfg(feature = "secrets")]
+        if SecretDecryptor::is_encrypted(proxy) {
+            continue;
+        }
+        problems.note("proxy", proxy, egress::parse(proxy, args.proxy_auth.as_deref()));
+    }
+    problems.note("storage", &args.storage, storage::check(&args.storage));
+    if let Some(secret) = &args.signing_secret {
+        problems.note("signing-secret", secret, UrlSigner::check_secret(secret));
+    }
+    if let Some(target) = &args.access_log {
+        problems.note("access-log", target, AccessLog::check(target));
+    }
+    if let Some(target) = &args.audit_log {
+        let config = AuditConfig {
+            target: target.clone(),
+            mode: args.audit_mode,
+            salt: args.audit_salt.clone(),
+            max_size: args.audit_max_size,
+            max_files: args.audit_max_files,
+        };
+        problems.note("audit-log", target, AuditLog::check(&config));
+    }
+
+    for spec in &args.target {
+        problems.note("target", spec, WeightedTarget::parse(spec));
+    }
+    for (i, spec) in args.routes.iter().enumerate() {
+        problems.note("route", spec, RouteRule::parse(spec, i));
+    }
+    for spec in &args.providers {
+        problems.note("provider", spec, Provider::parse(spec));
+    }
+    let groups = args
+        .key_groups
+        .iter()
+        .filter_map(|spec| {
+            let group = key_pool::parse_group(spec);
+            let ok = group.as_ref().ok().cloned();
+            problems.note("key-group", spec, group);
+            ok
+        })
+        .collect::<HashMap<_, _>>();
+    let keys = match key_source(args) {
+        Ok(source) => source.load().await,
+        Err(e) => Err(e),
+    };
+    match keys {
+        Ok(keys) if !keys.is_empty() => {
+            match KeyPool::new(keys, args.key_injection).and_then(|pool| pool.with_groups(groups)) {
+                Ok(_) => {}
+                Err(e) => problems.push(format!("Invalid key pool: {}", e)),
+            }
+        }
+        Ok(_) => {}
+        Err(e) => problems.push(format!("Invalid key pool: {}", e)),
+    }
+    if let Some(name) = &args.key_affinity_header {
+        problems.note("key-affinity-header", name, reqwest::header::HeaderName::from_bytes(name.trim().as_bytes()).map_err(|e| e.to_string()));
+    }
+    if let Some(name) = args.queue_priority_header.as_deref().map(str::trim).filter(|name| !name.is_empty()) {
+        problems.note("queue-priority-header", name, reqwest::header::HeaderName::from_bytes(name.as_bytes()).map_err(|e| e.to_string()));
+    }
+    for spec in &args.queue_priorities {
+        problems.note("queue-priority", spec, QueueConfig::parse_priority(spec));
+    }
+
+    for spec in &args.rewrites {
+        problems.note("rewrite", spec, RewriteRule::parse(spec));
+    }
+    for spec in &args.header_rules {
+        problems.note("header-rule", spec, HeaderRule::parse(spec));
+    }
+    if !args.cors_origin.is_empty() {
+        let cors = Cors::new(CorsConfig {
+            origins: args.cors_origin.clone(),
+            methods: args.cors_methods.clone(),
+            headers: args.cors_headers.clone(),
+            expose_headers: args.cors_expose_headers.clone(),
+            max_age_secs: args.cors_max_age_secs,
+            allow_credentials: args.cors_allow_credentials,
+        });
+        problems.note_all("cors-origin", &args.cors_origin, cors);
+    }
+    if !args.allow_paths.is_empty() {
+        problems.note_all("allow-path", &args.allow_paths, PathAllowlist::new(args.allow_paths.iter().cloned()));
+    }
+    #[cfg(feature = "geoip")]
+    if let Some(path) = &args.geoip_db {
+        problems.note("geoip-db", path, GeoIp::open(path, &args.geoip_allow, &args.geoip_deny));
+    }
+    for spec in &args.tenants {
+        problems.note("tenant", spec, Tenant::parse(spec));
+    }
+    for spec in &args.rate_limits {
+        problems.note("rate-limit", spec, RateLimit::parse(spec));
+    }
+    for spec in &args.quotas {
+        problems.note("quota", spec, Quota::parse(spec));
+    }
+    for spec in &args.bandwidth_limits {
+        problems.note("bandwidth-limit", spec, BandwidthLimit::parse(spec));
+    }
+    for spec in &args.schedules {
+        problems.note("schedule", spec, Schedule::parse(spec));
+    }
+    if let Some(path) = &args.price_table {
+        problems.note("price-table", path, PriceTable::load(path));
+    }
+    for spec in &args.model_aliases {
+        problems.note("model-alias", spec, ModelAlias::parse(spec));
+    }
+    for spec in &args.budgets {
+        problems.note("budget", spec, Budget::parse(spec));
+    }
+    if let Some(header) = &args.metering_auth {
+        problems.note("metering-auth", header, MeteringConfig::parse_header(header));
+    }
+    for spec in &args.cache_attach {
+        problems.note("cache-attach", spec, CacheAttach::parse(spec));
+    }
+    let mut templates = ErrorTemplates::new();
+    for spec in &args.error_templates {
+        problems.note("error-template", spec, templates.load_spec(spec));
+    }
+    #[cfg(feature = "schema")]
+    for spec in &args.request_schemas {
+        problems.note("request-schema", spec, RequestValidator::new().schema(spec));
+    }
+    problems.note_all("model-map", &args.model_map, ModelMap::parse(&args.model_map));
+    problems.note_all("model-fallback", &args.model_fallbacks, ModelFallbacks::parse(&args.model_fallbacks));
+    problems.note_all(
+        "generation-policy",
+        &args.generation_policy,
+        GenerationPolicy::parse(&args.generation_policy, &args.force_safety),
+    );
+    problems.note_all("system-prompt", &args.system_prompts, SystemPrompt::parse(&args.system_prompts, args.system_prompt_mode));
+    problems.note_all("redact", &args.redact, BodyRedactor::parse(&args.redact));
+    problems.note_all("stream-transform", &args.stream_transforms, StreamTransform::parse(&args.stream_transforms));
+    problems.note_all(
+        "strip-response-field",
+        &args.strip_response_fields,
+        ResponseFilter::parse(&args.strip_response_fields),
+    );
+
+    #[cfg(feature = "wasm")]
+    for spec in &args.wasm_plugins {
+        problems.note("wasm-plugin", spec, crate::wasm_plugin::WasmPlugin::from_spec(spec));
+    }
+    #[cfg(feature = "scripting")]
+    for path in &args.scripts {
+        problems.note("script", path, crate::script_plugin::ScriptPlugin::load(path));
+    }
+    #[cfg(feature = "vertex")]
+    if let Some(path) = &args.vertex_credentials {
+        let config = VertexConfig {
+            project: args.vertex_project.clone(),
+            location: args.vertex_location.clone(),
+            proxy: args.proxy.first().cloned(),
+            proxy_auth: args.proxy_auth.clone(),
+            ..VertexConfig::new(path.clone())
+        };
+        problems.note("vertex-credentials", path, VertexAuth::new(config));
+    }
+    #[cfg(feature = "devtools")]
+    {
+        if args.mock_upstream {
+            let status = StatusCode::from_u16(args.mock_error_status).map_err(|e| e.to_string());
+            problems.note("mock-error-status", &args.mock_error_status.to_string(), status);
+        }
+        if let Some(dir) = &args.replay {
+            problems.note("replay", dir, record::load_recordings(dir.as_ref()));
+        }
+        if let Some(dir) = &args.record {
+            problems.note("record", dir, Recorder::check(dir.as_ref()));
+        }
+        for spec in &args.chaos {
+            problems.note("chaos", spec, ChaosRule::parse(spec));
+        }
+        if let Some(path) = &args.canned {
+            problems.note("canned", path, CannedResponses::load(path));
+        }
+    }
+    problems.errors
+}
+
+fn client_tokens(args: &Args) -> Result<Vec<ClientToken>, String> {
+    let mut tokens = match &args.client_tokens_file {
+        Some(path) => {
+            let content = std::fs::read_to_string(path).map_err(|e| format!("--client-tokens-file: {}: {}", path, e))?;
+            ClientTokens::parse_file(&content).map_err(|e| format!("--client-tokens-file: {}", e))?
+        }
+        None => Vec::new(),
+    };
+    for spec in &args.client_tokens {
+        tokens.push(ClientToken::parse(spec).map_err(|e| format!("--client-token: {}", e))?);
+    }
+    Ok(tokens)
+}
+
+/// 解密敏感参数 (代理地址里可能带账号密码)
+#[cfg(feature = "secrets")]
+fn decrypt_args(args: &mut Args, client_tokens: &mut [ClientToken]) -> Result<(), String> {
+    let decryptor = SecretDecryptor::new(args.age_identity.as_deref(), args.age_passphrase.as_deref())
+        .map_err(|e| format!("Failed to load age identities: {}", e))?;
+    let failed = |flag: &'static str| move |e: String| format!("Failed to decrypt --{}: {}", flag, e);
+    for proxy in &mut args.proxy {
+        *proxy = decryptor.decrypt_value(proxy).map_err(failed("proxy"))?;
+    }
+    // 只解密 --provider 里的 key=
+    for spec in &mut args.providers {
+        let parts = spec
+            .split(';')
+            .map(|part| match part.trim().strip_prefix("key=") {
+                Some(key) => decryptor.decrypt_value(key).map(|key| format!("key={}", key)).map_err(failed("provider key")),
+                None => Ok(part.to_string()),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        *spec = parts.join(";");
+    }
+    decryptor.decrypt_opt(&mut args.proxy_auth).map_err(failed("proxy-auth"))?;
+    decryptor.decrypt_opt(&mut args.signing_secret).map_err(failed("signing-secret"))?;
+    decryptor.decrypt_opt(&mut args.metering_auth).map_err(failed("metering-auth"))?;
+    decryptor.decrypt_opt(&mut args.audit_salt).map_err(failed("audit-salt"))?;
+    decryptor.decrypt_opt(&mut args.mirror_key).map_err(failed("mirror-key"))?;
+    decryptor.decrypt_opt(&mut args.keys_url_auth).map_err(failed("keys-url-auth"))?;
+    #[cfg(feature = "admin")]
+    decryptor.decrypt_opt(&mut args.admin_token).map_err(failed("admin-token"))?;
+    for client in client_tokens {
+        client.token = decryptor.decrypt_value(&client.token).map_err(failed("client token"))?;
+    }
+    Ok(())
+}
+
+/// key 池：文件、地址和 --keys 合在一起解析，没起名的 key 按顺序编号；之后按间隔重新读取文件和地址
+fn key_source(args: &Args) -> Result<KeySource, String> {
+    let mut source = KeySource {
+        file: args.keys_file.clone(),
+        url: args.keys_url.clone(),
+        url_authorization: args.keys_url_auth.clone(),
+        inline: args.keys.clone(),
+        interval: Duration::from_secs(args.keys_reload_secs),
+        ..KeySource::default()
+    };
+    // 启动时网关的客户端还没建好，先按同样的 CA 证书、代理设置单独建一个
+    if args.keys_url.is_some() {
+        source.client = Some(key_source_client(args).map_err(|e| format!("--keys-url: {}", e))?);
+    }
+    // 每次重新读取时解密 ENC[...]；age 的身份不能跨线程共享，按需重新加载
+    #[cfg(feature = "secrets")]
+    {
+        let (identity, passphrase) = (args.age_identity.clone(), args.age_passphrase.clone());
+        source.decrypt = Some(Arc::new(move |value: &str| {
+            SecretDecryptor::new(identity.as_deref(), passphrase.as_deref())?.decrypt_value(value)
+        }));
+    }
+    Ok(source)
+}
+
+fn key_source_client(args: &Args) -> Result<reqwest::Client, String> {
+    let mut client = reqwest::Client::builder().connect_timeout(Duration::from_secs(args.connect_timeout));
+    for path in &args.ca_cert {
+        let pem = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        for cert in reqwest::Certificate::from_pem_bundle(&pem).map_err(|e| format!("{}: invalid CA bundle: {}", path, e))? {
+            client = client.add_root_certificate(cert);
+        }
+    }
+    if let Some(proxy) = args.proxy.first() {
+        client = client.proxy(egress::parse(proxy, args.proxy_auth.as_deref())?);
+    }
+    if args.insecure {
+        client = client.danger_accept_invalid_certs(true);
+    }
+    client.build().map_err(|e| e.to_string())
+}
+
+fn upstream_http(args: &Args) -> UpstreamHttpConfig {
+    UpstreamHttpConfig {
+        protocol: args.upstream_protocol,
+        initial_stream_window: args.upstream_h2_stream_window,
+        initial_connection_window: args.upstream_h2_connection_window,
+        adaptive_window: args.upstream_h2_adaptive_window,
+        max_frame_size: args.upstream_h2_max_frame_size,
+        keep_alive_interval: args.upstream_h2_keepalive_secs.map(Duration::from_secs),
+    }
+}
+
+fn dns_config(args: &Args) -> DnsConfig {
+    DnsConfig {
+        #[cfg(feature = "dns")]
+        servers: args.dns_servers.clone(),
+        #[cfg(not(feature = "dns"))]
+        servers: Vec::new(),
+        overrides: args.resolve.clone(),
+        strategy: args.ip_strategy,
+        happy_eyeballs: args.happy_eyeballs_ms.map(Duration::from_millis),
+    }
+}
+
+/// 按参数组装网关；只打印签名 URL 时返回 None。热重载时传入 `reuse`
+pub async fn build(
+    #[cfg_attr(not(any(feature = "secrets", feature = "devtools")), allow(unused_mut))] mut args: Args,
+    reuse: Option<Reuse>,
+) -> Result<Option<Gateway>, String> {
+    #[cfg_attr(not(feature = "secrets"), allow(unused_mut))]
+    let mut client_tokens = client_tokens(&args)?;
+    #[cfg(feature = "secrets")]
+    decrypt_args(&mut args, &mut client_tokens)?;
+    let key_source = key_source(&args)?;
+    let pool_keys = key_source.load().await.map_err(|e| format!("Invalid key pool: {}", e))?;
+
+    let signer = args
+        .signing_secret
+        .as_ref()
+        .map(|secret| {
+            UrlSigner::check_secret(secret).map_err(|e| format!("Invalid --signing-secret: {}", e))?;
+            Ok::<_, String>(UrlSigner::new(secret, args.signed_url_required))
+        })
+        .transpose()?;
+
+    if let (Some(path), Some(signer)) = (&args.sign_url, &signer) {
+        println!("{}", signer.signed_path(path, args.sign_ttl));
+        return Ok(None);
+    }
+
+    // 启动时初始化日志，热重载时已经初始化过
+    // 日志过滤器只作用于 fmt 层，链路追踪的 span 由 otel 层单独过滤；过滤器可以通过管理 API 在运行时替换
+    if reuse.is_none() {
+        let filter = tracing_subscriber::EnvFilter::new(args.log_level.clone());
+        #[cfg_attr(not(feature = "admin"), allow(unused_variables))]
+        let (filter, handle) = tracing_subscriber::reload::Layer::new(filter);
+        #[cfg(feature = "admin")]
+        let _ = LOG_LEVEL.set(LogLevelControl::new(args.log_level.clone(), move |level| {
+            let filter = tracing_subscriber::EnvFilter::try_new(level).map_err(|e| format!("invalid log level '{}': {}", level, e))?;
+            handle.reload(filter).map_err(|e| e.to_string())
+        }));
+        let registry = tracing_subscriber::registry().with(tracing_subscriber::fmt::layer().with_filter(filter));
+        #[cfg(feature = "otel")]
+        let registry = registry.with(
+            args.otlp_endpoint
+                .as_ref()
+                .map(|endpoint| {
+                    let (layer, provider) =
+                        otel::layer(endpoint, &args.otlp_service_name).map_err(|e| format!("--otlp-endpoint: {}", e))?;
+                    opentelemetry::global::set_tracer_provider(provider);
+                    Ok::<_, String>(layer)
+                })
+                .transpose()?,
+        );
+        registry.init();
+        #[cfg(feature = "otel")]
+        if let Some(endpoint) = &args.otlp_endpoint {
+            info!("🔭 Exporting traces to {}", endpoint);
+        }
+
+        info!("🚀 Aizasy Gateway Starting...");
+    }
+
+    // mock / 回放上游只在启动时拉起一次
+    #[cfg(feature = "devtools")]
+    if let Some(reuse) = reuse.as_ref().filter(|_| args.mock_upstream || args.replay.is_some()) {
+        args.target = vec![reuse.target.clone()];
+    }
+
+    #[cfg(feature = "devtools")]
+    if args.mock_upstream && reuse.is_none() {
+        let config = MockConfig {
+            latency: Duration::from_millis(args.mock_latency_ms),
+            chunks: args.mock_chunks,
+            chunk_interval: Duration::from_millis(args.mock_chunk_interval_ms),
+            error_rate: args.mock_error_rate,
+            error_status: StatusCode::from_u16(args.mock_error_status).map_err(|e| format!("Invalid --mock-error-status: {}", e))?,
+            drop_after: args.mock_drop_after,
+        };
+        let addr = mock::spawn(config).await.map_err(|e| format!("Failed to start mock upstream: {}", e))?;
+        info!("🎭 Mock upstream on {}", addr);
+        args.target = vec![format!("http://{}", addr)];
+    }
+
+    #[cfg(feature = "devtools")]
+    if let Some(dir) = args.replay.as_ref().filter(|_| reuse.is_none()) {
+        let addr = record::spawn_replay(dir.as_ref(), !args.replay_fast)
+            .await
+            .map_err(|e| format!("Failed to start replay upstream: {}", e))?;
+        info!("📼 Replaying {} on {}", dir, addr);
+        args.target = vec![format!("http://{}", addr)];
+    }
+
+    #[cfg(feature = "vertex")]
+    let vertex = args.vertex_credentials.as_ref().map(|path| {
+        if !pool_keys.is_empty() {
+            return Err("--vertex-credentials cannot be combined with --keys".to_string());
+        }
+        let config = VertexConfig {
+            project: args.vertex_project.clone(),
+            location: args.vertex_location.clone(),
+            proxy: args.proxy.first().cloned(),
+            proxy_auth: args.proxy_auth.clone(),
+            ..VertexConfig::new(path.clone())
+        };
+        if args.target == [DEFAULT_TARGET] {
+            args.target = vec![config.endpoint()];
+        }
+        VertexAuth::new(config).map_err(|e| format!("--vertex-credentials: {}", e))
+    })
+    .transpose()?;
+
+    let target_policy = TargetPolicy {
+        require_https: !args.dynamic_target_allow_http,
+        allow_private: args.dynamic_target_allow_private,
+        allowed_hosts: args.dynamic_target_hosts.clone(),
+    };
+    let targets: Vec<WeightedTarget> = args
+        .target
+        .iter()
+        .map(|spec| WeightedTarget::parse(spec).map_err(|e| format!("Invalid --target: {}", e)))
+        .collect::<Result<_, _>>()?;
+    if targets.len() > 1 {
+        for target in &targets {
+            info!("🌐 Upstream {} (weight {})", target.url, target.weight);
+        }
+    }
+    // 启动参数里的 target 由运维直接指定，不强制策略，只提示
+    for target in &targets {
+        if let Err(e) = target_policy.check(&target.url).await {
+            warn!("⚠️  Target {} would be rejected by the dynamic target policy: {}", target.url, e);
+        }
+    }
+
+    #[cfg(feature = "geoip")]
+    let geoip = args.geoip_db.as_ref().map(|path| {
+        let db = GeoIp::open(path, &args.geoip_allow, &args.geoip_deny).map_err(|e| format!("Failed to open GeoIP database: {}", e))?;
+        info!("🌍 GeoIP: {} (allow: {:?}, deny: {:?})", path, args.geoip_allow, args.geoip_deny);
+        Ok::<_, String>(db)
+    })
+    .transpose()?;
+
+    let scanner = args.scanner_ban.then(|| {
+        info!("🪤 Scanner auto-ban: {}s, tarpit {}ms", args.scanner_ban_secs, args.scanner_tarpit_ms);
+        ScannerGuard::new(
+            &args.scanner_paths,
+            Duration::from_secs(args.scanner_ban_secs),
+            Duration::from_millis(args.scanner_tarpit_ms),
+        )
+    });
+
+    if signer.is_some() {
+        info!("🔏 Signed URLs enabled (required: {})", args.signed_url_required);
+    }
+
+    let lockout = args.auth_lockout.then(|| {
+        info!(
+            "🔒 Auth lockout: after {} failures, {}s..{}s",
+            args.auth_lockout_threshold, args.auth_lockout_base_secs, args.auth_lockout_max_secs
+        );
+        AuthLockout::new(
+            args.auth_lockout_threshold,
+            Duration::from_secs(args.auth_lockout_base_secs),
+            Duration::from_secs(args.auth_lockout_max_secs),
+        )
+    });
+
+    let burst = args.burst_guard.then(|| {
+        info!(
+            "🚫 Burst guard: {} requests / {} auth failures, per {}s, delay {}ms..{}ms, ban after {} for {}s",
+            args.burst_requests,
+            args.burst_auth_failures,
+            args.burst_window_secs,
+            args.burst_delay_ms,
+            args.burst_max_delay_ms,
+            args.burst_ban_after,
+            args.burst_ban_secs
+        );
+        BurstGuard::new(BurstConfig {
+            window: Duration::from_secs(args.burst_window_secs.max(1)),
+            requests: args.burst_requests,
+            auth_failures: args.burst_auth_failures,
+            delay: Duration::from_millis(args.burst_delay_ms),
+            max_delay: Duration::from_millis(args.burst_max_delay_ms),
+            ban_after: args.burst_ban_after,
+            ban: Duration::from_secs(args.burst_ban_secs),
+            exempt: args.burst_exempt.clone(),
+        })
+    });
+
+    #[cfg(feature = "tls")]
+    let tls = args.tls_cert.is_some();
+    #[cfg(not(feature = "tls"))]
+    let tls = false;
+    let security_headers = args.security_headers.then(|| {
+        info!("🛡️  Security headers enabled");
+        // 只有网关自己终结 TLS 时才下发 HSTS
+        SecurityHeaders::new(tls, args.hsts_max_age, &args.security_headers_skip)
+    });
+
+    let mut error_templates = ErrorTemplates::new();
+    for spec in &args.error_templates {
+        error_templates.load_spec(spec).map_err(|e| format!("Invalid --error-template: {}", e))?;
+    }
+    if !error_templates.is_empty() {
+        info!("🧾 Error templates: {}", args.error_templates.len());
+    }
+
+    let tenants = (!args.tenants.is_empty()).then(|| {
+        let tenants: Vec<Tenant> = args
+            .tenants
+            .iter()
+            .map(|spec| Tenant::parse(spec).map_err(|e| format!("Invalid --tenant: {}", e)))
+            .collect::<Result<_, _>>()?;
+        for tenant in &tenants {
+            let mut scope = Vec::new();
+            if let Some(target) = &tenant.target {
+                scope.push(format!("-> {}", target));
+            }
+            if let Some(group) = &tenant.key_group {
+                scope.push(format!("keys {}", group));
+            }
+            if !tenant.clients.is_empty() {
+                scope.push(format!("clients {}", tenant.clients.join(",")));
+            }
+            info!("🏢 Tenant {} {}", tenant.name, scope.join(", "));
+        }
+        Ok::<_, String>(Tenants::new(tenants, args.tenant_required))
+    })
+    .transpose()?;
+
+    let storage = match &reuse {
+        Some(reuse) => reuse.storage.clone(),
+        None => storage::open(&args.storage).await.map_err(|e| format!("Failed to open storage backend: {}", e))?,
+    };
+    info!("🗄️  Storage: {}", storage.name());
+
+    let (unix_paths, addrs): (Vec<&str>, Vec<&str>) = args
+        .listen
+        .iter()
+        .map(|addr| addr.trim())
+        .partition(|addr| addr.starts_with("unix:"));
+    // http:// 前缀的地址不加密，https:// 和不带前缀的一样跟随 --tls-cert
+    #[cfg(feature = "tls")]
+    let tls_enabled = args.tls_cert.is_some();
+    #[cfg(not(feature = "tls"))]
+    let tls_enabled = false;
+    if !tls_enabled && addrs.iter().any(|addr| addr.starts_with("https://")) {
+        return Err("https:// listen addresses need --tls-cert and --tls-key".to_string());
+    }
+    let (plain_addrs, addrs): (Vec<&str>, Vec<&str>) = addrs.into_iter().partition(|addr| addr.starts_with("http://"));
+    let parse_addr = |addr: &str| -> Result<SocketAddr, String> { addr.parse().map_err(|e| format!("Invalid listen address {}: {}", addr, e)) };
+    let addrs: Vec<SocketAddr> = addrs
+        .into_iter()
+        .map(|addr| parse_addr(addr.trim_start_matches("https://")))
+        .collect::<Result<_, _>>()?;
+
+    let mut builder = Gateway::builder()
+        .targets(targets)
+        .target_cooldown(Duration::from_secs(args.target_cooldown_secs))
+        .target_balancing(args.target_balancing)
+        .listen_all(addrs)
+        .unix_socket_mode(args.unix_socket_mode)
+        .insecure(args.insecure)
+        .storage(storage)
+        .target_policy(target_policy)
+        .drain_delay(Duration::from_secs(args.drain_delay_secs))
+        .drain_timeout(Duration::from_secs(args.drain_timeout_secs));
+    if let Some(reuse) = &reuse {
+        builder = builder.drain(reuse.drain.clone());
+    }
+    if !args.routes.is_empty() {
+        let routes = args
+            .routes
+            .iter()
+            .enumerate()
+            .map(|(i, spec)| RouteRule::parse(spec, i))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("--route: {}", e))?;
+        for route in &routes {
+            info!("🧭 Route {} -> {}{}", route.prefix, route.target, if route.strip_prefix { " (prefix stripped)" } else { "" });
+        }
+        builder = builder.routes(routes);
+    }
+    if let Some(interval) = args.health_check_interval_secs.filter(|s| *s > 0) {
+        info!("🩺 Health checks: GET {} every {}s", args.health_check_path, interval);
+        builder = builder.health_check(HealthCheckConfig {
+            interval: Duration::from_secs(interval),
+            timeout: Duration::from_secs(args.health_check_timeout_secs),
+            path: args.health_check_path.clone(),
+            threshold: args.health_check_threshold,
+        });
+    }
+    #[cfg(feature = "tls")]
+    if let (Some(cert), Some(key)) = (&args.tls_cert, &args.tls_key) {
+        info!("🔐 TLS: {}", cert);
+        builder = builder.tls(cert, key);
+    }
+    #[cfg(feature = "http3")]
+    {
+        builder = builder.http3(args.http3);
+    }
+    for addr in plain_addrs {
+        builder = builder.listen_plain(parse_addr(addr.trim_start_matches("http://"))?);
+    }
+    for path in unix_paths {
+        builder = builder.listen_unix(path.trim_start_matches("unix:"));
+    }
+    for path in &args.ca_cert {
+        builder = builder.ca_cert(path);
+    }
+    if let (Some(cert), Some(key)) = (&args.upstream_client_cert, &args.upstream_client_key) {
+        builder = builder.upstream_client_cert(cert, key);
+    }
+    if !args.proxy.is_empty() {
+        builder = builder.proxies(args.proxy.clone()).proxy_pool(EgressPoolConfig {
+            rotation: args.proxy_rotation,
+            cooldown: Duration::from_secs(args.proxy_cooldown_secs),
+            probe_interval: Duration::from_secs(args.proxy_probe_interval_secs.max(1)),
+            ..EgressPoolConfig::default()
+        });
+    }
+    if let Some(auth) = &args.proxy_auth {
+        builder = builder.proxy_auth(auth.clone());
+    }
+    if !pool_keys.is_empty() {
+        let failover = FailoverConfig {
+            max_attempts: args.key_max_attempts,
+            rate_limited_cooldown: Duration::from_secs(args.key_cooldown_secs),
+            forbidden_cooldown: Duration::from_secs(args.key_forbidden_cooldown_secs),
+        };
+        let affinity_header = args
+            .key_affinity_header
+            .as_deref()
+            .map(|name| reqwest::header::HeaderName::from_bytes(name.trim().as_bytes()).map_err(|e| format!("Invalid --key-affinity-header: {}", e)))
+            .transpose()?;
+        let pool = KeyPool::new(pool_keys, args.key_injection).map_err(|e| format!("Invalid key pool: {}", e))?;
+        if args.key_selection == KeySelection::Sticky {
+            info!("🔑 Sticky key selection ({})", args.key_affinity_header.as_deref().unwrap_or("by client"));
+        }
+        if args.key_max_concurrency > 0 {
+            info!("🔑 At most {} concurrent requests per key", args.key_max_concurrency);
+        }
+        let pool = pool
+            .with_failover(failover)
+            .with_selection(args.key_selection, affinity_header)
+            .with_max_concurrency(args.key_max_concurrency)
+            .with_quarantine(args.key_quarantine_after);
+        let groups = args
+            .key_groups
+            .iter()
+            .map(|spec| key_pool::parse_group(spec))
+            .collect::<Result<HashMap<_, _>, _>>()
+            .map_err(|e| format!("--key-group: {}", e))?;
+        for (name, keys) in &groups {
+            info!("🔑 Key group {}: {} key(s)", name, keys.len());
+        }
+        let pool = pool.with_groups(groups).map_err(|e| format!("--key-group: {}", e))?;
+        builder = builder.key_pool(pool);
+        if key_source.watchable() {
+            match args.keys_reload_secs {
+                0 => info!("🔑 Key pool loaded from {} (reload via POST /admin/keys/reload)", key_source.describe()),
+                secs => info!("🔑 Watching {} for key changes every {}s", key_source.describe(), secs),
+            }
+            builder = builder.key_source(key_source);
+        }
+        if let Some(url) = &args.key_quarantine_webhook {
+            builder = builder.key_quarantine_webhook(url);
+        }
+        if args.rate_limit_queue > 0 {
+            let priorities = args
+                .queue_priorities
+                .iter()
+                .map(|spec| QueueConfig::parse_priority(spec))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("--queue-priority: {}", e))?;
+            let priority_header = args
+                .queue_priority_header
+                .as_deref()
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(|name| reqwest::header::HeaderName::from_bytes(name.as_bytes()).map_err(|e| format!("Invalid --queue-priority-header: {}", e)))
+                .transpose()?;
+            info!(
+                "⏳ Queueing up to {} requests for {}s while upstream keys are rate limited",
+                args.rate_limit_queue, args.rate_limit_queue_secs
+            );
+            builder = builder.request_queue(QueueConfig {
+                max_queue: args.rate_limit_queue,
+                max_wait: Duration::from_secs(args.rate_limit_queue_secs),
+                priority_header,
+                max_header_priority: args.queue_priority_max,
+                priorities,
+            });
+        }
+    }
+    if let Some(target) = &args.access_log {
+        info!("📝 Access log: {}", target);
+        builder = builder.access_log(AccessLog::open(target).map_err(|e| format!("Failed to open --access-log: {}", e))?);
+    }
+    builder = builder.max_request_size(args.max_request_size as usize);
+    if let Some(limit) = args.max_response_size {
+        builder = builder.max_response_size(limit);
+    }
+    builder = builder
+        .connect_timeout(Duration::from_secs(args.connect_timeout))
+        .upstream_http(upstream_http(&args))
+        .first_byte_timeout(Duration::from_secs(args.first_byte_timeout));
+    if let Some(secs) = args.total_timeout.filter(|secs| *secs > 0) {
+        builder = builder.total_timeout(Duration::from_secs(secs));
+    }
+    if !args.trusted_proxies.is_empty() {
+        builder = builder.trusted_proxies(args.trusted_proxies.clone());
+    }
+    if !args.ip_allow.is_empty() || !args.ip_deny.is_empty() {
+        info!("🧱 IP filter: {} allowed, {} denied range(s)", args.ip_allow.len(), args.ip_deny.len());
+        builder = builder.ip_filter(IpFilter::new(args.ip_allow.clone(), args.ip_deny.clone()));
+    }
+    if !args.rewrites.is_empty() {
+        let rules: Vec<RewriteRule> = args
+            .rewrites
+            .iter()
+            .map(|spec| RewriteRule::parse(spec).map_err(|e| format!("Invalid --rewrite: {}", e)))
+            .collect::<Result<_, _>>()?;
+        info!("✏️  Path rewrite rules: {}", rules.len());
+        builder = builder.path_rewrites(rules);
+    }
+    if !args.header_rules.is_empty() {
+        let rules: Vec<HeaderRule> = args
+            .header_rules
+            .iter()
+            .map(|spec| HeaderRule::parse(spec).map_err(|e| format!("Invalid --header-rule: {}", e)))
+            .collect::<Result<_, _>>()?;
+        info!("🏷️  Header rules: {}", rules.len());
+        builder = builder.header_rules(rules);
+    }
+    if !args.cors_origin.is_empty() {
+        let cors = Cors::new(CorsConfig {
+            origins: args.cors_origin.clone(),
+            methods: args.cors_methods.clone(),
+            headers: args.cors_headers.clone(),
+            expose_headers: args.cors_expose_headers.clone(),
+            max_age_secs: args.cors_max_age_secs,
+            allow_credentials: args.cors_allow_credentials,
+        })
+        .map_err(|e| format!("Invalid CORS configuration: {}", e))?;
+        info!("🌐 CORS origins: {}", args.cors_origin.join(", "));
+        builder = builder.cors(cors);
+    }
+    if args.retries > 0 {
+        builder = builder.retry(RetryConfig {
+            max_retries: args.retries,
+            backoff: Duration::from_millis(args.retry_backoff_ms),
+            max_backoff: Duration::from_millis(args.retry_max_backoff_ms),
+            jitter: args.retry_jitter,
+            max_body: args.retry_max_body as usize,
+        });
+    }
+    if let Some(target) = args.canary.clone() {
+        builder = builder.canary(CanaryConfig {
+            target,
+            percent: args.canary_percent,
+            proxy: args.canary_proxy.clone(),
+            sticky: args.canary_sticky,
+        });
+    }
+    if let Some(target) = args.mirror.clone() {
+        builder = builder.mirror(MirrorConfig {
+            target,
+            percent: args.mirror_percent,
+            proxy: args.mirror_proxy.clone(),
+            timeout: Duration::from_secs(args.mirror_timeout_secs),
+            max_inflight: args.mirror_max_inflight,
+            key: args.mirror_key.clone(),
+        });
+    }
+    if let Some(max_inflight) = args.max_inflight.filter(|n| *n > 0) {
+        info!("🚦 Max in-flight requests: {} (queue {})", max_inflight, args.max_inflight_queue);
+        builder = builder.max_inflight(InflightConfig {
+            max_inflight,
+            queue: args.max_inflight_queue,
+            queue_timeout: Duration::from_secs(args.max_inflight_queue_secs),
+        });
+    }
+    if let Some(secs) = args.stream_idle_timeout_secs.filter(|secs| *secs > 0) {
+        builder = builder.stream_idle_timeout(Duration::from_secs(secs));
+    }
+    builder = builder.accept_encoding(args.accept_encoding);
+    builder = builder.dns(dns_config(&args));
+    if let Some(ms) = args.slow_request_ms.filter(|ms| *ms > 0) {
+        builder = builder.slow_request(Duration::from_millis(ms));
+    }
+    if args.prewarm_connections > 0 {
+        info!("🔥 Pre-warming {} connection(s) per upstream", args.prewarm_connections);
+        builder = builder.prewarm(PrewarmConfig {
+            connections: args.prewarm_connections,
+            idle: Duration::from_secs(args.prewarm_idle_secs.max(1)),
+            timeout: Duration::from_secs(args.connect_timeout),
+        });
+    }
+    if args.upload_session_ttl_secs > 0 {
+        builder = builder.uploads(UploadConfig {
+            ttl: Duration::from_secs(args.upload_session_ttl_secs),
+            public_url: args.public_url.clone(),
+            tls,
+        });
+    }
+    if args.grpc {
+        builder = builder.grpc(GrpcConfig {
+            target: args.grpc_target.trim_end_matches('/').to_string(),
+        });
+    }
+    if let Some(secs) = args.sse_keepalive_secs.filter(|secs| *secs > 0) {
+        builder = builder.sse_keepalive(Duration::from_secs(secs));
+    }
+    #[cfg(feature = "geoip")]
+    if let Some(geoip) = geoip {
+        builder = builder.geoip(geoip);
+    }
+    if let Some(scanner) = scanner {
+        builder = builder.scanner_guard(scanner);
+    }
+    if !args.allow_paths.is_empty() {
+        let allowlist = PathAllowlist::new(args.allow_paths.iter().cloned())
+            .map_err(|e| format!("--allow-path: {}", e))?;
+        info!("🚫 Path allowlist: {}", allowlist.patterns().join(", "));
+        builder = builder.path_allowlist(allowlist);
+    }
+    if let Some(signer) = signer {
+        builder = builder.url_signer(signer);
+    }
+    if let Some(lockout) = lockout {
+        builder = builder.auth_lockout(lockout);
+    }
+    if let Some(burst) = burst {
+        builder = builder.burst_guard(burst);
+    }
+    if !client_tokens.is_empty() {
+        let tokens = ClientTokens::new(client_tokens).map_err(|e| format!("Invalid client tokens: {}", e))?;
+        info!("🎫 Client tokens required: {}", tokens.len());
+        for name in tokens.expired() {
+            warn!("⚠️  Client token {} has already expired", name);
+        }
+        builder = builder.client_tokens(tokens);
+    }
+    if let Some(security_headers) = security_headers {
+        builder = builder.security_headers(security_headers);
+    }
+    #[cfg(feature = "compression")]
+    if !args.compress.is_empty() {
+        let config = CompressionConfig {
+            algorithms: args.compress.clone(),
+            min_size: args.compress_min_size,
+        };
+        info!("🗜️  Compressing responses >= {} bytes: {}", config.min_size, config.names().join(", "));
+        builder = builder.compression(config);
+    }
+    builder = builder.error_templates(error_templates);
+    #[cfg(feature = "admin")]
+    if let Some(token) = &args.admin_token {
+        if args.admin_listen.is_none() {
+            info!("🔑 Admin API enabled at /admin");
+        }
+        builder = builder.admin_token(token.clone());
+    }
+    #[cfg(feature = "admin")]
+    if let Some(addr) = args.admin_listen {
+        if args.admin_token.is_none() {
+            warn!("⚠️  Admin API on {} has no --admin-token, anyone who can reach it has full control", addr);
+        }
+        builder = builder.admin_listen(addr);
+    }
+    #[cfg(feature = "admin")]
+    if args.dashboard {
+        match (args.admin_listen, &args.admin_token) {
+            (Some(addr), _) => info!("📊 Dashboard at http://{}/admin/dashboard", addr),
+            (None, Some(_)) => info!("📊 Dashboard at /admin/dashboard"),
+            (None, None) => warn!("⚠️  --dashboard needs --admin-token or --admin-listen to be reachable"),
+        }
+        builder = builder.dashboard();
+    }
+    #[cfg(feature = "metrics")]
+    if args.metrics_public {
+        warn!("⚠️  /metrics is public on the main listener, anyone who can reach it sees key and client labels");
+        builder = builder.metrics_public();
+    } else {
+        #[cfg(feature = "admin")]
+        match (args.admin_listen, &args.admin_token) {
+            (Some(addr), _) => info!("📈 Metrics at http://{}/metrics", addr),
+            (None, Some(_)) => info!("📈 Metrics at /metrics (admin token required)"),
+            (None, None) => warn!("⚠️  /metrics needs --admin-token, --admin-listen or --metrics-public to be reachable"),
+        }
+        #[cfg(not(feature = "admin"))]
+        warn!("⚠️  /metrics needs --metrics-public to be reachable in builds without the admin API");
+    }
+    #[cfg(feature = "admin")]
+    if let Some(control) = LOG_LEVEL.get() {
+        builder = builder.log_level_control(control.clone());
+    }
+    if let Some(tenants) = tenants {
+        builder = builder.tenants(tenants);
+    }
+    let mut maintenance = MaintenanceState {
+        enabled: args.maintenance,
+        retry_after_secs: args.maintenance_retry_after_secs,
+        ..Default::default()
+    };
+    if let Some(message) = &args.maintenance_message {
+        maintenance.message = message.clone();
+    }
+    if maintenance.enabled {
+        warn!("🚧 Starting in maintenance mode");
+    }
+    builder = builder.maintenance(maintenance);
+    if args.batch {
+        info!("📦 Batch endpoint: up to {} requests, {} in flight", args.batch_max_requests, args.batch_concurrency);
+        builder = builder.batch(BatchConfig {
+            max_requests: args.batch_max_requests,
+            concurrency: args.batch_concurrency,
+        });
+    }
+    if args.coalesce_get {
+        info!("🪢 Coalescing identical concurrent GET requests");
+        builder = builder.coalesce_get(true);
+    }
+    if args.openai_compat {
+        info!("🔁 OpenAI-compatible endpoints enabled");
+        builder = builder.openai_compat(true);
+    }
+    for spec in &args.providers {
+        let provider = Provider::parse(spec).map_err(|e| format!("--provider: {}", e))?;
+        info!("🌐 Provider {} -> {} ({})", provider.name, provider.url, provider.models.join(", "));
+        builder = builder.provider(provider);
+    }
+    if args.inspector > 0 {
+        info!("🔎 Request inspector keeps the last {} requests", args.inspector);
+        builder = builder.inspector(Arc::new(RequestInspector::new(args.inspector)));
+    }
+    if args.failure_log > 0 {
+        info!("🔁 Keeping the last {} failed requests", args.failure_log);
+        builder = builder.failure_log(Arc::new(FailureLog::new(args.failure_log)));
+    }
+    if !args.projects.is_empty() {
+        info!("🏷️ Projects: {}", args.projects.join(", "));
+        builder = builder.projects(Projects::new(args.projects.clone(), args.project_required));
+    }
+
+    let prices = match &args.price_table {
+        Some(path) => PriceTable::load(path).map_err(|e| format!("Failed to load --price-table: {}", e))?,
+        None => PriceTable::default(),
+    };
+    #[cfg_attr(not(feature = "schema"), allow(unused_mut))]
+    let mut validator = RequestValidator::new().gemini(args.validate_requests);
+    #[cfg(feature = "schema")]
+    for spec in &args.request_schemas {
+        validator = validator.schema(spec).map_err(|e| format!("Invalid --request-schema: {}", e))?;
+    }
+    if !validator.is_empty() {
+        info!("🧾 Request validation enabled");
+        builder = builder.plugin(validator);
+    }
+    let policy = GenerationPolicy::parse(&args.generation_policy, &args.force_safety).map_err(|e| format!("Invalid generation policy: {}", e))?;
+    if !policy.is_empty() {
+        info!("📏 Generation policy rules: {}", policy.len());
+        builder = builder.plugin(policy);
+    }
+    if !args.system_prompts.is_empty() {
+        let prompt = SystemPrompt::parse(&args.system_prompts, args.system_prompt_mode)
+            .map_err(|e| format!("--system-prompt: {}", e))?;
+        info!("🧭 System prompt rules: {} ({})", prompt.len(), prompt.mode().name());
+        builder = builder.plugin(prompt);
+    }
+    if !args.redact.is_empty() {
+        let redactor = BodyRedactor::parse(&args.redact).map_err(|e| format!("Invalid --redact: {}", e))?;
+        info!("🕶️  Request body redaction rules: {}", redactor.len());
+        builder = builder.plugin(redactor);
+    }
+    if !args.stream_transforms.is_empty() {
+        let transform = StreamTransform::parse(&args.stream_transforms).map_err(|e| format!("--stream-transform: {}", e))?;
+        info!("🌊 Streaming response transforms: {}", transform.len());
+        builder = builder.plugin(transform);
+    }
+    if !args.strip_response_fields.is_empty() {
+        let filter = ResponseFilter::parse(&args.strip_response_fields)
+            .map_err(|e| format!("--strip-response-field: {}", e))?;
+        info!("✂️  Stripping {} fields from responses", filter.len());
+        builder = builder.plugin(filter);
+    }
+    if !args.model_map.is_empty() {
+        let map = ModelMap::parse(&args.model_map).map_err(|e| format!("Invalid --model-map: {}", e))?;
+        info!("🗺️  Model mappings: {}", map.len());
+        builder = builder.plugin(map);
+    }
+    if !args.model_fallbacks.is_empty() {
+        let fallbacks = ModelFallbacks::parse(&args.model_fallbacks).map_err(|e| format!("Invalid --model-fallback: {}", e))?;
+        info!("🪂 Model fallback chains: {}", fallbacks.len());
+        builder = builder.model_fallbacks(fallbacks);
+    }
+    if !args.model_aliases.is_empty() {
+        let aliases: Vec<ModelAlias> = args
+            .model_aliases
+            .iter()
+            .map(|spec| ModelAlias::parse(spec).map_err(|e| format!("Invalid --model-alias: {}", e)))
+            .collect::<Result<_, _>>()?;
+        info!("🧭 Cost-aware model aliases: {}", aliases.len());
+        let cooldown = Duration::from_secs(args.model_cooldown_secs);
+        builder = builder.plugin(CostRouter::new(aliases, prices.clone(), cooldown));
+    }
+
+    if !args.quotas.is_empty() {
+        let quotas: Vec<Quota> = args
+            .quotas
+            .iter()
+            .map(|spec| Quota::parse(spec).map_err(|e| format!("Invalid --quota: {}", e)))
+            .collect::<Result<_, _>>()?;
+        info!("⏳ Client quotas: {}", quotas.len());
+        let limiter = QuotaLimiter::new(quotas, builder.storage_handle());
+        builder = builder.plugin(limiter);
+    }
+    if !args.rate_limits.is_empty() {
+        let limits: Vec<RateLimit> = args
+            .rate_limits
+            .iter()
+            .map(|spec| RateLimit::parse(spec).map_err(|e| format!("Invalid --rate-limit: {}", e)))
+            .collect::<Result<_, _>>()?;
+        info!("🚦 Rate limits: {}", limits.len());
+        let limiter = RateLimiter::new(limits, builder.storage_handle());
+        builder = builder.plugin(limiter);
+    }
+    if !args.bandwidth_limits.is_empty() {
+        let limits: Vec<BandwidthLimit> = args
+            .bandwidth_limits
+            .iter()
+            .map(|spec| BandwidthLimit::parse(spec).map_err(|e| format!("Invalid --bandwidth-limit: {}", e)))
+            .collect::<Result<_, _>>()?;
+        info!("🐌 Bandwidth limits: {}", limits.len());
+        builder = builder.bandwidth(Bandwidth::new(limits));
+    }
+    if !args.schedules.is_empty() {
+        let schedules: Vec<Schedule> = args
+            .schedules
+            .iter()
+            .map(|spec| Schedule::parse(spec).map_err(|e| format!("Invalid --schedule: {}", e)))
+            .collect::<Result<_, _>>()?;
+        info!("🕒 Scheduled windows: {}", schedules.len());
+        let guard = ScheduleGuard::new(schedules, builder.storage_handle());
+        builder = builder.plugin(guard);
+    }
+    if args.local_count_tokens {
+        info!("🔢 countTokens answered locally (approximate)");
+        builder = builder.plugin(LocalTokenCounter);
+    }
+    if args.response_cache {
+        info!(
+            "🗃️ Response cache: {} entries, {} MB, ttl {}s{}",
+            args.response_cache_entries,
+            args.response_cache_max_mb,
+            args.response_cache_ttl_secs,
+            if args.response_cache_deterministic { " (+ deterministic generateContent)" } else { "" }
+        );
+        builder = builder.plugin(ResponseCache::new(ResponseCacheConfig {
+            max_entries: args.response_cache_entries.max(1),
+            max_bytes: args.response_cache_max_mb * 1024 * 1024,
+            ttl: Duration::from_secs(args.response_cache_ttl_secs),
+            deterministic: args.response_cache_deterministic,
+        }));
+    }
+
+    if args.ledger {
+        info!("💰 Billing ledger enabled");
+        if builder.storage_handle().name() == "memory" {
+            warn!("💰 Ledger entries live in memory and are lost on restart; use --storage sqlite:PATH to keep them");
+        }
+        let pending = LEDGER_WRITES.get_or_init(PendingWrites::default).clone();
+        let mut ledger = Ledger::new(builder.storage_handle(), prices).pending(pending.clone());
+        if !args.budgets.is_empty() {
+            let budgets: Vec<Budget> = args
+                .budgets
+                .iter()
+                .map(|spec| Budget::parse(spec).map_err(|e| format!("Invalid --budget: {}", e)))
+                .collect::<Result<_, _>>()?;
+            info!("💸 Budgets: {}", budgets.len());
+            let monitor = BudgetMonitor::new(budgets, builder.storage_handle(), args.budget_webhook.clone());
+            ledger = ledger.hook(Arc::new(monitor));
+        }
+        if args.credits {
+            info!("🪙 Prepaid credits enabled ({:?})", args.credit_unit);
+            let credits = Arc::new(CreditAccounts::new(builder.storage_handle(), args.credit_unit));
+            ledger = ledger.hook(credits.clone());
+            builder = builder.credit_accounts(credits);
+        }
+        if let Some(url) = &args.metering_url {
+            let mut config = MeteringConfig::new(url.clone());
+            if let Some(header) = &args.metering_auth {
+                config.auth_header = Some(MeteringConfig::parse_header(header).map_err(|e| format!("Invalid --metering-auth: {}", e))?);
+            }
+            config.batch_size = args.metering_batch_size;
+            config.flush_interval = Duration::from_secs(args.metering_flush_secs.max(1));
+            config.max_retries = args.metering_retries;
+            info!("📡 Metering push to {} (batch {})", url, config.batch_size);
+            ledger = ledger.hook(Arc::new(MeteringPush::start(config)));
+        }
+        builder = builder.plugin(ledger);
+
+        // 导出任务读的是共享存储，热重载时沿用启动时的那个
+        if let Some(target) = args.usage_export.as_ref().filter(|_| reuse.is_none()) {
+            info!("📤 Usage export to {} every {}s", target, args.usage_export_interval_secs);
+            UsageExporter::new(builder.storage_handle(), ExportSink::parse(target), args.usage_export_format)
+                .pending(pending)
+                .spawn(Duration::from_secs(args.usage_export_interval_secs.max(1)));
+        }
+    }
+
+    if args.cached_contents {
+        let attach: Vec<CacheAttach> = args
+            .cache_attach
+            .iter()
+            .map(|spec| CacheAttach::parse(spec).map_err(|e| format!("Invalid --cache-attach: {}", e)))
+            .collect::<Result<_, _>>()?;
+        for rule in &attach {
+            info!("🧊 {} uses {}", rule.model, rule.cache);
+        }
+        info!("🧊 cachedContents tracking enabled (isolation: {})", args.cache_isolation);
+        let caches = Arc::new(CachedContents::new(
+            builder.storage_handle(),
+            attach,
+            args.cache_isolation,
+            args.cache_max_ttl_secs.map(Duration::from_secs),
+        ));
+        if reuse.is_none() {
+            caches.spawn_sweeper(Duration::from_secs(300));
+        }
+        builder = builder.cached_contents(caches);
+    }
+
+    #[cfg(feature = "wasm")]
+    for spec in &args.wasm_plugins {
+        let plugin = crate::wasm_plugin::WasmPlugin::from_spec(spec)
+            .map_err(|e| format!("--wasm-plugin: {}", e))?;
+        info!("🧩 WASM plugin: {}", spec);
+        builder = builder.plugin(plugin);
+    }
+
+    #[cfg(feature = "scripting")]
+    for path in &args.scripts {
+        let script = Arc::new(
+            crate::script_plugin::ScriptPlugin::load(path).map_err(|e| format!("Failed to load script: {}", e))?,
+        );
+        script.watch(Duration::from_secs(2));
+        info!("📜 Script: {}", path);
+        builder = builder.plugin(script);
+    }
+
+    #[cfg(feature = "devtools")]
+    if !args.chaos.is_empty() {
+        let rules: Vec<ChaosRule> = args
+            .chaos
+            .iter()
+            .map(|spec| ChaosRule::parse(spec).map_err(|e| format!("Invalid --chaos rule: {}", e)))
+            .collect::<Result<_, _>>()?;
+        warn!("🐒 Chaos mode: {} rule(s) active", rules.len());
+        builder = builder.plugin(ChaosPlugin::new(rules));
+    }
+
+    #[cfg(feature = "devtools")]
+    if let Some(path) = &args.canned {
+        let canned = CannedResponses::load(path).map_err(|e| format!("Failed to load --canned: {}", e))?;
+        info!("🥫 Canned responses from {}", path);
+        builder = builder.plugin(canned);
+    }
+
+    #[cfg(feature = "devtools")]
+    if let Some(dir) = &args.record {
+        let recorder = Recorder::new(dir).map_err(|e| format!("Failed to create record directory: {}", e))?;
+        info!("📼 Recording traffic to {}", dir);
+        builder = builder.plugin(recorder);
+    }
+
+    // 放在其他插件后面，记录的是最终发往上游的请求体
+    if let Some(target) = &args.audit_log {
+        let audit = AuditLog::open(&AuditConfig {
+            target: target.clone(),
+            mode: args.audit_mode,
+            salt: args.audit_salt.clone(),
+            max_size: args.audit_max_size,
+            max_files: args.audit_max_files,
+        })
+        .map_err(|e| format!("--audit-log: {}", e))?;
+        info!("🧾 Audit log ({}): {}", args.audit_mode.name(), egress::redact(target));
+        builder = builder.plugin(audit);
+    }
+
+    #[cfg(feature = "vertex")]
+    if let Some(vertex) = vertex {
+        info!("🔐 Vertex AI project {} as {}", vertex.project(), vertex.client_email());
+        vertex::preflight(&vertex).await;
+        builder = builder.plugin(vertex);
+    }
+
+    builder.build().map(Some)
+}
\ No newline at end of file
@@ -0,0 +1,237 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+use crate::cached_contents::parse_timestamp;
+use crate::lockout::AuthFailure;
+use crate::sanitize::sanitize_path;
+use crate::signed_url::SignedAccess;
+use crate::tenant::CurrentTenant;
+use crate::usage::ClientIdentity;
+use crate::AppState;
+
+// --- 客户端令牌 ---
+// 网关自己签发给客户端的令牌，和上游 Gemini key 无关。开启后代理路由必须带有效令牌，
+// 否则直接 401，不转发给上游。令牌可以放在 `Authorization: Bearer`、x-goog-api-key 或 ?key= 里
+// (兼容只能填 API key 的 SDK)；校验通过后从请求里去掉，令牌名作为 ClientIdentity 用于计费、配额。
+// 定义格式: NAME=TOKEN[;expires=2026-12-31 | 2026-12-31T08:00:00Z | UNIX 秒]
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientToken {
+    pub name: String,
+    pub token: String,
+    /// 过期时间 (unix 秒)
+    pub expires_at: Option<u64>,
+}
+
+impl ClientToken {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut parts = spec.split(';');
+        let head = parts.next().unwrap_or("").trim();
+        let (name, token) = head
+            .split_once('=')
+            .map(|(name, token)| (name.trim(), token.trim()))
+            .filter(|(name, token)| !name.is_empty() && !token.is_empty())
+            .ok_or_else(|| "client token must be NAME=TOKEN".to_string())?;
+        let mut client = ClientToken {
+            name: name.to_string(),
+            token: token.to_string(),
+            expires_at: None,
+        };
+        for part in parts.map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("client token option '{}' must be key=value", part))?;
+            match key {
+                "expires" => {
+                    client.expires_at = Some(
+                        parse_expiry(value.trim())
+                            .ok_or_else(|| format!("client token '{}': invalid expires '{}'", name, value))?,
+                    )
+                }
+                _ => return Err(format!("unknown client token option '{}'", key)),
+            }
+        }
+        Ok(client)
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|at| at <= now)
+    }
+}
+
+fn parse_expiry(value: &str) -> Option<u64> {
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(secs);
+    }
+    // 只写日期时按当天 0 点 (UTC) 过期
+    let ts = if value.contains('T') { value.to_string() } else { format!("{}T00:00:00Z", value) };
+    parse_timestamp(&ts).map(|ms| ms / 1000)
+}
+
+fn unix_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+pub struct ClientTokens {
+    by_token: HashMap<String, ClientToken>,
+}
+
+impl ClientTokens {
+    pub fn new(tokens: Vec<ClientToken>) -> Result<Self, String> {
+        let mut by_token = HashMap::with_capacity(tokens.len());
+        let mut names = std::collections::HashSet::new();
+        for token in tokens {
+            if !names.insert(token.name.clone()) {
+                return Err(format!("duplicate client token name '{}'", token.name));
+            }
+            if by_token.contains_key(&token.token) {
+                return Err(format!("client token '{}' reuses another token", token.name));
+            }
+            by_token.insert(token.token.clone(), token);
+        }
+        Ok(Self { by_token })
+    }
+
+    /// 每行一个定义，空行和 # 开头的行忽略
+    pub fn parse_file(content: &str) -> Result<Vec<ClientToken>, String> {
+        content
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(i, line)| ClientToken::parse(line).map_err(|e| format!("line {}: {}", i + 1, e)))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_token.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_token.is_empty()
+    }
+
+    pub(crate) fn has_name(&self, name: &str) -> bool {
+        self.by_token.values().any(|t| t.name == name)
+    }
+
+    /// 已经过期的令牌名
+    pub fn expired(&self) -> Vec<&str> {
+        let now = unix_secs();
+        self.by_token.values().filter(|t| t.is_expired(now)).map(|t| t.name.as_str()).collect()
+    }
+
+    fn lookup(&self, token: &str) -> Option<&ClientToken> {
+        self.by_token.get(token)
+    }
+
+    /// 令牌存在且没有过期
+    pub(crate) fn accepts(&self, token: &str) -> bool {
+        self.lookup(token).is_some_and(|t| !t.is_expired(unix_secs()))
+    }
+}
+
+/// 令牌出现的位置，校验通过后从那里去掉
+enum Source {
+    Bearer,
+    ApiKey,
+    Query(String),
+}
+
+fn presented(headers: &HeaderMap, uri: &Uri) -> Option<(String, Source)> {
+    if let Some(token) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+    {
+        return Some((token.trim().to_string(), Source::Bearer));
+    }
+    if let Some(token) = headers.get("x-goog-api-key").and_then(|v| v.to_str().ok()) {
+        return Some((token.trim().to_string(), Source::ApiKey));
+    }
+    let query = uri.query()?;
+    let token = query.split('&').find_map(|pair| pair.strip_prefix("key="))?;
+    Some((token.to_string(), Source::Query(sanitize_path(&uri.to_string()))))
+}
+
+/// 请求里出示的令牌，不管是否有效
+pub(crate) fn presented_token(headers: &HeaderMap, uri: &Uri) -> Option<String> {
+    presented(headers, uri).map(|(token, _)| token)
+}
+
+fn unauthorized(message: &str) -> Response {
+    let body = json!({
+        "error": {
+            "code": 401,
+            "message": message,
+            "status": "UNAUTHENTICATED",
+        }
+    });
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, "Bearer")],
+        Extension(AuthFailure),
+        Json(body),
+    )
+        .into_response()
+}
+
+pub(crate) async fn guard(State(state): State<Arc<AppState>>, mut req: Request, next: Next) -> Response {
+    let Some(tokens) = &state.client_tokens else {
+        return next.run(req).await;
+    };
+
+    let Some((token, source)) = presented(req.headers(), req.uri()) else {
+        // 有效签名 URL 本身就是授权，不要求再带令牌
+        if req.extensions().get::<SignedAccess>().is_some() {
+            return next.run(req).await;
+        }
+        state.metrics.inc("aizasy_client_auth_rejected_total", &[("reason", "missing")]);
+        return unauthorized("Missing gateway token");
+    };
+    let Some(client) = tokens.lookup(&token) else {
+        state.metrics.inc("aizasy_client_auth_rejected_total", &[("reason", "invalid")]);
+        return unauthorized("Invalid gateway token");
+    };
+    if client.is_expired(unix_secs()) {
+        state.metrics.inc("aizasy_client_auth_rejected_total", &[("reason", "expired")]);
+        return unauthorized("Gateway token expired");
+    }
+
+    debug!("🎫 Client {}", client.name);
+    match source {
+        Source::Bearer => {
+            req.headers_mut().remove(header::AUTHORIZATION);
+        }
+        Source::ApiKey => {
+            req.headers_mut().remove("x-goog-api-key");
+        }
+        Source::Query(stripped) => match stripped.parse::<Uri>() {
+            Ok(uri) => *req.uri_mut() = uri,
+            Err(_) => return unauthorized("Invalid gateway token"),
+        },
+    }
+    if let Some(tenant) = req.extensions().get::<CurrentTenant>().filter(|t| !t.0.allows(&client.name)) {
+        state.metrics.inc("aizasy_client_auth_rejected_total", &[("reason", "tenant")]);
+        let body = json!({
+            "error": {
+                "code": 403,
+                "message": format!("Gateway token cannot access tenant {}", tenant.0.name),
+                "status": "PERMISSION_DENIED",
+            }
+        });
+        return (StatusCode::FORBIDDEN, Json(body)).into_response();
+    }
+    req.extensions_mut().insert(ClientIdentity(client.name.clone()));
+    next.run(req).await
+}
@@ -0,0 +1,97 @@
+use async_trait::async_trait;
+use axum::{
+    extract::{ConnectInfo, FromRequestParts, Request, State},
+    http::{request::Parts, HeaderMap, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use std::convert::Infallible;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+
+use crate::cidr::Cidr;
+use crate::AppState;
+
+/// 请求来源 IP
+///
+/// 对端在 `--trusted-proxies` 内时是从 X-Forwarded-For 还原出的真实客户端地址，否则是对端地址。
+/// 优先取 `ConnectInfo<SocketAddr>`；嵌入到未开启 connect info 的宿主应用时退化为 0.0.0.0，
+/// 此时基于 IP 的功能 (GeoIP、封禁、锁定) 实际上只看到同一个来源。
+#[derive(Clone, Copy, Debug)]
+pub struct ClientIp(pub IpAddr);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ClientIp {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        // resolve 中间件已经算好了
+        if let Some(ip) = parts.extensions.get::<ClientIp>() {
+            return Ok(*ip);
+        }
+        Ok(ClientIp(peer_ip(parts)))
+    }
+}
+
+fn peer_ip(parts: &Parts) -> IpAddr {
+    parts
+        .extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        // 双栈监听时 IPv4 客户端显示为 ::ffff:a.b.c.d，统一还原成 IPv4
+        .map(|ConnectInfo(addr)| addr.ip().to_canonical())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+}
+
+// --- 受信任的反向代理 ---
+// 对端地址在 --trusted-proxies 内时，从右往左跳过 X-Forwarded-For 里同样受信任的代理，
+// 第一个不受信任的地址就是真实客户端，限流、封禁、GeoIP、访问日志都用它。
+// 这种请求转发给上游时保留原来的 X-Forwarded-For 并追加对端地址；其他请求的 X-Forwarded-For 一律丢弃，
+// 防止客户端伪造。
+
+/// 转发给上游的 X-Forwarded-For，只有对端受信任时才有
+#[derive(Clone, Debug)]
+pub(crate) struct ForwardedFor(pub(crate) HeaderValue);
+
+fn real_client(peer: IpAddr, headers: &HeaderMap, trusted: &[Cidr]) -> IpAddr {
+    let mut client = peer;
+    let hops = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .collect::<Vec<_>>();
+    for hop in hops.iter().rev() {
+        if !trusted.iter().any(|cidr| cidr.contains(client)) {
+            break;
+        }
+        match hop.trim().parse::<IpAddr>() {
+            Ok(ip) => client = ip.to_canonical(),
+            Err(_) => break,
+        }
+    }
+    client
+}
+
+pub(crate) async fn resolve(State(state): State<Arc<AppState>>, req: Request, next: Next) -> Response {
+    if state.trusted_proxies.is_empty() {
+        return next.run(req).await;
+    }
+    let (mut parts, body) = req.into_parts();
+    let peer = peer_ip(&parts);
+    if state.trusted_proxies.iter().any(|cidr| cidr.contains(peer)) {
+        let client = real_client(peer, &parts.headers, &state.trusted_proxies);
+        let existing = parts
+            .headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let forwarded = if existing.is_empty() { peer.to_string() } else { format!("{}, {}", existing, peer) };
+        if let Ok(value) = HeaderValue::from_str(&forwarded) {
+            parts.extensions.insert(ForwardedFor(value));
+        }
+        parts.extensions.insert(ClientIp(client));
+    }
+    next.run(Request::from_parts(parts, body)).await
+}
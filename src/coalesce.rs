@@ -0,0 +1,143 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::{response::Parts, Method},
+    middleware::Next,
+    response::Response,
+};
+use futures_util::stream::{self, StreamExt};
+use http_body_util::BodyExt;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tracing::debug;
+
+use crate::usage::ClientIdentity;
+use crate::AppState;
+
+// --- 合并相同的并发 GET ---
+// 打开 --coalesce-get 后，同一时刻到达的相同 GET 请求只有第一个转发给上游，其余的等它完成后拿到同一份响应，
+// 避免大量客户端频繁刷新 /v1beta/models 之类的列表时一起打到上游。
+// "相同" 指 path + query、客户端凭证、Accept-Encoding、x-aizasy-* 请求头和客户端身份都一样。
+// 只合并在途的请求，不缓存：第一个请求完成之后再来的相同请求照常转发。
+// 响应体超过 MAX_SHARED_BODY、读取出错或者第一个请求被客户端中断时不共享，等待的请求各自转发。
+// 鉴权、限流等中间件对每个请求照常生效，合并只发生在转发这一步。
+
+// 超过这个大小的响应体不共享 (例如文件下载)，直接流给第一个请求
+const MAX_SHARED_BODY: usize = 4 * 1024 * 1024;
+
+// 参与合并键的请求头，其余 (User-Agent、traceparent 等) 不影响上游的响应
+const KEY_HEADERS: &[&str] = &["authorization", "x-goog-api-key", "accept-encoding"];
+
+type Shared = Option<(Parts, Bytes)>;
+
+#[derive(Default)]
+pub(crate) struct Coalescer {
+    flights: Mutex<HashMap<String, broadcast::Sender<Arc<Shared>>>>,
+}
+
+fn flight_key(req: &Request) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(req.uri().path_and_query().map(|p| p.as_str()).unwrap_or("/"));
+    for (name, value) in req.headers() {
+        if KEY_HEADERS.contains(&name.as_str()) || name.as_str().starts_with("x-aizasy-") {
+            hasher.update([0]);
+            hasher.update(name.as_str());
+            hasher.update([b':']);
+            hasher.update(value.as_bytes());
+        }
+    }
+    if let Some(identity) = req.extensions().get::<ClientIdentity>() {
+        hasher.update([0]);
+        hasher.update(&identity.0);
+    }
+    hex::encode(hasher.finalize())
+}
+
+// 第一个请求持有；没有正常共享就被丢弃时 (客户端中断) 移除这一项，等待的请求收到 Closed 后各自转发
+struct Flight<'a> {
+    coalescer: &'a Coalescer,
+    key: String,
+}
+
+impl Flight<'_> {
+    fn finish(mut self, shared: Shared) {
+        // 取走 key，drop 时不会误删之后新来的同一个键
+        let key = std::mem::take(&mut self.key);
+        let sender = self.coalescer.flights.lock().unwrap_or_else(|e| e.into_inner()).remove(&key);
+        if let Some(sender) = sender {
+            let _ = sender.send(Arc::new(shared));
+        }
+    }
+}
+
+impl Drop for Flight<'_> {
+    fn drop(&mut self) {
+        if self.key.is_empty() {
+            return;
+        }
+        self.coalescer.flights.lock().unwrap_or_else(|e| e.into_inner()).remove(&self.key);
+    }
+}
+
+// 读完整个响应体；超过上限或读取出错时返回拼回去的响应体，原样交给第一个请求
+async fn collect(mut body: Body) -> Result<Bytes, Body> {
+    let mut collected = Vec::new();
+    while let Some(frame) = body.frame().await {
+        match frame {
+            Ok(frame) => {
+                let Ok(data) = frame.into_data() else {
+                    continue;
+                };
+                if collected.len() + data.len() > MAX_SHARED_BODY {
+                    let head = stream::iter([Ok(Bytes::from(collected)), Ok(data)]);
+                    return Err(Body::from_stream(head.chain(body.into_data_stream())));
+                }
+                collected.extend_from_slice(&data);
+            }
+            Err(e) => return Err(Body::from_stream(stream::iter([Ok(Bytes::from(collected)), Err(e)]))),
+        }
+    }
+    Ok(Bytes::from(collected))
+}
+
+pub(crate) async fn coalesce(State(state): State<Arc<AppState>>, req: Request, next: Next) -> Response {
+    let Some(coalescer) = state.coalescer.as_ref().filter(|_| req.method() == Method::GET) else {
+        return next.run(req).await;
+    };
+    let key = flight_key(&req);
+    let waiting = {
+        let mut flights = coalescer.flights.lock().unwrap_or_else(|e| e.into_inner());
+        match flights.get(&key) {
+            Some(sender) => Some(sender.subscribe()),
+            None => {
+                flights.insert(key.clone(), broadcast::channel(1).0);
+                None
+            }
+        }
+    };
+    if let Some(mut receiver) = waiting {
+        if let Ok(shared) = receiver.recv().await {
+            if let Some((parts, body)) = shared.as_ref() {
+                debug!("🪢 Coalesced GET {} into an in-flight request", req.uri().path());
+                state.metrics.inc("aizasy_coalesced_requests_total", &[]);
+                return Response::from_parts(parts.clone(), Body::from(body.clone()));
+            }
+        }
+        return next.run(req).await;
+    }
+
+    let flight = Flight { coalescer, key };
+    let (parts, body) = next.run(req).await.into_parts();
+    match collect(body).await {
+        Ok(body) => {
+            flight.finish(Some((parts.clone(), body.clone())));
+            Response::from_parts(parts, Body::from(body))
+        }
+        Err(body) => {
+            flight.finish(None);
+            Response::from_parts(parts, body)
+        }
+    }
+}
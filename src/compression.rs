@@ -0,0 +1,79 @@
+use axum::http::{header, Extensions, HeaderMap, StatusCode, Version};
+use axum::Router;
+use std::sync::Arc;
+use tower_http::compression::CompressionLayer;
+
+use crate::AppState;
+
+// --- 发给客户端的响应压缩 ---
+// 默认 (--accept-encoding identity) 网关不替客户端向上游要压缩，上游的响应体是未压缩的。
+// 客户端在移动网络等慢链路上时，可以让网关按客户端的 Accept-Encoding 压缩响应 (gzip / br)：
+//   - 只压缩带 Content-Length 且不小于 min_size 的响应，流式响应 (SSE、没有长度的 JSON 流) 原样转发，
+//     避免压缩器攒数据拖慢首字；
+//   - 已经带 Content-Encoding 的响应 (上游自己压缩过) 不再压缩。
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Gzip,
+    Brotli,
+}
+
+impl std::str::FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gzip" => Ok(Self::Gzip),
+            "br" | "brotli" => Ok(Self::Brotli),
+            other => Err(format!("unknown compression '{}', expected gzip or br", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompressionConfig {
+    pub algorithms: Vec<Algorithm>,
+    /// 小于这个字节数的响应不压缩
+    pub min_size: u64,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            algorithms: vec![Algorithm::Gzip, Algorithm::Brotli],
+            min_size: 1024,
+        }
+    }
+}
+
+impl CompressionConfig {
+    pub fn names(&self) -> Vec<&'static str> {
+        self.algorithms
+            .iter()
+            .map(|algorithm| match algorithm {
+                Algorithm::Gzip => "gzip",
+                Algorithm::Brotli => "br",
+            })
+            .collect()
+    }
+}
+
+pub(crate) fn apply(router: Router<Arc<AppState>>, config: &CompressionConfig) -> Router<Arc<AppState>> {
+    let min_size = config.min_size;
+    let predicate = move |_: StatusCode, _: Version, headers: &HeaderMap, _: &Extensions| {
+        let streaming = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("text/event-stream"));
+        let len = headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        !streaming && len.is_some_and(|len| len >= min_size)
+    };
+    let layer = CompressionLayer::new()
+        .gzip(config.algorithms.contains(&Algorithm::Gzip))
+        .br(config.algorithms.contains(&Algorithm::Brotli))
+        .compress_when(predicate);
+    router.layer(layer)
+}
@@ -0,0 +1,121 @@
+use serde_json::Value;
+
+// --- 配置文件 ---
+// TOML 或 YAML (按扩展名 .yaml / .yml 区分，其他当 TOML)。键名就是命令行参数的长名，
+// `-` 和 `_` 都可以；值是字符串、数字、布尔，可重复的参数写成列表：
+//   listen = ["0.0.0.0:3000", "[::]:3000"]
+//   target = "https://generativelanguage.googleapis.com"
+//   proxy = "socks5://127.0.0.1:1080"
+//   keys-file = "keys.txt"
+//   model-alias = ["fast=gemini-2.0-flash"]
+//   ledger = true
+// 这里只负责把文件展开成 键 -> 值列表，参数校验仍然交给命令行解析。
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigEntry {
+    /// 参数长名 (统一成 `-` 分隔)
+    pub key: String,
+    /// 布尔值展开为 "true" / "false"
+    pub values: Vec<String>,
+    /// 在文件里的行号 (从 1 开始)，找不到时为 None
+    pub line: Option<usize>,
+}
+
+/// 读取并展开配置文件，同时返回原文，报错时用来引用出错的那一行
+pub fn load(path: &str) -> Result<(String, Vec<ConfigEntry>), String> {
+    let content = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let yaml = path.ends_with(".yaml") || path.ends_with(".yml");
+    let entries = parse(&content, yaml).map_err(|e| format!("{}: {}", path, e))?;
+    Ok((content, entries))
+}
+
+/// 带上出错设置所在行的错误信息，便于在 CI 日志里直接定位:
+///   gateway.toml:12: unknown setting 'taret'
+///      12 | taret = "https://..."
+pub fn error_at(path: &str, content: &str, entry: &ConfigEntry, message: &str) -> String {
+    match entry.line {
+        Some(line) => format!("{}:{}: {}\n{}", path, line, message, snippet(content, line)),
+        None => format!("{}: {}", path, message),
+    }
+}
+
+fn snippet(content: &str, line: usize) -> String {
+    let text = content.lines().nth(line.saturating_sub(1)).unwrap_or("");
+    format!("{:>6} | {}", line, text)
+}
+
+// 顶层设置所在的行：TOML 是 `key =`，YAML 是 `key:`，键名可能带引号
+fn find_line(content: &str, key: &str, yaml: bool) -> Option<usize> {
+    let separator = if yaml { ':' } else { '=' };
+    content.lines().position(|line| {
+        let line = line.trim_start();
+        let (line, quote) = match line.chars().next() {
+            Some(q @ ('"' | '\'')) => (&line[1..], Some(q)),
+            _ => (line, None),
+        };
+        let Some(rest) = line.strip_prefix(key) else {
+            return false;
+        };
+        let rest = match quote {
+            Some(q) => match rest.strip_prefix(q) {
+                Some(rest) => rest,
+                None => return false,
+            },
+            None => rest,
+        };
+        rest.trim_start().starts_with(separator)
+    })
+    .map(|index| index + 1)
+}
+
+pub fn parse(content: &str, yaml: bool) -> Result<Vec<ConfigEntry>, String> {
+    let document: Value = if yaml {
+        // 空文件解析出来是 null
+        serde_yaml::from_str::<Option<Value>>(content)
+            .map_err(|e| match e.location() {
+                Some(location) => format!("{}\n{}", e, snippet(content, location.line())),
+                None => e.to_string(),
+            })?
+            .unwrap_or_else(|| Value::Object(Default::default()))
+    } else {
+        toml::from_str(content).map_err(|e| e.to_string())?
+    };
+    let Value::Object(settings) = document else {
+        return Err("config must be a table of settings".to_string());
+    };
+    let mut entries = Vec::with_capacity(settings.len());
+    for (raw, value) in settings {
+        let line = find_line(content, &raw, yaml);
+        let key = raw.replace('_', "-");
+        let values = match value {
+            Value::Null => continue,
+            Value::Array(items) => items
+                .iter()
+                .map(|item| {
+                    scalar(item)
+                        .ok_or_else(|| at(content, line, format!("'{}': list items must be strings, numbers or booleans", key)))
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            other => vec![scalar(&other)
+                .ok_or_else(|| at(content, line, format!("'{}': expected a string, number, boolean or list", key)))?],
+        };
+        entries.push(ConfigEntry { key, values, line });
+    }
+    Ok(entries)
+}
+
+fn at(content: &str, line: Option<usize>, message: String) -> String {
+    match line {
+        Some(line) => format!("line {}: {}\n{}", line, message, snippet(content, line)),
+        None => message,
+    }
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
@@ -0,0 +1,155 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+use tracing::debug;
+
+use crate::AppState;
+
+// --- CORS ---
+// 让浏览器里的应用直接调用网关。预检请求 (OPTIONS + Access-Control-Request-Method) 在网关这里直接回 204，
+// 不经过鉴权、也不转发上游；普通请求照常处理，响应 (包括上游透传的) 加上 Access-Control-Allow-Origin。
+// Origin 不在允许列表里时不加任何 CORS 头，由浏览器拦截；这种来源的预检返回 403。
+
+#[derive(Debug, Clone)]
+pub struct CorsConfig {
+    /// 允许的来源：`*`、完整来源 (`https://app.example.com`) 或子域通配 (`https://*.example.com`)
+    pub origins: Vec<String>,
+    pub methods: Vec<String>,
+    /// 为空时原样允许预检里请求的头
+    pub headers: Vec<String>,
+    /// 允许浏览器脚本读取的响应头
+    pub expose_headers: Vec<String>,
+    /// 预检结果缓存秒数 (Access-Control-Max-Age)
+    pub max_age_secs: u64,
+    pub allow_credentials: bool,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            origins: vec!["*".to_string()],
+            methods: ["GET", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"].map(String::from).to_vec(),
+            headers: Vec::new(),
+            expose_headers: Vec::new(),
+            max_age_secs: 600,
+            allow_credentials: false,
+        }
+    }
+}
+
+pub struct Cors {
+    config: CorsConfig,
+    methods: HeaderValue,
+    headers: Option<HeaderValue>,
+    expose_headers: Option<HeaderValue>,
+    max_age: HeaderValue,
+}
+
+fn join(values: &[String]) -> Result<Option<HeaderValue>, String> {
+    if values.is_empty() {
+        return Ok(None);
+    }
+    let joined = values.join(", ");
+    HeaderValue::from_str(&joined)
+        .map(Some)
+        .map_err(|_| format!("invalid header list '{}'", joined))
+}
+
+impl Cors {
+    pub fn new(config: CorsConfig) -> Result<Self, String> {
+        if config.origins.is_empty() {
+            return Err("at least one CORS origin is required".to_string());
+        }
+        Ok(Self {
+            methods: join(&config.methods)?.unwrap_or(HeaderValue::from_static("GET, POST, OPTIONS")),
+            headers: join(&config.headers)?,
+            expose_headers: join(&config.expose_headers)?,
+            max_age: HeaderValue::from(config.max_age_secs),
+            config,
+        })
+    }
+
+    fn is_allowed(&self, origin: &str) -> bool {
+        self.config.origins.iter().any(|allowed| {
+            if allowed == "*" {
+                return true;
+            }
+            match allowed.split_once("://*.") {
+                // https://*.example.com 匹配 https://a.example.com，不匹配 https://example.com
+                Some((scheme, domain)) => origin
+                    .strip_prefix(scheme)
+                    .and_then(|rest| rest.strip_prefix("://"))
+                    .and_then(|host| host.strip_suffix(domain))
+                    .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+                None => allowed.eq_ignore_ascii_case(origin),
+            }
+        })
+    }
+
+    // 带凭证时浏览器不接受 *，只能回显具体来源
+    fn allow_origin(&self, origin: &HeaderValue) -> HeaderValue {
+        let wildcard_only = self.config.origins.iter().all(|o| o == "*");
+        if wildcard_only && !self.config.allow_credentials {
+            HeaderValue::from_static("*")
+        } else {
+            origin.clone()
+        }
+    }
+
+    fn apply(&self, headers: &mut HeaderMap, origin: &HeaderValue) {
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, self.allow_origin(origin));
+        headers.append(header::VARY, HeaderValue::from_static("Origin"));
+        if self.config.allow_credentials {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, HeaderValue::from_static("true"));
+        }
+        if let Some(expose) = &self.expose_headers {
+            headers.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, expose.clone());
+        }
+    }
+
+    fn preflight(&self, origin: &HeaderValue, request_headers: Option<&HeaderValue>) -> Response {
+        let mut headers = HeaderMap::new();
+        self.apply(&mut headers, origin);
+        headers.remove(header::ACCESS_CONTROL_EXPOSE_HEADERS);
+        headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, self.methods.clone());
+        if let Some(allowed) = self.headers.clone().or_else(|| request_headers.cloned()) {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, allowed);
+        }
+        headers.insert(header::ACCESS_CONTROL_MAX_AGE, self.max_age.clone());
+        (StatusCode::NO_CONTENT, headers).into_response()
+    }
+}
+
+pub(crate) async fn handle(State(state): State<Arc<AppState>>, req: Request, next: Next) -> Response {
+    let Some(cors) = &state.cors else {
+        return next.run(req).await;
+    };
+    let Some(origin) = req.headers().get(header::ORIGIN).cloned() else {
+        return next.run(req).await;
+    };
+    let allowed = origin.to_str().is_ok_and(|o| cors.is_allowed(o));
+    let is_preflight =
+        req.method() == Method::OPTIONS && req.headers().contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+    if is_preflight {
+        if !allowed {
+            debug!("🌐 CORS preflight from {:?} rejected", origin);
+            state.metrics.inc("aizasy_cors_rejected_total", &[]);
+            return (StatusCode::FORBIDDEN, "CORS origin not allowed").into_response();
+        }
+        return cors.preflight(&origin, req.headers().get(header::ACCESS_CONTROL_REQUEST_HEADERS));
+    }
+
+    let mut response = next.run(req).await;
+    if allowed {
+        cors.apply(response.headers_mut(), &origin);
+    } else {
+        // 上游自己的 CORS 头也去掉，以这里的配置为准
+        response.headers_mut().remove(header::ACCESS_CONTROL_ALLOW_ORIGIN);
+        response.headers_mut().remove(header::ACCESS_CONTROL_ALLOW_CREDENTIALS);
+    }
+    response
+}
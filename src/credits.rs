@@ -0,0 +1,166 @@
+use async_trait::async_trait;
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use serde_json::json;
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+use crate::budget::BudgetUnit;
+use crate::ledger::{LedgerEntry, LedgerHook};
+use crate::plugin::{GatewayPlugin, RequestContext};
+use crate::storage::Storage;
+use crate::usage;
+
+// --- 预付费额度 ---
+// 每个客户端一个余额 (费用或 token)，请求完成后按账本记录扣减；余额耗尽时在转发前直接拒绝。
+// 扣减发生在请求结束之后，所以并发的最后几个请求可能让余额略低于 0。
+
+const COST_SCALE: f64 = 1_000_000.0;
+// 单次调整的上限 (存储的整数单位)：费用约 10 亿、token 10^15，远超正常充值，也离 i64 溢出很远
+const MAX_DELTA: f64 = 1e15;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Balance {
+    pub client: String,
+    pub balance: f64,
+    pub unit: BudgetUnit,
+}
+
+pub struct CreditAccounts {
+    storage: Arc<dyn Storage>,
+    unit: BudgetUnit,
+}
+
+impl CreditAccounts {
+    pub fn new(storage: Arc<dyn Storage>, unit: BudgetUnit) -> Self {
+        Self { storage, unit }
+    }
+
+    fn key(client: &str) -> String {
+        format!("credit:{}", client)
+    }
+
+    fn scale(&self) -> f64 {
+        match self.unit {
+            BudgetUnit::Cost => COST_SCALE,
+            BudgetUnit::Tokens => 1.0,
+        }
+    }
+
+    pub async fn balance(&self, client: &str) -> Result<Balance, String> {
+        let raw = match self.storage.get(&Self::key(client)).await? {
+            Some(v) => String::from_utf8_lossy(&v).parse::<i64>().unwrap_or(0),
+            None => 0,
+        };
+        Ok(Balance {
+            client: client.to_string(),
+            balance: raw as f64 / self.scale(),
+            unit: self.unit,
+        })
+    }
+
+    /// 把 amount 换算成存储的整数；NaN、无穷大和超过单次上限的都拒绝
+    pub fn delta(&self, amount: f64) -> Result<i64, String> {
+        let scaled = (amount * self.scale()).round();
+        if !scaled.is_finite() || scaled.abs() > MAX_DELTA {
+            return Err(format!("amount must be a finite number within ±{}", MAX_DELTA / self.scale()));
+        }
+        Ok(scaled as i64)
+    }
+
+    /// 充值 (amount 为负数时扣减)，返回新余额
+    pub async fn top_up(&self, client: &str, amount: f64) -> Result<Balance, String> {
+        let delta = self.delta(amount)?;
+        let raw = self.storage.incr(&Self::key(client), delta, None).await?;
+        Ok(Balance {
+            client: client.to_string(),
+            balance: raw as f64 / self.scale(),
+            unit: self.unit,
+        })
+    }
+}
+
+#[async_trait]
+impl GatewayPlugin for CreditAccounts {
+    fn name(&self) -> &str {
+        "credits"
+    }
+
+    async fn on_request(&self, ctx: &mut RequestContext) -> Result<(), Response> {
+        let client = usage::client_label(ctx);
+        let balance = match self.balance(&client).await {
+            Ok(balance) => balance,
+            Err(e) => {
+                // 存储不可用时放行，避免整个网关不可用
+                warn!("🪙 Credit lookup failed for {}: {}", client, e);
+                return Ok(());
+            }
+        };
+        if balance.balance > 0.0 {
+            return Ok(());
+        }
+        debug!("🪙 {} has no credit left", client);
+        let body = json!({
+            "error": {
+                "code": 402,
+                "message": format!("Insufficient credit balance for {}", client),
+                "status": "PAYMENT_REQUIRED",
+            }
+        });
+        Err((StatusCode::PAYMENT_REQUIRED, Json(body)).into_response())
+    }
+}
+
+#[async_trait]
+impl LedgerHook for CreditAccounts {
+    async fn on_entry(&self, entry: &LedgerEntry) {
+        let amount = match self.unit {
+            BudgetUnit::Cost => entry.cost,
+            BudgetUnit::Tokens => (entry.prompt_tokens + entry.output_tokens) as f64,
+        };
+        if amount <= 0.0 {
+            return;
+        }
+        if let Err(e) = self.top_up(&entry.client, -amount).await {
+            warn!("🪙 Failed to charge {}: {}", entry.client, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    fn accounts(unit: BudgetUnit) -> CreditAccounts {
+        CreditAccounts::new(Arc::new(MemoryStorage::new()), unit)
+    }
+
+    #[test]
+    fn delta_scales_by_unit() {
+        assert_eq!(accounts(BudgetUnit::Cost).delta(1.5).unwrap(), 1_500_000);
+        assert_eq!(accounts(BudgetUnit::Tokens).delta(-42.4).unwrap(), -42);
+    }
+
+    #[test]
+    fn delta_rejects_non_finite_and_out_of_range() {
+        let credits = accounts(BudgetUnit::Cost);
+        for amount in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY, 1e10, -1e10, f64::MAX] {
+            assert!(credits.delta(amount).is_err(), "{} accepted", amount);
+        }
+        assert!(accounts(BudgetUnit::Tokens).delta(1e16).is_err());
+    }
+
+    #[tokio::test]
+    async fn rejected_top_up_leaves_balance_untouched() {
+        let credits = accounts(BudgetUnit::Cost);
+        credits.top_up("alice", 2.0).await.unwrap();
+        assert!(credits.top_up("alice", f64::NAN).await.is_err());
+        assert!(credits.top_up("alice", f64::INFINITY).await.is_err());
+        assert_eq!(credits.balance("alice").await.unwrap().balance, 2.0);
+    }
+}
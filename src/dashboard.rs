@@ -0,0 +1,183 @@
+use async_trait::async_trait;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::plugin::{GatewayPlugin, Outcome, RequestContext};
+use crate::sanitize::sanitize_path;
+use crate::usage::UpstreamKeyId;
+
+// --- 内置监控面板 ---
+// 小规模部署不用搭 Prometheus / Grafana 也能看到网关的状态：管理 API 下的 /admin/dashboard 是一个静态页面，
+// 每隔两秒拉取 /admin/dashboard/data，显示实时 RPS、延迟分位数、每个 key 的用量和冷却状态、最近的错误。
+// 统计只在内存里按秒聚合，重启后清零；页面本身不含数据，打开时输入管理 token (没有 token 的独立管理端口不需要)。
+
+pub(crate) const PAGE: &str = include_str!("dashboard.html");
+
+// 按秒聚合的请求数保留这么久，也是页面上 RPS 曲线的长度
+const SERIES_SECS: u64 = 120;
+// 算延迟分位数用最近这么多秒、最多这么多个样本
+const LATENCY_SECS: u64 = 300;
+const MAX_LATENCY_SAMPLES: usize = 4096;
+const MAX_ERRORS: usize = 50;
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Second {
+    /// unix 秒
+    pub at: u64,
+    pub requests: u64,
+    pub errors: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct KeyUsage {
+    pub requests: u64,
+    pub errors: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecentError {
+    pub id: u64,
+    /// unix 毫秒
+    pub at: u64,
+    pub method: String,
+    /// 已脱敏的 path + query
+    pub path: String,
+    pub status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Latency {
+    pub samples: usize,
+    pub p50_ms: u64,
+    pub p90_ms: u64,
+    pub p99_ms: u64,
+    pub max_ms: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    series: VecDeque<Second>,
+    // (unix 秒, 毫秒)
+    latencies: VecDeque<(u64, u64)>,
+    keys: HashMap<String, KeyUsage>,
+    errors: VecDeque<RecentError>,
+}
+
+#[derive(Default)]
+pub struct Dashboard {
+    inner: Mutex<Inner>,
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+// 已排序样本的第 q 分位
+fn percentile(sorted: &[u64], q: f64) -> u64 {
+    let rank = ((sorted.len() as f64 * q).ceil() as usize).clamp(1, sorted.len());
+    sorted[rank - 1]
+}
+
+impl Dashboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 最近 SERIES_SECS 秒每秒的请求数，没有请求的秒补零，最后一个是当前这一秒
+    pub(crate) fn series(&self) -> Vec<Second> {
+        let now = now_ms() / 1000;
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        (now + 1 - SERIES_SECS..=now)
+            .map(|at| inner.series.iter().find(|s| s.at == at).copied().unwrap_or(Second { at, ..Second::default() }))
+            .collect()
+    }
+
+    pub(crate) fn latency(&self) -> Option<Latency> {
+        let since = (now_ms() / 1000).saturating_sub(LATENCY_SECS);
+        let mut sorted: Vec<u64> = {
+            let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+            inner.latencies.iter().filter(|(at, _)| *at >= since).map(|(_, ms)| *ms).collect()
+        };
+        if sorted.is_empty() {
+            return None;
+        }
+        sorted.sort_unstable();
+        Some(Latency {
+            samples: sorted.len(),
+            p50_ms: percentile(&sorted, 0.5),
+            p90_ms: percentile(&sorted, 0.9),
+            p99_ms: percentile(&sorted, 0.99),
+            max_ms: sorted[sorted.len() - 1],
+        })
+    }
+
+    /// 启动以来每个 key 的请求数和错误数
+    pub(crate) fn keys(&self) -> HashMap<String, KeyUsage> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner()).keys.clone()
+    }
+
+    /// 最近的错误，按时间倒序
+    pub(crate) fn errors(&self) -> Vec<RecentError> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner()).errors.iter().rev().cloned().collect()
+    }
+}
+
+#[async_trait]
+impl GatewayPlugin for Dashboard {
+    fn name(&self) -> &str {
+        "dashboard"
+    }
+
+    fn on_complete(&self, ctx: &RequestContext, outcome: &Outcome) {
+        let at_ms = now_ms();
+        let at = at_ms / 1000;
+        // 4xx 多半是客户端自己的问题，只把 5xx、429 和连接层面的失败算作错误
+        let failed = outcome.error.is_some()
+            || outcome.status.is_none_or(|s| s.is_server_error() || s.as_u16() == 429);
+        let key = ctx.extensions.get::<UpstreamKeyId>().map(|k| k.0.clone());
+
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        match inner.series.back_mut().filter(|s| s.at == at) {
+            Some(second) => {
+                second.requests += 1;
+                second.errors += failed as u64;
+            }
+            None => {
+                inner.series.push_back(Second { at, requests: 1, errors: failed as u64 });
+                while inner.series.front().is_some_and(|s| s.at + SERIES_SECS <= at) {
+                    inner.series.pop_front();
+                }
+            }
+        }
+        if inner.latencies.len() == MAX_LATENCY_SAMPLES {
+            inner.latencies.pop_front();
+        }
+        inner.latencies.push_back((at, outcome.duration.as_millis() as u64));
+        if let Some(key) = &key {
+            let usage = inner.keys.entry(key.clone()).or_default();
+            usage.requests += 1;
+            usage.errors += failed as u64;
+        }
+        if failed {
+            if inner.errors.len() == MAX_ERRORS {
+                inner.errors.pop_front();
+            }
+            let path = ctx.uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+            inner.errors.push_back(RecentError {
+                id: ctx.id,
+                at: at_ms,
+                method: ctx.method.to_string(),
+                path: sanitize_path(path),
+                status: outcome.status.map(|s| s.as_u16()),
+                key,
+                error: outcome.error.clone(),
+            });
+        }
+    }
+}
@@ -0,0 +1,411 @@
+use futures_util::future::select_ok;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::ClientBuilder;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tracing::debug;
+#[cfg(feature = "dns")]
+use hickory_resolver::{
+    config::{LookupIpStrategy, NameServerConfig, ResolverConfig},
+    name_server::TokioConnectionProvider,
+    proto::xfer::Protocol,
+    TokioResolver,
+};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+// --- 上游域名解析 ---
+// 有些网络里系统 DNS 对 googleapis.com 的解析被污染或者很慢，除了改 /etc/hosts 没有别的办法：
+//   --resolve HOST=IP[,IP...]   固定某个域名解析到的地址 (端口仍然按 URL)，不查 DNS
+//   --dns-server SERVER          不用系统 DNS，改用指定的服务器解析 (需要 dns 特性)，可重复指定：
+//       1.1.1.1 / 1.1.1.1:53            UDP，查询被截断时退回 TCP
+//       tcp://1.1.1.1                   只用 TCP
+//       tls://1.1.1.1#cloudflare-dns.com             DNS over TLS，默认端口 853，# 后是证书上的域名
+//       https://1.1.1.1/dns-query#cloudflare-dns.com DNS over HTTPS，默认端口 443、路径 /dns-query
+//   --ip-strategy                ipv4 / ipv6 只用一个地址族，prefer-ipv4 / prefer-ipv6 调整先后
+//   --happy-eyeballs-ms N        两个地址族都有时先探测一次哪个连得上 (首选的 N 毫秒没连上就同时试另一个)
+// 对所有上游客户端生效 (默认上游、单独的上游代理、镜像、gRPC)；走代理时上游域名由代理解析，这里只影响代理自己的域名。
+// --resolve 固定的地址原样使用，不经过地址族的选择。
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DnsTransport {
+    Udp,
+    Tcp,
+    Tls,
+    Https,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DnsServer {
+    pub transport: DnsTransport,
+    pub addr: SocketAddr,
+    /// DoT / DoH 证书上的域名
+    pub tls_name: Option<String>,
+    /// DoH 路径
+    pub path: Option<String>,
+}
+
+impl std::str::FromStr for DnsServer {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let invalid = |why: &str| format!("invalid DNS server '{}': {}", spec, why);
+        let (transport, rest) = match spec.split_once("://") {
+            None => (DnsTransport::Udp, spec),
+            Some(("udp", rest)) => (DnsTransport::Udp, rest),
+            Some(("tcp", rest)) => (DnsTransport::Tcp, rest),
+            Some(("tls", rest)) => (DnsTransport::Tls, rest),
+            Some(("https", rest)) => (DnsTransport::Https, rest),
+            Some((scheme, _)) => return Err(invalid(&format!("unknown scheme '{}' (expected udp, tcp, tls or https)", scheme))),
+        };
+        let (rest, tls_name) = match rest.split_once('#') {
+            Some((rest, name)) if !name.is_empty() => (rest, Some(name.to_string())),
+            Some(_) => return Err(invalid("empty TLS name after '#'")),
+            None => (rest, None),
+        };
+        let (host, path) = match rest.find('/') {
+            Some(pos) => (&rest[..pos], Some(rest[pos..].to_string())),
+            None => (rest, None),
+        };
+        if path.is_some() && transport != DnsTransport::Https {
+            return Err(invalid("only https servers take a path"));
+        }
+        let default_port = match transport {
+            DnsTransport::Udp | DnsTransport::Tcp => 53,
+            DnsTransport::Tls => 853,
+            DnsTransport::Https => 443,
+        };
+        let addr = host
+            .parse::<SocketAddr>()
+            .or_else(|_| host.trim_matches(['[', ']']).parse::<IpAddr>().map(|ip| SocketAddr::new(ip, default_port)))
+            .map_err(|_| invalid("expected an IP address, optionally with a port"))?;
+        let encrypted = matches!(transport, DnsTransport::Tls | DnsTransport::Https);
+        if encrypted && tls_name.is_none() {
+            return Err(invalid("tls and https servers need the certificate name after '#'"));
+        }
+        if !encrypted && tls_name.is_some() {
+            return Err(invalid("only tls and https servers take a certificate name"));
+        }
+        Ok(Self {
+            transport,
+            addr,
+            tls_name,
+            path,
+        })
+    }
+}
+
+impl std::fmt::Display for DnsServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let scheme = match self.transport {
+            DnsTransport::Udp => "udp",
+            DnsTransport::Tcp => "tcp",
+            DnsTransport::Tls => "tls",
+            DnsTransport::Https => "https",
+        };
+        write!(f, "{}://{}{}", scheme, self.addr, self.path.as_deref().unwrap_or(""))?;
+        if let Some(name) = &self.tls_name {
+            write!(f, "#{}", name)?;
+        }
+        Ok(())
+    }
+}
+
+/// 固定解析：域名和地址
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostOverride {
+    pub host: String,
+    pub addrs: Vec<IpAddr>,
+}
+
+impl std::str::FromStr for HostOverride {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let (host, addrs) = spec
+            .split_once('=')
+            .ok_or_else(|| format!("host override '{}' must be HOST=IP[,IP...]", spec))?;
+        let host = host.trim().to_ascii_lowercase();
+        if host.is_empty() {
+            return Err(format!("host override '{}' has no host", spec));
+        }
+        let addrs = addrs
+            .split(',')
+            .map(|ip| ip.trim().trim_matches(['[', ']']).parse::<IpAddr>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format!("invalid address in host override '{}'", spec))?;
+        Ok(Self { host, addrs })
+    }
+}
+
+/// 上游地址的 IPv4 / IPv6 选择
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IpStrategy {
+    /// 按解析结果的顺序
+    #[default]
+    Auto,
+    /// 只用 IPv4
+    Ipv4,
+    /// 只用 IPv6
+    Ipv6,
+    /// IPv4 在前，连不上再退回 IPv6
+    PreferIpv4,
+    /// IPv6 在前，连不上再退回 IPv4
+    PreferIpv6,
+}
+
+impl std::str::FromStr for IpStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "ipv4" => Ok(Self::Ipv4),
+            "ipv6" => Ok(Self::Ipv6),
+            "prefer-ipv4" => Ok(Self::PreferIpv4),
+            "prefer-ipv6" => Ok(Self::PreferIpv6),
+            _ => Err(format!("unknown ip strategy '{}' (expected auto, ipv4, ipv6, prefer-ipv4 or prefer-ipv6)", s)),
+        }
+    }
+}
+
+impl IpStrategy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Ipv4 => "ipv4",
+            Self::Ipv6 => "ipv6",
+            Self::PreferIpv4 => "prefer-ipv4",
+            Self::PreferIpv6 => "prefer-ipv6",
+        }
+    }
+
+    fn order(&self, addrs: &mut Vec<IpAddr>) {
+        match self {
+            Self::Auto => {}
+            Self::Ipv4 => addrs.retain(IpAddr::is_ipv4),
+            Self::Ipv6 => addrs.retain(IpAddr::is_ipv6),
+            // 稳定排序，同一地址族内保持解析顺序
+            Self::PreferIpv4 => addrs.sort_by_key(IpAddr::is_ipv6),
+            Self::PreferIpv6 => addrs.sort_by_key(IpAddr::is_ipv4),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DnsConfig {
+    pub servers: Vec<DnsServer>,
+    pub overrides: Vec<HostOverride>,
+    pub strategy: IpStrategy,
+    /// Happy Eyeballs：首选地址族这么久还没连上就同时试另一个地址族
+    pub happy_eyeballs: Option<Duration>,
+}
+
+impl DnsConfig {
+    pub fn is_empty(&self) -> bool {
+        self.servers.is_empty() && self.overrides.is_empty() && self.strategy == IpStrategy::Auto && self.happy_eyeballs.is_none()
+    }
+}
+
+// Happy Eyeballs 探测的是上游 URL 里的端口，不认识的域名 (运行时新加的路由等) 按 HTTPS 的 443；
+// 胜出的地址族按域名记住 WINNER_TTL，两个地址族都连不上时记住 FAILURE_TTL，期间新连接不再探测
+const DEFAULT_PROBE_PORT: u16 = 443;
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+const WINNER_TTL: Duration = Duration::from_secs(300);
+const FAILURE_TTL: Duration = Duration::from_secs(30);
+
+struct Inner {
+    #[cfg(feature = "dns")]
+    custom: Option<TokioResolver>,
+    strategy: IpStrategy,
+    happy_eyeballs: Option<Duration>,
+    // 域名 -> 上游 URL 里的端口
+    ports: HashMap<String, u16>,
+    // 域名 -> (胜出的是 IPv6，都连不上时为 None；记录时间)
+    winners: Mutex<HashMap<String, (Option<bool>, Instant)>>,
+}
+
+// hyper 连接时自己也做 Happy Eyeballs，但固定 300ms 而且每个新连接都要重新等：
+// 这里解析完先让两个地址族赛跑一次，把连得上的地址族排到前面，坏掉的 IPv6 路由只在探测时付一次代价
+#[derive(Clone)]
+struct AddressResolver(Arc<Inner>);
+
+impl Inner {
+    async fn lookup(&self, host: &str) -> Result<Vec<IpAddr>, BoxError> {
+        #[cfg(feature = "dns")]
+        if let Some(resolver) = &self.custom {
+            return Ok(resolver.lookup_ip(host).await?.into_iter().collect());
+        }
+        Ok(tokio::net::lookup_host((host, 0)).await?.map(|addr| addr.ip()).collect())
+    }
+
+    /// 记住的探测结果；外层 None 表示没有或者已经过期
+    fn winner(&self, host: &str) -> Option<Option<bool>> {
+        let winners = self.winners.lock().ok()?;
+        let (ipv6, at) = winners.get(host)?;
+        let ttl = if ipv6.is_some() { WINNER_TTL } else { FAILURE_TTL };
+        (at.elapsed() < ttl).then_some(*ipv6)
+    }
+
+    async fn race(&self, host: &str, addrs: &[IpAddr], delay: Duration) -> Option<bool> {
+        let first = *addrs.first()?;
+        let other = *addrs.iter().find(|ip| ip.is_ipv6() != first.is_ipv6())?;
+        if let Some(ipv6) = self.winner(host) {
+            return ipv6;
+        }
+        let port = self.ports.get(host).copied().unwrap_or(DEFAULT_PROBE_PORT);
+        let probe = |ip: IpAddr, wait: Duration| {
+            Box::pin(async move {
+                tokio::time::sleep(wait).await;
+                match tokio::time::timeout(PROBE_TIMEOUT, TcpStream::connect((ip, port))).await {
+                    Ok(Ok(_)) => Ok(ip.is_ipv6()),
+                    _ => Err(()),
+                }
+            })
+        };
+        let ipv6 = select_ok([probe(first, Duration::ZERO), probe(other, delay)]).await.ok().map(|(ipv6, _)| ipv6);
+        match ipv6 {
+            Some(ipv6) if ipv6 != first.is_ipv6() => {
+                debug!("🏁 {} is unreachable over {}, using {}", host, family(first.is_ipv6()), family(ipv6))
+            }
+            Some(_) => {}
+            None => debug!("🏁 {} port {} is unreachable over both IPv4 and IPv6", host, port),
+        }
+        if let Ok(mut winners) = self.winners.lock() {
+            winners.insert(host.to_string(), (ipv6, Instant::now()));
+        }
+        ipv6
+    }
+}
+
+fn family(ipv6: bool) -> &'static str {
+    if ipv6 {
+        "IPv6"
+    } else {
+        "IPv4"
+    }
+}
+
+impl Resolve for AddressResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let inner = self.0.clone();
+        Box::pin(async move {
+            let host = name.as_str();
+            let started = Instant::now();
+            let mut addrs = inner.lookup(host).await?;
+            crate::slow_log::record_dns(started.elapsed());
+            inner.strategy.order(&mut addrs);
+            if addrs.is_empty() {
+                return Err(format!("no {} address for {}", inner.strategy.name(), host).into());
+            }
+            if let Some(delay) = inner.happy_eyeballs {
+                if let Some(ipv6) = inner.race(host, &addrs, delay).await {
+                    addrs.sort_by_key(|ip| ip.is_ipv6() != ipv6);
+                }
+            }
+            let addrs: Addrs = Box::new(addrs.into_iter().map(|ip| SocketAddr::new(ip, 0)));
+            Ok(addrs)
+        })
+    }
+}
+
+// 同一个域名出现在多个端口上时用第一个
+fn probe_ports<'a>(upstreams: impl IntoIterator<Item = &'a str>) -> HashMap<String, u16> {
+    let mut ports = HashMap::new();
+    for url in upstreams {
+        let Ok(url) = reqwest::Url::parse(url) else {
+            continue;
+        };
+        if let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) {
+            ports.entry(host.trim_start_matches('[').trim_end_matches(']').to_string()).or_insert(port);
+        }
+    }
+    ports
+}
+
+/// 所有上游客户端共用的解析配置
+#[derive(Clone, Default)]
+pub(crate) struct Resolvers {
+    overrides: Vec<(String, Vec<SocketAddr>)>,
+    resolver: Option<AddressResolver>,
+}
+
+impl Resolvers {
+    /// `timed` 时即使没有别的解析设置也用自己的解析器，慢请求日志才能拿到 DNS 耗时；
+    /// `upstreams` 是已知的上游 URL，Happy Eyeballs 按其中的端口探测
+    pub(crate) fn new<'a>(config: &DnsConfig, timed: bool, upstreams: impl IntoIterator<Item = &'a str>) -> Result<Self, String> {
+        let overrides = config
+            .overrides
+            .iter()
+            .map(|o| (o.host.clone(), o.addrs.iter().map(|ip| SocketAddr::new(*ip, 0)).collect()))
+            .collect();
+        #[cfg(not(feature = "dns"))]
+        if !config.servers.is_empty() {
+            return Err("custom DNS servers need the dns feature".to_string());
+        }
+        #[cfg(feature = "dns")]
+        let custom = (!config.servers.is_empty()).then(|| {
+            let mut resolver_config = ResolverConfig::new();
+            for server in &config.servers {
+                let protocols: &[Protocol] = match server.transport {
+                    DnsTransport::Udp => &[Protocol::Udp, Protocol::Tcp],
+                    DnsTransport::Tcp => &[Protocol::Tcp],
+                    DnsTransport::Tls => &[Protocol::Tls],
+                    DnsTransport::Https => &[Protocol::Https],
+                };
+                for protocol in protocols {
+                    let mut name_server = NameServerConfig::new(server.addr, *protocol);
+                    name_server.tls_dns_name = server.tls_name.clone();
+                    name_server.http_endpoint = server.path.clone();
+                    resolver_config.add_name_server(name_server);
+                }
+            }
+            let mut builder = TokioResolver::builder_with_config(resolver_config, TokioConnectionProvider::default());
+            // 两个地址族都要，顺序交给 IpStrategy
+            builder.options_mut().ip_strategy = LookupIpStrategy::Ipv4AndIpv6;
+            builder.build()
+        });
+        let resolver = (timed || !config.servers.is_empty() || config.strategy != IpStrategy::Auto || config.happy_eyeballs.is_some()).then(|| {
+            AddressResolver(Arc::new(Inner {
+                #[cfg(feature = "dns")]
+                custom,
+                strategy: config.strategy,
+                happy_eyeballs: config.happy_eyeballs,
+                ports: probe_ports(upstreams),
+                winners: Mutex::new(HashMap::new()),
+            }))
+        });
+        Ok(Self { overrides, resolver })
+    }
+
+    pub(crate) fn apply(&self, builder: ClientBuilder) -> ClientBuilder {
+        match &self.resolver {
+            Some(resolver) => self.apply_with(builder, Arc::new(resolver.clone())),
+            None => self.apply_overrides(builder),
+        }
+    }
+
+    /// 用 `wrap` 包住这里的解析器 (没有时为系统解析) 后再装到客户端上
+    pub(crate) fn apply_wrapped<R: Resolve + 'static>(
+        &self,
+        builder: ClientBuilder,
+        wrap: impl FnOnce(Option<Arc<dyn Resolve>>) -> R,
+    ) -> ClientBuilder {
+        let inner = self.resolver.clone().map(|r| Arc::new(r) as Arc<dyn Resolve>);
+        self.apply_with(builder, Arc::new(wrap(inner)))
+    }
+
+    fn apply_with<R: Resolve + 'static>(&self, builder: ClientBuilder, resolver: Arc<R>) -> ClientBuilder {
+        self.apply_overrides(builder.dns_resolver(resolver))
+    }
+
+    fn apply_overrides(&self, mut builder: ClientBuilder) -> ClientBuilder {
+        for (host, addrs) in &self.overrides {
+            builder = builder.resolve_to_addrs(host, addrs);
+        }
+        builder
+    }
+}
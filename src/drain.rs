@@ -0,0 +1,48 @@
+use std::sync::Arc;
+use tokio::sync::watch;
+use tracing::info;
+
+// --- 摘流 (drain) ---
+// 滚动发布时先摘流再退出：通过管理 API 的 POST /admin/drain 或 SIGUSR1 触发后，
+// /health 返回 503 让负载均衡把实例摘掉；等待 drain_delay 后所有监听地址停止接受新连接
+// (空闲的 keep-alive 连接直接关闭)，已经在处理的请求 (包括流式响应) 继续完成。
+// 全部结束或超过 drain_timeout 后 serve 返回，进程退出。
+// 热重载时新旧网关要共用同一个 Drain，否则对新网关触发的摘流不会让监听停下。
+
+#[derive(Debug, Clone)]
+pub struct Drain {
+    tx: Arc<watch::Sender<bool>>,
+}
+
+impl Default for Drain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drain {
+    pub fn new() -> Self {
+        Self {
+            tx: Arc::new(watch::Sender::new(false)),
+        }
+    }
+
+    /// 开始摘流；已经在摘流时返回 false
+    pub fn start(&self) -> bool {
+        let started = self.tx.send_if_modified(|draining| !std::mem::replace(draining, true));
+        if started {
+            info!("🚰 Draining: /health now reports unhealthy, in-flight requests will be allowed to finish");
+        }
+        started
+    }
+
+    pub fn is_draining(&self) -> bool {
+        *self.tx.borrow()
+    }
+
+    /// 摘流开始时完成
+    pub async fn started(&self) {
+        let mut rx = self.tx.subscribe();
+        let _ = rx.wait_for(|draining| *draining).await;
+    }
+}
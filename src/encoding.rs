@@ -0,0 +1,80 @@
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue};
+
+// --- 上游响应的内容编码 ---
+// 网关请求上游时关闭了 reqwest 的自动解压 (no_gzip 等)，上游的响应体总是原样转发，两种模式：
+//   identity (默认)   转发时去掉客户端的 Accept-Encoding，上游只返回未压缩的响应，
+//                     用量统计、响应改写、错误信息打码等插件都能看到明文；要压缩给客户端用 --compress
+//   passthrough       保留客户端的 Accept-Encoding，上游压缩过的响应体逐字节转发，
+//                     Content-Encoding / Content-Length 和上游一致；压缩过的响应不经过改写类插件，
+//                     也不插入 SSE keepalive，用量统计读不到其中的 usageMetadata
+// 两种模式下都去掉两个方向的逐跳头 (Connection、Transfer-Encoding 等)：分块与否由各自的连接决定，
+// 不会出现上游的 Transfer-Encoding: chunked 和网关缓冲后重新计算的 Content-Length 同时下发。
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EncodingMode {
+    #[default]
+    Identity,
+    Passthrough,
+}
+
+impl std::str::FromStr for EncodingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "identity" => Ok(Self::Identity),
+            "passthrough" => Ok(Self::Passthrough),
+            _ => Err(format!("unknown accept-encoding mode '{}' (expected identity or passthrough)", s)),
+        }
+    }
+}
+
+impl EncodingMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Identity => "identity",
+            Self::Passthrough => "passthrough",
+        }
+    }
+
+    /// 按模式处理转发给上游的 Accept-Encoding
+    pub(crate) fn apply(&self, headers: &mut HeaderMap) {
+        if *self == Self::Identity {
+            headers.remove(header::ACCEPT_ENCODING);
+        }
+    }
+}
+
+const HOP_BY_HOP: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// 去掉逐跳头，包括 Connection 里列出的；`TE: trailers` 保留 (gRPC 要求，见 trailers)
+pub(crate) fn strip_hop_by_hop(headers: &mut HeaderMap) {
+    let trailers = headers
+        .get(header::TE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case("trailers")));
+    let listed: Vec<HeaderName> = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect();
+    for name in listed {
+        headers.remove(name);
+    }
+    for name in HOP_BY_HOP {
+        headers.remove(*name);
+    }
+    if trailers {
+        headers.insert(header::TE, HeaderValue::from_static("trailers"));
+    } else {
+        headers.remove(header::TE);
+    }
+}
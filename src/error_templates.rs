@@ -0,0 +1,130 @@
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::events::RequestId;
+use crate::security_headers::UpstreamResponse;
+use crate::AppState;
+
+// --- 网关错误响应模板 ---
+// 只替换网关自己生成的错误 (鉴权、限流、上游不可达等)，透传的上游响应保持原样。
+// 模板变量: {{status}} {{reason}} {{message}} {{request_id}} {{retry_after}}
+// .json 模板里的变量值会做 JSON 字符串转义。
+
+// 原始错误体只是一行提示，读太多没有意义
+const MAX_ORIGINAL_BODY: usize = 64 * 1024;
+
+struct Template {
+    content_type: HeaderValue,
+    body: String,
+    json: bool,
+}
+
+#[derive(Default)]
+pub struct ErrorTemplates {
+    templates: HashMap<StatusCode, Template>,
+}
+
+impl ErrorTemplates {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 注册模板；content_type 决定变量是否按 JSON 转义
+    pub fn set(&mut self, status: StatusCode, content_type: &str, body: impl Into<String>) -> Result<(), String> {
+        let value = HeaderValue::from_str(content_type).map_err(|e| format!("invalid content type: {}", e))?;
+        self.templates.insert(
+            status,
+            Template {
+                content_type: value,
+                body: body.into(),
+                json: content_type.contains("json"),
+            },
+        );
+        Ok(())
+    }
+
+    /// 解析 `STATUS=FILE`，按扩展名推断 content type
+    pub fn load_spec(&mut self, spec: &str) -> Result<(), String> {
+        let (status, file) = spec
+            .split_once('=')
+            .ok_or_else(|| format!("error template '{}' must be STATUS=FILE", spec))?;
+        let status = status
+            .trim()
+            .parse::<u16>()
+            .ok()
+            .and_then(|s| StatusCode::from_u16(s).ok())
+            .ok_or_else(|| format!("invalid status in error template '{}'", spec))?;
+        let body = std::fs::read_to_string(file).map_err(|e| format!("{}: {}", file, e))?;
+        let content_type = match Path::new(file).extension().and_then(|e| e.to_str()) {
+            Some("json") => "application/json",
+            Some("html") | Some("htm") => "text/html; charset=utf-8",
+            _ => "text/plain; charset=utf-8",
+        };
+        self.set(status, content_type, body)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.templates.is_empty()
+    }
+
+    fn render(&self, status: StatusCode, vars: &[(&str, &str)]) -> Option<(HeaderValue, String)> {
+        let template = self.templates.get(&status)?;
+        let mut body = template.body.clone();
+        for (name, value) in vars {
+            let value = if template.json { json_escape(value) } else { value.to_string() };
+            body = body.replace(&format!("{{{{{}}}}}", name), &value);
+        }
+        Some((template.content_type.clone(), body))
+    }
+}
+
+fn json_escape(value: &str) -> String {
+    let quoted = serde_json::to_string(value).unwrap_or_default();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+pub(crate) async fn apply(State(state): State<Arc<AppState>>, req: Request, next: Next) -> Response {
+    let Some(templates) = &state.error_templates else {
+        return next.run(req).await;
+    };
+    let request_id = req.extensions().get::<RequestId>().map(|id| id.0);
+
+    let response = next.run(req).await;
+    let status = response.status();
+    if !templates.templates.contains_key(&status) || response.extensions().get::<UpstreamResponse>().is_some() {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let original = axum::body::to_bytes(body, MAX_ORIGINAL_BODY).await.unwrap_or_default();
+    let message = String::from_utf8_lossy(&original);
+    let request_id = request_id.map(|id| id.to_string()).unwrap_or_default();
+    let retry_after = parts
+        .headers
+        .get(header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+
+    let vars = [
+        ("status", status.as_str()),
+        ("reason", status.canonical_reason().unwrap_or("")),
+        ("message", message.trim()),
+        ("request_id", request_id.as_str()),
+        ("retry_after", retry_after.as_str()),
+    ];
+    let Some((content_type, rendered)) = templates.render(status, &vars) else {
+        return Response::from_parts(parts, Body::from(original));
+    };
+    parts.headers.insert(header::CONTENT_TYPE, content_type);
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(rendered))
+}
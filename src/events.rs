@@ -0,0 +1,90 @@
+use serde::Serialize;
+use std::net::IpAddr;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+// --- 请求生命周期事件 ---
+// 代理热路径只负责把事件丢进 broadcast channel，订阅方 (插件、面板、导出器) 自己消费。
+// 没有订阅者时不构造事件；订阅方消费太慢会丢掉最旧的事件 (RecvError::Lagged)。
+
+const DEFAULT_CAPACITY: usize = 1024;
+
+/// 网关入口分配的请求编号，挂在请求 extensions 上
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestId(pub u64);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum GatewayEvent {
+    /// 请求体读取完毕，即将进入插件链
+    RequestStarted {
+        id: u64,
+        method: String,
+        path: String,
+        client_ip: IpAddr,
+    },
+    /// 收到上游响应头
+    UpstreamConnected { id: u64, status: u16, elapsed_ms: u64 },
+    /// 第一个响应数据块转发给客户端
+    FirstByte { id: u64, elapsed_ms: u64 },
+    /// 正常结束 (包括插件直接返回的响应)
+    Completed {
+        id: u64,
+        status: u16,
+        bytes_out: u64,
+        elapsed_ms: u64,
+    },
+    /// 上游连接失败、流中断或客户端断开
+    Failed {
+        id: u64,
+        status: Option<u16>,
+        error: String,
+        elapsed_ms: u64,
+    },
+}
+
+impl GatewayEvent {
+    pub fn request_id(&self) -> u64 {
+        match self {
+            Self::RequestStarted { id, .. }
+            | Self::UpstreamConnected { id, .. }
+            | Self::FirstByte { id, .. }
+            | Self::Completed { id, .. }
+            | Self::Failed { id, .. } => *id,
+        }
+    }
+}
+
+/// 事件总线句柄，clone 后共享同一个 channel
+#[derive(Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<GatewayEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity.max(1));
+        Self { tx }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<GatewayEvent> {
+        self.tx.subscribe()
+    }
+
+    /// 有订阅者时才调用 `make` 构造事件
+    pub fn emit_with(&self, make: impl FnOnce() -> GatewayEvent) {
+        if self.tx.receiver_count() > 0 {
+            let _ = self.tx.send(make());
+        }
+    }
+}
+
+pub(crate) fn elapsed_ms(elapsed: Duration) -> u64 {
+    elapsed.as_millis() as u64
+}
@@ -0,0 +1,245 @@
+use reqwest::Client;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::ledger::{self, LedgerEntry, PendingWrites};
+use crate::storage::Storage;
+
+// --- 定时用量导出 ---
+// 定期把账本里新增的记录按 (日期, 客户端, 项目, key, 模型) 汇总后写到本地文件或 POST 到外部地址。
+// 导出进度 (水位) 存在存储后端里，重启后不会重复导出。账本是异步写入的，水位只推进到
+// 最早一条还没写完的记录之前，不会跳过晚落盘的记录。
+
+const WATERMARK_KEY: &str = "export:watermark";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    /// 文件里每行一个对象；HTTP 时是 JSON 数组
+    Json,
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            other => Err(format!("unknown export format '{}'", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ExportSink {
+    /// 追加写入
+    File(String),
+    /// POST 到该地址
+    Http(String),
+}
+
+impl ExportSink {
+    /// http(s):// 开头视为 URL，其他视为文件路径
+    pub fn parse(spec: &str) -> Self {
+        if spec.starts_with("http://") || spec.starts_with("https://") {
+            Self::Http(spec.to_string())
+        } else {
+            Self::File(spec.to_string())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageSummary {
+    pub day: String,
+    pub client: String,
+    pub project: String,
+    pub key: String,
+    pub model: String,
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub output_tokens: u64,
+    pub cost: f64,
+}
+
+/// 1970-01-01 起的天数 -> (年, 月, 日)，Howard Hinnant 的 civil_from_days
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month as u32, day as u32)
+}
+
+/// unix 毫秒 -> UTC 日期 YYYY-MM-DD
+pub fn utc_day(ts_ms: u64) -> String {
+    let (year, month, day) = civil_from_days((ts_ms / 86_400_000) as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+pub fn summarize(entries: &[LedgerEntry]) -> Vec<UsageSummary> {
+    type Group = (String, String, String, String, String);
+    let mut groups: BTreeMap<Group, UsageSummary> = BTreeMap::new();
+    for entry in entries {
+        let day = utc_day(entry.ts);
+        let project = entry.project.clone().unwrap_or_default();
+        let key = entry.key.clone().unwrap_or_default();
+        let group = (day.clone(), entry.client.clone(), project.clone(), key.clone(), entry.model.clone());
+        let summary = groups
+            .entry(group)
+            .or_insert_with(|| UsageSummary {
+                day,
+                client: entry.client.clone(),
+                project,
+                key,
+                model: entry.model.clone(),
+                requests: 0,
+                prompt_tokens: 0,
+                output_tokens: 0,
+                cost: 0.0,
+            });
+        summary.requests += 1;
+        summary.prompt_tokens += entry.prompt_tokens;
+        summary.output_tokens += entry.output_tokens;
+        summary.cost += entry.cost;
+    }
+    groups.into_values().collect()
+}
+
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+const CSV_HEADER: &str = "day,client,project,key,model,requests,prompt_tokens,output_tokens,cost\n";
+
+fn to_csv(rows: &[UsageSummary], header: bool) -> String {
+    let mut out = if header { CSV_HEADER.to_string() } else { String::new() };
+    for row in rows {
+        let _ = writeln!(
+            out,
+            "{},{},{},{},{},{},{},{},{:.6}",
+            row.day,
+            csv_field(&row.client),
+            csv_field(&row.project),
+            csv_field(&row.key),
+            csv_field(&row.model),
+            row.requests,
+            row.prompt_tokens,
+            row.output_tokens,
+            row.cost
+        );
+    }
+    out
+}
+
+pub struct UsageExporter {
+    storage: Arc<dyn Storage>,
+    sink: ExportSink,
+    format: ExportFormat,
+    client: Client,
+    pending: PendingWrites,
+}
+
+impl UsageExporter {
+    pub fn new(storage: Arc<dyn Storage>, sink: ExportSink, format: ExportFormat) -> Self {
+        Self {
+            storage,
+            sink,
+            format,
+            client: Client::new(),
+            pending: PendingWrites::default(),
+        }
+    }
+
+    /// 同一进程里账本的在途写入 ([`crate::ledger::Ledger::pending`])
+    pub fn pending(mut self, pending: PendingWrites) -> Self {
+        self.pending = pending;
+        self
+    }
+
+    /// 导出水位之后的全部记录，返回导出的汇总行数
+    pub async fn run_once(&self) -> Result<usize, String> {
+        let now = self.pending.horizon();
+        let since = match self.storage.get(WATERMARK_KEY).await? {
+            Some(value) => String::from_utf8_lossy(&value).parse().unwrap_or(0),
+            None => 0,
+        };
+
+        let entries = ledger::query(self.storage.as_ref(), since, now, None).await?;
+        let rows = summarize(&entries);
+        if !rows.is_empty() {
+            self.write(&rows).await?;
+        }
+        self.storage.set(WATERMARK_KEY, now.to_string().into_bytes(), None).await?;
+        Ok(rows.len())
+    }
+
+    async fn write(&self, rows: &[UsageSummary]) -> Result<(), String> {
+        match &self.sink {
+            ExportSink::File(path) => {
+                let new_file = std::fs::metadata(path).map(|m| m.len() == 0).unwrap_or(true);
+                let data = match self.format {
+                    ExportFormat::Csv => to_csv(rows, new_file),
+                    ExportFormat::Json => rows
+                        .iter()
+                        .filter_map(|r| serde_json::to_string(r).ok())
+                        .map(|line| line + "\n")
+                        .collect(),
+                };
+                let mut file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| format!("{}: {}", path, e))?;
+                file.write_all(data.as_bytes()).map_err(|e| format!("{}: {}", path, e))
+            }
+            ExportSink::Http(url) => {
+                let request = match self.format {
+                    ExportFormat::Csv => self
+                        .client
+                        .post(url)
+                        .header("content-type", "text/csv")
+                        .body(to_csv(rows, true)),
+                    ExportFormat::Json => self.client.post(url).json(rows),
+                };
+                let response = request.send().await.map_err(|e| format!("{}: {}", url, e))?;
+                if !response.status().is_success() {
+                    return Err(format!("{}: HTTP {}", url, response.status()));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// 后台按固定间隔导出；失败时保留水位，下次重试
+    pub fn spawn(self, interval: Duration) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            // 第一次 tick 立即返回，跳过它，避免启动时就导出
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                match self.run_once().await {
+                    Ok(0) => {}
+                    Ok(rows) => info!("📤 Exported {} usage rows", rows),
+                    Err(e) => warn!("📤 Usage export failed: {}", e),
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,109 @@
+use async_trait::async_trait;
+use axum::body::Bytes;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::plugin::{GatewayPlugin, Outcome, RequestContext, UpstreamTarget};
+use crate::proxy::UpstreamPlan;
+use crate::sanitize::{sanitize_headers, sanitize_path};
+
+// --- 失败请求留存 ---
+// 上游 5xx / 429、连接失败或中途断流的请求，脱敏后保留最近 N 条在内存里，
+// 管理 API 可以列出并重新发给上游，排查偶发故障时不用再让客户端复现。
+// 凭证类请求头和 key 参数不会保留，重放时需要由管理员补上 (或由网关侧注入 key)。
+
+// 单条请求体最多保留这么多字节，超出的请求不能重放
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedRequest {
+    pub id: u64,
+    /// unix 毫秒
+    pub at: u64,
+    pub method: String,
+    /// 已脱敏的 path + query
+    pub path: String,
+    /// 当时使用的上游地址
+    pub target: Option<String>,
+    pub headers: Vec<(String, String)>,
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    pub duration_ms: u64,
+    /// 请求体被截断时为 true，这类请求不能重放
+    pub truncated: bool,
+    #[serde(skip)]
+    pub body: Bytes,
+    /// 当时怎么选的 key 和客户端，重放时照原样选
+    #[serde(skip)]
+    #[cfg_attr(not(feature = "admin"), allow(dead_code))]
+    pub(crate) plan: UpstreamPlan,
+}
+
+pub struct FailureLog {
+    capacity: usize,
+    entries: Mutex<VecDeque<FailedRequest>>,
+}
+
+impl FailureLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// 按时间倒序
+    pub fn list(&self) -> Vec<FailedRequest> {
+        self.entries.lock().unwrap().iter().rev().cloned().collect()
+    }
+
+    pub fn get(&self, id: u64) -> Option<FailedRequest> {
+        self.entries.lock().unwrap().iter().find(|e| e.id == id).cloned()
+    }
+
+    fn push(&self, entry: FailedRequest) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+}
+
+fn is_failure(outcome: &Outcome) -> bool {
+    match outcome.status {
+        Some(status) => status.is_server_error() || status.as_u16() == 429 || outcome.error.is_some(),
+        None => true,
+    }
+}
+
+#[async_trait]
+impl GatewayPlugin for FailureLog {
+    fn name(&self) -> &str {
+        "failure-log"
+    }
+
+    fn on_complete(&self, ctx: &RequestContext, outcome: &Outcome) {
+        if !is_failure(outcome) {
+            return;
+        }
+        let truncated = ctx.body.len() > MAX_BODY_BYTES;
+        let path = ctx.uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+        self.push(FailedRequest {
+            id: ctx.id,
+            at: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64,
+            method: ctx.method.to_string(),
+            path: sanitize_path(path),
+            target: ctx.extensions.get::<UpstreamTarget>().map(|t| t.0.clone()),
+            headers: sanitize_headers(&ctx.headers),
+            status: outcome.status.map(|s| s.as_u16()),
+            error: outcome.error.clone(),
+            duration_ms: outcome.duration.as_millis() as u64,
+            truncated,
+            body: if truncated { Bytes::new() } else { ctx.body.clone() },
+            plan: ctx.extensions.get::<UpstreamPlan>().cloned().unwrap_or_default(),
+        });
+    }
+}
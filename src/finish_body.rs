@@ -0,0 +1,102 @@
+use axum::body::{Body, Bytes};
+use axum::http::header;
+use axum::response::Response;
+use http_body::Body as HttpBody;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+// --- 响应体结束回调 ---
+// 访问日志、慢请求日志、按路由的耗时指标都要等响应体真正结束才能记，结束有几种情况：
+//   - 读到流结束或出错；
+//   - 按 Content-Length 发完后 hyper 不会再轮询到流结束；
+//   - 客户端断开，或者空响应体一次都没被轮询就被丢弃。
+
+/// 观察经过的响应体，结束时回调一次
+pub(crate) trait BodyWatcher: Send + Unpin + 'static {
+    /// 每个数据帧发给客户端前调用
+    fn on_data(&mut self, _data: &Bytes) {}
+
+    /// 响应体结束时调用；incomplete 表示没有发完 (出错或客户端断开)
+    fn on_finish(self, incomplete: bool);
+}
+
+/// 把响应体换成带 watcher 的包装
+pub(crate) fn watch<W: BodyWatcher>(response: Response, watcher: W) -> Response {
+    let (parts, body) = response.into_parts();
+    let expected_len = parts
+        .headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .or_else(|| body.size_hint().exact());
+    let body = WatchedBody {
+        inner: body,
+        expected_len,
+        sent: 0,
+        watcher: Some(watcher),
+    };
+    Response::from_parts(parts, Body::new(body))
+}
+
+struct WatchedBody<W: BodyWatcher> {
+    inner: Body,
+    expected_len: Option<u64>,
+    sent: u64,
+    // 回调过后置为 None
+    watcher: Option<W>,
+}
+
+impl<W: BodyWatcher> WatchedBody<W> {
+    fn done(&self) -> bool {
+        self.inner.is_end_stream() || self.expected_len.is_some_and(|len| self.sent >= len)
+    }
+
+    fn finish(&mut self, incomplete: bool) {
+        if let Some(watcher) = self.watcher.take() {
+            watcher.on_finish(incomplete);
+        }
+    }
+}
+
+impl<W: BodyWatcher> HttpBody for WatchedBody<W> {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<http_body::Frame<Self::Data>, Self::Error>>> {
+        let this = &mut *self;
+        let polled = Pin::new(&mut this.inner).poll_frame(cx);
+        match &polled {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let (Some(data), Some(watcher)) = (frame.data_ref(), this.watcher.as_mut()) {
+                    watcher.on_data(data);
+                    this.sent += data.len() as u64;
+                }
+                if this.done() {
+                    this.finish(false);
+                }
+            }
+            Poll::Ready(Some(Err(_))) => this.finish(true),
+            Poll::Ready(None) => this.finish(false),
+            Poll::Pending => {}
+        }
+        polled
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<W: BodyWatcher> Drop for WatchedBody<W> {
+    fn drop(&mut self) {
+        let incomplete = !self.done();
+        self.finish(incomplete);
+    }
+}
@@ -0,0 +1,1944 @@
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{any, get, post, Route},
+    Extension, Json, Router,
+};
+use serde_json::json;
+use std::convert::Infallible;
+use tower::{Layer, Service};
+use reqwest::{Certificate, Client, Identity, Proxy};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use futures_util::FutureExt;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::access_log::{self, AccessLog};
+#[cfg(feature = "geoip")]
+use crate::geoip::{self, GeoIp};
+#[cfg(feature = "admin")]
+use crate::admin;
+use crate::bandwidth::{self, Bandwidth};
+use crate::batch::{self, BatchConfig, BatchFanout};
+use crate::build_info;
+use crate::burst::{self, BurstGuard};
+use crate::cached_contents::CachedContents;
+use crate::cidr::Cidr;
+use crate::client_auth::{self, ClientTokens};
+use crate::client_ip;
+use crate::coalesce::{self, Coalescer};
+use crate::cors::{self, Cors};
+use crate::credits::CreditAccounts;
+#[cfg(feature = "admin")]
+use crate::dashboard::Dashboard;
+use crate::dns::{DnsConfig, IpStrategy, Resolvers};
+use crate::drain::Drain;
+use crate::egress::{self, EgressPool, EgressPoolConfig, EgressStatus, UpstreamHttpConfig, UpstreamProtocol};
+use crate::encoding::EncodingMode;
+use crate::error_templates::{self, ErrorTemplates};
+use crate::failures::FailureLog;
+use crate::grpc::GrpcConfig;
+use crate::events::{EventBus, RequestId};
+use crate::header_rules::{self, HeaderRule};
+use crate::inspector::RequestInspector;
+use crate::ip_filter::{self, IpFilter};
+use crate::key_pool::{self, KeyPool, KeySource, QuarantineWebhook};
+use crate::request_queue::{QueueConfig, RequestQueue};
+use crate::load_shed::{self, InflightConfig, InflightLimit};
+use crate::lockout::{self, AuthLockout};
+use crate::maintenance::{self, Maintenance, MaintenanceState};
+use crate::metrics::Metrics;
+#[cfg(feature = "metrics")]
+use crate::request_metrics::{self, ModelLabels};
+use crate::model_fallback::ModelFallbacks;
+use crate::openai;
+use crate::plugin::{GatewayPlugin, Plugins};
+use crate::prewarm::{self, Prewarm, PrewarmConfig};
+use crate::project::{self, Projects};
+use crate::providers::Provider;
+use crate::proxy::proxy_handler;
+use crate::mirror::{Mirror, MirrorConfig};
+use crate::canary::{Canary, CanaryConfig};
+use crate::retry::RetryConfig;
+use crate::path_allowlist::PathAllowlist;
+use crate::scanner::{self, ScannerGuard};
+use crate::security_headers::{self, SecurityHeaders};
+#[cfg(feature = "compression")]
+use crate::compression::{self, CompressionConfig};
+use crate::signed_url::{self, UrlSigner};
+use crate::slow_log;
+use crate::storage::{MemoryStorage, Storage};
+use crate::rewrite::RewriteRule;
+use crate::routes::{RouteRule, RoutingTable};
+use crate::target_policy::{PolicyResolver, TargetPolicy};
+use crate::tenant::{self, Tenants};
+#[cfg(feature = "tls")]
+use crate::tls::{self, TlsCerts};
+use crate::uploads::{UploadConfig, UploadSessions};
+use crate::upstreams::{self, Balancing, HealthCheckConfig, UpstreamStatus, Upstreams, WeightedTarget};
+
+pub const DEFAULT_TARGET: &str = "https://generativelanguage.googleapis.com";
+
+#[cfg(feature = "admin")]
+static STARTED: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+
+// --- 运行时共享状态 ---
+pub(crate) struct AppState {
+    pub(crate) client: Client,
+    /// 默认上游中的第一个
+    pub(crate) target_url: String,
+    pub(crate) upstreams: Upstreams,
+    /// 多个全局代理组成的代理池
+    pub(crate) egress: Option<EgressPool>,
+    #[cfg(feature = "geoip")]
+    pub(crate) geoip: Option<GeoIp>,
+    pub(crate) scanner: Option<ScannerGuard>,
+    /// 允许转发的上游路径
+    pub(crate) path_allowlist: Option<PathAllowlist>,
+    /// 响应体下行带宽限制
+    pub(crate) bandwidth: Option<Bandwidth>,
+    pub(crate) signer: Option<UrlSigner>,
+    pub(crate) lockout: Option<AuthLockout>,
+    pub(crate) burst: Option<BurstGuard>,
+    pub(crate) client_tokens: Option<ClientTokens>,
+    pub(crate) security_headers: Option<SecurityHeaders>,
+    #[cfg(feature = "compression")]
+    pub(crate) compression: Option<CompressionConfig>,
+    pub(crate) error_templates: Option<ErrorTemplates>,
+    pub(crate) tenants: Option<Tenants>,
+    pub(crate) projects: Option<Projects>,
+    pub(crate) maintenance: Arc<Maintenance>,
+    pub(crate) drain: Drain,
+    pub(crate) routes: RoutingTable,
+    pub(crate) batch: Option<BatchFanout>,
+    pub(crate) openai_compat: bool,
+    pub(crate) coalescer: Option<Coalescer>,
+    pub(crate) access_log: Option<Arc<AccessLog>>,
+    pub(crate) max_request_size: usize,
+    pub(crate) max_response_size: Option<u64>,
+    pub(crate) stream_idle_timeout: Option<Duration>,
+    pub(crate) sse_keepalive: Option<Duration>,
+    pub(crate) accept_encoding: EncodingMode,
+    pub(crate) grpc: Option<GrpcConfig>,
+    pub(crate) uploads: Option<UploadSessions>,
+    pub(crate) prewarm: Option<Prewarm>,
+    pub(crate) slow_request: Option<Duration>,
+    #[cfg(feature = "admin")]
+    pub(crate) dns: DnsConfig,
+    // gRPC 只走 HTTP/2
+    pub(crate) grpc_client: Option<Client>,
+    // 运行时设置的路由用的客户端：解析结果按 SSRF 策略过滤、不跟随重定向；
+    // 策略允许内网或者走代理 (域名由代理解析) 时为 None，和其他上游共用客户端
+    pub(crate) dynamic_client: Option<Client>,
+    #[cfg(feature = "admin")]
+    pub(crate) connect_timeout: Duration,
+    pub(crate) first_byte_timeout: Duration,
+    pub(crate) total_timeout: Option<Duration>,
+    pub(crate) inflight: Option<InflightLimit>,
+    pub(crate) request_queue: Option<RequestQueue>,
+    /// 当前在处理的代理请求数
+    pub(crate) in_flight: Arc<AtomicUsize>,
+    pub(crate) trusted_proxies: Vec<Cidr>,
+    pub(crate) ip_filter: Option<IpFilter>,
+    pub(crate) cors: Option<Cors>,
+    pub(crate) rewrites: Vec<RewriteRule>,
+    pub(crate) providers: Vec<Provider>,
+    pub(crate) header_rules: Vec<HeaderRule>,
+    pub(crate) model_fallbacks: Option<ModelFallbacks>,
+    pub(crate) retry: Option<RetryConfig>,
+    /// 流量镜像到影子上游
+    pub(crate) mirror: Option<Mirror>,
+    /// 分走一部分默认上游流量的金丝雀上游
+    pub(crate) canary: Option<Canary>,
+    pub(crate) key_pool: Option<KeyPool>,
+    /// key 被隔离时通知的 webhook
+    pub(crate) quarantine_webhook: Option<QuarantineWebhook>,
+    // key 池从文件 / 地址加载时，后台和管理 API 从这里重新读取
+    pub(crate) key_source: Option<KeySource>,
+    #[cfg(feature = "admin")]
+    pub(crate) credits: Option<Arc<CreditAccounts>>,
+    #[cfg(feature = "admin")]
+    pub(crate) cached_contents: Option<Arc<CachedContents>>,
+    #[cfg(feature = "admin")]
+    pub(crate) failures: Option<Arc<FailureLog>>,
+    #[cfg(feature = "admin")]
+    pub(crate) inspector: Option<Arc<RequestInspector>>,
+    #[cfg(feature = "admin")]
+    pub(crate) dashboard: Option<Arc<Dashboard>>,
+    pub(crate) target_policy: TargetPolicy,
+    #[cfg(feature = "admin")]
+    pub(crate) admin_token: Option<String>,
+    #[cfg(feature = "admin")]
+    pub(crate) log_level: Option<LogLevelControl>,
+    pub(crate) plugins: Plugins,
+    pub(crate) metrics: Metrics,
+    // /readyz 在后台探测上游用的设置 (同主动健康检查)
+    pub(crate) health_probe: HealthCheckConfig,
+    #[cfg(feature = "metrics")]
+    pub(crate) model_labels: ModelLabels,
+    pub(crate) events: EventBus,
+    pub(crate) storage: Arc<dyn Storage>,
+    pub(crate) request_ids: AtomicU64,
+    #[cfg(feature = "admin")]
+    pub(crate) started: std::time::Instant,
+}
+
+impl AppState {
+    /// 网关需要整个读进内存的上游响应体 (格式转换、插件改写) 的上限：--max-response-size，没配置时同请求体上限
+    pub(crate) fn buffer_limit(&self) -> usize {
+        self.max_response_size.map_or(self.max_request_size, |limit| limit.try_into().unwrap_or(usize::MAX))
+    }
+
+    /// 上游通过 SSRF 策略校验后才写入路由表；返回是否覆盖了已有规则
+    pub(crate) async fn set_route(&self, mut rule: RouteRule) -> Result<bool, String> {
+        if !rule.prefix.starts_with('/') {
+            return Err("prefix must start with '/'".to_string());
+        }
+        rule.validate()?;
+        self.check_key_group(&rule)?;
+        let url = self
+            .target_policy
+            .check(&rule.target)
+            .await
+            .map_err(|e| format!("target rejected: {}", e))?;
+        rule.target = url.as_str().trim_end_matches('/').to_string();
+        rule.dynamic = true;
+        Ok(self.routes.upsert(rule))
+    }
+
+    // 路由指定的 key 分组要存在，否则这条路由上的请求一个 key 都选不到
+    pub(crate) fn check_key_group(&self, rule: &RouteRule) -> Result<(), String> {
+        let Some(group) = &rule.key_group else {
+            return Ok(());
+        };
+        match &self.key_pool {
+            Some(pool) if pool.has_group(group) => Ok(()),
+            Some(_) => Err(format!("route '{}': unknown key group '{}'", rule.id, group)),
+            None => Err(format!("route '{}': key group '{}' needs a key pool", rule.id, group)),
+        }
+    }
+}
+
+/// 自定义 tower layer 的挂载位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerPosition {
+    /// 代理路由上、所有访问控制 (扫描器、租户、GeoIP、锁定、签名) 之前
+    PreAuth,
+    /// 代理路由上、访问控制之后，紧挨着转发
+    PreProxy,
+    /// 包住整个 Router (含 health / metrics)，看到的是错误模板和安全头处理后的最终响应
+    PostResponse,
+}
+
+/// 运行时调整日志级别，由宿主程序提供实际的切换逻辑 (通常是 tracing-subscriber 的 reload handle)，
+/// 管理 API 通过 `PUT /admin/log-level` 调用
+#[cfg(feature = "admin")]
+#[derive(Clone)]
+pub struct LogLevelControl {
+    current: Arc<RwLock<String>>,
+    apply: LogLevelFn,
+}
+
+#[cfg(feature = "admin")]
+type LogLevelFn = Arc<dyn Fn(&str) -> Result<(), String> + Send + Sync>;
+
+#[cfg(feature = "admin")]
+impl LogLevelControl {
+    pub fn new(initial: impl Into<String>, apply: impl Fn(&str) -> Result<(), String> + Send + Sync + 'static) -> Self {
+        Self {
+            current: Arc::new(RwLock::new(initial.into())),
+            apply: Arc::new(apply),
+        }
+    }
+
+    pub fn get(&self) -> String {
+        self.current.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// 切换失败 (如过滤表达式不合法) 时保持原级别
+    pub fn set(&self, level: &str) -> Result<(), String> {
+        (self.apply)(level)?;
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = level.to_string();
+        Ok(())
+    }
+}
+
+type LayerFn = Arc<dyn Fn(Router<Arc<AppState>>) -> Router<Arc<AppState>> + Send + Sync>;
+
+/// 一个配置好的网关实例
+pub struct Gateway {
+    state: Arc<AppState>,
+    listen: Vec<SocketAddr>,
+    // 配置了 TLS 时仍然走明文的地址
+    plaintext: Vec<SocketAddr>,
+    unix_listen: Vec<PathBuf>,
+    unix_socket_mode: u32,
+    #[cfg(feature = "tls")]
+    tls: Option<Arc<TlsCerts>>,
+    #[cfg(feature = "http3")]
+    http3: bool,
+    layers: Vec<(LayerPosition, LayerFn)>,
+    #[cfg(feature = "admin")]
+    admin_listen: Option<SocketAddr>,
+    #[cfg(feature = "metrics")]
+    metrics_public: bool,
+    drain_delay: Duration,
+    drain_timeout: Duration,
+}
+
+/// 通过 [`Gateway::builder`] 创建
+pub struct GatewayBuilder {
+    targets: Vec<WeightedTarget>,
+    target_cooldown: Duration,
+    target_balancing: Balancing,
+    health_check: Option<HealthCheckConfig>,
+    listen: Vec<SocketAddr>,
+    // 配置了 TLS 时仍然走明文的地址
+    plaintext: Vec<SocketAddr>,
+    unix_listen: Vec<PathBuf>,
+    unix_socket_mode: u32,
+    #[cfg(feature = "tls")]
+    tls: Option<(PathBuf, PathBuf)>,
+    #[cfg(feature = "http3")]
+    http3: bool,
+    proxies: Vec<String>,
+    proxy_auth: Option<String>,
+    proxy_pool: EgressPoolConfig,
+    upstream_client_cert: Option<(PathBuf, PathBuf)>,
+    ca_certs: Vec<PathBuf>,
+    insecure: bool,
+    #[cfg(feature = "geoip")]
+    geoip: Option<GeoIp>,
+    scanner: Option<ScannerGuard>,
+    path_allowlist: Option<PathAllowlist>,
+    bandwidth: Option<Bandwidth>,
+    signer: Option<UrlSigner>,
+    lockout: Option<AuthLockout>,
+    burst: Option<BurstGuard>,
+    client_tokens: Option<ClientTokens>,
+    security_headers: Option<SecurityHeaders>,
+    #[cfg(feature = "compression")]
+    compression: Option<CompressionConfig>,
+    error_templates: Option<ErrorTemplates>,
+    tenants: Option<Tenants>,
+    projects: Option<Projects>,
+    maintenance: MaintenanceState,
+    drain: Drain,
+    drain_delay: Duration,
+    drain_timeout: Duration,
+    routes: Vec<RouteRule>,
+    batch: Option<BatchConfig>,
+    openai_compat: bool,
+    coalesce_get: bool,
+    access_log: Option<Arc<AccessLog>>,
+    max_request_size: usize,
+    max_response_size: Option<u64>,
+    stream_idle_timeout: Option<Duration>,
+    sse_keepalive: Option<Duration>,
+    accept_encoding: EncodingMode,
+    grpc: Option<GrpcConfig>,
+    uploads: Option<UploadConfig>,
+    prewarm: Option<PrewarmConfig>,
+    slow_request: Option<Duration>,
+    dns: DnsConfig,
+    connect_timeout: Duration,
+    upstream_http: UpstreamHttpConfig,
+    first_byte_timeout: Duration,
+    total_timeout: Option<Duration>,
+    inflight: Option<InflightConfig>,
+    request_queue: Option<QueueConfig>,
+    trusted_proxies: Vec<Cidr>,
+    ip_filter: Option<IpFilter>,
+    cors: Option<Cors>,
+    rewrites: Vec<RewriteRule>,
+    providers: Vec<Provider>,
+    header_rules: Vec<HeaderRule>,
+    model_fallbacks: Option<ModelFallbacks>,
+    retry: Option<RetryConfig>,
+    mirror: Option<MirrorConfig>,
+    canary: Option<CanaryConfig>,
+    key_pool: Option<KeyPool>,
+    quarantine_webhook: Option<QuarantineWebhook>,
+    key_source: Option<KeySource>,
+    #[cfg(feature = "admin")]
+    credits: Option<Arc<CreditAccounts>>,
+    #[cfg(feature = "admin")]
+    cached_contents: Option<Arc<CachedContents>>,
+    #[cfg(feature = "admin")]
+    failures: Option<Arc<FailureLog>>,
+    #[cfg(feature = "admin")]
+    inspector: Option<Arc<RequestInspector>>,
+    #[cfg(feature = "admin")]
+    dashboard: Option<Arc<Dashboard>>,
+    target_policy: TargetPolicy,
+    #[cfg(feature = "admin")]
+    admin_token: Option<String>,
+    #[cfg(feature = "admin")]
+    admin_listen: Option<SocketAddr>,
+    #[cfg(feature = "metrics")]
+    metrics_public: bool,
+    #[cfg(feature = "admin")]
+    log_level: Option<LogLevelControl>,
+    plugins: Vec<Arc<dyn GatewayPlugin>>,
+    layers: Vec<(LayerPosition, LayerFn)>,
+    events: EventBus,
+    storage: Arc<dyn Storage>,
+}
+
+impl Default for GatewayBuilder {
+    fn default() -> Self {
+        Self {
+            targets: vec![WeightedTarget {
+                url: DEFAULT_TARGET.to_string(),
+                weight: 1,
+                proxy: None,
+            }],
+            target_cooldown: Duration::from_secs(30),
+            target_balancing: Balancing::Weighted,
+            health_check: None,
+            listen: vec![SocketAddr::from(([0, 0, 0, 0], 3000))],
+            plaintext: Vec::new(),
+            unix_listen: Vec::new(),
+            unix_socket_mode: 0o660,
+            #[cfg(feature = "tls")]
+            tls: None,
+            #[cfg(feature = "http3")]
+            http3: false,
+            proxies: Vec::new(),
+            proxy_auth: None,
+            proxy_pool: EgressPoolConfig::default(),
+            upstream_client_cert: None,
+            ca_certs: Vec::new(),
+            insecure: false,
+            #[cfg(feature = "geoip")]
+            geoip: None,
+            scanner: None,
+            path_allowlist: None,
+            bandwidth: None,
+            signer: None,
+            lockout: None,
+            burst: None,
+            client_tokens: None,
+            security_headers: None,
+            #[cfg(feature = "compression")]
+            compression: None,
+            error_templates: None,
+            tenants: None,
+            projects: None,
+            maintenance: MaintenanceState::default(),
+            drain: Drain::new(),
+            drain_delay: Duration::ZERO,
+            drain_timeout: Duration::from_secs(30),
+            routes: Vec::new(),
+            batch: None,
+            openai_compat: false,
+            coalesce_get: false,
+            access_log: None,
+            max_request_size: 64 * 1024 * 1024,
+            max_response_size: None,
+            stream_idle_timeout: None,
+            sse_keepalive: None,
+            accept_encoding: EncodingMode::default(),
+            grpc: None,
+            uploads: None,
+            prewarm: None,
+            slow_request: None,
+            dns: DnsConfig::default(),
+            connect_timeout: Duration::from_secs(10),
+            upstream_http: UpstreamHttpConfig::default(),
+            first_byte_timeout: Duration::from_secs(120),
+            total_timeout: None,
+            inflight: None,
+            request_queue: None,
+            trusted_proxies: Vec::new(),
+            ip_filter: None,
+            cors: None,
+            rewrites: Vec::new(),
+            providers: Vec::new(),
+            header_rules: Vec::new(),
+            model_fallbacks: None,
+            retry: None,
+            mirror: None,
+            canary: None,
+            key_pool: None,
+            quarantine_webhook: None,
+            key_source: None,
+            #[cfg(feature = "admin")]
+            credits: None,
+            #[cfg(feature = "admin")]
+            cached_contents: None,
+            #[cfg(feature = "admin")]
+            failures: None,
+            #[cfg(feature = "admin")]
+            inspector: None,
+            #[cfg(feature = "admin")]
+            dashboard: None,
+            target_policy: TargetPolicy::default(),
+            #[cfg(feature = "admin")]
+            admin_token: None,
+            #[cfg(feature = "admin")]
+            admin_listen: None,
+            #[cfg(feature = "metrics")]
+            metrics_public: false,
+            #[cfg(feature = "admin")]
+            log_level: None,
+            plugins: Vec::new(),
+            layers: Vec::new(),
+            events: EventBus::default(),
+            storage: Arc::new(MemoryStorage::new()),
+        }
+    }
+}
+
+impl GatewayBuilder {
+    /// 上游地址，默认 Gemini 官方 endpoint
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        let url = target.into().trim_end_matches('/').to_string();
+        self.targets = vec![WeightedTarget { url, weight: 1, proxy: None }];
+        self
+    }
+
+    /// 多个上游按权重轮询，连接失败或 5xx 时换下一个重试
+    pub fn targets(mut self, targets: Vec<WeightedTarget>) -> Self {
+        self.targets = targets;
+        self
+    }
+
+    /// 上游连接失败或返回 5xx 后暂停使用的时长，默认 30 秒
+    pub fn target_cooldown(mut self, cooldown: Duration) -> Self {
+        self.target_cooldown = cooldown;
+        self
+    }
+
+    /// 多个默认上游之间的选择策略，默认按权重轮询
+    pub fn target_balancing(mut self, balancing: Balancing) -> Self {
+        self.target_balancing = balancing;
+        self
+    }
+
+    /// 定期主动探测默认上游，连续失败的移出轮询；需要在 tokio 运行时里调用 build
+    pub fn health_check(mut self, config: HealthCheckConfig) -> Self {
+        self.health_check = Some(config);
+        self
+    }
+
+    pub fn listen(mut self, addr: SocketAddr) -> Self {
+        self.listen = vec![addr];
+        self
+    }
+
+    /// 同时监听多个地址。单独的 `[::]` 会接受 v4-mapped 连接 (双栈)；
+    /// 同一端口上同时列出 v4 和 v6 地址时，v6 socket 只收 IPv6。
+    /// 空列表表示不监听 TCP，这时至少要有一个 [`Self::listen_unix`]
+    pub fn listen_all(mut self, addrs: Vec<SocketAddr>) -> Self {
+        self.listen = addrs;
+        self
+    }
+
+    /// 另外监听一个明文 HTTP 地址，配置了 [`Self::tls`] 时也不加密 (如本机的健康检查、sidecar 端口)；
+    /// 在 [`Self::listen`] / [`Self::listen_all`] 之后调用
+    pub fn listen_plain(mut self, addr: SocketAddr) -> Self {
+        self.listen.push(addr);
+        self.plaintext.push(addr);
+        self
+    }
+
+    /// 另外监听一个 Unix domain socket (只支持 Unix 平台)，可多次调用；明文 HTTP，不受 [`Self::tls`] 影响。
+    /// 摘流结束退出时删除 socket 文件；进程被直接杀掉留下的旧文件在下次绑定时替换
+    pub fn listen_unix(mut self, path: impl Into<PathBuf>) -> Self {
+        self.unix_listen.push(path.into());
+        self
+    }
+
+    /// Unix socket 文件的权限，默认 0o660 (同组的 nginx / caddy 可以连接)
+    pub fn unix_socket_mode(mut self, mode: u32) -> Self {
+        self.unix_socket_mode = mode;
+        self
+    }
+
+    /// 直接监听 HTTPS，证书 (可含证书链) 和私钥都是 PEM 文件，文件变化后自动重新加载
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, cert_path: impl Into<PathBuf>, key_path: impl Into<PathBuf>) -> Self {
+        self.tls = Some((cert_path.into(), key_path.into()));
+        self
+    }
+
+    /// 在每个 HTTPS 地址的同一端口 (UDP) 上再监听 HTTP/3，并通过 Alt-Svc 告诉客户端；需要配置 [`Self::tls`]
+    #[cfg(feature = "http3")]
+    pub fn http3(mut self, enabled: bool) -> Self {
+        self.http3 = enabled;
+        self
+    }
+
+    /// 出站代理 (http / https / socks4 / socks5 / socks5h)；单个上游可以在 target 里用 `;proxy=` 覆盖
+    pub fn proxy(mut self, proxy: impl Into<String>) -> Self {
+        self.proxies = vec![proxy.into()];
+        self
+    }
+
+    /// 多个全局代理组成代理池，按 [`Self::proxy_pool`] 的方式轮换，连接失败的暂停使用并定期重新探测；
+    /// 只有一个时等价于 [`Self::proxy`]
+    pub fn proxies(mut self, proxies: Vec<String>) -> Self {
+        self.proxies = proxies;
+        self
+    }
+
+    /// 代理池的轮换方式、冷却时间和探测间隔；探测需要在 tokio 运行时里调用 build
+    pub fn proxy_pool(mut self, config: EgressPoolConfig) -> Self {
+        self.proxy_pool = config;
+        self
+    }
+
+    /// 全局代理的账号密码 `USER:PASS`，覆盖代理 URL 里写的
+    pub fn proxy_auth(mut self, auth: impl Into<String>) -> Self {
+        self.proxy_auth = Some(auth.into());
+        self
+    }
+
+    /// 连接上游 (或要求 mTLS 的中间网关) 时出示的客户端证书，证书 (可含证书链) 和私钥都是 PEM 文件；
+    /// 只在 build 时读取一次，换证书需要热重载
+    pub fn upstream_client_cert(mut self, cert_path: impl Into<PathBuf>, key_path: impl Into<PathBuf>) -> Self {
+        self.upstream_client_cert = Some((cert_path.into(), key_path.into()));
+        self
+    }
+
+    /// 额外信任的 CA 证书 (PEM，一个文件可以有多张)，可多次调用；加在内置根证书之外，
+    /// 用来信任公司的 TLS 中间人代理，不必用 [`Self::insecure`] 关掉校验
+    pub fn ca_cert(mut self, path: impl Into<PathBuf>) -> Self {
+        self.ca_certs.push(path.into());
+        self
+    }
+
+    /// 关闭上游证书校验
+    pub fn insecure(mut self, insecure: bool) -> Self {
+        self.insecure = insecure;
+        self
+    }
+
+    #[cfg(feature = "geoip")]
+    pub fn geoip(mut self, geoip: GeoIp) -> Self {
+        self.geoip = Some(geoip);
+        self
+    }
+
+    pub fn scanner_guard(mut self, scanner: ScannerGuard) -> Self {
+        self.scanner = Some(scanner);
+        self
+    }
+
+    /// 只转发路径匹配白名单的请求，其余返回 403
+    pub fn path_allowlist(mut self, allowlist: PathAllowlist) -> Self {
+        self.path_allowlist = Some(allowlist);
+        self
+    }
+
+    /// 按客户端 / 全局限制响应体的下行速率
+    pub fn bandwidth(mut self, bandwidth: Bandwidth) -> Self {
+        self.bandwidth = Some(bandwidth);
+        self
+    }
+
+    pub fn url_signer(mut self, signer: UrlSigner) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    pub fn auth_lockout(mut self, lockout: AuthLockout) -> Self {
+        self.lockout = Some(lockout);
+        self
+    }
+
+    /// 按来源 IP 检测请求突发和鉴权失败，超限后拖延、临时封禁；和按客户端的限流互相独立
+    pub fn burst_guard(mut self, burst: BurstGuard) -> Self {
+        self.burst = Some(burst);
+        self
+    }
+
+    /// 要求客户端带网关签发的令牌，没有或无效时返回 401
+    pub fn client_tokens(mut self, tokens: ClientTokens) -> Self {
+        self.client_tokens = Some(tokens);
+        self
+    }
+
+    /// 按客户端的 Accept-Encoding 压缩非流式响应
+    #[cfg(feature = "compression")]
+    pub fn compression(mut self, config: CompressionConfig) -> Self {
+        self.compression = Some(config);
+        self
+    }
+
+    pub fn security_headers(mut self, headers: SecurityHeaders) -> Self {
+        self.security_headers = Some(headers);
+        self
+    }
+
+    /// 自定义网关生成的错误响应体
+    pub fn error_templates(mut self, templates: ErrorTemplates) -> Self {
+        self.error_templates = Some(templates);
+        self
+    }
+
+    /// 按 Host / 路径前缀划分的租户
+    pub fn tenants(mut self, tenants: Tenants) -> Self {
+        self.tenants = Some(tenants);
+        self
+    }
+
+    /// 允许通过 X-Aizasy-Project 头标记的项目
+    pub fn projects(mut self, projects: Projects) -> Self {
+        self.projects = Some(projects);
+        self
+    }
+
+    /// 初始的维护模式设置 (管理 API 可以在运行时修改)
+    pub fn maintenance(mut self, maintenance: MaintenanceState) -> Self {
+        self.maintenance = maintenance;
+        self
+    }
+
+    /// 摘流开关；热重载时传入同一个实例，新旧网关共享摘流状态
+    pub fn drain(mut self, drain: Drain) -> Self {
+        self.drain = drain;
+        self
+    }
+
+    /// 摘流开始后继续接受新连接的时长，默认 0。这段时间里 /health 已经返回 503，
+    /// 给负载均衡的健康检查留出发现的时间，之后才停止监听
+    pub fn drain_delay(mut self, delay: Duration) -> Self {
+        self.drain_delay = delay;
+        self
+    }
+
+    /// 停止监听后等待在途请求完成的最长时间，默认 30 秒，超过后 serve 直接返回
+    pub fn drain_timeout(mut self, timeout: Duration) -> Self {
+        self.drain_timeout = timeout;
+        self
+    }
+
+    /// 路径改写规则，在路由匹配之前按顺序尝试
+    pub fn path_rewrites(mut self, rules: Vec<RewriteRule>) -> Self {
+        self.rewrites = rules;
+        self
+    }
+
+    /// OpenAI / Anthropic 等厂商的上游，按请求体里的 model 匹配，先配置的优先
+    pub fn provider(mut self, provider: Provider) -> Self {
+        self.providers.push(provider);
+        self
+    }
+
+    /// 请求头 / 响应头改写规则，按顺序执行
+    pub fn header_rules(mut self, rules: Vec<HeaderRule>) -> Self {
+        self.header_rules = rules;
+        self
+    }
+
+    /// 模型降级链：上游对请求的模型返回 404 / 429 / 503 时换下一个模型重试
+    pub fn model_fallbacks(mut self, fallbacks: ModelFallbacks) -> Self {
+        self.model_fallbacks = Some(fallbacks);
+        self
+    }
+
+    /// 上游连接失败或 5xx 时按指数退避重试
+    pub fn retry(mut self, config: RetryConfig) -> Self {
+        self.retry = Some(config);
+        self
+    }
+
+    /// 把一部分请求复制一份发给影子上游 (后台发送，响应丢弃)，用真实流量验证新区域或新出口
+    pub fn mirror(mut self, config: MirrorConfig) -> Self {
+        self.mirror = Some(config);
+        self
+    }
+
+    /// 把一定比例发往默认上游的请求改发给金丝雀上游，失败时退回默认上游
+    pub fn canary(mut self, config: CanaryConfig) -> Self {
+        self.canary = Some(config);
+        self
+    }
+
+    /// 初始路由规则 (路径前缀 -> 上游)
+    pub fn routes(mut self, routes: Vec<RouteRule>) -> Self {
+        self.routes = routes;
+        self
+    }
+
+    /// 上游 key 池：转发时轮询注入，客户端自带的 key 会被丢弃
+    pub fn key_pool(mut self, pool: KeyPool) -> Self {
+        if !pool.is_empty() {
+            info!("🔑 Key pool: {} key(s)", pool.len());
+        }
+        self.key_pool = Some(pool);
+        self
+    }
+
+    /// key 池的来源：按 source.interval 在后台重新读取，有变化时原地替换池里的 key
+    pub fn key_source(mut self, source: KeySource) -> Self {
+        self.key_source = Some(source);
+        self
+    }
+
+    /// key 被隔离时把通知 POST 到这个地址
+    pub fn key_quarantine_webhook(mut self, url: impl Into<String>) -> Self {
+        self.quarantine_webhook = Some(QuarantineWebhook::new(url.into()));
+        self
+    }
+
+    /// 启用 POST /batch 批量扇出端点
+    pub fn batch(mut self, config: BatchConfig) -> Self {
+        self.batch = Some(config);
+        self
+    }
+
+    /// 同一时刻的相同 GET 请求只转发一个，其余共享它的响应
+    pub fn coalesce_get(mut self, enabled: bool) -> Self {
+        self.coalesce_get = enabled;
+        self
+    }
+
+    /// 启用 OpenAI 兼容端点 (/v1/chat/completions、/v1/embeddings、/v1/models)
+    pub fn openai_compat(mut self, enabled: bool) -> Self {
+        self.openai_compat = enabled;
+        self
+    }
+
+    /// 每个请求结束后写一行 JSON 访问日志 (覆盖所有路由，包括被中间件拒绝的请求)
+    pub fn access_log(mut self, log: AccessLog) -> Self {
+        self.access_log = Some(Arc::new(log));
+        self
+    }
+
+    /// 代理请求体上限 (字节)，超过时返回 413，默认 64MB
+    pub fn max_request_size(mut self, bytes: usize) -> Self {
+        self.max_request_size = bytes;
+        self
+    }
+
+    /// 上游响应体上限 (字节)：Content-Length 超过时返回 502，流式响应超过时中断连接；默认不限制
+    pub fn max_response_size(mut self, bytes: u64) -> Self {
+        self.max_response_size = Some(bytes);
+        self
+    }
+
+    /// 上游响应流连续这么久没有数据时中断，默认不限制
+    pub fn stream_idle_timeout(mut self, idle: Duration) -> Self {
+        self.stream_idle_timeout = Some(idle);
+        self
+    }
+
+    /// SSE 响应连续这么久没有数据时给客户端发一行 `: keepalive` 注释，防止中间设备掐断静默连接；默认不发
+    pub fn sse_keepalive(mut self, interval: Duration) -> Self {
+        self.sse_keepalive = Some(interval);
+        self
+    }
+
+    /// 客户端 Accept-Encoding 的处理方式，默认 identity (上游只返回未压缩的响应)
+    pub fn accept_encoding(mut self, mode: EncodingMode) -> Self {
+        self.accept_encoding = mode;
+        self
+    }
+
+    /// 转发 `application/grpc` 请求到 gRPC 上游
+    pub fn grpc(mut self, config: GrpcConfig) -> Self {
+        self.grpc = Some(config);
+        self
+    }
+
+    /// 把 File API 断点续传的上传地址换成指向网关的地址
+    pub fn uploads(mut self, config: UploadConfig) -> Self {
+        self.uploads = Some(config);
+        self
+    }
+
+    /// 启动时和空闲之后预先建立到默认上游的连接
+    pub fn prewarm(mut self, config: PrewarmConfig) -> Self {
+        self.prewarm = Some(config);
+        self
+    }
+
+    /// 上游首字节时间或整个请求超过阈值时打 WARN 日志，带上 DNS、连接、首字节、流式传输的耗时
+    pub fn slow_request(mut self, threshold: Duration) -> Self {
+        self.slow_request = Some(threshold);
+        self
+    }
+
+    /// 上游域名的解析方式：指定 DNS 服务器、固定解析
+    pub fn dns(mut self, config: DnsConfig) -> Self {
+        self.dns = config;
+        self
+    }
+
+    /// 和上游建立连接 (含 TLS 握手) 的超时，默认 10 秒
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// 到上游用的 HTTP 版本 (默认 ALPN 协商) 和 HTTP/2 窗口、保活设置；所有出站客户端 (含代理池) 都生效
+    pub fn upstream_http(mut self, config: UpstreamHttpConfig) -> Self {
+        self.upstream_http = config;
+        self
+    }
+
+    /// 发出请求到收到上游响应头的超时，默认 120 秒；超时返回 504
+    pub fn first_byte_timeout(mut self, timeout: Duration) -> Self {
+        self.first_byte_timeout = timeout;
+        self
+    }
+
+    /// 整个请求 (含流式响应体) 的超时，默认不限制；长时间的流式生成会被它截断
+    pub fn total_timeout(mut self, timeout: Duration) -> Self {
+        self.total_timeout = Some(timeout);
+        self
+    }
+
+    /// 同时处理的代理请求上限，超出 (且排队已满或等待超时) 时返回 503
+    pub fn max_inflight(mut self, config: InflightConfig) -> Self {
+        self.inflight = Some(config);
+        self
+    }
+
+    /// key 池的 key 都在冷却时让请求排队等待，而不是直接返回 429
+    pub fn request_queue(mut self, config: QueueConfig) -> Self {
+        self.request_queue = Some(config);
+        self
+    }
+
+    /// 受信任的反向代理网段：来自这些地址的请求按 X-Forwarded-For 识别真实客户端 IP，
+    /// 并把 X-Forwarded-For 转发给上游
+    pub fn trusted_proxies(mut self, proxies: Vec<Cidr>) -> Self {
+        self.trusted_proxies = proxies;
+        self
+    }
+
+    /// 来源 IP 黑白名单，覆盖所有路由，拦截时返回 403
+    pub fn ip_filter(mut self, filter: IpFilter) -> Self {
+        self.ip_filter = Some(filter);
+        self
+    }
+
+    /// 启用 CORS：预检请求由网关直接应答，允许的来源拿到 Access-Control-Allow-* 响应头
+    pub fn cors(mut self, cors: Cors) -> Self {
+        self.cors = Some(cors);
+        self
+    }
+
+    /// 预付费额度：注册为插件 (余额不足时拒绝)，并在管理 API 中提供查询和充值
+    pub fn credit_accounts(mut self, credits: Arc<CreditAccounts>) -> Self {
+        self.plugins.push(credits.clone());
+        #[cfg(feature = "admin")]
+        {
+            self.credits = Some(credits);
+        }
+        self
+    }
+
+    /// cachedContents 跟踪：注册为插件，并在管理 API 中列出各客户端的缓存
+    pub fn cached_contents(mut self, caches: Arc<CachedContents>) -> Self {
+        self.plugins.push(caches.clone());
+        #[cfg(feature = "admin")]
+        {
+            self.cached_contents = Some(caches);
+        }
+        self
+    }
+
+    /// 留存最近的失败请求，管理 API 可以查看和重放
+    pub fn failure_log(mut self, failures: Arc<FailureLog>) -> Self {
+        self.plugins.push(failures.clone());
+        #[cfg(feature = "admin")]
+        {
+            self.failures = Some(failures);
+        }
+        self
+    }
+
+    /// 内置监控面板：按秒统计请求、延迟和每个 key 的用量，在管理 API 的 /admin/dashboard 查看
+    #[cfg(feature = "admin")]
+    pub fn dashboard(mut self) -> Self {
+        let dashboard = Arc::new(Dashboard::new());
+        self.plugins.push(dashboard.clone());
+        self.dashboard = Some(dashboard);
+        self
+    }
+
+    /// 在内存里保留最近的请求摘要，管理 API 可以查看和实时订阅
+    pub fn inspector(mut self, inspector: Arc<RequestInspector>) -> Self {
+        self.plugins.push(inspector.clone());
+        #[cfg(feature = "admin")]
+        {
+            self.inspector = Some(inspector);
+        }
+        self
+    }
+
+    /// 运行时设置上游 (管理 API) 时使用的 SSRF 策略
+    pub fn target_policy(mut self, policy: TargetPolicy) -> Self {
+        self.target_policy = policy;
+        self
+    }
+
+    /// 开启 /admin 管理 API
+    #[cfg(feature = "admin")]
+    pub fn admin_token(mut self, token: impl Into<String>) -> Self {
+        self.admin_token = Some(token.into());
+        self
+    }
+
+    /// 管理 API 改到单独的地址上监听 (如 127.0.0.1:9100)，主监听地址不再提供 /admin。
+    /// 没有设置 admin token 时这个地址上的管理 API 不要求鉴权，只应绑定在内网或本机
+    #[cfg(feature = "admin")]
+    pub fn admin_listen(mut self, addr: SocketAddr) -> Self {
+        self.admin_listen = Some(addr);
+        self
+    }
+
+    /// 在主监听地址上不鉴权地提供 /metrics。指标带着 key、客户端、代理和国家等标签，
+    /// 默认只对管理员开放：主监听地址上要带 admin token，或者放在单独的管理监听地址上
+    #[cfg(feature = "metrics")]
+    pub fn metrics_public(mut self) -> Self {
+        self.metrics_public = true;
+        self
+    }
+
+    /// 允许通过管理 API 在运行时切换日志级别
+    #[cfg(feature = "admin")]
+    pub fn log_level_control(mut self, control: LogLevelControl) -> Self {
+        self.log_level = Some(control);
+        self
+    }
+
+    /// 注册插件，钩子按注册顺序执行
+    pub fn plugin(mut self, plugin: impl GatewayPlugin) -> Self {
+        self.plugins.push(Arc::new(plugin));
+        self
+    }
+
+    /// 在指定位置插入任意 tower layer；同一位置的多个 layer 按注册顺序由内向外包裹
+    ///
+    /// ```no_run
+    /// use aizasy_gateway::{Gateway, LayerPosition};
+    /// use axum::{extract::Request, middleware::{self, Next}, response::Response};
+    ///
+    /// async fn audit(req: Request, next: Next) -> Response {
+    ///     println!("{} {}", req.method(), req.uri());
+    ///     next.run(req).await
+    /// }
+    ///
+    /// let gateway = Gateway::builder()
+    ///     .layer(LayerPosition::PreAuth, middleware::from_fn(audit))
+    ///     .build()
+    ///     .expect("invalid gateway config");
+    /// ```
+    pub fn layer<L>(mut self, position: LayerPosition, layer: L) -> Self
+    where
+        L: Layer<Route> + Clone + Send + Sync + 'static,
+        L::Service: Service<Request> + Clone + Send + 'static,
+        <L::Service as Service<Request>>::Response: IntoResponse + 'static,
+        <L::Service as Service<Request>>::Error: Into<Infallible> + 'static,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        let apply: LayerFn = match position {
+            LayerPosition::PostResponse => Arc::new(move |router| router.layer(layer.clone())),
+            _ => Arc::new(move |router| router.route_layer(layer.clone())),
+        };
+        self.layers.push((position, apply));
+        self
+    }
+
+    /// 生命周期事件总线；可以在 build 之前交给插件或导出器订阅
+    pub fn events(&self) -> EventBus {
+        self.events.clone()
+    }
+
+    /// 共享状态的存储后端，默认进程内存
+    pub fn storage(mut self, storage: Arc<dyn Storage>) -> Self {
+        self.storage = storage;
+        self
+    }
+
+    /// 当前存储后端；插件可以在 build 之前拿到同一个实例
+    pub fn storage_handle(&self) -> Arc<dyn Storage> {
+        self.storage.clone()
+    }
+
+    /// 构建后直接返回绑定好状态的 Router，等价于 `build()?.router()`；
+    /// 只嵌入宿主应用、不需要 Gateway 其他句柄 (事件、维护模式、路由表) 时使用
+    pub fn into_router(self) -> Result<Router, String> {
+        Ok(self.build()?.router())
+    }
+
+    pub fn build(self) -> Result<Gateway, String> {
+        // --- 构建 HTTP 客户端 ---
+        let identity = match &self.upstream_client_cert {
+            Some((cert, key)) => {
+                info!("🪪 Upstream client certificate: {}", cert.display());
+                Some(load_identity(cert, key)?)
+            }
+            None => None,
+        };
+        let mut roots = Vec::new();
+        for path in &self.ca_certs {
+            let certs = load_ca_certs(path)?;
+            info!("🏛️  Trusting {} CA certificate(s) from {}", certs.len(), path.display());
+            roots.extend(certs);
+        }
+
+        let upstream_urls = self
+            .targets
+            .iter()
+            .map(|t| t.url.as_str())
+            .chain(self.routes.iter().map(|r| r.target.as_str()))
+            .chain(self.mirror.iter().map(|m| m.target.as_str()))
+            .chain(self.canary.iter().map(|c| c.target.as_str()))
+            .chain(self.grpc.iter().map(|g| g.target.as_str()))
+            .chain(self.providers.iter().map(|p| p.url.as_str()));
+        let resolvers = Resolvers::new(&self.dns, self.slow_request.is_some(), upstream_urls)?;
+        for server in &self.dns.servers {
+            info!("🧭 DNS server: {}", server);
+        }
+        for host in &self.dns.overrides {
+            info!("📌 Resolving {} to {:?}", host.host, host.addrs);
+        }
+        if self.dns.strategy != IpStrategy::Auto {
+            info!("🌍 Upstream addresses: {}", self.dns.strategy.name());
+        }
+        if let Some(delay) = self.dns.happy_eyeballs {
+            info!("🏁 Happy Eyeballs fallback after {}ms", delay.as_millis());
+        }
+
+        // 全局一个；单独配置了代理的上游各一个
+        let make_client_with = |proxy: Option<Proxy>, http: &UpstreamHttpConfig| {
+            let mut client_builder = Client::builder()
+                .pool_idle_timeout(Duration::from_secs(90))
+                .pool_max_idle_per_host(50)
+                .tcp_nodelay(true)
+                .connect_timeout(self.connect_timeout)
+                .no_gzip()
+                .no_brotli()
+                .no_deflate()
+                .no_zstd();
+            client_builder = http.apply(client_builder);
+            client_builder = resolvers.apply(client_builder);
+            if self.slow_request.is_some() {
+                client_builder = client_builder.connector_layer(slow_log::ConnectTiming);
+            }
+            if let Some(proxy) = proxy {
+                client_builder = client_builder.proxy(proxy);
+            }
+            if let Some(identity) = &identity {
+                client_builder = client_builder.identity(identity.clone());
+            }
+            for cert in &roots {
+                client_builder = client_builder.add_root_certificate(cert.clone());
+            }
+            if self.insecure {
+                client_builder = client_builder.danger_accept_invalid_certs(true);
+            }
+            client_builder
+                .build()
+                .map_err(|e| format!("Failed to build client: {}", e))
+        };
+        let make_client = |proxy: Option<Proxy>| make_client_with(proxy, &self.upstream_http);
+
+        let dynamic_client = if self.target_policy.allow_private {
+            None
+        } else if !self.proxies.is_empty() {
+            // 代理自己解析域名，连接时过滤不了；由 proxy::check_proxied_route 在每次发送前检查
+            warn!("⚠️  Runtime routes go through the proxy, which resolves their hosts; targets are re-checked against the target policy before each request");
+            None
+        } else {
+            let mut client_builder = Client::builder()
+                .pool_idle_timeout(Duration::from_secs(90))
+                .tcp_nodelay(true)
+                .connect_timeout(self.connect_timeout)
+                .redirect(reqwest::redirect::Policy::none())
+                .no_gzip()
+                .no_brotli()
+                .no_deflate()
+                .no_zstd();
+            client_builder = self.upstream_http.apply(client_builder);
+            client_builder = resolvers.apply_wrapped(client_builder, PolicyResolver::new);
+            if self.slow_request.is_some() {
+                client_builder = client_builder.connector_layer(slow_log::ConnectTiming);
+            }
+            if let Some(identity) = &identity {
+                client_builder = client_builder.identity(identity.clone());
+            }
+            for cert in &roots {
+                client_builder = client_builder.add_root_certificate(cert.clone());
+            }
+            if self.insecure {
+                client_builder = client_builder.danger_accept_invalid_certs(true);
+            }
+            Some(client_builder.build().map_err(|e| format!("Failed to build client: {}", e))?)
+        };
+
+        if self.insecure {
+            warn!("⚠️  Insecure Mode: SSL validation disabled");
+        }
+
+        // gRPC 客户端：只有一个代理时同样走代理
+        let grpc_client = match &self.grpc {
+            Some(config) => {
+                let http = UpstreamHttpConfig {
+                    protocol: UpstreamProtocol::Http2,
+                    ..self.upstream_http.clone()
+                };
+                let proxy = match self.proxies.as_slice() {
+                    [proxy_url] => Some(egress::parse(proxy_url, self.proxy_auth.as_deref())?),
+                    _ => None,
+                };
+                info!("📡 gRPC passthrough to {}", config.target);
+                Some(make_client_with(proxy, &http)?)
+            }
+            None => None,
+        };
+
+        let mut proxies = Vec::with_capacity(self.proxies.len());
+        for proxy_url in &self.proxies {
+            info!("🔌 Proxy: {}", egress::redact(proxy_url));
+            let proxy = egress::parse(proxy_url, self.proxy_auth.as_deref())?;
+            proxies.push((egress::redact(proxy_url), make_client(Some(proxy))?));
+        }
+        // 只有一个代理时直接作为全局客户端；多个时组成代理池，全局客户端不走代理
+        let (client, egress) = match proxies.len() {
+            0 => (make_client(None)?, None),
+            1 => (proxies.remove(0).1, None),
+            _ => (make_client(None)?, Some(EgressPool::new(proxies, self.proxy_pool))),
+        };
+
+        if self.targets.is_empty() {
+            return Err("at least one target is required".to_string());
+        }
+        #[cfg(feature = "http3")]
+        if self.http3 && self.tls.is_none() {
+            return Err("HTTP/3 needs a TLS certificate".to_string());
+        }
+        if self.listen.is_empty() && self.unix_listen.is_empty() {
+            return Err("at least one listen address is required".to_string());
+        }
+        #[cfg(not(unix))]
+        if !self.unix_listen.is_empty() {
+            return Err("unix socket listeners are not supported on this platform".to_string());
+        }
+        let mut clients = Vec::with_capacity(self.targets.len());
+        for target in &self.targets {
+            clients.push(match &target.proxy {
+                Some(proxy_url) => {
+                    info!("🔌 Proxy for {}: {}", target.url, egress::redact(proxy_url));
+                    Some(make_client(Some(egress::parse(proxy_url, None)?))?)
+                }
+                None => None,
+            });
+        }
+        if self.targets.len() > 1 && self.target_balancing != Balancing::Weighted {
+            info!("⏱️  Upstream balancing: {}", self.target_balancing.name());
+        }
+        let upstreams = Upstreams::new(self.targets, clients, self.target_cooldown, self.target_balancing);
+        let upstream_count = upstreams.len();
+
+        let mirror = match self.mirror {
+            Some(config) => {
+                info!("🪞 Mirroring {}% of requests to {}", config.percent, config.target);
+                let client = match &config.proxy {
+                    Some(proxy_url) => {
+                        info!("🔌 Proxy for mirror: {}", egress::redact(proxy_url));
+                        Some(make_client(Some(egress::parse(proxy_url, None)?))?)
+                    }
+                    None => None,
+                };
+                Some(Mirror::new(config, client)?)
+            }
+            None => None,
+        };
+        let canary = match self.canary {
+            Some(config) => {
+                info!("🐤 Routing {}% of requests to canary {}", config.percent, config.target);
+                let client = match &config.proxy {
+                    Some(proxy_url) => {
+                        info!("🔌 Proxy for canary: {}", egress::redact(proxy_url));
+                        Some(make_client(Some(egress::parse(proxy_url, None)?))?)
+                    }
+                    None => None,
+                };
+                Some(Canary::new(config, client))
+            }
+            None => None,
+        };
+
+        #[cfg(feature = "tls")]
+        let tls = match self.tls {
+            Some((cert, key)) => Some(Arc::new(TlsCerts::load(cert, key)?)),
+            None => None,
+        };
+
+        // 后台重新读取 key 地址时和上游请求一样用 --ca-cert、--proxy 等设置
+        let key_source = self.key_source.map(|source| KeySource {
+            client: Some(client.clone()),
+            ..source
+        });
+
+        // 突发检测按鉴权失败判断时读 lockout 的失败计数，没开 --auth-lockout 就建一个只计数不锁定的
+        let lockout = match (self.lockout, self.burst.as_ref().and_then(BurstGuard::auth_failure_window)) {
+            (None, Some(window)) => Some(AuthLockout::counting(window)),
+            (lockout, _) => lockout,
+        };
+
+        let state = Arc::new(AppState {
+            client,
+            target_url: upstreams.primary().to_string(),
+            upstreams,
+            egress,
+            #[cfg(feature = "geoip")]
+            geoip: self.geoip,
+            scanner: self.scanner,
+            path_allowlist: self.path_allowlist,
+            bandwidth: self.bandwidth,
+            signer: self.signer,
+            lockout,
+            burst: self.burst,
+            client_tokens: self.client_tokens,
+            security_headers: self.security_headers,
+            #[cfg(feature = "compression")]
+            compression: self.compression,
+            error_templates: self.error_templates.filter(|t| !t.is_empty()),
+            tenants: self.tenants,
+            projects: self.projects,
+            maintenance: Arc::new(Maintenance::new(self.maintenance)),
+            drain: self.drain,
+            routes: RoutingTable::new(self.routes),
+            batch: self.batch.map(BatchFanout::new),
+            openai_compat: self.openai_compat,
+            coalescer: self.coalesce_get.then(Coalescer::default),
+            access_log: self.access_log,
+            max_request_size: self.max_request_size,
+            max_response_size: self.max_response_size,
+            stream_idle_timeout: self.stream_idle_timeout,
+            sse_keepalive: self.sse_keepalive,
+            accept_encoding: self.accept_encoding,
+            grpc: self.grpc,
+            grpc_client,
+            dynamic_client,
+            uploads: self.uploads.map(UploadSessions::new),
+            prewarm: self.prewarm.map(|config| Prewarm::new(config, upstream_count)),
+            slow_request: self.slow_request,
+            #[cfg(feature = "admin")]
+            dns: self.dns,
+            #[cfg(feature = "admin")]
+            connect_timeout: self.connect_timeout,
+            first_byte_timeout: self.first_byte_timeout,
+            total_timeout: self.total_timeout,
+            inflight: self.inflight.map(InflightLimit::new),
+            request_queue: self.request_queue.map(RequestQueue::new),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            trusted_proxies: self.trusted_proxies,
+            ip_filter: self.ip_filter.filter(|f| !f.is_empty()),
+            cors: self.cors,
+            rewrites: self.rewrites,
+            providers: self.providers,
+            header_rules: self.header_rules,
+            model_fallbacks: self.model_fallbacks.filter(|f| !f.is_empty()),
+            retry: self.retry.filter(|r| r.max_retries > 0),
+            mirror,
+            canary,
+            key_pool: self.key_pool,
+            quarantine_webhook: self.quarantine_webhook,
+            key_source,
+            #[cfg(feature = "admin")]
+            credits: self.credits,
+            #[cfg(feature = "admin")]
+            cached_contents: self.cached_contents,
+            #[cfg(feature = "admin")]
+            failures: self.failures,
+            #[cfg(feature = "admin")]
+            inspector: self.inspector,
+            #[cfg(feature = "admin")]
+            dashboard: self.dashboard,
+            target_policy: self.target_policy,
+            #[cfg(feature = "admin")]
+            admin_token: self.admin_token,
+            #[cfg(feature = "admin")]
+            log_level: self.log_level,
+            plugins: Arc::new(self.plugins),
+            metrics: Metrics::new(),
+            health_probe: self.health_check.clone().unwrap_or_default(),
+            #[cfg(feature = "metrics")]
+            model_labels: ModelLabels::default(),
+            events: self.events,
+            storage: self.storage,
+            request_ids: AtomicU64::new(0),
+            // 热重载会重新 build，运行时长仍从第一个实例算起
+            #[cfg(feature = "admin")]
+            started: *STARTED.get_or_init(std::time::Instant::now),
+        });
+
+        for rule in state.routes.snapshot().iter() {
+            state.check_key_group(rule)?;
+        }
+        for tenant in state.tenants.iter().flat_map(|t| t.iter()) {
+            if let Some(group) = &tenant.key_group {
+                if !state.key_pool.as_ref().is_some_and(|pool| pool.has_group(group)) {
+                    return Err(format!("tenant '{}': unknown key group '{}'", tenant.name, group));
+                }
+            }
+            if let Some(client) = tenant.clients.iter().find(|c| !state.client_tokens.as_ref().is_some_and(|t| t.has_name(c))) {
+                return Err(format!("tenant '{}': no client token named '{}'", tenant.name, client));
+            }
+        }
+
+        if state.key_pool.is_some() && key_pool::shared(&state) {
+            match tokio::runtime::Handle::try_current() {
+                Ok(_) => key_pool::spawn_cooldown_sync(Arc::downgrade(&state)),
+                Err(_) => warn!("⚠️  Shared key cooldowns need a tokio runtime, not synced"),
+            }
+        }
+
+        if let Some(source) = state.key_source.as_ref().filter(|s| state.key_pool.is_some() && s.watchable()) {
+            if !source.interval.is_zero() {
+                match tokio::runtime::Handle::try_current() {
+                    Ok(_) => key_pool::spawn_reload(Arc::downgrade(&state), source.interval),
+                    Err(_) => warn!("⚠️  Key pool reload needs a tokio runtime, not watching"),
+                }
+            }
+        }
+
+        if state.burst.is_some() {
+            match tokio::runtime::Handle::try_current() {
+                Ok(_) => burst::spawn_sweeper(Arc::downgrade(&state)),
+                Err(_) => warn!("⚠️  Burst guard cleanup needs a tokio runtime, idle entries are kept until restart"),
+            }
+        }
+
+        if state.lockout.is_some() {
+            match tokio::runtime::Handle::try_current() {
+                Ok(_) => lockout::spawn_sweeper(Arc::downgrade(&state)),
+                Err(_) => warn!("⚠️  Auth lockout cleanup needs a tokio runtime, idle entries are kept until restart"),
+            }
+        }
+
+        if state.scanner.is_some() {
+            match tokio::runtime::Handle::try_current() {
+                Ok(_) => scanner::spawn_sweeper(Arc::downgrade(&state)),
+                Err(_) => warn!("⚠️  Scanner ban cleanup needs a tokio runtime, expired bans are kept until restart"),
+            }
+        }
+
+        if state.egress.is_some() {
+            match tokio::runtime::Handle::try_current() {
+                Ok(_) => egress::spawn_probes(Arc::downgrade(&state)),
+                Err(_) => warn!("⚠️  Proxy probes need a tokio runtime, not started"),
+            }
+        }
+
+        if state.prewarm.is_some() {
+            match tokio::runtime::Handle::try_current() {
+                Ok(_) => prewarm::spawn(Arc::downgrade(&state)),
+                Err(_) => warn!("⚠️  Connection pre-warming needs a tokio runtime, not started"),
+            }
+        }
+
+        if let Some(config) = self.health_check {
+            match tokio::runtime::Handle::try_current() {
+                Ok(_) => upstreams::spawn_health_checks(Arc::downgrade(&state), config),
+                Err(_) => warn!("⚠️  Health checks need a tokio runtime, not started"),
+            }
+        }
+
+        Ok(Gateway {
+            state,
+            listen: self.listen,
+            plaintext: self.plaintext,
+            unix_listen: self.unix_listen,
+            unix_socket_mode: self.unix_socket_mode,
+            #[cfg(feature = "tls")]
+            tls,
+            #[cfg(feature = "http3")]
+            http3: self.http3,
+            layers: self.layers,
+            #[cfg(feature = "admin")]
+            admin_listen: self.admin_listen,
+            #[cfg(feature = "metrics")]
+            metrics_public: self.metrics_public,
+            drain_delay: self.drain_delay,
+            drain_timeout: self.drain_timeout,
+        })
+    }
+}
+
+impl Gateway {
+    pub fn builder() -> GatewayBuilder {
+        GatewayBuilder::default()
+    }
+
+    pub fn events(&self) -> EventBus {
+        self.state.events.clone()
+    }
+
+    pub fn storage(&self) -> Arc<dyn Storage> {
+        self.state.storage.clone()
+    }
+
+    /// 启动时配置的 (第一个) 默认上游地址
+    pub fn target(&self) -> &str {
+        &self.state.target_url
+    }
+
+    /// 默认上游的当前状态 (权重、剩余不可用时间)
+    pub fn upstreams(&self) -> Vec<UpstreamStatus> {
+        self.state.upstreams.status()
+    }
+
+    /// 用网关自己的客户端 (代理、CA、key 注入都生效) 向每个上游发一次健康检查请求，返回 (地址, 耗时或错误)
+    pub async fn probe_upstreams(&self, timeout: Duration) -> Vec<(String, Result<Duration, String>)> {
+        upstreams::probe_all(&self.state, timeout).await
+    }
+
+    /// 代理池里每个代理的状态；没有配置多个代理时为空
+    pub fn proxies(&self) -> Vec<EgressStatus> {
+        self.state.egress.as_ref().map(EgressPool::status).unwrap_or_default()
+    }
+
+    /// 维护模式开关，修改立即对新请求生效
+    pub fn maintenance(&self) -> &Maintenance {
+        &self.state.maintenance
+    }
+
+    /// 摘流开关，调用 start() 后 serve 停止接受新连接，在途请求完成后返回
+    pub fn drain(&self) -> Drain {
+        self.state.drain.clone()
+    }
+
+    /// 运行时路由表，修改立即对新请求生效
+    pub fn routes(&self) -> &RoutingTable {
+        &self.state.routes
+    }
+
+    /// 与管理 API 相同：校验上游后新增或覆盖路由规则
+    pub async fn set_route(&self, rule: RouteRule) -> Result<bool, String> {
+        self.state.set_route(rule).await
+    }
+
+    /// 返回已绑定状态的 Router，可以 `nest` 到宿主应用的任意前缀下
+    ///
+    /// 嵌套时 axum 会去掉前缀再交给网关，上游看到的仍是原始 API 路径。
+    /// 宿主应用应使用 `into_make_service_with_connect_info::<SocketAddr>()`，
+    /// 否则基于来源 IP 的功能拿不到真实地址。
+    pub fn router(&self) -> Router {
+        let state = self.state.clone();
+
+        // 代理路由挂载访问控制中间件，health / metrics 不受影响
+        let mut router = Router::new();
+        if state.batch.is_some() {
+            // 子请求和直接发来的请求一样经过 PreProxy 层和突发限制
+            let subrequests = self
+                .apply_layers(Router::new().route("/*path", any(proxy_handler)), LayerPosition::PreProxy)
+                .route_layer(middleware::from_fn_with_state(state.clone(), burst::guard))
+                .with_state(state.clone());
+            router = router.route("/batch", any(batch::handler).layer(Extension(batch::Subrequests(subrequests))));
+        }
+        if state.openai_compat {
+            router = router
+                .route("/v1/chat/completions", post(openai::chat_completions))
+                .route("/v1/embeddings", post(openai::embeddings))
+                .route("/v1/models", get(openai::models));
+        }
+        let router = router
+            .route("/*path", any(proxy_handler))
+            .route("/", any(proxy_handler))
+            .route_layer(middleware::from_fn_with_state(state.clone(), coalesce::coalesce));
+        let router = self.apply_layers(router, LayerPosition::PreProxy)
+            .route_layer(middleware::from_fn_with_state(state.clone(), bandwidth::throttle))
+            .route_layer(middleware::from_fn_with_state(state.clone(), client_auth::guard))
+            .route_layer(middleware::from_fn_with_state(state.clone(), lockout::guard))
+            // 签名校验在锁定和令牌校验外层：带有效签名的请求不需要令牌，也不受锁定影响；签名失败由签名层自己计数
+            .route_layer(middleware::from_fn_with_state(state.clone(), signed_url::guard))
+            .route_layer(middleware::from_fn_with_state(state.clone(), burst::guard));
+        #[cfg(feature = "geoip")]
+        let router = router.route_layer(middleware::from_fn_with_state(state.clone(), geoip::guard));
+        let router = router
+            .route_layer(middleware::from_fn_with_state(state.clone(), project::guard))
+            .route_layer(middleware::from_fn_with_state(state.clone(), tenant::guard))
+            .route_layer(middleware::from_fn_with_state(state.clone(), scanner::guard))
+            .route_layer(middleware::from_fn_with_state(state.clone(), maintenance::guard))
+            .route_layer(middleware::from_fn_with_state(state.clone(), load_shed::guard));
+        #[cfg(feature = "metrics")]
+        let router = router.route_layer(middleware::from_fn_with_state(state.clone(), request_metrics::record));
+        let router = self
+            .apply_layers(router, LayerPosition::PreAuth)
+            .route("/health", get(health_check))
+            .route("/livez", get(liveness))
+            .route("/readyz", get(readiness))
+            .route("/version", get(version));
+        #[cfg(feature = "metrics")]
+        let router = match self.metrics_public {
+            true => router.route("/metrics", get(metrics_handler)),
+            false => self.private_metrics(router),
+        };
+        #[cfg(feature = "admin")]
+        let router = match self.admin_listen {
+            Some(_) => router,
+            None => router.nest("/admin", admin::router(state.clone(), false)),
+        };
+
+        let router = router
+            .layer(middleware::from_fn_with_state(state.clone(), error_templates::apply))
+            .layer(middleware::from_fn_with_state(state.clone(), security_headers::inject))
+            .layer(middleware::from_fn_with_state(state.clone(), header_rules::rewrite_response))
+            .layer(middleware::from_fn_with_state(state.clone(), cors::handle));
+        // 压缩放在所有改写响应头的中间件外面，看到的是最终的响应
+        #[cfg(feature = "compression")]
+        let router = match &state.compression {
+            Some(config) => compression::apply(router, config),
+            None => router,
+        };
+        self.apply_layers(router, LayerPosition::PostResponse)
+            .layer(middleware::from_fn_with_state(state.clone(), ip_filter::guard))
+            .layer(middleware::from_fn_with_state(state.clone(), slow_log::record))
+            .layer(middleware::from_fn_with_state(state.clone(), access_log::record))
+            .layer(middleware::from_fn_with_state(state.clone(), client_ip::resolve))
+            .layer(middleware::from_fn_with_state(state.clone(), assign_request_id))
+            .with_state(state)
+    }
+
+    // 不公开的 /metrics：有单独的管理监听地址时放在那边，否则和管理 API 一样要求 admin token (没有设置时不提供)
+    #[cfg(feature = "metrics")]
+    fn private_metrics(&self, router: Router<Arc<AppState>>) -> Router<Arc<AppState>> {
+        #[cfg(feature = "admin")]
+        if self.admin_listen.is_none() {
+            let guard = middleware::from_fn_with_state(self.state.clone(), admin::require_token);
+            return router.route("/metrics", get(metrics_handler).route_layer(guard));
+        }
+        router
+    }
+
+    /// 单独监听时使用的管理 API Router (路径仍以 /admin 开头，另外提供 /metrics)
+    ///
+    /// 和主监听地址一样经过来源 IP 白名单，并写进访问日志
+    #[cfg(feature = "admin")]
+    pub fn admin_router(&self) -> Router {
+        let state = self.state.clone();
+        let router = Router::new().nest("/admin", admin::router(state.clone(), true));
+        #[cfg(feature = "metrics")]
+        let router = match (self.metrics_public, &state.admin_token) {
+            (true, _) => router,
+            (false, Some(_)) => {
+                let guard = middleware::from_fn_with_state(state.clone(), admin::require_token);
+                router.route("/metrics", get(metrics_handler).route_layer(guard))
+            }
+            (false, None) => router.route("/metrics", get(metrics_handler)),
+        };
+        router
+            .layer(middleware::from_fn_with_state(state.clone(), ip_filter::guard))
+            .layer(middleware::from_fn_with_state(state.clone(), access_log::record))
+            .layer(middleware::from_fn_with_state(state.clone(), client_ip::resolve))
+            .layer(middleware::from_fn_with_state(state.clone(), assign_request_id))
+            .with_state(state)
+    }
+
+    fn apply_layers(&self, router: Router<Arc<AppState>>, position: LayerPosition) -> Router<Arc<AppState>> {
+        self.layers
+            .iter()
+            .filter(|(p, _)| *p == position)
+            .fold(router, |router, (_, apply)| apply(router))
+    }
+
+    /// 绑定所有监听地址并一直运行，任一地址出错都会返回
+    pub async fn serve(self) -> std::io::Result<()> {
+        let app = self.router();
+        let admin = self.admin_app();
+        self.run(app, admin).await
+    }
+
+    // 单独监听的管理 API
+    fn admin_addr(&self) -> Option<SocketAddr> {
+        #[cfg(feature = "admin")]
+        return self.admin_listen;
+        #[cfg(not(feature = "admin"))]
+        None
+    }
+
+    fn admin_app(&self) -> Option<Router> {
+        #[cfg(feature = "admin")]
+        if self.admin_listen.is_some() {
+            return Some(self.admin_router());
+        }
+        None
+    }
+
+    /// 和 [`Gateway::serve`] 一样监听，但每从 `reloads` 收到一个新的 Gateway 就原地替换路由：
+    /// socket 不重新绑定，新请求立即使用新配置，已经在处理的请求 (包括进行中的流式响应)
+    /// 继续使用旧配置直到结束。新 Gateway 的监听地址会被忽略。
+    pub async fn serve_reloadable(self, mut reloads: mpsc::Receiver<Gateway>) -> std::io::Result<()> {
+        let current = Arc::new(RwLock::new(self.router()));
+        let admin = self.admin_app().map(|router| Arc::new(RwLock::new(router)));
+        let (swap, admin_swap) = (current.clone(), admin.clone());
+        let listeners = (self.listen.clone(), self.plaintext.clone(), self.unix_listen.clone(), self.admin_addr());
+        tokio::spawn(async move {
+            while let Some(next) = reloads.recv().await {
+                let next_listeners = (next.listen.clone(), next.plaintext.clone(), next.unix_listen.clone(), next.admin_addr());
+                if next_listeners != listeners {
+                    warn!("⚠️  Listen addresses changed, restart to apply");
+                }
+                #[cfg(unix)]
+                crate::systemd::notify("RELOADING=1");
+                *swap.write().unwrap_or_else(|e| e.into_inner()) = next.router();
+                if let (Some(admin), Some(router)) = (&admin_swap, next.admin_app()) {
+                    *admin.write().unwrap_or_else(|e| e.into_inner()) = router;
+                }
+                #[cfg(unix)]
+                crate::systemd::notify("READY=1");
+            }
+        });
+        self.run(swappable(current), admin.map(swappable)).await
+    }
+
+    /// 所有 TCP 监听地址共用同一份证书 (listen_plain 的地址和 Unix socket 总是明文)；serve_reloadable 替换进来的 Gateway 的
+    /// TLS 设置被忽略，证书更新靠文件监视
+    /// 管理 API 单独监听时只走明文 HTTP。摘流开始 drain_delay 之后所有监听停止接受新连接，
+    /// 在途请求完成或再超过 drain_timeout 后返回
+    async fn run(&self, app: Router, admin: Option<Router>) -> std::io::Result<()> {
+        let delay = self.drain_delay;
+        let shutdown = || {
+            let drain = self.state.drain.clone();
+            async move {
+                drain.started().await;
+                tokio::time::sleep(delay).await;
+            }
+        };
+        #[cfg(feature = "tls")]
+        if let Some(certs) = &self.tls {
+            tls::watch(certs.clone());
+        }
+        let mut servers: Vec<futures_util::future::BoxFuture<std::io::Result<()>>> = Vec::new();
+        // systemd 传下来了监听 socket 时用它们代替 --listen / --unix-socket
+        #[cfg(unix)]
+        let mut inherited = crate::systemd::take_listeners();
+        #[cfg(unix)]
+        let admin_inherited = self.admin_addr().and_then(|addr| inherited.take_tcp(addr));
+        #[cfg(unix)]
+        let socket_activated = !inherited.is_empty();
+        #[cfg(not(unix))]
+        let socket_activated = false;
+        let mut listeners = Vec::new();
+        #[cfg(unix)]
+        for (addr, listener) in std::mem::take(&mut inherited.tcp) {
+            info!("🎧 Listening on {} (systemd)", addr);
+            listeners.push((addr, tokio::net::TcpListener::from_std(listener)?));
+        }
+        if !socket_activated {
+            for &addr in &self.listen {
+                listeners.push((addr, bind(addr, &self.listen)?));
+            }
+        }
+        #[cfg_attr(not(feature = "tls"), allow(unused_variables))]
+        for (addr, listener) in listeners {
+            let app = app.clone();
+            #[cfg(feature = "tls")]
+            if let Some(certs) = self.tls.clone().filter(|_| !self.plaintext.contains(&addr)) {
+                #[cfg(feature = "http3")]
+                let app = match self.http3 {
+                    true => {
+                        let endpoint = crate::http3::bind(addr, &certs)?;
+                        servers.push(Box::pin(crate::http3::serve(endpoint, app.clone(), shutdown())));
+                        crate::http3::advertise(app, addr.port())
+                    }
+                    false => app,
+                };
+                servers.push(Box::pin(tls::serve(listener, app, certs, shutdown())));
+                continue;
+            }
+            let shutdown = shutdown();
+            servers.push(Box::pin(async move {
+                axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+                    .with_graceful_shutdown(shutdown)
+                    .await
+            }));
+        }
+        #[cfg(unix)]
+        for listener in std::mem::take(&mut inherited.unix) {
+            let listener = tokio::net::UnixListener::from_std(listener)?;
+            info!("🎧 Listening on unix:{} (systemd)", listener.local_addr()?.as_pathname().map(|p| p.display().to_string()).unwrap_or_default());
+            servers.push(Box::pin(crate::unix_socket::serve(listener, app.clone(), shutdown())));
+        }
+        #[cfg(unix)]
+        for path in self.unix_listen.iter().filter(|_| !socket_activated) {
+            let listener = crate::unix_socket::bind(path, self.unix_socket_mode)?;
+            servers.push(Box::pin(crate::unix_socket::serve(listener, app.clone(), shutdown())));
+        }
+        if let (Some(addr), Some(admin)) = (self.admin_addr(), admin) {
+            #[cfg(unix)]
+            let listener = match admin_inherited {
+                Some(listener) => tokio::net::TcpListener::from_std(listener)?,
+                None => bind(addr, &[addr])?,
+            };
+            #[cfg(not(unix))]
+            let listener = bind(addr, &[addr])?;
+            info!("🛠️  Admin API on http://{}/admin", addr);
+            let shutdown = shutdown();
+            servers.push(Box::pin(async move {
+                axum::serve(listener, admin.into_make_service_with_connect_info::<SocketAddr>())
+                    .with_graceful_shutdown(shutdown)
+                    .await
+            }));
+        }
+        #[cfg(unix)]
+        {
+            crate::systemd::notify("READY=1");
+            crate::systemd::spawn_watchdog();
+            let drain = self.state.drain.clone();
+            tokio::spawn(async move {
+                drain.started().await;
+                crate::systemd::notify("STOPPING=1");
+            });
+        }
+        let deadline = shutdown().then(|_| tokio::time::sleep(self.drain_timeout));
+        let result = tokio::select! {
+            result = futures_util::future::try_join_all(servers) => {
+                result.map(|_| info!("👋 Drained, shutting down"))
+            }
+            _ = deadline => {
+                warn!(
+                    "⏱️  Drain deadline of {}s reached with {} requests in flight, shutting down",
+                    self.drain_timeout.as_secs(),
+                    self.state.in_flight.load(Ordering::Relaxed)
+                );
+                Ok(())
+            }
+        };
+        // 出错退出时也删掉 socket 文件；systemd 的 socket 归 systemd 管
+        for path in self.unix_listen.iter().filter(|_| !socket_activated) {
+            let _ = std::fs::remove_file(path);
+        }
+        result
+    }
+}
+
+// 按请求取当前路由，keep-alive 连接上的下一个请求也会用到新配置
+fn swappable(current: Arc<RwLock<Router>>) -> Router {
+    Router::new().fallback(move |req: Request| {
+        let mut router = current.read().unwrap_or_else(|e| e.into_inner()).clone();
+        async move { router.call(req).await }
+    })
+}
+
+// rustls 要求证书和私钥在同一段 PEM 里
+fn load_identity(cert: &std::path::Path, key: &std::path::Path) -> Result<Identity, String> {
+    let mut pem = std::fs::read(cert).map_err(|e| format!("{}: {}", cert.display(), e))?;
+    pem.push(b'\n');
+    pem.extend(std::fs::read(key).map_err(|e| format!("{}: {}", key.display(), e))?);
+    Identity::from_pem(&pem).map_err(|e| format!("Invalid upstream client certificate: {}", e))
+}
+
+fn load_ca_certs(path: &std::path::Path) -> Result<Vec<Certificate>, String> {
+    let pem = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let certs = Certificate::from_pem_bundle(&pem).map_err(|e| format!("{}: invalid CA bundle: {}", path.display(), e))?;
+    if certs.is_empty() {
+        return Err(format!("{}: no certificates found", path.display()));
+    }
+    Ok(certs)
+}
+
+// v6 地址显式设置 IPV6_V6ONLY，不依赖系统的 net.ipv6.bindv6only
+fn bind(addr: SocketAddr, all: &[SocketAddr]) -> std::io::Result<tokio::net::TcpListener> {
+    let domain = if addr.is_ipv6() { Domain::IPV6 } else { Domain::IPV4 };
+    let socket = Socket::new(domain, Type::STREAM, Some(Protocol::TCP))?;
+    let mode = if addr.is_ipv6() {
+        // 同一端口另外绑定了 v4 地址时必须只收 v6，否则会和它冲突
+        let v6_only = all.iter().any(|other| other.is_ipv4() && other.port() == addr.port());
+        socket.set_only_v6(v6_only)?;
+        match (v6_only, addr.ip().is_unspecified()) {
+            (false, true) => " (dual-stack)",
+            _ => " (IPv6 only)",
+        }
+    } else {
+        ""
+    };
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket
+        .bind(&addr.into())
+        .map_err(|e| std::io::Error::new(e.kind(), format!("bind {}: {}", addr, e)))?;
+    socket.listen(1024)?;
+    info!("🎧 Listening on {}{}", addr, mode);
+    tokio::net::TcpListener::from_std(socket.into())
+}
+
+// 最外层：在所有中间件之前分配请求编号
+async fn assign_request_id(State(state): State<Arc<AppState>>, mut req: Request, next: Next) -> Response {
+    let id = state.request_ids.fetch_add(1, Ordering::Relaxed) + 1;
+    req.extensions_mut().insert(RequestId(id));
+    next.run(req).await
+}
+
+// 摘流或者所有默认上游都没通过主动健康检查时报告不健康，负载均衡停止往这里分流量
+async fn health_check(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    if state.drain.is_draining() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "Draining");
+    }
+    if !state.upstreams.any_healthy() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "No healthy upstream");
+    }
+    (StatusCode::OK, "OK")
+}
+
+async fn version() -> impl IntoResponse {
+    Json(build_info::json())
+}
+
+// 存活探针：进程能处理请求就是活的，摘流中也不让编排系统重启
+async fn liveness() -> impl IntoResponse {
+    Json(json!({ "status": "ok" }))
+}
+
+// 就绪探针：没在摘流、上游连得上 (最近有成功的请求或探测，否则在后台探测一次、这次先算未就绪)、有启用中的 key。
+// 所有 key 都在冷却只在详情里体现，不算未就绪：配额用完时摘掉所有副本只会更糟
+async fn readiness(State(state): State<Arc<AppState>>) -> Response {
+    let mut reasons = Vec::new();
+    let draining = state.drain.is_draining();
+    if draining {
+        reasons.push("draining");
+    }
+    let reachable = state.upstreams.any_healthy() && upstreams::ensure_reachable(&state);
+    if !reachable {
+        reasons.push("no reachable upstream");
+    }
+    let keys = state.key_pool.as_ref().map(|pool| {
+        if pool.enabled() == 0 {
+            reasons.push("no enabled key");
+        }
+        json!({
+            "total": pool.len(),
+            "enabled": pool.enabled(),
+            "available": pool.available(),
+        })
+    });
+    let status = match reasons.is_empty() {
+        true => StatusCode::OK,
+        false => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    let body = json!({
+        "status": if reasons.is_empty() { "ready" } else { "not_ready" },
+        "reasons": reasons,
+        "draining": draining,
+        "upstreams": state.upstreams.status(),
+        "keys": keys,
+    });
+    (status, Json(body)).into_response()
+}
+
+#[cfg(feature = "metrics")]
+async fn metrics_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    (
+        [("content-type", "text/plain; version=0.0.4")],
+        state.metrics.render(),
+    )
+}
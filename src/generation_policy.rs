@@ -0,0 +1,220 @@
+use async_trait::async_trait;
+use axum::{body::Bytes, response::Response};
+use serde_json::{Map, Value};
+use tracing::debug;
+
+use crate::plugin::{GatewayPlugin, RequestContext};
+use crate::validate::snake_case;
+
+// --- 生成参数策略 ---
+// 给不受信任的客户端统一设上限：转发前改写 generateContent / streamGenerateContent 请求体里的
+// generationConfig 和 safetySettings。规则写法 (FIELD 相对 generationConfig，可以用 . 访问下级，
+// 如 thinkingConfig.thinkingBudget)：
+//   FIELD<=N       超过 N 时改成 N
+//   FIELD>=N       低于 N 时改成 N
+//   FIELD=VALUE    总是改成 VALUE (JSON 值，如 1、0.5、"text/plain"、["END"])
+//   FIELD?=VALUE   客户端没给时补上 VALUE (限制输出长度时和 <= 搭配使用，否则没写 maxOutputTokens 的请求不受限)
+// 安全设置按类别强制：CATEGORY=THRESHOLD 覆盖客户端对同一类别的设置，没写的类别补上。
+// 字段名同时认 camelCase 和 snake_case，沿用客户端原来的写法。
+
+const METHODS: &[&str] = &["generateContent", "streamGenerateContent"];
+
+#[derive(Debug, Clone)]
+enum Op {
+    Max(f64),
+    Min(f64),
+    Set(Value),
+    Default(Value),
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    path: Vec<String>,
+    op: Op,
+}
+
+impl Rule {
+    fn parse(spec: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid generation policy '{}' (expected FIELD<=N, FIELD>=N, FIELD=VALUE or FIELD?=VALUE)", spec);
+        let (field, op) = if let Some((field, n)) = spec.split_once("<=") {
+            (field, Op::Max(n.trim().parse().map_err(|_| invalid())?))
+        } else if let Some((field, n)) = spec.split_once(">=") {
+            (field, Op::Min(n.trim().parse().map_err(|_| invalid())?))
+        } else if let Some((field, value)) = spec.split_once("?=") {
+            (field, Op::Default(parse_value(value)))
+        } else if let Some((field, value)) = spec.split_once('=') {
+            (field, Op::Set(parse_value(value)))
+        } else {
+            return Err(invalid());
+        };
+        let field = field.trim().strip_prefix("generationConfig.").unwrap_or(field.trim());
+        let path: Vec<String> = field.split('.').map(str::to_string).collect();
+        if path.iter().any(String::is_empty) {
+            return Err(invalid());
+        }
+        Ok(Self { path, op })
+    }
+
+    /// 应用到 generationConfig；有改动时返回 true
+    fn apply(&self, config: &mut Map<String, Value>) -> bool {
+        let (last, parents) = self.path.split_last().expect("non-empty path");
+        let mut target = config;
+        for name in parents {
+            let key = existing_key(target, name).unwrap_or_else(|| name.clone());
+            if !target.contains_key(&key) {
+                // 只有要写入值的规则才创建中间对象
+                if !matches!(self.op, Op::Set(_) | Op::Default(_)) {
+                    return false;
+                }
+                target.insert(key.clone(), Value::Object(Map::new()));
+            }
+            match target.get_mut(&key) {
+                Some(Value::Object(inner)) => target = inner,
+                _ => return false,
+            }
+        }
+        let key = existing_key(target, last);
+        let current = key.as_ref().and_then(|key| target.get(key));
+        let value = match (&self.op, current) {
+            (Op::Max(max), Some(current)) => clamp(current, |n| n > *max, *max),
+            (Op::Min(min), Some(current)) => clamp(current, |n| n < *min, *min),
+            (Op::Set(value), current) if current != Some(value) => Some(value.clone()),
+            (Op::Default(value), None) => Some(value.clone()),
+            _ => None,
+        };
+        let Some(value) = value else {
+            return false;
+        };
+        target.insert(key.unwrap_or_else(|| last.clone()), value);
+        true
+    }
+}
+
+fn parse_value(value: &str) -> Value {
+    let value = value.trim();
+    serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()))
+}
+
+/// 请求里实际使用的字段名 (camelCase 或 snake_case)
+fn existing_key(obj: &Map<String, Value>, name: &str) -> Option<String> {
+    if obj.contains_key(name) {
+        return Some(name.to_string());
+    }
+    let snake = snake_case(name);
+    obj.contains_key(&snake).then_some(snake)
+}
+
+/// 越界时返回 limit，否则 None
+fn clamp(current: &Value, over: impl Fn(f64) -> bool, limit: f64) -> Option<Value> {
+    let current = current.as_f64().filter(|n| over(*n))?;
+    // 整数字段 (maxOutputTokens、candidateCount 等) 保持整数
+    Some(if current.fract() == 0.0 && limit.fract() == 0.0 {
+        Value::from(limit as i64)
+    } else {
+        Value::from(limit)
+    })
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GenerationPolicy {
+    rules: Vec<Rule>,
+    /// (category, threshold)
+    safety: Vec<(String, String)>,
+}
+
+impl GenerationPolicy {
+    pub fn parse(rules: &[String], safety: &[String]) -> Result<Self, String> {
+        let rules = rules.iter().map(|spec| Rule::parse(spec)).collect::<Result<_, _>>()?;
+        let safety = safety
+            .iter()
+            .map(|spec| match spec.split_once('=') {
+                Some((category, threshold)) if !category.trim().is_empty() && !threshold.trim().is_empty() => {
+                    Ok((category.trim().to_string(), threshold.trim().to_string()))
+                }
+                _ => Err(format!("safety setting '{}' must be CATEGORY=THRESHOLD", spec)),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { rules, safety })
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len() + self.safety.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn apply_safety(&self, req: &mut Map<String, Value>) -> bool {
+        let key = existing_key(req, "safetySettings").unwrap_or_else(|| "safetySettings".to_string());
+        let settings = req.entry(key).or_insert_with(|| Value::Array(Vec::new()));
+        let Some(settings) = settings.as_array_mut() else {
+            return false;
+        };
+        let mut changed = false;
+        for (category, threshold) in &self.safety {
+            let existing = settings
+                .iter_mut()
+                .filter_map(Value::as_object_mut)
+                .find(|s| s.get("category").and_then(Value::as_str) == Some(category.as_str()));
+            match existing {
+                Some(setting) => {
+                    if setting.get("threshold").and_then(Value::as_str) != Some(threshold.as_str()) {
+                        setting.insert("threshold".to_string(), Value::String(threshold.clone()));
+                        changed = true;
+                    }
+                }
+                None => {
+                    let mut setting = Map::new();
+                    setting.insert("category".to_string(), Value::String(category.clone()));
+                    setting.insert("threshold".to_string(), Value::String(threshold.clone()));
+                    settings.push(Value::Object(setting));
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+
+    /// 改写后的请求体；没有改动时返回 None
+    fn apply(&self, body: &Bytes) -> Option<Bytes> {
+        let mut json: Value = serde_json::from_slice(body).ok()?;
+        let req = json.as_object_mut()?;
+        let mut changed = false;
+        if !self.rules.is_empty() {
+            let key = existing_key(req, "generationConfig").unwrap_or_else(|| "generationConfig".to_string());
+            let config = req.entry(key.clone()).or_insert_with(|| Value::Object(Map::new()));
+            if let Some(config) = config.as_object_mut() {
+                for rule in &self.rules {
+                    changed |= rule.apply(config);
+                }
+                if config.is_empty() {
+                    req.remove(&key);
+                }
+            }
+        }
+        if !self.safety.is_empty() {
+            changed |= self.apply_safety(req);
+        }
+        changed.then(|| Bytes::from(json.to_string()))
+    }
+}
+
+#[async_trait]
+impl GatewayPlugin for GenerationPolicy {
+    fn name(&self) -> &str {
+        "generation-policy"
+    }
+
+    async fn on_request(&self, ctx: &mut RequestContext) -> Result<(), Response> {
+        let method = ctx.uri.path().rsplit_once(':').map(|(_, method)| method);
+        if !method.is_some_and(|m| METHODS.contains(&m)) {
+            return Ok(());
+        }
+        if let Some(body) = self.apply(&ctx.body) {
+            debug!("📏 Applied generation policy to request {}", ctx.id);
+            ctx.body = body;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use maxminddb::{geoip2, Reader};
+use std::net::IpAddr;
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::client_ip::ClientIp;
+use crate::AppState;
+
+// --- GeoIP 国家级访问控制 ---
+pub struct GeoIp {
+    reader: Reader<Vec<u8>>,
+    // ISO 3166 国家码，统一大写
+    allow: Vec<String>,
+    deny: Vec<String>,
+}
+
+/// 写入 request extensions，供日志 / 指标读取
+#[derive(Clone, Debug)]
+pub struct GeoCountry(pub String);
+
+impl GeoIp {
+    pub fn open(path: &str, allow: &[String], deny: &[String]) -> Result<Self, String> {
+        let reader = Reader::open_readfile(path).map_err(|e| format!("{}: {}", path, e))?;
+        Ok(Self {
+            reader,
+            allow: allow.iter().map(|c| c.trim().to_uppercase()).collect(),
+            deny: deny.iter().map(|c| c.trim().to_uppercase()).collect(),
+        })
+    }
+
+    /// 查不到时返回 None (内网地址、库里缺失等)
+    pub fn country(&self, ip: IpAddr) -> Option<String> {
+        let record: geoip2::Country = self.reader.lookup(ip).ok()?;
+        record
+            .country
+            .and_then(|c| c.iso_code)
+            .map(|code| code.to_string())
+    }
+
+    /// 白名单非空时只放行名单内国家；未知国家在有白名单时一律拒绝
+    pub fn is_allowed(&self, country: Option<&str>) -> bool {
+        match country {
+            Some(code) => {
+                if self.deny.iter().any(|c| c == code) {
+                    return false;
+                }
+                self.allow.is_empty() || self.allow.iter().any(|c| c == code)
+            }
+            None => self.allow.is_empty(),
+        }
+    }
+}
+
+pub(crate) async fn guard(
+    State(state): State<Arc<AppState>>,
+    ClientIp(client_ip): ClientIp,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let Some(geoip) = &state.geoip else {
+        return next.run(req).await;
+    };
+
+    let country = geoip.country(client_ip);
+    let label = country.as_deref().unwrap_or("unknown");
+
+    if !geoip.is_allowed(country.as_deref()) {
+        warn!("🌍 GeoIP denied {} [{}]", client_ip, label);
+        state.metrics.inc("aizasy_geoip_denied_total", &[("country", label)]);
+        return (StatusCode::FORBIDDEN, "Forbidden").into_response();
+    }
+
+    state.metrics.inc("aizasy_geoip_requests_total", &[("country", label)]);
+    req.extensions_mut().insert(GeoCountry(label.to_string()));
+    next.run(req).await
+}
@@ -0,0 +1,82 @@
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+
+// --- gRPC 透传 ---
+// 打开后 `application/grpc` (含 grpc-web) 请求转发到单独的 gRPC 上游 (默认 generativelanguage.googleapis.com:443)，
+// 用只走 HTTP/2 的客户端，gRPC SDK 可以和 REST / SSE 客户端一样指向网关：
+//   - 响应不缓冲、不改写，逐帧转发，grpc-status / grpc-message 等 trailers 原样带回 (见 trailers)；
+//   - key 池照常使用，key 一律放在 x-goog-api-key 元数据里 (gRPC 没有查询参数)；
+//   - 上游只带响应头的错误 (trailers-only，如 RESOURCE_EXHAUSTED) 按对应的 HTTP 状态参与 key 冷却、换上游和重试；
+//   - 网关自己的错误 (没有可用 key、排队超时、连不上上游) 也按 gRPC 的方式返回，客户端能拿到正确的状态码。
+// 请求体仍然完整读取后再转发，一元调用和服务端流 (StreamGenerateContent) 都没问题，客户端流 / 双向流不支持。
+
+pub const DEFAULT_GRPC_TARGET: &str = "https://generativelanguage.googleapis.com";
+
+#[derive(Debug, Clone)]
+pub struct GrpcConfig {
+    pub target: String,
+}
+
+impl Default for GrpcConfig {
+    fn default() -> Self {
+        Self {
+            target: DEFAULT_GRPC_TARGET.to_string(),
+        }
+    }
+}
+
+pub(crate) fn is_grpc(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/grpc"))
+}
+
+/// trailers-only 响应里的 grpc-status 换成对应的 HTTP 状态，正常的响应 (状态在 trailers 里) 保持原样
+pub(crate) fn effective_status(status: StatusCode, headers: &HeaderMap) -> StatusCode {
+    let code = headers
+        .get("grpc-status")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u16>().ok());
+    match code {
+        Some(4) => StatusCode::GATEWAY_TIMEOUT,
+        Some(7) => StatusCode::FORBIDDEN,
+        Some(8) => StatusCode::TOO_MANY_REQUESTS,
+        Some(13) => StatusCode::INTERNAL_SERVER_ERROR,
+        Some(14) => StatusCode::SERVICE_UNAVAILABLE,
+        Some(16) => StatusCode::UNAUTHORIZED,
+        _ => status,
+    }
+}
+
+fn code_for(status: StatusCode) -> u16 {
+    match status {
+        StatusCode::BAD_REQUEST => 3,
+        StatusCode::UNAUTHORIZED => 16,
+        StatusCode::FORBIDDEN => 7,
+        StatusCode::NOT_FOUND => 5,
+        StatusCode::TOO_MANY_REQUESTS => 8,
+        StatusCode::GATEWAY_TIMEOUT => 4,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE => 14,
+        _ => 2,
+    }
+}
+
+/// 网关自己的错误，以 trailers-only 的 gRPC 响应返回
+pub(crate) fn error_response(status: StatusCode, message: &str) -> Response {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/grpc"));
+    headers.insert("grpc-status", HeaderValue::from(code_for(status)));
+    // grpc-message 按百分号编码
+    let encoded: String = message
+        .bytes()
+        .map(|b| match b {
+            b' '..=b'~' if b != b'%' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect();
+    if let Ok(value) = HeaderValue::from_str(&encoded) {
+        headers.insert("grpc-message", value);
+    }
+    (StatusCode::OK, headers).into_response()
+}
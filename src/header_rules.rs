@@ -0,0 +1,95 @@
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::Arc;
+
+use crate::AppState;
+
+// --- 请求 / 响应头改写规则 ---
+// 格式 DIRECTION:ACTION:NAME[=VALUE]，按配置顺序执行：
+//   request:set:x-goog-user-project=my-project    发往上游的请求头，覆盖同名头
+//   request:remove:x-client-version
+//   response:remove:server                        返回给客户端的响应 (上游透传的和网关自己生成的都算)
+//   response:add:strict-transport-security=max-age=31536000
+// add 追加一个值，set 替换所有同名值，remove 删除。请求方向在网关自带的清洗 (host、
+// x-forwarded-for、cf-* 等) 之后执行，所以也可以用 set 把这些头按需要补回去。
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Request,
+    Response,
+}
+
+#[derive(Debug, Clone)]
+enum Action {
+    Add(HeaderValue),
+    Set(HeaderValue),
+    Remove,
+}
+
+#[derive(Debug, Clone)]
+pub struct HeaderRule {
+    direction: Direction,
+    name: HeaderName,
+    action: Action,
+}
+
+impl HeaderRule {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut parts = spec.splitn(3, ':');
+        let (Some(direction), Some(action), Some(rest)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(format!("header rule '{}' must be DIRECTION:ACTION:NAME[=VALUE]", spec));
+        };
+        let direction = match direction.trim() {
+            "request" | "req" => Direction::Request,
+            "response" | "resp" => Direction::Response,
+            other => return Err(format!("unknown header rule direction '{}'", other)),
+        };
+        let (name, value) = match rest.split_once('=') {
+            Some((name, value)) => (name.trim(), Some(value.trim())),
+            None => (rest.trim(), None),
+        };
+        let name = HeaderName::try_from(name).map_err(|_| format!("invalid header name '{}'", name))?;
+        let parse_value = |value: Option<&str>| {
+            let value = value.ok_or_else(|| format!("header rule '{}' needs a value", spec))?;
+            HeaderValue::from_str(value).map_err(|_| format!("invalid header value in '{}'", spec))
+        };
+        let action = match action.trim() {
+            "add" => Action::Add(parse_value(value)?),
+            "set" => Action::Set(parse_value(value)?),
+            "remove" | "del" => Action::Remove,
+            other => return Err(format!("unknown header rule action '{}'", other)),
+        };
+        Ok(Self { direction, name, action })
+    }
+
+    fn apply(&self, headers: &mut HeaderMap) {
+        match &self.action {
+            Action::Add(value) => {
+                headers.append(self.name.clone(), value.clone());
+            }
+            Action::Set(value) => {
+                headers.insert(self.name.clone(), value.clone());
+            }
+            Action::Remove => {
+                headers.remove(&self.name);
+            }
+        }
+    }
+}
+
+/// 按顺序执行某个方向上的所有规则
+pub(crate) fn apply(rules: &[HeaderRule], direction: Direction, headers: &mut HeaderMap) {
+    for rule in rules.iter().filter(|r| r.direction == direction) {
+        rule.apply(headers);
+    }
+}
+
+pub(crate) async fn rewrite_response(State(state): State<Arc<AppState>>, req: Request, next: Next) -> Response {
+    let mut response = next.run(req).await;
+    apply(&state.header_rules, Direction::Response, response.headers_mut());
+    response
+}
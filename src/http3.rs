@@ -0,0 +1,169 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{ConnectInfo, Request},
+    http::{header, HeaderValue},
+    response::Response,
+    Router,
+};
+use futures_util::StreamExt;
+use hyper::body::Buf;
+use quinn::{Endpoint, Incoming};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tower::Service;
+use tracing::{debug, info};
+
+use crate::tls::TlsCerts;
+
+// --- HTTP/3 监听 ---
+// 在每个 HTTPS 地址的同一端口上再监听 UDP，跑 HTTP/3 (QUIC)；TCP 上的响应带 Alt-Svc 头，
+// 支持的客户端下次自动切到 HTTP/3。QUIC 没有 TCP 的队头阻塞，连接还能在 IP 变化后迁移，
+// 移动网络上丢包多时长时间的 SSE 流更稳定。证书和 HTTPS 监听共用，热更新同样生效。
+// 摘流时先发 GOAWAY 不再接受新请求，已经在处理的请求 (包括流式响应) 继续完成。
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 摘流时等客户端关闭连接的时间
+const CLOSE_GRACE: Duration = Duration::from_secs(1);
+
+/// Alt-Svc 广告的有效期
+const ALT_SVC_MAX_AGE: u64 = 86400;
+
+pub(crate) fn bind(addr: SocketAddr, certs: &Arc<TlsCerts>) -> std::io::Result<Endpoint> {
+    let config = certs
+        .quic_config()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let endpoint = Endpoint::server(config, addr)
+        .map_err(|e| std::io::Error::new(e.kind(), format!("bind udp {}: {}", addr, e)))?;
+    info!("🎧 Listening on {} (HTTP/3)", addr);
+    Ok(endpoint)
+}
+
+/// 给 TCP 上的响应加上 Alt-Svc，告诉客户端同一端口有 HTTP/3
+pub(crate) fn advertise(app: Router, port: u16) -> Router {
+    let value = HeaderValue::from_str(&format!("h3=\":{}\"; ma={}", port, ALT_SVC_MAX_AGE)).expect("valid Alt-Svc");
+    app.layer(axum::middleware::map_response(move |mut response: Response| {
+        let value = value.clone();
+        async move {
+            response.headers_mut().insert(header::ALT_SVC, value);
+            response
+        }
+    }))
+}
+
+/// 接受 QUIC 连接并把每个 HTTP/3 请求交给 app；shutdown 完成后不再接受新连接，
+/// 对已有连接发 GOAWAY，等在途请求处理完再返回
+pub(crate) async fn serve(endpoint: Endpoint, app: Router, shutdown: impl Future<Output = ()>) -> std::io::Result<()> {
+    let (stop_tx, stop_rx) = watch::channel(false);
+    let mut connections = JoinSet::new();
+    tokio::pin!(shutdown);
+    loop {
+        let incoming = tokio::select! {
+            incoming = endpoint.accept() => incoming,
+            _ = &mut shutdown => break,
+        };
+        let Some(incoming) = incoming else {
+            break;
+        };
+        let app = app.clone();
+        let stop = stop_rx.clone();
+        connections.spawn(async move {
+            let addr = incoming.remote_address();
+            if let Err(e) = serve_connection(incoming, addr, app, stop).await {
+                debug!("HTTP/3 connection from {} closed: {}", addr, e);
+            }
+        });
+        // 回收已经结束的连接任务
+        while connections.try_join_next().is_some() {}
+    }
+    endpoint.set_server_config(None);
+    let _ = stop_tx.send(true);
+    while connections.join_next().await.is_some() {}
+    endpoint.close(0u32.into(), b"");
+    endpoint.wait_idle().await;
+    Ok(())
+}
+
+async fn serve_connection(
+    incoming: Incoming,
+    addr: SocketAddr,
+    app: Router,
+    mut stop: watch::Receiver<bool>,
+) -> Result<(), String> {
+    let connection = tokio::time::timeout(HANDSHAKE_TIMEOUT, incoming)
+        .await
+        .map_err(|_| "handshake timed out".to_string())?
+        .map_err(|e| e.to_string())?;
+    let quic = connection.clone();
+    let mut connection = h3::server::Connection::<_, Bytes>::new(h3_quinn::Connection::new(connection))
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut requests = JoinSet::new();
+    let result = loop {
+        let accepted = tokio::select! {
+            accepted = connection.accept() => accepted,
+            _ = stop.changed() => {
+                // GOAWAY：不再接受新请求，已经收到的继续处理
+                let result = connection.shutdown(0).await.map_err(|e| e.to_string());
+                while requests.join_next().await.is_some() {}
+                // 给客户端一点时间确认最后的数据并自己关闭连接，之后由 endpoint 统一关闭
+                let _ = tokio::time::timeout(CLOSE_GRACE, quic.closed()).await;
+                break result;
+            }
+        };
+        match accepted {
+            Ok(Some(resolver)) => {
+                let app = app.clone();
+                requests.spawn(async move {
+                    if let Err(e) = serve_request(resolver, addr, app).await {
+                        debug!("HTTP/3 request from {} failed: {}", addr, e);
+                    }
+                });
+                while requests.try_join_next().is_some() {}
+            }
+            Ok(None) => break Ok(()),
+            Err(e) if e.is_h3_no_error() => break Ok(()),
+            Err(e) => break Err(e.to_string()),
+        }
+    };
+    // 连接要活到所有请求结束
+    while requests.join_next().await.is_some() {}
+    result
+}
+
+async fn serve_request(
+    resolver: h3::server::RequestResolver<h3_quinn::Connection, Bytes>,
+    addr: SocketAddr,
+    mut app: Router,
+) -> Result<(), String> {
+    let (request, stream) = resolver.resolve_request().await.map_err(|e| e.to_string())?;
+    let (mut send, recv) = stream.split();
+
+    // 请求体边收边交给 app
+    let body = futures_util::stream::unfold(recv, |mut recv| async move {
+        match recv.recv_data().await {
+            Ok(Some(mut chunk)) => Some((Ok(chunk.copy_to_bytes(chunk.remaining())), recv)),
+            Ok(None) => None,
+            Err(e) => Some((Err(e), recv)),
+        }
+    });
+    let (parts, ()) = request.into_parts();
+    let mut request = Request::from_parts(parts, Body::from_stream(body));
+    request.extensions_mut().insert(ConnectInfo(addr));
+
+    let response = app.call(request).await.unwrap_or_else(|e| match e {});
+    let (parts, body) = response.into_parts();
+    send.send_response(Response::from_parts(parts, ()))
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut body = body.into_data_stream();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(|e| e.to_string())?;
+        send.send_data(chunk).await.map_err(|e| e.to_string())?;
+    }
+    send.finish().await.map_err(|e| e.to_string())
+}
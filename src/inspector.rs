@@ -0,0 +1,92 @@
+use async_trait::async_trait;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+
+use crate::plugin::{GatewayPlugin, Outcome, RequestContext};
+use crate::sanitize::sanitize_path;
+use crate::usage::{self, UpstreamKeyId};
+
+// --- 实时请求查看器 ---
+// 内存里保留最近 N 个请求的摘要，管理 API 可以直接查看，也可以通过 SSE 实时订阅新完成的请求，
+// 不需要登录机器翻日志。只记录摘要，不含请求体和请求头。
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RequestSummary {
+    pub id: u64,
+    /// unix 毫秒
+    pub at: u64,
+    pub method: String,
+    /// 已脱敏的 path + query
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    pub client: String,
+    pub status: Option<u16>,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+pub struct RequestInspector {
+    capacity: usize,
+    recent: Mutex<VecDeque<RequestSummary>>,
+    live: broadcast::Sender<RequestSummary>,
+}
+
+impl RequestInspector {
+    pub fn new(capacity: usize) -> Self {
+        let (live, _) = broadcast::channel(256);
+        Self {
+            capacity: capacity.max(1),
+            recent: Mutex::new(VecDeque::new()),
+            live,
+        }
+    }
+
+    /// 最近的请求，按时间倒序
+    pub fn recent(&self, limit: usize) -> Vec<RequestSummary> {
+        self.recent.lock().unwrap().iter().rev().take(limit).cloned().collect()
+    }
+
+    /// 订阅之后完成的请求；跟不上时会丢掉最旧的摘要 (RecvError::Lagged)
+    pub fn subscribe(&self) -> broadcast::Receiver<RequestSummary> {
+        self.live.subscribe()
+    }
+}
+
+#[async_trait]
+impl GatewayPlugin for RequestInspector {
+    fn name(&self) -> &str {
+        "inspector"
+    }
+
+    fn on_complete(&self, ctx: &RequestContext, outcome: &Outcome) {
+        let path = ctx.uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+        let summary = RequestSummary {
+            id: ctx.id,
+            at: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64,
+            method: ctx.method.to_string(),
+            path: sanitize_path(path),
+            model: usage::model_from_path(ctx.uri.path()).map(str::to_string),
+            client: usage::client_label(ctx),
+            status: outcome.status.map(|s| s.as_u16()),
+            latency_ms: outcome.duration.as_millis() as u64,
+            key: ctx.extensions.get::<UpstreamKeyId>().map(|k| k.0.clone()),
+            error: outcome.error.clone(),
+        };
+        {
+            let mut recent = self.recent.lock().unwrap();
+            if recent.len() == self.capacity {
+                recent.pop_front();
+            }
+            recent.push_back(summary.clone());
+        }
+        // 没有订阅者时发送失败，忽略即可
+        let _ = self.live.send(summary);
+    }
+}
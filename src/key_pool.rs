@@ -0,0 +1,1287 @@
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, info, warn};
+
+use crate::keys::KeyEntry;
+use crate::sanitize::sanitize_path;
+use crate::AppState;
+
+// --- 上游 key 池 ---
+// 网关持有一组真实的 Gemini API key，转发时按请求轮询注入，客户端不需要持有真实 key。
+// 客户端自带的 x-goog-api-key / ?key= 会被丢弃，避免绕过 key 池直接打到上游。
+// 上游对某个 key 返回 429 / 403 时，这个 key 进入冷却，请求换下一个 key 透明重试；
+// 所有 key 都在冷却时仍然选最早恢复的那个，而不是直接拒绝。
+// 管理 API 可以在运行时停用某个 key (如泄露、欠费)，停用的 key 不再被选中，直到重新启用。
+//
+// 选 key 默认轮询；sticky 模式按客户端身份 (或指定的会话请求头) 做 rendezvous 哈希，同一个客户端总是
+// 落到同一个 key 上：上游按 key 做的隐式缓存更容易命中，滥用也能追溯到具体客户端。
+// 首选的 key 冷却或停用时按同一个哈希顺序取下一个，增删 key 只影响原来落在这个 key 上的客户端。
+//
+// key 可以分组 (--key-group NAME=KEY1,KEY2)：路由规则或租户指定了分组时只从组里的 key 中选，
+// 一个团队、一条路由的流量不会用掉别人的配额；没有指定分组的请求照常使用整个池。
+//
+// 可以限制每个 key 同时在处理的请求数 (部分档位的 Gemini 按 key 限制并发)：从选中到响应体发完都占着名额，
+// 满了的 key 跳过，请求落到池里其他 key 上；所有 key 都满时开了 --rate-limit-queue 就排队等名额，否则返回 429。
+//
+// 失效的 key 可以被隔离 (--key-quarantine-after，默认关闭)：上游连续 N 次对同一个 key 返回
+// `API key not valid` (API_KEY_INVALID) 等只和 key 有关的原因时，这个 key 不再参与选择，
+// 和会自动恢复的冷却不同，隔离要通过管理 API 解除 (SIGHUP 重载配置会重置)。中间只要有一次别的响应就重新计数，偶发的 403 不会触发隔离。
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyInjection {
+    /// `x-goog-api-key` 请求头
+    Header,
+    /// `?key=` 查询参数
+    Query,
+}
+
+impl FromStr for KeyInjection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "header" => Ok(KeyInjection::Header),
+            "query" => Ok(KeyInjection::Query),
+            other => Err(format!("unknown key injection '{}', expected header or query", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeySelection {
+    RoundRobin,
+    /// 按客户端身份 / 会话头固定 key
+    Sticky,
+}
+
+impl FromStr for KeySelection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "round-robin" => Ok(KeySelection::RoundRobin),
+            "sticky" => Ok(KeySelection::Sticky),
+            other => Err(format!("unknown key selection '{}', expected round-robin or sticky", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FailoverConfig {
+    /// 单个请求最多尝试几个 key (含第一次)
+    pub max_attempts: usize,
+    /// 429 且上游没给 Retry-After 时的冷却时间
+    pub rate_limited_cooldown: Duration,
+    /// 403 (key 失效、无权限) 的冷却时间
+    pub forbidden_cooldown: Duration,
+}
+
+impl Default for FailoverConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            rate_limited_cooldown: Duration::from_secs(60),
+            forbidden_cooldown: Duration::from_secs(600),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyStatus {
+    pub name: String,
+    /// 剩余冷却秒数，0 表示可用
+    pub cooldown_secs: u64,
+    pub disabled: bool,
+    /// 被隔离的原因 (API_KEY_INVALID 等)，没有隔离时为 null
+    pub quarantined: Option<String>,
+    /// 当前在处理的请求数
+    pub active: usize,
+}
+
+// 一个 key 和它的运行时状态；热重载时同名同值的 key 沿用原来的状态
+struct Slot {
+    // 对外的 key 序号：按加入顺序分配，不会复用，压缩 slots 后也不变
+    id: usize,
+    entry: Arc<KeyEntry>,
+    // 冷却到的 unix 毫秒
+    cooldown_until: AtomicU64,
+    disabled: AtomicBool,
+    // 连续被判定失效的次数和隔离原因
+    strikes: AtomicU32,
+    quarantined: Mutex<Option<String>>,
+    // 当前占用的并发名额
+    active: AtomicUsize,
+    // 热重载时从 key 列表里去掉了；还有在途请求时先留着，下次重载再清掉
+    removed: AtomicBool,
+}
+
+impl Slot {
+    fn new(id: usize, entry: KeyEntry) -> Arc<Self> {
+        Arc::new(Self {
+            id,
+            entry: Arc::new(entry),
+            cooldown_until: AtomicU64::new(0),
+            disabled: AtomicBool::new(false),
+            strikes: AtomicU32::new(0),
+            quarantined: Mutex::new(None),
+            active: AtomicUsize::new(0),
+            removed: AtomicBool::new(false),
+        })
+    }
+
+    fn quarantine_reason(&self) -> Option<String> {
+        self.quarantined.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn live(&self) -> bool {
+        !self.removed.load(Ordering::Relaxed)
+    }
+
+    // 没有被删掉、停用或隔离
+    fn usable(&self) -> bool {
+        self.live() && !self.disabled.load(Ordering::Relaxed) && self.quarantine_reason().is_none()
+    }
+}
+
+pub struct KeyPool {
+    // 按 id 递增排列；热重载删掉的 key 先标记为 removed，没有在途请求后移除
+    slots: RwLock<Vec<Arc<Slot>>>,
+    // 下一个新 key 的 id
+    next_id: AtomicUsize,
+    injection: KeyInjection,
+    failover: FailoverConfig,
+    selection: KeySelection,
+    // sticky 模式下优先按这个请求头的值固定 key，没有这个头时按客户端身份
+    affinity_header: Option<HeaderName>,
+    next: AtomicUsize,
+    // 连续多少次被上游判定失效后隔离，0 表示不隔离
+    quarantine_after: u32,
+    // 每个 key 的并发上限
+    max_concurrency: Option<usize>,
+    // 分组名 -> 组里的 key 名
+    groups: HashMap<String, Vec<String>>,
+}
+
+
+fn unix_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+// FNV-1a 加 splitmix64 收尾：不依赖标准库哈希的实现细节，多个副本、不同版本算出来的结果一致。
+// 用 key 名而不是序号参与哈希，调整 key 的顺序不会改变分配
+fn rendezvous_score(affinity: &str, name: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in affinity.bytes().chain([0]).chain(name.bytes()) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash ^= hash >> 30;
+    hash = hash.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash ^= hash >> 27;
+    hash = hash.wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+impl KeyPool {
+    pub fn new(keys: Vec<KeyEntry>, injection: KeyInjection) -> Result<Self, String> {
+        validate(&keys)?;
+        let count = keys.len();
+        Ok(Self {
+            slots: RwLock::new(keys.into_iter().enumerate().map(|(id, entry)| Slot::new(id, entry)).collect()),
+            next_id: AtomicUsize::new(count),
+            injection,
+            failover: FailoverConfig::default(),
+            selection: KeySelection::RoundRobin,
+            affinity_header: None,
+            next: AtomicUsize::new(0),
+            quarantine_after: 0,
+            max_concurrency: None,
+            groups: HashMap::new(),
+        })
+    }
+
+    /// key 分组；组里的 key 名要在池里 (热重载后不在的 key 只是选不到)
+    pub fn with_groups(mut self, groups: HashMap<String, Vec<String>>) -> Result<Self, String> {
+        let names = self.names();
+        for (group, keys) in &groups {
+            if let Some(missing) = keys.iter().find(|name| !names.contains(name)) {
+                return Err(format!("key group '{}': no key named '{}'", group, missing));
+            }
+        }
+        self.groups = groups;
+        Ok(self)
+    }
+
+    pub fn has_group(&self, group: &str) -> bool {
+        self.groups.contains_key(group)
+    }
+
+    /// 分组名和组里的 key 名
+    pub fn groups(&self) -> &HashMap<String, Vec<String>> {
+        &self.groups
+    }
+
+    // 没有指定分组时所有 key 都算；不存在的分组一个 key 都没有
+    fn in_group(&self, slot: &Slot, group: Option<&str>) -> bool {
+        match group {
+            None => true,
+            Some(group) => self.groups.get(group).is_some_and(|keys| keys.contains(&slot.entry.name)),
+        }
+    }
+
+    pub fn with_failover(mut self, failover: FailoverConfig) -> Self {
+        self.failover = failover;
+        self
+    }
+
+    pub fn with_selection(mut self, selection: KeySelection, affinity_header: Option<HeaderName>) -> Self {
+        self.selection = selection;
+        self.affinity_header = affinity_header;
+        self
+    }
+
+    /// 连续 after 次被上游判定失效后隔离 key，0 表示不隔离
+    pub fn with_quarantine(mut self, after: u32) -> Self {
+        self.quarantine_after = after;
+        self
+    }
+
+    pub fn quarantine_after(&self) -> u32 {
+        self.quarantine_after
+    }
+
+    /// 每个 key 同时在处理的请求上限
+    pub fn with_max_concurrency(mut self, max: usize) -> Self {
+        self.max_concurrency = Some(max).filter(|max| *max > 0);
+        self
+    }
+
+    pub fn max_concurrency(&self) -> Option<usize> {
+        self.max_concurrency
+    }
+
+    pub fn selection(&self) -> KeySelection {
+        self.selection
+    }
+
+    fn slots(&self) -> RwLockReadGuard<'_, Vec<Arc<Slot>>> {
+        self.slots.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // 按序号找 key；已经被重载清掉的返回 None
+    fn slot(&self, index: usize) -> Option<Arc<Slot>> {
+        let slots = self.slots();
+        slots.binary_search_by_key(&index, |s| s.id).ok().map(|i| slots[i].clone())
+    }
+
+    // 按名字找还在池里的 key
+    fn find(&self, name: &str) -> Option<Arc<Slot>> {
+        self.slots().iter().find(|s| s.live() && s.entry.name == name).cloned()
+    }
+
+    /// sticky 模式下这个请求用来固定 key 的值：会话头，没有时调用 client 取客户端身份；轮询模式返回 None
+    pub fn affinity(&self, headers: &HeaderMap, client: impl FnOnce() -> String) -> Option<String> {
+        if self.selection != KeySelection::Sticky {
+            return None;
+        }
+        let session = self
+            .affinity_header
+            .as_ref()
+            .and_then(|name| headers.get(name))
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| !v.is_empty());
+        Some(match session {
+            Some(session) => format!("session:{}", session),
+            None => client(),
+        })
+    }
+
+    /// 单个请求最多尝试的 key 数，不超过 (分组里) 启用中的 key 数
+    pub fn max_attempts(&self, group: Option<&str>) -> usize {
+        let enabled = self.slots().iter().filter(|s| s.usable() && self.in_group(s, group)).count();
+        self.failover.max_attempts.clamp(1, enabled.max(1))
+    }
+
+    /// 启用中 (没有停用也没有被隔离) 的 key 数
+    pub fn enabled(&self) -> usize {
+        self.slots().iter().filter(|s| s.usable()).count()
+    }
+
+    /// 上游判定 key 失效 (reason 为 API_KEY_INVALID 等)；连续次数达到阈值时隔离，
+    /// 刚被隔离时返回 true
+    pub(crate) fn record_rejection(&self, index: usize, reason: &str) -> bool {
+        let Some(slot) = self.slot(index).filter(|_| self.quarantine_after > 0) else {
+            return false;
+        };
+        let strikes = slot.strikes.fetch_add(1, Ordering::Relaxed) + 1;
+        if strikes < self.quarantine_after {
+            return false;
+        }
+        let mut quarantined = slot.quarantined.lock().unwrap_or_else(|e| e.into_inner());
+        if quarantined.is_some() {
+            return false;
+        }
+        *quarantined = Some(reason.to_string());
+        true
+    }
+
+    /// 上游正常处理了这个 key 的请求 (包括 429 等与 key 是否有效无关的错误)，重新计数
+    pub(crate) fn record_accepted(&self, index: usize) {
+        if let Some(slot) = self.slot(index).filter(|_| self.quarantine_after > 0) {
+            slot.strikes.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// 解除隔离，没有这个 key 时返回 false
+    pub fn release_quarantine(&self, name: &str) -> bool {
+        match self.find(name) {
+            Some(slot) => {
+                slot.strikes.store(0, Ordering::Relaxed);
+                *slot.quarantined.lock().unwrap_or_else(|e| e.into_inner()) = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 按名字停用 / 启用 key，没有这个 key 时返回 false
+    pub fn set_disabled(&self, name: &str, disabled: bool) -> bool {
+        match self.find(name) {
+            Some(slot) => {
+                slot.disabled.store(disabled, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 池里的 key 数
+    pub fn len(&self) -> usize {
+        self.slots().iter().filter(|s| s.live()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.slots().iter().filter(|s| s.live()).map(|s| s.entry.name.clone()).collect()
+    }
+
+    /// 序号为 index 的 key 的名字
+    pub(crate) fn name(&self, index: usize) -> String {
+        self.slot(index).map(|s| s.entry.name.clone()).unwrap_or_default()
+    }
+
+    /// 轮询取下一个 key；池里没有可用的 key 时也返回一个
+    pub fn next(&self) -> Arc<KeyEntry> {
+        match self.pick(&[], None) {
+            Some((_, entry)) => entry,
+            None => {
+                let slots = self.slots();
+                let slot = slots.iter().find(|s| s.live()).unwrap_or(&slots[0]);
+                slot.entry.clone()
+            }
+        }
+    }
+
+    /// 取下一个不在冷却中、也没在本次请求里试过的 key (跳过停用的)：给了 affinity 时按哈希顺序，否则轮询；
+    /// 都在冷却时取最早恢复的，全部试过或都已停用时返回 None
+    pub fn pick(&self, tried: &[usize], affinity: Option<&str>) -> Option<(usize, Arc<KeyEntry>)> {
+        self.select(tried, affinity, None, false)
+    }
+
+    /// 和 pick 一样，但只在 group 分组里选 (None 表示整个池)，设置了并发上限时跳过名额已满的 key，
+    /// 并占用选中 key 的一个名额 (用 release 归还)；没试过的 key 全满时返回 None
+    pub(crate) fn reserve(
+        &self,
+        tried: &[usize],
+        affinity: Option<&str>,
+        group: Option<&str>,
+    ) -> Option<(usize, Arc<KeyEntry>)> {
+        self.select(tried, affinity, group, self.max_concurrency.is_some())
+    }
+
+    pub(crate) fn release(&self, index: usize) {
+        if let Some(slot) = self.slot(index) {
+            let _ = slot.active.fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1));
+        }
+    }
+
+    fn try_acquire(&self, slot: &Slot) -> bool {
+        let max = self.max_concurrency.unwrap_or(usize::MAX);
+        slot.active
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| (n < max).then_some(n + 1))
+            .is_ok()
+    }
+
+    fn full(&self, slot: &Slot) -> bool {
+        self.max_concurrency.is_some_and(|max| slot.active.load(Ordering::Acquire) >= max)
+    }
+
+    fn select(
+        &self,
+        tried: &[usize],
+        affinity: Option<&str>,
+        group: Option<&str>,
+        reserve: bool,
+    ) -> Option<(usize, Arc<KeyEntry>)> {
+        let slots = self.slots();
+        let order: Vec<usize> = match affinity {
+            Some(affinity) => {
+                let mut order: Vec<(u64, usize)> = slots
+                    .iter()
+                    .enumerate()
+                    .map(|(i, slot)| (rendezvous_score(affinity, &slot.entry.name), i))
+                    .collect();
+                order.sort_unstable_by(|a, b| b.cmp(a));
+                order.into_iter().map(|(_, i)| i).collect()
+            }
+            None => {
+                let start = self.next.fetch_add(1, Ordering::Relaxed);
+                (0..slots.len()).map(|offset| (start + offset) % slots.len()).collect()
+            }
+        };
+        let now = unix_ms();
+        let candidates = order
+            .into_iter()
+            .filter(|i| !tried.contains(&slots[*i].id) && slots[*i].usable() && self.in_group(&slots[*i], group));
+        let mut cooling: Vec<(u64, usize)> = Vec::new();
+        for i in candidates {
+            let until = slots[i].cooldown_until.load(Ordering::Relaxed);
+            if until > now {
+                cooling.push((until, i));
+            } else if !reserve || self.try_acquire(&slots[i]) {
+                return Some((slots[i].id, slots[i].entry.clone()));
+            }
+        }
+        // 都在冷却：按恢复时间先后，取第一个还有名额的
+        cooling.sort_unstable();
+        cooling
+            .into_iter()
+            .find(|(_, i)| !reserve || self.try_acquire(&slots[*i]))
+            .map(|(_, i)| (slots[i].id, slots[i].entry.clone()))
+    }
+
+    /// 上游返回 429 / 403 时让 key 冷却，返回冷却时长；其他状态码返回 None
+    pub fn cool_down(&self, index: usize, status: StatusCode, headers: &HeaderMap) -> Option<Duration> {
+        let duration = match status {
+            StatusCode::TOO_MANY_REQUESTS => headers
+                .get(header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(self.failover.rate_limited_cooldown),
+            StatusCode::FORBIDDEN => self.failover.forbidden_cooldown,
+            _ => return None,
+        };
+        let until = unix_ms() + duration.as_millis() as u64;
+        if let Some(slot) = self.slot(index) {
+            slot.cooldown_until.fetch_max(until, Ordering::Relaxed);
+        }
+        Some(duration)
+    }
+
+    /// 启用中、不在冷却而且还有名额的 key 数
+    pub(crate) fn available(&self) -> usize {
+        let now = unix_ms();
+        self.slots()
+            .iter()
+            .filter(|s| s.usable() && s.cooldown_until.load(Ordering::Relaxed) <= now)
+            .filter(|s| !self.full(s))
+            .count()
+    }
+
+    /// 最早恢复的 key 还要冷却多久；启用中的 key 都没在冷却时返回 None
+    pub(crate) fn next_ready(&self) -> Option<Duration> {
+        let now = unix_ms();
+        self.slots()
+            .iter()
+            .filter(|s| s.usable())
+            .map(|s| s.cooldown_until.load(Ordering::Relaxed))
+            .filter(|until| *until > now)
+            .min()
+            .map(|until| Duration::from_millis(until - now))
+    }
+
+    /// 合并其他实例记录的冷却时间，只会延长不会缩短
+    fn merge_cooldown(&self, name: &str, until: u64) {
+        if let Some(slot) = self.find(name) {
+            slot.cooldown_until.fetch_max(until, Ordering::Relaxed);
+        }
+    }
+
+    pub fn status(&self) -> Vec<KeyStatus> {
+        let now = unix_ms();
+        self.slots()
+            .iter()
+            .filter(|s| s.live())
+            .map(|slot| KeyStatus {
+                name: slot.entry.name.clone(),
+                cooldown_secs: slot.cooldown_until.load(Ordering::Relaxed).saturating_sub(now).div_ceil(1000),
+                disabled: slot.disabled.load(Ordering::Relaxed),
+                quarantined: slot.quarantine_reason(),
+                active: slot.active.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    /// 换成新的 key 列表：名字和值都没变的 key 保留冷却、停用、隔离和并发状态，
+    /// 新出现的追加进来，不在列表里的不再被选中 (在途请求照常完成，之后的重载把它清掉)；返回新增和去掉的个数
+    pub fn replace(&self, keys: Vec<KeyEntry>) -> Result<(usize, usize), String> {
+        validate(&keys)?;
+        let mut slots = self.slots.write().unwrap_or_else(|e| e.into_inner());
+        let same = |slot: &Slot, entry: &KeyEntry| slot.entry.name == entry.name && slot.entry.key == entry.key;
+        let removed = slots
+            .iter()
+            .filter(|slot| slot.live() && !keys.iter().any(|entry| same(slot, entry)))
+            .inspect(|slot| slot.removed.store(true, Ordering::Relaxed))
+            .count();
+        let mut added = 0;
+        for entry in keys {
+            match slots.iter().find(|slot| same(slot, &entry)) {
+                Some(slot) => {
+                    // 之前去掉又加回来的 key 接着用原来的状态 (没有重复的 live 同名 key，parse 已经保证)
+                    if !slot.live() {
+                        slot.removed.store(false, Ordering::Relaxed);
+                        added += 1;
+                    }
+                }
+                None => {
+                    slots.push(Slot::new(self.next_id.fetch_add(1, Ordering::Relaxed), entry));
+                    added += 1;
+                }
+            }
+        }
+        // 去掉的 key 没有在途请求了就不再占位置，slots 不会随着重载次数一直变长
+        slots.retain(|slot| slot.live() || slot.active.load(Ordering::Acquire) > 0);
+        Ok((added, removed))
+    }
+
+    /// 去掉客户端带来的 key 并注入池里的 key，返回改写后的 path + query
+    pub fn inject(&self, entry: &KeyEntry, headers: &mut HeaderMap, path_and_query: &str) -> String {
+        headers.remove("x-goog-api-key");
+        let path = sanitize_path(path_and_query);
+        match self.injection {
+            KeyInjection::Header => {
+                // new() 里已经校验过
+                if let Ok(value) = HeaderValue::from_str(&entry.key) {
+                    headers.insert("x-goog-api-key", value);
+                }
+                path
+            }
+            KeyInjection::Query => {
+                let sep = if path.contains('?') { '&' } else { '?' };
+                format!("{}{}key={}", path, sep, entry.key)
+            }
+        }
+    }
+
+    /// 序号为 index 的 key；热重载去掉、还没被清掉的 key 也返回 (开在它上面的上传会话只能继续用它)
+    pub(crate) fn entry(&self, index: usize) -> Option<Arc<KeyEntry>> {
+        self.slot(index).map(|s| s.entry.clone())
+    }
+
+    /// 不管注入方式，放进 `x-goog-api-key` 请求头 (gRPC 元数据)
+    pub(crate) fn inject_header(&self, entry: &KeyEntry, headers: &mut HeaderMap) {
+        if let Ok(value) = HeaderValue::from_str(&entry.key) {
+            headers.insert("x-goog-api-key", value);
+        }
+    }
+}
+
+/// 解析 `--key-group NAME=KEY1,KEY2`
+pub fn parse_group(spec: &str) -> Result<(String, Vec<String>), String> {
+    let (name, keys) = spec
+        .split_once('=')
+        .ok_or_else(|| format!("key group '{}' must be NAME=KEY1,KEY2", spec))?;
+    let keys: Vec<String> = keys.split(',').map(|k| k.trim().to_string()).filter(|k| !k.is_empty()).collect();
+    if name.trim().is_empty() || keys.is_empty() {
+        return Err(format!("key group '{}' needs a name and at least one key", spec));
+    }
+    Ok((name.trim().to_string(), keys))
+}
+
+fn validate(keys: &[KeyEntry]) -> Result<(), String> {
+    if keys.is_empty() {
+        return Err("key pool needs at least one key".to_string());
+    }
+    for entry in keys {
+        HeaderValue::from_str(&entry.key).map_err(|_| format!("key '{}' is not a valid header value", entry.name))?;
+    }
+    Ok(())
+}
+
+// --- 失效 key 的隔离 ---
+
+// 上游 ErrorInfo 里只针对 key 本身的原因。笼统的 PERMISSION_DENIED 不算：客户端访问别的项目的
+// 文件、cachedContent、tunedModel 也会得到它，计入的话任何客户端都能把整个 key 池隔离掉
+const KEY_REASONS: &[&str] = &[
+    "API_KEY_INVALID",
+    "API_KEY_EXPIRED",
+    "API_KEY_SERVICE_BLOCKED",
+    "API_KEY_IP_ADDRESS_BLOCKED",
+    "CONSUMER_SUSPENDED",
+    "SERVICE_DISABLED",
+];
+
+/// 上游错误响应里说明 key 本身失效的原因
+fn rejection_reason(body: &[u8]) -> Option<&'static str> {
+    let body: serde_json::Value = serde_json::from_slice(body).ok()?;
+    let error = body.get("error")?;
+    let detail = error.get("details").and_then(|d| d.as_array()).and_then(|details| {
+        details
+            .iter()
+            .filter_map(|d| d.get("reason").and_then(|r| r.as_str()))
+            .find_map(|reason| KEY_REASONS.iter().copied().find(|known| *known == reason))
+    });
+    if detail.is_some() {
+        return detail;
+    }
+    let message = error.get("message").and_then(|m| m.as_str()).unwrap_or("");
+    if message.contains("API key not valid") {
+        return Some("API_KEY_INVALID");
+    }
+    message.contains("API key expired").then_some("API_KEY_EXPIRED")
+}
+
+/// 400 / 403 响应先读出错误体判断 key 是否失效，再拼回一个同样的响应交给后面的流程；其他响应原样返回
+pub(crate) async fn inspect_rejection(
+    response: reqwest::Response,
+) -> Result<(reqwest::Response, Option<&'static str>), reqwest::Error> {
+    // 错误体都很小，声明得特别大的不是我们要找的
+    let small = response.content_length().is_none_or(|len| len <= 64 * 1024);
+    if !matches!(response.status(), StatusCode::BAD_REQUEST | StatusCode::FORBIDDEN) || !small {
+        return Ok((response, None));
+    }
+    let status = response.status();
+    let version = response.version();
+    let headers = response.headers().clone();
+    let body = response.bytes().await?;
+    let reason = rejection_reason(&body);
+    let mut rebuilt = axum::http::Response::new(reqwest::Body::from(body));
+    *rebuilt.status_mut() = status;
+    *rebuilt.version_mut() = version;
+    *rebuilt.headers_mut() = headers;
+    Ok((reqwest::Response::from(rebuilt), reason))
+}
+
+/// key 被隔离时通知的 webhook (POST JSON)
+pub struct QuarantineWebhook {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl QuarantineWebhook {
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+/// key 刚被隔离：记日志、指标，配置了 webhook 时发通知；立即返回
+pub(crate) fn alert_quarantine(state: &Arc<AppState>, index: usize, reason: &str) {
+    let Some(pool) = &state.key_pool else {
+        return;
+    };
+    let name = pool.name(index);
+    error!(
+        "🔑 Key {} quarantined after {} consecutive {} responses; release it via PUT /admin/keys/{}",
+        name, pool.quarantine_after, reason, name
+    );
+    state.metrics.inc("aizasy_key_quarantined_total", &[("key", &name), ("reason", reason)]);
+    let Some(webhook) = &state.quarantine_webhook else {
+        return;
+    };
+    let request = webhook.client.post(&webhook.url).json(&serde_json::json!({
+        "event": "key_quarantined",
+        "key": name,
+        "reason": reason,
+        "strikes": pool.quarantine_after,
+    }));
+    let url = webhook.url.clone();
+    tokio::spawn(async move {
+        if let Err(e) = request.send().await.and_then(|r| r.error_for_status()) {
+            warn!("🔑 Quarantine webhook {} failed: {}", url, e);
+        }
+    });
+}
+
+/// 占用中的并发名额，drop 时归还并唤醒排队的请求；要带进响应流，所以持有 Arc<AppState>
+pub(crate) struct KeySlot {
+    state: Arc<AppState>,
+    index: usize,
+}
+
+impl KeySlot {
+    pub(crate) fn new(state: Arc<AppState>, index: usize) -> Self {
+        Self { state, index }
+    }
+}
+
+impl Drop for KeySlot {
+    fn drop(&mut self) {
+        if let Some(pool) = &self.state.key_pool {
+            pool.release(self.index);
+        }
+        if let Some(queue) = &self.state.request_queue {
+            queue.wake();
+        }
+    }
+}
+
+/// 用 KeyPool::reserve 选一个 key；设置了并发上限时一并返回占用的名额
+pub(crate) fn reserve(
+    state: &Arc<AppState>,
+    pool: &KeyPool,
+    tried: &[usize],
+    affinity: Option<&str>,
+    group: Option<&str>,
+) -> Option<(usize, Arc<KeyEntry>, Option<KeySlot>)> {
+    let (index, entry) = pool.reserve(tried, affinity, group)?;
+    let slot = pool.max_concurrency().map(|_| KeySlot::new(state.clone(), index));
+    Some((index, entry, slot))
+}
+
+// --- 多实例共享冷却状态 ---
+// 存储后端不是 memory 时 (多个副本共用 Redis / 同一个 sqlite 文件)，key 进入冷却后把截止时间写到
+// keypool:cooldown:<key 名>，TTL 就是冷却时长，同时给 keypool:cooldown-version 加一。后台每秒只读这个版本号，
+// 变了才按池里的 key 名逐个读取、合并到本地，不扫描整个存储。这样一个副本被上游 429 之后，
+// 其他副本最多晚一秒也避开这个 key，而不是各自再撞一次。选 key 仍然只看本地状态，不给请求路径加一次存储往返。
+
+const COOLDOWN_PREFIX: &str = "keypool:cooldown:";
+const COOLDOWN_VERSION: &str = "keypool:cooldown-version";
+const COOLDOWN_SYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+pub(crate) fn shared(state: &AppState) -> bool {
+    state.storage.name() != "memory"
+}
+
+/// 把刚进入冷却的 key (池里的序号) 写到共享存储；立即返回
+pub(crate) fn publish_cooldown(state: &Arc<AppState>, index: usize, duration: Duration) {
+    let Some(pool) = state.key_pool.as_ref().filter(|_| shared(state)) else {
+        return;
+    };
+    if duration.is_zero() {
+        return;
+    }
+    let storage = state.storage.clone();
+    let key = format!("{}{}", COOLDOWN_PREFIX, pool.name(index));
+    let until = unix_ms() + duration.as_millis() as u64;
+    tokio::spawn(async move {
+        let result = async {
+            storage.set(&key, until.to_string().into_bytes(), Some(duration)).await?;
+            storage.incr(COOLDOWN_VERSION, 1, None).await
+        };
+        if let Err(e) = result.await {
+            warn!("🔑 Failed to share cooldown of {}: {}", key, e);
+        }
+    });
+}
+
+/// 后台定期合并其他实例写入的冷却状态；网关被释放 (热重载换掉) 后自动退出
+pub(crate) fn spawn_cooldown_sync(state: Weak<AppState>) {
+    tokio::spawn(async move {
+        let mut timer = tokio::time::interval(COOLDOWN_SYNC_INTERVAL);
+        let mut seen = None;
+        loop {
+            timer.tick().await;
+            let Some(state) = state.upgrade() else {
+                return;
+            };
+            let Some(pool) = &state.key_pool else {
+                return;
+            };
+            let result = async {
+                let version = state.storage.get(COOLDOWN_VERSION).await?;
+                if version.is_none() || version == seen {
+                    return Ok(());
+                }
+                for name in pool.names() {
+                    if let Some(value) = state.storage.get(&format!("{}{}", COOLDOWN_PREFIX, name)).await? {
+                        pool.merge_cooldown(&name, String::from_utf8_lossy(&value).parse::<u64>().unwrap_or(0));
+                    }
+                }
+                // 全部读完才记下版本号，读到一半失败下次重来
+                seen = version;
+                Ok::<_, String>(())
+            };
+            if let Err(e) = result.await {
+                debug!("🔑 Failed to read shared key cooldowns: {}", e);
+            }
+        }
+    });
+}
+
+// --- key 池热重载 ---
+// key 列表可以放在单独的文件或 HTTPS 地址 (secret manager 的读取接口) 上，后台定期重新读取，
+// 有变化时在原地换掉池里的 key：名字和值都没变的 key 保留冷却、停用、隔离和并发名额，只有新增和删除的 key 受影响，
+// 不用重启也不用 SIGHUP 重载整个配置。地址返回的内容和 --keys-file 的格式一样 (每行 KEY 或 NAME=KEY)。
+// 读取失败、解析出错或者读到空列表时保留当前的 key 并记警告，不会因为一次抖动清空 key 池。
+
+pub type DecryptFn = Arc<dyn Fn(&str) -> Result<String, String> + Send + Sync>;
+
+#[derive(Clone)]
+pub struct KeySource {
+    /// key 文件
+    pub file: Option<String>,
+    /// 返回 key 列表的地址
+    pub url: Option<String>,
+    /// 访问 url 时带的 Authorization 请求头
+    pub url_authorization: Option<String>,
+    /// 访问 url 用的客户端 (带上 CA 证书、代理等设置)；网关启动后换成它的上游客户端，不设置时用默认客户端
+    pub client: Option<reqwest::Client>,
+    /// 直接写在命令行上的 key (--keys)，不会变，和文件、地址的内容拼在一起
+    pub inline: Vec<String>,
+    /// 解密 ENC[...] 形式的 key
+    pub decrypt: Option<DecryptFn>,
+    /// 重新读取的间隔，为零时只在启动和管理 API 要求时读取
+    pub interval: Duration,
+}
+
+impl Default for KeySource {
+    fn default() -> Self {
+        Self {
+            file: None,
+            url: None,
+            url_authorization: None,
+            client: None,
+            inline: Vec::new(),
+            decrypt: None,
+            interval: Duration::from_secs(30),
+        }
+    }
+}
+
+impl KeySource {
+    /// 有文件或地址，值得在后台重新读取
+    pub fn watchable(&self) -> bool {
+        self.file.is_some() || self.url.is_some()
+    }
+
+    pub fn describe(&self) -> String {
+        self.file.iter().chain(self.url.iter()).cloned().collect::<Vec<_>>().join(" + ")
+    }
+
+    /// 读一遍完整的 key 列表，没起名的 key 按顺序编号
+    pub async fn load(&self) -> Result<Vec<KeyEntry>, String> {
+        let mut source = String::new();
+        if let Some(path) = &self.file {
+            let content = tokio::fs::read_to_string(path).await.map_err(|e| format!("{}: {}", path, e))?;
+            source.push_str(&content);
+            source.push('\n');
+        }
+        if let Some(url) = &self.url {
+            let client = self.client.clone().unwrap_or_default();
+            let mut request = client.get(url).timeout(Duration::from_secs(10));
+            if let Some(auth) = &self.url_authorization {
+                request = request.header(header::AUTHORIZATION, auth);
+            }
+            let response = request
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| format!("{}: {}", url, e))?;
+            source.push_str(&response.text().await.map_err(|e| format!("{}: {}", url, e))?);
+            source.push('\n');
+        }
+        source.push_str(&self.inline.join("\n"));
+        let mut entries = crate::keys::parse(&source)?;
+        if let Some(decrypt) = &self.decrypt {
+            for entry in &mut entries {
+                entry.key = decrypt(&entry.key).map_err(|e| format!("key '{}': {}", entry.name, e))?;
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// 重新读取 key 列表并换进池里，返回新增和去掉的个数
+pub(crate) async fn reload(state: &AppState) -> Result<(usize, usize), String> {
+    let (Some(pool), Some(source)) = (&state.key_pool, &state.key_source) else {
+        return Err("key pool is not loaded from a file or URL".to_string());
+    };
+    let (added, removed) = pool.replace(source.load().await?)?;
+    state.metrics.inc("aizasy_key_pool_reloads_total", &[]);
+    if added > 0 || removed > 0 {
+        info!("🔑 Key pool reloaded: +{} -{} ({} keys)", added, removed, pool.len());
+    }
+    Ok((added, removed))
+}
+
+/// 后台按间隔重新读取 key 列表；网关被释放 (热重载换掉) 后自动退出
+pub(crate) fn spawn_reload(state: Weak<AppState>, interval: Duration) {
+    tokio::spawn(async move {
+        let mut timer = tokio::time::interval(interval);
+        timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        // 第一次 tick 立即返回，启动时已经读过了
+        timer.tick().await;
+        loop {
+            timer.tick().await;
+            let Some(state) = state.upgrade() else {
+                return;
+            };
+            if let Err(e) = reload(&state).await {
+                warn!("🔑 Key pool reload failed, keeping the current keys: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(names: &[&str]) -> KeyPool {
+        KeyPool::new(entries(names), KeyInjection::Header).unwrap()
+    }
+
+    fn entries(names: &[&str]) -> Vec<KeyEntry> {
+        names
+            .iter()
+            .map(|name| KeyEntry {
+                name: name.to_string(),
+                key: format!("AIza-{}", name),
+            })
+            .collect()
+    }
+
+    fn picked(pool: &KeyPool, tried: &[usize], affinity: Option<&str>) -> Option<String> {
+        pool.pick(tried, affinity).map(|(_, entry)| entry.name.clone())
+    }
+
+    fn retry_after(secs: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::RETRY_AFTER, HeaderValue::from_str(secs).unwrap());
+        headers
+    }
+
+    #[test]
+    fn round_robin_cycles_through_keys() {
+        let pool = pool(&["a", "b", "c"]);
+        let order: Vec<String> = (0..4).map(|_| picked(&pool, &[], None).unwrap()).collect();
+        assert_eq!(order, ["a", "b", "c", "a"]);
+        assert_eq!(pool.next().name, "b");
+    }
+
+    #[test]
+    fn tried_keys_are_skipped() {
+        let pool = pool(&["a", "b", "c"]);
+        assert_eq!(picked(&pool, &[0, 1], None).as_deref(), Some("c"));
+        assert_eq!(picked(&pool, &[0, 1, 2], None), None);
+    }
+
+    #[test]
+    fn rate_limited_and_forbidden_keys_fail_over() {
+        let pool = pool(&["a", "b", "c"]).with_failover(FailoverConfig {
+            max_attempts: 3,
+            rate_limited_cooldown: Duration::from_secs(60),
+            forbidden_cooldown: Duration::from_secs(600),
+        });
+        // 上游给了 Retry-After 时按它冷却
+        assert_eq!(pool.cool_down(0, StatusCode::TOO_MANY_REQUESTS, &retry_after("30")), Some(Duration::from_secs(30)));
+        assert_eq!(pool.cool_down(1, StatusCode::FORBIDDEN, &HeaderMap::new()), Some(Duration::from_secs(600)));
+        assert_eq!(pool.cool_down(2, StatusCode::INTERNAL_SERVER_ERROR, &HeaderMap::new()), None);
+
+        for _ in 0..3 {
+            assert_eq!(picked(&pool, &[], None).as_deref(), Some("c"));
+        }
+        assert_eq!(pool.available(), 1);
+        let status = pool.status();
+        assert_eq!(status[0].cooldown_secs, 30);
+        assert_eq!(status[1].cooldown_secs, 600);
+        assert_eq!(status[2].cooldown_secs, 0);
+    }
+
+    #[test]
+    fn rate_limit_without_retry_after_uses_default_cooldown() {
+        let pool = pool(&["a"]);
+        let cooldown = pool.cool_down(0, StatusCode::TOO_MANY_REQUESTS, &retry_after("soon"));
+        assert_eq!(cooldown, Some(FailoverConfig::default().rate_limited_cooldown));
+    }
+
+    #[test]
+    fn cooldown_expires() {
+        let pool = pool(&["a", "b"]);
+        pool.cool_down(0, StatusCode::TOO_MANY_REQUESTS, &retry_after("60"));
+        assert_eq!(pool.available(), 1);
+        assert!(pool.next_ready().is_some());
+
+        pool.slot(0).unwrap().cooldown_until.store(unix_ms() - 1, Ordering::Relaxed);
+        assert_eq!(pool.available(), 2);
+        assert_eq!(pool.next_ready(), None);
+        assert_eq!(picked(&pool, &[1], None).as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn all_cooling_falls_back_to_soonest_recovery() {
+        let pool = pool(&["a", "b", "c"]);
+        pool.cool_down(0, StatusCode::TOO_MANY_REQUESTS, &retry_after("60"));
+        pool.cool_down(1, StatusCode::TOO_MANY_REQUESTS, &retry_after("10"));
+        pool.cool_down(2, StatusCode::TOO_MANY_REQUESTS, &retry_after("30"));
+
+        assert_eq!(picked(&pool, &[], None).as_deref(), Some("b"));
+        assert_eq!(picked(&pool, &[1], None).as_deref(), Some("c"));
+        assert_eq!(pool.available(), 0);
+        let ready = pool.next_ready().unwrap();
+        assert!(ready > Duration::from_secs(9) && ready <= Duration::from_secs(10));
+    }
+
+    #[test]
+    fn disabled_keys_are_skipped() {
+        let pool = pool(&["a", "b"]);
+        assert!(pool.set_disabled("a", true));
+        assert!(!pool.set_disabled("missing", true));
+        assert_eq!(pool.enabled(), 1);
+        assert_eq!(picked(&pool, &[], None).as_deref(), Some("b"));
+        assert_eq!(picked(&pool, &[], None).as_deref(), Some("b"));
+
+        pool.set_disabled("b", true);
+        assert_eq!(picked(&pool, &[], None), None);
+        // 没有可用的 key 时 next 仍然返回一个
+        assert_eq!(pool.next().name, "a");
+        assert_eq!(pool.max_attempts(None), 1);
+    }
+
+    #[test]
+    fn max_attempts_is_capped_by_enabled_keys() {
+        let pool = pool(&["a", "b"]).with_failover(FailoverConfig {
+            max_attempts: 5,
+            ..FailoverConfig::default()
+        });
+        assert_eq!(pool.max_attempts(None), 2);
+    }
+
+    #[test]
+    fn affinity_comes_from_session_header_or_client() {
+        let round_robin = pool(&["a"]);
+        assert_eq!(round_robin.affinity(&HeaderMap::new(), || "client".to_string()), None);
+
+        let sticky = pool(&["a"]).with_selection(KeySelection::Sticky, Some(HeaderName::from_static("x-session")));
+        let mut headers = HeaderMap::new();
+        assert_eq!(sticky.affinity(&headers, || "client".to_string()).as_deref(), Some("client"));
+        headers.insert("x-session", HeaderValue::from_static("s1"));
+        assert_eq!(sticky.affinity(&headers, || "client".to_string()).as_deref(), Some("session:s1"));
+    }
+
+    #[test]
+    fn affinity_is_stable_and_spreads_clients() {
+        let pool = pool(&["a", "b", "c", "d"]);
+        let assigned: Vec<String> = (0..64).map(|i| picked(&pool, &[], Some(&format!("client-{}", i))).unwrap()).collect();
+        for (i, name) in assigned.iter().enumerate() {
+            assert_eq!(picked(&pool, &[], Some(&format!("client-{}", i))).as_ref(), Some(name));
+        }
+        let mut distinct = assigned.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert!(distinct.len() > 1);
+
+        // 去掉一个 key 只影响原来落在它上面的客户端
+        pool.replace(entries(&["a", "b", "c"])).unwrap();
+        for (i, name) in assigned.iter().enumerate().filter(|(_, name)| *name != "d") {
+            assert_eq!(picked(&pool, &[], Some(&format!("client-{}", i))).as_ref(), Some(name));
+        }
+    }
+
+    #[test]
+    fn affinity_falls_back_in_hash_order_while_cooling() {
+        let pool = pool(&["a", "b", "c"]);
+        let (first, _) = pool.pick(&[], Some("client")).unwrap();
+        let (second, _) = pool.pick(&[first], Some("client")).unwrap();
+        pool.cool_down(first, StatusCode::TOO_MANY_REQUESTS, &retry_after("60"));
+        assert_eq!(pool.pick(&[], Some("client")).unwrap().0, second);
+
+        pool.slot(first).unwrap().cooldown_until.store(0, Ordering::Relaxed);
+        assert_eq!(pool.pick(&[], Some("client")).unwrap().0, first);
+    }
+
+    #[test]
+    fn rendezvous_score_is_deterministic() {
+        assert_eq!(rendezvous_score("client", "a"), rendezvous_score("client", "a"));
+        assert_ne!(rendezvous_score("client", "a"), rendezvous_score("client", "b"));
+        // 分隔符避免 "ab"+"c" 和 "a"+"bc" 撞在一起
+        assert_ne!(rendezvous_score("ab", "c"), rendezvous_score("a", "bc"));
+    }
+
+    #[test]
+    fn concurrency_cap_reserves_and_releases() {
+        let pool = pool(&["a", "b"]).with_max_concurrency(1);
+        let (first, _) = pool.reserve(&[], None, None).unwrap();
+        let (second, _) = pool.reserve(&[], None, None).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(pool.reserve(&[], None, None), None);
+        assert_eq!(pool.available(), 0);
+        assert_eq!(pool.status().iter().map(|s| s.active).sum::<usize>(), 2);
+        // pick 不占名额，也不看名额
+        assert!(pool.pick(&[], None).is_some());
+
+        pool.release(first);
+        assert_eq!(pool.available(), 1);
+        assert_eq!(pool.reserve(&[], None, None).unwrap().0, first);
+        // 多余的 release 不会把计数减成负数
+        pool.release(second);
+        pool.release(second);
+        assert_eq!(pool.status()[second].active, 0);
+    }
+
+    #[test]
+    fn zero_concurrency_means_unlimited() {
+        let pool = pool(&["a"]).with_max_concurrency(0);
+        assert_eq!(pool.max_concurrency(), None);
+        assert!(pool.reserve(&[], None, None).is_some());
+        assert!(pool.reserve(&[], None, None).is_some());
+    }
+
+    #[test]
+    fn groups_limit_selection() {
+        let groups = HashMap::from([("team".to_string(), vec!["b".to_string(), "c".to_string()])]);
+        let pool = pool(&["a", "b", "c"]).with_groups(groups).unwrap();
+        assert!(pool.has_group("team"));
+        for _ in 0..4 {
+            let (_, entry) = pool.reserve(&[], None, Some("team")).unwrap();
+            assert_ne!(entry.name, "a");
+        }
+        assert_eq!(pool.max_attempts(Some("team")), 2);
+        assert_eq!(pool.reserve(&[], None, Some("other")), None);
+
+        let unknown = HashMap::from([("team".to_string(), vec!["z".to_string()])]);
+        assert!(self::pool(&["a"]).with_groups(unknown).is_err());
+    }
+
+    #[test]
+    fn quarantine_after_consecutive_rejections() {
+        let pool = pool(&["a", "b"]).with_quarantine(2);
+        assert!(!pool.record_rejection(0, "API_KEY_INVALID"));
+        // 中间有一次正常响应就重新计数
+        pool.record_accepted(0);
+        assert!(!pool.record_rejection(0, "API_KEY_INVALID"));
+        assert!(pool.record_rejection(0, "API_KEY_INVALID"));
+        // 已经隔离的不会重复报告
+        assert!(!pool.record_rejection(0, "API_KEY_INVALID"));
+
+        assert_eq!(pool.status()[0].quarantined.as_deref(), Some("API_KEY_INVALID"));
+        assert_eq!(pool.enabled(), 1);
+        assert_eq!(picked(&pool, &[], None).as_deref(), Some("b"));
+        assert_eq!(picked(&pool, &[], None).as_deref(), Some("b"));
+
+        assert!(pool.release_quarantine("a"));
+        assert!(!pool.release_quarantine("missing"));
+        assert_eq!(pool.status()[0].quarantined, None);
+        assert_eq!(pool.enabled(), 2);
+        // 解除后重新计数
+        assert!(!pool.record_rejection(0, "API_KEY_INVALID"));
+    }
+
+    #[test]
+    fn quarantine_is_off_by_default() {
+        let pool = pool(&["a"]);
+        for _ in 0..10 {
+            assert!(!pool.record_rejection(0, "API_KEY_INVALID"));
+        }
+        assert_eq!(pool.enabled(), 1);
+    }
+
+    #[test]
+    fn replace_keeps_state_of_unchanged_keys() {
+        let pool = pool(&["a", "b", "c"]).with_quarantine(1);
+        pool.cool_down(0, StatusCode::TOO_MANY_REQUESTS, &retry_after("60"));
+        pool.set_disabled("b", true);
+        pool.record_rejection(2, "API_KEY_EXPIRED");
+
+        let mut keys = entries(&["a", "b", "c", "d"]);
+        // 同名但换了值的 key 算新 key
+        keys[2].key = "AIza-rotated".to_string();
+        assert_eq!(pool.replace(keys).unwrap(), (2, 1));
+        assert_eq!(pool.names(), ["a", "b", "c", "d"]);
+
+        let status = pool.status();
+        assert!(status[0].cooldown_secs > 0);
+        assert!(status[1].disabled);
+        assert_eq!(status[2].quarantined, None);
+        // 新 key 的序号接着往后排，不复用
+        assert!(pool.entry(2).is_none());
+        assert_eq!(pool.name(3), "c");
+        assert_eq!(pool.pick(&[0, 3], None).unwrap().0, 4);
+    }
+
+    #[test]
+    fn removed_key_with_requests_in_flight_is_kept_until_released() {
+        let pool = pool(&["a", "b"]).with_max_concurrency(1);
+        let (index, _) = pool.reserve(&[1], None, None).unwrap();
+        assert_eq!(index, 0);
+
+        assert_eq!(pool.replace(entries(&["b"])).unwrap(), (0, 1));
+        assert_eq!(pool.len(), 1);
+        // 在途请求还能拿到它的 key，但不会再被选中
+        assert!(pool.entry(0).is_some());
+        assert_eq!(picked(&pool, &[], None).as_deref(), Some("b"));
+        assert_eq!(picked(&pool, &[1], None), None);
+
+        pool.release(0);
+        pool.replace(entries(&["b"])).unwrap();
+        assert!(pool.entry(0).is_none());
+    }
+
+    #[test]
+    fn replace_rejects_empty_and_invalid_keys() {
+        let pool = pool(&["a"]);
+        assert!(pool.replace(Vec::new()).is_err());
+        let invalid = vec![KeyEntry {
+            name: "bad".to_string(),
+            key: "line\nbreak".to_string(),
+        }];
+        assert!(pool.replace(invalid).is_err());
+        assert_eq!(pool.names(), ["a"]);
+    }
+
+    #[test]
+    fn inject_replaces_client_key() {
+        let header = pool(&["a"]);
+        let entry = header.next();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-goog-api-key", HeaderValue::from_static("client-key"));
+        let path = header.inject(&entry, &mut headers, "/v1beta/models?key=client-key&pageSize=5");
+        assert_eq!(headers["x-goog-api-key"], "AIza-a");
+        assert!(!path.contains("client-key"));
+
+        let query = KeyPool::new(entries(&["a"]), KeyInjection::Query).unwrap();
+        let mut headers = HeaderMap::new();
+        assert_eq!(query.inject(&entry, &mut headers, "/v1beta/models"), "/v1beta/models?key=AIza-a");
+        assert!(headers.get("x-goog-api-key").is_none());
+    }
+
+    #[test]
+    fn rejection_reasons() {
+        let detail = br#"{"error":{"code":400,"message":"bad","details":[{"reason":"API_KEY_INVALID"}]}}"#;
+        assert_eq!(rejection_reason(detail), Some("API_KEY_INVALID"));
+        let message = br#"{"error":{"code":400,"message":"API key expired. Please renew the API key."}}"#;
+        assert_eq!(rejection_reason(message), Some("API_KEY_EXPIRED"));
+        // 笼统的权限错误不算 key 失效
+        let denied = br#"{"error":{"code":403,"details":[{"reason":"PERMISSION_DENIED"}]}}"#;
+        assert_eq!(rejection_reason(denied), None);
+        assert_eq!(rejection_reason(b"not json"), None);
+    }
+
+    #[test]
+    fn parses_key_groups() {
+        assert_eq!(parse_group("team = a, b,").unwrap(), ("team".to_string(), vec!["a".to_string(), "b".to_string()]));
+        assert!(parse_group("team").is_err());
+        assert!(parse_group("=a").is_err());
+        assert!(parse_group("team=").is_err());
+    }
+
+    #[test]
+    fn parses_injection_and_selection() {
+        assert_eq!("query".parse::<KeyInjection>(), Ok(KeyInjection::Query));
+        assert!("cookie".parse::<KeyInjection>().is_err());
+        assert_eq!("sticky".parse::<KeySelection>(), Ok(KeySelection::Sticky));
+        assert!("random".parse::<KeySelection>().is_err());
+    }
+}
@@ -0,0 +1,95 @@
+use std::io::Write;
+use std::time::Duration;
+
+// --- 上游 key 文件 ---
+// 每行一个 Gemini API key，可以写成 NAME=KEY 给 key 起名 (日志、指标里只显示名字)；
+// 空行和 # 开头的行忽略，没起名的 key 按行序命名为 key-1、key-2 ...
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyEntry {
+    pub name: String,
+    pub key: String,
+}
+
+pub fn parse(content: &str) -> Result<Vec<KeyEntry>, String> {
+    let mut entries: Vec<KeyEntry> = Vec::new();
+    for (lineno, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        // 名字只允许字母数字和 -_.，这样 ENC[age:...] 里 base64 的 '=' 不会被当成分隔符
+        let (name, key) = match line.split_once('=') {
+            Some((name, key)) if is_name(name.trim()) => (name.trim().to_string(), key.trim().to_string()),
+            _ => (format!("key-{}", entries.len() + 1), line.to_string()),
+        };
+        if key.is_empty() || line.starts_with('=') {
+            return Err(format!("line {}: expected KEY or NAME=KEY", lineno + 1));
+        }
+        if entries.iter().any(|e| e.name == name) {
+            return Err(format!("line {}: duplicate key name '{}'", lineno + 1, name));
+        }
+        entries.push(KeyEntry { name, key });
+    }
+    Ok(entries)
+}
+
+fn is_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+}
+
+pub fn load(path: &str) -> Result<Vec<KeyEntry>, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    parse(&content).map_err(|e| format!("{}: {}", path, e))
+}
+
+/// 追加一个 key，文件不存在时创建；返回实际使用的名字
+pub fn append(path: &str, name: Option<&str>, key: &str) -> Result<String, String> {
+    let existing = match std::fs::read_to_string(path) {
+        Ok(content) => parse(&content).map_err(|e| format!("{}: {}", path, e))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(format!("{}: {}", path, e)),
+    };
+    let key = key.trim();
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return Err("key must be a non-empty token".to_string());
+    }
+    if existing.iter().any(|e| e.key == key) {
+        return Err("key is already in the file".to_string());
+    }
+    let name = name.map(str::to_string).unwrap_or_else(|| format!("key-{}", existing.len() + 1));
+    if !is_name(&name) || existing.iter().any(|e| e.name == name) {
+        return Err(format!("invalid or duplicate key name '{}'", name));
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("{}: {}", path, e))?;
+    writeln!(file, "{}={}", name, key).map_err(|e| format!("{}: {}", path, e))?;
+    Ok(name)
+}
+
+/// 只保留首尾几位，用于展示
+pub fn mask(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    if chars.len() <= 8 {
+        return "*".repeat(chars.len());
+    }
+    let head: String = chars[..4].iter().collect();
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}…{}", head, tail)
+}
+
+/// 用 key 调一次 models 列表接口，返回上游状态码
+pub async fn test(client: &reqwest::Client, target: &str, key: &str) -> Result<u16, String> {
+    let url = format!("{}/v1beta/models?pageSize=1", target.trim_end_matches('/'));
+    let response = client
+        .get(url)
+        .header("x-goog-api-key", key)
+        .timeout(Duration::from_secs(15))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(response.status().as_u16())
+}
@@ -0,0 +1,251 @@
+use async_trait::async_trait;
+use axum::body::Bytes;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+use crate::plugin::{ChunkAction, GatewayPlugin, Outcome, RequestContext};
+use crate::project::CurrentProject;
+use crate::providers::ProviderRoute;
+use crate::storage::Storage;
+use crate::tenant::CurrentTenant;
+use crate::usage::{self, UpstreamKeyId, UsageScanner};
+
+// --- 计费账本 ---
+// 每个完成的请求追加一条记录 (token 数 × 价格表)，写入存储后端。
+// key 形如 ledger:<unix 毫秒，13 位补零>:<请求编号>，字典序即时间序，按时间范围查询直接 scan。
+
+const KEY_PREFIX: &str = "ledger:";
+
+/// 每百万 token 的价格
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Price {
+    #[serde(default)]
+    pub input: f64,
+    #[serde(default)]
+    pub output: f64,
+}
+
+/// 模型名 -> 价格，按最长前缀匹配 (gemini-1.5-pro 覆盖 gemini-1.5-pro-002)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PriceTable {
+    prices: HashMap<String, Price>,
+}
+
+impl PriceTable {
+    /// 读取 JSON 价格表: {"gemini-1.5-pro": {"input": 1.25, "output": 5.0}, ...}
+    pub fn load(path: &str) -> Result<Self, String> {
+        let data = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        serde_json::from_slice(&data).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn insert(&mut self, model: impl Into<String>, price: Price) {
+        self.prices.insert(model.into(), price);
+    }
+
+    pub fn price(&self, model: &str) -> Option<Price> {
+        self.prices
+            .iter()
+            .filter(|(prefix, _)| model.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, price)| *price)
+    }
+
+    pub fn cost(&self, model: &str, prompt_tokens: u64, output_tokens: u64) -> f64 {
+        match self.price(model) {
+            Some(price) => (prompt_tokens as f64 * price.input + output_tokens as f64 * price.output) / 1_000_000.0,
+            None => 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    /// unix 毫秒
+    pub ts: u64,
+    pub request_id: u64,
+    pub client: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// X-Aizasy-Project 标记的项目
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    /// 使用的上游 key 标识 (不是 key 本身)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    pub model: String,
+    pub status: Option<u16>,
+    pub prompt_tokens: u64,
+    /// candidatesTokenCount (不含思考 token)；早期记录没有这个字段
+    #[serde(default)]
+    pub candidate_tokens: u64,
+    /// 计费用的输出 token，包含思考 token
+    pub output_tokens: u64,
+    pub cost: f64,
+}
+
+fn entry_key(ts: u64, request_id: u64) -> String {
+    format!("{}{:013}:{:010}", KEY_PREFIX, ts, request_id)
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+/// 查询 [from_ms, to_ms) 内的记录，client 为 None 时不过滤
+pub async fn query(
+    storage: &dyn Storage,
+    from_ms: u64,
+    to_ms: u64,
+    client: Option<&str>,
+) -> Result<Vec<LedgerEntry>, String> {
+    let start = format!("{}{:013}", KEY_PREFIX, from_ms);
+    let end = format!("{}{:013}", KEY_PREFIX, to_ms);
+    let mut entries = Vec::new();
+    for (key, value) in storage.scan(&start, &end).await? {
+        match serde_json::from_slice::<LedgerEntry>(&value) {
+            Ok(entry) if client.is_none_or(|c| entry.client == c) => entries.push(entry),
+            Ok(_) => {}
+            Err(e) => warn!("💰 Skipping corrupt ledger entry {}: {}", key, e),
+        }
+    }
+    Ok(entries)
+}
+
+/// 已经生成、还没写进存储的记录时间戳。写入是异步的，导出水位不能越过其中最早的一条，
+/// 否则这条记录落盘时已经在水位之前，永远不会被导出
+#[derive(Clone, Default)]
+pub struct PendingWrites(Arc<Mutex<BTreeMap<u64, usize>>>);
+
+impl PendingWrites {
+    // 时间戳在锁里取，horizon 之前不在表里的记录一定已经写完
+    fn begin(&self) -> u64 {
+        let mut pending = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let ts = unix_ms();
+        *pending.entry(ts).or_default() += 1;
+        ts
+    }
+
+    fn finish(&self, ts: u64) {
+        let mut pending = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(count) = pending.get_mut(&ts) {
+            *count -= 1;
+            if *count == 0 {
+                pending.remove(&ts);
+            }
+        }
+    }
+
+    /// 早于这个时间 (unix 毫秒) 的记录都已经写入存储
+    pub fn horizon(&self) -> u64 {
+        let pending = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let now = unix_ms();
+        pending.keys().next().map_or(now, |oldest| (*oldest).min(now))
+    }
+}
+
+/// 账本记录写入后的回调 (预算告警、预付费扣减等)
+#[async_trait]
+pub trait LedgerHook: Send + Sync + 'static {
+    async fn on_entry(&self, entry: &LedgerEntry);
+}
+
+// 进行中的请求挂在 RequestContext.extensions 上
+#[derive(Clone)]
+struct Metering(Arc<Mutex<UsageScanner>>);
+
+pub struct Ledger {
+    storage: Arc<dyn Storage>,
+    prices: PriceTable,
+    hooks: Vec<Arc<dyn LedgerHook>>,
+    pending: PendingWrites,
+}
+
+impl Ledger {
+    pub fn new(storage: Arc<dyn Storage>, prices: PriceTable) -> Self {
+        Self {
+            storage,
+            prices,
+            hooks: Vec::new(),
+            pending: PendingWrites::default(),
+        }
+    }
+
+    pub fn hook(mut self, hook: Arc<dyn LedgerHook>) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    /// 和 [`crate::export::UsageExporter::pending`] 共用一个，导出时避开还在写入的记录
+    pub fn pending(mut self, pending: PendingWrites) -> Self {
+        self.pending = pending;
+        self
+    }
+}
+
+#[async_trait]
+impl GatewayPlugin for Ledger {
+    fn name(&self) -> &str {
+        "ledger"
+    }
+
+    async fn on_request(&self, ctx: &mut RequestContext) -> Result<(), axum::response::Response> {
+        if usage::model_from_path(ctx.uri.path()).is_some() || ctx.extensions.get::<ProviderRoute>().is_some() {
+            ctx.extensions.insert(Metering(Arc::new(Mutex::new(UsageScanner::new()))));
+        }
+        Ok(())
+    }
+
+    fn on_chunk(&self, ctx: &RequestContext, chunk: &Bytes) -> ChunkAction {
+        if let Some(Metering(scanner)) = ctx.extensions.get::<Metering>() {
+            scanner.lock().unwrap().feed(chunk);
+        }
+        ChunkAction::Continue
+    }
+
+    fn on_complete(&self, ctx: &RequestContext, outcome: &Outcome) {
+        let Some(Metering(scanner)) = ctx.extensions.get::<Metering>() else {
+            return;
+        };
+        let usage = scanner.lock().unwrap().usage().unwrap_or_default();
+        let model = match ctx.extensions.get::<ProviderRoute>() {
+            Some(route) => route.model.clone(),
+            None => usage::model_from_path(ctx.uri.path()).unwrap_or("unknown").to_string(),
+        };
+        // totalTokenCount 里还包含思考 token，按 total - prompt 计输出
+        let output_tokens = usage.total.saturating_sub(usage.prompt).max(usage.candidates);
+
+        let entry = LedgerEntry {
+            ts: self.pending.begin(),
+            request_id: ctx.id,
+            client: usage::client_label(ctx),
+            tenant: ctx.extensions.get::<CurrentTenant>().map(|t| t.0.name.clone()),
+            project: ctx.extensions.get::<CurrentProject>().map(|p| p.0.clone()),
+            key: ctx.extensions.get::<UpstreamKeyId>().map(|k| k.0.clone()),
+            cost: self.prices.cost(&model, usage.prompt, output_tokens),
+            model,
+            status: outcome.status.map(|s| s.as_u16()),
+            prompt_tokens: usage.prompt,
+            candidate_tokens: usage.candidates,
+            output_tokens,
+        };
+
+        let storage = self.storage.clone();
+        let hooks = self.hooks.clone();
+        let pending = self.pending.clone();
+        tokio::spawn(async move {
+            let key = entry_key(entry.ts, entry.request_id);
+            let value = serde_json::to_vec(&entry).unwrap_or_default();
+            if let Err(e) = storage.set(&key, value, None).await {
+                warn!("💰 Failed to append ledger entry: {}", e);
+            }
+            pending.finish(entry.ts);
+            for hook in &hooks {
+                hook.on_entry(&entry).await;
+            }
+        });
+    }
+}
@@ -0,0 +1,168 @@
+//! Aizasy Gateway: 面向 Gemini API 的低延迟反向代理。
+//!
+//! 既可以作为独立二进制运行，也可以通过 [`Gateway::builder`] 嵌入到其他 Rust 服务:
+//!
+//! ```no_run
+//! # async fn run() -> std::io::Result<()> {
+//! let gateway = aizasy_gateway::Gateway::builder()
+//!     .target("https://generativelanguage.googleapis.com")
+//!     .listen("127.0.0.1:3000".parse().unwrap())
+//!     .build()
+//!     .expect("invalid gateway config");
+//! gateway.serve().await
+//! # }
+//! ```
+//!
+//! 也可以只取出 [`Gateway::router`]，挂到已有的 axum 应用下:
+//!
+//! ```no_run
+//! # async fn run() -> std::io::Result<()> {
+//! use std::net::SocketAddr;
+//!
+//! let gateway = aizasy_gateway::Gateway::builder().build().expect("invalid gateway config");
+//! let app = axum::Router::new().nest("/gemini", gateway.router());
+//!
+//! let listener = tokio::net::TcpListener::bind("127.0.0.1:8080").await?;
+//! axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await
+//! # }
+//! ```
+//!
+//! 不需要 [`Gateway`] 的其他句柄时，[`GatewayBuilder::into_router`] 一步得到 Router:
+//!
+//! ```no_run
+//! let router = aizasy_gateway::Gateway::builder()
+//!     .target("https://generativelanguage.googleapis.com")
+//!     .into_router()
+//!     .expect("invalid gateway config");
+//! let app: axum::Router = axum::Router::new().nest("/gemini", router);
+//! ```
+
+pub mod access_log;
+#[cfg(feature = "admin")]
+mod admin;
+pub mod audit;
+pub mod bandwidth;
+pub mod batch;
+pub mod bench;
+pub mod budget;
+pub mod build_info;
+pub mod burst;
+pub mod cached_contents;
+pub mod canary;
+pub mod cidr;
+#[cfg(feature = "devtools")]
+pub mod canned;
+#[cfg(feature = "devtools")]
+pub mod chaos;
+pub mod cli;
+pub mod client_auth;
+pub mod client_ip;
+pub mod coalesce;
+#[cfg(feature = "compression")]
+pub mod compression;
+#[cfg(feature = "config")]
+pub mod config_file;
+pub mod cors;
+pub mod credits;
+#[cfg(feature = "admin")]
+pub mod dashboard;
+pub mod dns;
+pub mod drain;
+pub mod egress;
+pub mod encoding;
+pub mod error_templates;
+pub mod events;
+pub mod export;
+pub mod failures;
+pub mod generation_policy;
+pub mod grpc;
+#[cfg(feature = "geoip")]
+pub mod geoip;
+pub mod header_rules;
+pub mod inspector;
+pub mod ip_filter;
+pub mod key_pool;
+pub mod keys;
+pub mod ledger;
+pub mod load_shed;
+pub mod lockout;
+pub mod maintenance;
+pub mod metering;
+pub mod metrics;
+pub mod mirror;
+#[cfg(feature = "devtools")]
+pub mod mock;
+pub mod model_fallback;
+pub mod model_map;
+pub mod model_router;
+pub mod otel;
+pub mod path_allowlist;
+pub mod plugin;
+pub mod prewarm;
+#[cfg(feature = "devtools")]
+pub mod record;
+pub mod project;
+pub mod providers;
+pub mod quota;
+pub mod rate_limit;
+pub mod redact;
+pub mod report;
+#[cfg(feature = "metrics")]
+pub mod request_metrics;
+pub mod request_queue;
+pub mod response_cache;
+pub mod response_filter;
+pub mod retry;
+pub mod rewrite;
+pub mod routes;
+pub mod sanitize;
+pub mod scanner;
+pub mod schedule;
+#[cfg(feature = "scripting")]
+pub mod script_plugin;
+#[cfg(feature = "secrets")]
+pub mod secrets;
+pub mod security_headers;
+pub mod signed_url;
+pub mod slow_log;
+pub mod storage;
+pub mod stream_transform;
+pub mod system_prompt;
+pub mod target_policy;
+pub mod tenant;
+pub mod token_count;
+pub mod trailers;
+pub mod uploads;
+pub mod upstreams;
+pub mod usage;
+pub mod validate;
+#[cfg(feature = "vertex")]
+pub mod vertex;
+#[cfg(feature = "wasm")]
+pub mod wasm_plugin;
+#[cfg(all(windows, feature = "windows-service"))]
+pub mod win_service;
+
+mod finish_body;
+mod gateway;
+#[cfg(feature = "http3")]
+mod http3;
+mod openai;
+mod proxy;
+mod stream_timeout;
+#[cfg(unix)]
+mod systemd;
+#[cfg(feature = "tls")]
+mod tls;
+#[cfg(unix)]
+mod unix_socket;
+
+pub use events::{EventBus, GatewayEvent};
+pub use gateway::{Gateway, GatewayBuilder, LayerPosition, DEFAULT_TARGET};
+#[cfg(feature = "admin")]
+pub use gateway::LogLevelControl;
+pub use plugin::{ChunkAction, ChunkTransformer, GatewayHook, GatewayPlugin, Outcome, RequestContext};
+#[cfg(feature = "tls")]
+pub use tls::check_certificate;
+
+pub(crate) use gateway::AppState;
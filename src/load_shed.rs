@@ -0,0 +1,112 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use http_body_util::BodyExt;
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::warn;
+
+use crate::AppState;
+
+// --- 并发上限 / 过载保护 ---
+// 同时在处理的代理请求 (从进入网关到响应体发完，流式响应也算) 不超过 max_inflight 个。
+// 满了以后新请求最多排队 queue 个、每个最多等 queue_timeout，排不上或等超时直接返回 503，
+// 流量突增时不至于把小机器的文件描述符和内存耗尽。health / metrics / 管理 API 不受限制。
+// 没有设置上限时也统计当前在处理的请求数，供管理 API 的 /stats 查看。
+
+#[derive(Debug, Clone)]
+pub struct InflightConfig {
+    pub max_inflight: usize,
+    /// 满载时允许排队等待的请求数，0 表示不排队直接拒绝
+    pub queue: usize,
+    pub queue_timeout: Duration,
+}
+
+pub(crate) struct InflightLimit {
+    config: InflightConfig,
+    semaphore: Arc<Semaphore>,
+    waiting: AtomicUsize,
+}
+
+impl InflightLimit {
+    pub(crate) fn new(config: InflightConfig) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(config.max_inflight)),
+            config,
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    #[cfg(feature = "admin")]
+    pub(crate) fn config(&self) -> &InflightConfig {
+        &self.config
+    }
+
+    async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+        if let Ok(permit) = self.semaphore.clone().try_acquire_owned() {
+            return Some(permit);
+        }
+        if self.waiting.fetch_add(1, Ordering::AcqRel) >= self.config.queue {
+            self.waiting.fetch_sub(1, Ordering::AcqRel);
+            return None;
+        }
+        let permit = tokio::time::timeout(self.config.queue_timeout, self.semaphore.clone().acquire_owned()).await;
+        self.waiting.fetch_sub(1, Ordering::AcqRel);
+        permit.ok().and_then(Result::ok)
+    }
+}
+
+// 计入 in_flight，drop 时减掉
+struct Tracked(Arc<AtomicUsize>);
+
+impl Tracked {
+    fn new(counter: &Arc<AtomicUsize>) -> Self {
+        counter.fetch_add(1, Ordering::Relaxed);
+        Self(counter.clone())
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub(crate) async fn guard(State(state): State<Arc<AppState>>, req: Request, next: Next) -> Response {
+    let permit = match &state.inflight {
+        Some(limit) => match limit.acquire().await {
+            Some(permit) => Some(permit),
+            None => return shed(&state, limit, &req),
+        },
+        None => None,
+    };
+    let tracked = Tracked::new(&state.in_flight);
+    let response = next.run(req).await;
+    // 许可跟着响应体走，流式响应发完 (或客户端断开) 才释放
+    response.map(|body| {
+        axum::body::Body::new(body.map_frame(move |frame| {
+            let _ = (&permit, &tracked);
+            frame
+        }))
+    })
+}
+
+fn shed(state: &AppState, limit: &InflightLimit, req: &Request) -> Response {
+    warn!("🚦 Over {} in-flight requests, shedding {}", limit.config.max_inflight, req.uri().path());
+    state.metrics.inc("aizasy_load_shed_total", &[]);
+    let body = json!({
+        "error": {
+            "code": 503,
+            "message": "The gateway is overloaded, please retry later",
+            "status": "UNAVAILABLE",
+        }
+    });
+    (StatusCode::SERVICE_UNAVAILABLE, [(header::RETRY_AFTER, "1")], Json(body)).into_response()
+}
@@ -0,0 +1,227 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use crate::client_auth;
+use crate::client_ip::ClientIp;
+use crate::signed_url::SignedAccess;
+use crate::AppState;
+
+/// 鉴权层在拒绝请求时放进 response extensions，lockout 层据此计数
+#[derive(Clone, Debug)]
+pub struct AuthFailure;
+
+// 失败计数的对象。出示了有效客户端令牌的请求按令牌计数，同一个 NAT 后面的客户端互不牵连；
+// 其他请求按来源 IP 计数，出示了令牌的再按令牌计一份，换 IP 反复试同一个令牌也会被锁。
+// 令牌只存带随机种子的哈希，不在内存里多留一份明文。
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Subject {
+    Ip(IpAddr),
+    Token(u64),
+}
+
+impl fmt::Display for Subject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Subject::Ip(ip) => write!(f, "{}", ip),
+            Subject::Token(hash) => write!(f, "client token #{:016x}", hash),
+        }
+    }
+}
+
+struct FailureState {
+    failures: u32,
+    last_failure: Instant,
+    locked_until: Option<Instant>,
+}
+
+// 最多跟踪的 IP 和令牌数；表满时新出现的不计数，等后台清理腾出位置
+const MAX_ENTRIES: usize = 100_000;
+
+// 后台清理长时间没有失败的条目的间隔，请求路径上不做全表扫描
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+// --- 鉴权失败限流 + 指数锁定 ---
+pub struct AuthLockout {
+    // 连续失败多少次后开始锁定
+    threshold: u32,
+    base: Duration,
+    max: Duration,
+    entries: Mutex<HashMap<Subject, FailureState>>,
+    token_hasher: RandomState,
+}
+
+impl AuthLockout {
+    pub fn new(threshold: u32, base: Duration, max: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            base,
+            max,
+            entries: Mutex::new(HashMap::new()),
+            token_hasher: RandomState::new(),
+        }
+    }
+
+    /// 只计数不锁定：突发检测按鉴权失败判断而没有开 lockout 时用，recovery 内没有新失败就清零
+    pub(crate) fn counting(recovery: Duration) -> Self {
+        Self::new(u32::MAX, recovery, recovery)
+    }
+
+    /// 这个 IP 还没恢复的连续失败次数
+    pub(crate) fn failures(&self, ip: IpAddr) -> u32 {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries
+            .get(&Subject::Ip(ip))
+            .filter(|e| e.last_failure.elapsed() < self.max)
+            .map_or(0, |e| e.failures)
+    }
+
+    /// 仍在锁定期内时返回剩余时间
+    fn locked_for(&self, subject: Subject) -> Option<Duration> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let until = entries.get(&subject)?.locked_until?;
+        until.checked_duration_since(Instant::now())
+    }
+
+    fn record_failure(&self, subject: Subject) -> Option<Duration> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.len() >= MAX_ENTRIES && !entries.contains_key(&subject) {
+            return None;
+        }
+        let entry = entries.entry(subject).or_insert(FailureState {
+            failures: 0,
+            last_failure: now,
+            locked_until: None,
+        });
+        // 长时间没有失败的条目视为已恢复，后台还没清理到时在这里重新计数
+        if now.duration_since(entry.last_failure) >= self.max {
+            entry.failures = 0;
+            entry.locked_until = None;
+        }
+        entry.failures += 1;
+        entry.last_failure = now;
+
+        if entry.failures < self.threshold {
+            return None;
+        }
+        // 第 threshold 次失败锁 base，之后每次翻倍，封顶 max
+        let exp = (entry.failures - self.threshold).min(16);
+        let lock = self.base.saturating_mul(1 << exp).min(self.max);
+        entry.locked_until = Some(now + lock);
+        Some(lock)
+    }
+
+    fn record_success(&self, subject: Subject) {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).remove(&subject);
+    }
+
+    fn token(&self, token: &str) -> Subject {
+        Subject::Token(self.token_hasher.hash_one(token))
+    }
+
+    // 去掉长时间没有失败、也不在锁定期内的条目
+    fn sweep(&self) {
+        let now = Instant::now();
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|_, e| now.duration_since(e.last_failure) < self.max || e.locked_until.is_some_and(|u| u > now));
+    }
+}
+
+/// 后台定期清理恢复了的条目；网关被释放 (热重载换掉) 后自动退出
+pub(crate) fn spawn_sweeper(state: Weak<AppState>) {
+    tokio::spawn(async move {
+        let mut timer = tokio::time::interval(SWEEP_INTERVAL);
+        timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        timer.tick().await;
+        loop {
+            timer.tick().await;
+            let Some(state) = state.upgrade() else {
+                return;
+            };
+            if let Some(lockout) = &state.lockout {
+                lockout.sweep();
+            }
+        }
+    });
+}
+
+// 计一次失败，达到阈值时打日志
+fn fail(state: &AppState, lockout: &AuthLockout, subject: Subject) {
+    if let Some(lock) = lockout.record_failure(subject) {
+        warn!("🔒 {} locked out for {}s after repeated auth failures", subject, lock.as_secs());
+        state.metrics.inc("aizasy_auth_lockouts_total", &[]);
+    }
+}
+
+/// 签名层在 lockout 外面，签名校验失败时直接在这里按 IP 计数
+pub(crate) fn record_failure(state: &AppState, ip: IpAddr) {
+    if let Some(lockout) = &state.lockout {
+        state.metrics.inc("aizasy_auth_failures_total", &[]);
+        fail(state, lockout, Subject::Ip(ip));
+    }
+}
+
+pub(crate) async fn guard(
+    State(state): State<Arc<AppState>>,
+    ClientIp(ip): ClientIp,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Some(lockout) = &state.lockout else {
+        return next.run(req).await;
+    };
+    // 带有效签名 URL 的请求不受锁定影响，也不清掉失败计数；签名层在外面，已经校验过了
+    if req.extensions().get::<SignedAccess>().is_some() {
+        return next.run(req).await;
+    }
+
+    // 有效令牌只看令牌自己的计数；否则看 IP，出示了令牌的同时看令牌
+    let token = state.client_tokens.as_ref().and_then(|tokens| {
+        let token = client_auth::presented_token(req.headers(), req.uri())?;
+        Some((lockout.token(&token), tokens.accepts(&token)))
+    });
+    let (subject, also) = match token {
+        Some((token, true)) => (token, None),
+        Some((token, false)) => (Subject::Ip(ip), Some(token)),
+        None => (Subject::Ip(ip), None),
+    };
+
+    let locked = [Some(subject), also].into_iter().flatten().filter_map(|s| lockout.locked_for(s)).max();
+    if let Some(remaining) = locked {
+        state.metrics.inc("aizasy_auth_lockout_rejected_total", &[]);
+        let retry_after = remaining.as_secs().max(1).to_string();
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, retry_after)],
+            "Too many authentication failures",
+        )
+            .into_response();
+    }
+
+    let response = next.run(req).await;
+
+    if response.extensions().get::<AuthFailure>().is_some() {
+        state.metrics.inc("aizasy_auth_failures_total", &[]);
+        for subject in [Some(subject), also].into_iter().flatten() {
+            fail(&state, lockout, subject);
+        }
+    } else {
+        lockout.record_success(subject);
+    }
+
+    response
+}
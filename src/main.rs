@@ -1,161 +1,577 @@
-use axum::{
-    body::Body,
-    extract::{State, Request},
-    http::{HeaderMap, Method, Uri, StatusCode},
-    response::{IntoResponse, Response},
-    routing::{any, get},
-    Router,
-};
-use clap::Parser;
-use reqwest::{Client, Proxy};
+use aizasy_gateway::bench::{self, BenchConfig};
+use aizasy_gateway::cli::{self, check_config, probe_gateway, Args};
+#[cfg(all(unix, feature = "config"))]
+use aizasy_gateway::cli::Reuse;
+#[cfg(feature = "config")]
+use aizasy_gateway::config_file;
+use aizasy_gateway::drain::Drain;
+use aizasy_gateway::egress;
+use aizasy_gateway::keys;
+#[cfg(feature = "devtools")]
+use aizasy_gateway::record;
+#[cfg(all(unix, feature = "config"))]
+use aizasy_gateway::Gateway;
+use aizasy_gateway::DEFAULT_TARGET;
+#[cfg(feature = "config")]
+use clap::builder::Resettable;
+#[cfg(feature = "config")]
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use std::ffi::OsString;
+#[cfg(feature = "devtools")]
 use std::net::SocketAddr;
-use std::sync::Arc;
 use std::time::Duration;
-use tracing::{error, info, warn, debug};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+#[cfg(unix)]
+use tokio::signal::unix::{signal, SignalKind};
+#[cfg(all(unix, feature = "config"))]
+use tokio::sync::mpsc;
+use tracing::info;
+#[cfg(any(all(unix, feature = "config"), all(windows, feature = "windows-service")))]
+use tracing::warn;
 
-// --- 配置参数 ---
-#[derive(Parser, Debug, Clone)]
-#[command(author, version, about = "Aizasy Gateway")]
-struct Args {
-    #[arg(short, long, env = "AIZASY_LISTEN", default_value = "0.0.0.0:3000")]
-    listen: String,
+// --- 命令行 ---
+// 不带子命令时等同于 `serve`，原来的平铺参数写法保持可用
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Aizasy Gateway", args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    #[arg(short, long, env = "AIZASY_PROXY")]
-    proxy: Option<String>,
+    #[command(flatten)]
+    serve: Args,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// 启动网关 (默认)
+    Serve(Box<Args>),
+    /// 加载并校验全部配置 (规则、文件、存储后端)，报告所有错误，不监听端口
+    Check(Box<CheckArgs>),
+    /// 管理上游 key 文件
+    Keys(KeysArgs),
+    /// 对网关发起并发压测
+    Bench(BenchArgs),
+    /// 生成带全部环境变量的配置模板
+    Init(InitArgs),
+    /// 把 --record 录制的目录当作上游单独提供服务，不经过网关也不访问真实上游 (离线测试桩)
+    #[cfg(feature = "devtools")]
+    Replay(ReplayArgs),
+    /// 作为 Windows 服务运行 (由服务管理器启动，参数同 serve)
+    #[cfg(all(windows, feature = "windows-service"))]
+    Service(Box<Args>),
+    /// 注册为自动启动的 Windows 服务，`--` 之后的参数在服务启动时传给网关
+    #[cfg(all(windows, feature = "windows-service"))]
+    InstallService(InstallServiceArgs),
+    /// 停止并删除 Windows 服务
+    #[cfg(all(windows, feature = "windows-service"))]
+    UninstallService(ServiceNameArgs),
+}
+
+#[cfg(all(windows, feature = "windows-service"))]
+#[derive(clap::Args, Debug)]
+struct ServiceNameArgs {
+    /// 服务名
+    #[arg(long, default_value = aizasy_gateway::win_service::DEFAULT_SERVICE_NAME)]
+    name: String,
+}
+
+#[cfg(all(windows, feature = "windows-service"))]
+#[derive(clap::Args, Debug)]
+struct InstallServiceArgs {
+    #[command(flatten)]
+    service: ServiceNameArgs,
 
-    #[arg(short, long, env = "AIZASY_TARGET", default_value = "https://generativelanguage.googleapis.com")]
-    target: String,
+    /// 网关参数，文件路径要写绝对路径 (服务的工作目录是 System32)
+    #[arg(last = true)]
+    args: Vec<OsString>,
+}
+
+#[derive(clap::Args, Debug)]
+struct CheckArgs {
+    /// 另外向每个上游 (默认上游和 --route 里的) 发一次健康检查请求，确认网络、代理和证书都没问题；
+    /// 超时用 --health-check-timeout-secs
+    #[arg(long)]
+    probe_upstreams: bool,
+
+    #[command(flatten)]
+    serve: Args,
+}
 
-    #[arg(long, env = "AIZASY_INSECURE", default_value = "false")]
-    insecure: bool,
+#[derive(clap::Args, Debug)]
+struct KeysArgs {
+    /// key 文件路径 (每行 KEY 或 NAME=KEY)
+    #[arg(long, env = "AIZASY_KEYS_FILE", default_value = "keys.txt", global = true)]
+    file: String,
 
-    #[arg(long, env = "AIZASY_LOG", default_value = "info")]
-    log_level: String,
+    #[command(subcommand)]
+    command: KeysCommand,
 }
 
-#[derive(Clone)]
-struct AppState {
-    client: Client,
-    target_url: String,
+#[derive(Subcommand, Debug)]
+enum KeysCommand {
+    /// 列出 key (打码显示)
+    List,
+    /// 追加一个 key
+    Add {
+        key: String,
+        /// key 名称，默认 key-N
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// 逐个调用上游 models 接口检查 key 是否可用
+    Test {
+        #[arg(short, long, env = "AIZASY_TARGET", default_value = DEFAULT_TARGET)]
+        target: String,
+
+        #[arg(short, long, env = "AIZASY_PROXY")]
+        proxy: Option<String>,
+
+        #[arg(long, env = "AIZASY_PROXY_AUTH", value_name = "USER:PASS", requires = "proxy")]
+        proxy_auth: Option<String>,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+struct BenchArgs {
+    /// 压测地址
+    #[arg(default_value = "http://127.0.0.1:3000/v1beta/models/gemini-1.5-flash:generateContent")]
+    url: String,
+
+    /// 总请求数
+    #[arg(short = 'n', long, default_value = "100")]
+    requests: usize,
+
+    /// 并发数
+    #[arg(short, long, default_value = "10")]
+    concurrency: usize,
+
+    #[arg(short = 'X', long, default_value = "POST")]
+    method: String,
+
+    /// 请求体 (JSON)，默认是一条很短的 generateContent 请求；GET 请求不发送
+    #[arg(short, long)]
+    body: Option<String>,
+
+    /// 额外请求头，可重复指定: "Name: value"
+    #[arg(short = 'H', long = "header")]
+    headers: Vec<String>,
+
+    /// 以 x-goog-api-key 发送的 key
+    #[arg(long, env = "AIZASY_BENCH_KEY", hide_env_values = true)]
+    key: Option<String>,
+
+    /// 单个请求超时秒数
+    #[arg(long, default_value = "60")]
+    timeout_secs: u64,
+}
+
+#[derive(clap::Args, Debug)]
+struct InitArgs {
+    /// 输出文件
+    #[arg(default_value = "aizasy.env")]
+    output: String,
+
+    /// 覆盖已有文件
+    #[arg(long, default_value = "false")]
+    force: bool,
+}
+
+#[cfg(feature = "devtools")]
+#[derive(clap::Args, Debug)]
+struct ReplayArgs {
+    /// 录制目录
+    dir: String,
+
+    /// 监听地址
+    #[arg(short, long, default_value = "127.0.0.1:3000")]
+    listen: SocketAddr,
+
+    /// 不保留原始分块节奏，立即返回
+    #[arg(long, default_value = "false")]
+    fast: bool,
 }
 
 #[tokio::main]
 async fn main() {
-    let args = Args::parse();
+    let cli = parse_cli().unwrap_or_else(|e| exit_with(e));
+    match cli.command.unwrap_or_else(|| Command::Serve(Box::new(cli.serve))) {
+        Command::Serve(args) => serve(*args).await,
+        Command::Check(args) => check_command(args.serve, args.probe_upstreams).await,
+        Command::Keys(args) => keys_command(args).await,
+        Command::Bench(args) => bench_command(args).await,
+        Command::Init(args) => init_command(args),
+        #[cfg(feature = "devtools")]
+        Command::Replay(args) => replay_command(args).await,
+        #[cfg(all(windows, feature = "windows-service"))]
+        Command::Service(args) => service_command(*args),
+        #[cfg(all(windows, feature = "windows-service"))]
+        Command::InstallService(args) => {
+            match aizasy_gateway::win_service::install(&args.service.name, args.args) {
+                Ok(()) => println!("✅ Installed service {}", args.service.name),
+                Err(e) => exit_with(e),
+            }
+        }
+        #[cfg(all(windows, feature = "windows-service"))]
+        Command::UninstallService(args) => match aizasy_gateway::win_service::uninstall(&args.name) {
+            Ok(()) => println!("✅ Removed service {}", args.name),
+            Err(e) => exit_with(e),
+        },
+    }
+}
 
-    // 初始化日志
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::fmt::layer())
-        .with(tracing_subscriber::EnvFilter::new(args.log_level.clone()))
-        .init();
+/// 解析命令行；指定了 --config 时把文件里的设置追加成命令行参数再解析一次，
+/// 命令行或环境变量已经给出的参数以它们为准
+fn parse_cli() -> Result<Cli, String> {
+    let argv: Vec<OsString> = std::env::args_os().collect();
+    let matches = Cli::command().get_matches_from(&argv);
+    #[cfg(feature = "config")]
+    {
+        let serve = match matches.subcommand() {
+            Some(("serve" | "check" | "service", sub)) => Some(sub),
+            Some(_) => None,
+            None => Some(&matches),
+        };
+        if let Some(serve) = serve {
+            if let Some(path) = serve.get_one::<String>("config") {
+                let extra = config_args(path, serve)?;
+                return Cli::try_parse_from(argv.into_iter().chain(extra)).map_err(|e| format!("{}: {}", path, e));
+            }
+        }
+    }
+    Cli::from_arg_matches(&matches).map_err(|e| e.to_string())
+}
 
-    info!("🚀 Aizasy Gateway Starting...");
+#[cfg(feature = "config")]
+fn config_args(path: &str, matches: &clap::ArgMatches) -> Result<Vec<OsString>, String> {
+    let command = Cli::command();
+    let (content, entries) = config_file::load(path)?;
+    let mut out = Vec::new();
+    // 一次报告文件里的全部错误，而不是改一个报一个
+    let mut errors = Vec::new();
+    for entry in entries {
+        let Some(arg) = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(entry.key.as_str()) && arg.get_id() != "config")
+        else {
+            errors.push((entry.line, config_file::error_at(path, &content, &entry, &format!("unknown setting '{}'", entry.key))));
+            continue;
+        };
+        if matches!(
+            matches.value_source(arg.get_id().as_str()),
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        ) {
+            continue;
+        }
+        let flag = !arg.get_action().takes_values();
+        for value in &entry.values {
+            match (flag, value.as_str()) {
+                (true, "true") => out.push(format!("--{}", entry.key).into()),
+                (true, "false") => {}
+                (true, _) => errors.push((
+                    entry.line,
+                    config_file::error_at(path, &content, &entry, &format!("'{}' expects true or false", entry.key)),
+                )),
+                (false, _) => match check_value(arg, &entry.key, value) {
+                    Ok(()) => out.push(format!("--{}={}", entry.key, value).into()),
+                    Err(message) => errors.push((entry.line, config_file::error_at(path, &content, &entry, &message))),
+                },
+            }
+        }
+    }
+    if !errors.is_empty() {
+        errors.sort_by_key(|(line, _)| *line);
+        let errors: Vec<String> = errors.into_iter().map(|(_, e)| e).collect();
+        return Err(errors.join("\n❌ "));
+    }
+    Ok(out)
+}
 
-    // --- 构建 HTTP 客户端 ---
-    let mut client_builder = Client::builder()
-        .pool_idle_timeout(Duration::from_secs(90))
-        .pool_max_idle_per_host(50)
-        .tcp_nodelay(true)
-        .connect_timeout(Duration::from_secs(10))
-        .timeout(Duration::from_secs(120))
-        .no_gzip();
+// 单独解析这一个值，好把错误对应到文件里的行；和其他参数的依赖关系留给完整解析
+#[cfg(feature = "config")]
+fn check_value(arg: &clap::Arg, key: &str, value: &str) -> Result<(), String> {
+    let arg = arg.clone().required(false).requires(Resettable::Reset).conflicts_with(Resettable::Reset);
+    clap::Command::new("config")
+        .no_binary_name(true)
+        .arg(arg)
+        .try_get_matches_from([format!("--{}={}", key, value)])
+        .map(|_| ())
+        .map_err(|e| {
+            // clap 的错误信息第一行就是原因，后面是帮助提示
+            let e = e.to_string();
+            e.lines().next().unwrap_or_default().trim_start_matches("error: ").to_string()
+        })
+}
 
-    if let Some(proxy_url) = &args.proxy {
-        info!("🔌 Proxy: {}", proxy_url);
-        let proxy = Proxy::all(proxy_url).expect("Invalid proxy URL");
-        client_builder = client_builder.proxy(proxy);
+#[cfg(all(unix, feature = "config"))]
+fn serve_args(cli: Cli) -> Args {
+    match cli.command {
+        Some(Command::Serve(args)) => *args,
+        _ => cli.serve,
     }
+}
 
-    if args.insecure {
-        warn!("⚠️  Insecure Mode: SSL validation disabled");
-        client_builder = client_builder.danger_accept_invalid_certs(true);
+async fn serve(args: Args) {
+    if args.check_config {
+        return check_command(args, false).await;
     }
+    let Some(gateway) = cli::build(args.clone(), None).await.unwrap_or_else(|e| exit_with(e)) else {
+        return;
+    };
+    #[cfg(unix)]
+    tokio::spawn(drain_on_sigusr1(gateway.drain()));
+    #[cfg(all(unix, feature = "config"))]
+    if let Some(path) = args.config.clone() {
+        let (reloads, rx) = mpsc::channel(1);
+        let reuse = Reuse {
+            storage: gateway.storage(),
+            target: gateway.target().to_string(),
+            drain: gateway.drain(),
+        };
+        tokio::spawn(reload_on_sighup(path, args, reuse, reloads));
+        gateway.serve_reloadable(rx).await.unwrap_or_else(|e| exit_with(e));
+        return;
+    }
+    gateway.serve().await.unwrap_or_else(|e| exit_with(e));
+}
 
-    let client = client_builder.build().expect("Failed to build client");
-
-    let state = Arc::new(AppState {
-        client,
-        target_url: args.target.trim_end_matches('/').to_string(),
+// 服务管理器的停止 / 关机请求走摘流，和 SIGUSR1 一样等在途请求完成后退出
+#[cfg(all(windows, feature = "windows-service"))]
+fn service_command(args: Args) {
+    // 留出摘流延迟和等待在途请求的时间，再多给几秒收尾
+    let stop_wait = Duration::from_secs(args.drain_delay_secs + args.drain_timeout_secs + 5);
+    let result = aizasy_gateway::win_service::run(stop_wait, move |stop| {
+        Box::pin(async move {
+            let Some(gateway) = cli::build(args, None).await.unwrap_or_else(|e| exit_with(e)) else {
+                return;
+            };
+            let drain = gateway.drain();
+            tokio::spawn(async move {
+                if stop.await.is_ok() {
+                    info!("🚰 Service stop requested");
+                    drain.start();
+                }
+            });
+            if let Err(e) = gateway.serve().await {
+                warn!("⚠️  Gateway stopped: {}", e);
+            }
+        })
     });
+    if let Err(e) = result {
+        exit_with(e);
+    }
+}
+
+// 滚动发布：SIGUSR1 开始摘流，在途请求完成 (或超过 --drain-timeout-secs) 后 serve 返回、进程退出
+#[cfg(unix)]
+async fn drain_on_sigusr1(drain: Drain) {
+    let mut usr1 = signal(SignalKind::user_defined1()).expect("Failed to install SIGUSR1 handler");
+    if usr1.recv().await.is_some() {
+        info!("🚰 SIGUSR1 received");
+        drain.start();
+    }
+}
+
+// --- 热重载 ---
+// SIGHUP 时重新读取配置文件并完整构建一个新网关，替换正在服务的路由。
+// 存储后端沿用启动时打开的 (内存存储里的配额、余额不能丢)，监听地址、日志级别和
+// mock / 回放上游需要重启才能生效；通过管理 API 做的运行时修改会被配置文件覆盖。
+
+#[cfg(all(unix, feature = "config"))]
+async fn reload_on_sighup(path: String, mut current: Args, reuse: Reuse, reloads: mpsc::Sender<Gateway>) {
+    let mut hangup = signal(SignalKind::hangup()).expect("Failed to install SIGHUP handler");
+    while hangup.recv().await.is_some() {
+        info!("🔄 SIGHUP received, reloading {}", path);
+        let args = match parse_cli() {
+            Ok(cli) => serve_args(cli),
+            Err(e) => {
+                warn!("❌ Reload failed, keeping the current configuration: {}", e);
+                continue;
+            }
+        };
+        #[cfg(feature = "devtools")]
+        let devtools_changed = args.mock_upstream != current.mock_upstream || args.replay != current.replay;
+        #[cfg(not(feature = "devtools"))]
+        let devtools_changed = false;
+        for (setting, changed) in [
+            ("storage", args.storage != current.storage),
+            ("log-level", args.log_level != current.log_level),
+            ("mock-upstream / replay", devtools_changed),
+        ] {
+            if changed {
+                warn!("⚠️  {} changed, restart to apply", setting);
+            }
+        }
+        let next = Reuse {
+            storage: reuse.storage.clone(),
+            target: reuse.target.clone(),
+            drain: reuse.drain.clone(),
+        };
+        // 配置有错时 build 返回错误，只放弃这次重载；放到单独的任务里，意外 panic 也不会拖垮正在服务的网关
+        match tokio::spawn(cli::build(args.clone(), Some(next))).await {
+            Ok(Ok(Some(gateway))) => {
+                if reloads.send(gateway).await.is_err() {
+                    break;
+                }
+                current = args;
+                info!("✅ Configuration reloaded");
+            }
+            Ok(Ok(None)) => {}
+            Ok(Err(e)) => warn!("❌ Reload failed, keeping the current configuration: {}", e),
+            Err(_) => warn!("❌ Reload failed, keeping the current configuration"),
+        }
+    }
+}
+
+fn exit_with(message: impl std::fmt::Display) -> ! {
+    eprintln!("❌ {}", message);
+    std::process::exit(1);
+}
 
-    let app = Router::new()
-        .route("/health", get(health_check))
-        .route("/{*path}", any(proxy_handler))
-        .route("/", any(proxy_handler))
-        .with_state(state);
-
-    let addr: SocketAddr = args.listen.parse().expect("Invalid listen address");
-    info!("🎧 Listening on {}", addr);
-
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
-}
-
-async fn health_check() -> impl IntoResponse {
-    (StatusCode::OK, "OK")
-}
-
-// --- 核心处理函数 ---
-async fn proxy_handler(
-    State(state): State<Arc<AppState>>,
-    // 这里我们接收一个通用的 Request
-    req: Request, 
-) -> impl IntoResponse {
-    // 1. 提取路径和查询参数
-    let path = req.uri().path_and_query().map(|x| x.as_str()).unwrap_or("/");
-    let target_uri = format!("{}{}", state.target_url, path);
-    let method = req.method().clone();
-    let headers = req.headers().clone();
-
-    debug!("-> {} {}", method, target_uri);
-
-    // 2. 关键修复：显式读取 Body
-    // 将 Axum 的 Body 转换为 Bytes。Reqwest 原生支持 Bytes。
-    // 设置 64MB 限制，防止内存溢出
-    let req_body = req.into_body();
-    let req_bytes = match axum::body::to_bytes(req_body, 64 * 1024 * 1024).await {
-        Ok(bytes) => bytes,
-        Err(e) => {
-            error!("Failed to read request body: {}", e);
-            return (StatusCode::BAD_REQUEST, "Body too large or invalid").into_response();
+async fn keys_command(args: KeysArgs) {
+    match args.command {
+        KeysCommand::List => {
+            let entries = keys::load(&args.file).unwrap_or_else(|e| exit_with(e));
+            for entry in &entries {
+                println!("{}\t{}", entry.name, keys::mask(&entry.key));
+            }
+            println!("{} key(s) in {}", entries.len(), args.file);
+        }
+        KeysCommand::Add { key, name } => {
+            let name = keys::append(&args.file, name.as_deref(), &key).unwrap_or_else(|e| exit_with(e));
+            println!("✅ Added {} ({}) to {}", name, keys::mask(&key), args.file);
         }
+        KeysCommand::Test { target, proxy, proxy_auth } => {
+            let entries = keys::load(&args.file).unwrap_or_else(|e| exit_with(e));
+            let mut client = reqwest::Client::builder();
+            if let Some(proxy) = &proxy {
+                client = client.proxy(egress::parse(proxy, proxy_auth.as_deref()).unwrap_or_else(|e| exit_with(e)));
+            }
+            let client = client.build().unwrap_or_else(|e| exit_with(e));
+            let mut failed = 0;
+            for entry in &entries {
+                match keys::test(&client, &target, &entry.key).await {
+                    Ok(200) => println!("✅ {}\t{}", entry.name, keys::mask(&entry.key)),
+                    Ok(status) => {
+                        failed += 1;
+                        println!("❌ {}\t{}\tHTTP {}", entry.name, keys::mask(&entry.key), status);
+                    }
+                    Err(e) => {
+                        failed += 1;
+                        println!("❌ {}\t{}\t{}", entry.name, keys::mask(&entry.key), e);
+                    }
+                }
+            }
+            if failed > 0 {
+                exit_with(format!("{} of {} key(s) failed", failed, entries.len()));
+            }
+        }
+    }
+}
+
+async fn bench_command(args: BenchArgs) {
+    let method: reqwest::Method = args.method.to_ascii_uppercase().parse().unwrap_or_else(|e| exit_with(e));
+    let mut headers = reqwest::header::HeaderMap::new();
+    for spec in &args.headers {
+        let (name, value) = spec
+            .split_once(':')
+            .unwrap_or_else(|| exit_with(format!("invalid header '{}', expected 'Name: value'", spec)));
+        let name = reqwest::header::HeaderName::from_bytes(name.trim().as_bytes()).unwrap_or_else(|e| exit_with(e));
+        let value = reqwest::header::HeaderValue::from_str(value.trim()).unwrap_or_else(|e| exit_with(e));
+        headers.append(name, value);
+    }
+    if let Some(key) = &args.key {
+        let value = reqwest::header::HeaderValue::from_str(key).unwrap_or_else(|e| exit_with(e));
+        headers.insert("x-goog-api-key", value);
+    }
+    let body = (method != reqwest::Method::GET).then(|| {
+        args.body
+            .clone()
+            .unwrap_or_else(|| r#"{"contents":[{"parts":[{"text":"ping"}]}]}"#.to_string())
+    });
+    let config = BenchConfig {
+        url: args.url,
+        method,
+        headers,
+        body,
+        requests: args.requests,
+        concurrency: args.concurrency,
+        timeout: Duration::from_secs(args.timeout_secs),
     };
+    println!("🏋️  {} {} x{} (concurrency {})", config.method, config.url, config.requests, config.concurrency);
+    let client = reqwest::Client::new();
+    print!("{}", bench::run(&client, &config).await);
+}
 
-    // 3. 清洗 Headers
-    let mut new_headers = headers.clone();
-    new_headers.remove("host");
-    new_headers.remove("cf-connecting-ip");
-    new_headers.remove("cf-ipcountry");
-    new_headers.remove("x-forwarded-for");
-    new_headers.remove("content-length"); // 让 reqwest 重新计算
-
-    // 4. 发送请求
-    // .body(req_bytes) 这里传入的是 bytes::Bytes 类型
-    // 编译器看到这里会非常高兴，因为 reqwest::Body 实现 From<Bytes>
-    let request_builder = state.client
-        .request(method, target_uri)
-        .headers(new_headers)
-        .body(req_bytes); 
-
-    match request_builder.send().await {
-        Ok(response) => {
-            let status = response.status();
-            let mut resp_headers = HeaderMap::new();
-            for (k, v) in response.headers() {
-                resp_headers.insert(k, v.clone());
+fn init_command(args: InitArgs) {
+    if !args.force && std::path::Path::new(&args.output).exists() {
+        exit_with(format!("{} already exists, use --force to overwrite", args.output));
+    }
+    let cli = Cli::command();
+    let mut out = String::from("# Aizasy Gateway 配置模板，取消注释并修改需要的项\n");
+    for arg in cli.get_arguments() {
+        let Some(env) = arg.get_env() else { continue };
+        out.push('\n');
+        if let Some(help) = arg.get_help() {
+            for line in help.to_string().lines() {
+                out.push_str(&format!("# {}\n", line));
             }
+        }
+        let default: Vec<String> = arg
+            .get_default_values()
+            .iter()
+            .map(|v| v.to_string_lossy().into_owned())
+            .collect();
+        out.push_str(&format!("# {}={}\n", env.to_string_lossy(), default.join(",")));
+    }
+    std::fs::write(&args.output, out).unwrap_or_else(|e| exit_with(format!("{}: {}", args.output, e)));
+    println!("✅ Wrote {}", args.output);
+}
+
+#[cfg(feature = "devtools")]
+async fn replay_command(args: ReplayArgs) {
+    tracing_subscriber::fmt().with_env_filter(tracing_subscriber::EnvFilter::new("info")).init();
+    let app = record::replay_router(args.dir.as_ref(), !args.fast).unwrap_or_else(|e| exit_with(e));
+    let listener = tokio::net::TcpListener::bind(args.listen)
+        .await
+        .unwrap_or_else(|e| exit_with(format!("bind {}: {}", args.listen, e)));
+    info!("📼 Replaying {} on http://{}", args.dir, args.listen);
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await
+        .unwrap_or_else(|e| exit_with(e));
+}
 
-            // 5. 响应流式转发 (Streaming)
-            // 这里我们保持流式，以支持打字机效果
-            let resp_stream = response.bytes_stream();
-            let body = Body::from_stream(resp_stream);
-            
-            (status, resp_headers, body).into_response()
+// --- 配置检查 ---
+// `check` 子命令 / --check-config：先逐项解析规则、文件、证书和代理，收集全部错误一次报告 (配置文件的错误带行号)，
+// 只检查不组装：不打开存储、不创建日志文件、不起后台任务；--probe-upstreams 时另外组装一个只有上游和代理的网关，向每个上游发一次请求。
+// 有任何错误都以非零状态退出，坏配置在 CI 里就能拦下来，不用等到上线。
+
+async fn check_command(mut args: Args, probe: bool) {
+    let errors = check_config(&mut args).await;
+    if !errors.is_empty() {
+        for e in &errors {
+            eprintln!("❌ {}", e);
         }
-        Err(e) => {
-            error!("Proxy error: {}", e);
-            (StatusCode::BAD_GATEWAY, format!("Gateway Error: {}", e)).into_response()
+        exit_with(format!("{} configuration error(s)", errors.len()));
+    }
+    if probe {
+        let gateway = probe_gateway(&args).await.unwrap_or_else(|e| exit_with(e));
+        let mut unreachable = 0;
+        for (url, result) in gateway.probe_upstreams(Duration::from_secs(args.health_check_timeout_secs)).await {
+            match result {
+                Ok(elapsed) => println!("✅ {} reachable ({} ms)", url, elapsed.as_millis()),
+                Err(e) => {
+                    unreachable += 1;
+                    eprintln!("❌ {}: {}", url, e);
+                }
+            }
+        }
+        if unreachable > 0 {
+            exit_with(format!("{} upstream(s) unreachable", unreachable));
         }
     }
+    println!("✅ Configuration OK");
 }
+
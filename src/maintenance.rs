@@ -0,0 +1,78 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::{Arc, RwLock};
+use tracing::info;
+
+use crate::AppState;
+
+// --- 维护模式 ---
+// 打开后代理路由统一返回 503 + Retry-After，health / metrics / 管理 API 不受影响，
+// 用于计划内的上游或 key 维护。可以启动时用 --maintenance 打开，也可以通过管理 API 随时切换。
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaintenanceState {
+    pub enabled: bool,
+    pub message: String,
+    pub retry_after_secs: u64,
+}
+
+impl Default for MaintenanceState {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            message: "The gateway is under maintenance, please retry later".to_string(),
+            retry_after_secs: 300,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Maintenance {
+    state: RwLock<MaintenanceState>,
+}
+
+impl Maintenance {
+    pub fn new(state: MaintenanceState) -> Self {
+        Self { state: RwLock::new(state) }
+    }
+
+    pub fn get(&self) -> MaintenanceState {
+        self.state.read().unwrap().clone()
+    }
+
+    pub fn set(&self, state: MaintenanceState) {
+        info!(
+            "🚧 Maintenance mode {}",
+            if state.enabled { "enabled" } else { "disabled" }
+        );
+        *self.state.write().unwrap() = state;
+    }
+}
+
+pub(crate) async fn guard(State(state): State<Arc<AppState>>, req: Request, next: Next) -> Response {
+    let current = state.maintenance.get();
+    if !current.enabled {
+        return next.run(req).await;
+    }
+    state.metrics.inc("aizasy_maintenance_rejected_total", &[]);
+    let body = json!({
+        "error": {
+            "code": 503,
+            "message": current.message,
+            "status": "UNAVAILABLE",
+        }
+    });
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [(header::RETRY_AFTER, current.retry_after_secs.to_string())],
+        Json(body),
+    )
+        .into_response()
+}
@@ -0,0 +1,161 @@
+use async_trait::async_trait;
+use reqwest::{header::HeaderName, header::HeaderValue, Client};
+use serde::Serialize;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+use crate::ledger::{LedgerEntry, LedgerHook};
+
+// --- 计量推送 ---
+// 每条账本记录转成计量事件，攒够一批 (或到了刷新间隔) 后 POST 到外部计费系统。
+// 失败按指数退避重试，重试耗尽后丢弃这一批 (账本里仍有原始记录，可以用导出补齐)。
+// 每条事件带 idempotency_key，接收方据此去重，重试不会重复计费。
+
+// 推送端处理不过来时最多缓存这么多条，超出直接丢弃
+const QUEUE_CAPACITY: usize = 10_000;
+
+#[derive(Debug, Clone)]
+pub struct MeteringConfig {
+    pub url: String,
+    /// 例如 ("authorization", "Bearer xxx")
+    pub auth_header: Option<(HeaderName, HeaderValue)>,
+    pub batch_size: usize,
+    pub flush_interval: Duration,
+    pub max_retries: u32,
+}
+
+impl MeteringConfig {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            auth_header: None,
+            batch_size: 100,
+            flush_interval: Duration::from_secs(10),
+            max_retries: 3,
+        }
+    }
+
+    /// 解析 `Name: value` 形式的请求头
+    pub fn parse_header(spec: &str) -> Result<(HeaderName, HeaderValue), String> {
+        let (name, value) = spec
+            .split_once(':')
+            .ok_or_else(|| "header must be 'Name: value'".to_string())?;
+        let name = HeaderName::from_bytes(name.trim().as_bytes()).map_err(|e| e.to_string())?;
+        let mut value = HeaderValue::from_str(value.trim()).map_err(|e| e.to_string())?;
+        value.set_sensitive(true);
+        Ok((name, value))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MeteringEvent {
+    pub idempotency_key: String,
+    /// unix 毫秒
+    pub timestamp: u64,
+    pub client: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    pub model: String,
+    pub prompt_tokens: u64,
+    pub output_tokens: u64,
+    pub cost: f64,
+}
+
+impl From<&LedgerEntry> for MeteringEvent {
+    fn from(entry: &LedgerEntry) -> Self {
+        Self {
+            idempotency_key: format!("{}-{}", entry.ts, entry.request_id),
+            timestamp: entry.ts,
+            client: entry.client.clone(),
+            tenant: entry.tenant.clone(),
+            project: entry.project.clone(),
+            model: entry.model.clone(),
+            prompt_tokens: entry.prompt_tokens,
+            output_tokens: entry.output_tokens,
+            cost: entry.cost,
+        }
+    }
+}
+
+pub struct MeteringPush {
+    queue: mpsc::Sender<MeteringEvent>,
+}
+
+impl MeteringPush {
+    /// 启动后台推送任务
+    pub fn start(config: MeteringConfig) -> Self {
+        let (queue, rx) = mpsc::channel(QUEUE_CAPACITY);
+        tokio::spawn(run(config, rx));
+        Self { queue }
+    }
+}
+
+#[async_trait]
+impl LedgerHook for MeteringPush {
+    async fn on_entry(&self, entry: &LedgerEntry) {
+        if self.queue.try_send(MeteringEvent::from(entry)).is_err() {
+            warn!("📡 Metering queue full, dropping event for {}", entry.client);
+        }
+    }
+}
+
+async fn run(config: MeteringConfig, mut rx: mpsc::Receiver<MeteringEvent>) {
+    let client = Client::new();
+    let batch_size = config.batch_size.max(1);
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut ticker = tokio::time::interval(config.flush_interval);
+    loop {
+        tokio::select! {
+            event = rx.recv() => {
+                let Some(event) = event else { break };
+                batch.push(event);
+                if batch.len() < batch_size {
+                    continue;
+                }
+            }
+            _ = ticker.tick() => {
+                if batch.is_empty() {
+                    continue;
+                }
+            }
+        }
+        send_with_retry(&client, &config, &batch).await;
+        batch.clear();
+    }
+    if !batch.is_empty() {
+        send_with_retry(&client, &config, &batch).await;
+    }
+}
+
+async fn send_with_retry(client: &Client, config: &MeteringConfig, batch: &[MeteringEvent]) {
+    let mut backoff = Duration::from_millis(500);
+    for attempt in 0..=config.max_retries {
+        let mut request = client.post(&config.url).json(batch);
+        if let Some((name, value)) = &config.auth_header {
+            request = request.header(name, value);
+        }
+        let error = match request.send().await {
+            Ok(response) if response.status().is_success() => {
+                debug!("📡 Pushed {} metering events", batch.len());
+                return;
+            }
+            // 4xx (除了 429) 重试也不会成功
+            Ok(response) if response.status().is_client_error() && response.status().as_u16() != 429 => {
+                warn!("📡 Metering endpoint rejected {} events: HTTP {}", batch.len(), response.status());
+                return;
+            }
+            Ok(response) => format!("HTTP {}", response.status()),
+            Err(e) => e.to_string(),
+        };
+        if attempt == config.max_retries {
+            warn!("📡 Dropping {} metering events after {} attempts: {}", batch.len(), attempt + 1, error);
+            return;
+        }
+        debug!("📡 Metering push failed ({}), retrying in {:?}", error, backoff);
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(Duration::from_secs(30));
+    }
+}
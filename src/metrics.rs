@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+// --- 轻量指标注册表 ---
+// 不引入完整的 metrics 生态，只维护带标签的计数器、少量仪表 (gauge) 和固定分桶的耗时直方图，
+// 按 Prometheus 文本格式输出。
+// 关闭 `metrics` feature 时计数调用为空操作，调用方无需区分。
+#[derive(Default)]
+pub struct Metrics {
+    // key: 指标名, value: (标签串 -> 数值)
+    counters: Mutex<BTreeMap<String, BTreeMap<String, u64>>>,
+    gauges: Mutex<BTreeMap<String, BTreeMap<String, u64>>>,
+    histograms: Mutex<BTreeMap<String, BTreeMap<String, Histogram>>>,
+}
+
+/// 直方图的桶上界 (秒)，覆盖非流式请求到长时间的流式生成
+const BUCKETS: [f64; 12] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0];
+
+#[derive(Default)]
+struct Histogram {
+    // 每个桶 (不累计) 的计数，最后一个是 +Inf
+    buckets: [u64; BUCKETS.len() + 1],
+    sum: f64,
+    count: u64,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn inc(&self, name: &str, labels: &[(&str, &str)]) {
+        self.add(name, labels, 1);
+    }
+
+    #[cfg(not(feature = "metrics"))]
+    pub fn add(&self, _name: &str, _labels: &[(&str, &str)], _value: u64) {}
+
+    #[cfg(feature = "metrics")]
+    pub fn add(&self, name: &str, labels: &[(&str, &str)], value: u64) {
+        let series = format_labels(labels);
+        let mut counters = self.counters.lock().unwrap();
+        *counters
+            .entry(name.to_string())
+            .or_default()
+            .entry(series)
+            .or_insert(0) += value;
+    }
+
+    #[cfg(not(feature = "metrics"))]
+    pub fn set(&self, _name: &str, _labels: &[(&str, &str)], _value: u64) {}
+
+    /// 设置仪表的当前值
+    #[cfg(feature = "metrics")]
+    pub fn set(&self, name: &str, labels: &[(&str, &str)], value: u64) {
+        let series = format_labels(labels);
+        let mut gauges = self.gauges.lock().unwrap();
+        gauges.entry(name.to_string()).or_default().insert(series, value);
+    }
+
+    #[cfg(not(feature = "metrics"))]
+    pub fn observe(&self, _name: &str, _labels: &[(&str, &str)], _seconds: f64) {}
+
+    /// 往直方图里记一次耗时 (秒)
+    #[cfg(feature = "metrics")]
+    pub fn observe(&self, name: &str, labels: &[(&str, &str)], seconds: f64) {
+        let series = format_labels(labels);
+        let mut histograms = self.histograms.lock().unwrap();
+        let histogram = histograms.entry(name.to_string()).or_default().entry(series).or_default();
+        let bucket = BUCKETS.iter().position(|le| seconds <= *le).unwrap_or(BUCKETS.len());
+        histogram.buckets[bucket] += 1;
+        histogram.sum += seconds;
+        histogram.count += 1;
+    }
+
+    /// 渲染为 Prometheus exposition 文本
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for (kind, metrics) in [("counter", &self.counters), ("gauge", &self.gauges)] {
+            let metrics = metrics.lock().unwrap();
+            for (name, series) in metrics.iter() {
+                let _ = writeln!(out, "# TYPE {} {}", name, kind);
+                for (labels, value) in series {
+                    let _ = writeln!(out, "{}{} {}", name, labels, value);
+                }
+            }
+        }
+        let histograms = self.histograms.lock().unwrap();
+        for (name, series) in histograms.iter() {
+            let _ = writeln!(out, "# TYPE {} histogram", name);
+            for (labels, histogram) in series {
+                // 标签串形如 {a="1"}，le 接在已有标签后面
+                let inner = labels.trim_start_matches('{').trim_end_matches('}');
+                let sep = if inner.is_empty() { "" } else { "," };
+                let mut cumulative = 0;
+                for (i, count) in histogram.buckets.iter().enumerate() {
+                    cumulative += count;
+                    let le = BUCKETS.get(i).map(|le| le.to_string()).unwrap_or_else(|| "+Inf".to_string());
+                    let _ = writeln!(out, "{}_bucket{{{}{}le=\"{}\"}} {}", name, inner, sep, le, cumulative);
+                }
+                let _ = writeln!(out, "{}_sum{} {}", name, labels, histogram.sum);
+                let _ = writeln!(out, "{}_count{} {}", name, labels, histogram.count);
+            }
+        }
+        out
+    }
+}
+
+#[cfg(feature = "metrics")]
+fn format_labels(labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let inner: Vec<String> = labels
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, v.replace('\\', "\\\\").replace('"', "\\\"")))
+        .collect();
+    format!("{{{}}}", inner.join(","))
+}
@@ -0,0 +1,143 @@
+use axum::body::Bytes;
+use axum::http::{HeaderMap, HeaderValue, Method};
+use futures_util::StreamExt;
+use reqwest::Client;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tracing::debug;
+
+use crate::sanitize::{redact_path, redact_text, sanitize_path};
+use crate::AppState;
+
+// --- 流量镜像 ---
+// 把一部分请求原样复制一份发给影子上游 (新区域、换了代理的出口等)，用真实流量验证之后再切换。
+// 镜像请求在后台发出，响应读完丢弃，成败都不影响客户端；发的是第一次尝试的上游请求
+// (插件改写之后)，换 key / 重试不会重复镜像。生产上游的凭据 (x-goog-api-key、Authorization、
+// ?key= 等) 发出前全部去掉，影子上游需要鉴权时用 --mirror-key 单独配置。
+// 按比例均匀抽样 (不是随机)，同时在途的镜像请求有上限，影子上游变慢时多出来的直接丢弃，不会堆积。
+
+#[derive(Debug, Clone)]
+pub struct MirrorConfig {
+    /// 影子上游，例如 https://shadow.example.com
+    pub target: String,
+    /// 镜像比例 (0..=100)
+    pub percent: f64,
+    /// 访问影子上游走的代理，不设置时和主上游用同一个客户端
+    pub proxy: Option<String>,
+    /// 单个镜像请求的超时 (含读完响应体)
+    pub timeout: Duration,
+    /// 同时在途的镜像请求上限
+    pub max_inflight: usize,
+    /// 发给影子上游的 API key (x-goog-api-key)；不设置时镜像请求不带任何凭据
+    pub key: Option<String>,
+}
+
+impl MirrorConfig {
+    pub fn new(target: impl Into<String>, percent: f64) -> Self {
+        Self {
+            target: target.into(),
+            percent,
+            proxy: None,
+            timeout: Duration::from_secs(60),
+            max_inflight: 64,
+            key: None,
+        }
+    }
+}
+
+pub(crate) struct Mirror {
+    pub(crate) target: String,
+    #[cfg(feature = "admin")]
+    pub(crate) percent: f64,
+    ratio: f64,
+    client: Option<Client>,
+    pub(crate) key: Option<HeaderValue>,
+    timeout: Duration,
+    slots: Arc<Semaphore>,
+    seen: AtomicU64,
+}
+
+impl Mirror {
+    pub(crate) fn new(config: MirrorConfig, client: Option<Client>) -> Result<Self, String> {
+        let key = match &config.key {
+            Some(key) => Some(HeaderValue::from_str(key.trim()).map_err(|_| "mirror key is not a valid header value".to_string())?),
+            None => None,
+        };
+        Ok(Self {
+            target: config.target.trim_end_matches('/').to_string(),
+            #[cfg(feature = "admin")]
+            percent: config.percent.clamp(0.0, 100.0),
+            ratio: config.percent.clamp(0.0, 100.0) / 100.0,
+            client,
+            key,
+            timeout: config.timeout,
+            slots: Arc::new(Semaphore::new(config.max_inflight.max(1))),
+            seen: AtomicU64::new(0),
+        })
+    }
+
+    /// 第 n 个请求是否镜像：累计应镜像的个数跨过整数时镜像，比例为 p 时每 1/p 个请求恰好一个
+    fn sampled(&self) -> bool {
+        let n = self.seen.fetch_add(1, Ordering::Relaxed) as f64;
+        ((n + 1.0) * self.ratio).floor() > (n * self.ratio).floor()
+    }
+}
+
+// 主上游和其他厂商的鉴权头，query 里的 key= 由 sanitize_path 去掉
+const CREDENTIAL_HEADERS: &[&str] = &["x-goog-api-key", "authorization", "x-api-key", "api-key"];
+
+/// 按比例把这次上游请求复制一份发给影子上游；立即返回
+pub(crate) fn send(state: &Arc<AppState>, method: &Method, path: &str, headers: &HeaderMap, body: &Bytes) {
+    let Some(mirror) = &state.mirror else {
+        return;
+    };
+    if !mirror.sampled() {
+        return;
+    }
+    let Ok(permit) = mirror.slots.clone().try_acquire_owned() else {
+        debug!("🪞 Mirror is saturated, dropping {} {}", method, redact_path(path));
+        state.metrics.inc("aizasy_mirror_requests_total", &[("result", "dropped")]);
+        return;
+    };
+    let mut headers = headers.clone();
+    for name in CREDENTIAL_HEADERS {
+        headers.remove(*name);
+    }
+    if let Some(key) = &mirror.key {
+        headers.insert("x-goog-api-key", key.clone());
+    }
+    let client = mirror.client.as_ref().unwrap_or(&state.client);
+    let request = client
+        .request(method.clone(), format!("{}{}", mirror.target, sanitize_path(path)))
+        .headers(headers)
+        .body(body.clone())
+        .timeout(mirror.timeout);
+    let state = state.clone();
+    let path = redact_path(path);
+    tokio::spawn(async move {
+        let _permit = permit;
+        let result = async {
+            let response = request.send().await?;
+            let status = response.status();
+            // 读完响应体，连接才能放回连接池复用
+            let mut body = response.bytes_stream();
+            while let Some(chunk) = body.next().await {
+                chunk?;
+            }
+            Ok::<_, reqwest::Error>(status)
+        }
+        .await;
+        match result {
+            Ok(status) => {
+                debug!("🪞 Mirrored {} -> {}", path, status);
+                state.metrics.inc("aizasy_mirror_requests_total", &[("result", "ok")]);
+            }
+            Err(e) => {
+                debug!("🪞 Mirror of {} failed: {}", path, redact_text(&e.to_string()));
+                state.metrics.inc("aizasy_mirror_requests_total", &[("result", "error")]);
+            }
+        }
+    });
+}
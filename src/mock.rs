@@ -0,0 +1,232 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use futures_util::stream;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+// --- 内置 Mock 上游 ---
+// 模拟 Gemini REST API 的最小子集，用于集成测试和无 key 的客户端开发。
+
+#[derive(Clone, Debug)]
+pub struct MockConfig {
+    /// 返回响应头前的固定延迟
+    pub latency: Duration,
+    /// 流式响应的分块数
+    pub chunks: usize,
+    /// 流式响应每块之间的间隔
+    pub chunk_interval: Duration,
+    /// 按比例注入错误 (0.0 ~ 1.0)
+    pub error_rate: f64,
+    /// 注入错误时返回的状态码
+    pub error_status: StatusCode,
+    /// 流式响应在输出这么多块后中断 (模拟断流)
+    pub drop_after: Option<usize>,
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        Self {
+            latency: Duration::ZERO,
+            chunks: 5,
+            chunk_interval: Duration::from_millis(100),
+            error_rate: 0.0,
+            error_status: StatusCode::TOO_MANY_REQUESTS,
+            drop_after: None,
+        }
+    }
+}
+
+/// 构建 mock 上游的 Router
+pub fn router(config: MockConfig) -> Router {
+    Router::new()
+        .route("/v1beta/models", get(list_models))
+        .route("/v1/models", get(list_models))
+        .route("/v1beta/models/:action", post(model_action).get(get_model))
+        .route("/v1/models/:action", post(model_action).get(get_model))
+        .with_state(Arc::new(config))
+}
+
+/// 在 127.0.0.1 的随机端口上启动 mock 上游，返回监听地址
+pub async fn spawn(config: MockConfig) -> std::io::Result<SocketAddr> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let app = router(config);
+    tokio::spawn(async move {
+        let _ = axum::serve(listener, app).await;
+    });
+    Ok(addr)
+}
+
+fn status_name(status: StatusCode) -> &'static str {
+    match status.as_u16() {
+        400 => "INVALID_ARGUMENT",
+        403 => "PERMISSION_DENIED",
+        404 => "NOT_FOUND",
+        429 => "RESOURCE_EXHAUSTED",
+        503 => "UNAVAILABLE",
+        504 => "DEADLINE_EXCEEDED",
+        _ => "INTERNAL",
+    }
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response {
+    let body = json!({
+        "error": { "code": status.as_u16(), "message": message, "status": status_name(status) }
+    });
+    (status, Json(body)).into_response()
+}
+
+/// 统一处理延迟和错误注入
+async fn preamble(config: &MockConfig) -> Option<Response> {
+    if !config.latency.is_zero() {
+        tokio::time::sleep(config.latency).await;
+    }
+    if config.error_rate > 0.0 && rand::random::<f64>() < config.error_rate {
+        return Some(error_response(config.error_status, "Injected error from mock upstream"));
+    }
+    None
+}
+
+async fn list_models(State(config): State<Arc<MockConfig>>) -> Response {
+    if let Some(err) = preamble(&config).await {
+        return err;
+    }
+    Json(json!({
+        "models": [
+            model_info("gemini-2.0-flash"),
+            model_info("gemini-1.5-pro"),
+            model_info("text-embedding-004"),
+        ]
+    }))
+    .into_response()
+}
+
+fn model_info(name: &str) -> Value {
+    json!({
+        "name": format!("models/{}", name),
+        "displayName": name,
+        "supportedGenerationMethods": ["generateContent", "streamGenerateContent", "countTokens", "embedContent"],
+    })
+}
+
+async fn get_model(State(config): State<Arc<MockConfig>>, Path(action): Path<String>) -> Response {
+    if let Some(err) = preamble(&config).await {
+        return err;
+    }
+    Json(model_info(&action)).into_response()
+}
+
+async fn model_action(
+    State(config): State<Arc<MockConfig>>,
+    Path(action): Path<String>,
+    Query(query): Query<HashMap<String, String>>,
+    body: Bytes,
+) -> Response {
+    let Some((model, method)) = action.split_once(':') else {
+        return error_response(StatusCode::NOT_FOUND, "Unknown model action");
+    };
+    if let Some(err) = preamble(&config).await {
+        return err;
+    }
+
+    // 粗略地把请求体长度当作 prompt token 数的依据
+    let prompt_tokens = (body.len() / 4).max(1);
+
+    match method {
+        "generateContent" => {
+            let text = mock_text(config.chunks);
+            Json(candidate(model, &text, Some(usage(prompt_tokens, config.chunks)))).into_response()
+        }
+        "streamGenerateContent" => {
+            let sse = query.get("alt").map(|a| a == "sse").unwrap_or(false);
+            stream_response(config, model.to_string(), prompt_tokens, sse)
+        }
+        "countTokens" => Json(json!({ "totalTokens": prompt_tokens })).into_response(),
+        "embedContent" => {
+            let values: Vec<f64> = (0..8).map(|i| (i as f64 * 0.125) - 0.5).collect();
+            Json(json!({ "embedding": { "values": values } })).into_response()
+        }
+        _ => error_response(StatusCode::NOT_FOUND, "Unknown model method"),
+    }
+}
+
+fn mock_text(words: usize) -> String {
+    (0..words).map(|i| format!("mock{} ", i)).collect()
+}
+
+pub(crate) fn usage(prompt: usize, candidates: usize) -> Value {
+    json!({
+        "promptTokenCount": prompt,
+        "candidatesTokenCount": candidates,
+        "totalTokenCount": prompt + candidates,
+    })
+}
+
+pub(crate) fn candidate(model: &str, text: &str, usage: Option<Value>) -> Value {
+    let mut value = json!({
+        "candidates": [{
+            "content": { "parts": [{ "text": text }], "role": "model" },
+            "index": 0,
+        }],
+        "modelVersion": model,
+    });
+    if let Some(usage) = usage {
+        value["candidates"][0]["finishReason"] = json!("STOP");
+        value["usageMetadata"] = usage;
+    }
+    value
+}
+
+fn stream_response(config: Arc<MockConfig>, model: String, prompt_tokens: usize, sse: bool) -> Response {
+    let total = config.chunks.max(1);
+    let emit = config.drop_after.map(|n| n.min(total)).unwrap_or(total);
+    let interval = config.chunk_interval;
+
+    let chunks = stream::unfold(0usize, move |i| {
+        let model = model.clone();
+        async move {
+            if i == emit && emit < total {
+                // 模拟断流：直接以错误终止，连接被异常关闭
+                return Some((Err(std::io::Error::other("mock stream dropped")), usize::MAX));
+            }
+            if i >= emit {
+                // 非 SSE 模式下 JSON 数组要闭合
+                if !sse && i == total {
+                    return Some((Ok(Bytes::from_static(b"]")), i + 1));
+                }
+                return None;
+            }
+            if i > 0 && !interval.is_zero() {
+                tokio::time::sleep(interval).await;
+            }
+
+            let last = i + 1 == total;
+            let usage = last.then(|| usage(prompt_tokens, total));
+            let payload = candidate(&model, &format!("mock{} ", i), usage).to_string();
+            let frame = if sse {
+                format!("data: {}\r\n\r\n", payload)
+            } else if i == 0 {
+                format!("[{}", payload)
+            } else {
+                format!("\r\n,{}", payload)
+            };
+            Some((Ok(Bytes::from(frame)), i + 1))
+        }
+    });
+
+    let content_type = if sse { "text/event-stream" } else { "application/json" };
+    (
+        [(header::CONTENT_TYPE, content_type)],
+        Body::from_stream(chunks),
+    )
+        .into_response()
+}
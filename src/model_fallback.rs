@@ -0,0 +1,63 @@
+use axum::http::StatusCode;
+use std::collections::HashMap;
+
+// --- 模型降级链 ---
+// 请求的模型返回 404 / 429 / 503 时，换成配置的下一个模型重试 (如 gemini-2.5-flash -> gemini-2.5-flash-lite)，
+// 路径和请求体里的模型名一起改写。重试发生在 key 池、多上游的故障转移都用完之后；
+// 请求体已经缓冲在内存里，可以原样重放。实际返回结果的模型写在 x-aizasy-routed-model 响应头里。
+
+/// 触发降级的上游状态码
+const FALLBACK_STATUSES: &[StatusCode] = &[
+    StatusCode::NOT_FOUND,
+    StatusCode::TOO_MANY_REQUESTS,
+    StatusCode::SERVICE_UNAVAILABLE,
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct ModelFallbacks {
+    // 请求的模型 -> 依次尝试的备选模型
+    chains: HashMap<String, Vec<String>>,
+}
+
+impl ModelFallbacks {
+    /// 解析 `MODEL=FALLBACK[,FALLBACK...]` 列表
+    pub fn parse(specs: &[String]) -> Result<Self, String> {
+        let mut chains = HashMap::new();
+        for spec in specs {
+            let (model, fallbacks) = spec
+                .split_once('=')
+                .ok_or_else(|| format!("model fallback '{}' must be MODEL=FALLBACK,...", spec))?;
+            let fallbacks: Vec<String> = fallbacks
+                .split(',')
+                .map(|m| m.trim().trim_start_matches("models/").to_string())
+                .filter(|m| !m.is_empty())
+                .collect();
+            let model = model.trim().trim_start_matches("models/");
+            if model.is_empty() || fallbacks.is_empty() {
+                return Err(format!("invalid model fallback '{}'", spec));
+            }
+            chains.insert(model.to_string(), fallbacks);
+        }
+        Ok(Self { chains })
+    }
+
+    pub fn len(&self) -> usize {
+        self.chains.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chains.is_empty()
+    }
+
+    /// `requested` 的降级链里下一个没试过的模型；状态码不触发降级时返回 None
+    pub(crate) fn next(&self, requested: &str, status: StatusCode, tried: &[String]) -> Option<&str> {
+        if !FALLBACK_STATUSES.contains(&status) {
+            return None;
+        }
+        self.chains
+            .get(requested)?
+            .iter()
+            .find(|m| !tried.contains(m))
+            .map(String::as_str)
+    }
+}
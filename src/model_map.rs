@@ -0,0 +1,129 @@
+use async_trait::async_trait;
+use axum::{
+    body::Bytes,
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::Response,
+};
+use serde_json::Value;
+use std::collections::HashMap;
+use tracing::debug;
+
+use crate::model_router::{replace_model, ROUTED_MODEL_HEADER};
+use crate::plugin::{GatewayPlugin, RequestContext};
+use crate::usage;
+
+// --- 模型名映射 ---
+// 固定的 名字 -> 具体模型版本 对照表 (如 gemini-pro-latest=gemini-2.5-pro)，转发前同时改写
+// 路径里的 /models/<名字> 和请求体里引用模型的字段 (batchEmbedContents 的 requests[].model、
+// countTokens 的 generateContentRequest.model 等)。换模型只需改网关配置，不用重新发布客户端。
+// 在按成本选模型之前执行，映射的目标也可以是一个成本别名。
+
+/// 本次请求被映射成的模型
+#[derive(Debug, Clone)]
+pub struct MappedModel {
+    pub from: String,
+    pub to: String,
+}
+
+pub struct ModelMap {
+    map: HashMap<String, String>,
+}
+
+impl ModelMap {
+    /// 解析 `FROM=TO` 列表
+    pub fn parse(specs: &[String]) -> Result<Self, String> {
+        let mut map = HashMap::new();
+        for spec in specs {
+            let (from, to) = spec
+                .split_once('=')
+                .ok_or_else(|| format!("model mapping '{}' must be FROM=TO", spec))?;
+            let (from, to) = (strip_models(from.trim()), strip_models(to.trim()));
+            if from.is_empty() || to.is_empty() || from.contains([':', '/']) || to.contains([':', '/']) {
+                return Err(format!("invalid model mapping '{}'", spec));
+            }
+            map.insert(from.to_string(), to.to_string());
+        }
+        Ok(Self { map })
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    fn lookup(&self, model: &str) -> Option<&str> {
+        self.map.get(strip_models(model)).map(String::as_str)
+    }
+}
+
+// 模型字段写成 models/X 或 X，保持原来的写法
+fn map_field(value: &mut Value, map: &impl Fn(&str) -> Option<String>) -> bool {
+    let Some(model) = value.as_str() else {
+        return false;
+    };
+    let Some(to) = map(strip_models(model)) else {
+        return false;
+    };
+    *value = Value::String(if model.starts_with("models/") { format!("models/{}", to) } else { to });
+    true
+}
+
+/// 改写请求体里引用模型的字段；`map` 的参数是去掉 models/ 前缀的模型名，
+/// 返回 None 表示不改。没有字段被改写时返回 None
+pub(crate) fn map_body_models(body: &Bytes, map: impl Fn(&str) -> Option<String>) -> Option<Bytes> {
+    let mut json: Value = serde_json::from_slice(body).ok()?;
+    let mut changed = false;
+    if let Some(model) = json.get_mut("model") {
+        changed |= map_field(model, &map);
+    }
+    if let Some(model) = json.pointer_mut("/generateContentRequest/model") {
+        changed |= map_field(model, &map);
+    }
+    if let Some(requests) = json.get_mut("requests").and_then(Value::as_array_mut) {
+        for request in requests {
+            if let Some(model) = request.get_mut("model") {
+                changed |= map_field(model, &map);
+            }
+        }
+    }
+    changed.then(|| serde_json::to_vec(&json).ok().map(Bytes::from)).flatten()
+}
+
+fn strip_models(model: &str) -> &str {
+    model.strip_prefix("models/").unwrap_or(model)
+}
+
+#[async_trait]
+impl GatewayPlugin for ModelMap {
+    fn name(&self) -> &str {
+        "model-map"
+    }
+
+    async fn on_request(&self, ctx: &mut RequestContext) -> Result<(), Response> {
+        if let Some(from) = usage::model_from_path(ctx.uri.path()).map(str::to_string) {
+            if let Some(to) = self.lookup(&from).map(str::to_string) {
+                if let Some(uri) = replace_model(&ctx.uri, &from, &to) {
+                    debug!("🗺️  Model {} -> {}", from, to);
+                    ctx.uri = uri;
+                    ctx.extensions.insert(MappedModel { from, to });
+                }
+            }
+        }
+        if let Some(body) = map_body_models(&ctx.body, |model| self.lookup(model).map(str::to_string)) {
+            ctx.body = body;
+        }
+        Ok(())
+    }
+
+    async fn on_upstream_response(&self, ctx: &RequestContext, _status: StatusCode, headers: &mut HeaderMap) {
+        // 成本路由在后面执行，映射目标是成本别名时它会用选中的模型覆盖这个头
+        if let Some(mapped) = ctx.extensions.get::<MappedModel>() {
+            if let Ok(value) = HeaderValue::from_str(&mapped.to) {
+                headers.insert(ROUTED_MODEL_HEADER, value);
+            }
+        }
+    }
+}
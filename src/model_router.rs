@@ -0,0 +1,216 @@
+use async_trait::async_trait;
+use axum::{
+    http::{HeaderMap, HeaderValue, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+use crate::ledger::PriceTable;
+use crate::plugin::{GatewayPlugin, Outcome, RequestContext};
+use crate::usage;
+
+// --- 按成本选择模型 ---
+// 客户端请求一个别名 (例如 /v1beta/models/auto:generateContent)，网关在别名的候选模型里
+// 选出满足质量等级、当前可用、且按价格表最便宜的一个，改写路径后再转发。
+// 上游对某个模型返回 429 / 403 时，该模型冷却一段时间，期间优先选其他候选。
+
+/// 请求头：本次请求要求的最低质量等级，覆盖别名上的默认值 (转发前移除)
+pub const TIER_HEADER: &str = "x-aizasy-quality-tier";
+/// 响应头：实际使用的模型
+pub const ROUTED_MODEL_HEADER: &str = "x-aizasy-routed-model";
+
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub model: String,
+    pub tier: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct ModelAlias {
+    pub alias: String,
+    /// 按配置顺序；价格相同时靠前的优先
+    pub candidates: Vec<Candidate>,
+    /// 默认要求的最低质量等级
+    pub min_tier: u8,
+}
+
+impl ModelAlias {
+    /// 解析 `ALIAS=MODEL[@TIER],MODEL[@TIER],...;tier=N`，未写等级的模型为 0 级
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut parts = spec.split(';');
+        let head = parts.next().unwrap_or("");
+        let (alias, models) = head
+            .split_once('=')
+            .ok_or_else(|| format!("model alias '{}' must be ALIAS=MODEL,...", spec))?;
+        let alias = alias.trim();
+        if alias.is_empty() || alias.contains([':', '/']) {
+            return Err(format!("invalid alias name in '{}'", spec));
+        }
+        let mut candidates = Vec::new();
+        for model in models.split(',').map(str::trim).filter(|m| !m.is_empty()) {
+            let candidate = match model.split_once('@') {
+                Some((model, tier)) => Candidate {
+                    model: model.to_string(),
+                    tier: tier.parse().map_err(|_| format!("invalid tier '{}' in '{}'", tier, spec))?,
+                },
+                None => Candidate {
+                    model: model.to_string(),
+                    tier: 0,
+                },
+            };
+            candidates.push(candidate);
+        }
+        if candidates.is_empty() {
+            return Err(format!("model alias '{}' has no candidates", spec));
+        }
+        let mut min_tier = 0;
+        for part in parts.map(str::trim).filter(|p| !p.is_empty()) {
+            match part.split_once('=') {
+                Some(("tier", tier)) => {
+                    min_tier = tier.parse().map_err(|_| format!("invalid tier '{}' in '{}'", tier, spec))?
+                }
+                _ => return Err(format!("unknown model alias option '{}'", part)),
+            }
+        }
+        Ok(Self {
+            alias: alias.to_string(),
+            candidates,
+            min_tier,
+        })
+    }
+}
+
+/// 本次请求选中的模型，放进请求 extensions
+#[derive(Debug, Clone)]
+pub struct RoutedModel {
+    pub alias: String,
+    pub model: String,
+}
+
+pub struct CostRouter {
+    aliases: HashMap<String, ModelAlias>,
+    prices: PriceTable,
+    cooldown: Duration,
+    // 模型 -> 冷却结束时间
+    cooling: Mutex<HashMap<String, Instant>>,
+}
+
+impl CostRouter {
+    pub fn new(aliases: Vec<ModelAlias>, prices: PriceTable, cooldown: Duration) -> Self {
+        Self {
+            aliases: aliases.into_iter().map(|a| (a.alias.clone(), a)).collect(),
+            prices,
+            cooldown,
+            cooling: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // 输入 + 输出单价之和；没有价格的模型排在最后
+    fn unit_cost(&self, model: &str) -> f64 {
+        self.prices
+            .price(model)
+            .map(|p| p.input + p.output)
+            .unwrap_or(f64::INFINITY)
+    }
+
+    fn is_cooling(&self, model: &str) -> bool {
+        let mut cooling = self.cooling.lock().unwrap();
+        match cooling.get(model) {
+            Some(until) if *until > Instant::now() => true,
+            Some(_) => {
+                cooling.remove(model);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// 选出满足等级的最便宜模型；候选全部在冷却时仍按价格选，交给上游决定
+    pub fn select<'a>(&self, alias: &'a ModelAlias, min_tier: u8) -> Option<&'a str> {
+        let eligible: Vec<&Candidate> = alias.candidates.iter().filter(|c| c.tier >= min_tier).collect();
+        let by_cost = |a: &&&Candidate, b: &&&Candidate| self.unit_cost(&a.model).total_cmp(&self.unit_cost(&b.model));
+        eligible
+            .iter()
+            .filter(|c| !self.is_cooling(&c.model))
+            .min_by(by_cost)
+            .or_else(|| eligible.iter().min_by(by_cost))
+            .map(|c| c.model.as_str())
+    }
+}
+
+// 把路径里的 /models/<from> 换成 /models/<to>
+pub(crate) fn replace_model(uri: &Uri, from: &str, to: &str) -> Option<Uri> {
+    let path_and_query = uri.path_and_query()?.as_str();
+    let needle = format!("/models/{}", from);
+    let pos = path_and_query.find(&needle)?;
+    let rewritten = format!(
+        "{}/models/{}{}",
+        &path_and_query[..pos],
+        to,
+        &path_and_query[pos + needle.len()..]
+    );
+    rewritten.parse().ok()
+}
+
+#[async_trait]
+impl GatewayPlugin for CostRouter {
+    fn name(&self) -> &str {
+        "cost-router"
+    }
+
+    async fn on_request(&self, ctx: &mut RequestContext) -> Result<(), Response> {
+        let requested_tier = ctx
+            .headers
+            .remove(TIER_HEADER)
+            .and_then(|v| v.to_str().ok().and_then(|v| v.trim().parse::<u8>().ok()));
+        let Some(alias) = usage::model_from_path(ctx.uri.path()).and_then(|m| self.aliases.get(m)) else {
+            return Ok(());
+        };
+        let min_tier = requested_tier.unwrap_or(alias.min_tier);
+        let Some(model) = self.select(alias, min_tier) else {
+            let body = json!({
+                "error": {
+                    "code": 400,
+                    "message": format!("No model in alias '{}' satisfies quality tier {}", alias.alias, min_tier),
+                    "status": "INVALID_ARGUMENT",
+                }
+            });
+            return Err((StatusCode::BAD_REQUEST, Json(body)).into_response());
+        };
+        if let Some(uri) = replace_model(&ctx.uri, &alias.alias, model) {
+            debug!("🧭 {} -> {} (tier >= {})", alias.alias, model, min_tier);
+            ctx.uri = uri;
+            ctx.extensions.insert(RoutedModel {
+                alias: alias.alias.clone(),
+                model: model.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    async fn on_upstream_response(&self, ctx: &RequestContext, _status: StatusCode, headers: &mut HeaderMap) {
+        if let Some(routed) = ctx.extensions.get::<RoutedModel>() {
+            if let Ok(value) = HeaderValue::from_str(&routed.model) {
+                headers.insert(ROUTED_MODEL_HEADER, value);
+            }
+        }
+    }
+
+    fn on_complete(&self, ctx: &RequestContext, outcome: &Outcome) {
+        let Some(routed) = ctx.extensions.get::<RoutedModel>() else {
+            return;
+        };
+        if matches!(outcome.status, Some(StatusCode::TOO_MANY_REQUESTS | StatusCode::FORBIDDEN)) {
+            info!("🧭 {} unavailable ({:?}), cooling down for {:?}", routed.model, outcome.status, self.cooldown);
+            self.cooling
+                .lock()
+                .unwrap()
+                .insert(routed.model.clone(), Instant::now() + self.cooldown);
+        }
+    }
+}
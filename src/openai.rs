@@ -0,0 +1,1280 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures_util::{stream, StreamExt};
+use http_body_util::{BodyExt, LengthLimitError, Limited};
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
+
+use crate::client_ip::ClientIp;
+use crate::events::RequestId;
+use crate::providers;
+use crate::proxy;
+use crate::sanitize::redact_path;
+use crate::AppState;
+
+// --- OpenAI 兼容层 ---
+// 接受 OpenAI 格式的 /v1/chat/completions、/v1/embeddings、/v1/models，
+// 转成 Gemini 的 generateContent / streamGenerateContent / batchEmbedContents / models 调用，
+// 再把响应 (包括流式 SSE) 转回 OpenAI 格式，LiteLLM、LangChain 等客户端不用改就能接入。
+// 转换后的请求完整走一遍代理流程，模型别名、key 池、计费等照常生效。
+// `Authorization: Bearer <key>` 会被当作 Gemini key 转成 x-goog-api-key。
+// 注意开启后 GET /v1/models 由兼容层处理，Gemini v1 的模型列表请改用 /v1beta/models。
+// model 属于配置的其他厂商 (见 providers) 时不做转换，原样转发给那个厂商。
+
+fn unix_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+fn openai_error(status: StatusCode, message: impl Into<String>) -> Response {
+    let kind = match status.as_u16() {
+        400 | 404 | 413 | 422 => "invalid_request_error",
+        401 => "authentication_error",
+        403 => "permission_error",
+        429 => "rate_limit_error",
+        _ => "api_error",
+    };
+    let body = json!({
+        "error": {
+            "message": message.into(),
+            "type": kind,
+            "param": null,
+            "code": null,
+        }
+    });
+    (status, Json(body)).into_response()
+}
+
+// 读完上游响应体，超过 buffer_limit 或读取失败时返回 502
+async fn read_upstream(state: &AppState, response: Response) -> Result<Bytes, Response> {
+    axum::body::to_bytes(response.into_body(), state.buffer_limit())
+        .await
+        .map_err(|e| openai_error(StatusCode::BAD_GATEWAY, format!("failed to read upstream response: {}", e)))
+}
+
+// 上游 / 网关的错误响应转成 OpenAI 的错误格式，状态码保持不变
+async fn translate_error(state: &AppState, response: Response) -> Response {
+    let status = response.status();
+    let body = match read_upstream(state, response).await {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+    let message = serde_json::from_slice::<Value>(&body)
+        .ok()
+        .and_then(|v| v.pointer("/error/message").and_then(Value::as_str).map(str::to_string))
+        .unwrap_or_else(|| String::from_utf8_lossy(&body).into_owned());
+    openai_error(status, message)
+}
+
+// OpenAI 客户端把 key 放在 Authorization 里，Gemini 用 x-goog-api-key
+fn gemini_headers(headers: &HeaderMap) -> HeaderMap {
+    let mut headers = headers.clone();
+    if let Some(token) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .and_then(|v| HeaderValue::from_str(v.trim()).ok())
+    {
+        if !headers.contains_key("x-goog-api-key") {
+            headers.insert("x-goog-api-key", token);
+        }
+    }
+    headers.remove(header::AUTHORIZATION);
+    headers.remove(header::ACCEPT);
+    headers.remove(header::CONTENT_LENGTH);
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    headers
+}
+
+fn gemini_path(model: &str, method: &str, query: Option<&str>) -> String {
+    let model = model.strip_prefix("models/").unwrap_or(model);
+    match query.filter(|q| !q.is_empty()) {
+        Some(query) => format!("/v1beta/models/{}:{}?{}", model, method, query),
+        None => format!("/v1beta/models/{}:{}", model, method),
+    }
+}
+
+/// model 属于其他厂商时原样交给代理流程，否则返回 None
+async fn passthrough(
+    state: &Arc<AppState>,
+    client_ip: std::net::IpAddr,
+    parts: &mut axum::http::request::Parts,
+    value: &Value,
+) -> Option<Response> {
+    let model = value.get("model").and_then(Value::as_str)?;
+    let provider = providers::for_model(&state.providers, model)?;
+    debug!("🔁 OpenAI request for {} passed through to {}", model, provider.name);
+    let uri = parts.uri.path_and_query().map(|p| p.as_str()).unwrap_or("/").to_string();
+    let headers = std::mem::take(&mut parts.headers);
+    let extensions = std::mem::take(&mut parts.extensions);
+    let body = Bytes::from(value.to_string());
+    Some(match proxy::dispatch(state, client_ip, Method::POST, &uri, headers, extensions, body).await {
+        Ok(response) => response,
+        Err(e) => openai_error(StatusCode::BAD_GATEWAY, e),
+    })
+}
+
+async fn read_json(state: &AppState, req: Request) -> Result<(axum::http::request::Parts, Value), Response> {
+    let (parts, body) = req.into_parts();
+    let body = match Limited::new(body, state.max_request_size).collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(e) if e.is::<LengthLimitError>() => {
+            state.metrics.inc("aizasy_body_too_large_total", &[("direction", "request")]);
+            return Err(openai_error(StatusCode::PAYLOAD_TOO_LARGE, "request body too large"));
+        }
+        Err(e) => return Err(openai_error(StatusCode::BAD_REQUEST, format!("failed to read body: {}", e))),
+    };
+    let value = serde_json::from_slice(&body)
+        .map_err(|e| openai_error(StatusCode::BAD_REQUEST, format!("invalid JSON body: {}", e)))?;
+    Ok((parts, value))
+}
+
+// --- 请求转换 ---
+
+// Gemini 的 schema 是 OpenAPI 子集，不认识这些 JSON Schema 字段
+fn clean_schema(schema: &Value) -> Value {
+    match schema {
+        Value::Object(obj) => Value::Object(
+            obj.iter()
+                .filter(|(k, _)| !matches!(k.as_str(), "$schema" | "additionalProperties" | "strict"))
+                .map(|(k, v)| (k.clone(), clean_schema(v)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(clean_schema).collect()),
+        other => other.clone(),
+    }
+}
+
+fn image_part(url: &str) -> Value {
+    // data:image/png;base64,xxxx
+    if let Some((meta, data)) = url.strip_prefix("data:").and_then(|rest| rest.split_once(',')) {
+        let mime = meta.strip_suffix(";base64").unwrap_or(meta);
+        return json!({ "inlineData": { "mimeType": mime, "data": data } });
+    }
+    let lower = url.to_ascii_lowercase();
+    let mime = if lower.ends_with(".png") {
+        "image/png"
+    } else if lower.ends_with(".webp") {
+        "image/webp"
+    } else if lower.ends_with(".gif") {
+        "image/gif"
+    } else {
+        "image/jpeg"
+    };
+    json!({ "fileData": { "mimeType": mime, "fileUri": url } })
+}
+
+fn content_parts(content: &Value) -> Result<Vec<Value>, String> {
+    match content {
+        Value::Null => Ok(Vec::new()),
+        Value::String(text) => Ok(vec![json!({ "text": text })]),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| match item.get("type").and_then(Value::as_str) {
+                Some("text") => Ok(json!({ "text": item.get("text").and_then(Value::as_str).unwrap_or("") })),
+                Some("image_url") => {
+                    let url = item
+                        .pointer("/image_url/url")
+                        .or_else(|| item.get("image_url"))
+                        .and_then(Value::as_str)
+                        .ok_or("image_url part needs a url")?;
+                    Ok(image_part(url))
+                }
+                Some("input_audio") => {
+                    let data = item.pointer("/input_audio/data").and_then(Value::as_str).unwrap_or("");
+                    let format = item.pointer("/input_audio/format").and_then(Value::as_str).unwrap_or("wav");
+                    Ok(json!({ "inlineData": { "mimeType": format!("audio/{}", format), "data": data } }))
+                }
+                other => Err(format!("unsupported content part type {:?}", other)),
+            })
+            .collect(),
+        _ => Err("message content must be a string or an array".to_string()),
+    }
+}
+
+fn text_of(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(items) => items
+            .iter()
+            .filter_map(|i| i.get("text").and_then(Value::as_str))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+// 相邻同角色的消息合并，并行工具调用的多个结果要放在同一个 content 里
+fn push_content(contents: &mut Vec<Value>, role: &str, parts: Vec<Value>) {
+    if parts.is_empty() {
+        return;
+    }
+    if let Some(last) = contents.last_mut() {
+        if last["role"] == role {
+            if let Some(existing) = last["parts"].as_array_mut() {
+                existing.extend(parts);
+                return;
+            }
+        }
+    }
+    contents.push(json!({ "role": role, "parts": parts }));
+}
+
+struct ChatRequest {
+    model: String,
+    stream: bool,
+    include_usage: bool,
+    body: Value,
+}
+
+fn chat_to_gemini(req: &Value) -> Result<ChatRequest, String> {
+    let model = req.get("model").and_then(Value::as_str).ok_or("model is required")?.to_string();
+    let messages = req.get("messages").and_then(Value::as_array).ok_or("messages is required")?;
+
+    let mut system = Vec::new();
+    let mut contents = Vec::new();
+    // tool_call_id -> 函数名，tool 消息里只有 id
+    let mut tool_names: HashMap<String, String> = HashMap::new();
+    for message in messages {
+        let role = message.get("role").and_then(Value::as_str).unwrap_or("user");
+        let content = message.get("content").unwrap_or(&Value::Null);
+        match role {
+            "system" | "developer" => system.push(json!({ "text": text_of(content) })),
+            "user" => push_content(&mut contents, "user", content_parts(content)?),
+            "assistant" => {
+                let mut parts = content_parts(content)?;
+                for call in message.get("tool_calls").and_then(Value::as_array).into_iter().flatten() {
+                    let name = call.pointer("/function/name").and_then(Value::as_str).unwrap_or_default();
+                    let arguments = call.pointer("/function/arguments").and_then(Value::as_str).unwrap_or("{}");
+                    let args: Value = serde_json::from_str(arguments).unwrap_or_else(|_| json!({}));
+                    if let Some(id) = call.get("id").and_then(Value::as_str) {
+                        tool_names.insert(id.to_string(), name.to_string());
+                    }
+                    parts.push(json!({ "functionCall": { "name": name, "args": args } }));
+                }
+                push_content(&mut contents, "model", parts);
+            }
+            "tool" | "function" => {
+                let name = message
+                    .get("tool_call_id")
+                    .and_then(Value::as_str)
+                    .and_then(|id| tool_names.get(id).cloned())
+                    .or_else(|| message.get("name").and_then(Value::as_str).map(str::to_string))
+                    .ok_or("tool message must reference a previous tool call")?;
+                let text = text_of(content);
+                let response = match serde_json::from_str::<Value>(&text) {
+                    Ok(Value::Object(obj)) => Value::Object(obj),
+                    _ => json!({ "content": text }),
+                };
+                let part = json!({ "functionResponse": { "name": name, "response": response } });
+                push_content(&mut contents, "user", vec![part]);
+            }
+            other => return Err(format!("unsupported message role '{}'", other)),
+        }
+    }
+
+    let mut config = Map::new();
+    let number = |key: &str| req.get(key).filter(|v| v.is_number()).cloned();
+    for (from, to) in [
+        ("temperature", "temperature"),
+        ("top_p", "topP"),
+        ("n", "candidateCount"),
+        ("presence_penalty", "presencePenalty"),
+        ("frequency_penalty", "frequencyPenalty"),
+        ("seed", "seed"),
+    ] {
+        if let Some(value) = number(from) {
+            config.insert(to.to_string(), value);
+        }
+    }
+    if let Some(max) = number("max_completion_tokens").or_else(|| number("max_tokens")) {
+        config.insert("maxOutputTokens".to_string(), max);
+    }
+    match req.get("stop") {
+        Some(Value::String(stop)) => {
+            config.insert("stopSequences".to_string(), json!([stop]));
+        }
+        Some(Value::Array(stops)) => {
+            config.insert("stopSequences".to_string(), Value::Array(stops.clone()));
+        }
+        _ => {}
+    }
+    match req.pointer("/response_format/type").and_then(Value::as_str) {
+        Some("json_object") => {
+            config.insert("responseMimeType".to_string(), json!("application/json"));
+        }
+        Some("json_schema") => {
+            config.insert("responseMimeType".to_string(), json!("application/json"));
+            if let Some(schema) = req.pointer("/response_format/json_schema/schema") {
+                config.insert("responseSchema".to_string(), clean_schema(schema));
+            }
+        }
+        _ => {}
+    }
+
+    let mut body = json!({ "contents": contents });
+    if !system.is_empty() {
+        body["systemInstruction"] = json!({ "parts": system });
+    }
+    if !config.is_empty() {
+        body["generationConfig"] = Value::Object(config);
+    }
+    let declarations: Vec<Value> = req
+        .get("tools")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|t| t.get("type").and_then(Value::as_str) == Some("function"))
+        .filter_map(|t| t.get("function"))
+        .map(|f| {
+            let mut decl = json!({ "name": f.get("name").cloned().unwrap_or(Value::Null) });
+            if let Some(description) = f.get("description") {
+                decl["description"] = description.clone();
+            }
+            if let Some(parameters) = f.get("parameters") {
+                decl["parameters"] = clean_schema(parameters);
+            }
+            decl
+        })
+        .collect();
+    if !declarations.is_empty() {
+        body["tools"] = json!([{ "functionDeclarations": declarations }]);
+    }
+    let choice = match req.get("tool_choice") {
+        Some(Value::String(mode)) if mode == "none" => Some(json!({ "mode": "NONE" })),
+        Some(Value::String(mode)) if mode == "auto" => Some(json!({ "mode": "AUTO" })),
+        Some(Value::String(mode)) if mode == "required" => Some(json!({ "mode": "ANY" })),
+        Some(choice @ Value::Object(_)) => choice
+            .pointer("/function/name")
+            .map(|name| json!({ "mode": "ANY", "allowedFunctionNames": [name] })),
+        _ => None,
+    };
+    if let Some(choice) = choice {
+        body["toolConfig"] = json!({ "functionCallingConfig": choice });
+    }
+
+    Ok(ChatRequest {
+        model,
+        stream: req.get("stream").and_then(Value::as_bool).unwrap_or(false),
+        include_usage: req.pointer("/stream_options/include_usage").and_then(Value::as_bool).unwrap_or(false),
+        body,
+    })
+}
+
+// --- 响应转换 ---
+
+fn finish_reason(reason: &str) -> &'static str {
+    match reason {
+        "STOP" => "stop",
+        "MAX_TOKENS" => "length",
+        "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII" | "IMAGE_SAFETY" => "content_filter",
+        _ => "stop",
+    }
+}
+
+fn usage(response: &Value) -> Option<Value> {
+    let meta = response.get("usageMetadata")?;
+    let prompt = meta.get("promptTokenCount").and_then(Value::as_u64).unwrap_or(0);
+    let completion = meta.get("candidatesTokenCount").and_then(Value::as_u64).unwrap_or(0)
+        + meta.get("thoughtsTokenCount").and_then(Value::as_u64).unwrap_or(0);
+    let total = meta.get("totalTokenCount").and_then(Value::as_u64).unwrap_or(prompt + completion);
+    Some(json!({ "prompt_tokens": prompt, "completion_tokens": completion, "total_tokens": total }))
+}
+
+// 一个 candidate 的文本 (跳过 thought) 和函数调用
+fn candidate_output(candidate: &Value) -> (String, Vec<(String, Value)>) {
+    let mut text = String::new();
+    let mut calls = Vec::new();
+    for part in candidate.pointer("/content/parts").and_then(Value::as_array).into_iter().flatten() {
+        if part.get("thought").and_then(Value::as_bool) == Some(true) {
+            continue;
+        }
+        if let Some(t) = part.get("text").and_then(Value::as_str) {
+            text.push_str(t);
+        }
+        if let Some(call) = part.get("functionCall") {
+            let name = call.get("name").and_then(Value::as_str).unwrap_or_default().to_string();
+            calls.push((name, call.get("args").cloned().unwrap_or_else(|| json!({}))));
+        }
+    }
+    (text, calls)
+}
+
+fn gemini_to_chat(response: &Value, id: &str, model: &str) -> Value {
+    let mut choices: Vec<Value> = response
+        .get("candidates")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .map(|(i, candidate)| {
+            let index = candidate.get("index").and_then(Value::as_u64).unwrap_or(i as u64);
+            let (text, calls) = candidate_output(candidate);
+            let mut message = json!({ "role": "assistant", "content": text });
+            let reason = if calls.is_empty() {
+                finish_reason(candidate.get("finishReason").and_then(Value::as_str).unwrap_or("STOP"))
+            } else {
+                let tool_calls: Vec<Value> = calls
+                    .into_iter()
+                    .enumerate()
+                    .map(|(j, (name, args))| {
+                        json!({
+                            "id": format!("call_{}_{}", index, j),
+                            "type": "function",
+                            "function": { "name": name, "arguments": args.to_string() },
+                        })
+                    })
+                    .collect();
+                message["tool_calls"] = Value::Array(tool_calls);
+                "tool_calls"
+            };
+            json!({ "index": index, "message": message, "finish_reason": reason })
+        })
+        .collect();
+    // 提示词被拦截时没有 candidate
+    if choices.is_empty() {
+        choices.push(json!({
+            "index": 0,
+            "message": { "role": "assistant", "content": "" },
+            "finish_reason": "content_filter",
+        }));
+    }
+    let mut out = json!({
+        "id": id,
+        "object": "chat.completion",
+        "created": unix_secs(),
+        "model": model,
+        "choices": choices,
+    });
+    if let Some(usage) = usage(response) {
+        out["usage"] = usage;
+    }
+    out
+}
+
+// 流式转换的状态：上游 SSE 一块一块进来，凑齐完整事件后转成 OpenAI chunk。
+// 缓冲的是原始字节，只对完整的事件做 UTF-8 解码，多字节字符被切在两块之间也不会变成乱码
+struct ChunkState {
+    id: String,
+    model: String,
+    created: u64,
+    include_usage: bool,
+    role_sent: bool,
+    // 每个 candidate 已经输出的工具调用数，用作 delta.tool_calls[].index
+    tool_calls: HashMap<u64, usize>,
+    usage: Option<Value>,
+    buf: Vec<u8>,
+    // buf 里这个位置之前已经找过事件分隔符，下一块进来时从这里接着找
+    scanned: usize,
+    // 一个事件最多缓冲这么多字节 (AppState::buffer_limit)
+    limit: usize,
+}
+
+// 下一个事件的结束位置和分隔符 (\n\n 或 \r\n\r\n) 的长度
+fn event_end(buf: &[u8], from: usize) -> Option<(usize, usize)> {
+    let mut i = from;
+    while let Some(offset) = buf.get(i..)?.iter().position(|b| *b == b'\n') {
+        i += offset;
+        match buf.get(i + 1..i + 3) {
+            Some([b'\n', _]) => return Some((i, 2)),
+            Some([b'\r', b'\n']) => return Some((i, 3)),
+            // 末尾只剩一个字节时还不能确定
+            None if buf.get(i + 1) == Some(&b'\n') => return Some((i, 2)),
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+impl ChunkState {
+    fn chunk(&self, choices: Value, usage: Option<Value>) -> Bytes {
+        let mut chunk = json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": choices,
+        });
+        if let Some(usage) = usage {
+            chunk["usage"] = usage;
+        }
+        Bytes::from(format!("data: {}\n\n", chunk))
+    }
+
+    fn event(&mut self, data: &str, out: &mut VecDeque<Bytes>) {
+        let Ok(response) = serde_json::from_str::<Value>(data) else {
+            return;
+        };
+        if let Some(usage) = usage(&response) {
+            self.usage = Some(usage);
+        }
+        for (i, candidate) in response.get("candidates").and_then(Value::as_array).into_iter().flatten().enumerate() {
+            let index = candidate.get("index").and_then(Value::as_u64).unwrap_or(i as u64);
+            let (text, calls) = candidate_output(candidate);
+            let mut delta = Map::new();
+            if !self.role_sent {
+                delta.insert("role".to_string(), json!("assistant"));
+            }
+            if !text.is_empty() {
+                delta.insert("content".to_string(), json!(text));
+            }
+            if !calls.is_empty() {
+                let seen = self.tool_calls.entry(index).or_default();
+                let deltas: Vec<Value> = calls
+                    .into_iter()
+                    .map(|(name, args)| {
+                        let n = *seen;
+                        *seen += 1;
+                        json!({
+                            "index": n,
+                            "id": format!("call_{}_{}", index, n),
+                            "type": "function",
+                            "function": { "name": name, "arguments": args.to_string() },
+                        })
+                    })
+                    .collect();
+                delta.insert("tool_calls".to_string(), Value::Array(deltas));
+            }
+            let reason = candidate.get("finishReason").and_then(Value::as_str).map(|r| {
+                if self.tool_calls.get(&index).is_some_and(|n| *n > 0) {
+                    "tool_calls"
+                } else {
+                    finish_reason(r)
+                }
+            });
+            if delta.is_empty() && reason.is_none() {
+                continue;
+            }
+            self.role_sent = true;
+            let choice = json!({ "index": index, "delta": delta, "finish_reason": reason });
+            out.push_back(self.chunk(json!([choice]), None));
+        }
+    }
+
+    // 取出缓冲区里所有完整的 SSE 事件；flush 时剩下的内容也当作一个事件。
+    // 不完整的事件超过 limit 时返回错误
+    fn drain(&mut self, out: &mut VecDeque<Bytes>, flush: bool) -> Result<(), String> {
+        let mut start = 0;
+        while let Some((end, delimiter)) = event_end(&self.buf, self.scanned.max(start)) {
+            let event = self.buf[start..end].to_vec();
+            self.dispatch(&event, out);
+            start = end + delimiter;
+        }
+        self.buf.drain(..start);
+        // 分隔符最长 3 字节，可能有一部分已经在缓冲区末尾
+        self.scanned = self.buf.len().saturating_sub(2);
+        if flush && !self.buf.is_empty() {
+            let rest = std::mem::take(&mut self.buf);
+            self.dispatch(&rest, out);
+            self.scanned = 0;
+        }
+        if self.buf.len() > self.limit {
+            return Err(format!("upstream event exceeds {} bytes", self.limit));
+        }
+        Ok(())
+    }
+
+    // 一个完整事件的 data 行交给 event
+    fn dispatch(&mut self, event: &[u8], out: &mut VecDeque<Bytes>) {
+        let event = String::from_utf8_lossy(event);
+        let data: Vec<&str> = event
+            .lines()
+            .filter_map(|l| l.trim_end_matches('\r').strip_prefix("data:"))
+            .map(str::trim_start)
+            .collect();
+        if !data.is_empty() {
+            self.event(&data.join("\n"), out);
+        }
+    }
+
+    fn finish(&mut self, out: &mut VecDeque<Bytes>) {
+        // flush 之后缓冲区是空的，不会超限
+        let _ = self.drain(out, true);
+        if self.include_usage {
+            let usage = self.usage.take().unwrap_or_else(|| json!({ "prompt_tokens": 0, "completion_tokens": 0, "total_tokens": 0 }));
+            out.push_back(self.chunk(json!([]), Some(usage)));
+        }
+        out.push_back(Bytes::from_static(b"data: [DONE]\n\n"));
+    }
+}
+
+fn translate_stream(response: Response, state: ChunkState) -> Response {
+    let upstream = response.into_body().into_data_stream();
+    let frames = stream::unfold(
+        (upstream, state, VecDeque::new(), false),
+        |(mut upstream, mut state, mut out, mut done)| async move {
+            loop {
+                if let Some(frame) = out.pop_front() {
+                    return Some((Ok::<_, std::io::Error>(frame), (upstream, state, out, done)));
+                }
+                if done {
+                    return None;
+                }
+                match upstream.next().await {
+                    Some(Ok(bytes)) => {
+                        state.buf.extend_from_slice(&bytes);
+                        if let Err(e) = state.drain(&mut out, false) {
+                            warn!("🔁 OpenAI stream aborted: {}", e);
+                            done = true;
+                            return Some((Err(std::io::Error::other(e)), (upstream, state, out, done)));
+                        }
+                    }
+                    Some(Err(e)) => {
+                        done = true;
+                        return Some((Err(std::io::Error::other(e)), (upstream, state, out, done)));
+                    }
+                    None => {
+                        done = true;
+                        state.finish(&mut out);
+                    }
+                }
+            }
+        },
+    );
+    (
+        [(header::CONTENT_TYPE, "text/event-stream"), (header::CACHE_CONTROL, "no-cache")],
+        Body::from_stream(frames),
+    )
+        .into_response()
+}
+
+// --- 处理函数 ---
+
+pub(crate) async fn chat_completions(
+    State(state): State<Arc<AppState>>,
+    ClientIp(client_ip): ClientIp,
+    req: Request,
+) -> Response {
+    let (mut parts, value) = match read_json(&state, req).await {
+        Ok(parsed) => parsed,
+        Err(response) => return response,
+    };
+    if let Some(response) = passthrough(&state, client_ip, &mut parts, &value).await {
+        return response;
+    }
+    let chat = match chat_to_gemini(&value) {
+        Ok(chat) => chat,
+        Err(e) => return openai_error(StatusCode::BAD_REQUEST, e),
+    };
+    let id = format!("chatcmpl-{}", parts.extensions.get::<RequestId>().map(|r| r.0).unwrap_or_default());
+    let (method, query) = if chat.stream {
+        let query = match parts.uri.query() {
+            Some(q) => format!("alt=sse&{}", q),
+            None => "alt=sse".to_string(),
+        };
+        ("streamGenerateContent", Some(query))
+    } else {
+        ("generateContent", parts.uri.query().map(str::to_string))
+    };
+    let uri = gemini_path(&chat.model, method, query.as_deref());
+    debug!("🔁 OpenAI chat -> {}", redact_path(&uri));
+    let headers = gemini_headers(&parts.headers);
+    let body = Bytes::from(chat.body.to_string());
+    let response = match proxy::dispatch(&state, client_ip, Method::POST, &uri, headers, parts.extensions, body).await {
+        Ok(response) => response,
+        Err(e) => return openai_error(StatusCode::BAD_GATEWAY, e),
+    };
+    if !response.status().is_success() {
+        return translate_error(&state, response).await;
+    }
+    if chat.stream {
+        let chunks = ChunkState {
+            id,
+            model: chat.model,
+            created: unix_secs(),
+            include_usage: chat.include_usage,
+            role_sent: false,
+            tool_calls: HashMap::new(),
+            usage: None,
+            buf: Vec::new(),
+            scanned: 0,
+            limit: state.buffer_limit(),
+        };
+        return translate_stream(response, chunks);
+    }
+    let body = match read_upstream(&state, response).await {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+    match serde_json::from_slice::<Value>(&body) {
+        Ok(gemini) => Json(gemini_to_chat(&gemini, &id, &chat.model)).into_response(),
+        Err(e) => openai_error(StatusCode::BAD_GATEWAY, format!("invalid upstream response: {}", e)),
+    }
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// encoding_format=base64：float32 小端序再做 base64 (openai-python 默认就用这种格式)
+fn base64_floats(values: &[Value]) -> String {
+    let bytes: Vec<u8> = values
+        .iter()
+        .flat_map(|v| (v.as_f64().unwrap_or(0.0) as f32).to_le_bytes())
+        .collect();
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+struct EmbeddingRequest {
+    model: String,
+    base64: bool,
+    body: Value,
+}
+
+fn embeddings_to_gemini(req: &Value) -> Result<EmbeddingRequest, String> {
+    let model = req.get("model").and_then(Value::as_str).ok_or("model is required")?;
+    let model = model.strip_prefix("models/").unwrap_or(model).to_string();
+    let inputs: Vec<&str> = match req.get("input") {
+        Some(Value::String(text)) => vec![text.as_str()],
+        Some(Value::Array(items)) if items.iter().all(Value::is_string) => items.iter().filter_map(Value::as_str).collect(),
+        _ => return Err("input must be a string or an array of strings".to_string()),
+    };
+    let requests: Vec<Value> = inputs
+        .iter()
+        .map(|text| {
+            let mut request = json!({
+                "model": format!("models/{}", model),
+                "content": { "parts": [{ "text": text }] },
+            });
+            if let Some(dimensions) = req.get("dimensions") {
+                request["outputDimensionality"] = dimensions.clone();
+            }
+            request
+        })
+        .collect();
+    Ok(EmbeddingRequest {
+        base64: req.get("encoding_format").and_then(Value::as_str) == Some("base64"),
+        body: json!({ "requests": requests }),
+        model,
+    })
+}
+
+fn gemini_to_embeddings(response: &Value, model: &str, base64: bool) -> Value {
+    let data: Vec<Value> = response
+        .get("embeddings")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .map(|(i, embedding)| {
+            let values = embedding.get("values").and_then(Value::as_array).cloned().unwrap_or_default();
+            let embedding = if base64 { json!(base64_floats(&values)) } else { Value::Array(values) };
+            json!({ "object": "embedding", "index": i, "embedding": embedding })
+        })
+        .collect();
+    json!({
+        "object": "list",
+        "data": data,
+        "model": model,
+        // batchEmbedContents 不返回 token 数
+        "usage": { "prompt_tokens": 0, "total_tokens": 0 },
+    })
+}
+
+fn gemini_to_models(response: &Value) -> Value {
+    let data: Vec<Value> = response
+        .get("models")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|m| m.get("name").and_then(Value::as_str))
+        .map(|name| {
+            json!({
+                "id": name.strip_prefix("models/").unwrap_or(name),
+                "object": "model",
+                "created": 0,
+                "owned_by": "google",
+            })
+        })
+        .collect();
+    json!({ "object": "list", "data": data })
+}
+
+pub(crate) async fn embeddings(
+    State(state): State<Arc<AppState>>,
+    ClientIp(client_ip): ClientIp,
+    req: Request,
+) -> Response {
+    let (mut parts, value) = match read_json(&state, req).await {
+        Ok(parsed) => parsed,
+        Err(response) => return response,
+    };
+    if let Some(response) = passthrough(&state, client_ip, &mut parts, &value).await {
+        return response;
+    }
+    let embed = match embeddings_to_gemini(&value) {
+        Ok(embed) => embed,
+        Err(e) => return openai_error(StatusCode::BAD_REQUEST, e),
+    };
+    let uri = gemini_path(&embed.model, "batchEmbedContents", parts.uri.query());
+    let body = Bytes::from(embed.body.to_string());
+    let headers = gemini_headers(&parts.headers);
+    let response = match proxy::dispatch(&state, client_ip, Method::POST, &uri, headers, parts.extensions, body).await {
+        Ok(response) => response,
+        Err(e) => return openai_error(StatusCode::BAD_GATEWAY, e),
+    };
+    if !response.status().is_success() {
+        return translate_error(&state, response).await;
+    }
+    let body = match read_upstream(&state, response).await {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+    let Ok(gemini) = serde_json::from_slice::<Value>(&body) else {
+        return openai_error(StatusCode::BAD_GATEWAY, "invalid upstream response");
+    };
+    Json(gemini_to_embeddings(&gemini, &embed.model, embed.base64)).into_response()
+}
+
+pub(crate) async fn models(State(state): State<Arc<AppState>>, ClientIp(client_ip): ClientIp, req: Request) -> Response {
+    let (parts, _) = req.into_parts();
+    let uri = match parts.uri.query() {
+        Some(q) => format!("/v1beta/models?pageSize=1000&{}", q),
+        None => "/v1beta/models?pageSize=1000".to_string(),
+    };
+    let headers = gemini_headers(&parts.headers);
+    let response = match proxy::dispatch(&state, client_ip, Method::GET, &uri, headers, parts.extensions, Bytes::new()).await {
+        Ok(response) => response,
+        Err(e) => return openai_error(StatusCode::BAD_GATEWAY, e),
+    };
+    if !response.status().is_success() {
+        return translate_error(&state, response).await;
+    }
+    let body = match read_upstream(&state, response).await {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+    let Ok(gemini) = serde_json::from_slice::<Value>(&body) else {
+        return openai_error(StatusCode::BAD_GATEWAY, "invalid upstream response");
+    };
+    Json(gemini_to_models(&gemini)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream_state(include_usage: bool) -> ChunkState {
+        ChunkState {
+            id: "chatcmpl-1".to_string(),
+            model: "gemini-2.0-flash".to_string(),
+            created: 0,
+            include_usage,
+            role_sent: false,
+            tool_calls: HashMap::new(),
+            usage: None,
+            buf: Vec::new(),
+            scanned: 0,
+            limit: 1024,
+        }
+    }
+
+    // 喂一块上游数据，返回这次产生的 chunk
+    fn feed(state: &mut ChunkState, bytes: &[u8]) -> Vec<Value> {
+        let mut out = VecDeque::new();
+        state.buf.extend_from_slice(bytes);
+        state.drain(&mut out, false).unwrap();
+        out.iter().map(frame).collect()
+    }
+
+    fn frame(bytes: &Bytes) -> Value {
+        let text = std::str::from_utf8(bytes).unwrap();
+        let data = text.strip_prefix("data: ").and_then(|t| t.strip_suffix("\n\n")).unwrap();
+        serde_json::from_str(data).unwrap()
+    }
+
+    #[test]
+    fn chat_request_maps_roles_and_tool_round_trip() {
+        let req = json!({
+            "model": "gemini-2.0-flash",
+            "messages": [
+                { "role": "system", "content": "Be brief." },
+                { "role": "developer", "content": [{ "type": "text", "text": "Answer in English." }] },
+                { "role": "user", "content": "Weather?" },
+                { "role": "assistant", "content": null, "tool_calls": [
+                    { "id": "call_1", "type": "function", "function": { "name": "weather", "arguments": "{\"city\":\"Paris\"}" } },
+                ] },
+                { "role": "tool", "tool_call_id": "call_1", "content": "{\"temp\":20}" },
+                { "role": "user", "content": [{ "type": "text", "text": "Thanks" }] },
+            ],
+        });
+        let chat = chat_to_gemini(&req).unwrap();
+        assert_eq!(chat.model, "gemini-2.0-flash");
+        assert!(!chat.stream);
+        assert_eq!(chat.body["systemInstruction"], json!({ "parts": [{ "text": "Be brief." }, { "text": "Answer in English." }] }));
+        // 工具结果和紧跟着的用户消息合并成一个 user content
+        assert_eq!(
+            chat.body["contents"],
+            json!([
+                { "role": "user", "parts": [{ "text": "Weather?" }] },
+                { "role": "model", "parts": [{ "functionCall": { "name": "weather", "args": { "city": "Paris" } } }] },
+                { "role": "user", "parts": [
+                    { "functionResponse": { "name": "weather", "response": { "temp": 20 } } },
+                    { "text": "Thanks" },
+                ] },
+            ])
+        );
+        assert!(chat.body.get("generationConfig").is_none());
+    }
+
+    #[test]
+    fn plain_text_tool_result_is_wrapped() {
+        let req = json!({
+            "model": "m",
+            "messages": [
+                { "role": "assistant", "tool_calls": [{ "id": "a", "function": { "name": "lookup", "arguments": "not json" } }] },
+                { "role": "tool", "tool_call_id": "a", "content": "42" },
+            ],
+        });
+        let contents = &chat_to_gemini(&req).unwrap().body["contents"];
+        assert_eq!(contents[0]["parts"][0]["functionCall"], json!({ "name": "lookup", "args": {} }));
+        assert_eq!(contents[1]["parts"][0]["functionResponse"]["response"], json!({ "content": "42" }));
+    }
+
+    #[test]
+    fn image_and_audio_parts() {
+        let req = json!({
+            "model": "m",
+            "messages": [{ "role": "user", "content": [
+                { "type": "image_url", "image_url": { "url": "data:image/png;base64,AAAA" } },
+                { "type": "image_url", "image_url": { "url": "https://example.com/cat.WEBP" } },
+                { "type": "input_audio", "input_audio": { "data": "BBBB", "format": "mp3" } },
+            ] }],
+        });
+        assert_eq!(
+            chat_to_gemini(&req).unwrap().body["contents"][0]["parts"],
+            json!([
+                { "inlineData": { "mimeType": "image/png", "data": "AAAA" } },
+                { "fileData": { "mimeType": "image/webp", "fileUri": "https://example.com/cat.WEBP" } },
+                { "inlineData": { "mimeType": "audio/mp3", "data": "BBBB" } },
+            ])
+        );
+    }
+
+    #[test]
+    fn generation_config_maps_sampling_n_and_stop() {
+        let req = json!({
+            "model": "m",
+            "messages": [{ "role": "user", "content": "hi" }],
+            "temperature": 0.2,
+            "top_p": 0.9,
+            "n": 3,
+            "seed": 7,
+            "max_tokens": 100,
+            "max_completion_tokens": 200,
+            "stop": "END",
+            // 不是数字的忽略
+            "presence_penalty": "high",
+        });
+        assert_eq!(
+            chat_to_gemini(&req).unwrap().body["generationConfig"],
+            json!({
+                "temperature": 0.2,
+                "topP": 0.9,
+                "candidateCount": 3,
+                "seed": 7,
+                "maxOutputTokens": 200,
+                "stopSequences": ["END"],
+            })
+        );
+
+        let req = json!({ "model": "m", "messages": [], "stop": ["a", "b"], "max_tokens": 50 });
+        let config = &chat_to_gemini(&req).unwrap().body["generationConfig"];
+        assert_eq!(config["stopSequences"], json!(["a", "b"]));
+        assert_eq!(config["maxOutputTokens"], 50);
+    }
+
+    #[test]
+    fn json_schema_response_format_is_cleaned() {
+        let req = json!({
+            "model": "m",
+            "messages": [],
+            "response_format": { "type": "json_schema", "json_schema": { "schema": {
+                "$schema": "http://json-schema.org/draft-07/schema#",
+                "type": "object",
+                "additionalProperties": false,
+                "properties": { "tags": { "type": "array", "items": [{ "type": "string", "additionalProperties": false }] } },
+            } } },
+        });
+        let config = &chat_to_gemini(&req).unwrap().body["generationConfig"];
+        assert_eq!(config["responseMimeType"], "application/json");
+        assert_eq!(
+            config["responseSchema"],
+            json!({ "type": "object", "properties": { "tags": { "type": "array", "items": [{ "type": "string" }] } } })
+        );
+    }
+
+    #[test]
+    fn tools_and_tool_choice() {
+        let req = json!({
+            "model": "m",
+            "messages": [],
+            "tools": [
+                { "type": "function", "function": {
+                    "name": "weather",
+                    "description": "Current weather",
+                    "strict": true,
+                    "parameters": { "type": "object", "additionalProperties": false, "properties": { "city": { "type": "string" } } },
+                } },
+                { "type": "code_interpreter" },
+            ],
+            "tool_choice": { "type": "function", "function": { "name": "weather" } },
+        });
+        let body = chat_to_gemini(&req).unwrap().body;
+        assert_eq!(
+            body["tools"],
+            json!([{ "functionDeclarations": [{
+                "name": "weather",
+                "description": "Current weather",
+                "parameters": { "type": "object", "properties": { "city": { "type": "string" } } },
+            }] }])
+        );
+        assert_eq!(body["toolConfig"], json!({ "functionCallingConfig": { "mode": "ANY", "allowedFunctionNames": ["weather"] } }));
+
+        for (choice, mode) in [("none", "NONE"), ("auto", "AUTO"), ("required", "ANY")] {
+            let req = json!({ "model": "m", "messages": [], "tool_choice": choice });
+            assert_eq!(chat_to_gemini(&req).unwrap().body["toolConfig"]["functionCallingConfig"]["mode"], mode);
+        }
+    }
+
+    #[test]
+    fn stream_options_are_read() {
+        let req = json!({ "model": "m", "messages": [], "stream": true, "stream_options": { "include_usage": true } });
+        let chat = chat_to_gemini(&req).unwrap();
+        assert!(chat.stream);
+        assert!(chat.include_usage);
+    }
+
+    #[test]
+    fn invalid_chat_requests_are_rejected() {
+        assert!(chat_to_gemini(&json!({ "messages": [] })).is_err());
+        assert!(chat_to_gemini(&json!({ "model": "m" })).is_err());
+        assert!(chat_to_gemini(&json!({ "model": "m", "messages": [{ "role": "narrator", "content": "x" }] })).is_err());
+        assert!(chat_to_gemini(&json!({ "model": "m", "messages": [{ "role": "tool", "tool_call_id": "nope", "content": "x" }] })).is_err());
+        assert!(chat_to_gemini(&json!({ "model": "m", "messages": [{ "role": "user", "content": [{ "type": "video" }] }] })).is_err());
+        assert!(chat_to_gemini(&json!({ "model": "m", "messages": [{ "role": "user", "content": 1 }] })).is_err());
+    }
+
+    #[test]
+    fn response_maps_text_finish_reason_and_usage() {
+        let gemini = json!({
+            "candidates": [
+                { "content": { "parts": [{ "text": "thinking...", "thought": true }, { "text": "Hello" }, { "text": " there" }] }, "finishReason": "MAX_TOKENS" },
+                { "index": 1, "content": { "parts": [{ "text": "no" }] }, "finishReason": "SAFETY" },
+            ],
+            "usageMetadata": { "promptTokenCount": 5, "candidatesTokenCount": 7, "thoughtsTokenCount": 3, "totalTokenCount": 15 },
+        });
+        let chat = gemini_to_chat(&gemini, "chatcmpl-1", "gemini-2.0-flash");
+        assert_eq!(chat["id"], "chatcmpl-1");
+        assert_eq!(chat["object"], "chat.completion");
+        assert_eq!(chat["model"], "gemini-2.0-flash");
+        assert_eq!(
+            chat["choices"],
+            json!([
+                { "index": 0, "message": { "role": "assistant", "content": "Hello there" }, "finish_reason": "length" },
+                { "index": 1, "message": { "role": "assistant", "content": "no" }, "finish_reason": "content_filter" },
+            ])
+        );
+        // 思考 token 算进 completion
+        assert_eq!(chat["usage"], json!({ "prompt_tokens": 5, "completion_tokens": 10, "total_tokens": 15 }));
+    }
+
+    #[test]
+    fn response_maps_function_calls() {
+        let gemini = json!({ "candidates": [{ "content": { "parts": [
+            { "functionCall": { "name": "weather", "args": { "city": "Paris" } } },
+            { "functionCall": { "name": "time" } },
+        ] }, "finishReason": "STOP" }] });
+        let chat = gemini_to_chat(&gemini, "id", "m");
+        assert_eq!(chat["choices"][0]["finish_reason"], "tool_calls");
+        assert_eq!(
+            chat["choices"][0]["message"]["tool_calls"],
+            json!([
+                { "id": "call_0_0", "type": "function", "function": { "name": "weather", "arguments": "{\"city\":\"Paris\"}" } },
+                { "id": "call_0_1", "type": "function", "function": { "name": "time", "arguments": "{}" } },
+            ])
+        );
+        assert!(chat.get("usage").is_none());
+    }
+
+    #[test]
+    fn blocked_prompt_has_a_filtered_choice() {
+        let chat = gemini_to_chat(&json!({ "promptFeedback": { "blockReason": "SAFETY" } }), "id", "m");
+        assert_eq!(chat["choices"], json!([{ "index": 0, "message": { "role": "assistant", "content": "" }, "finish_reason": "content_filter" }]));
+    }
+
+    #[test]
+    fn usage_total_defaults_to_sum() {
+        let usage = usage(&json!({ "usageMetadata": { "promptTokenCount": 2, "candidatesTokenCount": 3 } })).unwrap();
+        assert_eq!(usage, json!({ "prompt_tokens": 2, "completion_tokens": 3, "total_tokens": 5 }));
+        assert!(super::usage(&json!({})).is_none());
+    }
+
+    #[test]
+    fn stream_event_split_across_reads() {
+        let mut state = stream_state(false);
+        let event = b"data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"Hi\"}]}}]}\n\n";
+        let (head, tail) = event.split_at(20);
+        assert!(feed(&mut state, head).is_empty());
+        // 分隔符也被拆开
+        let (body, end) = tail.split_at(tail.len() - 1);
+        assert!(feed(&mut state, body).is_empty());
+        let chunks = feed(&mut state, end);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0]["object"], "chat.completion.chunk");
+        assert_eq!(chunks[0]["id"], "chatcmpl-1");
+        assert_eq!(chunks[0]["choices"], json!([{ "index": 0, "delta": { "role": "assistant", "content": "Hi" }, "finish_reason": null }]));
+
+        // role 只在第一块里出现
+        let chunks = feed(&mut state, b"data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"!\"}]},\"finishReason\":\"STOP\"}]}\r\n\r\n");
+        assert_eq!(chunks[0]["choices"][0]["delta"], json!({ "content": "!" }));
+        assert_eq!(chunks[0]["choices"][0]["finish_reason"], "stop");
+    }
+
+    #[test]
+    fn stream_keeps_multibyte_text_split_across_reads() {
+        let mut state = stream_state(false);
+        let event = "data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"你好\"}]}}]}\n\n".as_bytes();
+        let split = event.iter().position(|b| *b >= 0x80).unwrap() + 1;
+        assert!(feed(&mut state, &event[..split]).is_empty());
+        let chunks = feed(&mut state, &event[split..]);
+        assert_eq!(chunks[0]["choices"][0]["delta"]["content"], "你好");
+    }
+
+    #[test]
+    fn stream_tool_calls_are_indexed() {
+        let mut state = stream_state(false);
+        let call = |name: &str| format!("data: {{\"candidates\":[{{\"content\":{{\"parts\":[{{\"functionCall\":{{\"name\":\"{}\"}}}}]}}}}]}}\n\n", name);
+        let first = feed(&mut state, call("a").as_bytes());
+        let second = feed(&mut state, call("b").as_bytes());
+        assert_eq!(first[0]["choices"][0]["delta"]["tool_calls"][0]["index"], 0);
+        assert_eq!(second[0]["choices"][0]["delta"]["tool_calls"][0]["index"], 1);
+        assert_eq!(second[0]["choices"][0]["delta"]["tool_calls"][0]["id"], "call_0_1");
+        let done = feed(&mut state, b"data: {\"candidates\":[{\"finishReason\":\"STOP\"}]}\n\n");
+        assert_eq!(done[0]["choices"][0]["finish_reason"], "tool_calls");
+    }
+
+    #[test]
+    fn stream_finish_flushes_and_terminates() {
+        let mut state = stream_state(true);
+        // 上游自带的 [DONE] 和注释行都不是 JSON，直接忽略
+        assert!(feed(&mut state, b": keep-alive\n\ndata: [DONE]\n\n").is_empty());
+        // 最后一个事件没有结尾的空行
+        feed(&mut state, b"data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"end\"}]}}],");
+        state.buf.extend_from_slice(b"\"usageMetadata\":{\"promptTokenCount\":1,\"candidatesTokenCount\":2}}");
+
+        let mut out = VecDeque::new();
+        state.finish(&mut out);
+        assert_eq!(out.len(), 3);
+        assert_eq!(frame(&out[0])["choices"][0]["delta"]["content"], "end");
+        let usage = frame(&out[1]);
+        assert_eq!(usage["choices"], json!([]));
+        assert_eq!(usage["usage"], json!({ "prompt_tokens": 1, "completion_tokens": 2, "total_tokens": 3 }));
+        assert_eq!(out[2], Bytes::from_static(b"data: [DONE]\n\n"));
+    }
+
+    #[test]
+    fn stream_without_usage_option_only_sends_done() {
+        let mut state = stream_state(false);
+        let mut out = VecDeque::new();
+        state.finish(&mut out);
+        assert_eq!(out, VecDeque::from([Bytes::from_static(b"data: [DONE]\n\n")]));
+    }
+
+    #[test]
+    fn stream_rejects_oversized_events() {
+        let mut state = stream_state(false);
+        state.buf.extend_from_slice(&[b'x'; 2048]);
+        assert!(state.drain(&mut VecDeque::new(), false).is_err());
+    }
+
+    #[test]
+    fn multi_line_data_is_joined() {
+        let mut state = stream_state(false);
+        let chunks = feed(&mut state, b"data: {\"candidates\":\ndata: [{\"content\":{\"parts\":[{\"text\":\"ok\"}]}}]}\n\n");
+        assert_eq!(chunks[0]["choices"][0]["delta"]["content"], "ok");
+    }
+
+    #[test]
+    fn embeddings_request_mapping() {
+        let embed = embeddings_to_gemini(&json!({
+            "model": "models/text-embedding-004",
+            "input": ["a", "b"],
+            "dimensions": 256,
+            "encoding_format": "base64",
+        }))
+        .unwrap();
+        assert_eq!(embed.model, "text-embedding-004");
+        assert!(embed.base64);
+        assert_eq!(
+            embed.body,
+            json!({ "requests": [
+                { "model": "models/text-embedding-004", "content": { "parts": [{ "text": "a" }] }, "outputDimensionality": 256 },
+                { "model": "models/text-embedding-004", "content": { "parts": [{ "text": "b" }] }, "outputDimensionality": 256 },
+            ] })
+        );
+
+        let embed = embeddings_to_gemini(&json!({ "model": "m", "input": "one" })).unwrap();
+        assert!(!embed.base64);
+        assert_eq!(embed.body["requests"].as_array().unwrap().len(), 1);
+
+        assert!(embeddings_to_gemini(&json!({ "input": "x" })).is_err());
+        assert!(embeddings_to_gemini(&json!({ "model": "m", "input": [1, 2] })).is_err());
+    }
+
+    #[test]
+    fn embeddings_response_mapping() {
+        let gemini = json!({ "embeddings": [{ "values": [1.0, -2.0] }, { "values": [0.5] }] });
+        assert_eq!(
+            gemini_to_embeddings(&gemini, "m", false),
+            json!({
+                "object": "list",
+                "data": [
+                    { "object": "embedding", "index": 0, "embedding": [1.0, -2.0] },
+                    { "object": "embedding", "index": 1, "embedding": [0.5] },
+                ],
+                "model": "m",
+                "usage": { "prompt_tokens": 0, "total_tokens": 0 },
+            })
+        );
+        // float32 小端序：1.0 = 00 00 80 3f，-2.0 = 00 00 00 c0
+        let base64 = gemini_to_embeddings(&gemini, "m", true);
+        assert_eq!(base64["data"][0]["embedding"], "AACAPwAAAMA=");
+        assert_eq!(base64["data"][1]["embedding"], "AAAAPw==");
+    }
+
+    #[test]
+    fn models_are_listed_without_prefix() {
+        let gemini = json!({ "models": [{ "name": "models/gemini-2.0-flash" }, { "displayName": "nameless" }, { "name": "tuned" }] });
+        assert_eq!(
+            gemini_to_models(&gemini),
+            json!({ "object": "list", "data": [
+                { "id": "gemini-2.0-flash", "object": "model", "created": 0, "owned_by": "google" },
+                { "id": "tuned", "object": "model", "created": 0, "owned_by": "google" },
+            ] })
+        );
+    }
+
+    #[test]
+    fn gemini_path_keeps_query() {
+        assert_eq!(gemini_path("models/m", "generateContent", None), "/v1beta/models/m:generateContent");
+        assert_eq!(gemini_path("m", "streamGenerateContent", Some("alt=sse")), "/v1beta/models/m:streamGenerateContent?alt=sse");
+        assert_eq!(gemini_path("m", "embedContent", Some("")), "/v1beta/models/m:embedContent");
+    }
+}
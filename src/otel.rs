@@ -0,0 +1,152 @@
+use axum::http::{HeaderMap, Method, StatusCode};
+use tracing::Span;
+
+// --- 链路追踪 ---
+// 每个代理请求一个 server span ("proxy")，每次发往上游的尝试一个 client 子 span ("upstream")。
+// span 挂在请求上下文里，流式响应发完才结束，所以 server span 的时长覆盖整个响应。
+// span 用单独的 target、TRACE 级别创建，普通日志过滤器 (默认 info) 看不到它们，
+// 日志格式不受影响；开启 otel feature 并配置 --otlp-endpoint 后由 OTLP 导出。
+// 入站的 traceparent 作为 server span 的父节点，发往上游时换成 upstream span 的 traceparent。
+
+pub const TARGET: &str = "aizasy_gateway::otel";
+
+/// 请求级 span，放在 RequestContext.extensions 里，最后一个引用释放时结束；
+/// 插件可以在它下面创建自己的子 span
+#[derive(Clone)]
+pub struct RequestSpan(pub Span);
+
+pub(crate) fn request_span(method: &Method, path: &str, headers: &HeaderMap) -> Span {
+    let span = tracing::trace_span!(
+        target: TARGET,
+        "proxy",
+        otel.name = %format!("{} {}", method, path),
+        otel.kind = "server",
+        otel.status_code = tracing::field::Empty,
+        http.request.method = %method,
+        url.path = %path,
+        aizasy.request_id = tracing::field::Empty,
+        aizasy.client_ip = tracing::field::Empty,
+        http.response.status_code = tracing::field::Empty,
+    );
+    #[cfg(feature = "otel")]
+    propagation::set_parent(&span, headers);
+    #[cfg(not(feature = "otel"))]
+    let _ = headers;
+    span
+}
+
+/// 单次上游尝试；traceparent 写进发往上游的请求头
+pub(crate) fn upstream_span(method: &Method, target: &str, key: Option<&str>, headers: &mut HeaderMap) -> Span {
+    let span = tracing::trace_span!(
+        target: TARGET,
+        "upstream",
+        otel.name = %method,
+        otel.kind = "client",
+        otel.status_code = tracing::field::Empty,
+        http.request.method = %method,
+        server.address = %target,
+        aizasy.key = key.unwrap_or(""),
+        http.response.status_code = tracing::field::Empty,
+        error.message = tracing::field::Empty,
+    );
+    #[cfg(feature = "otel")]
+    propagation::inject(&span, headers);
+    #[cfg(not(feature = "otel"))]
+    let _ = headers;
+    span
+}
+
+/// 记录响应状态，5xx 标记为错误
+pub(crate) fn record_status(span: &Span, status: StatusCode) {
+    span.record("http.response.status_code", status.as_u16());
+    if status.is_server_error() {
+        span.record("otel.status_code", "ERROR");
+    }
+}
+
+pub(crate) fn record_error(span: &Span, error: &str) {
+    span.record("otel.status_code", "ERROR");
+    span.record("error.message", error);
+}
+
+#[cfg(feature = "otel")]
+mod propagation {
+    use axum::http::{HeaderMap, HeaderName, HeaderValue};
+    use opentelemetry::propagation::{Extractor, Injector};
+    use tracing::Span;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    struct HeaderExtractor<'a>(&'a HeaderMap);
+
+    impl Extractor for HeaderExtractor<'_> {
+        fn get(&self, key: &str) -> Option<&str> {
+            self.0.get(key).and_then(|v| v.to_str().ok())
+        }
+
+        fn keys(&self) -> Vec<&str> {
+            self.0.keys().map(|k| k.as_str()).collect()
+        }
+    }
+
+    struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+    impl Injector for HeaderInjector<'_> {
+        fn set(&mut self, key: &str, value: String) {
+            if let (Ok(name), Ok(value)) = (HeaderName::try_from(key), HeaderValue::from_str(&value)) {
+                self.0.insert(name, value);
+            }
+        }
+    }
+
+    // 使用全局 propagator：二进制里由 init 设置为 W3C TraceContext，嵌入时由宿主应用决定
+    pub(super) fn set_parent(span: &Span, headers: &HeaderMap) {
+        let parent = opentelemetry::global::get_text_map_propagator(|p| p.extract(&HeaderExtractor(headers)));
+        let _ = span.set_parent(parent);
+    }
+
+    pub(super) fn inject(span: &Span, headers: &mut HeaderMap) {
+        let cx = span.context();
+        opentelemetry::global::get_text_map_propagator(|p| p.inject_context(&cx, &mut HeaderInjector(headers)));
+    }
+}
+
+/// 构建 OTLP/HTTP 导出层；endpoint 只写到主机 (如 http://localhost:4318) 时自动补上 /v1/traces。
+/// 同时把全局 propagator 设为 W3C TraceContext。返回的 provider 需要一直持有 (比如交给
+/// `opentelemetry::global::set_tracer_provider`)，否则 span 不会导出
+#[cfg(feature = "otel")]
+pub fn layer<S>(
+    endpoint: &str,
+    service_name: &str,
+) -> Result<(impl tracing_subscriber::Layer<S>, opentelemetry_sdk::trace::SdkTracerProvider), String>
+where
+    S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+{
+    use opentelemetry::trace::TracerProvider;
+    use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+    use tracing_subscriber::filter::{LevelFilter, Targets};
+    use tracing_subscriber::Layer;
+
+    let endpoint = endpoint.trim_end_matches('/');
+    let has_path = endpoint.split_once("://").is_some_and(|(_, rest)| rest.contains('/'));
+    let endpoint = if has_path { endpoint.to_string() } else { format!("{}/v1/traces", endpoint) };
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let provider = opentelemetry_sdk::trace::SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(
+            opentelemetry_sdk::Resource::builder()
+                .with_service_name(service_name.to_string())
+                .build(),
+        )
+        .build();
+    opentelemetry::global::set_text_map_propagator(opentelemetry_sdk::propagation::TraceContextPropagator::new());
+    let tracer = provider.tracer("aizasy_gateway");
+    // 只导出网关自己的请求 span，不受 --log-level 影响
+    let layer = tracing_opentelemetry::layer()
+        .with_tracer(tracer)
+        .with_filter(Targets::new().with_target(TARGET, LevelFilter::TRACE));
+    Ok((layer, provider))
+}
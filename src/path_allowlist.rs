@@ -0,0 +1,130 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::json;
+
+// --- 上游路径白名单 ---
+// 配置后只转发路径匹配其中某条模式的请求，其余直接返回 403，不让客户端借网关的出口访问任意 Google 接口。
+// 检查的是最终发往上游的路径 (改写规则、路由前缀、插件都处理完之后，不含 query)，
+// 批量子请求和 OpenAI 兼容接口转换后的请求同样要匹配。
+// 模式里的 `*` 匹配任意字符 (包括 `/` 和 `:`)，其余字符精确匹配，例如
+//   */models/*:generateContent
+//   */models/*:streamGenerateContent
+//   /v1beta/models
+// 上游 URL 会把 `.`、`..` 段 (包括 `%2e%2e` 这种编码形式) 解析掉，`/v1beta/models/../files` 实际到的是
+// `/v1beta/files`；带这类路径段的请求一律不放行，不按收到的原样去匹配。
+
+#[derive(Debug, Clone)]
+pub struct PathAllowlist {
+    patterns: Vec<String>,
+}
+
+impl PathAllowlist {
+    pub fn new(patterns: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let patterns: Vec<String> = patterns.into_iter().map(|p| p.trim().to_string()).filter(|p| !p.is_empty()).collect();
+        if patterns.is_empty() {
+            return Err("path allowlist is empty".to_string());
+        }
+        Ok(Self { patterns })
+    }
+
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    pub fn allows(&self, path: &str) -> bool {
+        if has_dot_segment(path) {
+            return false;
+        }
+        self.patterns.iter().any(|pattern| matches(pattern.as_bytes(), path.as_bytes()))
+    }
+}
+
+// `\` 在 https URL 里同样被当作路径分隔符
+fn has_dot_segment(path: &str) -> bool {
+    path.split(['/', '\\']).any(|segment| {
+        let decoded = segment.to_ascii_lowercase().replace("%2e", ".");
+        decoded == "." || decoded == ".."
+    })
+}
+
+// `*` 通配；回溯到最近一个 `*`，最坏 O(模式长度 × 路径长度)
+fn matches(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            star = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&b| b == b'*')
+}
+
+pub(crate) fn reject() -> Response {
+    let body = json!({
+        "error": {
+            "code": 403,
+            "message": "this endpoint is not allowed through the gateway",
+            "status": "PERMISSION_DENIED",
+        }
+    });
+    (StatusCode::FORBIDDEN, Json(body)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowlist(patterns: &[&str]) -> PathAllowlist {
+        PathAllowlist::new(patterns.iter().map(|p| p.to_string())).unwrap()
+    }
+
+    #[test]
+    fn wildcard_matches_across_separators() {
+        let list = allowlist(&["*/models/*:generateContent", "/v1beta/models"]);
+        assert!(list.allows("/v1beta/models/gemini-2.0-flash:generateContent"));
+        assert!(list.allows("/v1/tunedModels/x/models/y:generateContent"));
+        assert!(list.allows("/v1beta/models"));
+        assert!(!list.allows("/v1beta/models/gemini-2.0-flash:streamGenerateContent"));
+        assert!(!list.allows("/v1beta/files"));
+        assert!(!list.allows("/v1beta/models/"));
+    }
+
+    #[test]
+    fn dot_segments_are_rejected() {
+        let list = allowlist(&["/v1beta/models/*"]);
+        assert!(list.allows("/v1beta/models/gemini-2.0-flash"));
+        assert!(!list.allows("/v1beta/models/../files"));
+        assert!(!list.allows("/v1beta/models/./../files"));
+        assert!(!list.allows("/v1beta/models/.."));
+    }
+
+    #[test]
+    fn encoded_dot_segments_are_rejected() {
+        let list = allowlist(&["/v1beta/models/*"]);
+        assert!(!list.allows("/v1beta/models/%2e%2e/files"));
+        assert!(!list.allows("/v1beta/models/%2E%2E/files"));
+        assert!(!list.allows("/v1beta/models/.%2e/files"));
+        assert!(!list.allows("/v1beta/models/%2e/files"));
+        assert!(!list.allows("/v1beta/models/..\\files"));
+        // 名字里带点的普通路径段不受影响
+        assert!(list.allows("/v1beta/models/gemini-1.5-pro..latest"));
+        assert!(list.allows("/v1beta/models/..."));
+    }
+
+    #[test]
+    fn empty_allowlist_is_an_error() {
+        assert!(PathAllowlist::new(vec![" ".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,508 @@
+use async_trait::async_trait;
+use axum::{
+    body::Bytes,
+    http::{Extensions, HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+};
+use futures_util::Stream;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use crate::events::{elapsed_ms, EventBus, GatewayEvent};
+
+// --- 插件系统 ---
+// 鉴权、改写、计量等功能以插件形式挂到 proxy_handler 的固定钩子上，按注册顺序执行。
+
+/// 单个请求在各个钩子之间共享的上下文
+pub struct RequestContext {
+    /// 网关内唯一的请求编号，和生命周期事件里的 id 一致
+    pub id: u64,
+    pub method: Method,
+    /// 发往上游前的 path + query，插件可以改写
+    pub uri: Uri,
+    /// 发往上游的请求头 (已经过网关清洗)
+    pub headers: HeaderMap,
+    /// 已完整读取的请求体，插件可以替换
+    pub body: Bytes,
+    pub client_ip: IpAddr,
+    /// 入站请求的 extensions (GeoIP、签名校验结果等)
+    pub extensions: Extensions,
+    pub started_at: Instant,
+}
+
+/// 最终选定的上游地址 (不含路径)，proxy_handler 在发出请求前放进 RequestContext.extensions
+#[derive(Debug, Clone)]
+pub struct UpstreamTarget(pub String);
+
+/// 请求结束时的汇总信息
+#[derive(Debug, Clone)]
+pub struct Outcome {
+    /// 上游状态码；连接失败时为 None
+    pub status: Option<StatusCode>,
+    pub bytes_out: u64,
+    pub duration: Duration,
+    /// 上游错误或客户端中途断开
+    pub error: Option<String>,
+}
+
+/// on_chunk 的返回值
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChunkAction {
+    Continue,
+    /// 立即中断响应流 (客户端会看到连接异常结束)
+    Abort(String),
+}
+
+/// 流式响应体的增量改写器，由 [`GatewayPlugin::stream_transformer`] 为单个响应创建；
+/// 数据块可能在任意位置被切开，不完整的部分可以先留在改写器里，等后面的数据或 finish 时再输出
+pub trait ChunkTransformer: Send {
+    fn transform(&mut self, chunk: &Bytes) -> Bytes;
+
+    /// 上游响应体结束时调用，输出还留着的数据
+    fn finish(&mut self) -> Bytes {
+        Bytes::new()
+    }
+}
+
+/// 请求 / 响应拦截钩子 (也可以按 [`GatewayHook`] 这个名字引用)；用 [`GatewayBuilder::plugin`](crate::GatewayBuilder::plugin) 注册，
+/// 自定义策略不需要改 proxy_handler:
+///
+/// ```no_run
+/// use aizasy_gateway::{ChunkAction, Gateway, GatewayPlugin, RequestContext};
+/// use async_trait::async_trait;
+/// use axum::{body::Bytes, http::{HeaderMap, StatusCode}, response::{IntoResponse, Response}};
+///
+/// struct TeamPolicy;
+///
+/// #[async_trait]
+/// impl GatewayPlugin for TeamPolicy {
+///     fn name(&self) -> &str {
+///         "team-policy"
+///     }
+///
+///     async fn on_request(&self, ctx: &mut RequestContext) -> Result<(), Response> {
+///         if !ctx.headers.contains_key("x-team") {
+///             return Err((StatusCode::FORBIDDEN, "missing x-team").into_response());
+///         }
+///         ctx.headers.remove("x-team");
+///         Ok(())
+///     }
+///
+///     async fn on_upstream_response(&self, _ctx: &RequestContext, _status: StatusCode, headers: &mut HeaderMap) {
+///         headers.insert("x-policy", "team".parse().unwrap());
+///     }
+///
+///     fn on_chunk(&self, ctx: &RequestContext, chunk: &Bytes) -> ChunkAction {
+///         tracing::info!("request {} streamed {} bytes", ctx.id, chunk.len());
+///         ChunkAction::Continue
+///     }
+/// }
+///
+/// let gateway = Gateway::builder().plugin(TeamPolicy).build().expect("invalid gateway config");
+/// ```
+#[async_trait]
+pub trait GatewayPlugin: Send + Sync + 'static {
+    fn name(&self) -> &str;
+
+    /// 转发前调用；返回 Err(response) 直接把该响应交给客户端，不再请求上游
+    async fn on_request(&self, _ctx: &mut RequestContext) -> Result<(), Response> {
+        Ok(())
+    }
+
+    /// 拿到上游响应头后调用，可以修改响应头
+    async fn on_upstream_response(&self, _ctx: &RequestContext, _status: StatusCode, _headers: &mut HeaderMap) {}
+
+    /// 是否要改写这个请求的响应体 (headers 是上游响应头，可以按 Content-Type 判断)；有插件返回 true 时，
+    /// 非流式且未压缩的上游响应会先完整缓冲，再交给 on_response_body
+    fn wants_response_body(&self, _ctx: &RequestContext, _headers: &HeaderMap) -> bool {
+        false
+    }
+
+    /// 非流式响应体缓冲完成后调用 (在 on_upstream_response 之后)，可以替换响应体和响应头；
+    /// Content-Length 由网关按最终的响应体重新计算
+    async fn on_response_body(&self, _ctx: &RequestContext, _status: StatusCode, _headers: &mut HeaderMap, _body: &mut Bytes) {}
+
+    /// 没有被缓冲的 (流式) 响应开始转发前调用，返回 Some 时响应体逐块经过这个改写器再发给客户端
+    /// (多个插件按注册顺序串联，在 on_chunk 之后，on_chunk 看到的仍是上游原始数据块)；响应头里的 Content-Length 会被去掉
+    fn stream_transformer(&self, _ctx: &RequestContext, _status: StatusCode, _headers: &HeaderMap) -> Option<Box<dyn ChunkTransformer>> {
+        None
+    }
+
+    /// 每个响应数据块经过时调用，处在热路径上，只应做轻量观察
+    fn on_chunk(&self, _ctx: &RequestContext, _chunk: &Bytes) -> ChunkAction {
+        ChunkAction::Continue
+    }
+
+    /// 请求结束 (正常结束、上游失败或客户端断开) 时调用一次
+    fn on_complete(&self, _ctx: &RequestContext, _outcome: &Outcome) {}
+}
+
+/// [`GatewayPlugin`] 的别名：on_request 改写或拒绝请求，on_upstream_response 改响应头，on_chunk 观察流式数据块
+pub use self::GatewayPlugin as GatewayHook;
+
+/// 允许调用方保留插件句柄 (例如脚本热加载)，同时注册到网关
+#[async_trait]
+impl<T: GatewayPlugin> GatewayPlugin for Arc<T> {
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    async fn on_request(&self, ctx: &mut RequestContext) -> Result<(), Response> {
+        (**self).on_request(ctx).await
+    }
+
+    async fn on_upstream_response(&self, ctx: &RequestContext, status: StatusCode, headers: &mut HeaderMap) {
+        (**self).on_upstream_response(ctx, status, headers).await
+    }
+
+    fn wants_response_body(&self, ctx: &RequestContext, headers: &HeaderMap) -> bool {
+        (**self).wants_response_body(ctx, headers)
+    }
+
+    async fn on_response_body(&self, ctx: &RequestContext, status: StatusCode, headers: &mut HeaderMap, body: &mut Bytes) {
+        (**self).on_response_body(ctx, status, headers, body).await
+    }
+
+    fn stream_transformer(&self, ctx: &RequestContext, status: StatusCode, headers: &HeaderMap) -> Option<Box<dyn ChunkTransformer>> {
+        (**self).stream_transformer(ctx, status, headers)
+    }
+
+    fn on_chunk(&self, ctx: &RequestContext, chunk: &Bytes) -> ChunkAction {
+        (**self).on_chunk(ctx, chunk)
+    }
+
+    fn on_complete(&self, ctx: &RequestContext, outcome: &Outcome) {
+        (**self).on_complete(ctx, outcome)
+    }
+}
+
+/// 外部插件 (WASM、脚本) 返回的改写指令
+#[derive(Deserialize, Default, Debug)]
+pub struct Directive {
+    /// 改写发往上游的 path + query
+    pub path: Option<String>,
+    #[serde(default)]
+    pub set_headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub remove_headers: Vec<String>,
+    /// 替换请求体 (on_request) 或响应体 (on_response_body)
+    pub body: Option<String>,
+    /// 仅 on_request 有效：直接拒绝
+    pub reject: Option<Reject>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Reject {
+    pub status: u16,
+    #[serde(default)]
+    pub body: String,
+}
+
+impl Directive {
+    /// 应用到请求上下文；带 reject 时返回拒绝响应
+    #[allow(clippy::result_large_err)] // 与 GatewayPlugin::on_request 的返回类型保持一致
+    pub fn apply_to_request(&self, ctx: &mut RequestContext) -> Result<(), Response> {
+        if let Some(reject) = &self.reject {
+            let status = StatusCode::from_u16(reject.status).unwrap_or(StatusCode::FORBIDDEN);
+            return Err((status, reject.body.clone()).into_response());
+        }
+        if let Some(path) = &self.path {
+            if let Ok(uri) = path.parse::<Uri>() {
+                ctx.uri = uri;
+            }
+        }
+        self.apply_headers(&mut ctx.headers);
+        if let Some(body) = &self.body {
+            ctx.body = Bytes::from(body.clone());
+        }
+        Ok(())
+    }
+
+    pub fn apply_headers(&self, headers: &mut HeaderMap) {
+        for name in &self.remove_headers {
+            headers.remove(name.as_str());
+        }
+        for (name, value) in &self.set_headers {
+            if let (Ok(name), Ok(value)) = (HeaderName::try_from(name.as_str()), HeaderValue::from_str(value)) {
+                headers.insert(name, value);
+            }
+        }
+    }
+}
+
+/// 交给外部插件 (WASM、脚本) 的请求快照
+#[derive(Serialize)]
+pub struct RequestSnapshot<'a> {
+    pub method: &'a str,
+    pub path: &'a str,
+    pub headers: BTreeMap<&'a str, &'a str>,
+    pub client_ip: String,
+}
+
+impl<'a> RequestSnapshot<'a> {
+    pub fn new(ctx: &'a RequestContext) -> Self {
+        Self {
+            method: ctx.method.as_str(),
+            path: ctx.uri.path_and_query().map(|p| p.as_str()).unwrap_or("/"),
+            headers: header_map(&ctx.headers),
+            client_ip: ctx.client_ip.to_string(),
+        }
+    }
+}
+
+/// 交给外部插件的上游响应快照
+#[derive(Serialize)]
+pub struct ResponseSnapshot<'a> {
+    pub path: &'a str,
+    pub status: u16,
+    pub headers: BTreeMap<&'a str, &'a str>,
+}
+
+impl<'a> ResponseSnapshot<'a> {
+    pub fn new(ctx: &'a RequestContext, status: StatusCode, headers: &'a HeaderMap) -> Self {
+        Self {
+            path: ctx.uri.path(),
+            status: status.as_u16(),
+            headers: header_map(headers),
+        }
+    }
+}
+
+// 非 UTF-8 的头部值直接跳过
+fn header_map(headers: &HeaderMap) -> BTreeMap<&str, &str> {
+    headers
+        .iter()
+        .filter_map(|(k, v)| v.to_str().ok().map(|v| (k.as_str(), v)))
+        .collect()
+}
+
+pub(crate) type Plugins = Arc<Vec<Arc<dyn GatewayPlugin>>>;
+
+pub(crate) async fn run_on_request(plugins: &Plugins, ctx: &mut RequestContext) -> Result<(), Response> {
+    for plugin in plugins.iter() {
+        plugin.on_request(ctx).await?;
+    }
+    Ok(())
+}
+
+pub(crate) async fn run_on_upstream_response(
+    plugins: &Plugins,
+    ctx: &RequestContext,
+    status: StatusCode,
+    headers: &mut HeaderMap,
+) {
+    for plugin in plugins.iter() {
+        plugin.on_upstream_response(ctx, status, headers).await;
+    }
+}
+
+pub(crate) async fn run_on_response_body(
+    plugins: &Plugins,
+    ctx: &RequestContext,
+    status: StatusCode,
+    headers: &mut HeaderMap,
+    body: &mut Bytes,
+) {
+    for plugin in plugins.iter() {
+        if !plugin.wants_response_body(ctx, headers) {
+            continue;
+        }
+        plugin.on_response_body(ctx, status, headers, body).await;
+    }
+}
+
+/// 各插件为这个响应创建的改写器，按注册顺序
+pub(crate) fn stream_transformers(
+    plugins: &Plugins,
+    ctx: &RequestContext,
+    status: StatusCode,
+    headers: &HeaderMap,
+) -> Vec<Box<dyn ChunkTransformer>> {
+    plugins.iter().filter_map(|p| p.stream_transformer(ctx, status, headers)).collect()
+}
+
+/// 让响应流依次经过改写器，上游流结束后再取出各改写器留着的数据
+pub(crate) fn transform_stream<S, E>(inner: S, transformers: Vec<Box<dyn ChunkTransformer>>) -> impl Stream<Item = Result<Bytes, E>> + Send
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + Unpin,
+    E: Send,
+{
+    use futures_util::StreamExt;
+    futures_util::stream::unfold((inner, Some(transformers)), |(mut inner, mut transformers)| async move {
+        let active = transformers.as_mut()?;
+        let item = match inner.next().await {
+            Some(Ok(chunk)) => Ok(active.iter_mut().fold(chunk, |chunk, t| t.transform(&chunk))),
+            Some(Err(e)) => {
+                transformers = None;
+                Err(e)
+            }
+            None => {
+                // 前一个改写器 finish 输出的数据还要交给后面的改写器
+                let mut rest = Bytes::new();
+                for t in transformers.take()?.iter_mut() {
+                    let mut out = if rest.is_empty() { Vec::new() } else { t.transform(&rest).to_vec() };
+                    out.extend_from_slice(&t.finish());
+                    rest = Bytes::from(out);
+                }
+                Ok(rest)
+            }
+        };
+        Some((item, (inner, transformers)))
+    })
+    .filter(|item| std::future::ready(!matches!(item, Ok(chunk) if chunk.is_empty())))
+}
+
+pub(crate) fn run_on_complete(plugins: &Plugins, ctx: &RequestContext, outcome: &Outcome) {
+    for plugin in plugins.iter() {
+        plugin.on_complete(ctx, outcome);
+    }
+}
+
+/// 按 Outcome 发出 Completed / Failed 事件
+pub(crate) fn emit_outcome(events: &EventBus, id: u64, outcome: &Outcome) {
+    events.emit_with(|| match (&outcome.error, outcome.status) {
+        (None, Some(status)) => GatewayEvent::Completed {
+            id,
+            status: status.as_u16(),
+            bytes_out: outcome.bytes_out,
+            elapsed_ms: elapsed_ms(outcome.duration),
+        },
+        (error, status) => GatewayEvent::Failed {
+            id,
+            status: status.map(|s| s.as_u16()),
+            error: error.clone().unwrap_or_default(),
+            elapsed_ms: elapsed_ms(outcome.duration),
+        },
+    });
+}
+
+/// 客户端断开时回调，参数是已经发给客户端的字节数
+pub(crate) type OnCancel = Box<dyn FnOnce(u64) + Send>;
+
+/// 包装上游响应流：逐块回调 on_chunk，流结束或被丢弃时回调 on_complete，并发出对应的生命周期事件
+pub(crate) struct PluginStream<S> {
+    inner: S,
+    plugins: Plugins,
+    events: EventBus,
+    ctx: Arc<RequestContext>,
+    status: StatusCode,
+    // 上游声明的 Content-Length；hyper 发完这么多字节后不会再轮询到流结束
+    expected_len: Option<u64>,
+    bytes_out: u64,
+    error: Option<String>,
+    first_byte: bool,
+    completed: bool,
+    on_cancel: Option<OnCancel>,
+}
+
+impl<S> PluginStream<S> {
+    pub(crate) fn new(
+        inner: S,
+        plugins: Plugins,
+        events: EventBus,
+        ctx: Arc<RequestContext>,
+        status: StatusCode,
+        expected_len: Option<u64>,
+    ) -> Self {
+        Self {
+            inner,
+            plugins,
+            events,
+            ctx,
+            status,
+            expected_len,
+            bytes_out: 0,
+            error: None,
+            first_byte: false,
+            completed: false,
+            on_cancel: None,
+        }
+    }
+
+    /// 客户端没读完响应就断开时调用 on_cancel；只用于上游响应体还在读的流，缓冲过的响应不算取消
+    pub(crate) fn on_cancel(mut self, on_cancel: OnCancel) -> Self {
+        self.on_cancel = Some(on_cancel);
+        self
+    }
+
+    fn complete(&mut self) {
+        if self.completed {
+            return;
+        }
+        self.completed = true;
+        let outcome = Outcome {
+            status: Some(self.status),
+            bytes_out: self.bytes_out,
+            duration: self.ctx.started_at.elapsed(),
+            error: self.error.take(),
+        };
+        emit_outcome(&self.events, self.ctx.id, &outcome);
+        run_on_complete(&self.plugins, &self.ctx, &outcome);
+    }
+}
+
+impl<S, E> Stream for PluginStream<S>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: std::fmt::Display,
+{
+    type Item = Result<Bytes, std::io::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if this.completed {
+            return Poll::Ready(None);
+        }
+        match Pin::new(&mut this.inner).poll_next(cx) {
+            Poll::Ready(Some(Ok(chunk))) => {
+                if !this.first_byte {
+                    this.first_byte = true;
+                    let id = this.ctx.id;
+                    let elapsed = this.ctx.started_at.elapsed();
+                    this.events.emit_with(|| GatewayEvent::FirstByte { id, elapsed_ms: elapsed_ms(elapsed) });
+                }
+                this.bytes_out += chunk.len() as u64;
+                for plugin in this.plugins.iter() {
+                    if let ChunkAction::Abort(reason) = plugin.on_chunk(&this.ctx, &chunk) {
+                        this.error = Some(format!("aborted by plugin {}: {}", plugin.name(), reason));
+                        this.complete();
+                        return Poll::Ready(Some(Err(std::io::Error::other(reason))));
+                    }
+                }
+                if this.expected_len.is_some_and(|len| this.bytes_out >= len) {
+                    this.complete();
+                }
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            Poll::Ready(Some(Err(e))) => {
+                let message = e.to_string();
+                this.error = Some(message.clone());
+                this.complete();
+                Poll::Ready(Some(Err(std::io::Error::other(message))))
+            }
+            Poll::Ready(None) => {
+                this.complete();
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<S> Drop for PluginStream<S> {
+    fn drop(&mut self) {
+        if !self.completed {
+            // 流没读完就被丢弃，说明客户端提前断开；inner 随后一起被丢弃，还在读的上游响应体也就断开了
+            if let Some(on_cancel) = self.on_cancel.take() {
+                on_cancel(self.bytes_out);
+            }
+            self.error.get_or_insert_with(|| "client disconnected".to_string());
+            self.complete();
+        }
+    }
+}
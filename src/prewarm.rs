@@ -0,0 +1,122 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Weak;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info};
+
+use crate::upstreams;
+use crate::AppState;
+
+// --- 上游连接预热 ---
+// 启动时先对每个默认上游并发发 N 个 HEAD / 请求，把 TCP + TLS 握手提前做掉，连接留在客户端的连接池里，
+// 第一批真实请求不用再等握手。连接池里的空闲连接 90 秒后会被关掉，所以网关空闲满 idle 之后再预热一轮，
+// 低峰期过后的第一个请求同样拿到热连接。有真实请求经过时不预热 (连接本来就是热的)。
+// 上游走 HTTP/2 时并发请求复用同一条连接，N 只对 HTTP/1.1 上游有意义。
+// 每个上游最近一轮预热成功的连接数记在 aizasy_warm_connections 仪表里。
+
+#[derive(Debug, Clone)]
+pub struct PrewarmConfig {
+    /// 每个上游预先建立的连接数
+    pub connections: usize,
+    /// 空闲多久后重新预热
+    pub idle: Duration,
+    pub timeout: Duration,
+}
+
+impl Default for PrewarmConfig {
+    fn default() -> Self {
+        Self {
+            connections: 2,
+            idle: Duration::from_secs(60),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+pub(crate) struct Prewarm {
+    config: PrewarmConfig,
+    // 最近一次访问上游 (真实请求或预热) 的 unix 毫秒
+    last_used: AtomicU64,
+    // 每个上游最近一轮预热成功的连接数
+    warm: Vec<AtomicUsize>,
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+impl Prewarm {
+    pub(crate) fn new(config: PrewarmConfig, upstreams: usize) -> Self {
+        Self {
+            config,
+            last_used: AtomicU64::new(0),
+            warm: (0..upstreams).map(|_| AtomicUsize::new(0)).collect(),
+        }
+    }
+
+    #[cfg(feature = "admin")]
+    pub(crate) fn config(&self) -> &PrewarmConfig {
+        &self.config
+    }
+
+    /// 每个上游最近一轮预热成功的连接数
+    #[cfg(feature = "admin")]
+    pub(crate) fn warm(&self) -> Vec<usize> {
+        self.warm.iter().map(|w| w.load(Ordering::Relaxed)).collect()
+    }
+
+    /// 有请求发往上游
+    pub(crate) fn touch(&self) {
+        self.last_used.store(unix_ms(), Ordering::Relaxed);
+    }
+
+    fn idle(&self) -> bool {
+        unix_ms().saturating_sub(self.last_used.load(Ordering::Relaxed)) >= self.config.idle.as_millis() as u64
+    }
+}
+
+/// 启动时预热一轮，之后空闲满 idle 就再来一轮；网关状态被释放 (热重载换成新实例) 后自动停止
+pub(crate) fn spawn(state: Weak<AppState>) {
+    tokio::spawn(async move {
+        let check = match state.upgrade().and_then(|s| s.prewarm.as_ref().map(|p| p.config.idle)) {
+            Some(idle) => (idle / 4).max(Duration::from_secs(1)),
+            None => return,
+        };
+        let mut timer = tokio::time::interval(check);
+        loop {
+            timer.tick().await;
+            let Some(state) = state.upgrade() else {
+                return;
+            };
+            let Some(prewarm) = state.prewarm.as_ref().filter(|p| p.idle()) else {
+                continue;
+            };
+            prewarm.touch();
+            let rounds = (0..state.upstreams.len()).map(|index| warm(&state, prewarm, index));
+            futures_util::future::join_all(rounds).await;
+        }
+    });
+}
+
+async fn warm(state: &AppState, prewarm: &Prewarm, index: usize) {
+    let url = state.upstreams.url(index);
+    let client = upstreams::client_for(state, index);
+    let requests = (0..prewarm.config.connections).map(|_| {
+        client
+            .head(format!("{}/", url))
+            .timeout(prewarm.config.timeout)
+            .send()
+    });
+    let results = futures_util::future::join_all(requests).await;
+    let mut warm = 0;
+    for result in results {
+        match result {
+            // 任何响应 (包括 404) 都说明连接已经建好
+            Ok(_) => warm += 1,
+            Err(e) => debug!("🔥 Pre-warming a connection to {} failed: {}", url, e),
+        }
+    }
+    if prewarm.warm[index].swap(warm, Ordering::Relaxed) != warm {
+        info!("🔥 {} warm connection(s) to {}", warm, url);
+    }
+    state.metrics.set("aizasy_warm_connections", &[("target", url)], warm as u64);
+}
@@ -0,0 +1,82 @@
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use tracing::debug;
+
+use crate::AppState;
+
+// --- 项目标签 ---
+// 同一个客户端凭证可以用 X-Aizasy-Project 头把用量记到不同的内部项目上。
+// 只接受白名单里的项目名，通过后挂 CurrentProject 到请求 extensions，账本和指标按它分维度；
+// 该请求头只在网关内使用，不转发给上游。
+
+pub const PROJECT_HEADER: &str = "x-aizasy-project";
+
+/// 当前请求所属的项目
+#[derive(Debug, Clone)]
+pub struct CurrentProject(pub String);
+
+#[derive(Debug, Clone, Default)]
+pub struct Projects {
+    allowed: BTreeSet<String>,
+    /// 为 true 时没有带项目头的请求被拒绝
+    required: bool,
+}
+
+impl Projects {
+    pub fn new(allowed: impl IntoIterator<Item = String>, required: bool) -> Self {
+        Self {
+            allowed: allowed.into_iter().collect(),
+            required,
+        }
+    }
+
+    pub fn contains(&self, project: &str) -> bool {
+        self.allowed.contains(project)
+    }
+}
+
+fn reject(message: String) -> Response {
+    let body = json!({
+        "error": {
+            "code": 400,
+            "message": message,
+            "status": "INVALID_ARGUMENT",
+        }
+    });
+    (StatusCode::BAD_REQUEST, Json(body)).into_response()
+}
+
+pub(crate) async fn guard(State(state): State<Arc<AppState>>, mut req: Request, next: Next) -> Response {
+    let Some(projects) = &state.projects else {
+        return next.run(req).await;
+    };
+
+    let project = req
+        .headers_mut()
+        .remove(PROJECT_HEADER)
+        .and_then(|v| v.to_str().ok().map(|v| v.trim().to_string()))
+        .filter(|v| !v.is_empty());
+    let Some(project) = project else {
+        if projects.required {
+            return reject(format!("Missing {} header", PROJECT_HEADER));
+        }
+        return next.run(req).await;
+    };
+    if !projects.contains(&project) {
+        state.metrics.inc("aizasy_project_rejected_total", &[]);
+        return reject(format!("Unknown project '{}'", project));
+    }
+
+    debug!("🏷️ Project {}", project);
+    state.metrics.inc("aizasy_project_requests_total", &[("project", &project)]);
+    req.extensions_mut().insert(CurrentProject(project));
+    next.run(req).await
+}
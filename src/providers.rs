@@ -0,0 +1,304 @@
+use axum::body::Bytes;
+use axum::http::{header, HeaderMap, HeaderValue, Uri};
+use serde::Serialize;
+use serde_json::Value;
+
+// --- 其他模型厂商的上游 ---
+// 除了 Gemini，网关还可以把 OpenAI / Anthropic 的原生请求转发给对应厂商，集群里所有大模型流量走同一个入口。
+// 按请求体里的 model 字段匹配 (gpt-*、claude-* 这类前缀，或完整模型名)，命中时：
+//   - 上游换成这个厂商的地址，不经过 Gemini 的 key 池；
+//   - 鉴权换成厂商的格式：OpenAI 用 Authorization: Bearer，Anthropic 用 x-api-key 加 anthropic-version；
+//     没有配置 key 时透传客户端自己的鉴权头；
+//   - 路径、请求体、响应 (包括 SSE 流) 原样转发，计费从响应里的 usage 提取。
+// 请求路径里带 /models/ 的 (Gemini 格式) 不参与匹配。
+// 只转发厂商的生成、向量和 token 计数接口 (OpenAI: /v1/chat/completions、/v1/completions、/v1/embeddings、
+// /v1/responses；Anthropic: /v1/messages、/v1/messages/count_tokens、/v1/complete)，
+// 命中厂商的其他路径返回 404，免得网关的厂商 key 被拿去调用文件、微调之类的管理接口。
+// 开启 OpenAI 兼容层时，命中厂商的 /v1/chat/completions、/v1/embeddings 不再转换成 Gemini，直接转发。
+//
+// Azure OpenAI 接收 OpenAI 格式的请求体，但路径按部署 (deployment) 划分，还要求 api-version 查询参数：
+//   /v1/chat/completions (model=gpt-4o)
+//   -> /openai/deployments/<gpt-4o 对应的部署名>/chat/completions?api-version=2024-10-21
+// 鉴权头是 api-key：配置了 key 时用配置的，否则把客户端 Authorization: Bearer 里的 key 挪过去。
+// 没有写进 deployments 的模型按同名部署处理。
+
+pub const DEFAULT_ANTHROPIC_VERSION: &str = "2023-06-01";
+pub const DEFAULT_AZURE_API_VERSION: &str = "2024-10-21";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    OpenAi,
+    Anthropic,
+    Azure,
+}
+
+impl std::str::FromStr for ProviderKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "openai" => Ok(Self::OpenAi),
+            "anthropic" => Ok(Self::Anthropic),
+            "azure" => Ok(Self::Azure),
+            other => Err(format!("unknown provider '{}', expected openai, anthropic or azure", other)),
+        }
+    }
+}
+
+impl ProviderKind {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::OpenAi => "openai",
+            Self::Anthropic => "anthropic",
+            Self::Azure => "azure",
+        }
+    }
+
+    /// Azure 的地址按资源而定 (https://<资源>.openai.azure.com)，没有默认值
+    fn default_url(&self) -> Option<&'static str> {
+        match self {
+            Self::OpenAi => Some("https://api.openai.com"),
+            Self::Anthropic => Some("https://api.anthropic.com"),
+            Self::Azure => None,
+        }
+    }
+
+    fn default_models(&self) -> &'static [&'static str] {
+        match self {
+            Self::OpenAi | Self::Azure => &["gpt-*", "chatgpt-*", "o1*", "o3*", "o4*", "text-embedding-*"],
+            Self::Anthropic => &["claude-*"],
+        }
+    }
+
+    /// 允许转发的接口：生成、向量和 token 计数
+    fn paths(&self) -> &'static [&'static str] {
+        match self {
+            Self::OpenAi | Self::Azure => &["/v1/chat/completions", "/v1/completions", "/v1/embeddings", "/v1/responses"],
+            Self::Anthropic => &["/v1/messages", "/v1/messages/count_tokens", "/v1/complete"],
+        }
+    }
+
+    fn allows_path(&self, path: &str) -> bool {
+        self.paths().contains(&path.trim_end_matches('/'))
+    }
+
+    fn default_version(&self) -> &'static str {
+        match self {
+            Self::Azure => DEFAULT_AZURE_API_VERSION,
+            _ => DEFAULT_ANTHROPIC_VERSION,
+        }
+    }
+}
+
+// 部署名原样放进路径，只允许字母、数字和 . _ -
+fn valid_deployment(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'_' | b'-'))
+}
+
+#[derive(Debug, Clone)]
+pub struct Provider {
+    /// 日志、计费里显示的名字，默认同 kind
+    pub name: String,
+    pub kind: ProviderKind,
+    /// 上游地址 (不含 /v1)
+    pub url: String,
+    /// 厂商的 API key；None 时透传客户端的鉴权头
+    pub key: Option<String>,
+    /// 模型名，末尾 `*` 表示前缀匹配
+    pub models: Vec<String>,
+    /// Anthropic 的 anthropic-version (客户端没带时补上)，或 Azure 的 api-version 查询参数
+    pub version: String,
+    /// Azure 的模型名 -> 部署名
+    pub deployments: Vec<(String, String)>,
+}
+
+impl Provider {
+    /// 解析 `KIND[;name=NAME][;models=PATTERN,PATTERN][;key=KEY][;url=URL][;version=V][;deployments=MODEL=DEPLOYMENT,...]`，
+    /// KIND 为 openai / anthropic / azure，models 默认为这个厂商的常见前缀；
+    /// azure 必须写 url，写了 deployments 而没写 models 时只匹配 deployments 里的模型
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut parts = spec.split(';');
+        let kind: ProviderKind = parts.next().unwrap_or("").trim().parse()?;
+        let mut provider = Provider {
+            name: kind.name().to_string(),
+            kind,
+            url: kind.default_url().unwrap_or_default().to_string(),
+            key: None,
+            models: kind.default_models().iter().map(|m| m.to_string()).collect(),
+            version: kind.default_version().to_string(),
+            deployments: Vec::new(),
+        };
+        let mut models_set = false;
+        for part in parts.map(str::trim).filter(|p| !p.is_empty()) {
+            match part.split_once('=') {
+                Some(("name", name)) => provider.name = name.trim().to_string(),
+                Some(("models", models)) => {
+                    provider.models = models.split(',').map(str::trim).filter(|m| !m.is_empty()).map(str::to_string).collect();
+                    models_set = true;
+                }
+                Some(("deployments", deployments)) if kind == ProviderKind::Azure => {
+                    for pair in deployments.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+                        match pair.split_once('=') {
+                            Some((model, deployment)) if !model.trim().is_empty() && valid_deployment(deployment.trim()) => {
+                                provider.deployments.push((model.trim().to_string(), deployment.trim().to_string()));
+                            }
+                            _ => return Err(format!("deployment '{}' must be MODEL=DEPLOYMENT", pair)),
+                        }
+                    }
+                }
+                Some(("key", key)) => provider.key = Some(key.trim().to_string()).filter(|k| !k.is_empty()),
+                Some(("url", url)) => provider.url = url.trim().trim_end_matches('/').to_string(),
+                Some(("version", version)) => provider.version = version.trim().to_string(),
+                _ => return Err(format!("unknown provider option '{}'", part)),
+            }
+        }
+        if !models_set && !provider.deployments.is_empty() {
+            provider.models = provider.deployments.iter().map(|(model, _)| model.clone()).collect();
+        }
+        if provider.url.is_empty() {
+            return Err(format!("provider '{}' needs url=https://<resource>.openai.azure.com", spec));
+        }
+        if provider.models.is_empty() {
+            return Err(format!("provider '{}' has no models", spec));
+        }
+        if !provider.url.starts_with("http://") && !provider.url.starts_with("https://") {
+            return Err(format!("provider url must start with http:// or https:// in '{}'", spec));
+        }
+        let check = |value: &str| HeaderValue::from_str(value).map(|_| ());
+        if provider.key.as_deref().is_some_and(|key| check(&format!("Bearer {}", key)).is_err()) || check(&provider.version).is_err() {
+            return Err(format!("provider '{}' has an invalid key or version", provider.name));
+        }
+        Ok(provider)
+    }
+
+    pub fn matches(&self, model: &str) -> bool {
+        self.models.iter().any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => model.starts_with(prefix),
+            None => model == pattern,
+        })
+    }
+
+    /// 模型对应的 Azure 部署名，没配置时同名
+    pub fn deployment<'a>(&'a self, model: &'a str) -> &'a str {
+        self.deployments
+            .iter()
+            .find(|(name, _)| name == model)
+            .map(|(_, deployment)| deployment.as_str())
+            .unwrap_or(model)
+    }
+
+    /// Azure 的请求路径：/v1/chat/completions -> /openai/deployments/<部署>/chat/completions?api-version=V
+    fn azure_uri(&self, uri: &Uri, model: &str) -> Option<Uri> {
+        let path = uri.path();
+        let rest = path.strip_prefix("/v1/").or_else(|| path.strip_prefix('/'))?;
+        let mut query: Vec<&str> = uri
+            .query()
+            .into_iter()
+            .flat_map(|q| q.split('&'))
+            .filter(|pair| !pair.is_empty() && !pair.starts_with("api-version="))
+            .collect();
+        let version = format!("api-version={}", self.version);
+        query.push(&version);
+        let deployment = self.deployment(model);
+        if !valid_deployment(deployment) {
+            return None;
+        }
+        format!("/openai/deployments/{}/{}?{}", deployment, rest, query.join("&")).parse().ok()
+    }
+
+    /// 换成这个厂商的鉴权头
+    pub(crate) fn authorize(&self, headers: &mut HeaderMap) {
+        headers.remove("x-goog-api-key");
+        match self.kind {
+            ProviderKind::OpenAi => {
+                headers.remove("x-api-key");
+                if let Some(key) = &self.key {
+                    if let Ok(value) = HeaderValue::from_str(&format!("Bearer {}", key)) {
+                        headers.insert(header::AUTHORIZATION, value);
+                    }
+                }
+            }
+            ProviderKind::Azure => {
+                // 客户端按 OpenAI 的习惯带的 Bearer key 挪到 api-key
+                let bearer = headers.remove(header::AUTHORIZATION).and_then(|value| {
+                    let value = value.to_str().ok()?.strip_prefix("Bearer ")?.trim().to_string();
+                    HeaderValue::from_str(&value).ok()
+                });
+                let key = self.key.as_deref().and_then(|key| HeaderValue::from_str(key).ok()).or(bearer);
+                if let Some(key) = key {
+                    headers.insert("api-key", key);
+                }
+            }
+            ProviderKind::Anthropic => {
+                if let Some(key) = &self.key {
+                    headers.remove(header::AUTHORIZATION);
+                    if let Ok(value) = HeaderValue::from_str(key) {
+                        headers.insert("x-api-key", value);
+                    }
+                }
+                if !headers.contains_key("anthropic-version") {
+                    if let Ok(value) = HeaderValue::from_str(&self.version) {
+                        headers.insert("anthropic-version", value);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 当前请求命中的厂商，proxy 在执行插件之前放进 RequestContext.extensions
+#[derive(Debug, Clone)]
+pub struct ProviderRoute {
+    pub provider: String,
+    pub kind: ProviderKind,
+    pub target: String,
+    pub model: String,
+}
+
+/// 请求体 JSON 里的 model 字段
+pub(crate) fn body_model(body: &[u8]) -> Option<String> {
+    // 快速跳过明显不是 JSON 对象、或根本没有 model 字段的请求体
+    if body.first().is_none_or(|b| *b != b'{') || !body.windows(7).any(|w| w == b"\"model\"") {
+        return None;
+    }
+    let value: Value = serde_json::from_slice(body).ok()?;
+    value.get("model")?.as_str().map(str::to_string)
+}
+
+pub(crate) fn for_model<'a>(providers: &'a [Provider], model: &str) -> Option<&'a Provider> {
+    providers.iter().find(|p| p.matches(model))
+}
+
+/// 匹配请求并改写鉴权头 (Azure 还改写路径)；Gemini 格式的路径 (带 /models/) 不匹配。
+/// 命中厂商但路径不是这个厂商的生成、向量、计数接口时返回 Err(厂商名)，
+/// 不拿网关配置的厂商 key 去请求任意路径
+pub(crate) fn resolve(
+    providers: &[Provider],
+    uri: &mut Uri,
+    headers: &mut HeaderMap,
+    body: &Bytes,
+) -> Result<Option<ProviderRoute>, String> {
+    if providers.is_empty() || uri.path().contains("/models/") {
+        return Ok(None);
+    }
+    let Some(model) = body_model(body) else {
+        return Ok(None);
+    };
+    let Some(provider) = for_model(providers, &model) else {
+        return Ok(None);
+    };
+    if !provider.kind.allows_path(uri.path()) {
+        return Err(provider.name.clone());
+    }
+    if provider.kind == ProviderKind::Azure {
+        *uri = provider.azure_uri(uri, &model).ok_or_else(|| provider.name.clone())?;
+    }
+    provider.authorize(headers);
+    Ok(Some(ProviderRoute {
+        provider: provider.name.clone(),
+        kind: provider.kind,
+        target: provider.url.clone(),
+        model,
+    }))
+}
@@ -0,0 +1,1014 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::{header, Extensions, HeaderMap, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures_util::{stream, StreamExt};
+use serde_json::json;
+use http_body_util::{BodyExt, LengthLimitError, Limited};
+use std::borrow::Cow;
+use std::net::IpAddr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn, Instrument, Span};
+
+#[cfg(feature = "geoip")]
+use crate::geoip::GeoCountry;
+use crate::encoding;
+use crate::grpc;
+use crate::events::{elapsed_ms, GatewayEvent, RequestId};
+use crate::otel::{self, RequestSpan};
+use crate::plugin::{self, Outcome, PluginStream, RequestContext, UpstreamTarget};
+use crate::header_rules::{self, Direction};
+use crate::canary::Side;
+use crate::mirror;
+use crate::path_allowlist;
+use crate::providers::{self, ProviderRoute};
+use crate::model_map::map_body_models;
+use crate::model_router::{replace_model, ROUTED_MODEL_HEADER};
+use crate::retry::{RetryConfig, NO_RETRY_HEADER};
+use crate::rewrite;
+use crate::routes::{strip_path_prefix, MatchedRoute};
+use crate::sanitize::{self, redact_path, sanitize_path};
+use crate::security_headers::UpstreamResponse;
+use crate::slow_log;
+use crate::stream_timeout::{IdleTimeout, SseKeepalive};
+use crate::access_log::UpstreamInfo;
+use crate::client_ip::{ClientIp, ForwardedFor};
+use crate::tenant::CurrentTenant;
+use crate::uploads::{self, UnknownSession, UploadSession};
+use crate::trailers::{self, RequestTrailers, TrailerSlot, WithTrailers};
+use crate::usage::{self, UpstreamKeyId};
+use crate::key_pool::{self, KeySlot};
+use crate::request_queue::QueueRejection;
+use crate::AppState;
+
+/// 一次上游尝试失败的原因
+#[derive(Debug)]
+enum SendError {
+    Http(reqwest::Error),
+    /// 在 first_byte_timeout 内没有收到响应头
+    FirstByte(Duration),
+    /// key 池里的 key 都被停用了
+    NoKey,
+    /// key 都在冷却，排队排不上或等超时
+    Queued(QueueRejection),
+    /// key 的并发名额都占满了 (没有开排队)
+    KeyBusy,
+    /// 经过代理的运行时路由没通过目标地址策略
+    Blocked(String),
+}
+
+impl SendError {
+    fn is_connect(&self) -> bool {
+        matches!(self, SendError::Http(e) if e.is_connect())
+    }
+
+    fn is_timeout(&self) -> bool {
+        match self {
+            SendError::Http(e) => e.is_timeout(),
+            SendError::FirstByte(_) => true,
+            SendError::NoKey | SendError::Queued(_) | SendError::KeyBusy | SendError::Blocked(_) => false,
+        }
+    }
+}
+
+impl std::fmt::Display for SendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            // reqwest 的错误信息带着完整 URL，key 放在 query 里时会原样出现
+            SendError::Http(e) => f.write_str(&sanitize::redact_text(&e.to_string())),
+            SendError::FirstByte(timeout) => write!(f, "no response from upstream within {}s", timeout.as_secs()),
+            SendError::NoKey => write!(f, "all upstream keys are disabled"),
+            SendError::Queued(rejection) => write!(f, "{}", rejection),
+            SendError::KeyBusy => write!(f, "all upstream keys are at their concurrency limit"),
+            SendError::Blocked(reason) => write!(f, "target rejected by the dynamic target policy: {}", reason),
+        }
+    }
+}
+
+/// 这次请求怎么选 key 和客户端，留存在请求的 extensions 里，重放失败请求时照原样选
+#[derive(Debug, Clone, Default)]
+pub(crate) struct UpstreamPlan {
+    /// 发往其他厂商、续传会话和透传路由的请求不用 key 池
+    #[cfg_attr(not(feature = "admin"), allow(dead_code))]
+    pub(crate) key_pool: bool,
+    pub(crate) key_group: Option<String>,
+    /// 命中运行时设置的路由，连接时按 SSRF 策略过滤解析结果
+    pub(crate) dynamic_route: bool,
+    pub(crate) grpc: bool,
+}
+
+/// 默认上游或金丝雀单独配置了代理时，发往它的请求用的客户端
+#[cfg(feature = "admin")]
+pub(crate) fn own_client<'a>(state: &'a AppState, target: &str) -> Option<&'a reqwest::Client> {
+    if let Some(canary) = state.canary.as_ref().filter(|c| c.target == target) {
+        return canary.client();
+    }
+    (0..state.upstreams.len())
+        .find(|&index| state.upstreams.url(index) == target)
+        .and_then(|index| state.upstreams.client(index))
+}
+
+/// 一次上游尝试用的客户端：gRPC 用专门的客户端；其次是上游自己的客户端 (own)，
+/// 运行时路由用按 SSRF 策略过滤解析结果的客户端，然后是代理池 (选中的代理记在 egress)，都没有时用全局客户端
+pub(crate) fn upstream_client<'a>(
+    state: &'a AppState,
+    plan: &UpstreamPlan,
+    own: Option<&'a reqwest::Client>,
+    egress: &mut Option<usize>,
+    key: Option<usize>,
+    tried_proxies: &[usize],
+) -> &'a reqwest::Client {
+    let route_client = state.dynamic_client.as_ref().filter(|_| plan.dynamic_route);
+    match (own.or(route_client), &state.egress) {
+        _ if plan.grpc => state.grpc_client.as_ref().unwrap_or(&state.client),
+        (Some(client), _) => client,
+        (None, Some(pool)) => {
+            let index = match *egress {
+                Some(index) => index,
+                // tried_proxies 只在还有没试过的代理时才会保留
+                None => pool.pick(key, tried_proxies).expect("no proxy left").0,
+            };
+            *egress = Some(index);
+            pool.client(index)
+        }
+        (None, None) => &state.client,
+    }
+}
+
+/// 运行时路由经过出站代理时由代理解析域名，连接时过滤不到内网地址 (没有 dynamic_client)，
+/// 发之前先按策略解析检查一遍；检查和代理解析之间的 DNS 变化仍然防不住，要彻底防住就不给运行时路由配代理
+pub(crate) async fn check_proxied_route(state: &AppState, plan: &UpstreamPlan, target: &str) -> Result<(), String> {
+    if !plan.dynamic_route || plan.grpc || state.dynamic_client.is_some() || state.target_policy.allow_private {
+        return Ok(());
+    }
+    state.target_policy.check(target).await.map(|_| ())
+}
+
+/// 客户端断开、上游请求被丢弃时记日志和指标；kind 区分断在等响应头 (headers) 还是读响应体 (stream / body)
+fn record_cancel(state: &AppState, id: u64, kind: &str, bytes: u64, started_at: Instant) {
+    info!(
+        "✂️  Client disconnected from request {} after {} bytes ({:.1}s), upstream request cancelled",
+        id,
+        bytes,
+        started_at.elapsed().as_secs_f64()
+    );
+    state.metrics.inc("aizasy_upstream_cancelled_total", &[("kind", kind)]);
+    state.metrics.add("aizasy_upstream_cancelled_bytes_total", &[("kind", kind)], bytes);
+}
+
+/// 等上游响应头期间存在；客户端断开、forward 被丢弃时，还没返回的 send() 也一起被丢弃，记一次取消
+struct AwaitingHeaders<'a> {
+    state: &'a AppState,
+    id: u64,
+    started_at: Instant,
+    armed: bool,
+}
+
+impl Drop for AwaitingHeaders<'_> {
+    fn drop(&mut self) {
+        if self.armed {
+            record_cancel(self.state, self.id, "headers", 0, self.started_at);
+        }
+    }
+}
+
+/// 网关内部发起的子请求 (批量扇出、协议转换)：分配新的请求编号后完整走一遍代理流程，
+/// 插件、key 池、计费都和普通请求一样生效
+pub(crate) async fn dispatch(
+    state: &Arc<AppState>,
+    client_ip: IpAddr,
+    method: Method,
+    uri: &str,
+    headers: HeaderMap,
+    extensions: Extensions,
+    body: Bytes,
+) -> Result<Response, String> {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .body(Body::from(body))
+        .map_err(|e| e.to_string())?;
+    *request.headers_mut() = headers;
+    *request.extensions_mut() = extensions;
+    let id = state.request_ids.fetch_add(1, Ordering::Relaxed) + 1;
+    request.extensions_mut().insert(RequestId(id));
+    Ok(proxy_handler(State(state.clone()), ClientIp(client_ip), request).await.into_response())
+}
+
+// --- 核心处理函数 ---
+pub(crate) async fn proxy_handler(
+    State(state): State<Arc<AppState>>,
+    ClientIp(client_ip): ClientIp,
+    // 这里我们接收一个通用的 Request
+    req: Request, 
+) -> Response {
+    let span = otel::request_span(req.method(), req.uri().path(), req.headers());
+    span.record("aizasy.client_ip", tracing::field::display(client_ip));
+    let response = forward(state, client_ip, req, span.clone()).instrument(span.clone()).await;
+    otel::record_status(&span, response.status());
+    response
+}
+
+/// 转发前整理好的请求，以及发送和转发响应时还要用到的状态
+struct Prepared {
+    ctx: RequestContext,
+    /// 客户端要求不重试
+    no_retry: bool,
+    /// 续传请求对应的上游会话
+    upload: Option<UploadSession>,
+    /// 网关对外的地址，上游返回续传地址时换成它
+    upload_origin: Option<String>,
+}
+
+/// 读完请求体、清洗请求头，再按改写规则、路由规则、其他厂商和续传会话定好发往上游的路径；
+/// 请求不合法时返回要交给客户端的响应
+async fn prepare(state: &AppState, client_ip: IpAddr, req: Request, span: Span, started_at: Instant) -> Result<Prepared, Response> {
+    let (parts, req_body) = req.into_parts();
+    // 直接挂载 proxy_handler 时没有经过分配编号的中间件
+    let id = parts.extensions.get::<RequestId>().map(|id| id.0).unwrap_or_default();
+    span.record("aizasy.request_id", id);
+
+    // 1. 关键修复：显式读取 Body
+    // 将 Axum 的 Body 转换为 Bytes。Reqwest 原生支持 Bytes。
+    // 限制大小 (--max-request-size，默认 64MB)，防止内存溢出；声明的 Content-Length 已经超限时不必读
+    let declared_len = parts
+        .headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if declared_len.is_some_and(|len| len > state.max_request_size as u64) {
+        warn!("📦 Request {} body exceeds {} bytes, rejected", id, state.max_request_size);
+        state.metrics.inc("aizasy_body_too_large_total", &[("direction", "request")]);
+        return Err((StatusCode::PAYLOAD_TOO_LARGE, "Request body too large").into_response());
+    }
+    // 请求体后面的 trailers 一起收下，转发时再发给上游
+    let (req_bytes, req_trailers) = match Limited::new(req_body, state.max_request_size).collect().await {
+        Ok(collected) => {
+            let trailers = collected.trailers().cloned();
+            (collected.to_bytes(), trailers)
+        }
+        Err(e) => {
+            error!("Failed to read request body: {}", e);
+            let too_large = e.is::<LengthLimitError>();
+            if too_large {
+                state.metrics.inc("aizasy_body_too_large_total", &[("direction", "request")]);
+                return Err((StatusCode::PAYLOAD_TOO_LARGE, "Request body too large").into_response());
+            }
+            return Err((StatusCode::BAD_REQUEST, "Invalid request body").into_response());
+        }
+    };
+
+    // 上游返回续传地址时要换成网关自己的地址，Host 马上就要去掉
+    let upload_origin = state.uploads.as_ref().and_then(|u| u.origin(&parts.headers, &parts.uri));
+
+    // 2. 清洗 Headers
+    let mut new_headers = parts.headers;
+    new_headers.remove("host");
+    new_headers.remove("cf-connecting-ip");
+    new_headers.remove("cf-ipcountry");
+    new_headers.remove("x-forwarded-for");
+    if let Some(ForwardedFor(value)) = parts.extensions.get::<ForwardedFor>() {
+        new_headers.insert("x-forwarded-for", value.clone());
+    }
+    new_headers.remove("content-length"); // 让 reqwest 重新计算
+    encoding::strip_hop_by_hop(&mut new_headers);
+    state.accept_encoding.apply(&mut new_headers);
+    let no_retry = new_headers.remove(NO_RETRY_HEADER).is_some();
+    header_rules::apply(&state.header_rules, Direction::Request, &mut new_headers);
+
+    // 3. 请求上下文：按改写规则、路由规则、其他厂商和续传会话定好路径
+    let mut ctx = RequestContext {
+        id,
+        method: parts.method,
+        uri: parts.uri,
+        headers: new_headers,
+        body: req_bytes,
+        client_ip,
+        extensions: parts.extensions,
+        started_at,
+    };
+    // 流式响应发完之前 span 不结束
+    ctx.extensions.insert(RequestSpan(span));
+    if let Some(trailers) = req_trailers {
+        ctx.extensions.insert(RequestTrailers(trailers));
+    }
+    if let Some(uri) = rewrite::rewrite(&state.rewrites, &ctx.uri) {
+        debug!("✏️  Rewrote {} -> {}", sanitize_path(ctx.uri.path()), sanitize_path(uri.path()));
+        ctx.uri = uri;
+    }
+    if let Some(rule) = state.routes.resolve(ctx.uri.path()) {
+        if rule.strip_prefix {
+            if let Some(uri) = strip_path_prefix(&ctx.uri, rule.strip_len()) {
+                ctx.uri = uri;
+            }
+        }
+        ctx.extensions.insert(MatchedRoute(rule));
+    }
+    match providers::resolve(&state.providers, &mut ctx.uri, &mut ctx.headers, &ctx.body) {
+        Ok(Some(route)) => {
+            debug!("🌐 Request {} goes to {} ({})", ctx.id, route.provider, route.model);
+            // 计费按厂商名归到 key 维度
+            ctx.extensions.insert(UpstreamKeyId(route.provider.clone()));
+            ctx.extensions.insert(route);
+        }
+        Ok(None) => {}
+        Err(provider) => {
+            warn!("🌐 Request {} to {} is not a supported {} endpoint", ctx.id, sanitize_path(ctx.uri.path()), provider);
+            let body = json!({
+                "error": {
+                    "code": 404,
+                    "message": format!("path is not a supported {} endpoint", provider),
+                    "status": "NOT_FOUND",
+                }
+            });
+            return Err((StatusCode::NOT_FOUND, Json(body)).into_response());
+        }
+    }
+
+    // 续传上传：换回上游的会话地址
+    let upload = match state.uploads.as_ref().map(|u| u.resolve(&ctx.uri)) {
+        Some(Ok(session)) => session,
+        Some(Err(UnknownSession)) => {
+            warn!("📤 Request {} refers to an unknown or expired upload session", ctx.id);
+            let body = json!({
+                "error": {
+                    "code": 404,
+                    "message": "upload session not found or expired",
+                    "status": "NOT_FOUND",
+                }
+            });
+            return Err((StatusCode::NOT_FOUND, Json(body)).into_response());
+        }
+        None => None,
+    };
+    if let Some(session) = &upload {
+        ctx.uri = session.uri.clone();
+    }
+    Ok(Prepared {
+        ctx,
+        no_retry,
+        upload,
+        upload_origin,
+    })
+}
+
+async fn forward(state: Arc<AppState>, client_ip: IpAddr, req: Request, span: Span) -> Response {
+    let started_at = Instant::now();
+    let Prepared { mut ctx, no_retry, upload, upload_origin } = match prepare(&state, client_ip, req, span, started_at).await {
+        Ok(prepared) => prepared,
+        Err(response) => return response,
+    };
+    let id = ctx.id;
+
+    // 插件 on_request：可以改写 uri / headers / body，或直接拒绝
+    state.events.emit_with(|| GatewayEvent::RequestStarted {
+        id: ctx.id,
+        method: ctx.method.to_string(),
+        path: ctx.uri.path().to_string(),
+        client_ip,
+    });
+    if let Err(response) = plugin::run_on_request(&state.plugins, &mut ctx).await {
+        state.events.emit_with(|| GatewayEvent::Completed {
+            id: ctx.id,
+            status: response.status().as_u16(),
+            bytes_out: 0,
+            elapsed_ms: elapsed_ms(started_at.elapsed()),
+        });
+        return response;
+    }
+    if state.path_allowlist.as_ref().is_some_and(|a| !a.allows(ctx.uri.path())) {
+        warn!("🚫 Request {} to {} is not in the path allowlist", ctx.id, sanitize_path(ctx.uri.path()));
+        state.metrics.inc("aizasy_path_denied_total", &[]);
+        let response = path_allowlist::reject();
+        state.events.emit_with(|| GatewayEvent::Completed {
+            id: ctx.id,
+            status: response.status().as_u16(),
+            bytes_out: 0,
+            elapsed_ms: elapsed_ms(started_at.elapsed()),
+        });
+        return response;
+    }
+
+    // 4. 优先级: gRPC 上游 > 其他厂商 > 路由规则 > 租户 > 默认上游 (可以有多个，按权重选)
+    // 发往其他厂商的请求已经换好了鉴权头，不使用 Gemini 的 key 池
+    let grpc = state.grpc.as_ref().filter(|_| grpc::is_grpc(&ctx.headers));
+    let provider_target = ctx.extensions.get::<ProviderRoute>().map(|p| p.target.clone());
+    // 续传请求固定用开会话的 key
+    let passthrough = ctx.extensions.get::<MatchedRoute>().is_some_and(|r| r.0.passthrough);
+    let use_key_pool = provider_target.is_none() && upload.is_none() && !passthrough;
+    let fixed_target = grpc
+        .map(|g| g.target.clone())
+        .or_else(|| upload.as_ref().map(|s| s.origin.clone()))
+        .or(provider_target)
+        .or_else(|| ctx.extensions.get::<MatchedRoute>().map(|r| r.0.target.clone()))
+        .or_else(|| ctx.extensions.get::<CurrentTenant>().and_then(|t| t.0.target.clone()));
+
+    #[cfg(feature = "geoip")]
+    let country = ctx.extensions.get::<GeoCountry>().map(|c| c.0.as_str().to_string());
+    #[cfg(not(feature = "geoip"))]
+    let country: Option<String> = None;
+
+    // 5. 发送请求
+    // 配置了 key 池时注入池里的 key；某个 key 返回 429 / 403 就让它冷却，换下一个 key 重试。
+    // 默认上游有多个时，连接失败或 5xx 的上游暂停使用，换下一个上游重试
+    // (请求体已经完整缓冲在 ctx.body 里，可以原样重放)
+    let mut tried_keys = Vec::new();
+    let mut tried_targets = Vec::new();
+    let mut key = None;
+    let mut upstream = None;
+    // 代理池里本次用的和已经连不上的代理
+    let mut egress = None;
+    let mut tried_proxies = Vec::new();
+    // 模型降级：请求的模型和已经试过的模型
+    let requested_model = usage::model_from_path(ctx.uri.path()).map(str::to_string);
+    let mut tried_models: Vec<String> = requested_model.iter().cloned().collect();
+    // 瞬时错误重试：请求体太大或客户端关闭时不重试
+    let retry = state.retry.as_ref().filter(|r| !no_retry && ctx.body.len() <= r.max_body);
+    let mut retries = 0;
+    // 只镜像第一次尝试
+    let mut mirrored = false;
+    let affinity = state.key_pool.as_ref().and_then(|pool| pool.affinity(&ctx.headers, || usage::client_label(&ctx)));
+    let plan = UpstreamPlan {
+        key_pool: use_key_pool,
+        // 路由规则的 key 分组优先于租户的
+        key_group: ctx
+            .extensions
+            .get::<MatchedRoute>()
+            .and_then(|r| r.0.key_group.clone())
+            .or_else(|| ctx.extensions.get::<CurrentTenant>().and_then(|t| t.0.key_group.clone())),
+        dynamic_route: ctx
+            .extensions
+            .get::<MatchedRoute>()
+            .is_some_and(|r| r.0.dynamic && fixed_target.as_deref() == Some(r.0.target.as_str())),
+        grpc: grpc.is_some(),
+    };
+    ctx.extensions.insert(plan.clone());
+    let key_group = plan.key_group.as_deref();
+    // 上游限流排队：第一次排队时取号，之后重新排队保留原来的位置和截止时间
+    let queue = state.request_queue.as_ref().filter(|_| use_key_pool);
+    let mut ticket = None;
+    let mut _dispatch = None;
+    // 设置了每个 key 并发上限时占用的名额，一直带到响应体发完
+    let mut slot = None;
+    let mut attempts = 0;
+    let mut timing = None;
+    // 最后一次尝试收到上游响应头的时刻
+    let mut responded_at = None;
+    // 金丝雀只分流原本发往默认上游的请求
+    let mut side = state.canary.as_ref().filter(|_| fixed_target.is_none()).map(|c| c.choose(|| usage::client_label(&ctx)));
+    let blocked = match &fixed_target {
+        Some(target) => check_proxied_route(&state, &plan, target).await.err(),
+        None => None,
+    };
+    let result = loop {
+        if let Some(reason) = &blocked {
+            warn!("🛡️  Request {} to runtime route {} blocked: {}", ctx.id, fixed_target.as_deref().unwrap_or_default(), reason);
+            state.metrics.inc("aizasy_dynamic_target_blocked_total", &[]);
+            break Err(SendError::Blocked(reason.clone()));
+        }
+        if key.is_none() && use_key_pool {
+            if let Some(pool) = &state.key_pool {
+                if let Some(queue) = queue.filter(|queue| !queue.can_bypass(pool)) {
+                    let ticket = ticket.get_or_insert_with(|| queue.ticket(&ctx.headers, &usage::client_label(&ctx)));
+                    debug!("⏳ Request {} queued (priority {}), {} waiting", ctx.id, ticket.priority(), queue.len());
+                    state.metrics.inc("aizasy_queued_requests_total", &[]);
+                    match queue.wait(ticket, pool).await {
+                        Ok(permit) => {
+                            _dispatch = Some(permit);
+                            // 恢复的 key 可能刚才试过
+                            tried_keys.clear();
+                        }
+                        Err(rejection) => {
+                            warn!("⏳ Request {} rejected: {}", ctx.id, rejection);
+                            let reason = match rejection {
+                                QueueRejection::Full => "full",
+                                QueueRejection::Timeout(_) => "timeout",
+                            };
+                            state.metrics.inc("aizasy_queue_rejections_total", &[("reason", reason)]);
+                            break Err(SendError::Queued(rejection));
+                        }
+                    }
+                }
+                // tried_keys 不会超过 max_attempts，而 max_attempts 不超过启用中的 key 数；
+                // 只有所有 key 都停用了 (或者刚好在这次请求中途被停用)、或者名额都满了才会取不到
+                slot = None;
+                let Some((index, entry, reserved)) = key_pool::reserve(&state, pool, &tried_keys, affinity.as_deref(), key_group) else {
+                    if pool.max_concurrency().is_none() || pool.enabled() == 0 {
+                        break Err(SendError::NoKey);
+                    }
+                    // 刚放行就被别的请求抢走了名额：回到队列里等
+                    if queue.is_some_and(|queue| !queue.can_bypass(pool)) && !ticket.is_some_and(|t| t.expired()) {
+                        continue;
+                    }
+                    break Err(SendError::KeyBusy);
+                };
+                slot = reserved;
+                tried_keys.push(index);
+                ctx.extensions.insert(UpstreamKeyId(entry.name.clone()));
+                key = Some(entry);
+            }
+        }
+        let on_canary = side == Some(Side::Canary);
+        let target = match (&fixed_target, &state.canary) {
+            (Some(target), _) => target.clone(),
+            (None, Some(canary)) if on_canary => canary.target.clone(),
+            (None, _) => {
+                let (index, url) = match upstream {
+                    Some(chosen) => chosen,
+                    // tried_targets 只在还有没试过的上游时才会增长
+                    None => state.upstreams.pick(&tried_targets).expect("no upstream left"),
+                };
+                upstream = Some((index, url));
+                url.to_string()
+            }
+        };
+        ctx.extensions.insert(UpstreamTarget(target.clone()));
+
+        // 提取路径和查询参数 (模型降级时会被改写)
+        let path = ctx.uri.path_and_query().map(|x| x.as_str()).unwrap_or("/").to_string();
+        let mut headers = ctx.headers.clone();
+        if let Some(queue) = &state.request_queue {
+            queue.strip_priority_header(&mut headers);
+        }
+        // 路由规则指定了 Host 时按它发，否则由 reqwest 按目标地址填写
+        let route_host = ctx.extensions.get::<MatchedRoute>().filter(|r| r.0.target == target).and_then(|r| r.0.host.as_deref());
+        if let Some(host) = route_host.and_then(|h| HeaderValue::from_str(h).ok()) {
+            headers.insert(header::HOST, host);
+        }
+        let path = match (&state.key_pool, key.as_deref()) {
+            (Some(pool), Some(entry)) if grpc.is_some() => {
+                pool.inject_header(entry, &mut headers);
+                path.clone()
+            }
+            (Some(pool), Some(entry)) => pool.inject(entry, &mut headers, &path),
+            (Some(pool), None) => {
+                if let Some((index, entry)) = upload.as_ref().and_then(|s| s.key).and_then(|i| pool.entry(i).map(|e| (i, e))) {
+                    pool.inject_header(&entry, &mut headers);
+                    if tried_keys.is_empty() {
+                        tried_keys.push(index);
+                        ctx.extensions.insert(UpstreamKeyId(entry.name.clone()));
+                    }
+                }
+                path.clone()
+            }
+            _ => path.clone(),
+        };
+        if !mirrored {
+            mirrored = true;
+            mirror::send(&state, &ctx.method, &path, &headers, &ctx.body);
+        }
+        let target_uri = format!("{}{}", target, path);
+        match &country {
+            Some(country) => debug!("-> {} {} [{}]", ctx.method, redact_path(&target_uri), country),
+            None => debug!("-> {} {}", ctx.method, redact_path(&target_uri)),
+        }
+
+        // .body(bytes) 这里传入的是 bytes::Bytes 类型
+        // 编译器看到这里会非常高兴，因为 reqwest::Body 实现 From<Bytes>
+        let key_name = ctx.extensions.get::<UpstreamKeyId>().map(|k| k.0.as_str());
+        let upstream_span = otel::upstream_span(&ctx.method, &target, key_name, &mut headers);
+        // 单独配置了代理的默认上游用自己的客户端
+        let own = match state.canary.as_ref().filter(|_| on_canary) {
+            Some(canary) => canary.client(),
+            None => upstream.and_then(|(index, _)| state.upstreams.client(index)),
+        };
+        let client = upstream_client(&state, &plan, own, &mut egress, tried_keys.last().copied(), &tried_proxies);
+        let body = match ctx.extensions.get::<RequestTrailers>() {
+            Some(RequestTrailers(trailers)) => {
+                trailers::declare(&mut headers, trailers);
+                trailers::request_body(ctx.body.clone(), trailers.clone())
+            }
+            None => ctx.body.clone().into(),
+        };
+        let mut request = client.request(ctx.method.clone(), target_uri).headers(headers).body(body);
+        if let Some(total) = state.total_timeout {
+            // 整体超时从请求进入网关算起，换 key / 换上游重试不重新计时；reqwest 把它一直应用到响应体读完
+            request = request.timeout(total.saturating_sub(started_at.elapsed()));
+        }
+        if let Some(prewarm) = &state.prewarm {
+            prewarm.touch();
+        }
+        attempts += 1;
+        let send = tokio::time::timeout(state.first_byte_timeout, request.send()).instrument(upstream_span.clone());
+        let mut awaiting = AwaitingHeaders { state: &state, id, started_at, armed: true };
+        let (result, attempt_timing) = slow_log::measure(send, attempts).await;
+        awaiting.armed = false;
+        timing = Some(attempt_timing);
+        let result = match result {
+            Ok(result) => result.map_err(SendError::Http),
+            Err(_) => Err(SendError::FirstByte(state.first_byte_timeout)),
+        };
+        responded_at = result.as_ref().ok().map(|_| Instant::now());
+        match &result {
+            Ok(response) => otel::record_status(&upstream_span, response.status()),
+            Err(e) => otel::record_error(&upstream_span, &e.to_string()),
+        }
+        // 收到响应头就让出排队放行的名额
+        _dispatch = None;
+
+        // 经过代理池连接失败：这个代理暂停使用，换下一个代理重放；都试过了就交给下面的换上游 / 重试
+        if let (Some(pool), Some(index), Err(e)) = (&state.egress, egress, &result) {
+            if e.is_connect() {
+                pool.mark_down(index);
+                tried_proxies.push(index);
+                warn!("🔌 Proxy {} unreachable ({}), failing over", pool.proxy(index), e);
+                state.metrics.inc("aizasy_proxy_failovers_total", &[("proxy", pool.proxy(index))]);
+                egress = None;
+                if tried_proxies.len() < pool.len() {
+                    continue;
+                }
+                tried_proxies.clear();
+            }
+        }
+
+        // 400 / 403 可能说明 key 本身失效：连续多次就把它隔离
+        let quarantine = state.key_pool.as_ref().filter(|pool| use_key_pool && grpc.is_none() && pool.quarantine_after() > 0);
+        let result = match (quarantine, result, tried_keys.last()) {
+            (Some(pool), Ok(response), Some(&index)) => match key_pool::inspect_rejection(response).await {
+                Ok((response, Some(reason))) => {
+                    if pool.record_rejection(index, reason) {
+                        key_pool::alert_quarantine(&state, index, reason);
+                    }
+                    // 403 下面按冷却换 key；失效 key 的 400 也换一个 key 重放，请求本身没有问题
+                    if response.status() == StatusCode::BAD_REQUEST && tried_keys.len() < pool.max_attempts(key_group) {
+                        state.metrics.inc("aizasy_key_failovers_total", &[]);
+                        key = None;
+                        continue;
+                    }
+                    Ok(response)
+                }
+                Ok((response, None)) => {
+                    pool.record_accepted(index);
+                    Ok(response)
+                }
+                Err(e) => Err(SendError::Http(e)),
+            },
+            (_, result, _) => result,
+        };
+
+        // gRPC 的错误状态在 grpc-status 里，HTTP 状态总是 200
+        let status_of = |response: &reqwest::Response| match grpc {
+            Some(_) => grpc::effective_status(response.status(), response.headers()),
+            None => response.status(),
+        };
+        if let (Some(pool), Ok(response), Some(&index)) = (&state.key_pool, &result, tried_keys.last()) {
+            if let Some(cooldown) = pool.cool_down(index, status_of(response), response.headers()) {
+                let name = ctx.extensions.get::<UpstreamKeyId>().map(|k| k.0.clone()).unwrap_or_default();
+                warn!("🔑 Key {} got {}, cooling down {}s", name, status_of(response), cooldown.as_secs());
+                key_pool::publish_cooldown(&state, index, cooldown);
+                state.metrics.inc("aizasy_key_cooldowns_total", &[("key", &name)]);
+                if tried_keys.len() < pool.max_attempts(key_group) {
+                    state.metrics.inc("aizasy_key_failovers_total", &[]);
+                    key = None;
+                    continue;
+                }
+                // 换 key 的次数用完了还是 429：开了排队就回到队列里等，直到截止时间
+                if let Some(queue) = queue.filter(|_| status_of(response) == StatusCode::TOO_MANY_REQUESTS) {
+                    let ticket = ticket.get_or_insert_with(|| queue.ticket(&ctx.headers, &usage::client_label(&ctx)));
+                    if !ticket.expired() {
+                        key = None;
+                        continue;
+                    }
+                }
+            }
+        }
+        if let (Some(canary), Some(current)) = (&state.canary, side) {
+            let ok = result.as_ref().is_ok_and(|response| !response.status().is_server_error());
+            canary.record(current, ok);
+            let result_label = if ok { "ok" } else { "error" };
+            state.metrics.inc("aizasy_canary_attempts_total", &[("side", current.name()), ("result", result_label)]);
+            let failed = match &result {
+                Ok(response) => response.status().is_server_error(),
+                Err(e) => e.is_connect(),
+            };
+            if current == Side::Canary && failed {
+                match &result {
+                    Ok(response) => warn!("🐤 Canary {} returned {}, falling back to stable", canary.target, response.status()),
+                    Err(e) => warn!("🐤 Canary {} unreachable ({}), falling back to stable", canary.target, e),
+                }
+                state.metrics.inc("aizasy_canary_fallbacks_total", &[]);
+                side = Some(Side::Stable);
+                continue;
+            }
+        }
+        if let Some((index, url)) = upstream {
+            let failed = match &result {
+                Ok(response) => response.status().is_server_error(),
+                Err(e) => e.is_connect(),
+            };
+            if result.as_ref().is_ok_and(|response| !response.status().is_server_error()) {
+                state.upstreams.record_success(index);
+                if let Some(timing) = &timing {
+                    state.upstreams.record_latency(index, timing.ttfb());
+                }
+            }
+            if failed && state.upstreams.len() > 1 {
+                state.upstreams.mark_down(index);
+                tried_targets.push(index);
+                match &result {
+                    Ok(response) => warn!("🌐 Upstream {} returned {}, failing over", url, response.status()),
+                    Err(e) => warn!("🌐 Upstream {} unreachable ({}), failing over", url, e),
+                }
+                if tried_targets.len() < state.upstreams.len() {
+                    state.metrics.inc("aizasy_upstream_failovers_total", &[("target", url)]);
+                    upstream = None;
+                    continue;
+                }
+            }
+        }
+        if let (Some(fallbacks), Some(requested), Ok(response)) = (&state.model_fallbacks, &requested_model, &result) {
+            let current = tried_models.last().cloned().unwrap_or_default();
+            if let Some(next) = fallbacks.next(requested, response.status(), &tried_models).map(str::to_string) {
+                if let Some(uri) = replace_model(&ctx.uri, &current, &next) {
+                    warn!("🪂 Model {} returned {}, falling back to {}", current, response.status(), next);
+                    state.metrics.inc("aizasy_model_fallbacks_total", &[("from", &current), ("to", &next)]);
+                    ctx.uri = uri;
+                    if let Some(body) = map_body_models(&ctx.body, |model| (model == current).then(|| next.clone())) {
+                        ctx.body = body;
+                    }
+                    tried_models.push(next);
+                    // 新模型重新挑 key
+                    if state.key_pool.is_some() {
+                        tried_keys.clear();
+                        key = None;
+                    }
+                    continue;
+                }
+            }
+        }
+        if let Some(retry) = retry {
+            let status = result.as_ref().ok().map(status_of);
+            let connect_error = result.as_ref().err().is_some_and(|e| e.is_connect());
+            if retries < retry.max_retries && RetryConfig::is_transient(status, connect_error) {
+                let delay = retry.delay(retries + 1);
+                // 等完就超过整体超时的话不必再试
+                if state.total_timeout.is_none_or(|total| started_at.elapsed() + delay < total) {
+                    retries += 1;
+                    match &result {
+                        Ok(response) => warn!("🔁 Upstream returned {}, retry {} in {:?}", status_of(response), retries, delay),
+                        Err(e) => warn!("🔁 Upstream unreachable ({}), retry {} in {:?}", e, retries, delay),
+                    }
+                    state.metrics.inc("aizasy_upstream_retries_total", &[]);
+                    drop(result);
+                    tokio::time::sleep(delay).await;
+                    continue;
+                }
+            }
+        }
+        break result;
+    };
+    let upstream_info = UpstreamInfo {
+        status: result.as_ref().ok().map(|r| r.status().as_u16()),
+        target: ctx.extensions.get::<UpstreamTarget>().map(|t| t.0.clone()).unwrap_or_default(),
+        key: ctx.extensions.get::<UpstreamKeyId>().map(|k| k.0.clone()),
+        key_index: tried_keys.last().copied(),
+        timing,
+        responded_at,
+    };
+
+    match result {
+        Ok(response) => {
+            let routed_model = tried_models.last().filter(|_| tried_models.len() > 1).cloned();
+            let relay = Relay {
+                upload,
+                upload_origin,
+                pool_key: tried_keys.last().copied().filter(|_| use_key_pool),
+                routed_model,
+                grpc: grpc.is_some(),
+                slot,
+                info: upstream_info,
+            };
+            relay_response(&state, ctx, response, relay).await
+        }
+        Err(e) => {
+            error!("Proxy error (request {}): {}", id, e);
+            let outcome = Outcome {
+                status: None,
+                bytes_out: 0,
+                duration: started_at.elapsed(),
+                error: Some(e.to_string()),
+            };
+            plugin::emit_outcome(&state.events, ctx.id, &outcome);
+            plugin::run_on_complete(&state.plugins, &ctx, &outcome);
+            let status = match &e {
+                SendError::NoKey => StatusCode::SERVICE_UNAVAILABLE,
+                SendError::Queued(_) | SendError::KeyBusy => StatusCode::TOO_MANY_REQUESTS,
+                SendError::Blocked(_) => StatusCode::FORBIDDEN,
+                e if e.is_timeout() => StatusCode::GATEWAY_TIMEOUT,
+                _ => StatusCode::BAD_GATEWAY,
+            };
+            let mut response = match grpc {
+                Some(_) => grpc::error_response(status, &format!("Gateway Error: {}", e)),
+                None => (status, format!("Gateway Error: {}", e)).into_response(),
+            };
+            response.extensions_mut().insert(upstream_info);
+            response
+        }
+    }
+}
+
+/// 把上游响应转给客户端时要用的、发送循环里得出的状态
+struct Relay {
+    upload: Option<UploadSession>,
+    upload_origin: Option<String>,
+    /// 用的 key 池序号，开续传会话时记下；不用 key 池时为 None
+    pool_key: Option<usize>,
+    /// 模型降级后实际使用的模型
+    routed_model: Option<String>,
+    grpc: bool,
+    /// 占用的 key 并发名额，响应体发完才归还
+    slot: Option<KeySlot>,
+    info: UpstreamInfo,
+}
+
+/// 转发上游响应：处理续传会话和插件的响应头钩子，按需缓冲或流式改写响应体
+async fn relay_response(state: &Arc<AppState>, ctx: RequestContext, response: reqwest::Response, relay: Relay) -> Response {
+    let Relay { upload, upload_origin, pool_key, routed_model, grpc, slot, info: upstream_info } = relay;
+    let id = ctx.id;
+    let started_at = ctx.started_at;
+    let status = response.status();
+    state.events.emit_with(|| GatewayEvent::UpstreamConnected {
+        id: ctx.id,
+        status: status.as_u16(),
+        elapsed_ms: elapsed_ms(started_at.elapsed()),
+    });
+    let mut resp_headers = HeaderMap::new();
+    for (k, v) in response.headers() {
+        resp_headers.append(k, v.clone());
+    }
+    encoding::strip_hop_by_hop(&mut resp_headers);
+    if let Some(uploads) = &state.uploads {
+        match (&upload, &upload_origin) {
+            (Some(session), _) if status.is_success() && uploads::is_finalize(&ctx.headers) => {
+                uploads.remove(&session.token);
+            }
+            (None, Some(origin)) if resp_headers.contains_key(uploads::UPLOAD_URL_HEADER) => {
+                let opened = uploads.register(&mut resp_headers, origin, pool_key);
+                if opened.is_some() {
+                    debug!("📤 Request {} opened an upload session, {} active", id, uploads.len());
+                }
+            }
+            _ => {}
+        }
+    }
+    plugin::run_on_upstream_response(&state.plugins, &ctx, status, &mut resp_headers).await;
+    if let Some(value) = routed_model.and_then(|m| HeaderValue::from_str(&m).ok()) {
+        resp_headers.insert(ROUTED_MODEL_HEADER, value);
+    }
+
+    // 6. 响应流式转发 (Streaming)
+    // 这里我们保持流式，以支持打字机效果
+    let content_length = response.content_length();
+    let limit = state.max_response_size;
+    if let (Some(limit), Some(len)) = (limit, content_length) {
+        if len > limit {
+            // 还没开始转发，可以直接换成 502
+            warn!("📦 Upstream response for request {} is {} bytes, over the {} byte limit", id, len, limit);
+            state.metrics.inc("aizasy_body_too_large_total", &[("direction", "response")]);
+            let outcome = Outcome {
+                status: Some(status),
+                bytes_out: 0,
+                duration: started_at.elapsed(),
+                error: Some(format!("upstream response exceeds {} bytes", limit)),
+            };
+            plugin::emit_outcome(&state.events, ctx.id, &outcome);
+            plugin::run_on_complete(&state.plugins, &ctx, &outcome);
+            let mut response = (StatusCode::BAD_GATEWAY, "Gateway Error: upstream response too large").into_response();
+            response.extensions_mut().insert(upstream_info);
+            return response;
+        }
+    }
+    // 没有 Content-Length (流式响应) 时边转发边计数，超限后以错误结束流，客户端看到连接中断
+    let counted = state.clone();
+    let mut seen = 0u64;
+    // trailers 不经过数据流，响应体发完后再补发给客户端
+    let trailer_slot = TrailerSlot::default();
+    let upstream_body = axum::http::Response::<reqwest::Body>::from(response).into_body();
+    let stream = IdleTimeout::new(
+        trailers::data_stream(upstream_body, trailer_slot.clone()).boxed(),
+        id,
+        state.stream_idle_timeout,
+    );
+    let mut limited = stream
+        .map(move |chunk| {
+            // 响应体发完 (或客户端断开) 时才归还 key 的并发名额
+            let _slot = &slot;
+            let chunk = chunk?;
+            seen += chunk.len() as u64;
+            match limit {
+                Some(limit) if seen > limit => {
+                    warn!("📦 Upstream response for request {} exceeded {} bytes, aborting", id, limit);
+                    counted.metrics.inc("aizasy_body_too_large_total", &[("direction", "response")]);
+                    Err(format!("upstream response exceeds {} bytes", limit))
+                }
+                _ => Ok(chunk),
+            }
+        })
+        .boxed();
+    let mut content_length = content_length;
+
+    // 有插件要改写响应体时，非流式、未压缩的响应先完整缓冲再交给插件；
+    // 上游错误响应同样缓冲，把错误信息里夹带的 key 打码后再返回
+    let streaming = resp_headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+    let encoded = resp_headers.contains_key(header::CONTENT_ENCODING);
+    let upstream_error = status.is_client_error() || status.is_server_error();
+    // gRPC 响应逐帧转发，不缓冲
+    let buffered = !streaming && !encoded && !grpc && (upstream_error || state.plugins.iter().any(|p| p.wants_response_body(&ctx, &resp_headers)));
+    if buffered {
+        // 整个读进内存，没配 --max-response-size 时也不能无限制地读
+        let buffer_limit = state.buffer_limit();
+        let mut body = Vec::new();
+        while let Some(chunk) = limited.next().await {
+            let chunk = chunk.and_then(|chunk| {
+                if body.len() + chunk.len() > buffer_limit {
+                    warn!("📦 Upstream response for request {} exceeded {} bytes while buffering", id, buffer_limit);
+                    state.metrics.inc("aizasy_body_too_large_total", &[("direction", "response")]);
+                    return Err(format!("upstream response exceeds {} bytes", buffer_limit));
+                }
+                Ok(chunk)
+            });
+            match chunk {
+                Ok(chunk) => body.extend_from_slice(&chunk),
+                Err(e) => {
+                    warn!("🧩 Failed to buffer response for request {}: {}", id, e);
+                    let outcome = Outcome {
+                        status: Some(status),
+                        bytes_out: 0,
+                        duration: started_at.elapsed(),
+                        error: Some(e),
+                    };
+                    plugin::emit_outcome(&state.events, ctx.id, &outcome);
+                    plugin::run_on_complete(&state.plugins, &ctx, &outcome);
+                    let mut response = (StatusCode::BAD_GATEWAY, "Gateway Error: failed to read upstream response").into_response();
+                    response.extensions_mut().insert(upstream_info);
+                    return response;
+                }
+            }
+        }
+        let mut body = match std::str::from_utf8(&body).ok().filter(|_| upstream_error).map(sanitize::redact_text) {
+            Some(Cow::Owned(redacted)) => Bytes::from(redacted),
+            _ => Bytes::from(body),
+        };
+        plugin::run_on_response_body(&state.plugins, &ctx, status, &mut resp_headers, &mut body).await;
+        if trailers::has_trailers(&trailer_slot) {
+            // HTTP/1.1 上 trailers 只能跟分块传输一起发
+            resp_headers.remove(header::CONTENT_LENGTH);
+            content_length = None;
+        } else {
+            resp_headers.insert(header::CONTENT_LENGTH, HeaderValue::from(body.len()));
+            content_length = Some(body.len() as u64);
+        }
+        limited = stream::once(async move { Ok(body) }).boxed();
+    }
+    // 流式改写：长度会变，去掉 Content-Length
+    let transformers = if buffered {
+        Vec::new()
+    } else {
+        plugin::stream_transformers(&state.plugins, &ctx, status, &resp_headers)
+    };
+    if !transformers.is_empty() {
+        resp_headers.remove(header::CONTENT_LENGTH);
+        content_length = None;
+    }
+    let resp_stream = PluginStream::new(
+        limited,
+        state.plugins.clone(),
+        state.events.clone(),
+        Arc::new(ctx),
+        status,
+        content_length,
+    );
+    // 缓冲过的响应体已经从上游读完，客户端这时断开不再取消任何东西
+    let resp_stream = if buffered {
+        resp_stream
+    } else {
+        let cancelled = state.clone();
+        let kind = if streaming { "stream" } else { "body" };
+        resp_stream.on_cancel(Box::new(move |bytes| record_cancel(&cancelled, id, kind, bytes, started_at)))
+    };
+    // 改写器套在插件流外面：on_chunk 上的计费、配额看到的是上游原始数据块
+    let resp_stream = if transformers.is_empty() {
+        resp_stream.boxed()
+    } else {
+        plugin::transform_stream(resp_stream, transformers).boxed()
+    };
+    // 压缩过的流里插入明文注释行会把响应体弄坏
+    let keepalive = state.sse_keepalive.filter(|_| streaming && !encoded);
+    let body = Body::new(WithTrailers::new(
+        Body::from_stream(SseKeepalive::new(resp_stream, keepalive)),
+        trailer_slot,
+    ));
+    
+    let mut response = (status, resp_headers, body).into_response();
+    response.extensions_mut().insert(UpstreamResponse);
+    response.extensions_mut().insert(upstream_info);
+    response
+}
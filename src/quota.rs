@@ -0,0 +1,573 @@
+use async_trait::async_trait;
+use axum::{
+    http::{header, HeaderName, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+
+use crate::budget::BudgetPeriod;
+use crate::cached_contents::format_timestamp;
+use crate::plugin::{ChunkAction, GatewayPlugin, Outcome, RequestContext};
+use crate::storage::Storage;
+use crate::usage::{self, UsageScanner};
+
+// --- 按客户端的请求配额 ---
+// 三种计数方式：
+//   fixed   固定窗口，窗口开始时额度重置；可选把上个窗口没用完的额度按比例结转过来
+//   sliding 滑动窗口，用上一窗口计数按时间加权近似，不会在窗口边界出现两倍突发
+//   leaky   漏桶，按 limit / window 的速率匀速放行，桶容量为 limit
+// 窗口可以是固定秒数，也可以是 UTC 自然日 / 自然月 (window=day|month)，用来做每天 / 每月的累计额度。
+// 除了请求数还可以按 token 数计 (unit=tokens)：请求结束后按上游返回的 totalTokenCount 累加，
+// 已经用满时拒绝新请求 (最后一个请求可能超出一点)。
+// 一个客户端可以同时有多条配额 (例如每小时 100 次 + 每天 100 万 token)，任何一条用满都拒绝。
+// 计数放在存储后端里，多实例共享 Redis 时配额也是全局的；用 sqlite / redis 时重启不会清零。
+
+const QUOTA_RESET: HeaderName = HeaderName::from_static("x-aizasy-quota-reset");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaPolicy {
+    Fixed,
+    Sliding,
+    Leaky,
+}
+
+impl std::str::FromStr for QuotaPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fixed" => Ok(Self::Fixed),
+            "sliding" => Ok(Self::Sliding),
+            "leaky" => Ok(Self::Leaky),
+            other => Err(format!("unknown quota policy '{}'", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaUnit {
+    Requests,
+    Tokens,
+}
+
+impl std::str::FromStr for QuotaUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "requests" => Ok(Self::Requests),
+            "tokens" => Ok(Self::Tokens),
+            other => Err(format!("unknown quota unit '{}'", other)),
+        }
+    }
+}
+
+impl QuotaUnit {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Requests => "requests",
+            Self::Tokens => "tokens",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Quota {
+    /// 客户端标识，或 `*` (没有单独配置的客户端各自计算)
+    pub subject: String,
+    /// 每个窗口的请求数 / token 数
+    pub limit: u64,
+    /// 滚动窗口长度，设置了 period 时不使用
+    pub window: Duration,
+    /// 按 UTC 自然日 / 自然月计数
+    pub period: Option<BudgetPeriod>,
+    pub unit: QuotaUnit,
+    pub policy: QuotaPolicy,
+    /// 结转比例 (0-1)：上个窗口剩余额度最多结转 limit × carry_over，只用于 fixed
+    pub carry_over: f64,
+}
+
+// 一个计数窗口
+struct Span {
+    id: String,
+    previous: String,
+    start: u64,
+    end: u64,
+}
+
+impl Quota {
+    /// 解析 `SUBJECT=LIMIT;window=SECS|day|month;unit=requests|tokens;policy=fixed|sliding|leaky;carry_over=RATIO`，
+    /// 默认按小时固定窗口计请求数、不结转
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut parts = spec.split(';');
+        let head = parts.next().unwrap_or("");
+        let (subject, limit) = head
+            .rsplit_once('=')
+            .ok_or_else(|| format!("quota '{}' must be SUBJECT=LIMIT", spec))?;
+        let limit: u64 = limit.trim().parse().map_err(|_| format!("invalid quota limit in '{}'", spec))?;
+        if limit == 0 {
+            return Err(format!("quota limit must be positive in '{}'", spec));
+        }
+        let mut quota = Quota {
+            subject: subject.trim().to_string(),
+            limit,
+            window: Duration::from_secs(3600),
+            period: None,
+            unit: QuotaUnit::Requests,
+            policy: QuotaPolicy::Fixed,
+            carry_over: 0.0,
+        };
+        for part in parts.map(str::trim).filter(|p| !p.is_empty()) {
+            match part.split_once('=') {
+                Some(("window", "day")) => quota.period = Some(BudgetPeriod::Day),
+                Some(("window", "month")) => quota.period = Some(BudgetPeriod::Month),
+                Some(("window", secs)) => {
+                    let secs: u64 = secs.parse().map_err(|_| format!("invalid window in '{}'", spec))?;
+                    quota.window = Duration::from_secs(secs.max(1));
+                    quota.period = None;
+                }
+                Some(("unit", unit)) => quota.unit = unit.parse()?,
+                Some(("policy", policy)) => quota.policy = policy.parse()?,
+                Some(("carry_over", ratio)) => {
+                    quota.carry_over = ratio
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|r| (0.0..=1.0).contains(r))
+                        .ok_or_else(|| format!("carry_over must be between 0 and 1 in '{}'", spec))?;
+                }
+                _ => return Err(format!("unknown quota option '{}'", part)),
+            }
+        }
+        if quota.carry_over > 0.0 && quota.policy != QuotaPolicy::Fixed {
+            return Err(format!("carry_over only applies to fixed windows in '{}'", spec));
+        }
+        if quota.policy == QuotaPolicy::Leaky && quota.period.is_some() {
+            return Err(format!("leaky quotas need a window in seconds in '{}'", spec));
+        }
+        if quota.unit == QuotaUnit::Tokens && quota.policy != QuotaPolicy::Fixed {
+            return Err(format!("token quotas only support fixed windows in '{}'", spec));
+        }
+        Ok(quota)
+    }
+
+    fn span(&self, now: u64) -> Span {
+        match self.period {
+            Some(period) => {
+                let (start, end) = period.bounds(now);
+                Span {
+                    id: period.key(now),
+                    previous: period.key(start.saturating_sub(1)),
+                    start,
+                    end,
+                }
+            }
+            None => {
+                let window_ms = self.window.as_millis() as u64;
+                let index = now / window_ms;
+                Span {
+                    id: index.to_string(),
+                    previous: index.saturating_sub(1).to_string(),
+                    start: index * window_ms,
+                    end: (index + 1) * window_ms,
+                }
+            }
+        }
+    }
+
+    fn window_tag(&self) -> String {
+        match self.period {
+            Some(BudgetPeriod::Day) => "day".to_string(),
+            Some(BudgetPeriod::Month) => "month".to_string(),
+            None => format!("{}s", self.window.as_secs()),
+        }
+    }
+
+    // 按秒计的请求数配额沿用原来的键名 `quota:CLIENT:INDEX`，升级后已经用掉的额度不会清零；
+    // token 配额、自然日 / 月配额带上单位和窗口，不和它冲突
+    fn counter_key(&self, client: &str, id: &str) -> String {
+        match (self.unit, self.period) {
+            (QuotaUnit::Requests, None) => format!("quota:{}:{}", client, id),
+            _ => format!("quota:{}:{}:{}:{}", client, self.unit.as_str(), self.window_tag(), id),
+        }
+    }
+
+    fn leaky_key(&self, client: &str) -> String {
+        format!("quota:{}:leaky:{}:{}", client, self.unit.as_str(), self.window_tag())
+    }
+
+    fn describe(&self) -> String {
+        match self.period {
+            Some(BudgetPeriod::Day) => format!("Daily quota of {} {}", self.limit, self.unit.as_str()),
+            Some(BudgetPeriod::Month) => format!("Monthly quota of {} {}", self.limit, self.unit.as_str()),
+            None => format!("Quota of {} {} per {}s", self.limit, self.unit.as_str(), self.window.as_secs()),
+        }
+    }
+}
+
+// 存储里的一个计数器
+#[derive(Debug, Clone)]
+struct Counter {
+    key: String,
+    ttl: Option<Duration>,
+}
+
+// 一次检查的结果：放行 (带上这次用到的计数器)，或拒绝到什么时候 (unix 毫秒)
+enum Decision {
+    Allow(Option<Counter>),
+    Deny(u64),
+}
+
+// 有 token 配额的请求挂在 RequestContext.extensions 上，结束后把用量加到计数器上
+#[derive(Clone)]
+struct TokenMeter {
+    client: String,
+    counters: Vec<Counter>,
+    scanner: Arc<StdMutex<UsageScanner>>,
+}
+
+pub struct QuotaLimiter {
+    quotas: Vec<Quota>,
+    storage: Arc<dyn Storage>,
+    // 漏桶是读-改-写，至少在单实例内串行化
+    leaky: Mutex<()>,
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+fn read_counter(value: Option<Vec<u8>>) -> i64 {
+    value
+        .and_then(|v| String::from_utf8_lossy(&v).parse().ok())
+        .unwrap_or(0)
+}
+
+impl QuotaLimiter {
+    pub fn new(quotas: Vec<Quota>, storage: Arc<dyn Storage>) -> Self {
+        Self {
+            quotas,
+            storage,
+            leaky: Mutex::new(()),
+        }
+    }
+
+    // 单独配置的客户端优先于 `*`：客户端有自己的配额时 `*` 的配额对它不生效。
+    // 只读的 token 配额先检查，漏桶放在最后 (撤回要读-改-写)
+    fn quotas_for(&self, client: &str) -> Vec<&Quota> {
+        let matching = |subject: &str| self.quotas.iter().filter(|q| q.subject == subject).collect::<Vec<_>>();
+        let mut quotas = matching(client);
+        if quotas.is_empty() {
+            quotas = matching("*");
+        }
+        quotas.sort_by_key(|q| match (q.unit, q.policy) {
+            (QuotaUnit::Tokens, _) => 0,
+            (_, QuotaPolicy::Leaky) => 2,
+            _ => 1,
+        });
+        quotas
+    }
+
+    async fn check(&self, quota: &Quota, client: &str) -> Result<Decision, String> {
+        let now = unix_ms();
+        let span = quota.span(now);
+        let window_ms = span.end - span.start;
+        let key = |id: &str| quota.counter_key(client, id);
+        // 保留两个窗口，供结转 / 滑动窗口读取上一窗口
+        let ttl = Some(Duration::from_millis(window_ms * 2));
+
+        match quota.policy {
+            QuotaPolicy::Fixed => {
+                let mut allowance = quota.limit as f64;
+                if quota.carry_over > 0.0 {
+                    let previous = read_counter(self.storage.get(&key(&span.previous)).await?);
+                    let unused = quota.limit.saturating_sub(previous.max(0) as u64) as f64;
+                    allowance += unused.min(quota.limit as f64 * quota.carry_over);
+                }
+                let counter = Counter { key: key(&span.id), ttl };
+                if quota.unit == QuotaUnit::Tokens {
+                    // token 数要等响应结束才知道，这里只看是否已经用满
+                    let used = read_counter(self.storage.get(&counter.key).await?);
+                    if used as f64 >= allowance {
+                        return Ok(Decision::Deny(span.end));
+                    }
+                    return Ok(Decision::Allow(Some(counter)));
+                }
+                let used = self.storage.incr(&counter.key, 1, ttl).await?;
+                if used as f64 > allowance {
+                    // 被拒绝的请求不占额度
+                    self.storage.incr(&counter.key, -1, ttl).await?;
+                    return Ok(Decision::Deny(span.end));
+                }
+                Ok(Decision::Allow(Some(counter)))
+            }
+            QuotaPolicy::Sliding => {
+                let previous = read_counter(self.storage.get(&key(&span.previous)).await?) as f64;
+                let elapsed = (now - span.start) as f64 / window_ms as f64;
+                let counter = Counter { key: key(&span.id), ttl };
+                let used = self.storage.incr(&counter.key, 1, ttl).await? as f64;
+                if previous * (1.0 - elapsed) + used > quota.limit as f64 {
+                    self.storage.incr(&counter.key, -1, ttl).await?;
+                    // 上一窗口的权重随时间线性下降，粗略估算一个请求的额度空出来的时间
+                    let per_request = window_ms / quota.limit;
+                    return Ok(Decision::Deny(now + per_request.max(1)));
+                }
+                Ok(Decision::Allow(Some(counter)))
+            }
+            QuotaPolicy::Leaky => {
+                let _guard = self.leaky.lock().await;
+                // 同一客户端可以有多个窗口 / 额度不同的漏桶，各用各的键
+                let key = quota.leaky_key(client);
+                let (level, last) = read_bucket(self.storage.get(&key).await?, now);
+                let leaked = now.saturating_sub(last) * quota.limit * 1000 / window_ms;
+                let level = level.saturating_sub(leaked);
+                let capacity = quota.limit * 1000;
+                if level + 1000 > capacity {
+                    let wait_ms = (level + 1000 - capacity) * window_ms / (quota.limit * 1000);
+                    return Ok(Decision::Deny(now + wait_ms.max(1)));
+                }
+                let value = format!("{}:{}", level + 1000, now);
+                self.storage.set(&key, value.into_bytes(), ttl).await?;
+                Ok(Decision::Allow(Some(Counter { key, ttl })))
+            }
+        }
+    }
+
+    // 后面的配额拒绝时把漏桶里这次加的水放掉
+    async fn refund_leaky(&self, counter: &Counter) -> Result<(), String> {
+        let _guard = self.leaky.lock().await;
+        let Some(value) = self.storage.get(&counter.key).await? else {
+            return Ok(());
+        };
+        let (level, last) = read_bucket(Some(value), unix_ms());
+        let value = format!("{}:{}", level.saturating_sub(1000), last);
+        self.storage.set(&counter.key, value.into_bytes(), counter.ttl).await
+    }
+}
+
+// 漏桶的值为 "水位(千分之一请求):上次时间"
+fn read_bucket(value: Option<Vec<u8>>, now: u64) -> (u64, u64) {
+    match value {
+        Some(v) => {
+            let v = String::from_utf8_lossy(&v).to_string();
+            let (level, last) = v.split_once(':').unwrap_or(("0", "0"));
+            (level.parse::<u64>().unwrap_or(0), last.parse::<u64>().unwrap_or(now))
+        }
+        None => (0, now),
+    }
+}
+
+fn rejection(quota: &Quota, client: &str, reset_ms: u64) -> Response {
+    let retry_after = reset_ms.saturating_sub(unix_ms()).div_ceil(1000).max(1);
+    let reset = format_timestamp(reset_ms);
+    let description = format!("{} exceeded for {}", quota.describe(), client);
+    let body = json!({
+        "error": {
+            "code": 429,
+            "message": format!("{}, resets at {}", description, reset),
+            "status": "RESOURCE_EXHAUSTED",
+            "details": [
+                {
+                    "@type": "type.googleapis.com/google.rpc.QuotaFailure",
+                    "violations": [{ "subject": client, "description": description }],
+                },
+                {
+                    "@type": "type.googleapis.com/google.rpc.RetryInfo",
+                    "retryDelay": format!("{}s", retry_after),
+                },
+            ],
+        }
+    });
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, retry_after.to_string()), (QUOTA_RESET, reset)],
+        Json(body),
+    )
+        .into_response()
+}
+
+#[async_trait]
+impl GatewayPlugin for QuotaLimiter {
+    fn name(&self) -> &str {
+        "quota"
+    }
+
+    async fn on_request(&self, ctx: &mut RequestContext) -> Result<(), Response> {
+        let client = usage::client_label(ctx);
+        let quotas = self.quotas_for(&client);
+        // 已经加上的请求计数和漏桶水位，后面的配额拒绝时撤回
+        let mut counted: Vec<Counter> = Vec::new();
+        let mut drained: Vec<Counter> = Vec::new();
+        let mut metered: Vec<Counter> = Vec::new();
+        for quota in quotas {
+            let reset = match self.check(quota, &client).await {
+                Ok(Decision::Allow(counter)) => {
+                    match (quota.unit, counter) {
+                        (_, Some(counter)) if quota.policy == QuotaPolicy::Leaky => drained.push(counter),
+                        (QuotaUnit::Tokens, Some(counter)) => metered.push(counter),
+                        (QuotaUnit::Requests, Some(counter)) => counted.push(counter),
+                        _ => {}
+                    }
+                    continue;
+                }
+                Ok(Decision::Deny(reset)) => reset,
+                Err(e) => {
+                    // 存储不可用时放行
+                    warn!("⏳ Quota check failed for {}: {}", client, e);
+                    continue;
+                }
+            };
+            for counter in &counted {
+                if let Err(e) = self.storage.incr(&counter.key, -1, counter.ttl).await {
+                    warn!("⏳ Failed to release quota for {}: {}", client, e);
+                }
+            }
+            for counter in &drained {
+                if let Err(e) = self.refund_leaky(counter).await {
+                    warn!("⏳ Failed to release quota for {}: {}", client, e);
+                }
+            }
+            debug!("⏳ {} exceeded its quota ({:?}, {})", client, quota.policy, quota.window_tag());
+            return Err(rejection(quota, &client, reset));
+        }
+        if !metered.is_empty() {
+            ctx.extensions.insert(TokenMeter {
+                client,
+                counters: metered,
+                scanner: Arc::new(StdMutex::new(UsageScanner::new())),
+            });
+        }
+        Ok(())
+    }
+
+    fn on_chunk(&self, ctx: &RequestContext, chunk: &axum::body::Bytes) -> ChunkAction {
+        if let Some(meter) = ctx.extensions.get::<TokenMeter>() {
+            meter.scanner.lock().unwrap_or_else(|e| e.into_inner()).feed(chunk);
+        }
+        ChunkAction::Continue
+    }
+
+    fn on_complete(&self, ctx: &RequestContext, _outcome: &Outcome) {
+        let Some(meter) = ctx.extensions.get::<TokenMeter>().cloned() else {
+            return;
+        };
+        // 先把用量拷出来，锁在 spawn 之前就释放
+        let usage = meter.scanner.lock().unwrap_or_else(|e| e.into_inner()).usage();
+        let Some(used) = usage.map(|u| u.total).filter(|t| *t > 0) else {
+            return;
+        };
+        let storage = self.storage.clone();
+        tokio::spawn(async move {
+            for counter in &meter.counters {
+                if let Err(e) = storage.incr(&counter.key, used as i64, counter.ttl).await {
+                    warn!("⏳ Failed to charge {} tokens to {}: {}", used, meter.client, e);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use axum::http::{Extensions, HeaderMap, Method};
+    use std::time::Instant;
+
+    fn request() -> RequestContext {
+        RequestContext {
+            id: 1,
+            method: Method::POST,
+            uri: "/v1beta/models/gemini-2.0-flash:generateContent".parse().unwrap(),
+            headers: HeaderMap::new(),
+            body: Default::default(),
+            client_ip: "127.0.0.1".parse().unwrap(),
+            extensions: Extensions::new(),
+            started_at: Instant::now(),
+        }
+    }
+
+    fn limiter(specs: &[&str], storage: Arc<dyn Storage>) -> QuotaLimiter {
+        QuotaLimiter::new(specs.iter().map(|s| Quota::parse(s).unwrap()).collect(), storage)
+    }
+
+    async fn bucket_level(storage: &dyn Storage, quota: &str) -> u64 {
+        let key = Quota::parse(quota).unwrap().leaky_key("ip:127.0.0.1");
+        read_bucket(storage.get(&key).await.unwrap(), unix_ms()).0
+    }
+
+    #[test]
+    fn parse_defaults_and_options() {
+        let quota = Quota::parse("team-a=100").unwrap();
+        assert_eq!((quota.subject.as_str(), quota.limit), ("team-a", 100));
+        assert_eq!(quota.window, Duration::from_secs(3600));
+        assert_eq!((quota.unit, quota.policy), (QuotaUnit::Requests, QuotaPolicy::Fixed));
+
+        let quota = Quota::parse("*=5000;window=day;unit=tokens").unwrap();
+        assert_eq!(quota.period, Some(BudgetPeriod::Day));
+        assert_eq!(quota.unit, QuotaUnit::Tokens);
+
+        assert!(Quota::parse("a=0").is_err());
+        assert!(Quota::parse("a=10;policy=sliding;carry_over=0.5").is_err());
+        assert!(Quota::parse("a=10;policy=leaky;window=day").is_err());
+        assert!(Quota::parse("a=10;unit=tokens;policy=leaky").is_err());
+        assert!(Quota::parse("a=10;carry_over=1.5").is_err());
+    }
+
+    #[test]
+    fn spans_follow_the_window() {
+        let quota = Quota::parse("a=10;window=60").unwrap();
+        let span = quota.span(125_000);
+        assert_eq!((span.id.as_str(), span.previous.as_str()), ("2", "1"));
+        assert_eq!((span.start, span.end), (120_000, 180_000));
+        assert_eq!(quota.counter_key("c", &span.id), "quota:c:2");
+
+        let tokens = Quota::parse("a=10;window=60;unit=tokens").unwrap();
+        assert_eq!(tokens.counter_key("c", "2"), "quota:c:tokens:60s:2");
+    }
+
+    #[tokio::test]
+    async fn fixed_window_carries_over_unused_allowance() {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+        let quota = Quota::parse("c=2;window=60;carry_over=0.5").unwrap();
+        let limiter = QuotaLimiter::new(vec![quota.clone()], storage.clone());
+        // 上个窗口一个都没用，结转 2 × 0.5 = 1
+        let previous = quota.span(unix_ms()).previous;
+        storage.set(&quota.counter_key("c", &previous), b"0".to_vec(), None).await.unwrap();
+        for _ in 0..3 {
+            assert!(matches!(limiter.check(&quota, "c").await.unwrap(), Decision::Allow(_)));
+        }
+        assert!(matches!(limiter.check(&quota, "c").await.unwrap(), Decision::Deny(_)));
+    }
+
+    #[tokio::test]
+    async fn leaky_quotas_keep_separate_buckets() {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+        let specs = ["*=1;window=60;policy=leaky", "*=5;window=3600;policy=leaky"];
+        let hourly = limiter(&specs[1..], storage.clone());
+        for _ in 0..3 {
+            assert!(hourly.on_request(&mut request()).await.is_ok());
+        }
+        // 每分钟 1 次的桶不受每小时 5 次的桶影响
+        let both = limiter(&specs, storage.clone());
+        assert!(both.on_request(&mut request()).await.is_ok());
+        assert!((1..=1000).contains(&bucket_level(storage.as_ref(), specs[0]).await));
+        assert!((3001..=4000).contains(&bucket_level(storage.as_ref(), specs[1]).await));
+    }
+
+    #[tokio::test]
+    async fn later_denial_refunds_leaky_bucket() {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+        let specs = ["*=10;window=60;policy=leaky", "*=1;window=3600;policy=leaky"];
+        let limiter = limiter(&specs, storage.clone());
+        assert!(limiter.on_request(&mut request()).await.is_ok());
+        let denied = limiter.on_request(&mut request()).await.expect_err("hourly bucket is full");
+        assert_eq!(denied.status(), StatusCode::TOO_MANY_REQUESTS);
+        // 第二次请求在每分钟的桶里加的水已经放掉，只剩第一次的 (减去这段时间漏掉的)
+        assert!(bucket_level(storage.as_ref(), specs[0]).await <= 1000);
+    }
+}
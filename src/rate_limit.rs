@@ -0,0 +1,390 @@
+use async_trait::async_trait;
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
+
+use crate::plugin::{ChunkAction, GatewayPlugin, Outcome, RequestContext};
+use crate::storage::Storage;
+use crate::tenant::CurrentTenant;
+use crate::usage::{self, UsageScanner};
+
+// --- 按客户端的速率限制 (滑动窗口) ---
+// 和 quota 的长周期配额不同，这里限制的是每分钟的请求数 (rpm) 和 token 数 (tpm)，
+// 防止单个客户端把共享的上游 key 池打满。客户端按鉴权身份 (客户端令牌) 区分，没有身份时按来源 IP。
+// 每个客户端每个维度按固定长度的窗口计数，用量 = 上一个窗口 × 还没滑出去的比例 + 当前窗口；
+// 窗口长度是 burst 分钟，额度是每分钟额度 × burst，平均速率不变，允许在一个窗口内集中用掉：
+//   rpm 每个请求先原子地加 1，超出额度时减回去并拒绝；
+//   tpm 在请求结束后按上游返回的 totalTokenCount 累加，可以超出额度，滑出窗口之前拒绝新请求。
+// 主体写成 tenant:NAME 时限制的是整个租户，和租户里每个客户端自己的限额同时生效。
+// 计数器放在存储后端里，只用 INCRBY 更新，多实例共享 Redis 时不会互相覆盖。
+
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    /// 客户端标识、`tenant:NAME` (整个租户)，或 `*` (没有单独配置的客户端各自计算)
+    pub subject: String,
+    pub requests_per_minute: Option<u64>,
+    pub tokens_per_minute: Option<u64>,
+    /// 窗口长度和额度相对一分钟的倍数，允许短时突发
+    pub burst: f64,
+}
+
+impl RateLimit {
+    /// 解析 `SUBJECT;rpm=N;tpm=N;burst=RATIO`，rpm / tpm 至少给一个，burst 默认 1
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut parts = spec.split(';');
+        let subject = parts.next().unwrap_or("").trim();
+        if subject.is_empty() {
+            return Err(format!("rate limit '{}' has no subject", spec));
+        }
+        let mut limit = RateLimit {
+            subject: subject.to_string(),
+            requests_per_minute: None,
+            tokens_per_minute: None,
+            burst: 1.0,
+        };
+        for part in parts.map(str::trim).filter(|p| !p.is_empty()) {
+            let positive = |value: &str| value.parse::<u64>().ok().filter(|v| *v > 0);
+            match part.split_once('=') {
+                Some(("rpm", value)) => {
+                    limit.requests_per_minute =
+                        Some(positive(value).ok_or_else(|| format!("invalid rpm in '{}'", spec))?)
+                }
+                Some(("tpm", value)) => {
+                    limit.tokens_per_minute =
+                        Some(positive(value).ok_or_else(|| format!("invalid tpm in '{}'", spec))?)
+                }
+                Some(("burst", value)) => {
+                    limit.burst = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|b| *b >= 1.0)
+                        .ok_or_else(|| format!("burst must be at least 1 in '{}'", spec))?
+                }
+                _ => return Err(format!("unknown rate limit option '{}'", part)),
+            }
+        }
+        if limit.requests_per_minute.is_none() && limit.tokens_per_minute.is_none() {
+            return Err(format!("rate limit '{}' needs rpm or tpm", spec));
+        }
+        Ok(limit)
+    }
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+// 计数器 key: ratelimit:<主体>:<rpm|tpm>:<窗口序号>，保留两个窗口
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    /// 窗口长度 (ms)
+    length: u64,
+    capacity: f64,
+}
+
+impl Window {
+    fn new(per_minute: u64, burst: f64) -> Self {
+        Self {
+            length: ((60_000.0 * burst) as u64).max(1),
+            capacity: per_minute as f64 * burst,
+        }
+    }
+
+    fn key(subject: &str, dimension: &str, index: u64) -> String {
+        format!("ratelimit:{}:{}:{}", subject, dimension, index)
+    }
+
+    fn ttl(&self) -> Option<Duration> {
+        Some(Duration::from_millis(self.length * 2))
+    }
+
+    /// 上一个窗口和当前窗口的计数
+    async fn counts(&self, storage: &dyn Storage, subject: &str, dimension: &str, now: u64) -> Result<(i64, i64), String> {
+        let index = now / self.length;
+        let previous = count(storage.get(&Self::key(subject, dimension, index.saturating_sub(1))).await?);
+        let current = count(storage.get(&Self::key(subject, dimension, index)).await?);
+        Ok((previous, current))
+    }
+
+    /// 上一个窗口还没滑出去的部分加上当前窗口
+    fn weighted(&self, previous: i64, current: i64, now: u64) -> f64 {
+        let elapsed = (now % self.length) as f64 / self.length as f64;
+        previous.max(0) as f64 * (1.0 - elapsed) + current.max(0) as f64
+    }
+
+    /// 之后没有新用量时，加权用量降到 target 以下还要多久
+    fn wait_until(&self, previous: i64, current: i64, target: f64, now: u64) -> Duration {
+        let offset = (now % self.length) as f64;
+        let length = self.length as f64;
+        let (previous, current) = (previous.max(0) as f64, current.max(0) as f64);
+        // 当前窗口内：previous × (1 - t / length) + current < target
+        let ms = if current < target && previous > 0.0 {
+            ((1.0 - (target - current) / previous) * length - offset).max(0.0)
+        } else {
+            // 等到下一个窗口，当前窗口变成上一个
+            let slide = if current > 0.0 { (1.0 - target / current).max(0.0) * length } else { 0.0 };
+            length - offset + slide
+        };
+        Duration::from_millis(ms.ceil() as u64)
+    }
+}
+
+fn count(value: Option<Vec<u8>>) -> i64 {
+    value.and_then(|v| String::from_utf8_lossy(&v).parse().ok()).unwrap_or(0)
+}
+
+// 请求结束时按实际 token 数累加 tpm 计数；client 是计数的主体 (客户端或 tenant:NAME)
+#[derive(Clone)]
+struct TokenMeter {
+    client: String,
+    window: Window,
+    scanner: Arc<StdMutex<UsageScanner>>,
+}
+
+#[derive(Clone)]
+struct TokenMeters(Vec<TokenMeter>);
+
+// 放行时已经加上的 rpm 计数，后面的主体拒绝时撤回
+struct Counter {
+    key: String,
+    ttl: Option<Duration>,
+}
+
+enum Decision {
+    Allow(Option<Counter>),
+    /// 需要等待的时间和被耗尽的维度
+    Deny(Duration, &'static str),
+}
+
+pub struct RateLimiter {
+    limits: Vec<RateLimit>,
+    storage: Arc<dyn Storage>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: Vec<RateLimit>, storage: Arc<dyn Storage>) -> Self {
+        Self { limits, storage }
+    }
+
+    // 单独配置的客户端优先于 `*`
+    fn limit_for(&self, client: &str) -> Option<&RateLimit> {
+        self.limits
+            .iter()
+            .find(|l| l.subject == client)
+            .or_else(|| self.limits.iter().find(|l| l.subject == "*"))
+    }
+
+    /// 放行时记上一个请求，返回加上的 rpm 计数
+    async fn check(&self, limit: &RateLimit, client: &str) -> Result<Decision, String> {
+        let now = unix_ms();
+        if let Some(tpm) = limit.tokens_per_minute {
+            let window = Window::new(tpm, limit.burst);
+            let (previous, current) = window.counts(self.storage.as_ref(), client, "tpm", now).await?;
+            if window.weighted(previous, current, now) >= window.capacity {
+                return Ok(Decision::Deny(window.wait_until(previous, current, window.capacity, now), "tokens"));
+            }
+        }
+        let Some(rpm) = limit.requests_per_minute else {
+            return Ok(Decision::Allow(None));
+        };
+        let window = Window::new(rpm, limit.burst);
+        let key = Window::key(client, "rpm", now / window.length);
+        // 先加再判断，并发的请求各自看到加过自己之后的值
+        let current = self.storage.incr(&key, 1, window.ttl()).await?;
+        let previous = count(self.storage.get(&Window::key(client, "rpm", (now / window.length).saturating_sub(1))).await?);
+        if window.weighted(previous, current, now) > window.capacity {
+            // 被拒绝的请求不占额度
+            self.storage.incr(&key, -1, window.ttl()).await?;
+            return Ok(Decision::Deny(window.wait_until(previous, current - 1, window.capacity - 1.0, now), "requests"));
+        }
+        Ok(Decision::Allow(Some(Counter { key, ttl: window.ttl() })))
+    }
+
+    // 按这次请求实际用掉的 token 数累加 tpm 计数
+    fn charge(&self, meter: TokenMeter) {
+        // 先把用量拷出来，锁在 spawn 之前就释放
+        let usage = meter.scanner.lock().unwrap_or_else(|e| e.into_inner()).usage();
+        let Some(used) = usage.map(|u| u.total).filter(|t| *t > 0) else {
+            return;
+        };
+        let storage = self.storage.clone();
+        tokio::spawn(async move {
+            let window = meter.window;
+            let key = Window::key(&meter.client, "tpm", unix_ms() / window.length);
+            if let Err(e) = storage.incr(&key, used as i64, window.ttl()).await {
+                warn!("🚦 Failed to charge {} tokens to {}: {}", used, meter.client, e);
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl GatewayPlugin for RateLimiter {
+    fn name(&self) -> &str {
+        "rate_limit"
+    }
+
+    async fn on_request(&self, ctx: &mut RequestContext) -> Result<(), Response> {
+        // 租户整体的限额 (tenant:NAME) 和按客户端的同时生效，先扣租户的
+        let tenant = ctx.extensions.get::<CurrentTenant>().map(|t| format!("tenant:{}", t.0.name));
+        let tenant = tenant.and_then(|subject| self.limits.iter().find(|l| l.subject == subject).map(|l| (subject, l)));
+        let client = usage::client_label(ctx);
+        let client = self.limit_for(&client).map(|l| (client, l));
+        let mut meters = Vec::new();
+        // 已经加上的请求计数，后面的主体拒绝时撤回
+        let mut counted: Vec<Counter> = Vec::new();
+        for (subject, limit) in tenant.into_iter().chain(client) {
+            let (wait, kind) = match self.check(limit, &subject).await {
+                Ok(Decision::Allow(counter)) => {
+                    counted.extend(counter);
+                    if let Some(tpm) = limit.tokens_per_minute {
+                        meters.push(TokenMeter {
+                            client: subject,
+                            window: Window::new(tpm, limit.burst),
+                            scanner: Arc::new(StdMutex::new(UsageScanner::new())),
+                        });
+                    }
+                    continue;
+                }
+                Ok(Decision::Deny(wait, kind)) => (wait, kind),
+                Err(e) => {
+                    // 存储不可用时放行
+                    warn!("🚦 Rate limit check failed for {}: {}", subject, e);
+                    continue;
+                }
+            };
+            for counter in &counted {
+                if let Err(e) = self.storage.incr(&counter.key, -1, counter.ttl).await {
+                    warn!("🚦 Failed to release rate limit for {}: {}", counter.key, e);
+                }
+            }
+            debug!("🚦 {} is over its {} per minute limit", subject, kind);
+            let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+            let body = json!({
+                "error": {
+                    "code": 429,
+                    "message": format!("Rate limit of {} per minute exceeded for {}, retry in {}s", kind, subject, retry_after),
+                    "status": "RESOURCE_EXHAUSTED",
+                }
+            });
+            return Err((StatusCode::TOO_MANY_REQUESTS, [(header::RETRY_AFTER, retry_after.to_string())], Json(body)).into_response());
+        }
+        if !meters.is_empty() {
+            ctx.extensions.insert(TokenMeters(meters));
+        }
+        Ok(())
+    }
+
+    fn on_chunk(&self, ctx: &RequestContext, chunk: &axum::body::Bytes) -> ChunkAction {
+        for meter in ctx.extensions.get::<TokenMeters>().map(|m| m.0.as_slice()).unwrap_or_default() {
+            meter.scanner.lock().unwrap_or_else(|e| e.into_inner()).feed(chunk);
+        }
+        ChunkAction::Continue
+    }
+
+    fn on_complete(&self, ctx: &RequestContext, _outcome: &Outcome) {
+        for meter in ctx.extensions.get::<TokenMeters>().map(|m| m.0.clone()).unwrap_or_default() {
+            self.charge(meter);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::tenant::Tenant;
+    use axum::http::{Extensions, HeaderMap, Method};
+    use std::time::Instant;
+
+    fn request(tenant: &str) -> RequestContext {
+        let mut extensions = Extensions::new();
+        extensions.insert(CurrentTenant(Arc::new(Tenant {
+            name: tenant.to_string(),
+            ..Default::default()
+        })));
+        RequestContext {
+            id: 1,
+            method: Method::POST,
+            uri: "/v1beta/models/gemini-2.0-flash:generateContent".parse().unwrap(),
+            headers: HeaderMap::new(),
+            body: Default::default(),
+            client_ip: "127.0.0.1".parse().unwrap(),
+            extensions,
+            started_at: Instant::now(),
+        }
+    }
+
+    async fn requests_counted(storage: &dyn Storage, subject: &str) -> i64 {
+        // 跨分钟边界时计数可能落在上一个窗口
+        let index = unix_ms() / 60_000;
+        let current = count(storage.get(&Window::key(subject, "rpm", index)).await.unwrap());
+        let previous = count(storage.get(&Window::key(subject, "rpm", index - 1)).await.unwrap());
+        current + previous
+    }
+
+    #[tokio::test]
+    async fn client_denial_releases_tenant_request() {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+        let limits = vec![
+            RateLimit::parse("tenant:team;rpm=10").unwrap(),
+            RateLimit::parse("ip:127.0.0.1;rpm=1").unwrap(),
+        ];
+        let limiter = RateLimiter::new(limits, storage.clone());
+
+        assert!(limiter.on_request(&mut request("team")).await.is_ok());
+        let denied = limiter.on_request(&mut request("team")).await.expect_err("client limit reached");
+        assert_eq!(denied.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(limiter.on_request(&mut request("team")).await.is_err());
+
+        assert_eq!(requests_counted(storage.as_ref(), "tenant:team").await, 1);
+        assert_eq!(requests_counted(storage.as_ref(), "ip:127.0.0.1").await, 1);
+    }
+
+    #[test]
+    fn parses_specs() {
+        let limit = RateLimit::parse("ci; rpm=60 ;tpm=1000;burst=1.5").unwrap();
+        assert_eq!(limit.subject, "ci");
+        assert_eq!(limit.requests_per_minute, Some(60));
+        assert_eq!(limit.tokens_per_minute, Some(1000));
+        assert_eq!(limit.burst, 1.5);
+        assert_eq!(RateLimit::parse("*;tpm=5").unwrap().burst, 1.0);
+        for bad in ["ci", ";rpm=1", "ci;rpm=0", "ci;rpm=x", "ci;rpm=1;burst=0.5", "ci;rph=1"] {
+            assert!(RateLimit::parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn burst_stretches_the_window() {
+        let window = Window::new(10, 2.0);
+        assert_eq!(window.length, 120_000);
+        assert_eq!(window.capacity, 20.0);
+        assert_eq!(window.ttl(), Some(Duration::from_secs(240)));
+    }
+
+    #[test]
+    fn previous_window_slides_out() {
+        let window = Window::new(60, 1.0);
+        assert_eq!(window.weighted(30, 10, 0), 40.0);
+        assert_eq!(window.weighted(30, 10, 15_000), 32.5);
+        assert_eq!(window.weighted(30, 10, 59_999 + 60_000 * 7).round(), 10.0);
+        // rpm 拒绝时会把计数减回去，负数按 0 算
+        assert_eq!(window.weighted(-1, -1, 0), 0.0);
+    }
+
+    #[test]
+    fn wait_until_below_target() {
+        let window = Window::new(60, 1.0);
+        // 上一个窗口滑出一半时降到 30
+        assert_eq!(window.wait_until(60, 0, 30.0, 15_000), Duration::from_secs(15));
+        assert_eq!(window.wait_until(60, 0, 30.0, 45_000), Duration::ZERO);
+        // 当前窗口已经超了：等到下一个窗口，再等当前窗口滑出一半
+        assert_eq!(window.wait_until(0, 60, 30.0, 15_000), Duration::from_secs(75));
+    }
+}
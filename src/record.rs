@@ -0,0 +1,288 @@
+use async_trait::async_trait;
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Router,
+};
+use base64::Engine;
+use futures_util::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+use crate::plugin::{ChunkAction, GatewayPlugin, Outcome, RequestContext};
+pub use crate::sanitize::sanitize_path;
+use crate::sanitize::sanitize_headers;
+
+// --- 流量录制与回放 ---
+// 录制：每个请求写成一个 JSON 文件 (已脱敏)，包括每个响应分块相对请求开始的时间。
+// 回放：把录制目录当作假上游，按 method + path + body 哈希匹配并按原节奏吐出分块。
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct Payload {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base64: Option<String>,
+}
+
+impl Payload {
+    fn from_bytes(bytes: &[u8]) -> Self {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => Self { text: Some(text.to_string()), base64: None },
+            Err(_) => Self {
+                text: None,
+                base64: Some(base64::engine::general_purpose::STANDARD.encode(bytes)),
+            },
+        }
+    }
+
+    fn to_bytes(&self) -> Bytes {
+        if let Some(text) = &self.text {
+            return Bytes::from(text.clone());
+        }
+        self.base64
+            .as_ref()
+            .and_then(|b| base64::engine::general_purpose::STANDARD.decode(b).ok())
+            .map(Bytes::from)
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RecordedChunk {
+    /// 相对请求开始的毫秒数
+    pub at_ms: u64,
+    #[serde(flatten)]
+    pub data: Payload,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Recording {
+    pub method: String,
+    /// 已去掉敏感 query 参数
+    pub path: String,
+    pub request_headers: Vec<(String, String)>,
+    pub request_body: Payload,
+    /// 请求体 SHA-256，回放匹配用
+    pub request_body_sha256: String,
+    pub status: Option<u16>,
+    pub response_headers: Vec<(String, String)>,
+    pub chunks: Vec<RecordedChunk>,
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+fn body_hash(body: &[u8]) -> String {
+    hex::encode(Sha256::digest(body))
+}
+
+// 录制中的请求挂在 RequestContext.extensions 上
+#[derive(Clone)]
+struct InFlight(Arc<Mutex<Recording>>);
+
+pub struct Recorder {
+    dir: PathBuf,
+    counter: AtomicU64,
+}
+
+impl Recorder {
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self, String> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        Ok(Self { dir, counter: AtomicU64::new(0) })
+    }
+
+    /// 目录已存在时必须是目录，不存在时检查能否在最近的上级目录里创建；不创建任何东西，配置检查用
+    pub fn check(dir: &Path) -> Result<(), String> {
+        let existing = dir.ancestors().find(|path| path.exists()).unwrap_or(Path::new("."));
+        if existing.is_dir() {
+            Ok(())
+        } else {
+            Err(format!("{}: {} is not a directory", dir.display(), existing.display()))
+        }
+    }
+
+    fn file_name(&self) -> PathBuf {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        let seq = self.counter.fetch_add(1, Ordering::Relaxed);
+        self.dir.join(format!("{}-{:06}.json", now, seq))
+    }
+}
+
+#[async_trait]
+impl GatewayPlugin for Recorder {
+    fn name(&self) -> &str {
+        "recorder"
+    }
+
+    async fn on_request(&self, ctx: &mut RequestContext) -> Result<(), Response> {
+        let path = ctx.uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+        let recording = Recording {
+            method: ctx.method.to_string(),
+            path: sanitize_path(path),
+            request_headers: sanitize_headers(&ctx.headers),
+            request_body: Payload::from_bytes(&ctx.body),
+            request_body_sha256: body_hash(&ctx.body),
+            status: None,
+            response_headers: Vec::new(),
+            chunks: Vec::new(),
+            error: None,
+            duration_ms: 0,
+        };
+        ctx.extensions.insert(InFlight(Arc::new(Mutex::new(recording))));
+        Ok(())
+    }
+
+    async fn on_upstream_response(&self, ctx: &RequestContext, status: StatusCode, headers: &mut HeaderMap) {
+        if let Some(InFlight(rec)) = ctx.extensions.get::<InFlight>() {
+            let mut rec = rec.lock().unwrap();
+            rec.status = Some(status.as_u16());
+            rec.response_headers = sanitize_headers(headers);
+        }
+    }
+
+    fn on_chunk(&self, ctx: &RequestContext, chunk: &Bytes) -> ChunkAction {
+        if let Some(InFlight(rec)) = ctx.extensions.get::<InFlight>() {
+            rec.lock().unwrap().chunks.push(RecordedChunk {
+                at_ms: ctx.started_at.elapsed().as_millis() as u64,
+                data: Payload::from_bytes(chunk),
+            });
+        }
+        ChunkAction::Continue
+    }
+
+    fn on_complete(&self, ctx: &RequestContext, outcome: &Outcome) {
+        let Some(InFlight(rec)) = ctx.extensions.get::<InFlight>() else {
+            return;
+        };
+        let mut recording = rec.lock().unwrap().clone();
+        recording.error = outcome.error.clone();
+        recording.duration_ms = outcome.duration.as_millis() as u64;
+
+        let file = self.file_name();
+        tokio::task::spawn_blocking(move || {
+            let json = serde_json::to_vec_pretty(&recording).unwrap_or_default();
+            if let Err(e) = std::fs::write(&file, json) {
+                warn!("📼 Failed to write recording {}: {}", file.display(), e);
+            }
+        });
+    }
+}
+
+// --- 回放 ---
+
+struct ReplayState {
+    // (method, path, body hash) -> 录制列表；同 key 多条时轮流返回
+    exact: HashMap<(String, String, String), Vec<Recording>>,
+    // (method, path) 兜底
+    loose: HashMap<(String, String), Vec<Recording>>,
+    cursor: AtomicUsize,
+    // false 时立即吐出全部分块
+    preserve_timing: bool,
+}
+
+pub fn load_recordings(dir: &Path) -> Result<Vec<Recording>, String> {
+    let mut recordings = Vec::new();
+    let entries = std::fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().map(|ext| ext == "json").unwrap_or(false))
+        .collect();
+    files.sort();
+    for file in files {
+        let data = std::fs::read(&file).map_err(|e| format!("{}: {}", file.display(), e))?;
+        match serde_json::from_slice::<Recording>(&data) {
+            Ok(rec) => recordings.push(rec),
+            Err(e) => warn!("📼 Skipping {}: {}", file.display(), e),
+        }
+    }
+    Ok(recordings)
+}
+
+/// 用录制目录构建一个假上游 Router
+pub fn replay_router(dir: &Path, preserve_timing: bool) -> Result<Router, String> {
+    let recordings = load_recordings(dir)?;
+    info!("📼 Loaded {} recordings from {}", recordings.len(), dir.display());
+
+    let mut exact: HashMap<_, Vec<Recording>> = HashMap::new();
+    let mut loose: HashMap<_, Vec<Recording>> = HashMap::new();
+    for rec in recordings {
+        exact
+            .entry((rec.method.clone(), rec.path.clone(), rec.request_body_sha256.clone()))
+            .or_default()
+            .push(rec.clone());
+        loose.entry((rec.method.clone(), rec.path.clone())).or_default().push(rec);
+    }
+
+    let state = Arc::new(ReplayState {
+        exact,
+        loose,
+        cursor: AtomicUsize::new(0),
+        preserve_timing,
+    });
+    Ok(Router::new().fallback(replay_handler).with_state(state))
+}
+
+/// 在 127.0.0.1 随机端口启动回放上游
+pub async fn spawn_replay(dir: &Path, preserve_timing: bool) -> Result<SocketAddr, String> {
+    let app = replay_router(dir, preserve_timing)?;
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .map_err(|e| e.to_string())?;
+    let addr = listener.local_addr().map_err(|e| e.to_string())?;
+    tokio::spawn(async move {
+        let _ = axum::serve(listener, app).await;
+    });
+    Ok(addr)
+}
+
+async fn replay_handler(State(state): State<Arc<ReplayState>>, req: Request) -> Response {
+    let method = req.method().to_string();
+    let path = sanitize_path(req.uri().path_and_query().map(|p| p.as_str()).unwrap_or("/"));
+    let body = axum::body::to_bytes(req.into_body(), usize::MAX).await.unwrap_or_default();
+
+    let candidates = state
+        .exact
+        .get(&(method.clone(), path.clone(), body_hash(&body)))
+        .or_else(|| state.loose.get(&(method.clone(), path.clone())));
+    let Some(candidates) = candidates.filter(|c| !c.is_empty()) else {
+        warn!("📼 No recording for {} {}", method, path);
+        return (StatusCode::NOT_FOUND, "No recording matches this request").into_response();
+    };
+    let rec = candidates[state.cursor.fetch_add(1, Ordering::Relaxed) % candidates.len()].clone();
+
+    let status = rec.status.and_then(|s| StatusCode::from_u16(s).ok()).unwrap_or(StatusCode::BAD_GATEWAY);
+    let mut headers = HeaderMap::new();
+    for (k, v) in &rec.response_headers {
+        // 长度和传输编码由回放服务自己决定
+        if k == "content-length" || k == "transfer-encoding" {
+            continue;
+        }
+        if let (Ok(k), Ok(v)) = (HeaderName::try_from(k.as_str()), HeaderValue::from_str(v)) {
+            headers.append(k, v);
+        }
+    }
+
+    let preserve_timing = state.preserve_timing;
+    let started = Instant::now();
+    let chunks = stream::iter(rec.chunks).then(move |chunk| async move {
+        if preserve_timing {
+            let due = Duration::from_millis(chunk.at_ms);
+            if let Some(wait) = due.checked_sub(started.elapsed()) {
+                tokio::time::sleep(wait).await;
+            }
+        }
+        Ok::<_, std::convert::Infallible>(chunk.data.to_bytes())
+    });
+
+    (status, headers, Body::from_stream(chunks)).into_response()
+}
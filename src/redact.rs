@@ -0,0 +1,447 @@
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{body::Bytes, Json};
+use regex::{Captures, Regex};
+use serde_json::{json, Value};
+use std::borrow::Cow;
+use std::ops::Range;
+use tracing::{debug, warn};
+
+use crate::plugin::{GatewayPlugin, RequestContext};
+use crate::uploads;
+
+// --- 请求体内容脱敏 ---
+// 转发前把请求体里的敏感内容 (邮箱、电话、卡号、自定义正则) 替换掉，不让它离开内网。
+// 规则写法: PATTERN[;path=JSONPATH][;with=TEXT]
+//   PATTERN   email / phone / card，或 regex:表达式
+//   path      只处理这些位置的字符串，支持 $.a.b、[*]、[N]、..name (任意深度)；
+//             不写时处理整个 JSON 里的所有字符串 (inlineData 里的 base64 跳过)
+//   with      替换成的文本，默认 [REDACTED]；写 with= (空) 表示直接删掉
+// 请求体不是 JSON 时按纯文本整体替换 (path 规则跳过)。
+// card 只认通过 Luhn 校验的 13 到 19 位数字，phone 要求带区号分隔符、括号或国家码 (中国大陆手机号除外)，
+// 两者前后都不能紧挨着数字，订单号、时间戳这类普通数字串不会被误伤。
+// 请求体在这之前已经完整缓冲 (上限见 --max-request-size)，规则作用在整个请求体上，不存在被切开的值。
+// JSON 请求体会先解析成 serde_json::Value，所有字符串 (包括 base64 附件) 都会复制一份；
+// 没有命中时原样转发，命中时才重新序列化。
+//
+// 断点续传上传 (File API) 的文件内容是一块一块分开发的：一个值可能被切在两块之间，单独处理每一块查不出来，
+// 替换又会改变长度、打乱上传协议的偏移量。所以配置了规则时：
+//   - 一次发完的文本上传 (upload, finalize 且偏移为 0) 按原长度用 * 遮盖命中的内容 (with= 不生效)；
+//   - 分多块发的文本上传直接拒绝 (400)，要求一次上传；二进制内容 (不是 UTF-8) 照常转发。
+
+const DEFAULT_REPLACEMENT: &str = "[REDACTED]";
+
+const PRESETS: &[(&str, &str)] = &[
+    ("email", r"[A-Za-z0-9._%+\-]+@[A-Za-z0-9\-]+(?:\.[A-Za-z0-9\-]+)*\.[A-Za-z]{2,}"),
+    (
+        "phone",
+        r"\+\d{1,3}[\s.\-]?\d{2,4}[\s.\-]?\d{3,4}[\s.\-]?\d{4}\b|(?:\(\d{2,4}\)\s?|\b\d{2,4}[\s.\-])\d{3,4}[\s.\-]?\d{4}\b|\b1[3-9]\d{9}\b",
+    ),
+    ("card", r"\b\d(?:[\s\-]?\d){12,18}\b"),
+];
+
+// 预设规则命中后的二次校验：整段文本和命中的范围
+type Check = fn(&str, Range<usize>) -> bool;
+
+fn check_for(preset: &str) -> Option<Check> {
+    match preset {
+        "card" => Some(valid_card),
+        "phone" => Some(isolated),
+        _ => None,
+    }
+}
+
+// 下一个字符是数字，或者是分隔符后面接着数字
+fn digit_next(mut chars: impl Iterator<Item = char>) -> bool {
+    match chars.next() {
+        Some(c) if c.is_ascii_digit() => true,
+        Some(' ' | '-' | '.') => chars.next().is_some_and(|c| c.is_ascii_digit()),
+        _ => false,
+    }
+}
+
+// 命中的前后都不挨着数字 (否则说明是更长的数字串的一部分)
+fn isolated(text: &str, range: Range<usize>) -> bool {
+    !digit_next(text[..range.start].chars().rev()) && !digit_next(text[range.end..].chars())
+}
+
+// Luhn 校验，位数在 13 到 19 之间
+fn valid_card(text: &str, range: Range<usize>) -> bool {
+    let digits: Vec<u32> = text[range.clone()].chars().filter_map(|c| c.to_digit(10)).collect();
+    if !(13..=19).contains(&digits.len()) || !isolated(text, range) {
+        return false;
+    }
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, d)| match i % 2 {
+            1 if *d * 2 > 9 => *d * 2 - 9,
+            1 => *d * 2,
+            _ => *d,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Key(String),
+    /// [*] 或 .*
+    Any,
+    Index(usize),
+    /// ..name
+    Descend(String),
+}
+
+fn parse_path(path: &str) -> Result<Vec<Segment>, String> {
+    let invalid = || format!("invalid JSON path '{}'", path);
+    let mut rest = path.strip_prefix('$').ok_or_else(invalid)?;
+    let mut segments = Vec::new();
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("..") {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            if end == 0 {
+                return Err(invalid());
+            }
+            segments.push(Segment::Descend(after[..end].to_string()));
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            segments.push(match &after[..end] {
+                "" => return Err(invalid()),
+                "*" => Segment::Any,
+                key => Segment::Key(key.to_string()),
+            });
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']').ok_or_else(invalid)?;
+            segments.push(match after[..end].trim_matches(['\'', '"']) {
+                "*" => Segment::Any,
+                inner => match inner.parse() {
+                    Ok(index) => Segment::Index(index),
+                    Err(_) if !inner.is_empty() => Segment::Key(inner.to_string()),
+                    Err(_) => return Err(invalid()),
+                },
+            });
+            rest = &after[end + 1..];
+        } else {
+            return Err(invalid());
+        }
+    }
+    Ok(segments)
+}
+
+struct Rule {
+    name: String,
+    pattern: Regex,
+    check: Option<Check>,
+    path: Option<Vec<Segment>>,
+    replacement: String,
+}
+
+impl Rule {
+    fn parse(spec: &str) -> Result<Self, String> {
+        let mut parts: Vec<&str> = spec.split(';').collect();
+        let mut path = None;
+        let mut replacement = None;
+        // 选项只认末尾的 path= / with=，正则里的分号原样保留
+        while parts.len() > 1 {
+            let last = parts[parts.len() - 1].trim();
+            if let Some(value) = last.strip_prefix("path=") {
+                path = Some(parse_path(value.trim())?);
+            } else if let Some(value) = last.strip_prefix("with=") {
+                replacement = Some(value.to_string());
+            } else {
+                break;
+            }
+            parts.pop();
+        }
+        let pattern = parts.join(";");
+        let (name, regex) = match pattern.strip_prefix("regex:") {
+            Some(regex) => ("regex".to_string(), regex.to_string()),
+            None => {
+                let name = pattern.trim();
+                let (_, regex) = PRESETS.iter().find(|(preset, _)| *preset == name).ok_or_else(|| {
+                    let names: Vec<&str> = PRESETS.iter().map(|(preset, _)| *preset).collect();
+                    format!("unknown redaction pattern '{}' (expected {} or regex:...)", name, names.join(", "))
+                })?;
+                (name.to_string(), regex.to_string())
+            }
+        };
+        let pattern = Regex::new(&regex).map_err(|e| format!("invalid redaction regex '{}': {}", regex, e))?;
+        Ok(Self {
+            check: check_for(&name),
+            name,
+            pattern,
+            path,
+            replacement: replacement.unwrap_or_else(|| DEFAULT_REPLACEMENT.to_string()),
+        })
+    }
+
+    /// 替换一段文本，返回命中次数
+    fn redact_str(&self, text: &mut String) -> usize {
+        self.replace(text, |_| self.replacement.clone())
+    }
+
+    /// 按原长度用 * 遮盖，给不能改变长度的上传内容用
+    fn mask_str(&self, text: &mut String) -> usize {
+        self.replace(text, |matched| "*".repeat(matched.len()))
+    }
+
+    fn replace(&self, text: &mut String, with: impl Fn(&str) -> String) -> usize {
+        let mut hits = 0;
+        let haystack = text.as_str();
+        let replaced = self.pattern.replace_all(haystack, |caps: &Captures| {
+            let matched = caps.get(0).expect("group 0 always matches");
+            if self.check.is_some_and(|check| !check(haystack, matched.range())) {
+                return matched.as_str().to_string();
+            }
+            hits += 1;
+            with(matched.as_str())
+        });
+        if let Cow::Owned(replaced) = replaced {
+            *text = replaced;
+        }
+        hits
+    }
+
+    fn redact_value(&self, value: &mut Value) -> usize {
+        match &self.path {
+            Some(path) => self.apply_path(value, path),
+            None => self.redact_all(value),
+        }
+    }
+
+    /// 处理 value 下面的所有字符串
+    fn redact_all(&self, value: &mut Value) -> usize {
+        match value {
+            Value::String(text) => self.redact_str(text),
+            Value::Array(items) => items.iter_mut().map(|item| self.redact_all(item)).sum(),
+            Value::Object(map) => map
+                .iter_mut()
+                .filter(|(key, _)| key.as_str() != "inlineData" && key.as_str() != "inline_data")
+                .map(|(_, item)| self.redact_all(item))
+                .sum(),
+            _ => 0,
+        }
+    }
+
+    fn apply_path(&self, value: &mut Value, path: &[Segment]) -> usize {
+        let Some((segment, rest)) = path.split_first() else {
+            return self.redact_all(value);
+        };
+        match (segment, value) {
+            (Segment::Key(key), Value::Object(map)) => map.get_mut(key).map_or(0, |v| self.apply_path(v, rest)),
+            (Segment::Index(index), Value::Array(items)) => items.get_mut(*index).map_or(0, |v| self.apply_path(v, rest)),
+            (Segment::Any, Value::Array(items)) => items.iter_mut().map(|v| self.apply_path(v, rest)).sum(),
+            (Segment::Any, Value::Object(map)) => map.values_mut().map(|v| self.apply_path(v, rest)).sum(),
+            (Segment::Descend(key), value) => self.descend(value, key, rest),
+            _ => 0,
+        }
+    }
+
+    fn descend(&self, value: &mut Value, key: &str, rest: &[Segment]) -> usize {
+        match value {
+            Value::Object(map) => {
+                let hits = map.get_mut(key).map_or(0, |v| self.apply_path(v, rest));
+                hits + map.values_mut().map(|v| self.descend(v, key, rest)).sum::<usize>()
+            }
+            Value::Array(items) => items.iter_mut().map(|v| self.descend(v, key, rest)).sum(),
+            _ => 0,
+        }
+    }
+}
+
+pub struct BodyRedactor {
+    rules: Vec<Rule>,
+}
+
+impl BodyRedactor {
+    pub fn parse(specs: &[String]) -> Result<Self, String> {
+        let rules = specs.iter().map(|spec| Rule::parse(spec)).collect::<Result<_, _>>()?;
+        Ok(Self { rules })
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// 脱敏后的请求体和每条规则的命中次数；没有命中时返回 None
+    fn redact(&self, body: &Bytes) -> Option<(Bytes, Vec<(&str, usize)>)> {
+        let mut hits = Vec::new();
+        if let Ok(mut json) = serde_json::from_slice::<Value>(body) {
+            for rule in &self.rules {
+                let n = rule.redact_value(&mut json);
+                if n > 0 {
+                    hits.push((rule.name.as_str(), n));
+                }
+            }
+            return (!hits.is_empty()).then(|| (Bytes::from(json.to_string()), hits));
+        }
+        let mut text = std::str::from_utf8(body).ok()?.to_string();
+        for rule in self.rules.iter().filter(|rule| rule.path.is_none()) {
+            let n = rule.redact_str(&mut text);
+            if n > 0 {
+                hits.push((rule.name.as_str(), n));
+            }
+        }
+        (!hits.is_empty()).then(|| (Bytes::from(text), hits))
+    }
+
+    /// 一次发完的上传内容按原长度遮盖；不是文本时返回 None
+    fn mask_upload(&self, body: &Bytes) -> Option<(Bytes, Vec<(&str, usize)>)> {
+        let mut text = std::str::from_utf8(body).ok()?.to_string();
+        let mut hits = Vec::new();
+        for rule in self.rules.iter().filter(|rule| rule.path.is_none()) {
+            let n = rule.mask_str(&mut text);
+            if n > 0 {
+                hits.push((rule.name.as_str(), n));
+            }
+        }
+        (!hits.is_empty()).then(|| (Bytes::from(text), hits))
+    }
+}
+
+// 上传分块是文本：整块是 UTF-8，或者只有末尾一个字符被切断
+fn is_text(body: &[u8]) -> bool {
+    match std::str::from_utf8(body) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none() && e.valid_up_to() > 0,
+    }
+}
+
+fn reject_chunked_upload() -> Response {
+    let body = json!({
+        "error": {
+            "code": 400,
+            "message": "the gateway redacts uploaded text, upload text files in a single request (X-Goog-Upload-Command: upload, finalize)",
+            "status": "FAILED_PRECONDITION",
+        }
+    });
+    (StatusCode::BAD_REQUEST, Json(body)).into_response()
+}
+
+fn log_hits(id: u64, what: &str, hits: &[(&str, usize)]) {
+    let summary: Vec<String> = hits.iter().map(|(name, n)| format!("{}×{}", name, n)).collect();
+    debug!("🕶️  Redacted request {} {}: {}", id, what, summary.join(", "));
+}
+
+#[async_trait]
+impl GatewayPlugin for BodyRedactor {
+    fn name(&self) -> &str {
+        "redact"
+    }
+
+    async fn on_request(&self, ctx: &mut RequestContext) -> Result<(), Response> {
+        if ctx.body.is_empty() {
+            return Ok(());
+        }
+        if uploads::has_command(&ctx.headers, "upload") {
+            if !is_text(&ctx.body) {
+                return Ok(());
+            }
+            let single = uploads::is_finalize(&ctx.headers) && uploads::upload_offset(&ctx.headers) == 0;
+            if !single {
+                warn!("🕶️  Rejected request {}: text upload sent in several chunks cannot be redacted", ctx.id);
+                return Err(reject_chunked_upload());
+            }
+            if let Some((body, hits)) = self.mask_upload(&ctx.body) {
+                log_hits(ctx.id, "upload", &hits);
+                ctx.body = body;
+            }
+            return Ok(());
+        }
+        if let Some((body, hits)) = self.redact(&ctx.body) {
+            log_hits(ctx.id, "body", &hits);
+            ctx.body = body;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redactor(specs: &[&str]) -> BodyRedactor {
+        BodyRedactor::parse(&specs.iter().map(|s| s.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    fn redact_text(specs: &[&str], text: &str) -> String {
+        match redactor(specs).redact(&Bytes::from(text.to_string())) {
+            Some((body, _)) => String::from_utf8(body.to_vec()).unwrap(),
+            None => text.to_string(),
+        }
+    }
+
+    #[test]
+    fn cards_need_a_valid_checksum() {
+        assert_eq!(redact_text(&["card"], "card 4111 1111 1111 1111 ok"), "card [REDACTED] ok");
+        assert_eq!(redact_text(&["card"], "card 4111111111111112 ok"), "card 4111111111111112 ok");
+        // 更长数字串的一部分不算
+        assert_eq!(redact_text(&["card"], "id 94111111111111111123"), "id 94111111111111111123");
+    }
+
+    #[test]
+    fn phones_skip_plain_numbers() {
+        assert_eq!(redact_text(&["phone"], "call +1 415 555 0100"), "call [REDACTED]");
+        assert_eq!(redact_text(&["phone"], "手机 13812345678。"), "手机 [REDACTED]。");
+        assert_eq!(redact_text(&["phone"], "ts 1700000000000"), "ts 1700000000000");
+    }
+
+    #[test]
+    fn replacement_and_regex_options() {
+        assert_eq!(redact_text(&["email;with=<email>"], "to a.b@example.com"), "to <email>");
+        assert_eq!(redact_text(&["email;with="], "to a.b@example.com!"), "to !");
+        // 正则里的分号不当作选项分隔符
+        assert_eq!(redact_text(&["regex:a;b"], "xa;by"), "x[REDACTED]y");
+    }
+
+    #[test]
+    fn paths_limit_where_rules_apply() {
+        assert_eq!(
+            parse_path("$.contents[*]..text").unwrap(),
+            vec![Segment::Key("contents".into()), Segment::Any, Segment::Descend("text".into())]
+        );
+        assert_eq!(parse_path("$['a'][2]").unwrap(), vec![Segment::Key("a".into()), Segment::Index(2)]);
+        for bad in ["contents", "$.", "$..", "$[1"] {
+            assert!(parse_path(bad).is_err(), "{}", bad);
+        }
+
+        let body = json!({
+            "contents": [{ "parts": [{ "text": "me@example.com" }] }],
+            "meta": { "text": "me@example.com" },
+        });
+        let redactor = redactor(&["email;path=$.contents..text"]);
+        let (redacted, hits) = redactor.redact(&Bytes::from(body.to_string())).unwrap();
+        let redacted: Value = serde_json::from_slice(&redacted).unwrap();
+        assert_eq!(hits, vec![("email", 1)]);
+        assert_eq!(redacted["contents"][0]["parts"][0]["text"], "[REDACTED]");
+        assert_eq!(redacted["meta"]["text"], "me@example.com");
+    }
+
+    #[test]
+    fn inline_data_and_uploads() {
+        let body = json!({ "inlineData": { "data": "me@example.com" }, "text": "me@example.com" });
+        let (redacted, _) = redactor(&["email"]).redact(&Bytes::from(body.to_string())).unwrap();
+        let redacted: Value = serde_json::from_slice(&redacted).unwrap();
+        assert_eq!(redacted["inlineData"]["data"], "me@example.com");
+        assert_eq!(redacted["text"], "[REDACTED]");
+
+        let (masked, _) = redactor(&["email;with=x"]).mask_upload(&Bytes::from_static(b"to me@example.com")).unwrap();
+        assert_eq!(&masked[..], b"to **************");
+    }
+
+    #[test]
+    fn rejects_unknown_patterns() {
+        assert!(BodyRedactor::parse(&["ssn".to_string()]).is_err());
+        assert!(BodyRedactor::parse(&["regex:(".to_string()]).is_err());
+        assert!(BodyRedactor::parse(&["email;path=text".to_string()]).is_err());
+    }
+}
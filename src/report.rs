@@ -0,0 +1,188 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::export::{csv_field, utc_day};
+use crate::ledger;
+use crate::storage::Storage;
+
+// --- 月度账单报表 ---
+// 把一个自然月 (UTC) 的账本记录按模型汇总，字段直接对应内部账单里的明细行。
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ModelLine {
+    pub model: String,
+    pub requests: u64,
+    pub errors: u64,
+    pub prompt_tokens: u64,
+    pub output_tokens: u64,
+    pub cost: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MonthlyReport {
+    /// YYYY-MM
+    pub month: String,
+    /// None 表示所有客户端
+    pub client: Option<String>,
+    /// 账单周期的第一天和最后一天 (含)
+    pub period_start: String,
+    pub period_end: String,
+    pub requests: u64,
+    /// 上游连接失败或返回 4xx / 5xx 的请求数
+    pub errors: u64,
+    pub prompt_tokens: u64,
+    pub output_tokens: u64,
+    pub total_cost: f64,
+    /// 按模型名排序
+    pub models: Vec<ModelLine>,
+}
+
+/// 用量按哪个维度汇总
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupBy {
+    /// 上游 key (key 池里的名称)
+    Key,
+    Client,
+}
+
+impl std::str::FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "key" => Ok(Self::Key),
+            "client" => Ok(Self::Client),
+            other => Err(format!("unknown grouping '{}', expected key or client", other)),
+        }
+    }
+}
+
+impl GroupBy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Key => "key",
+            Self::Client => "client",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageGroup {
+    /// key 名或客户端标识；没有走 key 池的请求为 None
+    pub name: Option<String>,
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub candidate_tokens: u64,
+    pub output_tokens: u64,
+    pub cost: f64,
+}
+
+/// 按上游 key 或客户端汇总 token 用量，按 name 排序
+pub fn usage_by(entries: &[ledger::LedgerEntry], by: GroupBy) -> Vec<UsageGroup> {
+    let mut groups: BTreeMap<Option<String>, UsageGroup> = BTreeMap::new();
+    for entry in entries {
+        let name = match by {
+            GroupBy::Key => entry.key.clone(),
+            GroupBy::Client => Some(entry.client.clone()),
+        };
+        let group = groups.entry(name.clone()).or_insert_with(|| UsageGroup {
+            name,
+            requests: 0,
+            prompt_tokens: 0,
+            candidate_tokens: 0,
+            output_tokens: 0,
+            cost: 0.0,
+        });
+        group.requests += 1;
+        group.prompt_tokens += entry.prompt_tokens;
+        group.candidate_tokens += entry.candidate_tokens;
+        group.output_tokens += entry.output_tokens;
+        group.cost += entry.cost;
+    }
+    groups.into_values().collect()
+}
+
+/// usage_by 的结果转成 CSV，第一列是 key 名或客户端标识
+pub fn usage_csv(groups: &[UsageGroup], by: GroupBy) -> String {
+    let mut out = format!("{},requests,prompt_tokens,candidate_tokens,output_tokens,cost\n", by.as_str());
+    for group in groups {
+        let _ = writeln!(
+            out,
+            "{},{},{},{},{},{:.6}",
+            csv_field(group.name.as_deref().unwrap_or("")),
+            group.requests,
+            group.prompt_tokens,
+            group.candidate_tokens,
+            group.output_tokens,
+            group.cost
+        );
+    }
+    out
+}
+
+// Howard Hinnant 的 days_from_civil，utc_day 的逆运算
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// `YYYY-MM` -> 该月 [开始, 下月开始) 的 unix 毫秒
+pub fn month_bounds(month: &str) -> Result<(u64, u64), String> {
+    let invalid = || format!("invalid month '{}', expected YYYY-MM", month);
+    let (year, mon) = month.split_once('-').ok_or_else(invalid)?;
+    let year: i64 = year.parse().map_err(|_| invalid())?;
+    let mon: i64 = mon.parse().map_err(|_| invalid())?;
+    if !(1..=12).contains(&mon) || !(1970..=9999).contains(&year) {
+        return Err(invalid());
+    }
+    let (next_year, next_mon) = if mon == 12 { (year + 1, 1) } else { (year, mon + 1) };
+    let start = days_from_civil(year, mon, 1) as u64 * 86_400_000;
+    let end = days_from_civil(next_year, next_mon, 1) as u64 * 86_400_000;
+    Ok((start, end))
+}
+
+/// ts_ms 所在的月份 YYYY-MM
+pub fn utc_month(ts_ms: u64) -> String {
+    utc_day(ts_ms)[..7].to_string()
+}
+
+pub async fn monthly(storage: &dyn Storage, month: &str, client: Option<&str>) -> Result<MonthlyReport, String> {
+    let (start, end) = month_bounds(month)?;
+    let entries = ledger::query(storage, start, end, client).await?;
+
+    let mut models: BTreeMap<String, ModelLine> = BTreeMap::new();
+    for entry in &entries {
+        let line = models.entry(entry.model.clone()).or_insert_with(|| ModelLine {
+            model: entry.model.clone(),
+            ..Default::default()
+        });
+        line.requests += 1;
+        if entry.status.is_none_or(|s| s >= 400) {
+            line.errors += 1;
+        }
+        line.prompt_tokens += entry.prompt_tokens;
+        line.output_tokens += entry.output_tokens;
+        line.cost += entry.cost;
+    }
+    let models: Vec<ModelLine> = models.into_values().collect();
+
+    Ok(MonthlyReport {
+        month: month.to_string(),
+        client: client.map(str::to_string),
+        period_start: utc_day(start),
+        period_end: utc_day(end - 1),
+        requests: models.iter().map(|m| m.requests).sum(),
+        errors: models.iter().map(|m| m.errors).sum(),
+        prompt_tokens: models.iter().map(|m| m.prompt_tokens).sum(),
+        output_tokens: models.iter().map(|m| m.output_tokens).sum(),
+        total_cost: models.iter().map(|m| m.cost).sum(),
+        models,
+    })
+}
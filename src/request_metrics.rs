@@ -0,0 +1,137 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::finish_body::{self, BodyWatcher};
+use crate::usage;
+use crate::AppState;
+
+// --- 按接口和模型拆分的请求指标 ---
+// 代理路由上的每个请求 (含被访问控制拒绝的) 按归一化后的接口和路径里的模型名打标签：
+//   aizasy_requests_total{route,model,status}           请求数，按状态码算错误率
+//   aizasy_time_to_first_byte_seconds{route,model}      收到响应头的耗时
+//   aizasy_request_duration_seconds{route,model}        响应体发完 (或客户端断开) 的总耗时
+// route 是 generateContent / streamGenerateContent / countTokens 这类方法名，不认识的归为 other；
+// model 取自 `/models/{model}:...`，路径里没有模型 (OpenAI 兼容接口、文件接口等) 时为空。
+// 模型名由客户端决定，为了不让时间序列无限增长，超过 MAX_MODELS 个不同的模型之后新出现的都记为 other。
+
+const MAX_MODELS: usize = 100;
+
+// 路径 `/models/{model}:{method}` 里认识的方法名
+const METHODS: &[&str] = &[
+    "generateContent",
+    "streamGenerateContent",
+    "countTokens",
+    "embedContent",
+    "batchEmbedContents",
+    "batchGenerateContent",
+    "asyncBatchEmbedContent",
+    "predict",
+    "predictLongRunning",
+    "bidiGenerateContent",
+];
+
+/// 已经用作标签的模型名
+#[derive(Default)]
+pub(crate) struct ModelLabels {
+    seen: Mutex<HashSet<String>>,
+}
+
+impl ModelLabels {
+    fn label(&self, model: &str) -> String {
+        let valid = model.len() <= 64 && model.bytes().all(|b| b.is_ascii_alphanumeric() || b"._-".contains(&b));
+        if !valid {
+            return "other".to_string();
+        }
+        let Ok(mut seen) = self.seen.lock() else {
+            return "other".to_string();
+        };
+        if seen.contains(model) || seen.len() < MAX_MODELS {
+            seen.insert(model.to_string());
+            return model.to_string();
+        }
+        "other".to_string()
+    }
+}
+
+/// 把请求路径归一化成接口名
+pub(crate) fn route(path: &str) -> String {
+    // gRPC: /google.ai.generativelanguage.v1beta.GenerativeService/GenerateContent
+    if let Some(method) = path.strip_prefix("/google.").and_then(|rest| rest.rsplit('/').next()) {
+        if !method.is_empty() && method.len() <= 40 && method.bytes().all(|b| b.is_ascii_alphanumeric()) {
+            return format!("grpc.{}", method);
+        }
+        return "other".to_string();
+    }
+    let path = path.trim_end_matches('/');
+    if let Some(rest) = path.find("/models/").map(|i| &path[i + "/models/".len()..]) {
+        return match rest.split_once(':') {
+            Some((_, method)) if METHODS.contains(&method) => method.to_string(),
+            Some(_) => "other".to_string(),
+            None => "getModel".to_string(),
+        };
+    }
+    let route = if path.ends_with("/models") {
+        "listModels"
+    } else if path.starts_with("/upload/") {
+        "upload"
+    } else if path.contains("/files") {
+        "files"
+    } else if path.contains("/cachedContents") {
+        "cachedContents"
+    } else if path.contains("/batches") || path.contains("/operations") {
+        "operations"
+    } else if path.contains("/tunedModels") {
+        "tunedModels"
+    } else if path.ends_with("/chat/completions") {
+        "openai.chatCompletions"
+    } else if path.ends_with("/embeddings") {
+        "openai.embeddings"
+    } else if path == "/batch" {
+        "batch"
+    } else {
+        "other"
+    };
+    route.to_string()
+}
+
+pub(crate) async fn record(State(state): State<Arc<AppState>>, req: Request, next: Next) -> Response {
+    let started_at = Instant::now();
+    let path = req.uri().path();
+    let route = route(path);
+    let model = usage::model_from_path(path).map(|m| state.model_labels.label(m)).unwrap_or_default();
+    let response = next.run(req).await;
+
+    let status = response.status().as_u16().to_string();
+    let labels = [("route", route.as_str()), ("model", model.as_str())];
+    state.metrics.inc("aizasy_requests_total", &[labels[0], labels[1], ("status", &status)]);
+    state.metrics.observe("aizasy_time_to_first_byte_seconds", &labels, started_at.elapsed().as_secs_f64());
+
+    let metered = Metered {
+        state,
+        route,
+        model,
+        started_at,
+    };
+    finish_body::watch(response, metered)
+}
+
+// 响应体发完或被丢弃时记总耗时
+struct Metered {
+    state: Arc<AppState>,
+    route: String,
+    model: String,
+    started_at: Instant,
+}
+
+impl BodyWatcher for Metered {
+    fn on_finish(self, _incomplete: bool) {
+        let labels = [("route", self.route.as_str()), ("model", self.model.as_str())];
+        self.state.metrics.observe("aizasy_request_duration_seconds", &labels, self.started_at.elapsed().as_secs_f64());
+    }
+}
@@ -0,0 +1,238 @@
+use axum::http::{HeaderMap, HeaderName};
+use std::cmp::Reverse;
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+use crate::key_pool::KeyPool;
+
+// --- 上游限流时排队 ---
+// key 池里的 key 都在冷却 (上游 429) 时，请求不再直接拿到 429，而是进入一个有上限的优先级队列，
+// 等有 key 恢复再按优先级依次放行：每个空闲的 key 同一时间只放行一个排队的请求，收到响应头后再放下一个，
+// 恢复的 key 如果马上又被 429，后面的请求继续等，不会一窝蜂全部打上去。
+// 优先级数字越大越先放行，同优先级按到达顺序；换 key 重试又撞上 429 的请求保留原来的位置重新排队。
+// 按 --queue-priority 给客户端配置的值排定优先级，没配置时为 0。可以另外允许客户端用请求头声明优先级
+// (默认关闭)，声明的值不超过 max_header_priority，免得任何客户端都能把自己排到最前面；
+// 这个请求头只给网关看，不转发给上游。
+// 队列满了，或者等待超过 max_wait (从第一次排队算起) 时返回 429。
+// 有请求在排队时新请求也要先排队，不能插到队伍前面抢刚恢复的 key。
+// 设置了每个 key 的并发上限时，key 名额全满的请求也在这里排队，有名额归还就放行。
+
+#[derive(Debug, Clone)]
+pub struct QueueConfig {
+    /// 同时排队的请求上限
+    pub max_queue: usize,
+    /// 单个请求最多等待的时间
+    pub max_wait: Duration,
+    /// 客户端声明优先级的请求头
+    pub priority_header: Option<HeaderName>,
+    /// 请求头里声明的优先级最高到这个值
+    pub max_header_priority: i64,
+    /// (客户端标识或 `*`, 优先级)
+    pub priorities: Vec<(String, i64)>,
+}
+
+impl QueueConfig {
+    /// 解析 `CLIENT=N`
+    pub fn parse_priority(spec: &str) -> Result<(String, i64), String> {
+        match spec.rsplit_once('=') {
+            Some((client, priority)) if !client.trim().is_empty() => priority
+                .trim()
+                .parse()
+                .map(|priority| (client.trim().to_string(), priority))
+                .map_err(|_| format!("invalid priority in '{}'", spec)),
+            _ => Err(format!("queue priority '{}' must be CLIENT=N", spec)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum QueueRejection {
+    Full,
+    Timeout(Duration),
+}
+
+impl std::fmt::Display for QueueRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueueRejection::Full => f.write_str("upstream is rate limited and the request queue is full"),
+            QueueRejection::Timeout(wait) => {
+                write!(f, "upstream is still rate limited after waiting {}s in the request queue", wait.as_secs())
+            }
+        }
+    }
+}
+
+/// 请求在队列里的位置：优先级高的排前面，同优先级按序号
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Ticket {
+    priority: i64,
+    seq: u64,
+    deadline: Instant,
+}
+
+impl Ticket {
+    pub(crate) fn priority(&self) -> i64 {
+        self.priority
+    }
+
+    pub(crate) fn expired(&self) -> bool {
+        Instant::now() >= self.deadline
+    }
+
+    fn entry(&self) -> (Reverse<i64>, u64) {
+        (Reverse(self.priority), self.seq)
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    waiting: BTreeSet<(Reverse<i64>, u64)>,
+    // 已经放行、还没收到响应头的请求数
+    dispatched: usize,
+}
+
+pub(crate) struct RequestQueue {
+    config: QueueConfig,
+    inner: Mutex<Inner>,
+    next_seq: AtomicU64,
+    notify: Notify,
+}
+
+/// 放行的名额，收到上游响应头后 drop，让下一个排队的请求继续
+pub(crate) struct Dispatch<'a> {
+    queue: &'a RequestQueue,
+}
+
+impl Drop for Dispatch<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut inner) = self.queue.inner.lock() {
+            inner.dispatched = inner.dispatched.saturating_sub(1);
+        }
+        self.queue.notify.notify_waiters();
+    }
+}
+
+// 等待中的请求被取消 (客户端断开) 时从队列里移除
+struct Waiting<'a> {
+    queue: &'a RequestQueue,
+    entry: (Reverse<i64>, u64),
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        let removed = self.queue.inner.lock().is_ok_and(|mut inner| inner.waiting.remove(&self.entry));
+        if removed {
+            self.queue.notify.notify_waiters();
+        }
+    }
+}
+
+// 没有可用 key 又算不出恢复时间时，隔这么久重新看一次 (其他副本同步过来的冷却、管理 API 重新启用 key)
+const RECHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+impl RequestQueue {
+    pub(crate) fn new(config: QueueConfig) -> Self {
+        Self {
+            config,
+            inner: Mutex::new(Inner::default()),
+            next_seq: AtomicU64::new(0),
+            notify: Notify::new(),
+        }
+    }
+
+    #[cfg(feature = "admin")]
+    pub(crate) fn config(&self) -> &QueueConfig {
+        &self.config
+    }
+
+    /// key 的状态变了 (并发名额归还)，让排队的请求重新检查
+    pub(crate) fn wake(&self) {
+        self.notify.notify_waiters();
+    }
+
+    /// 当前排队的请求数
+    pub(crate) fn len(&self) -> usize {
+        self.inner.lock().map(|inner| inner.waiting.len()).unwrap_or(0)
+    }
+
+    /// 去掉客户端声明优先级的请求头，转发给上游之前调用
+    pub(crate) fn strip_priority_header(&self, headers: &mut HeaderMap) {
+        if let Some(name) = &self.config.priority_header {
+            headers.remove(name);
+        }
+    }
+
+    /// 请求头里声明的优先级 (不超过 max_header_priority)，其次是客户端配置的，默认 0
+    pub(crate) fn ticket(&self, headers: &HeaderMap, client: &str) -> Ticket {
+        let configured = || {
+            let find = |subject: &str| self.config.priorities.iter().find(|(name, _)| name == subject).map(|(_, p)| *p);
+            find(client).or_else(|| find("*")).unwrap_or(0)
+        };
+        let priority = self
+            .config
+            .priority_header
+            .as_ref()
+            .and_then(|name| headers.get(name))
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<i64>().ok())
+            .map(|priority| priority.min(self.config.max_header_priority))
+            .unwrap_or_else(configured);
+        Ticket {
+            priority,
+            seq: self.next_seq.fetch_add(1, Ordering::Relaxed),
+            deadline: Instant::now() + self.config.max_wait,
+        }
+    }
+
+    /// 不用排队就能直接发：有空闲的 key，而且前面没有人在等
+    pub(crate) fn can_bypass(&self, pool: &KeyPool) -> bool {
+        let Ok(inner) = self.inner.lock() else {
+            return true;
+        };
+        inner.waiting.is_empty() && inner.dispatched < pool.available()
+    }
+
+    /// 排队直到轮到这个请求而且有空闲的 key
+    pub(crate) async fn wait(&self, ticket: &Ticket, pool: &KeyPool) -> Result<Dispatch<'_>, QueueRejection> {
+        let entry = ticket.entry();
+        {
+            let mut inner = self.inner.lock().map_err(|_| QueueRejection::Full)?;
+            if inner.waiting.len() >= self.config.max_queue {
+                return Err(QueueRejection::Full);
+            }
+            inner.waiting.insert(entry);
+        }
+        let waiting = Waiting { queue: self, entry };
+        loop {
+            // 先登记再检查状态，检查之后才发出的通知也不会漏掉
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            {
+                let mut inner = self.inner.lock().map_err(|_| QueueRejection::Full)?;
+                if inner.waiting.first() == Some(&entry) && inner.dispatched < pool.available() {
+                    inner.waiting.remove(&entry);
+                    inner.dispatched += 1;
+                    drop(inner);
+                    std::mem::forget(waiting);
+                    // 后面的请求可能也能放行了 (还有别的空闲 key)
+                    self.notify.notify_waiters();
+                    return Ok(Dispatch { queue: self });
+                }
+            }
+            let now = Instant::now();
+            if now >= ticket.deadline {
+                return Err(QueueRejection::Timeout(self.config.max_wait));
+            }
+            let recheck = pool.next_ready().unwrap_or(RECHECK_INTERVAL).min(RECHECK_INTERVAL);
+            tokio::select! {
+                _ = notified => {}
+                _ = tokio::time::sleep_until(ticket.deadline.min(now + recheck)) => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use async_trait::async_trait;
+use axum::{
+    body::Bytes,
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+use crate::plugin::{ChunkAction, GatewayPlugin, Outcome, RequestContext};
+use crate::security_headers::UpstreamResponse;
+
+// --- 响应缓存 ---
+// 对结果只取决于请求内容的接口 (embedContent / batchEmbedContents / countTokens)，
+// 把上游的 200 响应按 method + path + 请求体 (以及客户端凭证、Accept-Encoding) 的哈希缓存在内存里，
+// 相同请求直接返回，不再请求上游、也不计费。可选地把 temperature 为 0 的非流式 generateContent 也算进来。
+// 超过条数或总字节数上限时淘汰最久没用到的条目 (LRU)，条目过了 TTL 视为失效。
+
+pub const CACHE_HEADER: &str = "x-aizasy-cache";
+
+// 结果只取决于请求内容的接口
+const CACHEABLE_ACTIONS: &[&str] = &[":embedContent", ":batchEmbedContents", ":countTokens"];
+
+// 凭证参与哈希，不同 key 之间不共享缓存 (?key= 已经在 path 里)；
+// Accept-Encoding 不同时上游返回的编码也不同
+const KEY_HEADERS: &[&str] = &["authorization", "x-goog-api-key", "accept-encoding"];
+
+#[derive(Debug, Clone)]
+pub struct ResponseCacheConfig {
+    pub max_entries: usize,
+    /// 所有条目响应体的总字节数上限，单个响应超过它时不缓存
+    pub max_bytes: usize,
+    pub ttl: Duration,
+    /// temperature 为 0 的非流式 generateContent 也缓存
+    pub deterministic: bool,
+}
+
+impl Default for ResponseCacheConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: 1000,
+            max_bytes: 64 * 1024 * 1024,
+            ttl: Duration::from_secs(3600),
+            deterministic: false,
+        }
+    }
+}
+
+struct Entry {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    expires_at: Instant,
+    // 最近一次使用的序号，越小越久没用
+    used: u64,
+}
+
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<[u8; 32], Entry>,
+    // used -> key，按序号从小到大淘汰
+    order: BTreeMap<u64, [u8; 32]>,
+    bytes: usize,
+    tick: u64,
+}
+
+impl Lru {
+    fn get(&mut self, key: &[u8; 32]) -> Option<(StatusCode, HeaderMap, Bytes)> {
+        let entry = self.entries.get(key)?;
+        if entry.expires_at <= Instant::now() {
+            self.remove(key);
+            return None;
+        }
+        self.tick += 1;
+        let entry = self.entries.get_mut(key)?;
+        self.order.remove(&entry.used);
+        entry.used = self.tick;
+        self.order.insert(self.tick, *key);
+        Some((entry.status, entry.headers.clone(), entry.body.clone()))
+    }
+
+    fn remove(&mut self, key: &[u8; 32]) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.order.remove(&entry.used);
+            self.bytes -= entry.body.len();
+        }
+    }
+
+    fn insert(&mut self, key: [u8; 32], mut entry: Entry, config: &ResponseCacheConfig) {
+        self.remove(&key);
+        while !self.entries.is_empty()
+            && (self.entries.len() >= config.max_entries || self.bytes + entry.body.len() > config.max_bytes)
+        {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some(evicted) = self.entries.remove(&oldest) {
+                self.bytes -= evicted.body.len();
+            }
+        }
+        self.tick += 1;
+        entry.used = self.tick;
+        self.order.insert(self.tick, key);
+        self.bytes += entry.body.len();
+        self.entries.insert(key, entry);
+    }
+}
+
+// 一次未命中的请求：记下上游响应，结束后写入缓存
+#[derive(Clone)]
+struct Pending(Arc<Mutex<Capture>>);
+
+struct Capture {
+    key: [u8; 32],
+    status: Option<StatusCode>,
+    headers: HeaderMap,
+    body: Vec<u8>,
+    // 超出大小上限或不是 200，不再收集
+    skip: bool,
+}
+
+pub struct ResponseCache {
+    config: ResponseCacheConfig,
+    lru: Mutex<Lru>,
+}
+
+impl ResponseCache {
+    pub fn new(config: ResponseCacheConfig) -> Self {
+        Self {
+            config,
+            lru: Mutex::new(Lru::default()),
+        }
+    }
+
+    /// 当前条目数和总字节数
+    pub fn usage(&self) -> (usize, usize) {
+        let lru = self.lru.lock().unwrap();
+        (lru.entries.len(), lru.bytes)
+    }
+
+    fn cacheable(&self, ctx: &RequestContext) -> bool {
+        if ctx.method != Method::POST {
+            return false;
+        }
+        let path = ctx.uri.path();
+        if CACHEABLE_ACTIONS.iter().any(|action| path.ends_with(action)) {
+            return true;
+        }
+        self.config.deterministic && path.ends_with(":generateContent") && is_deterministic(&ctx.body)
+    }
+
+    fn key(ctx: &RequestContext) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(ctx.method.as_str());
+        hasher.update([0]);
+        hasher.update(ctx.uri.path_and_query().map(|p| p.as_str()).unwrap_or("/"));
+        hasher.update([0]);
+        for name in KEY_HEADERS {
+            if let Some(value) = ctx.headers.get(*name) {
+                hasher.update(value.as_bytes());
+            }
+            hasher.update([0]);
+        }
+        hasher.update(&ctx.body);
+        hasher.finalize().into()
+    }
+}
+
+/// 采样参数固定时 (temperature 为 0，单个候选) 同样的请求应得到同样的结果
+fn is_deterministic(body: &[u8]) -> bool {
+    let Ok(request) = serde_json::from_slice::<Value>(body) else {
+        return false;
+    };
+    let config = &request["generationConfig"];
+    config["temperature"].as_f64() == Some(0.0) && config["candidateCount"].as_u64().unwrap_or(1) == 1
+}
+
+#[async_trait]
+impl GatewayPlugin for ResponseCache {
+    fn name(&self) -> &str {
+        "response_cache"
+    }
+
+    async fn on_request(&self, ctx: &mut RequestContext) -> Result<(), Response> {
+        if !self.cacheable(ctx) {
+            return Ok(());
+        }
+        let key = Self::key(ctx);
+        let hit = self.lru.lock().unwrap().get(&key);
+        if let Some((status, mut headers, body)) = hit {
+            debug!("🗃️ Response cache hit for {}", ctx.uri.path());
+            headers.insert(CACHE_HEADER, HeaderValue::from_static("HIT"));
+            let mut response = (status, headers, body).into_response();
+            response.extensions_mut().insert(UpstreamResponse);
+            return Err(response);
+        }
+        ctx.extensions.insert(Pending(Arc::new(Mutex::new(Capture {
+            key,
+            status: None,
+            headers: HeaderMap::new(),
+            body: Vec::new(),
+            skip: false,
+        }))));
+        Ok(())
+    }
+
+    async fn on_upstream_response(&self, ctx: &RequestContext, status: StatusCode, headers: &mut HeaderMap) {
+        let Some(Pending(capture)) = ctx.extensions.get::<Pending>() else {
+            return;
+        };
+        headers.insert(CACHE_HEADER, HeaderValue::from_static("MISS"));
+        let mut capture = capture.lock().unwrap();
+        capture.status = Some(status);
+        capture.skip = status != StatusCode::OK;
+        capture.headers = headers.clone();
+        // 长度和连接相关的头由返回缓存时重新决定
+        for name in [header::CONTENT_LENGTH, header::TRANSFER_ENCODING, header::CONNECTION, header::DATE] {
+            capture.headers.remove(name);
+        }
+        capture.headers.remove(CACHE_HEADER);
+    }
+
+    fn on_chunk(&self, ctx: &RequestContext, chunk: &Bytes) -> ChunkAction {
+        if let Some(Pending(capture)) = ctx.extensions.get::<Pending>() {
+            let mut capture = capture.lock().unwrap();
+            if !capture.skip {
+                if capture.body.len() + chunk.len() > self.config.max_bytes {
+                    capture.skip = true;
+                    capture.body = Vec::new();
+                } else {
+                    capture.body.extend_from_slice(chunk);
+                }
+            }
+        }
+        ChunkAction::Continue
+    }
+
+    fn on_complete(&self, ctx: &RequestContext, outcome: &Outcome) {
+        let Some(Pending(capture)) = ctx.extensions.get::<Pending>() else {
+            return;
+        };
+        let mut capture = capture.lock().unwrap();
+        if capture.skip || outcome.error.is_some() {
+            return;
+        }
+        let Some(status) = capture.status else {
+            return;
+        };
+        let entry = Entry {
+            status,
+            headers: std::mem::take(&mut capture.headers),
+            body: Bytes::from(std::mem::take(&mut capture.body)),
+            expires_at: Instant::now() + self.config.ttl,
+            used: 0,
+        };
+        self.lru.lock().unwrap().insert(capture.key, entry, &self.config);
+    }
+}
@@ -0,0 +1,171 @@
+use async_trait::async_trait;
+use axum::{
+    body::Bytes,
+    http::{header, HeaderMap, StatusCode},
+};
+use serde_json::Value;
+use tracing::debug;
+
+use crate::plugin::{GatewayPlugin, RequestContext};
+use crate::validate::{camel_case, snake_case};
+
+// --- 非流式响应字段裁剪 ---
+// 网关服务移动端等带宽受限的客户端时，把响应里用不到的字段去掉再返回，例如 promptFeedback、
+// 模型回显的大段 inlineData。字段写法：
+//   NAME        任意层级上名为 NAME 的字段
+//   A.B.C       从响应顶层开始的路径，途经的数组逐个元素处理 (如 candidates.content.parts.inlineData)
+//   METHOD:F    只对这个方法 (如 generateContent) 的响应生效，F 是上面两种写法之一
+// 字段名同时认 camelCase 和 snake_case。只处理成功的、未压缩的 JSON 响应；
+// 只有命中了规则的请求才会缓冲响应体，其余响应照常逐块转发。
+// streamGenerateContent 不带 alt=sse 时返回的是逐步输出的 JSON 数组，缓冲会让它失去流式效果，不处理；
+// SSE 的流式响应见 stream_transform。
+
+// 字段名的 camelCase 和 snake_case 写法
+type Name = [String; 2];
+
+fn name(field: &str) -> Name {
+    let camel = camel_case(field);
+    [snake_case(&camel), camel]
+}
+
+fn same_name(key: &str, name: &Name) -> bool {
+    name.iter().any(|n| n == key)
+}
+
+#[derive(Debug, Clone)]
+enum Field {
+    Anywhere(Name),
+    Path(Vec<Name>),
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    // None 表示所有方法
+    method: Option<String>,
+    field: Field,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResponseFilter {
+    rules: Vec<Rule>,
+}
+
+// 请求路径里的方法名 (/v1beta/models/gemini-2.0-flash:generateContent -> generateContent)
+fn method_of(ctx: &RequestContext) -> &str {
+    ctx.uri.path().rsplit_once(':').map(|(_, method)| method).unwrap_or("")
+}
+
+impl ResponseFilter {
+    pub fn parse(fields: &[String]) -> Result<Self, String> {
+        let rules = fields
+            .iter()
+            .map(|spec| {
+                let (method, field) = match spec.trim().split_once(':') {
+                    Some((method, field)) if !method.trim().is_empty() => (Some(method.trim().to_string()), field),
+                    Some(_) => return Err(format!("invalid response field '{}'", spec)),
+                    None => (None, spec.as_str()),
+                };
+                let segments: Vec<&str> = field.trim().split('.').map(str::trim).collect();
+                if segments.iter().any(|s| s.is_empty()) {
+                    return Err(format!("invalid response field '{}'", spec));
+                }
+                let field = match segments.as_slice() {
+                    [single] => Field::Anywhere(name(single)),
+                    _ => Field::Path(segments.into_iter().map(name).collect()),
+                };
+                Ok(Rule { method, field })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { rules })
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    fn rules_for<'a>(&'a self, method: &'a str) -> impl Iterator<Item = &'a Rule> + 'a {
+        self.rules.iter().filter(move |rule| rule.method.as_deref().is_none_or(|m| m == method))
+    }
+
+    /// 去掉这个方法的规则命中的字段，返回去掉的个数
+    fn strip(&self, method: &str, value: &mut Value) -> usize {
+        self.rules_for(method)
+            .map(|rule| match &rule.field {
+                Field::Anywhere(name) => strip_anywhere(value, name),
+                Field::Path(path) => strip_path(value, path),
+            })
+            .sum()
+    }
+}
+
+fn strip_anywhere(value: &mut Value, name: &Name) -> usize {
+    match value {
+        Value::Object(map) => {
+            let before = map.len();
+            map.retain(|key, _| !same_name(key, name));
+            let removed = before - map.len();
+            removed + map.values_mut().map(|item| strip_anywhere(item, name)).sum::<usize>()
+        }
+        Value::Array(items) => items.iter_mut().map(|item| strip_anywhere(item, name)).sum(),
+        _ => 0,
+    }
+}
+
+fn strip_path(value: &mut Value, path: &[Name]) -> usize {
+    match value {
+        Value::Array(items) => items.iter_mut().map(|item| strip_path(item, path)).sum(),
+        Value::Object(map) => {
+            let Some((name, rest)) = path.split_first() else {
+                return 0;
+            };
+            if rest.is_empty() {
+                let before = map.len();
+                map.retain(|key, _| !same_name(key, name));
+                return before - map.len();
+            }
+            map.iter_mut()
+                .filter(|(key, _)| same_name(key, name))
+                .map(|(_, item)| strip_path(item, rest))
+                .sum()
+        }
+        _ => 0,
+    }
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"))
+}
+
+#[async_trait]
+impl GatewayPlugin for ResponseFilter {
+    fn name(&self) -> &str {
+        "response-filter"
+    }
+
+    fn wants_response_body(&self, ctx: &RequestContext, headers: &HeaderMap) -> bool {
+        let method = method_of(ctx);
+        method != "streamGenerateContent" && is_json(headers) && self.rules_for(method).next().is_some()
+    }
+
+    async fn on_response_body(&self, ctx: &RequestContext, status: StatusCode, headers: &mut HeaderMap, body: &mut Bytes) {
+        if !status.is_success() || !is_json(headers) {
+            return;
+        }
+        let Ok(mut value) = serde_json::from_slice::<Value>(body) else {
+            return;
+        };
+        let removed = self.strip(method_of(ctx), &mut value);
+        if removed > 0 {
+            let stripped = Bytes::from(value.to_string());
+            debug!("✂️  Stripped {} response fields from request {} ({} -> {} bytes)", removed, ctx.id, body.len(), stripped.len());
+            *body = stripped;
+        }
+    }
+}
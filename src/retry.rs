@@ -0,0 +1,72 @@
+use axum::http::StatusCode;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+// --- 瞬时错误重试 ---
+// 上游连接失败或返回 500 / 502 / 503 / 504 时，等待一段指数增长的时间后原样重放请求，
+// 最多 max_retries 次；一次偶发的上游抖动不再直接变成客户端看到的 502。
+// 只重试请求体不超过 max_body 的请求，客户端带上 `x-aizasy-no-retry` 头可以关闭 (转发前移除)。
+// 超时不重试：上游可能已经在生成，重放会重复计费。
+
+/// 请求头：本次请求不做重试
+pub const NO_RETRY_HEADER: &str = "x-aizasy-no-retry";
+
+const RETRY_STATUSES: &[StatusCode] = &[
+    StatusCode::INTERNAL_SERVER_ERROR,
+    StatusCode::BAD_GATEWAY,
+    StatusCode::SERVICE_UNAVAILABLE,
+    StatusCode::GATEWAY_TIMEOUT,
+];
+
+#[derive(Debug, Clone)]
+pub struct RetryConfig {
+    pub max_retries: u32,
+    /// 第一次重试前的等待时间，之后每次翻倍
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+    /// 随机抖动比例 (0..=1)：实际等待在 [backoff × (1 - jitter), backoff] 之间，避免大量请求同时重试
+    pub jitter: f64,
+    /// 请求体超过这个字节数时不重试
+    pub max_body: usize,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(5),
+            jitter: 0.5,
+            max_body: 1024 * 1024,
+        }
+    }
+}
+
+impl RetryConfig {
+    /// 上游状态码 (连接失败时为 None) 是否值得重试
+    pub(crate) fn is_transient(status: Option<StatusCode>, connect_error: bool) -> bool {
+        match status {
+            Some(status) => RETRY_STATUSES.contains(&status),
+            None => connect_error,
+        }
+    }
+
+    /// 第 attempt 次重试 (从 1 开始) 前的等待时间
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        let exp = self.backoff.saturating_mul(1 << (attempt - 1).min(16));
+        let delay = exp.min(self.max_backoff);
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return delay;
+        }
+        delay.mul_f64(1.0 - jitter * random_unit())
+    }
+}
+
+// [0, 1) 的随机数；只用于抖动，不需要密码学强度
+fn random_unit() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(0);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
@@ -0,0 +1,79 @@
+use axum::http::Uri;
+use regex::Regex;
+
+// --- 路径改写 ---
+// 在路由匹配和插件之前改写请求路径 (query 保留)，让网关挂在已有的 URL 方案下面而不用改客户端。
+// 规则按配置顺序尝试，第一条命中的生效：
+//   /gemini/*=/*              去掉 /gemini 前缀
+//   /api/*=/v1beta/*          /api/models -> /v1beta/models
+//   /healthz=/health          整条路径精确匹配
+//   ~^/m/([^/]+)/(.*)$=/v1beta/models/$1:$2    `~` 开头是正则，替换串里用 $1 引用分组
+// 鉴权、租户等访问控制中间件看到的仍是原始路径。
+
+#[derive(Debug, Clone)]
+enum Pattern {
+    /// 以 * 结尾的前缀，或不带 * 的整条路径
+    Prefix { from: String, wildcard: bool },
+    Regex(Regex),
+}
+
+#[derive(Debug, Clone)]
+pub struct RewriteRule {
+    pattern: Pattern,
+    to: String,
+}
+
+impl RewriteRule {
+    /// 解析 `FROM=TO`；替换串里没有 `=`，按最后一个 `=` 切分
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (from, to) = spec
+            .rsplit_once('=')
+            .ok_or_else(|| format!("rewrite rule '{}' must be FROM=TO", spec))?;
+        let (from, to) = (from.trim(), to.trim());
+        if !to.starts_with('/') {
+            return Err(format!("rewrite target '{}' must start with '/'", to));
+        }
+        let pattern = match from.strip_prefix('~') {
+            Some(regex) => Pattern::Regex(Regex::new(regex).map_err(|e| format!("invalid rewrite regex '{}': {}", regex, e))?),
+            None => {
+                if !from.starts_with('/') {
+                    return Err(format!("rewrite pattern '{}' must start with '/' or '~'", from));
+                }
+                match from.strip_suffix('*') {
+                    Some(prefix) => Pattern::Prefix { from: prefix.to_string(), wildcard: true },
+                    None => Pattern::Prefix { from: from.to_string(), wildcard: false },
+                }
+            }
+        };
+        if matches!(pattern, Pattern::Prefix { wildcard: false, .. }) && to.ends_with('*') {
+            return Err(format!("rewrite rule '{}': '*' in the target needs one in the pattern", spec));
+        }
+        Ok(Self { pattern, to: to.to_string() })
+    }
+
+    fn apply(&self, path: &str) -> Option<String> {
+        match &self.pattern {
+            Pattern::Prefix { from, wildcard: true } => {
+                let rest = path.strip_prefix(from.as_str())?;
+                Some(match self.to.strip_suffix('*') {
+                    Some(to) => format!("{}{}", to, rest),
+                    None => self.to.clone(),
+                })
+            }
+            Pattern::Prefix { from, wildcard: false } => (path == from).then(|| self.to.clone()),
+            Pattern::Regex(regex) => regex.is_match(path).then(|| regex.replace(path, self.to.as_str()).into_owned()),
+        }
+    }
+}
+
+/// 依次尝试规则，返回改写后的 URI；没有规则命中时返回 None
+pub(crate) fn rewrite(rules: &[RewriteRule], uri: &Uri) -> Option<Uri> {
+    let path = uri.path();
+    let rewritten = rules.iter().find_map(|rule| rule.apply(path))?;
+    let rewritten = if rewritten.starts_with('/') { rewritten } else { format!("/{}", rewritten) };
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{}?{}", rewritten, query),
+        None => rewritten,
+    };
+    path_and_query.parse().ok()
+}
@@ -0,0 +1,156 @@
+use axum::http::Uri;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+
+// --- 运行时路由表 ---
+// 路径前缀 -> 上游 / key 分组。读多写少：读取时拿一份 Arc 快照，
+// 修改时整体替换，进行中的请求不受影响。
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteRule {
+    pub id: String,
+    /// 路径前缀，按最长前缀匹配
+    pub prefix: String,
+    pub target: String,
+    /// 转发前去掉匹配的前缀
+    #[serde(default)]
+    pub strip_prefix: bool,
+    /// 只使用 key 池里这个分组的 key (--key-group)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_group: Option<String>,
+    /// 发给这个上游的 Host 请求头，不设置时按 target 的主机名 (target 写成 IP 或内网负载均衡时用)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// 不注入 key 池的 key，客户端自带的凭证原样转发 (路由到其他厂商时)
+    #[serde(default)]
+    pub passthrough: bool,
+    /// 通过 Gateway::set_route / 管理 API 在运行时设置的路由，建连时还要再过一遍 SSRF 策略
+    #[serde(skip)]
+    pub(crate) dynamic: bool,
+}
+
+impl RouteRule {
+    /// 解析 `PREFIX=TARGET[;strip][;host=HOST][;keys=GROUP][;passthrough][;id=ID]`，PREFIX 末尾的 `*` 可以省略；
+    /// 没有给 id 时按顺序命名为 route-N
+    pub fn parse(spec: &str, index: usize) -> Result<Self, String> {
+        let mut parts = spec.split(';');
+        let (prefix, target) = parts
+            .next()
+            .and_then(|head| head.split_once('='))
+            .ok_or_else(|| format!("route '{}' must be PREFIX=TARGET", spec))?;
+        let prefix = prefix.trim().trim_end_matches('*');
+        if !prefix.starts_with('/') {
+            return Err(format!("route '{}': prefix must start with '/'", spec));
+        }
+        let target = target.trim().trim_end_matches('/');
+        let url = reqwest::Url::parse(target).map_err(|e| format!("route '{}': invalid target: {}", spec, e))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(format!("route '{}': target must be http:// or https://", spec));
+        }
+        let mut rule = RouteRule {
+            id: format!("route-{}", index + 1),
+            prefix: prefix.to_string(),
+            target: target.to_string(),
+            strip_prefix: false,
+            key_group: None,
+            host: None,
+            passthrough: false,
+            dynamic: false,
+        };
+        for part in parts.map(str::trim).filter(|p| !p.is_empty()) {
+            match part.split_once('=') {
+                None if part == "strip" => rule.strip_prefix = true,
+                None if part == "passthrough" => rule.passthrough = true,
+                Some(("host", host)) => rule.host = Some(host.trim().to_string()),
+                Some(("keys", group)) if !group.trim().is_empty() => rule.key_group = Some(group.trim().to_string()),
+                Some(("id", id)) if !id.trim().is_empty() => rule.id = id.trim().to_string(),
+                _ => return Err(format!("unknown route option '{}'", part)),
+            }
+        }
+        rule.validate()?;
+        Ok(rule)
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        match &self.host {
+            Some(host) if host.is_empty() || axum::http::HeaderValue::from_str(host).is_err() => {
+                Err(format!("route '{}': invalid host '{}'", self.id, host))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// strip_prefix 时需要去掉的长度
+    pub(crate) fn strip_len(&self) -> usize {
+        self.prefix.trim_end_matches('/').len()
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        match path.strip_prefix(self.prefix.trim_end_matches('/')) {
+            Some(rest) => rest.is_empty() || rest.starts_with('/') || self.prefix.ends_with('/'),
+            None => false,
+        }
+    }
+}
+
+/// 当前请求命中的路由规则
+#[derive(Debug, Clone)]
+pub struct MatchedRoute(pub Arc<RouteRule>);
+
+#[derive(Default)]
+pub struct RoutingTable {
+    rules: RwLock<Arc<Vec<Arc<RouteRule>>>>,
+}
+
+impl RoutingTable {
+    pub fn new(rules: Vec<RouteRule>) -> Self {
+        let table = Self::default();
+        table.replace_all(rules);
+        table
+    }
+
+    pub fn snapshot(&self) -> Arc<Vec<Arc<RouteRule>>> {
+        self.rules.read().unwrap().clone()
+    }
+
+    /// 整体替换 (配置文件重载)
+    pub fn replace_all(&self, rules: Vec<RouteRule>) {
+        let mut rules: Vec<Arc<RouteRule>> = rules.into_iter().map(Arc::new).collect();
+        // 长前缀优先
+        rules.sort_by_key(|r| std::cmp::Reverse(r.prefix.len()));
+        *self.rules.write().unwrap() = Arc::new(rules);
+    }
+
+    /// 新增或按 id 覆盖；返回是否覆盖了已有规则
+    pub fn upsert(&self, rule: RouteRule) -> bool {
+        let mut guard = self.rules.write().unwrap();
+        let mut rules: Vec<Arc<RouteRule>> = guard.iter().filter(|r| r.id != rule.id).cloned().collect();
+        let replaced = rules.len() != guard.len();
+        rules.push(Arc::new(rule));
+        rules.sort_by_key(|r| std::cmp::Reverse(r.prefix.len()));
+        *guard = Arc::new(rules);
+        replaced
+    }
+
+    /// 按 id 删除；返回是否存在
+    pub fn remove(&self, id: &str) -> bool {
+        let mut guard = self.rules.write().unwrap();
+        let rules: Vec<Arc<RouteRule>> = guard.iter().filter(|r| r.id != id).cloned().collect();
+        let removed = rules.len() != guard.len();
+        *guard = Arc::new(rules);
+        removed
+    }
+
+    /// 最长前缀匹配
+    pub fn resolve(&self, path: &str) -> Option<Arc<RouteRule>> {
+        self.rules.read().unwrap().iter().find(|r| r.matches(path)).cloned()
+    }
+}
+
+/// 去掉 path 开头 `len` 个字节，保留 query
+pub(crate) fn strip_path_prefix(uri: &Uri, len: usize) -> Option<Uri> {
+    let path_and_query = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+    let rest = path_and_query.get(len..)?;
+    let rewritten = if rest.starts_with('/') { rest.to_string() } else { format!("/{}", rest) };
+    rewritten.parse().ok()
+}
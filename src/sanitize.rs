@@ -0,0 +1,87 @@
+use axum::http::HeaderMap;
+use regex::Regex;
+use std::borrow::Cow;
+use std::sync::LazyLock;
+
+// --- 脱敏 ---
+// 录制、失败请求留存等需要把请求落盘或展示的地方，统一去掉凭证类请求头和 query 参数。
+// 日志、访问日志和返回给客户端的错误信息里则把 key 打码成只剩最后 4 位 (`***abcd`)，
+// 排查时还能分辨是哪个 key；上游错误信息 (reqwest 错误里的 URL、上游错误响应体) 里夹带的
+// Google API key 和 `key=` 参数同样打码。
+
+pub const SENSITIVE_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "x-goog-api-key",
+    "cookie",
+    "set-cookie",
+];
+pub const SENSITIVE_QUERY: &[&str] = &["key"];
+
+pub fn sanitize_path(path_and_query: &str) -> String {
+    let Some((path, query)) = path_and_query.split_once('?') else {
+        return path_and_query.to_string();
+    };
+    let kept: Vec<&str> = query
+        .split('&')
+        .filter(|pair| {
+            let name = pair.split('=').next().unwrap_or("");
+            !SENSITIVE_QUERY.contains(&name)
+        })
+        .collect();
+    if kept.is_empty() {
+        path.to_string()
+    } else {
+        format!("{}?{}", path, kept.join("&"))
+    }
+}
+
+pub fn sanitize_headers(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .filter(|(k, _)| !SENSITIVE_HEADERS.contains(&k.as_str()))
+        .filter_map(|(k, v)| v.to_str().ok().map(|v| (k.to_string(), v.to_string())))
+        .collect()
+}
+
+/// 只保留最后 4 位
+pub fn mask_key(key: &str) -> String {
+    // 已经打过码的不再处理
+    if key.starts_with("***") {
+        return key.to_string();
+    }
+    let chars: Vec<char> = key.chars().collect();
+    if chars.len() <= 8 {
+        return "***".to_string();
+    }
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("***{}", tail)
+}
+
+/// 和 sanitize_path 一样，但敏感 query 参数保留并打码，用于日志
+pub fn redact_path(path_and_query: &str) -> String {
+    let Some((path, query)) = path_and_query.split_once('?') else {
+        return path_and_query.to_string();
+    };
+    let pairs: Vec<String> = query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((name, value)) if SENSITIVE_QUERY.contains(&name) => format!("{}={}", name, mask_key(value)),
+            _ => pair.to_string(),
+        })
+        .collect();
+    format!("{}?{}", path, pairs.join("&"))
+}
+
+// Google API key 固定是 AIza 开头加 35 位
+static API_KEY: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"AIza[0-9A-Za-z_\-]{35}").expect("valid regex"));
+static KEY_PARAM: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"([?&]key=)([^&\s"'<>()]+)"#).expect("valid regex"));
+
+/// 把任意文本里出现的 API key 和 `key=` 参数打码；没有需要打码的内容时不分配
+pub fn redact_text(text: &str) -> Cow<'_, str> {
+    let text = KEY_PARAM.replace_all(text, |caps: &regex::Captures| format!("{}{}", &caps[1], mask_key(&caps[2])));
+    if !API_KEY.is_match(&text) {
+        return text;
+    }
+    Cow::Owned(API_KEY.replace_all(&text, |caps: &regex::Captures| mask_key(&caps[0])).into_owned())
+}
@@ -0,0 +1,159 @@
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+use tracing::{info, warn};
+
+use crate::client_ip::ClientIp;
+use crate::AppState;
+
+// 公网上常见的扫描器探测路径，Gemini API 不会出现这些片段
+const DEFAULT_PATTERNS: &[&str] = &[
+    "/wp-login.php",
+    "/wp-admin",
+    "/wp-content",
+    "/xmlrpc.php",
+    "/.env",
+    "/.git/",
+    "/.aws/",
+    "/phpmyadmin",
+    "/cgi-bin/",
+    "/actuator",
+    "/vendor/phpunit",
+    "/boaform",
+    ".php",
+];
+
+// 被拖住的扫描请求同时最多这么多个，再多的直接返回 404，拖延本身不会拖垮网关
+const MAX_TARPITTED: usize = 1024;
+
+// 最多同时封禁的 IP 数；表满时新的扫描器只拖延不封禁，等后台清理腾出位置
+const MAX_BANNED: usize = 100_000;
+
+// 后台清理过期封禁的间隔，请求路径上不做全表扫描
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+// --- 扫描器路径检测 + 自动封禁 ---
+pub struct ScannerGuard {
+    patterns: Vec<String>,
+    ban_duration: Duration,
+    tarpit: Duration,
+    banned: Mutex<HashMap<IpAddr, Instant>>,
+    tarpitted: Semaphore,
+}
+
+impl ScannerGuard {
+    pub fn new(extra_patterns: &[String], ban_duration: Duration, tarpit: Duration) -> Self {
+        let patterns = DEFAULT_PATTERNS
+            .iter()
+            .map(|p| p.to_string())
+            .chain(extra_patterns.iter().map(|p| p.to_lowercase()))
+            .collect();
+        Self {
+            patterns,
+            ban_duration,
+            tarpit,
+            banned: Mutex::new(HashMap::new()),
+            tarpitted: Semaphore::new(MAX_TARPITTED),
+        }
+    }
+
+    fn matched_pattern(&self, path: &str) -> Option<&str> {
+        let path = path.to_lowercase();
+        self.patterns
+            .iter()
+            .find(|p| path.contains(p.as_str()))
+            .map(|p| p.as_str())
+    }
+
+    fn is_banned(&self, ip: IpAddr) -> bool {
+        let mut banned = self.banned.lock().unwrap();
+        match banned.get(&ip) {
+            Some(until) if *until > Instant::now() => true,
+            Some(_) => {
+                banned.remove(&ip);
+                info!("🔓 Scanner ban expired for {}", ip);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// 表满时不封禁，返回 false
+    fn ban(&self, ip: IpAddr) -> bool {
+        let mut banned = self.banned.lock().unwrap();
+        if banned.len() >= MAX_BANNED && !banned.contains_key(&ip) {
+            return false;
+        }
+        banned.insert(ip, Instant::now() + self.ban_duration);
+        true
+    }
+
+    fn sweep(&self) {
+        let now = Instant::now();
+        self.banned.lock().unwrap().retain(|_, until| *until > now);
+    }
+}
+
+/// 后台定期清理过期的封禁；网关被释放 (热重载换掉) 后自动退出
+pub(crate) fn spawn_sweeper(state: Weak<AppState>) {
+    tokio::spawn(async move {
+        let mut timer = tokio::time::interval(SWEEP_INTERVAL);
+        timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        timer.tick().await;
+        loop {
+            timer.tick().await;
+            let Some(state) = state.upgrade() else {
+                return;
+            };
+            if let Some(scanner) = &state.scanner {
+                scanner.sweep();
+            }
+        }
+    });
+}
+
+pub(crate) async fn guard(
+    State(state): State<Arc<AppState>>,
+    ClientIp(ip): ClientIp,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Some(scanner) = &state.scanner else {
+        return next.run(req).await;
+    };
+
+    if scanner.is_banned(ip) {
+        state.metrics.inc("aizasy_scanner_banned_requests_total", &[]);
+        return (StatusCode::FORBIDDEN, "Forbidden").into_response();
+    }
+
+    if let Some(pattern) = scanner.matched_pattern(req.uri().path()) {
+        warn!(
+            "🪤 Scanner probe from {}: {} (banned for {}s)",
+            ip,
+            req.uri().path(),
+            scanner.ban_duration.as_secs()
+        );
+        state.metrics.inc("aizasy_scanner_hits_total", &[("pattern", pattern)]);
+        if scanner.ban(ip) {
+            state.metrics.inc("aizasy_scanner_bans_total", &[]);
+        }
+
+        // Tarpit: 拖住扫描器的连接再返回；拖住的连接太多时直接返回
+        match scanner.tarpitted.try_acquire() {
+            Ok(_permit) => tokio::time::sleep(scanner.tarpit).await,
+            Err(_) => state.metrics.inc("aizasy_scanner_tarpit_full_total", &[]),
+        }
+        return (StatusCode::NOT_FOUND, "Not Found").into_response();
+    }
+
+    next.run(req).await
+}
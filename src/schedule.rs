@@ -0,0 +1,272 @@
+use async_trait::async_trait;
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
+
+use crate::export::civil_from_days;
+use crate::plugin::{GatewayPlugin, RequestContext};
+use crate::storage::Storage;
+use crate::usage;
+
+// --- 定时可用窗口 ---
+// 用类 cron 表达式描述时间窗口 (表达式匹配的每一分钟都算在窗口内)，窗口内对匹配的路径 / 客户端
+// 直接拒绝或限速，例如夜间禁止批处理任务调用 pro 模型：
+//   night-pro;cron=* 22-23,0-5 * * *;path=/v1beta/models/gemini-1.5-pro;action=block
+//   batch-day;cron=* 9-18 * * 1-5;client=batch;action=throttle;limit=30
+// 时间按 UTC 计算，可以用 utc_offset=+8 换成固定时区。
+
+// 推算窗口结束时间时最多向后看这么多分钟
+const LOOKAHEAD_MINUTES: i64 = 7 * 24 * 60;
+
+/// cron 的一个字段：允许的取值集合 (按位)
+#[derive(Debug, Clone, Copy)]
+struct Field {
+    bits: u64,
+    // 原文是 `*` (用于日 / 星期的 OR 语义)
+    any: bool,
+}
+
+impl Field {
+    fn parse(spec: &str, min: u32, max: u32) -> Result<Self, String> {
+        let mut bits = 0u64;
+        for part in spec.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => (range, step.parse::<u32>().map_err(|_| format!("invalid step '{}'", part))?),
+                None => (part, 1),
+            };
+            if step == 0 {
+                return Err(format!("invalid step '{}'", part));
+            }
+            let (start, end) = if range == "*" {
+                (min, max)
+            } else if let Some((a, b)) = range.split_once('-') {
+                let a = a.parse::<u32>().map_err(|_| format!("invalid range '{}'", part))?;
+                let b = b.parse::<u32>().map_err(|_| format!("invalid range '{}'", part))?;
+                (a, b)
+            } else {
+                let v = range.parse::<u32>().map_err(|_| format!("invalid value '{}'", part))?;
+                // `5/10` 表示从 5 开始每 10 个
+                (v, if step > 1 { max } else { v })
+            };
+            if start < min || end > max || start > end {
+                return Err(format!("'{}' out of range {}-{}", part, min, max));
+            }
+            for v in (start..=end).step_by(step as usize) {
+                bits |= 1 << v;
+            }
+        }
+        Ok(Self { bits, any: spec == "*" })
+    }
+
+    fn contains(&self, v: u32) -> bool {
+        self.bits & (1 << v) != 0
+    }
+}
+
+/// 五段式 cron 表达式：分 时 日 月 星期 (0 和 7 都是星期日)
+#[derive(Debug, Clone)]
+pub struct Cron {
+    minute: Field,
+    hour: Field,
+    day: Field,
+    month: Field,
+    weekday: Field,
+}
+
+impl Cron {
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!("cron '{}' must have 5 fields", expr));
+        };
+        let mut weekday = Field::parse(weekday, 0, 7)?;
+        if weekday.contains(7) {
+            weekday.bits |= 1;
+        }
+        Ok(Self {
+            minute: Field::parse(minute, 0, 59)?,
+            hour: Field::parse(hour, 0, 23)?,
+            day: Field::parse(day, 1, 31)?,
+            month: Field::parse(month, 1, 12)?,
+            weekday,
+        })
+    }
+
+    /// unix 分钟 (已按时区偏移) 是否落在表达式内
+    fn matches(&self, unix_minute: i64) -> bool {
+        let days = unix_minute.div_euclid(1440);
+        let minute_of_day = unix_minute.rem_euclid(1440);
+        let (_, month, day) = civil_from_days(days);
+        // 1970-01-01 是星期四
+        let weekday = (days + 4).rem_euclid(7) as u32;
+        // 和标准 cron 一样：日和星期都有限制时满足其一即可
+        let day_ok = match (self.day.any, self.weekday.any) {
+            (false, false) => self.day.contains(day) || self.weekday.contains(weekday),
+            _ => self.day.contains(day) && self.weekday.contains(weekday),
+        };
+        self.minute.contains((minute_of_day % 60) as u32)
+            && self.hour.contains((minute_of_day / 60) as u32)
+            && self.month.contains(month)
+            && day_ok
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScheduleAction {
+    Block,
+    /// 窗口内每个客户端每分钟最多这么多请求
+    Throttle(u64),
+}
+
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    pub name: String,
+    cron: Cron,
+    /// 路径前缀，None 表示所有路径
+    pub path: Option<String>,
+    /// 客户端标识，None 表示所有客户端
+    pub client: Option<String>,
+    pub action: ScheduleAction,
+    /// 相对 UTC 的分钟偏移
+    pub utc_offset_minutes: i64,
+}
+
+impl Schedule {
+    /// 解析 `NAME;cron=EXPR;path=PREFIX;client=ID;action=block|throttle;limit=N;utc_offset=+8`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut parts = spec.split(';');
+        let name = parts.next().unwrap_or("").trim();
+        if name.is_empty() {
+            return Err(format!("schedule '{}' needs a name", spec));
+        }
+        let mut cron = None;
+        let mut path = None;
+        let mut client = None;
+        let mut action = "block";
+        let mut limit = None;
+        let mut utc_offset_minutes = 0;
+        for part in parts.map(str::trim).filter(|p| !p.is_empty()) {
+            match part.split_once('=') {
+                Some(("cron", expr)) => cron = Some(Cron::parse(expr)?),
+                Some(("path", prefix)) => path = Some(prefix.to_string()),
+                Some(("client", id)) => client = Some(id.to_string()),
+                Some(("action", value)) => action = value,
+                Some(("limit", n)) => {
+                    limit = Some(n.parse::<u64>().map_err(|_| format!("invalid limit in '{}'", spec))?)
+                }
+                Some(("utc_offset", offset)) => {
+                    let hours: f64 = offset.parse().map_err(|_| format!("invalid utc_offset in '{}'", spec))?;
+                    utc_offset_minutes = (hours * 60.0).round() as i64;
+                }
+                _ => return Err(format!("unknown schedule option '{}'", part)),
+            }
+        }
+        let action = match (action, limit) {
+            ("block", _) => ScheduleAction::Block,
+            ("throttle", Some(limit)) => ScheduleAction::Throttle(limit),
+            ("throttle", None) => return Err(format!("throttle schedule '{}' needs limit=N", name)),
+            (other, _) => return Err(format!("unknown schedule action '{}'", other)),
+        };
+        Ok(Self {
+            name: name.to_string(),
+            cron: cron.ok_or_else(|| format!("schedule '{}' needs cron=...", name))?,
+            path,
+            client,
+            action,
+            utc_offset_minutes,
+        })
+    }
+
+    fn local_minute(&self, now_secs: u64) -> i64 {
+        (now_secs / 60) as i64 + self.utc_offset_minutes
+    }
+
+    pub fn active_at(&self, now_secs: u64) -> bool {
+        self.cron.matches(self.local_minute(now_secs))
+    }
+
+    // 距离窗口结束还有多久 (向后最多看 LOOKAHEAD_MINUTES)
+    fn remaining(&self, now_secs: u64) -> Duration {
+        let start = self.local_minute(now_secs);
+        let minutes = (1..=LOOKAHEAD_MINUTES)
+            .find(|m| !self.cron.matches(start + m))
+            .unwrap_or(LOOKAHEAD_MINUTES);
+        Duration::from_secs((minutes * 60) as u64 - now_secs % 60)
+    }
+
+    fn applies_to(&self, path: &str, client: &str) -> bool {
+        self.path.as_deref().is_none_or(|p| path.starts_with(p)) && self.client.as_deref().is_none_or(|c| c == client)
+    }
+}
+
+pub struct ScheduleGuard {
+    schedules: Vec<Schedule>,
+    storage: Arc<dyn Storage>,
+}
+
+impl ScheduleGuard {
+    pub fn new(schedules: Vec<Schedule>, storage: Arc<dyn Storage>) -> Self {
+        Self { schedules, storage }
+    }
+}
+
+fn reject(status: StatusCode, retry_after: Duration, message: String) -> Response {
+    let code = if status == StatusCode::TOO_MANY_REQUESTS { "RESOURCE_EXHAUSTED" } else { "UNAVAILABLE" };
+    let body = json!({
+        "error": {
+            "code": status.as_u16(),
+            "message": message,
+            "status": code,
+        }
+    });
+    (status, [(header::RETRY_AFTER, retry_after.as_secs().max(1).to_string())], Json(body)).into_response()
+}
+
+#[async_trait]
+impl GatewayPlugin for ScheduleGuard {
+    fn name(&self) -> &str {
+        "schedule"
+    }
+
+    async fn on_request(&self, ctx: &mut RequestContext) -> Result<(), Response> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let client = usage::client_label(ctx);
+        let path = ctx.uri.path();
+        for schedule in &self.schedules {
+            if !schedule.applies_to(path, &client) || !schedule.active_at(now) {
+                continue;
+            }
+            match schedule.action {
+                ScheduleAction::Block => {
+                    debug!("🕒 {} blocked by schedule {}", path, schedule.name);
+                    let message = format!("Not available during scheduled window '{}'", schedule.name);
+                    return Err(reject(StatusCode::SERVICE_UNAVAILABLE, schedule.remaining(now), message));
+                }
+                ScheduleAction::Throttle(limit) => {
+                    let key = format!("schedule:{}:{}:{}", schedule.name, client, now / 60);
+                    let used = match self.storage.incr(&key, 1, Some(Duration::from_secs(120))).await {
+                        Ok(used) => used,
+                        Err(e) => {
+                            warn!("🕒 Schedule counter failed: {}", e);
+                            continue;
+                        }
+                    };
+                    if used as u64 > limit {
+                        let message = format!(
+                            "Limited to {} requests per minute during scheduled window '{}'",
+                            limit, schedule.name
+                        );
+                        return Err(reject(StatusCode::TOO_MANY_REQUESTS, Duration::from_secs(60 - now % 60), message));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
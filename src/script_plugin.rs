@@ -0,0 +1,133 @@
+use async_trait::async_trait;
+use axum::{
+    http::{HeaderMap, StatusCode},
+    response::Response,
+};
+use rhai::{Dynamic, Engine, Scope, AST};
+use serde::Serialize;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+use crate::plugin::{Directive, GatewayPlugin, RequestContext, RequestSnapshot, ResponseSnapshot};
+
+// --- Rhai 脚本钩子 ---
+// 脚本里按需定义以下函数，返回 () 表示不修改，返回 map 则按 Directive 解析:
+//
+//   fn on_request(req) {
+//       if req.headers["x-team"] == "batch" { return #{ path: "/v1beta/models/gemini-flash:generateContent" }; }
+//   }
+//   fn on_upstream_response(resp) { #{ remove_headers: ["server"] } }
+//
+// 文件修改后自动重新编译，编译失败时继续使用旧版本。
+
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+pub struct ScriptPlugin {
+    path: String,
+    engine: Engine,
+    ast: RwLock<Arc<AST>>,
+    modified: Mutex<Option<SystemTime>>,
+}
+
+impl ScriptPlugin {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+
+        let ast = engine.compile_file(path.into()).map_err(|e| format!("{}: {}", path, e))?;
+        Ok(Self {
+            path: path.to_string(),
+            engine,
+            ast: RwLock::new(Arc::new(ast)),
+            modified: Mutex::new(modified_time(path)),
+        })
+    }
+
+    /// 文件 mtime 变化时重新编译
+    pub fn reload_if_changed(&self) {
+        let current = modified_time(&self.path);
+        {
+            let mut modified = self.modified.lock().unwrap();
+            if current.is_none() || *modified == current {
+                return;
+            }
+            *modified = current;
+        }
+
+        match self.engine.compile_file(self.path.clone().into()) {
+            Ok(ast) => {
+                *self.ast.write().unwrap() = Arc::new(ast);
+                info!("📜 Script reloaded: {}", self.path);
+            }
+            Err(e) => warn!("📜 Script {} failed to compile, keeping previous version: {}", self.path, e),
+        }
+    }
+
+    /// 后台轮询文件变化
+    pub fn watch(self: &Arc<Self>, interval: Duration) {
+        let plugin = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                plugin.reload_if_changed();
+            }
+        });
+    }
+
+    fn call(&self, hook: &str, input: &impl Serialize) -> Result<Option<Directive>, String> {
+        let ast = self.ast.read().unwrap().clone();
+        if !ast.iter_functions().any(|f| f.name == hook) {
+            return Ok(None);
+        }
+
+        let input = rhai::serde::to_dynamic(input).map_err(|e| e.to_string())?;
+        let result: Dynamic = self
+            .engine
+            .call_fn(&mut Scope::new(), &ast, hook, (input,))
+            .map_err(|e| e.to_string())?;
+        if result.is_unit() {
+            return Ok(None);
+        }
+
+        rhai::serde::from_dynamic(&result)
+            .map(Some)
+            .map_err(|e| format!("invalid directive: {}", e))
+    }
+}
+
+fn modified_time(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[async_trait]
+impl GatewayPlugin for ScriptPlugin {
+    fn name(&self) -> &str {
+        &self.path
+    }
+
+    async fn on_request(&self, ctx: &mut RequestContext) -> Result<(), Response> {
+        let input = RequestSnapshot::new(ctx);
+
+        match self.call("on_request", &input) {
+            Ok(Some(directive)) => directive.apply_to_request(ctx),
+            Ok(None) => Ok(()),
+            Err(e) => {
+                // 脚本出错不影响转发
+                warn!("📜 Script {} on_request failed: {}", self.path, e);
+                Ok(())
+            }
+        }
+    }
+
+    async fn on_upstream_response(&self, ctx: &RequestContext, status: StatusCode, headers: &mut HeaderMap) {
+        let input = ResponseSnapshot::new(ctx, status, headers);
+
+        match self.call("on_upstream_response", &input) {
+            Ok(Some(directive)) => directive.apply_headers(headers),
+            Ok(None) => {}
+            Err(e) => warn!("📜 Script {} on_upstream_response failed: {}", self.path, e),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use age::secrecy::SecretString;
+use base64::Engine;
+use std::io::Read;
+
+const PREFIX: &str = "ENC[age:";
+const SUFFIX: &str = "]";
+
+// --- 配置加密值解密 ---
+// 形如 ENC[age:<base64>] 的值在加载时解密，<base64> 是 age 的二进制密文，例如:
+//   printf 'secret' | age -r age1... | base64 -w0
+//   printf 'secret' | age -p | base64 -w0
+// 其余值原样返回，因此加密和明文可以混用。
+pub struct SecretDecryptor {
+    identities: Vec<Box<dyn age::Identity>>,
+}
+
+impl SecretDecryptor {
+    pub fn new(identity_file: Option<&str>, passphrase: Option<&str>) -> Result<Self, String> {
+        let mut identities: Vec<Box<dyn age::Identity>> = Vec::new();
+
+        if let Some(path) = identity_file {
+            let file = age::IdentityFile::from_file(path.to_string())
+                .map_err(|e| format!("{}: {}", path, e))?;
+            identities.extend(file.into_identities().map_err(|e| format!("{}: {}", path, e))?);
+        }
+        if let Some(passphrase) = passphrase {
+            let passphrase = SecretString::from(passphrase.to_string());
+            identities.push(Box::new(age::scrypt::Identity::new(passphrase)));
+        }
+
+        Ok(Self { identities })
+    }
+
+    pub fn is_encrypted(value: &str) -> bool {
+        value.starts_with(PREFIX) && value.ends_with(SUFFIX)
+    }
+
+    pub fn decrypt_value(&self, value: &str) -> Result<String, String> {
+        if !Self::is_encrypted(value) {
+            return Ok(value.to_string());
+        }
+        if self.identities.is_empty() {
+            return Err("encrypted value found but no age identity or passphrase configured".into());
+        }
+
+        let encoded = &value[PREFIX.len()..value.len() - SUFFIX.len()];
+        let ciphertext = base64::engine::general_purpose::STANDARD
+            .decode(encoded.trim())
+            .map_err(|e| format!("invalid base64 in encrypted value: {}", e))?;
+
+        let decryptor = age::Decryptor::new_buffered(&ciphertext[..]).map_err(|e| e.to_string())?;
+        let mut reader = decryptor
+            .decrypt(self.identities.iter().map(|i| i.as_ref()))
+            .map_err(|e| e.to_string())?;
+
+        let mut plaintext = String::new();
+        reader
+            .read_to_string(&mut plaintext)
+            .map_err(|e| format!("decrypted value is not valid UTF-8: {}", e))?;
+        Ok(plaintext)
+    }
+
+    pub fn decrypt_opt(&self, value: &mut Option<String>) -> Result<(), String> {
+        if let Some(v) = value {
+            *v = self.decrypt_value(v)?;
+        }
+        Ok(())
+    }
+}
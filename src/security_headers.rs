@@ -0,0 +1,73 @@
+use axum::{
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::Arc;
+
+use crate::AppState;
+
+/// proxy_handler 在透传上游响应时打上这个标记，这类响应不注入安全头
+#[derive(Clone, Debug)]
+pub struct UpstreamResponse;
+
+// --- 安全响应头注入 (只针对网关自己生成的响应) ---
+pub struct SecurityHeaders {
+    headers: Vec<(HeaderName, HeaderValue)>,
+    // 这些路径前缀下的响应不注入
+    skip_prefixes: Vec<String>,
+}
+
+impl SecurityHeaders {
+    pub fn new(tls: bool, hsts_max_age: u64, skip_prefixes: &[String]) -> Self {
+        let mut headers = vec![
+            (
+                HeaderName::from_static("x-content-type-options"),
+                HeaderValue::from_static("nosniff"),
+            ),
+            (
+                HeaderName::from_static("x-frame-options"),
+                HeaderValue::from_static("DENY"),
+            ),
+            (
+                HeaderName::from_static("referrer-policy"),
+                HeaderValue::from_static("no-referrer"),
+            ),
+        ];
+        // HSTS 只在网关自身终结 TLS 时才有意义
+        if tls {
+            let value = format!("max-age={}; includeSubDomains", hsts_max_age);
+            headers.push((
+                HeaderName::from_static("strict-transport-security"),
+                HeaderValue::from_str(&value).expect("valid HSTS header"),
+            ));
+        }
+        Self {
+            headers,
+            skip_prefixes: skip_prefixes.to_vec(),
+        }
+    }
+
+    fn applies_to(&self, path: &str) -> bool {
+        !self.skip_prefixes.iter().any(|p| path.starts_with(p.as_str()))
+    }
+}
+
+pub(crate) async fn inject(State(state): State<Arc<AppState>>, req: Request, next: Next) -> Response {
+    let Some(security) = &state.security_headers else {
+        return next.run(req).await;
+    };
+    let applies = security.applies_to(req.uri().path());
+
+    let mut response = next.run(req).await;
+    if applies && response.extensions().get::<UpstreamResponse>().is_none() {
+        let headers = response.headers_mut();
+        for (name, value) in &security.headers {
+            if !headers.contains_key(name) {
+                headers.insert(name.clone(), value.clone());
+            }
+        }
+    }
+    response
+}
@@ -0,0 +1,224 @@
+use axum::{
+    extract::{Request, State},
+    http::{StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+use crate::client_ip::ClientIp;
+use crate::lockout;
+use crate::AppState;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const EXPIRES_PARAM: &str = "aizasy_expires";
+pub const SIGNATURE_PARAM: &str = "aizasy_sig";
+/// 密钥最短字节数：空密钥谁都能签，太短的可以穷举，而有效签名可以绕过客户端令牌
+pub const MIN_SECRET_LEN: usize = 16;
+
+// --- 限时签名 URL ---
+// 签名内容为 "{path}\n{expires}"，只覆盖路径和过期时间，其余查询参数不参与签名。
+pub struct UrlSigner {
+    secret: Vec<u8>,
+    // true: 代理路由必须带有效签名；false: 只校验带了签名的请求
+    required: bool,
+}
+
+/// 通过签名校验的请求会带上这个标记，后续鉴权可以据此放行
+#[derive(Clone, Debug)]
+pub struct SignedAccess;
+
+impl UrlSigner {
+    pub fn new(secret: &str, required: bool) -> Self {
+        Self {
+            secret: secret.as_bytes().to_vec(),
+            required,
+        }
+    }
+
+    /// 检查密钥长度；加密的值要在解密之后检查
+    pub fn check_secret(secret: &str) -> Result<(), String> {
+        if secret.len() < MIN_SECRET_LEN {
+            return Err(format!("signing secret must be at least {} bytes", MIN_SECRET_LEN));
+        }
+        Ok(())
+    }
+
+    fn mac(&self, path: &str, expires: u64) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts any key length");
+        mac.update(path.as_bytes());
+        mac.update(b"\n");
+        mac.update(expires.to_string().as_bytes());
+        mac
+    }
+
+    pub fn sign(&self, path: &str, expires: u64) -> String {
+        hex::encode(self.mac(path, expires).finalize().into_bytes())
+    }
+
+    /// 生成完整的 path?query，供运维分发
+    pub fn signed_path(&self, path_and_query: &str, ttl_secs: u64) -> String {
+        let (path, query) = path_and_query.split_once('?').unwrap_or((path_and_query, ""));
+        let expires = now_secs() + ttl_secs;
+        let sig = self.sign(path, expires);
+        let sep = if query.is_empty() { "" } else { "&" };
+        format!("{}?{}{}{}={}&{}={}", path, query, sep, EXPIRES_PARAM, expires, SIGNATURE_PARAM, sig)
+    }
+
+    fn verify(&self, path: &str, expires: u64, sig: &str) -> Result<(), &'static str> {
+        if expires < now_secs() {
+            return Err("expired");
+        }
+        let sig = hex::decode(sig).map_err(|_| "malformed signature")?;
+        self.mac(path, expires)
+            .verify_slice(&sig)
+            .map_err(|_| "bad signature")
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// 拆出签名参数，返回 (expires, sig, 去掉签名参数后的 query)
+fn split_signature(query: &str) -> (Option<&str>, Option<&str>, String) {
+    let mut expires = None;
+    let mut sig = None;
+    let mut rest = Vec::new();
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+        match pair.split_once('=') {
+            Some((EXPIRES_PARAM, v)) => expires = Some(v),
+            Some((SIGNATURE_PARAM, v)) => sig = Some(v),
+            _ => rest.push(pair),
+        }
+    }
+    (expires, sig, rest.join("&"))
+}
+
+/// 校验请求 URI 上的签名：没带签名参数时返回 Ok(None)，签名有效时返回去掉签名参数后的 path?query
+pub(crate) fn check(signer: &UrlSigner, uri: &Uri) -> Result<Option<String>, &'static str> {
+    let path = uri.path();
+    let (expires, sig, rest) = split_signature(uri.query().unwrap_or(""));
+    let (Some(expires), Some(sig)) = (expires, sig) else {
+        return Ok(None);
+    };
+    let expires = expires.parse::<u64>().map_err(|_| "malformed expiry")?;
+    signer.verify(path, expires, sig)?;
+    Ok(Some(if rest.is_empty() { path.to_string() } else { format!("{}?{}", path, rest) }))
+}
+
+pub(crate) async fn guard(
+    State(state): State<Arc<AppState>>,
+    ClientIp(ip): ClientIp,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let Some(signer) = &state.signer else {
+        return next.run(req).await;
+    };
+
+    match check(signer, req.uri()) {
+        Ok(Some(path_and_query)) => {
+            // 签名参数不转发给上游
+            if let Ok(uri) = path_and_query.parse::<Uri>() {
+                *req.uri_mut() = uri;
+            }
+            req.extensions_mut().insert(SignedAccess);
+            state.metrics.inc("aizasy_signed_url_accepted_total", &[]);
+        }
+        Ok(None) if signer.required => {
+            state.metrics.inc("aizasy_signed_url_rejected_total", &[("reason", "missing")]);
+            lockout::record_failure(&state, ip);
+            return (StatusCode::FORBIDDEN, "Signature required").into_response();
+        }
+        Ok(None) => {}
+        Err(reason) => {
+            warn!("🔏 Signed URL rejected for {}: {}", req.uri().path(), reason);
+            state.metrics.inc("aizasy_signed_url_rejected_total", &[("reason", reason)]);
+            lockout::record_failure(&state, ip);
+            return (StatusCode::FORBIDDEN, "Invalid or expired signature").into_response();
+        }
+    }
+
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uri(s: &str) -> Uri {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn signed_path_round_trips_and_strips_params() {
+        let signer = UrlSigner::new("s3cret", false);
+        let signed = signer.signed_path("/v1beta/models?pageSize=5", 60);
+        assert_eq!(check(&signer, &uri(&signed)), Ok(Some("/v1beta/models?pageSize=5".to_string())));
+    }
+
+    #[test]
+    fn short_secrets_are_rejected() {
+        assert!(UrlSigner::check_secret("").is_err());
+        assert!(UrlSigner::check_secret("s3cret").is_err());
+        assert!(UrlSigner::check_secret(&"x".repeat(MIN_SECRET_LEN)).is_ok());
+    }
+
+    #[test]
+    fn unsigned_request_is_not_checked() {
+        let signer = UrlSigner::new("s3cret", true);
+        assert_eq!(check(&signer, &uri("/v1beta/models?pageSize=5")), Ok(None));
+    }
+
+    #[test]
+    fn expired_signature_is_rejected() {
+        let signer = UrlSigner::new("s3cret", false);
+        let expires = now_secs() - 1;
+        let sig = signer.sign("/v1beta/models", expires);
+        let url = format!("/v1beta/models?{}={}&{}={}", EXPIRES_PARAM, expires, SIGNATURE_PARAM, sig);
+        assert_eq!(check(&signer, &uri(&url)), Err("expired"));
+    }
+
+    #[test]
+    fn tampered_path_is_rejected() {
+        let signer = UrlSigner::new("s3cret", false);
+        let signed = signer.signed_path("/v1beta/models", 60);
+        let tampered = signed.replacen("/v1beta/models", "/v1beta/files", 1);
+        assert_eq!(check(&signer, &uri(&tampered)), Err("bad signature"));
+    }
+
+    #[test]
+    fn tampered_expiry_is_rejected() {
+        let signer = UrlSigner::new("s3cret", false);
+        let expires = now_secs() + 60;
+        let sig = signer.sign("/v1beta/models", expires);
+        let url = format!("/v1beta/models?{}={}&{}={}", EXPIRES_PARAM, expires + 3600, SIGNATURE_PARAM, sig);
+        assert_eq!(check(&signer, &uri(&url)), Err("bad signature"));
+    }
+
+    #[test]
+    fn tampered_signature_is_rejected() {
+        let signer = UrlSigner::new("s3cret", false);
+        let expires = now_secs() + 60;
+        let mut sig = signer.sign("/v1beta/models", expires);
+        let last = if sig.ends_with('0') { "1" } else { "0" };
+        sig.replace_range(sig.len() - 1.., last);
+        let url = format!("/v1beta/models?{}={}&{}={}", EXPIRES_PARAM, expires, SIGNATURE_PARAM, sig);
+        assert_eq!(check(&signer, &uri(&url)), Err("bad signature"));
+
+        let url = format!("/v1beta/models?{}={}&{}=zz", EXPIRES_PARAM, expires, SIGNATURE_PARAM);
+        assert_eq!(check(&signer, &uri(&url)), Err("malformed signature"));
+    }
+
+    #[test]
+    fn other_secret_is_rejected() {
+        let signed = UrlSigner::new("s3cret", false).signed_path("/v1beta/models", 60);
+        assert_eq!(check(&UrlSigner::new("other", false), &uri(&signed)), Err("bad signature"));
+    }
+}
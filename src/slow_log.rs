@@ -0,0 +1,206 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tower::{Layer, Service};
+use tracing::warn;
+
+use crate::access_log::UpstreamInfo;
+use crate::events::RequestId;
+use crate::finish_body::{self, BodyWatcher};
+use crate::sanitize::redact_path;
+use crate::AppState;
+
+// --- 慢请求日志 ---
+// 打开 --slow-request-ms 后，上游首字节时间或整个请求 (含流式响应) 超过阈值的请求在结束时打一条 WARN，
+// 带上最后一次上游尝试的耗时拆分，排查偶发的上游延迟：
+//   dns        解析上游域名 (--resolve 固定的地址、复用的连接没有这一项)
+//   connect    TCP 连接加 TLS 握手 (reqwest 不单独暴露 TLS 握手时间；复用连接时为 reused)
+//   ttfb       从发出请求到收到上游响应头，包括 dns 和 connect
+//   stream     从收到响应头到响应体发完
+//   total      从请求进入网关算起，包括排队、换 key / 换上游重试
+// 连接阶段的耗时由所有上游客户端的连接器和解析器记到当前请求的 task-local 里；
+// 网关自己生成的响应 (鉴权失败、缓存命中等) 只有 total。
+
+/// 一次上游尝试建立连接的耗时
+#[derive(Debug, Clone, Copy, Default)]
+struct Phases {
+    dns: Option<Duration>,
+    // 连接器整体耗时，含 dns
+    connect: Option<Duration>,
+}
+
+tokio::task_local! {
+    static PHASES: Arc<Mutex<Phases>>;
+}
+
+fn update(f: impl FnOnce(&mut Phases)) {
+    let _ = PHASES.try_with(|phases| {
+        if let Ok(mut phases) = phases.lock() {
+            f(&mut phases);
+        }
+    });
+}
+
+/// 解析器记下这次解析的耗时
+pub(crate) fn record_dns(elapsed: Duration) {
+    update(|phases| phases.dns = Some(elapsed));
+}
+
+/// 一次上游尝试的耗时，随 UpstreamInfo 挂在响应 extensions 上
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct UpstreamTiming {
+    dns: Option<Duration>,
+    connect: Option<Duration>,
+    ttfb: Duration,
+    attempts: usize,
+}
+
+impl UpstreamTiming {
+    /// 从发出请求到收到上游响应头
+    pub(crate) fn ttfb(&self) -> Duration {
+        self.ttfb
+    }
+}
+
+/// 发送一次上游请求，同时记下连接阶段的耗时
+pub(crate) async fn measure<F: Future>(send: F, attempts: usize) -> (F::Output, UpstreamTiming) {
+    let started = Instant::now();
+    let phases = Arc::new(Mutex::new(Phases::default()));
+    let output = PHASES.scope(phases.clone(), send).await;
+    let phases = phases.lock().map(|p| *p).unwrap_or_default();
+    let timing = UpstreamTiming {
+        dns: phases.dns,
+        connect: phases.connect,
+        ttfb: started.elapsed(),
+        attempts,
+    };
+    (output, timing)
+}
+
+/// 给上游客户端的连接器计时
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ConnectTiming;
+
+impl<S> Layer<S> for ConnectTiming {
+    type Service = TimedConnector<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TimedConnector(inner)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct TimedConnector<S>(S);
+
+impl<S, R> Service<R> for TimedConnector<S>
+where
+    S: Service<R>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: R) -> Self::Future {
+        let started = Instant::now();
+        let connecting = self.0.call(request);
+        Box::pin(async move {
+            let result = connecting.await;
+            if result.is_ok() {
+                update(|phases| phases.connect = Some(started.elapsed()));
+            }
+            result
+        })
+    }
+}
+
+struct Pending {
+    threshold: Duration,
+    request_id: Option<u64>,
+    method: String,
+    path: String,
+    status: u16,
+    upstream: Option<UpstreamInfo>,
+    // 收到响应头的时间
+    headers_at: Duration,
+}
+
+fn ms(d: Duration) -> String {
+    format!("{:.1}ms", d.as_secs_f64() * 1000.0)
+}
+
+impl Pending {
+    fn finish(self, total: Duration) {
+        let timing = self.upstream.as_ref().and_then(|u| u.timing);
+        let ttfb = timing.map(|t| t.ttfb);
+        if total < self.threshold && ttfb.is_none_or(|ttfb| ttfb < self.threshold) {
+            return;
+        }
+        let optional = |d: Option<Duration>| d.map(ms).unwrap_or_else(|| "-".to_string());
+        let connect = match timing {
+            Some(UpstreamTiming { connect: Some(connect), dns, .. }) => ms(connect.saturating_sub(dns.unwrap_or_default())),
+            Some(_) => "reused".to_string(),
+            None => "-".to_string(),
+        };
+        warn!(
+            request_id = self.request_id,
+            "🐢 Slow request {} {} -> {} (upstream {}, status {}): dns={} connect={} ttfb={} stream={} total={} attempts={}",
+            self.method,
+            self.path,
+            self.status,
+            self.upstream.as_ref().map(|u| u.target.as_str()).unwrap_or("-"),
+            self.upstream.as_ref().and_then(|u| u.status).map(|s| s.to_string()).unwrap_or_else(|| "-".to_string()),
+            optional(timing.and_then(|t| t.dns)),
+            connect,
+            optional(ttfb),
+            ms(total.saturating_sub(self.headers_at)),
+            ms(total),
+            timing.map(|t| t.attempts).unwrap_or(0),
+        );
+    }
+}
+
+pub(crate) async fn record(State(state): State<Arc<AppState>>, req: Request, next: Next) -> Response {
+    let Some(threshold) = state.slow_request else {
+        return next.run(req).await;
+    };
+    let started_at = Instant::now();
+    let request_id = req.extensions().get::<RequestId>().map(|id| id.0);
+    let method = req.method().to_string();
+    let path = redact_path(req.uri().path_and_query().map(|p| p.as_str()).unwrap_or("/"));
+    let response = next.run(req).await;
+
+    let pending = Pending {
+        threshold,
+        request_id,
+        method,
+        path,
+        status: response.status().as_u16(),
+        upstream: response.extensions().get::<UpstreamInfo>().cloned(),
+        headers_at: started_at.elapsed(),
+    };
+    finish_body::watch(response, Timed { started_at, pending })
+}
+
+// 响应体发完或被丢弃 (按 Content-Length 发完后 hyper 不再轮询、客户端断开) 时检查总耗时
+struct Timed {
+    started_at: Instant,
+    pending: Pending,
+}
+
+impl BodyWatcher for Timed {
+    fn on_finish(self, _incomplete: bool) {
+        self.pending.finish(self.started_at.elapsed());
+    }
+}
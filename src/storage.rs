@@ -0,0 +1,379 @@
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// --- 可插拔存储后端 ---
+// 计数器、配额、缓存、key 状态等都落在这个 KV 接口上：单机用内存或 SQLite，
+// 多实例部署换成 Redis 即可共享状态。计数器以十进制字符串存放，和 Redis INCRBY 语义一致。
+
+#[async_trait]
+pub trait Storage: Send + Sync + 'static {
+    fn name(&self) -> &str;
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String>;
+
+    /// 写入值；ttl 为 None 表示永不过期
+    async fn set(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) -> Result<(), String>;
+
+    async fn delete(&self, key: &str) -> Result<(), String>;
+
+    /// 原子地加上 delta 并返回新值；key 不存在时从 0 开始，ttl 只在新建时设置
+    async fn incr(&self, key: &str, delta: i64, ttl: Option<Duration>) -> Result<i64, String>;
+
+    /// 按 key 字典序返回 [start, end) 区间内的所有条目，用于账本这类按时间排序的 key
+    async fn scan(&self, start: &str, end: &str) -> Result<Vec<(String, Vec<u8>)>, String>;
+}
+
+/// 按 `memory` / `sqlite:PATH` / `redis://...` 打开存储后端
+pub async fn open(spec: &str) -> Result<Arc<dyn Storage>, String> {
+    if spec == "memory" {
+        return Ok(Arc::new(MemoryStorage::new()));
+    }
+    if let Some(_path) = spec.strip_prefix("sqlite:") {
+        #[cfg(feature = "sqlite")]
+        return Ok(Arc::new(SqliteStorage::open(_path)?));
+        #[cfg(not(feature = "sqlite"))]
+        return Err("SQLite storage requires the `sqlite` feature".to_string());
+    }
+    if spec.starts_with("redis://") || spec.starts_with("rediss://") {
+        #[cfg(feature = "redis")]
+        return Ok(Arc::new(RedisStorage::connect(spec).await?));
+        #[cfg(not(feature = "redis"))]
+        return Err("Redis storage requires the `redis` feature".to_string());
+    }
+    Err(format!("unknown storage backend '{}'", spec))
+}
+
+/// 检查后端写法，不打开文件也不连接 Redis；配置检查用
+pub fn check(spec: &str) -> Result<(), String> {
+    if spec == "memory" {
+        return Ok(());
+    }
+    if let Some(_path) = spec.strip_prefix("sqlite:") {
+        #[cfg(feature = "sqlite")]
+        return crate::access_log::check_writable(std::path::Path::new(_path));
+        #[cfg(not(feature = "sqlite"))]
+        return Err("SQLite storage requires the `sqlite` feature".to_string());
+    }
+    if spec.starts_with("redis://") || spec.starts_with("rediss://") {
+        #[cfg(feature = "redis")]
+        return reqwest::Url::parse(spec).map(|_| ()).map_err(|e| format!("{}: {}", spec, e));
+        #[cfg(not(feature = "redis"))]
+        return Err("Redis storage requires the `redis` feature".to_string());
+    }
+    Err(format!("unknown storage backend '{}'", spec))
+}
+
+fn parse_counter(value: &[u8]) -> Result<i64, String> {
+    std::str::from_utf8(value)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| "value is not an integer".to_string())
+}
+
+// --- 内存实现 ---
+
+// (值, 过期时间)
+type Entry = (Vec<u8>, Option<Instant>);
+
+#[derive(Default)]
+pub struct MemoryStorage {
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+fn live(entry: &Entry) -> bool {
+    entry.1.map(|at| at > Instant::now()).unwrap_or(true)
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    fn name(&self) -> &str {
+        "memory"
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some(entry) if live(entry) => Ok(Some(entry.0.clone())),
+            Some(_) => {
+                entries.remove(key);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) -> Result<(), String> {
+        let expires = ttl.map(|ttl| Instant::now() + ttl);
+        self.entries.lock().unwrap().insert(key.to_string(), (value, expires));
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), String> {
+        self.entries.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    async fn incr(&self, key: &str, delta: i64, ttl: Option<Duration>) -> Result<i64, String> {
+        let mut entries = self.entries.lock().unwrap();
+        let current = match entries.get(key) {
+            Some(entry) if live(entry) => Some((parse_counter(&entry.0)?, entry.1)),
+            _ => None,
+        };
+        let (value, expires) = match current {
+            Some((value, expires)) => (value + delta, expires),
+            None => (delta, ttl.map(|ttl| Instant::now() + ttl)),
+        };
+        entries.insert(key.to_string(), (value.to_string().into_bytes(), expires));
+        Ok(value)
+    }
+
+    async fn scan(&self, start: &str, end: &str) -> Result<Vec<(String, Vec<u8>)>, String> {
+        let entries = self.entries.lock().unwrap();
+        let mut found: Vec<(String, Vec<u8>)> = entries
+            .iter()
+            .filter(|(k, e)| k.as_str() >= start && k.as_str() < end && live(e))
+            .map(|(k, e)| (k.clone(), e.0.clone()))
+            .collect();
+        found.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(found)
+    }
+}
+
+// --- SQLite 实现 ---
+
+#[cfg(feature = "sqlite")]
+pub struct SqliteStorage {
+    conn: Arc<Mutex<rusqlite::Connection>>,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteStorage {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let conn = rusqlite::Connection::open(path).map_err(|e| format!("{}: {}", path, e))?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS kv (
+                 key TEXT PRIMARY KEY,
+                 value BLOB NOT NULL,
+                 expires_at INTEGER
+             );",
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    // rusqlite 是同步接口，放到阻塞线程池里执行
+    async fn with_conn<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut rusqlite::Connection) -> rusqlite::Result<T> + Send + 'static,
+    ) -> Result<T, String> {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || f(&mut conn.lock().unwrap()))
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn unix_ms() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+#[cfg(feature = "sqlite")]
+fn expires_at(ttl: Option<Duration>) -> Option<i64> {
+    ttl.map(|ttl| unix_ms() + ttl.as_millis() as i64)
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl Storage for SqliteStorage {
+    fn name(&self) -> &str {
+        "sqlite"
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String> {
+        use rusqlite::OptionalExtension;
+        let key = key.to_string();
+        self.with_conn(move |conn| {
+            conn.query_row(
+                "SELECT value FROM kv WHERE key = ?1 AND (expires_at IS NULL OR expires_at > ?2)",
+                rusqlite::params![key, unix_ms()],
+                |row| row.get(0),
+            )
+            .optional()
+        })
+        .await
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) -> Result<(), String> {
+        let key = key.to_string();
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO kv (key, value, expires_at) VALUES (?1, ?2, ?3)",
+                rusqlite::params![key, value, expires_at(ttl)],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), String> {
+        let key = key.to_string();
+        self.with_conn(move |conn| conn.execute("DELETE FROM kv WHERE key = ?1", [key]).map(|_| ()))
+            .await
+    }
+
+    async fn incr(&self, key: &str, delta: i64, ttl: Option<Duration>) -> Result<i64, String> {
+        use rusqlite::OptionalExtension;
+        let key = key.to_string();
+        let value = self
+            .with_conn(move |conn| {
+                let tx = conn.transaction()?;
+                let now = unix_ms();
+                let current: Option<(Vec<u8>, Option<i64>)> = tx
+                    .query_row(
+                        "SELECT value, expires_at FROM kv WHERE key = ?1 AND (expires_at IS NULL OR expires_at > ?2)",
+                        rusqlite::params![key, now],
+                        |row| Ok((row.get(0)?, row.get(1)?)),
+                    )
+                    .optional()?;
+                let (value, expires) = match current {
+                    Some((value, expires)) => (parse_counter(&value).map(|v| v + delta), expires),
+                    None => (Ok(delta), expires_at(ttl)),
+                };
+                if let Ok(value) = value {
+                    tx.execute(
+                        "INSERT OR REPLACE INTO kv (key, value, expires_at) VALUES (?1, ?2, ?3)",
+                        rusqlite::params![key, value.to_string().into_bytes(), expires],
+                    )?;
+                }
+                tx.commit()?;
+                Ok(value)
+            })
+            .await?;
+        value
+    }
+
+    async fn scan(&self, start: &str, end: &str) -> Result<Vec<(String, Vec<u8>)>, String> {
+        let (start, end) = (start.to_string(), end.to_string());
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT key, value FROM kv WHERE key >= ?1 AND key < ?2
+                 AND (expires_at IS NULL OR expires_at > ?3) ORDER BY key",
+            )?;
+            let rows = stmt.query_map(rusqlite::params![start, end, unix_ms()], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect()
+        })
+        .await
+    }
+}
+
+// --- Redis 实现 ---
+
+#[cfg(feature = "redis")]
+pub struct RedisStorage {
+    conn: redis::aio::ConnectionManager,
+}
+
+#[cfg(feature = "redis")]
+impl RedisStorage {
+    pub async fn connect(url: &str) -> Result<Self, String> {
+        let client = redis::Client::open(url).map_err(|e| e.to_string())?;
+        let conn = redis::aio::ConnectionManager::new(client)
+            .await
+            .map_err(|e| format!("{}: {}", url, e))?;
+        Ok(Self { conn })
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl Storage for RedisStorage {
+    fn name(&self) -> &str {
+        "redis"
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String> {
+        let mut conn = self.conn.clone();
+        redis::cmd("GET").arg(key).query_async(&mut conn).await.map_err(|e| e.to_string())
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) -> Result<(), String> {
+        let mut conn = self.conn.clone();
+        let mut cmd = redis::cmd("SET");
+        cmd.arg(key).arg(value);
+        if let Some(ttl) = ttl {
+            cmd.arg("PX").arg(ttl.as_millis().max(1) as u64);
+        }
+        cmd.query_async(&mut conn).await.map_err(|e| e.to_string())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), String> {
+        let mut conn = self.conn.clone();
+        redis::cmd("DEL").arg(key).query_async(&mut conn).await.map_err(|e| e.to_string())
+    }
+
+    async fn incr(&self, key: &str, delta: i64, ttl: Option<Duration>) -> Result<i64, String> {
+        let mut conn = self.conn.clone();
+        let mut pipe = redis::pipe();
+        pipe.atomic().cmd("INCRBY").arg(key).arg(delta);
+        if let Some(ttl) = ttl {
+            // NX: 只给还没有过期时间的新 key 设置，和其他后端一致
+            pipe.cmd("PEXPIRE").arg(key).arg(ttl.as_millis().max(1) as u64).arg("NX").ignore();
+        }
+        let (value,): (i64,) = pipe.query_async(&mut conn).await.map_err(|e| e.to_string())?;
+        Ok(value)
+    }
+
+    // Redis 没有按字典序取 key 区间的原语：用 start/end 的公共前缀 SCAN，再在本地过滤
+    async fn scan(&self, start: &str, end: &str) -> Result<Vec<(String, Vec<u8>)>, String> {
+        let mut conn = self.conn.clone();
+        let common = start.chars().zip(end.chars()).take_while(|(a, b)| a == b).map(|(a, _)| a).collect::<String>();
+        let pattern = format!("{}*", common.replace('*', "\\*").replace('?', "\\?"));
+
+        let mut keys: Vec<String> = Vec::new();
+        let mut cursor: u64 = 0;
+        loop {
+            let (next, batch): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(&pattern)
+                .arg("COUNT")
+                .arg(1000)
+                .query_async(&mut conn)
+                .await
+                .map_err(|e| e.to_string())?;
+            keys.extend(batch.into_iter().filter(|k| k.as_str() >= start && k.as_str() < end));
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        keys.sort();
+
+        let mut found = Vec::with_capacity(keys.len());
+        for chunk in keys.chunks(500) {
+            let values: Vec<Option<Vec<u8>>> = redis::cmd("MGET")
+                .arg(chunk)
+                .query_async(&mut conn)
+                .await
+                .map_err(|e| e.to_string())?;
+            found.extend(chunk.iter().cloned().zip(values).filter_map(|(k, v)| v.map(|v| (k, v))));
+        }
+        Ok(found)
+    }
+}
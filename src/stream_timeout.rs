@@ -0,0 +1,138 @@
+use axum::body::Bytes;
+use futures_util::Stream;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::{Instant, Sleep};
+use tracing::warn;
+
+// --- 流式响应空闲超时 ---
+// 上游 SSE 偶尔会停在半路既不发数据也不断开，连接就一直占着。
+// 包装上游响应流：连续 N 秒没有收到任何字节时以错误结束流，客户端看到连接中断，
+// PluginStream 照常回调 on_complete (带错误)，上游连接随之释放。
+
+pub(crate) struct IdleTimeout<S> {
+    inner: S,
+    id: u64,
+    idle: Option<Duration>,
+    sleep: Option<Pin<Box<Sleep>>>,
+    expired: bool,
+}
+
+impl<S> IdleTimeout<S> {
+    /// idle 为 None 时原样转发
+    pub(crate) fn new(inner: S, id: u64, idle: Option<Duration>) -> Self {
+        Self {
+            inner,
+            id,
+            idle,
+            sleep: idle.map(|idle| Box::pin(tokio::time::sleep(idle))),
+            expired: false,
+        }
+    }
+}
+
+impl<S, E> Stream for IdleTimeout<S>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: std::fmt::Display,
+{
+    type Item = Result<Bytes, String>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if this.expired {
+            return Poll::Ready(None);
+        }
+        match Pin::new(&mut this.inner).poll_next(cx) {
+            Poll::Ready(Some(item)) => {
+                if let (Some(sleep), Some(idle)) = (this.sleep.as_mut(), this.idle) {
+                    sleep.as_mut().reset(Instant::now() + idle);
+                }
+                Poll::Ready(Some(item.map_err(|e| e.to_string())))
+            }
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => {
+                let Some(sleep) = this.sleep.as_mut() else {
+                    return Poll::Pending;
+                };
+                if sleep.as_mut().poll(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                this.expired = true;
+                let secs = this.idle.unwrap_or_default().as_secs();
+                warn!("⏳ Upstream stream for request {} idle for {}s, aborting", this.id, secs);
+                Poll::Ready(Some(Err(format!("upstream stream idle for {}s", secs))))
+            }
+        }
+    }
+}
+
+// --- SSE 保活 ---
+// 上游思考时间长的流式请求可能几十秒一个字节都不发，客户端和网关之间的负载均衡 / 企业代理 / 浏览器
+// 会把静默连接当成死连接掐掉。连续 N 秒没有数据时往下游插一行 SSE 注释 `: keepalive`，
+// 客户端的 SSE 解析器会忽略注释。只在事件边界 (上一块以空行结尾) 插入，不会截断半个事件；
+// 保活行不经过插件，也不重置上面的空闲超时 (空闲超时看的是上游)。
+
+const KEEPALIVE: &[u8] = b": keepalive\n\n";
+
+pub(crate) struct SseKeepalive<S> {
+    inner: S,
+    interval: Option<Duration>,
+    sleep: Option<Pin<Box<Sleep>>>,
+    // 已经转发的内容停在事件边界上
+    at_boundary: bool,
+}
+
+impl<S> SseKeepalive<S> {
+    /// interval 为 None 时原样转发
+    pub(crate) fn new(inner: S, interval: Option<Duration>) -> Self {
+        Self {
+            inner,
+            interval,
+            sleep: interval.map(|interval| Box::pin(tokio::time::sleep(interval))),
+            at_boundary: true,
+        }
+    }
+}
+
+impl<S, E> Stream for SseKeepalive<S>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+{
+    type Item = Result<Bytes, E>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        match Pin::new(&mut this.inner).poll_next(cx) {
+            Poll::Ready(Some(item)) => {
+                if let Ok(chunk) = &item {
+                    if !chunk.is_empty() {
+                        this.at_boundary = chunk.ends_with(b"\n\n") || chunk.ends_with(b"\r\n\r\n");
+                    }
+                }
+                if let (Some(sleep), Some(interval)) = (this.sleep.as_mut(), this.interval) {
+                    sleep.as_mut().reset(Instant::now() + interval);
+                }
+                Poll::Ready(Some(item))
+            }
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => {
+                let (Some(sleep), Some(interval)) = (this.sleep.as_mut(), this.interval) else {
+                    return Poll::Pending;
+                };
+                if sleep.as_mut().poll(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                sleep.as_mut().reset(Instant::now() + interval);
+                if this.at_boundary {
+                    return Poll::Ready(Some(Ok(Bytes::from_static(KEEPALIVE))));
+                }
+                // 停在半个事件里，等下一轮；重新注册定时器的唤醒
+                let _ = sleep.as_mut().poll(cx);
+                Poll::Pending
+            }
+        }
+    }
+}
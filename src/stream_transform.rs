@@ -0,0 +1,478 @@
+use axum::{
+    body::Bytes,
+    http::{header, HeaderMap, StatusCode},
+};
+use serde_json::Value;
+use std::sync::Arc;
+
+use crate::plugin::{ChunkTransformer, GatewayPlugin, RequestContext};
+use crate::validate::{camel_case, snake_case};
+
+// --- 流式响应改写 ---
+// 边转发边改写没有被缓冲的响应体 (streamGenerateContent 的 SSE 流、JSON 数组流，以及直接透传的 JSON 响应)，
+// 例如去掉客户端用不到的 safetyRatings / citationMetadata，或者把 modelVersion 改成对外的模型名。
+// 规则写法：
+//   drop:FIELD         删掉任意层级上名为 FIELD 的字段
+//   set:FIELD=VALUE    任意层级上已有的 FIELD 改成 VALUE (JSON 值，不是合法 JSON 时按字符串)
+// 字段名同时认 camelCase 和 snake_case。
+// 按事件增量处理：SSE 按空行切出事件，多行 data: 拼起来解析，改写后合成一行；JSON 按括号深度切出每个顶层对象 (或顶层数组里的每个元素)。
+// 一个事件里没有出现任何规则里的字段名时原样转发，不做解析和重新序列化；改写只增加一个事件的延迟，不会等整个响应。
+// 压缩过的响应和上游错误响应不改写；非流式响应被其他插件缓冲时也不经过这里。
+
+#[derive(Debug, Clone)]
+enum Op {
+    Drop,
+    Set(Value),
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    /// 字段名的 snake_case 和 camelCase 写法
+    names: [String; 2],
+    op: Op,
+}
+
+impl Rule {
+    fn parse(spec: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid stream transform '{}' (expected drop:FIELD or set:FIELD=VALUE)", spec);
+        let (field, op) = if let Some(field) = spec.strip_prefix("drop:") {
+            (field, Op::Drop)
+        } else if let Some((field, value)) = spec.strip_prefix("set:").and_then(|rest| rest.split_once('=')) {
+            let value = value.trim();
+            (field, Op::Set(serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()))))
+        } else {
+            return Err(invalid());
+        };
+        let field = field.trim();
+        if field.is_empty() || field.contains(['.', '"']) {
+            return Err(invalid());
+        }
+        // 规则可以用任意一种写法
+        let camel = camel_case(field);
+        Ok(Self {
+            names: [snake_case(&camel), camel],
+            op,
+        })
+    }
+
+    fn matches(&self, key: &str) -> bool {
+        self.names.iter().any(|name| name == key)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StreamTransform {
+    rules: Arc<Vec<Rule>>,
+    // 快速判断一个事件要不要解析：所有规则里的字段名，带引号
+    needles: Arc<Vec<String>>,
+}
+
+impl StreamTransform {
+    pub fn parse(specs: &[String]) -> Result<Self, String> {
+        let rules: Vec<Rule> = specs.iter().map(|spec| Rule::parse(spec)).collect::<Result<_, _>>()?;
+        let mut needles: Vec<String> = rules.iter().flat_map(|r| r.names.iter().map(|n| format!("\"{}\"", n))).collect();
+        needles.sort();
+        needles.dedup();
+        Ok(Self {
+            rules: Arc::new(rules),
+            needles: Arc::new(needles),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    fn touches(&self, event: &[u8]) -> bool {
+        self.needles.iter().any(|needle| event.windows(needle.len()).any(|w| w == needle.as_bytes()))
+    }
+
+    /// 改写一个 JSON 事件；没有改动 (或不是 JSON) 时返回 None
+    fn rewrite(&self, event: &[u8]) -> Option<Vec<u8>> {
+        if !self.touches(event) {
+            return None;
+        }
+        let mut value: Value = serde_json::from_slice(event).ok()?;
+        self.apply(&mut value).then(|| value.to_string().into_bytes())
+    }
+
+    fn apply(&self, value: &mut Value) -> bool {
+        match value {
+            Value::Object(map) => {
+                let mut changed = false;
+                map.retain(|key, _| {
+                    let drop = self.rules.iter().any(|r| matches!(r.op, Op::Drop) && r.matches(key));
+                    changed |= drop;
+                    !drop
+                });
+                for (key, item) in map.iter_mut() {
+                    let set = self.rules.iter().find_map(|r| match &r.op {
+                        Op::Set(value) if r.matches(key) => Some(value),
+                        _ => None,
+                    });
+                    match set {
+                        Some(value) if item != value => {
+                            *item = value.clone();
+                            changed = true;
+                        }
+                        Some(_) => {}
+                        None => changed |= self.apply(item),
+                    }
+                }
+                changed
+            }
+            Value::Array(items) => items.iter_mut().fold(false, |changed, item| self.apply(item) | changed),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Sse,
+    Json,
+}
+
+struct Transformer {
+    transform: StreamTransform,
+    format: Format,
+    // SSE：还没结束的事件；JSON：还没结束的对象
+    pending: Vec<u8>,
+    // SSE：pending 里最后一个不完整的行从这里开始，之前的行已经找过换行
+    line_start: usize,
+    // JSON 扫描状态
+    in_object: bool,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    // 顶层是数组时对象在第 1 层开始
+    array: Option<bool>,
+}
+
+impl Transformer {
+    fn new(transform: StreamTransform, format: Format) -> Self {
+        Self {
+            transform,
+            format,
+            pending: Vec::new(),
+            line_start: 0,
+            in_object: false,
+            depth: 0,
+            in_string: false,
+            escaped: false,
+            array: None,
+        }
+    }
+
+    // 一个完整的事件 (含结尾的空行)：data 行拼起来改写，改写后的 JSON 放在第一个 data 行的位置，
+    // 其他行 (event:、id:、注释) 原样保留；没有改动时整个事件原样输出
+    fn sse_event(&self, event: &[u8], out: &mut Vec<u8>) {
+        let lines: Vec<&[u8]> = event.split_inclusive(|b| *b == b'\n').collect();
+        let data: Vec<&[u8]> = lines
+            .iter()
+            .filter_map(|line| line_content(line).strip_prefix(b"data:"))
+            .map(|data| data.trim_ascii_start())
+            .collect();
+        let Some(json) = (!data.is_empty()).then(|| data.join(&b'\n')).and_then(|data| self.transform.rewrite(&data)) else {
+            out.extend_from_slice(event);
+            return;
+        };
+        let mut written = false;
+        for line in lines {
+            let content = line_content(line);
+            if !content.starts_with(b"data:") {
+                out.extend_from_slice(line);
+            } else if !written {
+                out.extend_from_slice(b"data: ");
+                out.extend_from_slice(&json);
+                out.extend_from_slice(&line[content.len()..]);
+                written = true;
+            }
+        }
+    }
+
+    fn feed_sse(&mut self, chunk: &[u8], out: &mut Vec<u8>) {
+        self.pending.extend_from_slice(chunk);
+        let mut from = self.line_start;
+        while let Some(pos) = self.pending[from..].iter().position(|b| *b == b'\n') {
+            let end = from + pos + 1;
+            let blank = line_content(&self.pending[from..end]).is_empty();
+            from = end;
+            // 空行结束一个事件
+            if blank {
+                let event: Vec<u8> = self.pending.drain(..end).collect();
+                self.sse_event(&event, out);
+                from = 0;
+            }
+        }
+        self.line_start = from;
+    }
+
+    fn feed_json(&mut self, chunk: &[u8], out: &mut Vec<u8>) {
+        // 不在对象里的字节 (数组括号、逗号、空白) 直接输出，对象攒齐了再改写
+        let mut start = 0;
+        for (i, &b) in chunk.iter().enumerate() {
+            if self.in_string {
+                if self.escaped {
+                    self.escaped = false;
+                } else if b == b'\\' {
+                    self.escaped = true;
+                } else if b == b'"' {
+                    self.in_string = false;
+                }
+                continue;
+            }
+            match b {
+                b'"' => self.in_string = true,
+                b'[' | b'{' => {
+                    if self.array.is_none() {
+                        self.array = Some(b == b'[');
+                    }
+                    if !self.in_object && b == b'{' && self.depth == self.base() {
+                        out.extend_from_slice(&chunk[start..i]);
+                        start = i;
+                        self.in_object = true;
+                    }
+                    self.depth += 1;
+                }
+                b']' | b'}' => {
+                    self.depth = self.depth.saturating_sub(1);
+                    if self.in_object && self.depth == self.base() {
+                        self.pending.extend_from_slice(&chunk[start..=i]);
+                        let object = std::mem::take(&mut self.pending);
+                        match self.transform.rewrite(&object) {
+                            Some(json) => out.extend_from_slice(&json),
+                            None => out.extend_from_slice(&object),
+                        }
+                        start = i + 1;
+                        self.in_object = false;
+                    }
+                }
+                _ => {}
+            }
+        }
+        if self.in_object {
+            self.pending.extend_from_slice(&chunk[start..]);
+        } else {
+            out.extend_from_slice(&chunk[start..]);
+        }
+    }
+
+    // 对象所在的层数
+    fn base(&self) -> usize {
+        usize::from(self.array == Some(true))
+    }
+}
+
+// 去掉行尾的 \n 或 \r\n
+fn line_content(line: &[u8]) -> &[u8] {
+    let content = line.strip_suffix(b"\n").unwrap_or(line);
+    content.strip_suffix(b"\r").unwrap_or(content)
+}
+
+impl ChunkTransformer for Transformer {
+    fn transform(&mut self, chunk: &Bytes) -> Bytes {
+        let mut out = Vec::with_capacity(chunk.len());
+        match self.format {
+            Format::Sse => self.feed_sse(chunk, &mut out),
+            Format::Json => self.feed_json(chunk, &mut out),
+        }
+        Bytes::from(out)
+    }
+
+    fn finish(&mut self) -> Bytes {
+        let pending = std::mem::take(&mut self.pending);
+        match self.format {
+            // 最后一个事件没有结尾的空行
+            Format::Sse => {
+                self.line_start = 0;
+                let mut out = Vec::new();
+                self.sse_event(&pending, &mut out);
+                Bytes::from(out)
+            }
+            // 没结束的对象 (上游响应被截断) 原样输出
+            Format::Json => Bytes::from(pending),
+        }
+    }
+}
+
+impl GatewayPlugin for StreamTransform {
+    fn name(&self) -> &str {
+        "stream-transform"
+    }
+
+    fn stream_transformer(&self, _ctx: &RequestContext, status: StatusCode, headers: &HeaderMap) -> Option<Box<dyn ChunkTransformer>> {
+        if !status.is_success() || headers.contains_key(header::CONTENT_ENCODING) {
+            return None;
+        }
+        let content_type = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).unwrap_or("");
+        let format = if content_type.starts_with("text/event-stream") {
+            Format::Sse
+        } else if content_type.starts_with("application/json") {
+            Format::Json
+        } else {
+            return None;
+        };
+        Some(Box::new(Transformer::new(self.clone(), format)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{HeaderValue, Method};
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Instant;
+
+    fn transform(specs: &[&str]) -> StreamTransform {
+        StreamTransform::parse(&specs.iter().map(|s| s.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    // 把 chunks 依次喂给一个新的 Transformer，返回拼起来的输出
+    fn run(specs: &[&str], format: Format, chunks: &[&[u8]]) -> String {
+        let mut transformer = Transformer::new(transform(specs), format);
+        let mut out = Vec::new();
+        for chunk in chunks {
+            out.extend_from_slice(&transformer.transform(&Bytes::copy_from_slice(chunk)));
+        }
+        out.extend_from_slice(&transformer.finish());
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn parses_rules() {
+        let t = transform(&["drop:safety_ratings", "set:modelVersion=\"public\"", "set:count=3", "set:tag=plain"]);
+        assert_eq!(t.len(), 4);
+        assert_eq!(t.rules[0].names, ["safety_ratings".to_string(), "safetyRatings".to_string()]);
+        assert!(matches!(&t.rules[1].op, Op::Set(Value::String(v)) if v == "public"));
+        assert!(matches!(&t.rules[2].op, Op::Set(v) if *v == 3));
+        // 不是合法 JSON 的值按字符串
+        assert!(matches!(&t.rules[3].op, Op::Set(Value::String(v)) if v == "plain"));
+
+        for bad in ["safetyRatings", "drop:", "set:field", "drop:a.b", "drop:\"x\""] {
+            assert!(StreamTransform::parse(&[bad.to_string()]).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn drops_and_sets_fields_at_any_depth() {
+        let t = transform(&["drop:safetyRatings", "set:model_version=\"gemini-public\""]);
+        let event = br#"{"candidates":[{"content":{"parts":[{"text":"hi"}]},"safety_ratings":[{"x":1}]},{"safetyRatings":[]}],"modelVersion":"gemini-2.0-flash-001"}"#;
+        let rewritten: Value = serde_json::from_slice(&t.rewrite(event).unwrap()).unwrap();
+        assert_eq!(
+            rewritten,
+            serde_json::json!({
+                "candidates": [{ "content": { "parts": [{ "text": "hi" }] } }, {}],
+                "modelVersion": "gemini-public",
+            })
+        );
+    }
+
+    #[test]
+    fn unchanged_events_are_not_reserialised() {
+        let t = transform(&["set:modelVersion=\"v\""]);
+        // 字段名没出现
+        assert_eq!(t.rewrite(br#"{"text": "hi"}"#), None);
+        // 出现了但值已经一样
+        assert_eq!(t.rewrite(br#"{"modelVersion": "v"}"#), None);
+        // 字段名出现在字符串里，不是 JSON 时原样转发
+        assert_eq!(t.rewrite(br#"not json "modelVersion""#), None);
+    }
+
+    #[test]
+    fn sse_event_split_across_chunks() {
+        let stream = "data: {\"text\":\"hi\",\"citationMetadata\":{}}\r\n\r\ndata: {\"text\":\"there\"}\r\n\r\n";
+        let expected = "data: {\"text\":\"hi\"}\r\n\r\ndata: {\"text\":\"there\"}\r\n\r\n";
+        let bytes = stream.as_bytes();
+        // 每一个位置都切一刀，包括切在 \r\n\r\n 中间
+        for split in 1..bytes.len() {
+            let (head, tail) = bytes.split_at(split);
+            assert_eq!(run(&["drop:citationMetadata"], Format::Sse, &[head, tail]), expected, "split at {}", split);
+        }
+        let single_bytes: Vec<&[u8]> = bytes.chunks(1).collect();
+        assert_eq!(run(&["drop:citationMetadata"], Format::Sse, &single_bytes), expected);
+    }
+
+    #[test]
+    fn sse_output_waits_for_the_end_of_the_event() {
+        let mut transformer = Transformer::new(transform(&["drop:citationMetadata"]), Format::Sse);
+        assert!(transformer.transform(&Bytes::from_static(b"data: {\"text\":\"hi\"}\n")).is_empty());
+        assert_eq!(transformer.transform(&Bytes::from_static(b"\n")), Bytes::from_static(b"data: {\"text\":\"hi\"}\n\n"));
+        assert!(transformer.finish().is_empty());
+    }
+
+    #[test]
+    fn sse_multi_line_data_is_joined_and_rewritten() {
+        let stream = "event: message\nid: 7\ndata: {\"text\":\"hi\",\ndata: \"safetyRatings\":[]}\n\n";
+        assert_eq!(run(&["drop:safetyRatings"], Format::Sse, &[stream.as_bytes()]), "event: message\nid: 7\ndata: {\"text\":\"hi\"}\n\n");
+    }
+
+    #[test]
+    fn sse_non_json_passes_through() {
+        let stream = ": keep-alive\n\nevent: ping\n\ndata: [DONE]\n\ndata: not json but mentions \"safetyRatings\"\n\n";
+        assert_eq!(run(&["drop:safetyRatings"], Format::Sse, &[stream.as_bytes()]), stream);
+    }
+
+    #[test]
+    fn sse_last_event_without_blank_line_is_flushed() {
+        let out = run(&["drop:safetyRatings"], Format::Sse, &[b"data: {\"a\":1,\"safetyRatings\":[]}\n\ndata: {\"b\":2,", b"\"safetyRatings\":[]}"]);
+        assert_eq!(out, "data: {\"a\":1}\n\ndata: {\"b\":2}");
+    }
+
+    #[test]
+    fn json_array_stream_split_across_chunks() {
+        let stream = r#"[{"text":"a}{\"","safetyRatings":[{"p":"x"}]}
+,{"text":"b","modelVersion":"m"}]"#;
+        let expected = r#"[{"text":"a}{\""}
+,{"modelVersion":"public","text":"b"}]"#;
+        let bytes = stream.as_bytes();
+        for split in 1..bytes.len() {
+            let (head, tail) = bytes.split_at(split);
+            let out = run(&["drop:safetyRatings", "set:modelVersion=public"], Format::Json, &[head, tail]);
+            assert_eq!(out, expected, "split at {}", split);
+        }
+    }
+
+    #[test]
+    fn json_single_object_is_rewritten() {
+        let out = run(&["drop:usageMetadata"], Format::Json, &[br#"{"text":"hi","usageMetadata":{"totalTokenCount":3}}"#]);
+        assert_eq!(out, r#"{"text":"hi"}"#);
+    }
+
+    #[test]
+    fn truncated_json_is_passed_through() {
+        let out = run(&["drop:usageMetadata"], Format::Json, &[br#"[{"text":"a"},{"usageMetadata":{"#]);
+        assert_eq!(out, r#"[{"text":"a"},{"usageMetadata":{"#);
+    }
+
+    #[test]
+    fn only_successful_uncompressed_sse_and_json_are_transformed() {
+        let t = transform(&["drop:safetyRatings"]);
+        let ctx = RequestContext {
+            id: 1,
+            method: Method::POST,
+            uri: "/v1beta/models/m:streamGenerateContent?alt=sse".parse().unwrap(),
+            headers: HeaderMap::new(),
+            body: Bytes::new(),
+            client_ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            extensions: Default::default(),
+            started_at: Instant::now(),
+        };
+        let headers = |content_type: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+            headers
+        };
+        assert!(t.stream_transformer(&ctx, StatusCode::OK, &headers("text/event-stream")).is_some());
+        assert!(t.stream_transformer(&ctx, StatusCode::OK, &headers("application/json; charset=UTF-8")).is_some());
+        assert!(t.stream_transformer(&ctx, StatusCode::OK, &headers("application/octet-stream")).is_none());
+        assert!(t.stream_transformer(&ctx, StatusCode::BAD_REQUEST, &headers("application/json")).is_none());
+        let mut gzip = headers("application/json");
+        gzip.insert(header::CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+        assert!(t.stream_transformer(&ctx, StatusCode::OK, &gzip).is_none());
+    }
+}
@@ -0,0 +1,278 @@
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{body::Bytes, Json};
+use serde_json::{json, Map, Value};
+use std::str::FromStr;
+use tracing::debug;
+
+use crate::grpc;
+use crate::plugin::{GatewayPlugin, RequestContext};
+use crate::usage;
+use crate::validate::snake_case;
+
+// --- 强制系统提示词 ---
+// 转发前往 generateContent / streamGenerateContent 和创建 cachedContents 的请求体里注入组织要求的
+// systemInstruction，没有自己设置系统提示词的客户端也受约束。规则写法 SCOPE=TEXT，TEXT 以 @ 开头时从文件读取:
+//   *              所有请求
+//   /PREFIX        路径以 PREFIX 开头的请求 (按路由区分)
+//   client:NAME    这个客户端 (令牌名；没有令牌时按 ip:<地址>)
+//   METHOD         方法名，如 generateContent
+// 一个请求命中多条规则时按顺序全部注入。和客户端自己的 systemInstruction 的合并方式:
+//   prepend   放在客户端的 parts 前面 (默认)
+//   append    放在客户端的 parts 后面
+//   replace   丢弃客户端的内容，只用强制的
+//   default   只在客户端没有设置时补上
+// 引用了 cachedContent 的请求不能再带 systemInstruction (上游会拒绝)，这类请求跳过，改为在创建缓存时注入。
+// batchGenerateContent 注入到内联的每个子请求 (batch.inputConfig.requests.requests[].request)；
+// 用上传文件作为输入的批任务网关改不了内容，命中规则时拒绝。
+// gRPC 的 GenerateContent / StreamGenerateContent 请求体是 protobuf，同样无法注入，命中规则时拒绝，
+// 免得客户端换成 gRPC 就绕过强制的系统提示词。
+
+const METHODS: &[&str] = &["generateContent", "streamGenerateContent", "batchGenerateContent"];
+
+/// gRPC 方法名 (路径最后一段) 对应的 REST 方法名
+const GRPC_METHODS: &[(&str, &str)] = &[
+    ("GenerateContent", "generateContent"),
+    ("StreamGenerateContent", "streamGenerateContent"),
+    ("BatchGenerateContent", "batchGenerateContent"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SystemPromptMode {
+    Prepend,
+    Append,
+    Replace,
+    Default,
+}
+
+impl SystemPromptMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            SystemPromptMode::Prepend => "prepend",
+            SystemPromptMode::Append => "append",
+            SystemPromptMode::Replace => "replace",
+            SystemPromptMode::Default => "default",
+        }
+    }
+}
+
+impl FromStr for SystemPromptMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "prepend" => Ok(SystemPromptMode::Prepend),
+            "append" => Ok(SystemPromptMode::Append),
+            "replace" => Ok(SystemPromptMode::Replace),
+            "default" => Ok(SystemPromptMode::Default),
+            other => Err(format!(
+                "unknown system prompt mode '{}', expected prepend, append, replace or default",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Scope {
+    All,
+    Prefix(String),
+    Client(String),
+    Method(String),
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    scope: Scope,
+    text: String,
+}
+
+impl Rule {
+    fn parse(spec: &str) -> Result<Self, String> {
+        let (scope, text) = spec
+            .split_once('=')
+            .ok_or_else(|| format!("system prompt '{}' must be SCOPE=TEXT", spec))?;
+        let scope = match scope.trim() {
+            "" => return Err(format!("system prompt '{}' has an empty scope", spec)),
+            "*" => Scope::All,
+            prefix if prefix.starts_with('/') => Scope::Prefix(prefix.to_string()),
+            other => match other.strip_prefix("client:") {
+                Some("") => return Err(format!("system prompt '{}' has an empty client", spec)),
+                Some(client) => Scope::Client(client.to_string()),
+                None => Scope::Method(other.to_string()),
+            },
+        };
+        let text = match text.trim().strip_prefix('@') {
+            Some(path) => std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?,
+            None => text.to_string(),
+        };
+        if text.trim().is_empty() {
+            return Err(format!("system prompt '{}' has no text", spec));
+        }
+        Ok(Self { scope, text })
+    }
+
+    fn matches(&self, ctx: &RequestContext, method: &str) -> bool {
+        match &self.scope {
+            Scope::All => true,
+            Scope::Prefix(prefix) => ctx.uri.path().starts_with(prefix.as_str()),
+            Scope::Client(client) => usage::client_label(ctx) == *client,
+            Scope::Method(name) => name == method,
+        }
+    }
+}
+
+pub struct SystemPrompt {
+    rules: Vec<Rule>,
+    mode: SystemPromptMode,
+}
+
+impl SystemPrompt {
+    pub fn parse(specs: &[String], mode: SystemPromptMode) -> Result<Self, String> {
+        let rules = specs.iter().map(|spec| Rule::parse(spec)).collect::<Result<_, _>>()?;
+        Ok(Self { rules, mode })
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn mode(&self) -> SystemPromptMode {
+        self.mode
+    }
+
+    /// 注入后的请求体；没有改动时返回 None
+    fn apply(&self, body: &Bytes, texts: &[&str]) -> Option<Bytes> {
+        let mut json: Value = serde_json::from_slice(body).ok()?;
+        self.inject(json.as_object_mut()?, texts).then(|| Bytes::from(json.to_string()))
+    }
+
+    /// 批任务里每个内联子请求都注入；用文件输入时返回 Err(原因)
+    fn apply_batch(&self, body: &Bytes, texts: &[&str]) -> Result<Option<Bytes>, &'static str> {
+        let Ok(mut json) = serde_json::from_slice::<Value>(body) else {
+            return Ok(None);
+        };
+        let Some(input) = field_mut(&mut json, "batch").and_then(|batch| field_mut(batch, "inputConfig")) else {
+            return Ok(None);
+        };
+        if input.as_object().is_some_and(|obj| existing_key(obj, "fileName").is_some()) {
+            return Err("batches that read requests from a file cannot carry the enforced system prompt, send the requests inline");
+        }
+        let Some(Value::Array(items)) = field_mut(input, "requests").and_then(|requests| field_mut(requests, "requests")) else {
+            return Ok(None);
+        };
+        let mut changed = false;
+        for item in items {
+            if let Some(Value::Object(req)) = field_mut(item, "request") {
+                changed |= self.inject(req, texts);
+            }
+        }
+        Ok(changed.then(|| Bytes::from(json.to_string())))
+    }
+
+    /// 往一个 GenerateContentRequest 里注入，返回是否有改动
+    fn inject(&self, req: &mut Map<String, Value>, texts: &[&str]) -> bool {
+        if existing_key(req, "cachedContent").is_some() {
+            return false;
+        }
+        let key = existing_key(req, "systemInstruction").unwrap_or_else(|| "systemInstruction".to_string());
+        let enforced = texts.iter().map(|text| json!({ "text": text }));
+        let existing = req.get(&key).map(client_parts).unwrap_or_default();
+        let parts: Vec<Value> = match self.mode {
+            SystemPromptMode::Default if !existing.is_empty() => return false,
+            SystemPromptMode::Prepend => enforced.chain(existing).collect(),
+            SystemPromptMode::Append => existing.into_iter().chain(enforced).collect(),
+            SystemPromptMode::Replace | SystemPromptMode::Default => enforced.collect(),
+        };
+        req.insert(key, json!({ "parts": parts }));
+        true
+    }
+}
+
+/// 对象里的字段 (camelCase 或 snake_case)
+fn field_mut<'a>(value: &'a mut Value, name: &str) -> Option<&'a mut Value> {
+    let obj = value.as_object_mut()?;
+    let key = existing_key(obj, name)?;
+    obj.get_mut(&key)
+}
+
+fn reject(message: &str) -> Response {
+    let body = json!({
+        "error": {
+            "code": 400,
+            "message": message,
+            "status": "FAILED_PRECONDITION",
+        }
+    });
+    (StatusCode::BAD_REQUEST, Json(body)).into_response()
+}
+
+/// 请求里实际使用的字段名 (camelCase 或 snake_case)
+fn existing_key(obj: &Map<String, Value>, name: &str) -> Option<String> {
+    if obj.contains_key(name) {
+        return Some(name.to_string());
+    }
+    let snake = snake_case(name);
+    obj.contains_key(&snake).then_some(snake)
+}
+
+/// 客户端自己的系统提示词：Content 对象取 parts，有的 SDK 直接传字符串
+fn client_parts(value: &Value) -> Vec<Value> {
+    match value {
+        Value::String(text) if !text.is_empty() => vec![json!({ "text": text })],
+        Value::Object(content) => content.get("parts").and_then(Value::as_array).cloned().unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+#[async_trait]
+impl GatewayPlugin for SystemPrompt {
+    fn name(&self) -> &str {
+        "system-prompt"
+    }
+
+    async fn on_request(&self, ctx: &mut RequestContext) -> Result<(), Response> {
+        let path = ctx.uri.path();
+        let is_grpc = grpc::is_grpc(&ctx.headers);
+        let method = match is_grpc {
+            true => path.rsplit_once('/').and_then(|(_, name)| GRPC_METHODS.iter().find(|(grpc, _)| *grpc == name)).map(|(_, rest)| *rest),
+            false => path.rsplit_once(':').map(|(_, method)| method),
+        };
+        let method = method.unwrap_or("");
+        let creates_cache = !is_grpc && ctx.method == axum::http::Method::POST && path.ends_with("/cachedContents");
+        if !METHODS.contains(&method) && !creates_cache {
+            return Ok(());
+        }
+        let method = if creates_cache { "cachedContents" } else { method };
+        let texts: Vec<&str> = self
+            .rules
+            .iter()
+            .filter(|rule| rule.matches(ctx, method))
+            .map(|rule| rule.text.as_str())
+            .collect();
+        if texts.is_empty() {
+            return Ok(());
+        }
+        if is_grpc {
+            debug!("🧭 Rejected gRPC request {}: system prompt rules apply to it", ctx.id);
+            return Err(grpc::error_response(
+                StatusCode::FORBIDDEN,
+                "the gateway enforces a system prompt on this method, use the REST API",
+            ));
+        }
+        let body = match method {
+            "batchGenerateContent" => self.apply_batch(&ctx.body, &texts).map_err(reject)?,
+            _ => self.apply(&ctx.body, &texts),
+        };
+        if let Some(body) = body {
+            debug!("🧭 Injected {} system prompt(s) into request {}", texts.len(), ctx.id);
+            ctx.body = body;
+        }
+        Ok(())
+    }
+}
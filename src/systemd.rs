@@ -0,0 +1,124 @@
+use socket2::{Socket, Type};
+use std::net::SocketAddr;
+use std::os::fd::{FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::{UnixDatagram, UnixListener};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+// --- systemd 集成 ---
+// 不依赖 libsystemd，只实现网关用得到的两部分协议：
+//   socket 激活   由 systemd 监听端口 (.socket 单元)，进程通过 LISTEN_FDS / LISTEN_PID 继承 fd 3 起的监听 socket；
+//                 有继承的 socket 时用它们代替 --listen / --unix-socket (TLS、--listen-plain 照常按地址生效)，
+//                 和管理 API 地址相同的那个交给管理 API。重启服务期间连接在 socket 的队列里排队，不会被拒绝
+//   sd_notify     往 NOTIFY_SOCKET 发状态 (Type=notify)：所有端口就绪后 READY=1，开始摘流时 STOPPING=1，
+//                 热重载时 RELOADING=1 / READY=1；设置了 WatchdogSec 时按一半间隔发 WATCHDOG=1
+// 没有这些环境变量 (不是 systemd 启动的) 时什么都不做。
+
+// systemd 传下来的第一个 fd (SD_LISTEN_FDS_START)
+const LISTEN_FDS_START: i32 = 3;
+
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// systemd 传下来的监听 socket
+#[derive(Default)]
+pub(crate) struct Inherited {
+    pub(crate) tcp: Vec<(SocketAddr, std::net::TcpListener)>,
+    pub(crate) unix: Vec<UnixListener>,
+}
+
+impl Inherited {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.tcp.is_empty() && self.unix.is_empty()
+    }
+
+    /// 取出监听在 addr 上的 socket
+    pub(crate) fn take_tcp(&mut self, addr: SocketAddr) -> Option<std::net::TcpListener> {
+        let index = self.tcp.iter().position(|(a, _)| *a == addr)?;
+        Some(self.tcp.remove(index).1)
+    }
+}
+
+// 环境变量是否是给当前进程的 (LISTEN_PID / WATCHDOG_PID 没设置时不限制)
+fn for_us(pid_var: &str, required: bool) -> bool {
+    match std::env::var(pid_var).ok().and_then(|v| v.parse::<u32>().ok()) {
+        Some(pid) => pid == std::process::id(),
+        None => !required,
+    }
+}
+
+/// 取出 systemd 传下来的监听 socket；fd 只能接管一次，之后 (热重载) 返回空
+pub(crate) fn take_listeners() -> Inherited {
+    let mut inherited = Inherited::default();
+    if !for_us("LISTEN_PID", true) || TAKEN.swap(true, Ordering::SeqCst) {
+        return inherited;
+    }
+    let count = std::env::var("LISTEN_FDS").ok().and_then(|v| v.parse::<i32>().ok()).unwrap_or(0);
+    for fd in LISTEN_FDS_START..LISTEN_FDS_START + count {
+        // LISTEN_FDS 声明的 fd 归这个进程所有，而且只在这里接管一次
+        let socket = Socket::from(unsafe { OwnedFd::from_raw_fd(fd) });
+        if !matches!(socket.r#type(), Ok(Type::STREAM)) {
+            warn!("⚠️  Ignoring systemd socket fd {}: not a stream socket", fd);
+            continue;
+        }
+        let Ok(addr) = socket.local_addr() else {
+            warn!("⚠️  Ignoring systemd socket fd {}: not a socket", fd);
+            continue;
+        };
+        if let Err(e) = socket.set_nonblocking(true) {
+            warn!("⚠️  Ignoring systemd socket fd {}: {}", fd, e);
+            continue;
+        }
+        match addr.as_socket() {
+            Some(addr) => inherited.tcp.push((addr, socket.into())),
+            None if addr.is_unix() => inherited.unix.push(socket.into()),
+            None => warn!("⚠️  Ignoring systemd socket fd {}: unsupported address family", fd),
+        }
+    }
+    if !inherited.is_empty() {
+        info!("🧩 Inherited {} listening socket(s) from systemd", inherited.tcp.len() + inherited.unix.len());
+    }
+    inherited
+}
+
+/// 给 systemd 发一条状态，如 READY=1
+pub(crate) fn notify(state: &str) {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    let result = UnixDatagram::unbound().and_then(|socket| match path.as_bytes().strip_prefix(b"@") {
+        // 以 @ 开头的是 Linux 的抽象命名空间
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "abstract socket")),
+        None => socket.send_to(state.as_bytes(), &path),
+    });
+    if let Err(e) = result {
+        debug!("🧩 sd_notify {} failed: {}", state, e);
+    }
+}
+
+/// 设置了 WatchdogSec 时按一半的间隔发心跳
+pub(crate) fn spawn_watchdog() {
+    let Some(usec) = std::env::var("WATCHDOG_USEC").ok().and_then(|v| v.parse::<u64>().ok()).filter(|u| *u > 0) else {
+        return;
+    };
+    if !for_us("WATCHDOG_PID", false) {
+        return;
+    }
+    let interval = Duration::from_micros(usec / 2);
+    info!("🧩 systemd watchdog: pinging every {:?}", interval);
+    tokio::spawn(async move {
+        let mut timer = tokio::time::interval(interval);
+        loop {
+            timer.tick().await;
+            notify("WATCHDOG=1");
+        }
+    });
+}
@@ -0,0 +1,226 @@
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::Url;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+
+// --- 动态上游地址的 SSRF 防护策略 ---
+// 运行时通过 API 设置的 target 都要先过这里，防止管理 token 泄露后网关被当成内网扫描器。
+#[derive(Clone, Debug)]
+pub struct TargetPolicy {
+    pub require_https: bool,
+    pub allow_private: bool,
+    // 非空时只允许这些主机名 (精确匹配，或以 "." 开头表示后缀匹配)
+    pub allowed_hosts: Vec<String>,
+}
+
+impl Default for TargetPolicy {
+    fn default() -> Self {
+        Self {
+            require_https: true,
+            allow_private: false,
+            allowed_hosts: Vec::new(),
+        }
+    }
+}
+
+impl TargetPolicy {
+    /// 校验 URL；主机名会被解析，任一解析结果落在内网段都拒绝
+    pub async fn check(&self, target: &str) -> Result<Url, String> {
+        let url = Url::parse(target).map_err(|e| format!("invalid URL: {}", e))?;
+
+        match url.scheme() {
+            "https" => {}
+            "http" if !self.require_https => {}
+            scheme => return Err(format!("scheme '{}' not allowed", scheme)),
+        }
+
+        let host = url.host_str().ok_or("URL has no host")?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if !self.allowed_hosts.is_empty() && !self.host_allowed(host) {
+            return Err(format!("host '{}' not in allowlist", host));
+        }
+
+        if !self.allow_private {
+            let port = url.port_or_known_default().unwrap_or(443);
+            let addrs = tokio::net::lookup_host((host, port))
+                .await
+                .map_err(|e| format!("cannot resolve '{}': {}", host, e))?;
+            for addr in addrs {
+                if is_internal(addr.ip()) {
+                    return Err(format!("'{}' resolves to internal address {}", host, addr.ip()));
+                }
+            }
+        }
+
+        Ok(url)
+    }
+
+    fn host_allowed(&self, host: &str) -> bool {
+        let host = host.to_lowercase();
+        self.allowed_hosts.iter().any(|allowed| {
+            let allowed = allowed.to_lowercase();
+            match allowed.strip_prefix('.') {
+                Some(suffix) => host == suffix || host.ends_with(&allowed),
+                None => host == allowed,
+            }
+        })
+    }
+}
+
+// 设置路由时的检查挡不住 DNS 重绑定 (域名之后改解析到 127.0.0.1、169.254.169.254)：
+// 运行时设置的路由走单独的客户端，每次建连解析出的内网地址都在这里被过滤掉
+pub(crate) struct PolicyResolver {
+    // None 时用系统解析
+    inner: Option<Arc<dyn Resolve>>,
+}
+
+impl PolicyResolver {
+    pub(crate) fn new(inner: Option<Arc<dyn Resolve>>) -> Self {
+        Self { inner }
+    }
+}
+
+impl Resolve for PolicyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let addrs: Vec<SocketAddr> = match inner {
+                Some(inner) => inner.resolve(name).await?.collect(),
+                None => tokio::net::lookup_host((host.as_str(), 0)).await?.collect(),
+            };
+            let total = addrs.len();
+            let allowed: Vec<SocketAddr> = addrs.into_iter().filter(|addr| !is_internal(addr.ip())).collect();
+            if allowed.is_empty() && total > 0 {
+                return Err(format!("'{}' resolves only to internal addresses", host).into());
+            }
+            let addrs: Addrs = Box::new(allowed.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+pub fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => is_internal_v4(v4),
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => is_internal_v4(v4),
+            None => is_internal_v6(v6),
+        },
+    }
+}
+
+fn is_internal_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        // 100.64.0.0/10 运营商级 NAT
+        || (a == 100 && (64..128).contains(&b))
+        // 0.0.0.0/8
+        || a == 0
+}
+
+fn is_internal_v6(ip: Ipv6Addr) -> bool {
+    let segments = ip.segments();
+    let first = segments[0];
+    // 地址里嵌着 IPv4 的几种格式按嵌入的 IPv4 判断
+    let embedded = match segments {
+        // 64:ff9b::/96 NAT64
+        [0x64, 0xff9b, 0, 0, 0, 0, hi, lo] => Some((hi, lo)),
+        // ::a.b.c.d 旧式 IPv4 兼容地址
+        [0, 0, 0, 0, 0, 0, hi, lo] => Some((hi, lo)),
+        // 2002::/16 6to4，第 2、3 段是 IPv4
+        [0x2002, hi, lo, ..] => Some((hi, lo)),
+        _ => None,
+    };
+    if let Some((hi, lo)) = embedded {
+        let [a, b] = hi.to_be_bytes();
+        let [c, d] = lo.to_be_bytes();
+        if is_internal_v4(Ipv4Addr::new(a, b, c, d)) {
+            return true;
+        }
+    }
+    ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // fc00::/7 唯一本地地址
+        || (first & 0xfe00) == 0xfc00
+        // fe80::/10 链路本地
+        || (first & 0xffc0) == 0xfe80
+        // fec0::/10 站点本地 (已废弃，但还可能路由到内网)
+        || (first & 0xffc0) == 0xfec0
+        // 64:ff9b:1::/48 本地 NAT64
+        || (first == 0x64 && segments[1] == 0xff9b && segments[2] == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn internal(ip: &str) -> bool {
+        is_internal(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn ipv4_ranges() {
+        for ip in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1", "0.1.2.3"] {
+            assert!(internal(ip), "{}", ip);
+        }
+        for ip in ["8.8.8.8", "142.250.72.10", "100.128.0.1"] {
+            assert!(!internal(ip), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn ipv6_ranges() {
+        for ip in ["::1", "::", "fd00::1", "fe80::1", "fec0::1", "ff02::1", "::ffff:127.0.0.1", "64:ff9b:1::1"] {
+            assert!(internal(ip), "{}", ip);
+        }
+        for ip in ["2607:f8b0:4005:80a::200e", "64:ff9b::808:808", "2002:808:808::1"] {
+            assert!(!internal(ip), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn embedded_ipv4_is_checked() {
+        // NAT64、6to4、IPv4 兼容地址里嵌着内网 IPv4
+        for ip in ["64:ff9b::7f00:1", "64:ff9b::a9fe:a9fe", "2002:7f00:1::1", "2002:a00:1::", "::7f00:1", "::a9fe:a9fe"] {
+            assert!(internal(ip), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn check_rejects_internal_literals_and_plain_http() {
+        let policy = TargetPolicy::default();
+        assert!(policy.check("https://169.254.169.254/").await.is_err());
+        assert!(policy.check("https://[64:ff9b::7f00:1]/").await.is_err());
+        assert!(policy.check("http://8.8.8.8/").await.is_err());
+        assert!(policy.check("https://8.8.8.8/").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn resolver_drops_internal_addresses() {
+        struct Fixed(Vec<IpAddr>);
+        impl Resolve for Fixed {
+            fn resolve(&self, _: Name) -> Resolving {
+                let addrs = self.0.clone();
+                Box::pin(async move {
+                    let addrs: Addrs = Box::new(addrs.into_iter().map(|ip| SocketAddr::new(ip, 0)));
+                    Ok(addrs)
+                })
+            }
+        }
+        let name = |host: &str| host.parse::<Name>().unwrap();
+
+        let mixed = PolicyResolver::new(Some(Arc::new(Fixed(vec!["127.0.0.1".parse().unwrap(), "8.8.8.8".parse().unwrap()]))));
+        let addrs: Vec<IpAddr> = mixed.resolve(name("rebind.example")).await.unwrap().map(|a| a.ip()).collect();
+        assert_eq!(addrs, ["8.8.8.8".parse::<IpAddr>().unwrap()]);
+
+        let internal = PolicyResolver::new(Some(Arc::new(Fixed(vec!["169.254.169.254".parse().unwrap()]))));
+        assert!(internal.resolve(name("rebind.example")).await.is_err());
+    }
+}
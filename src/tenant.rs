@@ -0,0 +1,170 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+use tracing::debug;
+
+use crate::routes::strip_path_prefix;
+use crate::AppState;
+
+// --- 多租户 ---
+// 按 Host 头或路径前缀选出租户，命中后把 CurrentTenant 挂到请求 extensions 上。每个租户可以单独配置:
+//   target=URL        专用的上游 (路由规则命中时以路由为准)
+//   keys=GROUP        只用 key 池里这个分组的 key (--key-group)，不会用掉其他租户的配额
+//   clients=A,B       只接受这些客户端令牌 (--client-token 的名字)，其他令牌访问时返回 403
+// 限流可以按租户整体计算 (--rate-limit tenant:NAME;rpm=...)，和按客户端的限额同时生效；
+// 用量账本和计量推送的每条记录都带着租户名。路由表、插件和其他设置是所有租户共用的。
+
+#[derive(Debug, Clone, Default)]
+pub struct Tenant {
+    pub name: String,
+    /// 匹配的 Host (不含端口)，".example.com" 表示后缀匹配
+    pub hosts: Vec<String>,
+    /// 匹配的路径前缀，转发前会去掉
+    pub path_prefix: Option<String>,
+    /// 该租户专用的上游地址
+    pub target: Option<String>,
+    /// 使用的 key 分组
+    pub key_group: Option<String>,
+    /// 允许访问的客户端令牌名，空表示不限制
+    pub clients: Vec<String>,
+}
+
+/// 当前请求所属的租户
+#[derive(Debug, Clone)]
+pub struct CurrentTenant(pub Arc<Tenant>);
+
+impl Tenant {
+    /// 解析 `NAME;host=a.example.com,.team.example.com;prefix=/team-a;target=https://...;keys=GROUP;clients=a,b`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut parts = spec.split(';');
+        let name = parts.next().unwrap_or("").trim();
+        if name.is_empty() {
+            return Err(format!("tenant '{}' has no name", spec));
+        }
+        let mut tenant = Tenant {
+            name: name.to_string(),
+            ..Default::default()
+        };
+
+        for part in parts.map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("tenant option '{}' must be key=value", part))?;
+            match key {
+                "host" => tenant
+                    .hosts
+                    .extend(value.split(',').map(|h| h.trim().to_ascii_lowercase()).filter(|h| !h.is_empty())),
+                "prefix" => {
+                    let prefix = format!("/{}", value.trim_matches('/'));
+                    tenant.path_prefix = Some(prefix);
+                }
+                "target" => tenant.target = Some(value.trim_end_matches('/').to_string()),
+                "keys" if !value.trim().is_empty() => tenant.key_group = Some(value.trim().to_string()),
+                "clients" => tenant
+                    .clients
+                    .extend(value.split(',').map(|c| c.trim().to_string()).filter(|c| !c.is_empty())),
+                _ => return Err(format!("unknown tenant option '{}'", key)),
+            }
+        }
+        if tenant.hosts.is_empty() && tenant.path_prefix.is_none() {
+            return Err(format!("tenant '{}' needs a host or prefix", tenant.name));
+        }
+        Ok(tenant)
+    }
+
+    /// 这个客户端能否访问该租户
+    pub(crate) fn allows(&self, client: &str) -> bool {
+        self.clients.is_empty() || self.clients.iter().any(|c| c == client)
+    }
+
+    fn matches_host(&self, host: &str) -> bool {
+        self.hosts
+            .iter()
+            .any(|h| if h.starts_with('.') { host.ends_with(h.as_str()) || host == &h[1..] } else { host == h })
+    }
+
+    // 返回需要去掉的前缀长度
+    fn matches_path(&self, path: &str) -> Option<usize> {
+        let prefix = self.path_prefix.as_deref()?;
+        let rest = path.strip_prefix(prefix)?;
+        (rest.is_empty() || rest.starts_with('/')).then_some(prefix.len())
+    }
+}
+
+pub struct Tenants {
+    tenants: Vec<Arc<Tenant>>,
+    // 不属于任何租户的请求直接 404
+    required: bool,
+}
+
+impl Tenants {
+    pub fn new(tenants: Vec<Tenant>, required: bool) -> Self {
+        Self {
+            tenants: tenants.into_iter().map(Arc::new).collect(),
+            required,
+        }
+    }
+
+    /// 按名字查找租户
+    pub fn get(&self, name: &str) -> Option<Arc<Tenant>> {
+        self.tenants.iter().find(|t| t.name == name).cloned()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Tenant> {
+        self.tenants.iter().map(|t| t.as_ref())
+    }
+
+    // 同时配置了 host 和 prefix 的租户两者都要满足
+    fn resolve(&self, host: Option<&str>, path: &str) -> Option<(Arc<Tenant>, usize)> {
+        self.tenants.iter().find_map(|tenant| {
+            if !tenant.hosts.is_empty() && !host.is_some_and(|h| tenant.matches_host(h)) {
+                return None;
+            }
+            let strip = match &tenant.path_prefix {
+                Some(_) => tenant.matches_path(path)?,
+                None => 0,
+            };
+            Some((tenant.clone(), strip))
+        })
+    }
+}
+
+fn request_host(req: &Request) -> Option<String> {
+    let host = req
+        .headers()
+        .get(header::HOST)
+        .and_then(|h| h.to_str().ok())
+        .or_else(|| req.uri().host())?;
+    let host = host.rsplit_once(':').filter(|(_, port)| port.parse::<u16>().is_ok()).map(|(h, _)| h).unwrap_or(host);
+    Some(host.to_ascii_lowercase())
+}
+
+pub(crate) async fn guard(State(state): State<Arc<AppState>>, mut req: Request, next: Next) -> Response {
+    let Some(tenants) = &state.tenants else {
+        return next.run(req).await;
+    };
+
+    let host = request_host(&req);
+    let Some((tenant, strip)) = tenants.resolve(host.as_deref(), req.uri().path()) else {
+        if tenants.required {
+            state.metrics.inc("aizasy_tenant_unmatched_total", &[]);
+            return (StatusCode::NOT_FOUND, "Unknown tenant").into_response();
+        }
+        return next.run(req).await;
+    };
+
+    if strip > 0 {
+        if let Some(uri) = strip_path_prefix(req.uri(), strip) {
+            *req.uri_mut() = uri;
+        }
+    }
+
+    debug!("🏢 Tenant {} -> {}", tenant.name, req.uri().path());
+    state.metrics.inc("aizasy_tenant_requests_total", &[("tenant", &tenant.name)]);
+    req.extensions_mut().insert(CurrentTenant(tenant));
+    next.run(req).await
+}
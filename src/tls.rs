@@ -0,0 +1,214 @@
+use axum::{body::Body, extract::ConnectInfo, extract::Request, Router};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::server::graceful::GracefulShutdown;
+use std::future::Future;
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::net::TcpListener;
+use tokio_rustls::rustls::crypto::{ring, CryptoProvider};
+use tokio_rustls::rustls::server::{ClientHello, ResolvesServerCert};
+use tokio_rustls::rustls::sign::CertifiedKey;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+use tower::Service;
+use tracing::{debug, info, warn};
+
+// --- HTTPS 监听 ---
+// 网关直接终结 TLS，不需要前面再放一层 nginx。证书和私钥是 PEM 文件 (证书文件可以带中间证书链)，
+// 运行中定期检查文件修改时间，变了就重新加载，新握手立即使用新证书；
+// 新文件加载失败 (比如证书和私钥只替换了一半) 时继续用旧证书，等文件再次变化时重试。
+// ALPN 同时提供 h2 和 http/1.1。
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// 证书文件检查间隔
+const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+pub(crate) struct TlsCerts {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    provider: Arc<CryptoProvider>,
+    current: RwLock<Arc<CertifiedKey>>,
+    // 上次加载时两个文件的修改时间
+    loaded: RwLock<(Option<SystemTime>, Option<SystemTime>)>,
+}
+
+// ResolvesServerCert 要求 Debug，私钥不打印
+impl std::fmt::Debug for TlsCerts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TlsCerts")
+            .field("cert_path", &self.cert_path)
+            .field("key_path", &self.key_path)
+            .finish()
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn load(cert_path: &Path, key_path: &Path, provider: &CryptoProvider) -> Result<CertifiedKey, String> {
+    let cert_pem = std::fs::read(cert_path).map_err(|e| format!("{}: {}", cert_path.display(), e))?;
+    let certs = CertificateDer::pem_slice_iter(&cert_pem)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("{}: {}", cert_path.display(), e))?;
+    if certs.is_empty() {
+        return Err(format!("{}: no certificate found", cert_path.display()));
+    }
+    let key_pem = std::fs::read(key_path).map_err(|e| format!("{}: {}", key_path.display(), e))?;
+    let key = PrivateKeyDer::from_pem_slice(&key_pem).map_err(|e| format!("{}: {}", key_path.display(), e))?;
+    // 顺带校验私钥和证书是否配对
+    CertifiedKey::from_der(certs, key, provider).map_err(|e| format!("{}: {}", key_path.display(), e))
+}
+
+/// 加载一次证书和私钥，检查能否解析、是否配对；启动前检查配置用
+pub fn check_certificate(cert_path: &Path, key_path: &Path) -> Result<(), String> {
+    load(cert_path, key_path, &ring::default_provider()).map(|_| ())
+}
+
+impl TlsCerts {
+    pub(crate) fn load(cert_path: impl Into<PathBuf>, key_path: impl Into<PathBuf>) -> Result<Self, String> {
+        let cert_path = cert_path.into();
+        let key_path = key_path.into();
+        let provider = Arc::new(ring::default_provider());
+        let stamps = (modified(&cert_path), modified(&key_path));
+        let certified = load(&cert_path, &key_path, &provider)?;
+        Ok(Self {
+            cert_path,
+            key_path,
+            provider,
+            current: RwLock::new(Arc::new(certified)),
+            loaded: RwLock::new(stamps),
+        })
+    }
+
+    /// 文件修改时间变了就重新加载，返回是否换了证书
+    fn reload_if_changed(&self) -> bool {
+        let stamps = (modified(&self.cert_path), modified(&self.key_path));
+        if *self.loaded.read().unwrap_or_else(|e| e.into_inner()) == stamps {
+            return false;
+        }
+        // 失败时也记下修改时间，同一份坏文件只告警一次
+        *self.loaded.write().unwrap_or_else(|e| e.into_inner()) = stamps;
+        match load(&self.cert_path, &self.key_path, &self.provider) {
+            Ok(certified) => {
+                *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(certified);
+                true
+            }
+            Err(e) => {
+                warn!("🔐 TLS certificate reload failed, keeping the current one: {}", e);
+                false
+            }
+        }
+    }
+
+    fn server_config(self: &Arc<Self>) -> Result<Arc<ServerConfig>, String> {
+        let mut config = ServerConfig::builder_with_provider(self.provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(|e| e.to_string())?
+            .with_no_client_auth()
+            .with_cert_resolver(self.clone());
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(Arc::new(config))
+    }
+
+    /// HTTP/3 用的 QUIC 配置：和 TCP 监听共用同一个证书解析器，证书热更新同样生效；QUIC 只能用 TLS 1.3
+    #[cfg(feature = "http3")]
+    pub(crate) fn quic_config(self: &Arc<Self>) -> Result<quinn::ServerConfig, String> {
+        let mut config = ServerConfig::builder_with_provider(self.provider.clone())
+            .with_protocol_versions(&[&tokio_rustls::rustls::version::TLS13])
+            .map_err(|e| e.to_string())?
+            .with_no_client_auth()
+            .with_cert_resolver(self.clone());
+        config.alpn_protocols = vec![b"h3".to_vec()];
+        let crypto = quinn::crypto::rustls::QuicServerConfig::try_from(config).map_err(|e| e.to_string())?;
+        Ok(quinn::ServerConfig::with_crypto(Arc::new(crypto)))
+    }
+}
+
+impl ResolvesServerCert for TlsCerts {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap_or_else(|e| e.into_inner()).clone())
+    }
+}
+
+/// 后台定期检查证书文件
+pub(crate) fn watch(certs: Arc<TlsCerts>) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(RELOAD_INTERVAL);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let certs = certs.clone();
+            let reloaded = tokio::task::spawn_blocking(move || certs.reload_if_changed()).await;
+            if reloaded.unwrap_or(false) {
+                info!("🔐 TLS certificate reloaded");
+            }
+        }
+    });
+}
+
+/// 在已绑定的 listener 上接受 TLS 连接，HTTP/1.1 和 h2 都交给 app 处理
+// shutdown 完成后停止接受新连接，等已有连接上的请求处理完再返回
+pub(crate) async fn serve(
+    listener: TcpListener,
+    app: Router,
+    certs: Arc<TlsCerts>,
+    shutdown: impl Future<Output = ()>,
+) -> std::io::Result<()> {
+    let config = certs
+        .server_config()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let acceptor = TlsAcceptor::from(config);
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(shutdown);
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = &mut shutdown => break,
+        };
+        let (stream, addr) = match accepted {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                // 文件描述符耗尽之类的错误，稍等再继续接受
+                warn!("⚠️  Accept failed: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let _ = stream.set_nodelay(true);
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+        let watcher = graceful.watcher();
+        tokio::spawn(async move {
+            let stream = match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => stream,
+                Ok(Err(e)) => {
+                    debug!("🔐 TLS handshake with {} failed: {}", addr, e);
+                    return;
+                }
+                Err(_) => {
+                    debug!("🔐 TLS handshake with {} timed out", addr);
+                    return;
+                }
+            };
+            let service = hyper::service::service_fn(move |mut req: Request<hyper::body::Incoming>| {
+                req.extensions_mut().insert(ConnectInfo(addr));
+                let mut app = app.clone();
+                async move { app.call(req.map(Body::new)).await }
+            });
+            let builder = auto::Builder::new(TokioExecutor::new());
+            let connection = builder.serve_connection_with_upgrades(TokioIo::new(stream), service);
+            if let Err(e) = watcher.watch(connection).await {
+                debug!("Connection from {} closed: {}", addr, e);
+            }
+        });
+    }
+    drop(listener);
+    graceful.shutdown().await;
+    Ok(())
+}
@@ -0,0 +1,111 @@
+use async_trait::async_trait;
+use axum::{
+    http::{Method, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::{json, Value};
+use tracing::debug;
+
+use crate::plugin::{GatewayPlugin, RequestContext};
+
+// --- 本地 token 计数 ---
+// 拦截 models/{model}:countTokens，用近似分词在本地估算，不再请求上游。
+// 结果不是 Gemini 分词器的精确值：英文大约 ±15%，中日韩文本偏差更大，只适合做预算预估。
+// 响应带 ESTIMATE_HEADER，需要精确值的客户端应关闭该功能。
+
+/// 响应头：标记本地估算结果
+pub const ESTIMATE_HEADER: &str = "x-aizasy-token-estimate";
+// Gemini 对每张图片 / 每个文件按固定 token 数计
+const MEDIA_TOKENS: u64 = 258;
+
+/// 近似 token 数：拉丁文字按每 4 个字符一个 token，中日韩字符每个一个 token，标点各算一个
+pub fn estimate_text(text: &str) -> u64 {
+    let mut tokens = 0;
+    let mut word = 0u64;
+    for c in text.chars() {
+        if c.is_alphanumeric() && !is_cjk(c) {
+            word += 1;
+            continue;
+        }
+        tokens += word.div_ceil(4);
+        word = 0;
+        if is_cjk(c) || (!c.is_whitespace() && !c.is_control()) {
+            tokens += 1;
+        }
+    }
+    tokens + word.div_ceil(4)
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF      // 平假名 / 片假名
+        | 0x3400..=0x4DBF    // 扩展 A
+        | 0x4E00..=0x9FFF    // 基本汉字
+        | 0xAC00..=0xD7AF    // 谚文
+        | 0xF900..=0xFAFF
+        | 0x20000..=0x2FFFF)
+}
+
+fn estimate_parts(content: &Value) -> u64 {
+    let Some(parts) = content.get("parts").and_then(Value::as_array) else {
+        return 0;
+    };
+    parts
+        .iter()
+        .map(|part| {
+            if let Some(text) = part.get("text").and_then(Value::as_str) {
+                estimate_text(text)
+            } else if part.get("inlineData").is_some() || part.get("fileData").is_some() {
+                MEDIA_TOKENS
+            } else {
+                // functionCall / functionResponse 等按序列化后的文本估算
+                estimate_text(&part.to_string())
+            }
+        })
+        .sum()
+}
+
+/// 估算 countTokens 请求体；同时支持 {"contents": ...} 和 {"generateContentRequest": {...}}
+pub fn estimate_request(body: &Value) -> u64 {
+    let request = body.get("generateContentRequest").unwrap_or(body);
+    let contents: u64 = request
+        .get("contents")
+        .and_then(Value::as_array)
+        .map(|contents| contents.iter().map(estimate_parts).sum())
+        .unwrap_or(0);
+    let system = request
+        .get("systemInstruction")
+        .or_else(|| request.get("system_instruction"))
+        .map(estimate_parts)
+        .unwrap_or(0);
+    contents + system
+}
+
+pub struct LocalTokenCounter;
+
+#[async_trait]
+impl GatewayPlugin for LocalTokenCounter {
+    fn name(&self) -> &str {
+        "local-token-count"
+    }
+
+    async fn on_request(&self, ctx: &mut RequestContext) -> Result<(), Response> {
+        if ctx.method != Method::POST || !ctx.uri.path().ends_with(":countTokens") {
+            return Ok(());
+        }
+        let body: Value = match serde_json::from_slice(&ctx.body) {
+            Ok(body) => body,
+            // 解析不了就交给上游，由上游返回标准错误
+            Err(_) => return Ok(()),
+        };
+        let total = estimate_request(&body);
+        debug!("🔢 Estimated {} tokens locally for {}", total, ctx.uri.path());
+        Err((
+            StatusCode::OK,
+            [(ESTIMATE_HEADER, "approximate")],
+            Json(json!({ "totalTokens": total })),
+        )
+            .into_response())
+    }
+}
@@ -0,0 +1,111 @@
+use axum::body::Bytes;
+use axum::http::{header, HeaderMap, HeaderValue};
+use futures_util::{future, stream, Stream, StreamExt};
+use http_body::{Body as HttpBody, Frame, SizeHint};
+use http_body_util::{BodyStream, StreamBody};
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+
+// --- HTTP trailers 转发 ---
+// 客户端请求体后面的 trailers 在读请求体时一起收下，转发时跟在请求体后面发给上游；
+// 上游响应体的 trailers 在数据流读完后补发给客户端，gRPC-Web、带 trailers 的分块响应经过网关不会丢。
+// HTTP/1.1 上 trailers 只能跟分块传输一起发，hyper 也只发 Trailer 头里声明过的字段：
+//   - 请求侧客户端走 HTTP/2 (不需要 Trailer 头) 时按实际收到的 trailers 补上声明；
+//   - 响应侧上游没有声明 Trailer 时，trailers 只能发给 HTTP/2 客户端；
+//   - 网关缓冲过的响应带 trailers 时不设 Content-Length，保持分块传输。
+
+/// 客户端请求体后面的 trailers
+#[derive(Debug, Clone)]
+pub(crate) struct RequestTrailers(pub(crate) HeaderMap);
+
+/// 请求头里没有 Trailer 时按 trailers 补上
+pub(crate) fn declare(headers: &mut HeaderMap, trailers: &HeaderMap) {
+    if headers.contains_key(header::TRAILER) {
+        return;
+    }
+    let names: Vec<&str> = trailers.keys().map(|name| name.as_str()).collect();
+    if let Ok(value) = HeaderValue::from_str(&names.join(", ")) {
+        headers.insert(header::TRAILER, value);
+    }
+}
+
+/// 带 trailers 的上游请求体
+pub(crate) fn request_body(body: Bytes, trailers: HeaderMap) -> reqwest::Body {
+    let frames = [Ok::<_, Infallible>(Frame::data(body)), Ok(Frame::trailers(trailers))];
+    reqwest::Body::wrap(StreamBody::new(stream::iter(frames)))
+}
+
+/// 上游响应读到的 trailers，响应体发完时取出
+pub(crate) type TrailerSlot = Arc<Mutex<Option<HeaderMap>>>;
+
+/// 上游响应体里的数据帧，trailers 存进 slot
+pub(crate) fn data_stream<B>(body: B, slot: TrailerSlot) -> impl Stream<Item = Result<Bytes, B::Error>>
+where
+    B: HttpBody<Data = Bytes>,
+{
+    BodyStream::new(body).filter_map(move |frame| {
+        let data = match frame.map(Frame::into_data) {
+            Ok(Ok(data)) => Some(Ok(data)),
+            Ok(Err(frame)) => {
+                if let (Ok(trailers), Ok(mut slot)) = (frame.into_trailers(), slot.lock()) {
+                    match slot.as_mut() {
+                        Some(existing) => existing.extend(trailers),
+                        None => *slot = Some(trailers),
+                    }
+                }
+                None
+            }
+            Err(e) => Some(Err(e)),
+        };
+        future::ready(data)
+    })
+}
+
+pub(crate) fn has_trailers(slot: &TrailerSlot) -> bool {
+    slot.lock().is_ok_and(|slot| slot.is_some())
+}
+
+/// 数据发完后补发 slot 里的 trailers
+pub(crate) struct WithTrailers<B> {
+    inner: B,
+    slot: TrailerSlot,
+    done: bool,
+}
+
+impl<B> WithTrailers<B> {
+    pub(crate) fn new(inner: B, slot: TrailerSlot) -> Self {
+        Self { inner, slot, done: false }
+    }
+}
+
+impl<B> HttpBody for WithTrailers<B>
+where
+    B: HttpBody<Data = Bytes> + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, B::Error>>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        match ready!(Pin::new(&mut self.inner).poll_frame(cx)) {
+            Some(frame) => Poll::Ready(Some(frame)),
+            None => {
+                self.done = true;
+                let trailers = self.slot.lock().ok().and_then(|mut slot| slot.take());
+                Poll::Ready(trailers.map(|trailers| Ok(Frame::trailers(trailers))))
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.done
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
@@ -0,0 +1,80 @@
+use axum::{body::Body, extract::ConnectInfo, extract::Request, Router};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::server::graceful::GracefulShutdown;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::Path;
+use std::time::Duration;
+use tokio::net::UnixListener;
+use tower::Service;
+use tracing::{debug, info, warn};
+
+// --- Unix domain socket 监听 ---
+// 作为 nginx / caddy 的 sidecar 部署时，监听一个 socket 文件比占用本机 TCP 端口省一跳，也不用管端口分配。
+// 只走明文 HTTP (TLS 由前面的反向代理终结)。socket 没有对端 IP，统一当作 127.0.0.1，
+// 这样把 127.0.0.1 列为可信代理后，X-Forwarded-For 里的真实客户端 IP 照常生效。
+
+/// Unix socket 对端一律记成这个地址
+const PEER: SocketAddr = SocketAddr::new(std::net::IpAddr::V4(std::net::Ipv4Addr::LOCALHOST), 0);
+
+/// 绑定 socket 文件并设置权限；上次没清理掉的 socket 文件先删除，其他类型的文件不动
+pub(crate) fn bind(path: &Path, mode: u32) -> std::io::Result<UnixListener> {
+    if let Ok(meta) = std::fs::symlink_metadata(path) {
+        if !meta.file_type().is_socket() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("bind {}: file exists and is not a socket", path.display()),
+            ));
+        }
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)
+        .map_err(|e| std::io::Error::new(e.kind(), format!("bind {}: {}", path.display(), e)))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    info!("🎧 Listening on unix:{} (mode {:o})", path.display(), mode);
+    Ok(listener)
+}
+
+/// 在已绑定的 socket 上接受连接，HTTP/1.1 和 h2 (prior knowledge) 都交给 app 处理；
+/// shutdown 完成后停止接受新连接，等已有连接上的请求处理完再返回
+pub(crate) async fn serve(
+    listener: UnixListener,
+    app: Router,
+    shutdown: impl Future<Output = ()>,
+) -> std::io::Result<()> {
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(shutdown);
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = &mut shutdown => break,
+        };
+        let stream = match accepted {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                warn!("⚠️  Accept failed: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let app = app.clone();
+        let watcher = graceful.watcher();
+        tokio::spawn(async move {
+            let service = hyper::service::service_fn(move |mut req: Request<hyper::body::Incoming>| {
+                req.extensions_mut().insert(ConnectInfo(PEER));
+                let mut app = app.clone();
+                async move { app.call(req.map(Body::new)).await }
+            });
+            let builder = auto::Builder::new(TokioExecutor::new());
+            let connection = builder.serve_connection_with_upgrades(TokioIo::new(stream), service);
+            if let Err(e) = watcher.watch(connection).await {
+                debug!("Unix socket connection closed: {}", e);
+            }
+        });
+    }
+    drop(listener);
+    graceful.shutdown().await;
+    Ok(())
+}
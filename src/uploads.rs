@@ -0,0 +1,176 @@
+use axum::http::{header, HeaderMap, HeaderValue, Uri};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// --- 断点续传上传 (File API) ---
+// Gemini 的文件上传先 POST /upload/v1beta/files (X-Goog-Upload-Command: start) 开一个会话，
+// 上游在 x-goog-upload-url 响应头里给出后续分块要发往的地址，这个地址指向 Google 的域名，
+// 里面还可能带着网关注入的 key，客户端按它直接上传就绕过了网关。
+// 网关把这个地址换成指向自己的 `<网关>/upload/v1beta/files?upload_id=aizasy-<令牌>&upload_protocol=resumable`，
+// 真实的上游地址和开会话时用的 key 记在内存里；后续的 upload / query / finalize 请求按令牌找回会话，
+// 原样发往上游地址并带上同一个 key (会话绑定在开它的 key 上，不能换 key)。
+// 会话在 finalize 成功或过期后删除；会话只存在当前进程，多副本部署时需要让同一个上传落在同一个副本上。
+// 网关对外的地址优先用 --public-url，其次按请求的 Host 和 X-Forwarded-Proto。
+
+pub(crate) const UPLOAD_URL_HEADER: &str = "x-goog-upload-url";
+const TOKEN_PREFIX: &str = "aizasy-";
+
+#[derive(Debug, Clone)]
+pub struct UploadConfig {
+    /// 会话保留时长
+    pub ttl: Duration,
+    /// 改写上传地址用的网关对外地址 (如 https://gw.example.com)
+    pub public_url: Option<String>,
+    /// 没有 X-Forwarded-Proto 时的协议
+    pub tls: bool,
+}
+
+/// 找回的上传会话
+#[derive(Debug, Clone)]
+pub(crate) struct UploadSession {
+    pub(crate) token: String,
+    /// 上游地址的 scheme + host
+    pub(crate) origin: String,
+    /// 上游地址的路径和查询参数
+    pub(crate) uri: Uri,
+    /// 开会话时用的 key
+    pub(crate) key: Option<usize>,
+}
+
+struct Entry {
+    url: String,
+    key: Option<usize>,
+    expires: Instant,
+}
+
+/// 续传请求找不到会话
+#[derive(Debug)]
+pub(crate) struct UnknownSession;
+
+pub(crate) struct UploadSessions {
+    config: UploadConfig,
+    sessions: Mutex<HashMap<String, Entry>>,
+}
+
+fn new_token() -> String {
+    let mut bytes = [0u8; 18];
+    if getrandom::fill(&mut bytes).is_err() {
+        // 不应该发生；退化为时间戳，至少不和已有会话重复
+        let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_nanos();
+        bytes[..16].copy_from_slice(&nanos.to_le_bytes());
+    }
+    format!("{}{}", TOKEN_PREFIX, hex::encode(bytes))
+}
+
+fn upload_id(uri: &Uri) -> Option<&str> {
+    uri.query()?.split('&').find_map(|pair| pair.strip_prefix("upload_id="))
+}
+
+impl UploadSessions {
+    pub(crate) fn new(config: UploadConfig) -> Self {
+        Self {
+            config,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    #[cfg(feature = "admin")]
+    pub(crate) fn config(&self) -> &UploadConfig {
+        &self.config
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.sessions.lock().map(|s| s.len()).unwrap_or(0)
+    }
+
+    /// 网关对外的地址：配置的 public_url，其次按请求头推断
+    pub(crate) fn origin(&self, headers: &HeaderMap, uri: &Uri) -> Option<String> {
+        if let Some(url) = &self.config.public_url {
+            return Some(url.trim_end_matches('/').to_string());
+        }
+        let host = uri
+            .authority()
+            .map(|a| a.as_str())
+            .or_else(|| headers.get(header::HOST).and_then(|v| v.to_str().ok()))?;
+        let scheme = headers
+            .get("x-forwarded-proto")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(',').next())
+            .map(str::trim)
+            .filter(|v| *v == "http" || *v == "https")
+            .unwrap_or(if self.config.tls { "https" } else { "http" });
+        Some(format!("{}://{}", scheme, host))
+    }
+
+    /// 开会话的响应：记下上游给的上传地址，换成指向网关的地址
+    pub(crate) fn register(&self, headers: &mut HeaderMap, origin: &str, key: Option<usize>) -> Option<String> {
+        let url = headers.get(UPLOAD_URL_HEADER)?.to_str().ok()?.to_string();
+        let upstream: Uri = url.parse().ok()?;
+        let token = new_token();
+        let rewritten = format!("{}{}?upload_id={}&upload_protocol=resumable", origin, upstream.path(), token);
+        headers.insert(UPLOAD_URL_HEADER, HeaderValue::from_str(&rewritten).ok()?);
+        let now = Instant::now();
+        if let Ok(mut sessions) = self.sessions.lock() {
+            sessions.retain(|_, entry| entry.expires > now);
+            sessions.insert(
+                token.clone(),
+                Entry {
+                    url,
+                    key,
+                    expires: now + self.config.ttl,
+                },
+            );
+        }
+        Some(token)
+    }
+
+    /// 续传请求对应的会话；不是网关发出的上传地址时返回 Ok(None)
+    pub(crate) fn resolve(&self, uri: &Uri) -> Result<Option<UploadSession>, UnknownSession> {
+        let Some(token) = upload_id(uri).filter(|id| id.starts_with(TOKEN_PREFIX)) else {
+            return Ok(None);
+        };
+        let sessions = self.sessions.lock().map_err(|_| UnknownSession)?;
+        let entry = sessions.get(token).filter(|e| e.expires > Instant::now()).ok_or(UnknownSession)?;
+        let upstream: Uri = entry.url.parse().map_err(|_| UnknownSession)?;
+        let origin = match (upstream.scheme_str(), upstream.authority()) {
+            (Some(scheme), Some(authority)) => format!("{}://{}", scheme, authority),
+            _ => return Err(UnknownSession),
+        };
+        let path = upstream.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+        Ok(Some(UploadSession {
+            token: token.to_string(),
+            origin,
+            uri: path.parse().map_err(|_| UnknownSession)?,
+            key: entry.key,
+        }))
+    }
+
+    pub(crate) fn remove(&self, token: &str) {
+        if let Ok(mut sessions) = self.sessions.lock() {
+            sessions.remove(token);
+        }
+    }
+}
+
+/// 请求的 X-Goog-Upload-Command 里有这个命令 (upload、finalize、query ...)
+pub(crate) fn has_command(headers: &HeaderMap, command: &str) -> bool {
+    headers
+        .get("x-goog-upload-command")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|c| c.trim().eq_ignore_ascii_case(command)))
+}
+
+/// 请求的 X-Goog-Upload-Command 里有 finalize
+pub(crate) fn is_finalize(headers: &HeaderMap) -> bool {
+    has_command(headers, "finalize")
+}
+
+/// 上传分块的 X-Goog-Upload-Offset，没带时为 0
+pub(crate) fn upload_offset(headers: &HeaderMap) -> u64 {
+    headers
+        .get("x-goog-upload-offset")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(0)
+}
@@ -0,0 +1,551 @@
+use axum::http::HeaderMap;
+use reqwest::Client;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, warn};
+
+use crate::AppState;
+
+// --- 多上游负载均衡 ---
+// 默认上游可以配置多个 (区域端点、镜像)，按权重轮询选择；某个上游连接失败或返回 5xx 时
+// 被动标记为不可用一段时间，当前请求换下一个上游重试。全部不可用时仍然选最早恢复的那个。
+// 路由规则和租户指定的上游不参与负载均衡。
+// 每个上游可以用 `;proxy=URL` 走自己的出站代理 (见 egress)，没写时用全局 --proxy。
+//
+// 可以另外开启主动健康检查：定期请求每个上游的一个轻量接口 (默认 GET /v1beta/models，
+// 配置了 key 池时带上池里的 key)，连续失败 threshold 次 (连接失败、超时或 5xx) 就移出轮询，
+// 之后一次探测成功即恢复。被动标记只能在真实请求失败之后生效，主动探测能更早发现。
+//
+// 选择策略 (--target-balancing)：
+//   weighted        按权重轮询 (默认)
+//   least-latency   选首字节延迟 EWMA 最低的可用上游，权重不参与
+//   p2c             随机取两个可用上游，选延迟 EWMA 低的 (power of two choices)，比 least-latency 更不容易把流量全压到一个上游
+// 延迟是真实请求从发出到收到响应头的时间，只统计没有连接失败或 5xx 的请求。
+// 还没有样本、或者 LATENCY_STALE 内没有新样本的上游算作未知：每 LATENCY_TRIAL_INTERVAL 只放一个请求
+// 优先试它以刷新延迟 (避免偶发一次慢请求后再也轮不到)，其余请求把它按可用上游的平均延迟比较，
+// 不会因为没有样本就把流量全压过去。
+//
+// 每个上游最近一次成功 (真实请求没有连接失败或 5xx、或者探测通过) 的时间供 /readyz 判断上游是否连得上：
+// 最近 READY_WINDOW 内都没有成功过时 /readyz 在后台发起一次探测 (同一时间只有一个，
+// 两次之间至少隔 READY_PROBE_TTL)，本次请求只按已有的状态回答，探测通过后下一次 /readyz 才变成就绪。
+// /readyz 不需要鉴权，这样反复请求它也不会让网关拿池里的 key 不停地探测上游。
+
+#[derive(Debug, Clone)]
+pub struct HealthCheckConfig {
+    pub interval: Duration,
+    pub timeout: Duration,
+    /// 探测的 path + query
+    pub path: String,
+    /// 连续失败几次后判定为不健康
+    pub threshold: u32,
+}
+
+impl Default for HealthCheckConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(10),
+            timeout: Duration::from_secs(5),
+            path: "/v1beta/models".to_string(),
+            threshold: 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WeightedTarget {
+    pub url: String,
+    pub weight: u32,
+    /// 访问这个上游用的代理，None 时用全局代理
+    pub proxy: Option<String>,
+}
+
+impl WeightedTarget {
+    /// 解析 `URL[;weight=N][;proxy=PROXY_URL]`，权重默认 1
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut parts = spec.split(';');
+        let url = parts.next().unwrap_or("").trim().trim_end_matches('/');
+        if url.is_empty() {
+            return Err(format!("target '{}' has no URL", spec));
+        }
+        let mut target = WeightedTarget {
+            url: url.to_string(),
+            weight: 1,
+            proxy: None,
+        };
+        for part in parts.map(str::trim).filter(|p| !p.is_empty()) {
+            match part.split_once('=') {
+                Some(("weight", weight)) => {
+                    target.weight = weight
+                        .parse()
+                        .ok()
+                        .filter(|w| *w > 0)
+                        .ok_or_else(|| format!("invalid weight in target '{}'", spec))?;
+                }
+                Some(("proxy", proxy)) => {
+                    crate::egress::parse(proxy, None)?;
+                    target.proxy = Some(proxy.to_string());
+                }
+                _ => return Err(format!("unknown target option '{}'", part)),
+            }
+        }
+        Ok(target)
+    }
+}
+
+/// 多个默认上游之间的选择策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Balancing {
+    /// 按权重轮询
+    #[default]
+    Weighted,
+    /// 延迟 EWMA 最低的
+    LeastLatency,
+    /// 随机两个里延迟 EWMA 低的
+    P2c,
+}
+
+impl std::str::FromStr for Balancing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "weighted" => Ok(Self::Weighted),
+            "least-latency" => Ok(Self::LeastLatency),
+            "p2c" => Ok(Self::P2c),
+            _ => Err(format!("unknown balancing strategy '{}' (expected weighted, least-latency or p2c)", s)),
+        }
+    }
+}
+
+impl Balancing {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Weighted => "weighted",
+            Self::LeastLatency => "least-latency",
+            Self::P2c => "p2c",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UpstreamStatus {
+    pub url: String,
+    pub weight: u32,
+    /// 单独配置的代理 (密码已隐藏)
+    pub proxy: Option<String>,
+    /// 剩余不可用秒数，0 表示可用
+    pub down_secs: u64,
+    /// 主动健康检查的结果；没有开启时总是 true
+    pub healthy: bool,
+    /// 距最近一次成功的请求或探测的秒数，还没有成功过时为 None
+    pub last_success_secs: Option<u64>,
+    /// 首字节延迟的 EWMA 毫秒数，还没有样本时为 None
+    pub latency_ms: Option<f64>,
+}
+
+/// 最近这么久内有过成功的请求或探测就认为上游连得上
+const READY_WINDOW: Duration = Duration::from_secs(60);
+
+/// /readyz 触发的探测之间至少隔这么久，期间沿用上一次的结果
+const READY_PROBE_TTL: Duration = Duration::from_secs(10);
+
+/// 延迟 EWMA 里新样本的权重
+const LATENCY_ALPHA: f64 = 0.3;
+
+/// 这么久没有新样本的上游按延迟未知处理
+const LATENCY_STALE: Duration = Duration::from_secs(30);
+
+/// 延迟未知的上游每隔这么久放一个试探请求
+const LATENCY_TRIAL_INTERVAL: Duration = Duration::from_secs(5);
+
+pub(crate) struct Upstreams {
+    targets: Vec<WeightedTarget>,
+    // 单独配置了代理的上游用自己的客户端
+    clients: Vec<Option<Client>>,
+    total_weight: u64,
+    cooldown: Duration,
+    next: AtomicU64,
+    // 每个上游不可用到的 unix 毫秒
+    down_until: Vec<AtomicU64>,
+    // 主动健康检查的结果和连续失败次数
+    healthy: Vec<AtomicBool>,
+    failures: Vec<AtomicU32>,
+    // 最近一次成功的 unix 毫秒，0 表示还没有
+    last_success: Vec<AtomicU64>,
+    balancing: Balancing,
+    // 首字节延迟 EWMA 毫秒数的 f64 位模式和最近一次样本的 unix 毫秒，0 表示还没有样本
+    latency: Vec<AtomicU64>,
+    latency_at: Vec<AtomicU64>,
+    // 最近一次给延迟未知的上游放试探请求的 unix 毫秒
+    trial_at: Vec<AtomicU64>,
+    // /readyz 触发的探测是否正在进行，以及最近一次开始的 unix 毫秒
+    ready_probing: AtomicBool,
+    ready_probed_at: AtomicU64,
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+// splitmix64，p2c 用轮询计数生成两个候选，不需要真正的随机数
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+impl Upstreams {
+    pub(crate) fn new(targets: Vec<WeightedTarget>, clients: Vec<Option<Client>>, cooldown: Duration, balancing: Balancing) -> Self {
+        let total_weight = targets.iter().map(|t| t.weight as u64).sum::<u64>().max(1);
+        let down_until = targets.iter().map(|_| AtomicU64::new(0)).collect();
+        let healthy = targets.iter().map(|_| AtomicBool::new(true)).collect();
+        let failures = targets.iter().map(|_| AtomicU32::new(0)).collect();
+        let last_success = targets.iter().map(|_| AtomicU64::new(0)).collect();
+        let latency = targets.iter().map(|_| AtomicU64::new(0)).collect();
+        let latency_at = targets.iter().map(|_| AtomicU64::new(0)).collect();
+        let trial_at = targets.iter().map(|_| AtomicU64::new(0)).collect();
+        Self {
+            targets,
+            clients,
+            total_weight,
+            cooldown,
+            next: AtomicU64::new(0),
+            down_until,
+            healthy,
+            failures,
+            last_success,
+            balancing,
+            latency,
+            latency_at,
+            trial_at,
+            ready_probing: AtomicBool::new(false),
+            ready_probed_at: AtomicU64::new(0),
+        }
+    }
+
+    #[cfg(feature = "admin")]
+    pub(crate) fn balancing(&self) -> Balancing {
+        self.balancing
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.targets.len()
+    }
+
+    pub(crate) fn primary(&self) -> &str {
+        &self.targets[0].url
+    }
+
+    pub(crate) fn url(&self, index: usize) -> &str {
+        &self.targets[index].url
+    }
+
+    /// 第 index 个上游单独的客户端；None 时用全局客户端
+    pub(crate) fn client(&self, index: usize) -> Option<&Client> {
+        self.clients.get(index).and_then(Option::as_ref)
+    }
+
+    /// 按选择策略取一个可用且本次请求没试过的上游；都不可用时取最早恢复的 (健康检查不通过的排在最后)，
+    /// 全部试过时返回 None
+    pub(crate) fn pick(&self, tried: &[usize]) -> Option<(usize, &str)> {
+        let counter = self.next.fetch_add(1, Ordering::Relaxed);
+        // 轮询计数落在哪个权重区间，就从哪个上游开始找
+        let slot = counter % self.total_weight;
+        let mut acc = 0;
+        let start = self
+            .targets
+            .iter()
+            .position(|t| {
+                acc += t.weight as u64;
+                slot < acc
+            })
+            .unwrap_or(0);
+        let now = unix_ms();
+        let mut available = Vec::new();
+        let mut soonest: Option<(usize, u64)> = None;
+        for i in (0..self.targets.len()).map(|offset| (start + offset) % self.targets.len()) {
+            if tried.contains(&i) {
+                continue;
+            }
+            let until = match self.healthy[i].load(Ordering::Relaxed) {
+                true => self.down_until[i].load(Ordering::Relaxed),
+                false => u64::MAX,
+            };
+            if until <= now {
+                available.push(i);
+            } else if soonest.is_none_or(|(_, best)| until < best) {
+                soonest = Some((i, until));
+            }
+        }
+        // 延迟相同 (比如都还没有样本) 时按轮询顺序取前面的
+        let chosen = match self.balancing {
+            _ if available.is_empty() => soonest.map(|(i, _)| i),
+            Balancing::Weighted => available.first().copied(),
+            Balancing::LeastLatency => {
+                let mean = self.mean_latency(&available, now);
+                available.iter().copied().find(|i| self.claim_trial(*i, now)).or_else(|| {
+                    available
+                        .iter()
+                        .copied()
+                        .min_by(|a, b| self.latency_score(*a, now, mean).total_cmp(&self.latency_score(*b, now, mean)))
+                })
+            }
+            Balancing::P2c => {
+                // 两个候选不重复；只剩一个可用时就是它自己
+                let n = available.len() as u64;
+                let a = mix(counter) % n;
+                let b = (a + 1 + mix(counter ^ 0x5555_5555_5555_5555) % n.saturating_sub(1).max(1)) % n;
+                let (first, second) = (available[a as usize], available[b as usize]);
+                let mean = self.mean_latency(&available, now);
+                Some(match first {
+                    _ if self.claim_trial(first, now) => first,
+                    _ if self.claim_trial(second, now) => second,
+                    _ if self.latency_score(second, now, mean) < self.latency_score(first, now, mean) => second,
+                    _ => first,
+                })
+            }
+        };
+        chosen.map(|i| (i, self.targets[i].url.as_str()))
+    }
+
+    // 延迟 EWMA 毫秒数；没有样本或样本过期时为 None
+    fn latency(&self, index: usize, now: u64) -> Option<f64> {
+        let at = self.latency_at[index].load(Ordering::Relaxed);
+        if at == 0 || now.saturating_sub(at) > LATENCY_STALE.as_millis() as u64 {
+            return None;
+        }
+        Some(f64::from_bits(self.latency[index].load(Ordering::Relaxed)))
+    }
+
+    // 比较用的延迟：未知时按 mean 算
+    fn latency_score(&self, index: usize, now: u64, mean: f64) -> f64 {
+        self.latency(index, now).unwrap_or(mean)
+    }
+
+    // 候选里有样本的上游的平均延迟；都没有样本时为 0，按轮询顺序选
+    fn mean_latency(&self, candidates: &[usize], now: u64) -> f64 {
+        let known: Vec<f64> = candidates.iter().filter_map(|i| self.latency(*i, now)).collect();
+        match known.len() {
+            0 => 0.0,
+            n => known.iter().sum::<f64>() / n as f64,
+        }
+    }
+
+    // 延迟未知的上游距上一次试探超过 LATENCY_TRIAL_INTERVAL 时占下这次试探，并发的请求只有一个能占到
+    fn claim_trial(&self, index: usize, now: u64) -> bool {
+        if self.latency(index, now).is_some() {
+            return false;
+        }
+        let last = self.trial_at[index].load(Ordering::Relaxed);
+        now.saturating_sub(last) >= LATENCY_TRIAL_INTERVAL.as_millis() as u64
+            && self.trial_at[index].compare_exchange(last, now, Ordering::AcqRel, Ordering::Relaxed).is_ok()
+    }
+
+    /// 记一次成功请求的首字节延迟
+    pub(crate) fn record_latency(&self, index: usize, ttfb: Duration) {
+        let sample = ttfb.as_secs_f64() * 1000.0;
+        let now = unix_ms();
+        // 过期的旧值不再参考，直接用新样本
+        let fresh = self.latency(index, now).is_some();
+        let _ = self.latency[index].fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+            let ewma = match fresh {
+                true => f64::from_bits(bits) * (1.0 - LATENCY_ALPHA) + sample * LATENCY_ALPHA,
+                false => sample,
+            };
+            Some(ewma.to_bits())
+        });
+        self.latency_at[index].store(now, Ordering::Relaxed);
+    }
+
+    pub(crate) fn mark_down(&self, index: usize) {
+        let until = unix_ms() + self.cooldown.as_millis() as u64;
+        self.down_until[index].fetch_max(until, Ordering::Relaxed);
+    }
+
+    /// 是否至少有一个上游通过了健康检查
+    pub(crate) fn any_healthy(&self) -> bool {
+        self.healthy.iter().any(|h| h.load(Ordering::Relaxed))
+    }
+
+    /// 第 index 个上游返回了正常的响应
+    pub(crate) fn record_success(&self, index: usize) {
+        self.last_success[index].store(unix_ms(), Ordering::Relaxed);
+    }
+
+    /// 最近 READY_WINDOW 内是否有上游成功过
+    fn recently_reachable(&self) -> bool {
+        let since = unix_ms().saturating_sub(READY_WINDOW.as_millis() as u64);
+        self.last_success.iter().any(|at| at.load(Ordering::Relaxed) > since)
+    }
+
+    // 记录一次探测结果，健康状态变化时返回新状态
+    fn record_probe(&self, index: usize, ok: bool, threshold: u32) -> Option<bool> {
+        if ok {
+            self.record_success(index);
+            self.failures[index].store(0, Ordering::Relaxed);
+            return (!self.healthy[index].swap(true, Ordering::Relaxed)).then_some(true);
+        }
+        let failures = self.failures[index].fetch_add(1, Ordering::Relaxed) + 1;
+        if failures >= threshold.max(1) && self.healthy[index].swap(false, Ordering::Relaxed) {
+            return Some(false);
+        }
+        None
+    }
+
+    pub(crate) fn status(&self) -> Vec<UpstreamStatus> {
+        let now = unix_ms();
+        self.targets
+            .iter()
+            .zip(&self.down_until)
+            .zip(&self.healthy)
+            .zip(&self.last_success)
+            .enumerate()
+            .map(|(index, (((target, until), healthy), last_success))| UpstreamStatus {
+                url: target.url.clone(),
+                weight: target.weight,
+                proxy: target.proxy.as_deref().map(crate::egress::redact),
+                down_secs: until.load(Ordering::Relaxed).saturating_sub(now).div_ceil(1000),
+                healthy: healthy.load(Ordering::Relaxed),
+                last_success_secs: match last_success.load(Ordering::Relaxed) {
+                    0 => None,
+                    at => Some(now.saturating_sub(at) / 1000),
+                },
+                latency_ms: match self.latency_at[index].load(Ordering::Relaxed) {
+                    0 => None,
+                    _ => Some((f64::from_bits(self.latency[index].load(Ordering::Relaxed)) * 10.0).round() / 10.0),
+                },
+            })
+            .collect()
+    }
+}
+
+/// 后台定期探测所有默认上游；网关状态被释放 (热重载换成新实例) 后自动停止
+pub(crate) fn spawn_health_checks(state: Weak<AppState>, config: HealthCheckConfig) {
+    tokio::spawn(async move {
+        let mut timer = tokio::time::interval(config.interval);
+        loop {
+            timer.tick().await;
+            let Some(state) = state.upgrade() else {
+                return;
+            };
+            let probes = (0..state.upstreams.len()).map(|index| probe(&state, index, &config));
+            let results = futures_util::future::join_all(probes).await;
+            for (index, result) in results.into_iter().enumerate() {
+                let url = &state.upstreams.targets[index].url;
+                if let Err(e) = &result {
+                    debug!("🩺 Health check of {} failed: {}", url, e);
+                }
+                match state.upstreams.record_probe(index, result.is_ok(), config.threshold) {
+                    Some(true) => info!("🩺 Upstream {} is healthy again", url),
+                    Some(false) => {
+                        warn!("🩺 Upstream {} failed {} health checks, taking it out of rotation", url, config.threshold);
+                        state.metrics.inc("aizasy_upstream_unhealthy_total", &[("target", url)]);
+                    }
+                    None => {}
+                }
+            }
+        }
+    });
+}
+
+/// 最近有上游成功过就返回 true；否则在后台探测所有默认上游 (单飞，READY_PROBE_TTL 内不重复)，
+/// 这次先返回 false，探测通过后会记成最近成功
+pub(crate) fn ensure_reachable(state: &Arc<AppState>) -> bool {
+    let upstreams = &state.upstreams;
+    if upstreams.recently_reachable() {
+        return true;
+    }
+    let now = unix_ms();
+    let due = now.saturating_sub(upstreams.ready_probed_at.load(Ordering::Relaxed)) >= READY_PROBE_TTL.as_millis() as u64;
+    if !due || upstreams.ready_probing.swap(true, Ordering::AcqRel) {
+        return false;
+    }
+    upstreams.ready_probed_at.store(now, Ordering::Relaxed);
+    let state = Arc::clone(state);
+    tokio::spawn(async move {
+        let probes = (0..state.upstreams.len()).map(|index| probe(&state, index, &state.health_probe));
+        let results = futures_util::future::join_all(probes).await;
+        for (index, result) in results.into_iter().enumerate() {
+            match result {
+                Ok(()) => state.upstreams.record_success(index),
+                Err(e) => debug!("🩺 Readiness probe of {} failed: {}", state.upstreams.url(index), e),
+            }
+        }
+        state.upstreams.ready_probing.store(false, Ordering::Release);
+    });
+    false
+}
+
+/// 后台请求 (健康检查、连接预热) 访问某个默认上游用的客户端：上游自己的代理，其次代理池，最后直连
+pub(crate) fn client_for(state: &AppState, index: usize) -> &Client {
+    match (state.upstreams.client(index), &state.egress) {
+        (Some(client), _) => client,
+        (None, Some(pool)) => pool.pick(None, &[]).map(|(_, client)| client).unwrap_or(&state.client),
+        (None, None) => &state.client,
+    }
+}
+
+// 能连上且不是 5xx 就算健康；401 / 403 / 429 说明的是 key 的问题，不是上游的
+async fn probe(state: &AppState, index: usize, config: &HealthCheckConfig) -> Result<(), String> {
+    let url = &state.upstreams.targets[index].url;
+    probe_url(state, client_for(state, index), url, true, config).await
+}
+
+async fn probe_url(state: &AppState, client: &Client, url: &str, with_key: bool, config: &HealthCheckConfig) -> Result<(), String> {
+    let mut headers = HeaderMap::new();
+    let path = match state.key_pool.as_ref().filter(|_| with_key) {
+        Some(pool) => pool.inject(&pool.next(), &mut headers, &config.path),
+        None => config.path.clone(),
+    };
+    let response = client
+        .get(format!("{}{}", url, path))
+        .headers(headers)
+        .timeout(config.timeout)
+        .send()
+        .await
+        .map_err(describe)?;
+    match response.status() {
+        status if status.is_server_error() => Err(format!("HTTP {}", status)),
+        _ => Ok(()),
+    }
+}
+
+// reqwest 的错误本身只有一句 "error sending request"，具体原因 (连接被拒、证书不对) 在 source 链里；
+// 去掉 URL，里面可能带着 key
+fn describe(e: reqwest::Error) -> String {
+    let e = e.without_url();
+    let mut message = e.to_string();
+    let mut source = std::error::Error::source(&e);
+    while let Some(cause) = source {
+        message = format!("{}: {}", message, cause);
+        source = cause.source();
+    }
+    message
+}
+
+/// 对每个默认上游和路由规则里的上游各探测一次，返回 (地址, 耗时或错误)；启动前检查配置用
+pub(crate) async fn probe_all(state: &AppState, timeout: Duration) -> Vec<(String, Result<Duration, String>)> {
+    let config = HealthCheckConfig { timeout, ..state.health_probe.clone() };
+    let mut results = Vec::new();
+    for index in 0..state.upstreams.len() {
+        let started = Instant::now();
+        let result = probe(state, index, &config).await;
+        results.push((state.upstreams.url(index).to_string(), result.map(|()| started.elapsed())));
+    }
+    for rule in state.routes.snapshot().iter() {
+        if results.iter().any(|(url, _)| *url == rule.target) {
+            continue;
+        }
+        let client = match &state.egress {
+            Some(pool) => pool.pick(None, &[]).map(|(_, client)| client).unwrap_or(&state.client),
+            None => &state.client,
+        };
+        let started = Instant::now();
+        let result = probe_url(state, client, &rule.target, !rule.passthrough, &config).await;
+        results.push((rule.target.clone(), result.map(|()| started.elapsed())));
+    }
+    results
+}
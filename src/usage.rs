@@ -0,0 +1,126 @@
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+use crate::plugin::RequestContext;
+
+// --- 用量提取 ---
+// Gemini 在非流式响应体末尾、流式响应的最后几个分块里带 usageMetadata，
+// 这里逐块扫描，只保留最新一次解析到的用量，不缓存整个响应体。
+// 转发给其他厂商的请求 (见 providers) 从 usage 字段取：OpenAI 是 prompt_tokens / completion_tokens，
+// Anthropic 是 input_tokens / output_tokens，流式时输入 token 只在 message_start 里出现一次。
+
+const USAGE_KEY: &[u8] = b"\"usageMetadata\"";
+const PROVIDER_USAGE_KEY: &[u8] = b"\"usage\"";
+// usageMetadata 对象本身很小，跨分块时只需保留这么多尾部字节
+const CARRY_BYTES: usize = 4096;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    #[serde(rename = "promptTokenCount", default)]
+    pub prompt: u64,
+    #[serde(rename = "candidatesTokenCount", default)]
+    pub candidates: u64,
+    #[serde(rename = "totalTokenCount", default)]
+    pub total: u64,
+}
+
+/// 发起请求的客户端身份；鉴权层识别出身份后放进请求 extensions
+#[derive(Debug, Clone)]
+pub struct ClientIdentity(pub String);
+
+/// 本次请求使用的上游 key 标识 (不是 key 本身)；网关侧注入 key 时放进请求 extensions
+#[derive(Debug, Clone)]
+pub struct UpstreamKeyId(pub String);
+
+/// 计费 / 统计用的客户端标识：优先鉴权身份，其次来源 IP
+pub fn client_label(ctx: &RequestContext) -> String {
+    match ctx.extensions.get::<ClientIdentity>() {
+        Some(identity) => identity.0.clone(),
+        None => ip_label(ctx.client_ip),
+    }
+}
+
+pub(crate) fn ip_label(ip: IpAddr) -> String {
+    format!("ip:{}", ip)
+}
+
+/// 从 `/v1beta/models/gemini-1.5-pro:generateContent` 这类路径中取出模型名
+pub fn model_from_path(path: &str) -> Option<&str> {
+    let rest = &path[path.find("/models/")? + "/models/".len()..];
+    let model = rest.split([':', '/', '?']).next()?;
+    (!model.is_empty()).then_some(model)
+}
+
+#[derive(Default)]
+pub struct UsageScanner {
+    carry: Vec<u8>,
+    latest: Option<TokenUsage>,
+}
+
+impl UsageScanner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feed(&mut self, chunk: &[u8]) {
+        self.carry.extend_from_slice(chunk);
+        if let Some(usage) = parse_last_usage(&self.carry) {
+            self.latest = Some(usage);
+        } else if let Some(usage) = parse_provider_usage(&self.carry, self.latest) {
+            // Anthropic 后面的 message_delta 只带 output_tokens，输入 token 沿用之前解析到的
+            self.latest = Some(usage);
+        }
+        if self.carry.len() > CARRY_BYTES {
+            let cut = self.carry.len() - CARRY_BYTES;
+            self.carry.drain(..cut);
+        }
+    }
+
+    pub fn usage(&self) -> Option<TokenUsage> {
+        self.latest
+    }
+}
+
+// 按出现顺序合并缓冲区里所有的 usage 对象：输出 token 取最后一次，输入 token 取最后一次出现的
+fn parse_provider_usage(buf: &[u8], previous: Option<TokenUsage>) -> Option<TokenUsage> {
+    let mut merged: Option<TokenUsage> = None;
+    let positions = buf
+        .windows(PROVIDER_USAGE_KEY.len())
+        .enumerate()
+        .filter(|(_, w)| *w == PROVIDER_USAGE_KEY)
+        .map(|(pos, _)| pos);
+    for pos in positions {
+        let rest = &buf[pos + PROVIDER_USAGE_KEY.len()..];
+        let Some(colon) = rest.iter().position(|b| *b == b':') else {
+            continue;
+        };
+        let mut values = serde_json::Deserializer::from_slice(&rest[colon + 1..]).into_iter::<serde_json::Value>();
+        let Some(Ok(usage)) = values.next() else {
+            continue;
+        };
+        let count = |names: &[&str]| names.iter().find_map(|name| usage.get(*name).and_then(serde_json::Value::as_u64));
+        let prompt = count(&["prompt_tokens", "input_tokens"]);
+        let candidates = count(&["completion_tokens", "output_tokens"]);
+        if prompt.is_none() && candidates.is_none() {
+            continue;
+        }
+        let base = merged.or(previous).unwrap_or_default();
+        merged = Some(TokenUsage {
+            prompt: prompt.unwrap_or(base.prompt),
+            candidates: candidates.unwrap_or(base.candidates),
+            total: count(&["total_tokens"]).unwrap_or(0),
+        });
+    }
+    merged.map(|mut usage| {
+        usage.total = usage.total.max(usage.prompt + usage.candidates);
+        usage
+    })
+}
+
+fn parse_last_usage(buf: &[u8]) -> Option<TokenUsage> {
+    let pos = buf.windows(USAGE_KEY.len()).rposition(|w| w == USAGE_KEY)?;
+    let rest = &buf[pos + USAGE_KEY.len()..];
+    let colon = rest.iter().position(|b| *b == b':')?;
+    let mut values = serde_json::Deserializer::from_slice(&rest[colon + 1..]).into_iter::<TokenUsage>();
+    values.next()?.ok()
+}
@@ -0,0 +1,376 @@
+use async_trait::async_trait;
+use axum::{
+    http::{Method, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::{json, Map, Value};
+use tracing::debug;
+
+use crate::plugin::{GatewayPlugin, RequestContext};
+
+// --- 请求体校验 ---
+// 转发前按 Gemini API 的结构检查 JSON 请求体 (contents / parts / generationConfig 等字段的类型和取值范围)，
+// 明显不合法的请求直接在本地返回 400，不占上游配额，错误信息也会指出具体是哪个字段 (Google 的 400 往往很笼统)。
+// 字段名同时接受 camelCase 和 snake_case，和上游一致；不认识的字段放行，避免新版 API 加字段后误拦。
+// 启用 schema feature 后还可以按路径前缀或方法名给出自己的 JSON Schema。
+
+/// 内置检查覆盖的方法
+const GEMINI_METHODS: &[&str] = &[
+    "generateContent",
+    "streamGenerateContent",
+    "countTokens",
+    "embedContent",
+    "batchEmbedContents",
+];
+
+/// 一个 part 至少要有其中之一
+const PART_FIELDS: &[&str] = &[
+    "text",
+    "inlineData",
+    "fileData",
+    "functionCall",
+    "functionResponse",
+    "executableCode",
+    "codeExecutionResult",
+];
+
+const MAX_STOP_SEQUENCES: usize = 5;
+
+type Check = Result<(), String>;
+
+pub(crate) fn snake_case(camel: &str) -> String {
+    let mut out = String::with_capacity(camel.len() + 4);
+    for c in camel.chars() {
+        if c.is_ascii_uppercase() {
+            out.push('_');
+            out.push(c.to_ascii_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+pub(crate) fn camel_case(snake: &str) -> String {
+    let mut out = String::with_capacity(snake.len());
+    let mut upper = false;
+    for c in snake.chars() {
+        match c {
+            '_' => upper = !out.is_empty(),
+            c if upper => {
+                out.push(c.to_ascii_uppercase());
+                upper = false;
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn field<'a>(obj: &'a Map<String, Value>, name: &str) -> Option<&'a Value> {
+    obj.get(name).or_else(|| obj.get(&snake_case(name)))
+}
+
+fn object<'a>(value: &'a Value, at: &str) -> Result<&'a Map<String, Value>, String> {
+    value.as_object().ok_or_else(|| format!("{} must be an object", at))
+}
+
+fn non_empty_array<'a>(value: &'a Value, at: &str) -> Result<&'a Vec<Value>, String> {
+    match value.as_array() {
+        Some(items) if !items.is_empty() => Ok(items),
+        Some(_) => Err(format!("{} must not be empty", at)),
+        None => Err(format!("{} must be an array", at)),
+    }
+}
+
+fn string(value: &Value, at: &str) -> Check {
+    value.as_str().map(|_| ()).ok_or_else(|| format!("{} must be a string", at))
+}
+
+fn number_in(obj: &Map<String, Value>, name: &str, min: f64, max: f64) -> Check {
+    let Some(value) = field(obj, name) else {
+        return Ok(());
+    };
+    match value.as_f64() {
+        Some(n) if (min..=max).contains(&n) => Ok(()),
+        Some(_) if max == f64::MAX => Err(format!("generationConfig.{} must be at least {}", name, min)),
+        Some(_) => Err(format!("generationConfig.{} must be between {} and {}", name, min, max)),
+        None => Err(format!("generationConfig.{} must be a number", name)),
+    }
+}
+
+fn integer_at_least(obj: &Map<String, Value>, name: &str, min: i64) -> Check {
+    let Some(value) = field(obj, name) else {
+        return Ok(());
+    };
+    match value.as_i64() {
+        Some(n) if n >= min => Ok(()),
+        Some(_) => Err(format!("generationConfig.{} must be at least {}", name, min)),
+        None => Err(format!("generationConfig.{} must be an integer", name)),
+    }
+}
+
+fn check_part(value: &Value, at: &str) -> Check {
+    let part = object(value, at)?;
+    if !PART_FIELDS.iter().any(|name| field(part, name).is_some()) {
+        return Err(format!("{} must contain one of {}", at, PART_FIELDS.join(", ")));
+    }
+    if let Some(text) = field(part, "text") {
+        string(text, &format!("{}.text", at))?;
+    }
+    if let Some(inline) = field(part, "inlineData") {
+        let at = format!("{}.inlineData", at);
+        let inline = object(inline, &at)?;
+        for name in ["mimeType", "data"] {
+            match field(inline, name) {
+                Some(value) => string(value, &format!("{}.{}", at, name))?,
+                None => return Err(format!("{}.{} is required", at, name)),
+            }
+        }
+    }
+    if let Some(file) = field(part, "fileData") {
+        let at = format!("{}.fileData", at);
+        match field(object(file, &at)?, "fileUri") {
+            Some(uri) => string(uri, &format!("{}.fileUri", at))?,
+            None => return Err(format!("{}.fileUri is required", at)),
+        }
+    }
+    if let Some(call) = field(part, "functionCall") {
+        let at = format!("{}.functionCall", at);
+        match field(object(call, &at)?, "name") {
+            Some(name) => string(name, &format!("{}.name", at))?,
+            None => return Err(format!("{}.name is required", at)),
+        }
+    }
+    Ok(())
+}
+
+fn check_content(value: &Value, at: &str) -> Check {
+    let content = object(value, at)?;
+    if let Some(role) = field(content, "role") {
+        string(role, &format!("{}.role", at))?;
+    }
+    let at = format!("{}.parts", at);
+    let parts = non_empty_array(field(content, "parts").ok_or_else(|| format!("{} is required", at))?, &at)?;
+    for (i, part) in parts.iter().enumerate() {
+        check_part(part, &format!("{}[{}]", at, i))?;
+    }
+    Ok(())
+}
+
+fn check_generation_config(value: &Value) -> Check {
+    let config = object(value, "generationConfig")?;
+    number_in(config, "temperature", 0.0, 2.0)?;
+    number_in(config, "topP", 0.0, 1.0)?;
+    number_in(config, "topK", 0.0, f64::MAX)?;
+    number_in(config, "presencePenalty", -2.0, 2.0)?;
+    number_in(config, "frequencyPenalty", -2.0, 2.0)?;
+    integer_at_least(config, "candidateCount", 1)?;
+    integer_at_least(config, "maxOutputTokens", 1)?;
+    if let Some(stops) = field(config, "stopSequences") {
+        let stops = stops.as_array().ok_or("generationConfig.stopSequences must be an array")?;
+        if stops.len() > MAX_STOP_SEQUENCES {
+            return Err(format!("generationConfig.stopSequences allows at most {} entries", MAX_STOP_SEQUENCES));
+        }
+        for (i, stop) in stops.iter().enumerate() {
+            string(stop, &format!("generationConfig.stopSequences[{}]", i))?;
+        }
+    }
+    if let Some(mime) = field(config, "responseMimeType") {
+        string(mime, "generationConfig.responseMimeType")?;
+    }
+    Ok(())
+}
+
+fn check_generate(req: &Map<String, Value>, prefix: &str) -> Check {
+    let at = format!("{}contents", prefix);
+    let contents = non_empty_array(field(req, "contents").ok_or_else(|| format!("{} is required", at))?, &at)?;
+    for (i, content) in contents.iter().enumerate() {
+        check_content(content, &format!("{}[{}]", at, i))?;
+    }
+    if let Some(system) = field(req, "systemInstruction") {
+        check_content(system, &format!("{}systemInstruction", prefix))?;
+    }
+    if let Some(config) = field(req, "generationConfig") {
+        check_generation_config(config)?;
+    }
+    if let Some(settings) = field(req, "safetySettings") {
+        let at = format!("{}safetySettings", prefix);
+        let settings = settings.as_array().ok_or_else(|| format!("{} must be an array", at))?;
+        for (i, setting) in settings.iter().enumerate() {
+            let at = format!("{}[{}]", at, i);
+            let setting = object(setting, &at)?;
+            for name in ["category", "threshold"] {
+                match field(setting, name) {
+                    Some(value) => string(value, &format!("{}.{}", at, name))?,
+                    None => return Err(format!("{}.{} is required", at, name)),
+                }
+            }
+        }
+    }
+    if let Some(tools) = field(req, "tools") {
+        let at = format!("{}tools", prefix);
+        let tools = tools.as_array().ok_or_else(|| format!("{} must be an array", at))?;
+        for (i, tool) in tools.iter().enumerate() {
+            object(tool, &format!("{}[{}]", at, i))?;
+        }
+    }
+    if let Some(cached) = field(req, "cachedContent") {
+        string(cached, &format!("{}cachedContent", prefix))?;
+    }
+    Ok(())
+}
+
+/// 按方法检查 Gemini 请求体
+fn check_gemini(method: &str, body: &Value) -> Check {
+    let req = object(body, "request body")?;
+    match method {
+        "generateContent" | "streamGenerateContent" => check_generate(req, ""),
+        "countTokens" => match (field(req, "contents"), field(req, "generateContentRequest")) {
+            (_, Some(inner)) => check_generate(object(inner, "generateContentRequest")?, "generateContentRequest."),
+            (Some(_), None) => check_generate(req, ""),
+            (None, None) => Err("contents or generateContentRequest is required".to_string()),
+        },
+        "embedContent" => check_content(field(req, "content").ok_or("content is required")?, "content"),
+        "batchEmbedContents" => {
+            let requests = non_empty_array(field(req, "requests").ok_or("requests is required")?, "requests")?;
+            for (i, request) in requests.iter().enumerate() {
+                let at = format!("requests[{}]", i);
+                let request = object(request, &at)?;
+                let content = field(request, "content").ok_or_else(|| format!("{}.content is required", at))?;
+                check_content(content, &format!("{}.content", at))?;
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// 路径最后一段冒号后面的方法名，如 generateContent
+fn rpc_method(path: &str) -> Option<&str> {
+    path.rsplit('/').next()?.rsplit_once(':').map(|(_, method)| method)
+}
+
+#[cfg(feature = "schema")]
+struct SchemaRule {
+    /// 以 / 开头时是路径前缀，否则是方法名
+    selector: String,
+    file: String,
+    validator: jsonschema::Validator,
+}
+
+#[cfg(feature = "schema")]
+impl SchemaRule {
+    fn matches(&self, path: &str, method: Option<&str>) -> bool {
+        if self.selector.starts_with('/') {
+            path.starts_with(&self.selector)
+        } else {
+            method == Some(self.selector.as_str())
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct RequestValidator {
+    gemini: bool,
+    #[cfg(feature = "schema")]
+    schemas: Vec<SchemaRule>,
+}
+
+impl RequestValidator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 启用内置的 Gemini 请求体检查
+    pub fn gemini(mut self, enabled: bool) -> Self {
+        self.gemini = enabled;
+        self
+    }
+
+    /// 加载 `SELECTOR=FILE`：SELECTOR 是路径前缀 (以 / 开头) 或方法名 (如 generateContent)
+    #[cfg(feature = "schema")]
+    pub fn schema(mut self, spec: &str) -> Result<Self, String> {
+        let (selector, file) = spec
+            .split_once('=')
+            .ok_or_else(|| format!("request schema '{}' must be SELECTOR=FILE", spec))?;
+        let (selector, file) = (selector.trim(), file.trim());
+        if selector.is_empty() {
+            return Err(format!("request schema '{}' has an empty selector", spec));
+        }
+        let data = std::fs::read(file).map_err(|e| format!("{}: {}", file, e))?;
+        let schema: Value = serde_json::from_slice(&data).map_err(|e| format!("{}: {}", file, e))?;
+        let validator = jsonschema::validator_for(&schema).map_err(|e| format!("{}: invalid schema: {}", file, e))?;
+        self.schemas.push(SchemaRule {
+            selector: selector.to_string(),
+            file: file.to_string(),
+            validator,
+        });
+        Ok(self)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        #[cfg(feature = "schema")]
+        if !self.schemas.is_empty() {
+            return false;
+        }
+        !self.gemini
+    }
+
+    fn validate(&self, path: &str, body: &[u8]) -> Check {
+        let method = rpc_method(path);
+        let gemini = self.gemini && method.is_some_and(|m| GEMINI_METHODS.contains(&m));
+        #[cfg(feature = "schema")]
+        let schemas: Vec<&SchemaRule> = self.schemas.iter().filter(|rule| rule.matches(path, method)).collect();
+        #[cfg(feature = "schema")]
+        let checked = gemini || !schemas.is_empty();
+        #[cfg(not(feature = "schema"))]
+        let checked = gemini;
+        if !checked {
+            return Ok(());
+        }
+        let body: Value = serde_json::from_slice(body).map_err(|e| format!("request body is not valid JSON: {}", e))?;
+        if gemini {
+            check_gemini(method.unwrap_or_default(), &body)?;
+        }
+        #[cfg(feature = "schema")]
+        for rule in schemas {
+            if let Err(e) = rule.validator.validate(&body) {
+                let at = e.instance_path().to_string();
+                let at = if at.is_empty() { "request body".to_string() } else { at };
+                debug!("🧾 Request does not match schema {}", rule.file);
+                return Err(format!("{}: {}", at, e));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn invalid(message: String) -> Response {
+    let body = json!({
+        "error": {
+            "code": 400,
+            "message": format!("Invalid request: {}", message),
+            "status": "INVALID_ARGUMENT",
+        }
+    });
+    (StatusCode::BAD_REQUEST, Json(body)).into_response()
+}
+
+#[async_trait]
+impl GatewayPlugin for RequestValidator {
+    fn name(&self) -> &str {
+        "validate"
+    }
+
+    async fn on_request(&self, ctx: &mut RequestContext) -> Result<(), Response> {
+        if ctx.method != Method::POST {
+            return Ok(());
+        }
+        if let Err(message) = self.validate(ctx.uri.path(), &ctx.body) {
+            debug!("🧾 Rejected request {}: {}", ctx.id, message);
+            return Err(invalid(message));
+        }
+        Ok(())
+    }
+}
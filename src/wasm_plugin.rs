@@ -0,0 +1,202 @@
+use async_trait::async_trait;
+use axum::{
+    body::Bytes,
+    http::{HeaderMap, StatusCode},
+    response::Response,
+};
+use serde::Serialize;
+use std::borrow::Cow;
+use tracing::warn;
+use wasmtime::{Config, Engine, Instance, Module, Store};
+
+use crate::plugin::{Directive, GatewayPlugin, RequestContext, RequestSnapshot, ResponseSnapshot};
+
+// --- WASM 插件宿主 ---
+// Guest ABI (core wasm，无 import):
+//   export memory
+//   export aizasy_alloc(len: i32) -> i32                 宿主写入输入前申请内存
+//   export on_request(ptr: i32, len: i32) -> i64          可选
+//   export on_upstream_response(ptr: i32, len: i32) -> i64 可选
+//   export on_response_body(ptr: i32, len: i32) -> i64    可选，只对非流式、未压缩的响应调用
+// 输入输出都是 JSON；钩子返回 (out_ptr << 32) | out_len，返回 0 表示不做修改。
+// on_request / on_response_body 的输入带 `body` 字段 (按 UTF-8 解码)，返回的 Directive 里给出
+// `body` 就替换请求体 / 响应体。
+// 每次调用都在新的 Store 里实例化，互不共享状态，并用 fuel 限制执行步数。
+//
+// 插件可以绑定到部分路由：`--wasm-plugin filters/pii.wasm@/v1beta/models/gemini-2.5-pro|/v1beta/cachedContents`，
+// 只有转发路径以其中某个前缀开头的请求才会经过它；不带 @ 时对所有请求生效。
+
+const DEFAULT_FUEL: u64 = 10_000_000;
+
+pub struct WasmPlugin {
+    name: String,
+    engine: Engine,
+    module: Module,
+    fuel: u64,
+    /// 生效的路径前缀，为空表示所有请求
+    routes: Vec<String>,
+}
+
+// 给钩子的输入加上 body 字段
+#[derive(Serialize)]
+struct WithBody<'a, T: Serialize> {
+    #[serde(flatten)]
+    snapshot: T,
+    body: Cow<'a, str>,
+}
+
+impl WasmPlugin {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config).map_err(|e| e.to_string())?;
+        let module = Module::from_file(&engine, path).map_err(|e| format!("{}: {}", path, e))?;
+
+        for required in ["memory", "aizasy_alloc"] {
+            if module.get_export(required).is_none() {
+                return Err(format!("{}: missing export '{}'", path, required));
+            }
+        }
+
+        Ok(Self {
+            name: path.to_string(),
+            engine,
+            module,
+            fuel: DEFAULT_FUEL,
+            routes: Vec::new(),
+        })
+    }
+
+    /// 解析 `PATH[@PREFIX|PREFIX...]` 并加载；前缀末尾的 * 可以省略
+    pub fn from_spec(spec: &str) -> Result<Self, String> {
+        let Some((path, routes)) = spec.split_once('@') else {
+            return Self::load(spec.trim());
+        };
+        let routes: Vec<String> = routes
+            .split('|')
+            .map(|r| r.trim().trim_end_matches('*').to_string())
+            .filter(|r| !r.is_empty())
+            .collect();
+        if let Some(route) = routes.iter().find(|r| !r.starts_with('/')) {
+            return Err(format!("WASM plugin route '{}' must start with '/'", route));
+        }
+        Ok(Self::load(path.trim())?.with_routes(routes))
+    }
+
+    pub fn with_routes(mut self, routes: Vec<String>) -> Self {
+        self.routes = routes;
+        self
+    }
+
+    fn applies(&self, ctx: &RequestContext) -> bool {
+        self.routes.is_empty() || self.routes.iter().any(|r| ctx.uri.path().starts_with(r.as_str()))
+    }
+
+    fn call(&self, export: &str, input: &[u8]) -> Result<Option<Directive>, String> {
+        if self.module.get_export(export).is_none() {
+            return Ok(None);
+        }
+
+        let mut store = Store::new(&self.engine, ());
+        store.set_fuel(self.fuel).map_err(|e| e.to_string())?;
+        let instance = Instance::new(&mut store, &self.module, &[]).map_err(|e| e.to_string())?;
+
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or("export 'memory' is not a memory")?;
+        let alloc = instance
+            .get_typed_func::<i32, i32>(&mut store, "aizasy_alloc")
+            .map_err(|e| e.to_string())?;
+        let hook = instance
+            .get_typed_func::<(i32, i32), i64>(&mut store, export)
+            .map_err(|e| e.to_string())?;
+
+        let ptr = alloc.call(&mut store, input.len() as i32).map_err(|e| e.to_string())?;
+        memory
+            .write(&mut store, ptr as u32 as usize, input)
+            .map_err(|e| e.to_string())?;
+
+        let packed = hook
+            .call(&mut store, (ptr, input.len() as i32))
+            .map_err(|e| e.to_string())?;
+        if packed == 0 {
+            return Ok(None);
+        }
+
+        let out_ptr = (packed as u64 >> 32) as usize;
+        let out_len = (packed as u64 & 0xffff_ffff) as usize;
+        let mut out = vec![0u8; out_len];
+        memory.read(&store, out_ptr, &mut out).map_err(|e| e.to_string())?;
+
+        serde_json::from_slice(&out)
+            .map(Some)
+            .map_err(|e| format!("invalid directive JSON: {}", e))
+    }
+}
+
+#[async_trait]
+impl GatewayPlugin for WasmPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn on_request(&self, ctx: &mut RequestContext) -> Result<(), Response> {
+        if !self.applies(ctx) {
+            return Ok(());
+        }
+        let input = WithBody {
+            snapshot: RequestSnapshot::new(ctx),
+            body: String::from_utf8_lossy(&ctx.body),
+        };
+        let input = serde_json::to_vec(&input).unwrap_or_default();
+
+        let directive = match self.call("on_request", &input) {
+            Ok(Some(directive)) => directive,
+            Ok(None) => return Ok(()),
+            Err(e) => {
+                // 插件故障不影响转发
+                warn!("🧩 WASM plugin {} on_request failed: {}", self.name, e);
+                return Ok(());
+            }
+        };
+
+        directive.apply_to_request(ctx)
+    }
+
+    async fn on_upstream_response(&self, ctx: &RequestContext, status: StatusCode, headers: &mut HeaderMap) {
+        if !self.applies(ctx) {
+            return;
+        }
+        let input = ResponseSnapshot::new(ctx, status, headers);
+        let input = serde_json::to_vec(&input).unwrap_or_default();
+
+        match self.call("on_upstream_response", &input) {
+            Ok(Some(directive)) => directive.apply_headers(headers),
+            Ok(None) => {}
+            Err(e) => warn!("🧩 WASM plugin {} on_upstream_response failed: {}", self.name, e),
+        }
+    }
+
+    fn wants_response_body(&self, ctx: &RequestContext, _headers: &HeaderMap) -> bool {
+        self.applies(ctx) && self.module.get_export("on_response_body").is_some()
+    }
+
+    async fn on_response_body(&self, ctx: &RequestContext, status: StatusCode, headers: &mut HeaderMap, body: &mut Bytes) {
+        let input = WithBody {
+            snapshot: ResponseSnapshot::new(ctx, status, headers),
+            body: String::from_utf8_lossy(body),
+        };
+        let input = serde_json::to_vec(&input).unwrap_or_default();
+
+        match self.call("on_response_body", &input) {
+            Ok(Some(directive)) => {
+                directive.apply_headers(headers);
+                if let Some(replacement) = directive.body {
+                    *body = Bytes::from(replacement);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => warn!("🧩 WASM plugin {} on_response_body failed: {}", self.name, e),
+        }
+    }
+}
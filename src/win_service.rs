@@ -0,0 +1,142 @@
+use futures_util::future::BoxFuture;
+use std::ffi::OsString;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tracing::{error, info};
+use windows_service::service::{
+    ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode, ServiceInfo, ServiceStartType,
+    ServiceState, ServiceStatus, ServiceType,
+};
+use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+use windows_service::{define_windows_service, service_dispatcher};
+
+// --- Windows 服务 ---
+// `aizasy_gateway install-service [--name N] -- <网关参数>` 把当前可执行文件注册成自动启动的服务，
+// SCM 启动服务时以 `service <网关参数>` 运行，`uninstall-service` 停止并删除服务。
+// SCM 的停止 / 关机请求映射成摘流 (和 Unix 上的 SIGUSR1 一样)：不再接受新请求，在途请求完成后退出，
+// 摘流期间按 stop_wait 报告 StopPending，SCM 不会提前强杀进程。
+// 服务的工作目录是 System32，参数里的文件路径要写绝对路径；服务没有控制台，标准输出的日志会被丢弃，
+// 需要留存时用 --access-log 写文件或用 --otlp-endpoint 导出。
+
+pub const DEFAULT_SERVICE_NAME: &str = "AizasyGateway";
+
+/// 服务运行时收到停止请求后完成
+pub type StopSignal = oneshot::Receiver<()>;
+
+type Start = Box<dyn FnOnce(StopSignal) -> BoxFuture<'static, ()> + Send>;
+
+// service_main 由 SCM 的线程调用，启动信息先放在这里
+static PENDING: Mutex<Option<(Duration, tokio::runtime::Handle, Start)>> = Mutex::new(None);
+
+define_windows_service!(ffi_service_main, service_main);
+
+/// 作为服务运行，直到服务停止才返回；start 收到 StopSignal 后应当开始摘流并在完成后返回。
+/// 必须在 tokio 多线程运行时里调用
+pub fn run<F>(stop_wait: Duration, start: F) -> Result<(), String>
+where
+    F: FnOnce(StopSignal) -> BoxFuture<'static, ()> + Send + 'static,
+{
+    let handle = tokio::runtime::Handle::current();
+    *PENDING.lock().unwrap_or_else(|e| e.into_inner()) = Some((stop_wait, handle, Box::new(start)));
+    // 阻塞到服务结束；不是由 SCM 启动时这里直接报错。独占进程的服务不校验这里的服务名
+    tokio::task::block_in_place(|| service_dispatcher::start(DEFAULT_SERVICE_NAME, ffi_service_main))
+        .map_err(|e| format!("Failed to start the service dispatcher (is this running under the service manager?): {}", e))
+}
+
+fn service_main(arguments: Vec<OsString>) {
+    let Some((stop_wait, handle, start)) = PENDING.lock().unwrap_or_else(|e| e.into_inner()).take() else {
+        return;
+    };
+    // 第一个参数是安装时的服务名
+    let name = arguments.first().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(|| DEFAULT_SERVICE_NAME.to_string());
+    if let Err(e) = run_service(&name, stop_wait, handle, start) {
+        error!("❌ Service {} failed: {}", name, e);
+    }
+}
+
+fn status(state: ServiceState, wait_hint: Duration) -> ServiceStatus {
+    ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state: state,
+        controls_accepted: match state {
+            ServiceState::Running => ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+            _ => ServiceControlAccept::empty(),
+        },
+        exit_code: ServiceExitCode::Win32(0),
+        checkpoint: 0,
+        wait_hint,
+        process_id: None,
+    }
+}
+
+fn run_service(name: &str, stop_wait: Duration, handle: tokio::runtime::Handle, start: Start) -> windows_service::Result<()> {
+    let (stop_tx, stop_rx) = oneshot::channel();
+    let stop_tx = Mutex::new(Some(stop_tx));
+    let status_handle = service_control_handler::register(name, move |control| match control {
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            if let Some(tx) = stop_tx.lock().unwrap_or_else(|e| e.into_inner()).take() {
+                let _ = tx.send(());
+            }
+            ServiceControlHandlerResult::NoError
+        }
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    })?;
+    status_handle.set_service_status(status(ServiceState::Running, Duration::ZERO))?;
+    info!("🪟 Running as Windows service {}", name);
+
+    // 收到停止请求时先报告 StopPending (摘流可能要一段时间)，再通知网关开始摘流
+    let (drain_tx, drain_rx) = oneshot::channel();
+    handle.spawn(async move {
+        if stop_rx.await.is_ok() {
+            let _ = status_handle.set_service_status(status(ServiceState::StopPending, stop_wait));
+            let _ = drain_tx.send(());
+        }
+    });
+    let _ = handle.block_on(handle.spawn(start(drain_rx)));
+    status_handle.set_service_status(status(ServiceState::Stopped, Duration::ZERO))?;
+    Ok(())
+}
+
+/// 注册服务，SCM 以 `<当前可执行文件> service <arguments>` 启动
+pub fn install(name: &str, arguments: Vec<OsString>) -> Result<(), String> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE)
+        .map_err(|e| format!("Failed to open the service manager: {}", e))?;
+    let executable_path = std::env::current_exe().map_err(|e| format!("Failed to locate the executable: {}", e))?;
+    let mut launch_arguments = vec![OsString::from("service")];
+    launch_arguments.extend(arguments);
+    let info = ServiceInfo {
+        name: name.into(),
+        display_name: format!("Aizasy Gateway ({})", name).into(),
+        service_type: ServiceType::OWN_PROCESS,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path,
+        launch_arguments,
+        dependencies: Vec::new(),
+        account_name: None,
+        account_password: None,
+    };
+    let service = manager
+        .create_service(&info, ServiceAccess::CHANGE_CONFIG)
+        .map_err(|e| format!("Failed to create service {}: {}", name, e))?;
+    let _ = service.set_description("Gemini API gateway");
+    Ok(())
+}
+
+/// 停止 (如果在运行) 并删除服务
+pub fn uninstall(name: &str) -> Result<(), String> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)
+        .map_err(|e| format!("Failed to open the service manager: {}", e))?;
+    let service = manager
+        .open_service(name, ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE)
+        .map_err(|e| format!("Failed to open service {}: {}", name, e))?;
+    // 先标记删除，服务停止后 SCM 自动移除
+    service.delete().map_err(|e| format!("Failed to delete service {}: {}", name, e))?;
+    if service.query_status().is_ok_and(|s| s.current_state != ServiceState::Stopped) {
+        let _ = service.stop();
+    }
+    Ok(())
+}
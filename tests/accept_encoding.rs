@@ -0,0 +1,74 @@
+// --accept-encoding 两种模式下压缩响应的转发行为
+
+mod common;
+
+use aizasy_gateway::encoding::EncodingMode;
+use aizasy_gateway::Gateway;
+use axum::extract::Request;
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use axum::Router;
+
+// 客户端声明接受 gzip 时返回的"压缩"响应体；网关不解压，内容是否是真正的 gzip 无关紧要
+const GZIP_BODY: &[u8] = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x00\x03compressed-bytes\x00\xff";
+const PLAIN_BODY: &str = r#"{"candidates":[]}"#;
+
+// 假上游：请求带 gzip 的 Accept-Encoding 时返回压缩过的响应体，否则返回明文；
+// 收到的 Accept-Encoding 放在 x-seen-accept-encoding 里带回
+async fn upstream() -> String {
+    let router = Router::new().fallback(|req: Request| async move {
+        let accept = req.headers().get(header::ACCEPT_ENCODING).and_then(|v| v.to_str().ok()).unwrap_or("").to_string();
+        let seen = [("x-seen-accept-encoding", accept.clone())];
+        if accept.contains("gzip") {
+            (seen, [(header::CONTENT_ENCODING, "gzip")], GZIP_BODY).into_response()
+        } else {
+            (seen, [(header::CONTENT_TYPE, "application/json")], PLAIN_BODY).into_response()
+        }
+    });
+    common::spawn(router).await
+}
+
+async fn gateway(mode: EncodingMode) -> String {
+    let router = Gateway::builder()
+        .target(upstream().await)
+        .accept_encoding(mode)
+        .into_router()
+        .expect("valid gateway config");
+    common::spawn(router).await
+}
+
+async fn fetch(gateway: &str) -> reqwest::Response {
+    common::client()
+        .post(format!("{}/v1beta/models/gemini-2.0-flash:generateContent?key=CLIENT", gateway))
+        .header(header::ACCEPT_ENCODING, "gzip, br")
+        .body("{}")
+        .send()
+        .await
+        .expect("request through gateway")
+}
+
+#[tokio::test]
+async fn passthrough_forwards_compressed_bytes_unchanged() {
+    let gateway = gateway(EncodingMode::Passthrough).await;
+    let response = fetch(&gateway).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let headers = response.headers().clone();
+    assert_eq!(headers.get("x-seen-accept-encoding").and_then(|v| v.to_str().ok()), Some("gzip, br"));
+    assert_eq!(headers.get(header::CONTENT_ENCODING).and_then(|v| v.to_str().ok()), Some("gzip"));
+    let length = GZIP_BODY.len().to_string();
+    assert_eq!(headers.get(header::CONTENT_LENGTH).and_then(|v| v.to_str().ok()), Some(length.as_str()));
+    assert!(headers.get(header::TRANSFER_ENCODING).is_none());
+    let body = response.bytes().await.expect("response body");
+    assert_eq!(&body[..], GZIP_BODY);
+}
+
+#[tokio::test]
+async fn identity_asks_upstream_for_plain_bodies() {
+    let gateway = gateway(EncodingMode::Identity).await;
+    let response = fetch(&gateway).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let headers = response.headers().clone();
+    assert_eq!(headers.get("x-seen-accept-encoding").and_then(|v| v.to_str().ok()), Some(""));
+    assert!(headers.get(header::CONTENT_ENCODING).is_none());
+    assert_eq!(response.text().await.expect("response body"), PLAIN_BODY);
+}
@@ -0,0 +1,21 @@
+// 集成测试共用：在本机随机端口上起假上游和网关
+
+use axum::Router;
+use std::net::SocketAddr;
+
+/// 在 127.0.0.1 的随机端口上运行 router，返回 http://地址
+pub async fn spawn(router: Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.expect("bind test listener");
+    let addr = listener.local_addr().expect("local addr");
+    tokio::spawn(async move {
+        axum::serve(listener, router.into_make_service_with_connect_info::<SocketAddr>())
+            .await
+            .expect("serve test router");
+    });
+    format!("http://{}", addr)
+}
+
+/// 不走系统代理、不自动解压的客户端，测试看到的就是网关发出的原始字节
+pub fn client() -> reqwest::Client {
+    reqwest::Client::builder().no_proxy().build().expect("build test client")
+}
@@ -0,0 +1,137 @@
+// Gateway::builder 嵌入方式的端到端行为：转发、key 注入和插件钩子
+
+mod common;
+
+use aizasy_gateway::key_pool::{KeyInjection, KeyPool};
+use aizasy_gateway::keys::KeyEntry;
+use aizasy_gateway::{Gateway, GatewayPlugin, RequestContext};
+use async_trait::async_trait;
+use axum::extract::Request;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// 假上游：把收到的方法、路径和 key 原样写回，并记下请求次数
+async fn echo_upstream(hits: Arc<AtomicUsize>) -> String {
+    let router = Router::new().fallback(move |req: Request| {
+        let hits = hits.clone();
+        async move {
+            hits.fetch_add(1, Ordering::SeqCst);
+            let header = |name: &str| req.headers().get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+            Json(json!({
+                "method": req.method().as_str(),
+                "path": req.uri().path_and_query().map(|p| p.as_str()),
+                "key": header("x-goog-api-key"),
+                "team": header("x-team"),
+            }))
+        }
+    });
+    common::spawn(router).await
+}
+
+fn pool(keys: &[(&str, &str)]) -> KeyPool {
+    let entries = keys
+        .iter()
+        .map(|(name, key)| KeyEntry {
+            name: name.to_string(),
+            key: key.to_string(),
+        })
+        .collect();
+    KeyPool::new(entries, KeyInjection::Header).expect("valid key pool")
+}
+
+#[tokio::test]
+async fn nested_router_forwards_with_pool_key() {
+    let hits = Arc::new(AtomicUsize::new(0));
+    let upstream = echo_upstream(hits.clone()).await;
+    let router = Gateway::builder()
+        .target(upstream)
+        .key_pool(pool(&[("k1", "AAA1")]))
+        .into_router()
+        .expect("valid gateway config");
+    let gateway = common::spawn(Router::new().nest("/gemini", router)).await;
+
+    let response = common::client()
+        .post(format!("{}/gemini/v1beta/models/gemini-2.0-flash:generateContent", gateway))
+        .body(r#"{"contents":[]}"#)
+        .send()
+        .await
+        .expect("request through gateway");
+    assert_eq!(response.status(), StatusCode::OK);
+    let seen: Value = response.json().await.expect("upstream echo");
+    assert_eq!(seen["method"], "POST");
+    assert_eq!(seen["path"], "/v1beta/models/gemini-2.0-flash:generateContent");
+    assert_eq!(seen["key"], "AAA1");
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn pool_keys_rotate_between_requests() {
+    let upstream = echo_upstream(Arc::new(AtomicUsize::new(0))).await;
+    let router = Gateway::builder()
+        .target(upstream)
+        .key_pool(pool(&[("k1", "AAA1"), ("k2", "BBB2")]))
+        .into_router()
+        .expect("valid gateway config");
+    let gateway = common::spawn(router).await;
+
+    let mut keys = Vec::new();
+    for _ in 0..2 {
+        let seen: Value = common::client()
+            .get(format!("{}/v1beta/models", gateway))
+            .send()
+            .await
+            .expect("request through gateway")
+            .json()
+            .await
+            .expect("upstream echo");
+        keys.push(seen["key"].as_str().unwrap_or_default().to_string());
+    }
+    keys.sort();
+    assert_eq!(keys, ["AAA1", "BBB2"]);
+}
+
+// 没带 x-team 的请求直接拒绝；带了的去掉这个头再转发，并在响应头上做标记
+struct TeamPolicy;
+
+#[async_trait]
+impl GatewayPlugin for TeamPolicy {
+    fn name(&self) -> &str {
+        "team-policy"
+    }
+
+    async fn on_request(&self, ctx: &mut RequestContext) -> Result<(), Response> {
+        if !ctx.headers.contains_key("x-team") {
+            return Err((StatusCode::FORBIDDEN, "missing x-team").into_response());
+        }
+        ctx.headers.remove("x-team");
+        Ok(())
+    }
+
+    async fn on_upstream_response(&self, _ctx: &RequestContext, _status: StatusCode, headers: &mut HeaderMap) {
+        headers.insert("x-policy", "team".parse().unwrap());
+    }
+}
+
+#[tokio::test]
+async fn plugin_rejects_before_upstream_and_rewrites_headers() {
+    let hits = Arc::new(AtomicUsize::new(0));
+    let upstream = echo_upstream(hits.clone()).await;
+    let router = Gateway::builder().target(upstream).plugin(TeamPolicy).into_router().expect("valid gateway config");
+    let gateway = common::spawn(router).await;
+    let url = format!("{}/v1beta/models/gemini-2.0-flash:generateContent?key=CLIENT", gateway);
+
+    let rejected = common::client().post(&url).body("{}").send().await.expect("request through gateway");
+    assert_eq!(rejected.status(), StatusCode::FORBIDDEN);
+    assert_eq!(hits.load(Ordering::SeqCst), 0);
+
+    let accepted = common::client().post(&url).header("x-team", "ml").body("{}").send().await.expect("request through gateway");
+    assert_eq!(accepted.status(), StatusCode::OK);
+    assert_eq!(accepted.headers().get("x-policy").and_then(|v| v.to_str().ok()), Some("team"));
+    let seen: Value = accepted.json().await.expect("upstream echo");
+    assert_eq!(seen["team"], Value::Null);
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+}
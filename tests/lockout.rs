@@ -0,0 +1,54 @@
+// 鉴权失败锁定按 IP 和出示的令牌分别计数
+
+mod common;
+
+use aizasy_gateway::cidr::Cidr;
+use aizasy_gateway::client_auth::{ClientToken, ClientTokens};
+use aizasy_gateway::lockout::AuthLockout;
+use aizasy_gateway::Gateway;
+use axum::http::StatusCode;
+use axum::Router;
+use std::time::Duration;
+
+async fn gateway() -> String {
+    let upstream = common::spawn(Router::new().fallback(|| async { "ok" })).await;
+    let tokens = ClientTokens::new(vec![ClientToken::parse("ci=gw-ci-token").expect("valid token")]).expect("valid tokens");
+    let router = Gateway::builder()
+        .target(upstream)
+        .client_tokens(tokens)
+        // 测试里用 X-Forwarded-For 模拟不同来源
+        .trusted_proxies(vec![Cidr::parse("127.0.0.1/32").expect("valid cidr")])
+        .auth_lockout(AuthLockout::new(1, Duration::from_secs(60), Duration::from_secs(60)))
+        .into_router()
+        .expect("valid gateway config");
+    common::spawn(router).await
+}
+
+async fn get(gateway: &str, from: &str, token: Option<&str>) -> StatusCode {
+    let request = common::client().get(format!("{}/v1beta/models", gateway)).header("x-forwarded-for", from);
+    let request = match token {
+        Some(token) => request.bearer_auth(token),
+        None => request,
+    };
+    request.send().await.expect("request through gateway").status()
+}
+
+#[tokio::test]
+async fn valid_token_is_not_locked_by_its_neighbours() {
+    let gateway = gateway().await;
+
+    assert_eq!(get(&gateway, "10.0.0.1", Some("guess")).await, StatusCode::UNAUTHORIZED);
+    // 同一个出口 IP 后面持有有效令牌的客户端不受影响
+    assert_eq!(get(&gateway, "10.0.0.1", Some("gw-ci-token")).await, StatusCode::OK);
+    assert_eq!(get(&gateway, "10.0.0.1", Some("another-guess")).await, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(get(&gateway, "10.0.0.1", None).await, StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn same_token_from_many_ips_is_locked() {
+    let gateway = gateway().await;
+
+    assert_eq!(get(&gateway, "10.0.0.1", Some("guess")).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(get(&gateway, "10.0.0.2", Some("guess")).await, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(get(&gateway, "10.0.0.2", Some("other")).await, StatusCode::UNAUTHORIZED);
+}
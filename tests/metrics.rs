@@ -0,0 +1,41 @@
+// /metrics 默认只对管理员开放，--metrics-public 时才在主监听地址上公开
+#![cfg(all(feature = "metrics", feature = "admin"))]
+
+mod common;
+
+use aizasy_gateway::{Gateway, GatewayBuilder};
+use axum::http::StatusCode;
+
+// 上游不可达也没关系，这些请求都不会转发
+async fn gateway(builder: GatewayBuilder) -> String {
+    let router = builder.target("http://127.0.0.1:9").into_router().expect("valid gateway config");
+    common::spawn(router).await
+}
+
+async fn get_metrics(gateway: &str, token: Option<&str>) -> StatusCode {
+    let mut request = common::client().get(format!("{}/metrics", gateway));
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    request.send().await.expect("request through gateway").status()
+}
+
+#[tokio::test]
+async fn metrics_require_admin_token() {
+    let gateway = gateway(Gateway::builder().admin_token("adm1n")).await;
+    assert_eq!(get_metrics(&gateway, None).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(get_metrics(&gateway, Some("wrong")).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(get_metrics(&gateway, Some("adm1n")).await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn metrics_hidden_without_admin_token() {
+    let gateway = gateway(Gateway::builder()).await;
+    assert_eq!(get_metrics(&gateway, None).await, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn metrics_public_opt_in() {
+    let gateway = gateway(Gateway::builder().metrics_public()).await;
+    assert_eq!(get_metrics(&gateway, None).await, StatusCode::OK);
+}
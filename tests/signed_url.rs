@@ -0,0 +1,73 @@
+// 开启客户端令牌时，带有效签名的 URL 不需要令牌也能访问
+
+mod common;
+
+use aizasy_gateway::client_auth::{ClientToken, ClientTokens};
+use aizasy_gateway::lockout::AuthLockout;
+use aizasy_gateway::signed_url::UrlSigner;
+use aizasy_gateway::Gateway;
+use axum::extract::Request;
+use axum::http::StatusCode;
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+async fn echo_upstream() -> String {
+    let router = Router::new().fallback(|req: Request| async move {
+        Json(json!({ "path": req.uri().path_and_query().map(|p| p.as_str()) }))
+    });
+    common::spawn(router).await
+}
+
+async fn gateway(upstream: String) -> String {
+    let tokens = ClientTokens::new(vec![ClientToken::parse("ci=gw-ci-token").expect("valid token")]).expect("valid tokens");
+    let router = Gateway::builder()
+        .target(upstream)
+        .client_tokens(tokens)
+        .url_signer(UrlSigner::new("s3cret", false))
+        .auth_lockout(AuthLockout::new(1, Duration::from_secs(60), Duration::from_secs(60)))
+        .into_router()
+        .expect("valid gateway config");
+    common::spawn(router).await
+}
+
+#[tokio::test]
+async fn signed_url_skips_client_token() {
+    let gateway = gateway(echo_upstream().await).await;
+    let signed = UrlSigner::new("s3cret", false).signed_path("/v1beta/models?pageSize=5", 60);
+
+    let unsigned = common::client().get(format!("{}/v1beta/models", gateway)).send().await.expect("request through gateway");
+    assert_eq!(unsigned.status(), StatusCode::UNAUTHORIZED);
+
+    // 上一次失败已经把这个 IP 锁定，有效签名仍然放行
+    let response = common::client().get(format!("{}{}", gateway, signed)).send().await.expect("request through gateway");
+    assert_eq!(response.status(), StatusCode::OK);
+    let seen: Value = response.json().await.expect("upstream echo");
+    assert_eq!(seen["path"], "/v1beta/models?pageSize=5");
+
+    let locked = common::client().get(format!("{}/v1beta/models", gateway)).bearer_auth("wrong-token").send().await.expect("request through gateway");
+    assert_eq!(locked.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn bad_signatures_count_towards_lockout() {
+    let gateway = gateway(echo_upstream().await).await;
+    let signed = UrlSigner::new("s3cret", false).signed_path("/v1beta/models", 60);
+    let tampered = signed.replacen("/v1beta/models", "/v1beta/files", 1);
+
+    let response = common::client().get(format!("{}{}", gateway, tampered)).send().await.expect("request through gateway");
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let locked = common::client().get(format!("{}/v1beta/models", gateway)).send().await.expect("request through gateway");
+    assert_eq!(locked.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn tampered_signed_url_is_rejected() {
+    let gateway = gateway(echo_upstream().await).await;
+    let signed = UrlSigner::new("s3cret", false).signed_path("/v1beta/models", 60);
+    let tampered = signed.replacen("/v1beta/models", "/v1beta/files", 1);
+
+    let response = common::client().get(format!("{}{}", gateway, tampered)).send().await.expect("request through gateway");
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}